@@ -0,0 +1,1668 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Evaluation of `#[pg_extern(const_sql)]` functions while generating the extension schema.
+//!
+//! An `extension_sql!(r#"..."#, const_sql)` block can contain `{{ function_name(args) }}`
+//! placeholders.  Each one is replaced with the `Display` output of calling that function, which we
+//! interpret directly from its syntax tree -- there's no running Postgres (and no compiled
+//! extension) at this point.  Blocks without `const_sql` aren't expanded, as SQL can contain `{{`
+//! itself, such as in an array literal.
+//!
+//! Only a small, pure subset of Rust is understood:  integer, float, bool, char and string values,
+//! `let` bindings, the arithmetic, bitwise and comparison operators, `as` casts, `if`, `match` on
+//! literals and ranges, `loop`/`while`/`for` over ranges and `chars()`/`bytes()`, `String`
+//! building, `format!`, and calls to other `const_sql` functions.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use syn::punctuated::Punctuated;
+use syn::{
+    BinOp, Block, Expr, FnArg, Item, ItemFn, Lit, Pat, RangeLimits, ReturnType, Stmt, Token, Type,
+    UnOp,
+};
+
+/// Deepest chain of `const_sql` function calls we'll follow before assuming infinite recursion
+const MAX_CALL_DEPTH: usize = 128;
+
+/// Total number of loop iterations a single placeholder may perform
+const MAX_LOOP_ITERATIONS: usize = 10_000_000;
+
+/// A `{{ ... }}` placeholder that couldn't be expanded
+pub(crate) struct TemplateError {
+    /// byte offset of the placeholder's opening `{{` in the template
+    pub(crate) offset: usize,
+    pub(crate) message: String,
+}
+
+/// All the `#[pg_extern(const_sql)]` functions in the extension, by name
+#[derive(Default)]
+pub(crate) struct ConstSqlFunctions {
+    functions: HashMap<String, ItemFn>,
+}
+
+impl ConstSqlFunctions {
+    pub(crate) fn insert(&mut self, func: ItemFn) -> Result<(), String> {
+        let name = func.sig.ident.to_string();
+        if self.functions.contains_key(&name) {
+            return Err(format!(
+                "#[pg_extern(const_sql)] function `{}` is defined more than once",
+                name
+            ));
+        }
+        self.functions.insert(name, func);
+        Ok(())
+    }
+
+    /// Replace every `{{ function_name(args) }}` placeholder in `template` with the result of
+    /// evaluating that function call
+    pub(crate) fn expand(&self, template: &str) -> Result<String, TemplateError> {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+        let mut offset = 0;
+
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+
+            let position = offset + start;
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| TemplateError {
+                offset: position,
+                message: "unterminated `{{` placeholder".to_string(),
+            })?;
+            let source = after[..end].trim();
+            let value = self
+                .evaluate_placeholder(source)
+                .map_err(|message| TemplateError {
+                    offset: position,
+                    message: format!("`{{{{ {} }}}}`: {}", source, message),
+                })?;
+            output.push_str(&value);
+
+            let consumed = start + 2 + end + 2;
+            rest = &rest[consumed..];
+            offset += consumed;
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    fn evaluate_placeholder(&self, source: &str) -> Result<String, String> {
+        let expr = syn::parse_str::<Expr>(source)
+            .map_err(|e| format!("not a valid Rust expression: {}", e))?;
+        if !matches!(expr, Expr::Call(_)) {
+            return Err("placeholders must call a #[pg_extern(const_sql)] function".to_string());
+        }
+
+        let mut evaluator = Evaluator {
+            functions: &self.functions,
+            depth: 0,
+            iterations: 0,
+        };
+        match evaluator.eval(&expr, &mut Env::default()) {
+            Ok(Value::Unit) => Err("function did not return a value".to_string()),
+            Ok(value) => Ok(value.to_string()),
+            Err(Flow::Error(message)) => Err(message),
+            Err(_) => Err("unexpected control flow".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+    /// an unsuffixed integer literal whose type hasn't been pinned down yet
+    Unknown,
+}
+
+impl IntType {
+    fn from_name(name: &str) -> Option<IntType> {
+        Some(match name {
+            "i8" => IntType::I8,
+            "i16" => IntType::I16,
+            "i32" => IntType::I32,
+            "i64" => IntType::I64,
+            "i128" => IntType::I128,
+            "isize" => IntType::Isize,
+            "u8" => IntType::U8,
+            "u16" => IntType::U16,
+            "u32" => IntType::U32,
+            "u64" => IntType::U64,
+            "u128" => IntType::U128,
+            "usize" => IntType::Usize,
+            _ => return None,
+        })
+    }
+
+    fn bits(self) -> u32 {
+        match self {
+            IntType::I8 | IntType::U8 => 8,
+            IntType::I16 | IntType::U16 => 16,
+            IntType::I32 | IntType::U32 | IntType::Unknown => 32,
+            IntType::I64 | IntType::U64 | IntType::Isize | IntType::Usize => 64,
+            IntType::I128 | IntType::U128 => 128,
+        }
+    }
+
+    fn signed(self) -> bool {
+        !matches!(
+            self,
+            IntType::U8
+                | IntType::U16
+                | IntType::U32
+                | IntType::U64
+                | IntType::U128
+                | IntType::Usize
+        )
+    }
+
+    fn in_range(self, value: i128) -> bool {
+        match self {
+            // we don't know what rustc inferred, and it already rejected out-of-range literals
+            IntType::Unknown | IntType::I128 => true,
+            IntType::U128 => value >= 0,
+            _ if self.signed() => {
+                let half = 1i128 << (self.bits() - 1);
+                value >= -half && value < half
+            }
+            _ => value >= 0 && value < 1i128 << self.bits(),
+        }
+    }
+
+    /// Truncate `value` to this type, with the same semantics as an `as` cast
+    fn wrap(self, value: i128) -> i128 {
+        if self.bits() == 128 {
+            return value;
+        }
+        let modulus = 1i128 << self.bits();
+        let value = value.rem_euclid(modulus);
+        if self.signed() && value >= modulus / 2 {
+            value - modulus
+        } else {
+            value
+        }
+    }
+
+    /// The type of a binary expression over values of type `self` and `other`
+    fn unify(self, other: IntType) -> IntType {
+        if self == IntType::Unknown {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FloatType {
+    F32,
+    F64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Unit,
+    Bool(bool),
+    Int(i128, IntType),
+    Float(f64, FloatType),
+    Char(char),
+    Str(String),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i, _) => write!(f, "{}", i),
+            Value::Float(v, FloatType::F32) => write!(f, "{}", *v as f32),
+            Value::Float(v, FloatType::F64) => write!(f, "{}", v),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Unit => "()",
+            Value::Bool(_) => "bool",
+            Value::Int(..) => "integer",
+            Value::Float(..) => "float",
+            Value::Char(_) => "char",
+            Value::Str(_) => "string",
+        }
+    }
+}
+
+/// Non-local control flow, plus evaluation errors, unwinding through the evaluator
+enum Flow {
+    Break(Value),
+    Continue,
+    Return(Value),
+    Error(String),
+}
+
+type Eval<T> = Result<T, Flow>;
+
+fn error<T, S: Into<String>>(message: S) -> Eval<T> {
+    Err(Flow::Error(message.into()))
+}
+
+/// Variable bindings of the function being evaluated, innermost block last
+struct Env {
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env {
+            scopes: vec![HashMap::new()],
+        }
+    }
+}
+
+impl Env {
+    fn declare(&mut self, name: String, value: Value) {
+        self.scopes
+            .last_mut()
+            .expect("no scope to declare a variable in")
+            .insert(name, value);
+    }
+
+    fn get(&self, name: &str) -> Eval<Value> {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(value) => Ok(value.clone()),
+            None => error(format!("unknown variable `{}`", name)),
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Eval<&mut Value> {
+        match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            Some(value) => Ok(value),
+            None => error(format!("unknown variable `{}`", name)),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    functions: &'a HashMap<String, ItemFn>,
+    depth: usize,
+    iterations: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Eval<Value> {
+        let func = match self.functions.get(name) {
+            Some(func) => func,
+            None => {
+                return error(format!(
+                    "`{}` is not a #[pg_extern(const_sql)] function",
+                    name
+                ))
+            }
+        };
+        if func.sig.inputs.len() != args.len() {
+            return error(format!(
+                "`{}` takes {} argument(s) but {} were supplied",
+                name,
+                func.sig.inputs.len(),
+                args.len()
+            ));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return error(format!("recursion limit reached calling `{}`", name));
+        }
+
+        let mut env = Env::default();
+        for (input, value) in func.sig.inputs.iter().zip(args) {
+            match input {
+                FnArg::Typed(pat_type) => match pat_type.pat.as_ref() {
+                    Pat::Ident(ident) => {
+                        env.declare(ident.ident.to_string(), coerce(value, &pat_type.ty)?)
+                    }
+                    _ => return error("only simple argument names are supported"),
+                },
+                FnArg::Receiver(_) => return error("methods are not supported"),
+            }
+        }
+
+        self.depth += 1;
+        let result = match self.eval_block(&func.block, &mut env) {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Error(message)) => error(message),
+            Err(_) => error("`break` or `continue` outside of a loop"),
+        };
+        self.depth -= 1;
+
+        match &func.sig.output {
+            ReturnType::Default => result.map(|_| Value::Unit),
+            ReturnType::Type(_, ty) => coerce(result?, ty),
+        }
+    }
+
+    fn eval_block(&mut self, block: &Block, env: &mut Env) -> Eval<Value> {
+        env.scopes.push(HashMap::new());
+        let result = self.eval_stmts(&block.stmts, env);
+        env.scopes.pop();
+        result
+    }
+
+    fn eval_stmts(&mut self, stmts: &[Stmt], env: &mut Env) -> Eval<Value> {
+        let mut last = Value::Unit;
+        for stmt in stmts {
+            last = Value::Unit;
+            match stmt {
+                Stmt::Local(local) => {
+                    let value = match &local.init {
+                        Some((_, expr)) => self.eval(expr, env)?,
+                        None => return error("`let` without an initializer is not supported"),
+                    };
+                    match &local.pat {
+                        Pat::Ident(ident) => env.declare(ident.ident.to_string(), value),
+                        Pat::Type(pat_type) => match pat_type.pat.as_ref() {
+                            Pat::Ident(ident) => {
+                                env.declare(ident.ident.to_string(), coerce(value, &pat_type.ty)?)
+                            }
+                            _ => return error("only simple `let` bindings are supported"),
+                        },
+                        Pat::Wild(_) => {}
+                        _ => return error("only simple `let` bindings are supported"),
+                    }
+                }
+                Stmt::Expr(expr) => last = self.eval(expr, env)?,
+                Stmt::Semi(expr, _) => {
+                    self.eval(expr, env)?;
+                }
+                // statement-position macros, like a trailing `format!(...)`
+                Stmt::Item(Item::Macro(item)) if item.ident.is_none() => {
+                    let value = self.eval_macro(&item.mac, env)?;
+                    if item.semi_token.is_none() {
+                        last = value;
+                    }
+                }
+                Stmt::Item(_) => return error("nested items are not supported"),
+            }
+        }
+        Ok(last)
+    }
+
+    fn eval(&mut self, expr: &Expr, env: &mut Env) -> Eval<Value> {
+        match expr {
+            Expr::Lit(lit) => eval_lit(&lit.lit),
+            Expr::Paren(paren) => self.eval(&paren.expr, env),
+            Expr::Group(group) => self.eval(&group.expr, env),
+            Expr::Reference(reference) => self.eval(&reference.expr, env),
+            Expr::Block(block) => self.eval_block(&block.block, env),
+            Expr::Path(path) => {
+                let segments = &path.path.segments;
+                if let Some(ident) = path.path.get_ident() {
+                    env.get(&ident.to_string())
+                } else if segments.len() == 2 {
+                    let ty = segments[0].ident.to_string();
+                    let name = segments[1].ident.to_string();
+                    associated_const(&ty, &name)
+                } else {
+                    error(format!("unsupported path `{}`", path_string(&path.path)))
+                }
+            }
+            Expr::Unary(unary) => {
+                let value = self.eval(&unary.expr, env)?;
+                match (&unary.op, value) {
+                    (UnOp::Deref(_), value) => Ok(value),
+                    (UnOp::Not(_), Value::Bool(b)) => Ok(Value::Bool(!b)),
+                    (UnOp::Not(_), Value::Int(i, ty)) => Ok(Value::Int(ty.wrap(!i), ty)),
+                    (UnOp::Neg(_), Value::Int(i, ty)) => check_int(i.checked_neg(), ty, "negate"),
+                    (UnOp::Neg(_), Value::Float(f, ty)) => Ok(Value::Float(-f, ty)),
+                    (_, value) => error(format!("unsupported unary operation on {}", value.kind())),
+                }
+            }
+            Expr::Binary(binary) => match binary.op {
+                BinOp::And(_) => match self.eval(&binary.left, env)? {
+                    Value::Bool(false) => Ok(Value::Bool(false)),
+                    _ => self.eval(&binary.right, env),
+                },
+                BinOp::Or(_) => match self.eval(&binary.left, env)? {
+                    Value::Bool(true) => Ok(Value::Bool(true)),
+                    _ => self.eval(&binary.right, env),
+                },
+                op => {
+                    let left = self.eval(&binary.left, env)?;
+                    let right = self.eval(&binary.right, env)?;
+                    binary_op(op, left, right)
+                }
+            },
+            Expr::Assign(assign) => {
+                let value = self.eval(&assign.right, env)?;
+                *self.place(&assign.left, env)? = value;
+                Ok(Value::Unit)
+            }
+            Expr::AssignOp(assign) => {
+                let right = self.eval(&assign.right, env)?;
+                let place = self.place(&assign.left, env)?;
+                let op = match assign.op {
+                    BinOp::AddEq(_) => BinOp::Add(Default::default()),
+                    BinOp::SubEq(_) => BinOp::Sub(Default::default()),
+                    BinOp::MulEq(_) => BinOp::Mul(Default::default()),
+                    BinOp::DivEq(_) => BinOp::Div(Default::default()),
+                    BinOp::RemEq(_) => BinOp::Rem(Default::default()),
+                    BinOp::BitXorEq(_) => BinOp::BitXor(Default::default()),
+                    BinOp::BitAndEq(_) => BinOp::BitAnd(Default::default()),
+                    BinOp::BitOrEq(_) => BinOp::BitOr(Default::default()),
+                    BinOp::ShlEq(_) => BinOp::Shl(Default::default()),
+                    BinOp::ShrEq(_) => BinOp::Shr(Default::default()),
+                    _ => return error("unsupported compound assignment"),
+                };
+                *place = binary_op(op, place.clone(), right)?;
+                Ok(Value::Unit)
+            }
+            Expr::Cast(cast) => {
+                let value = self.eval(&cast.expr, env)?;
+                cast_value(value, &cast.ty)
+            }
+            Expr::If(expr_if) => match self.eval(&expr_if.cond, env)? {
+                Value::Bool(true) => self.eval_block(&expr_if.then_branch, env),
+                Value::Bool(false) => match &expr_if.else_branch {
+                    Some((_, else_branch)) => self.eval(else_branch, env),
+                    None => Ok(Value::Unit),
+                },
+                value => error(format!("`if` condition is a {}", value.kind())),
+            },
+            Expr::Match(expr_match) => {
+                let value = self.eval(&expr_match.expr, env)?;
+                for arm in &expr_match.arms {
+                    env.scopes.push(HashMap::new());
+                    let mut matched = pattern_matches(&arm.pat, &value, env)?;
+                    if let (true, Some((_, guard))) = (matched, &arm.guard) {
+                        matched = self.eval(guard, env)? == Value::Bool(true);
+                    }
+                    let result = if matched {
+                        Some(self.eval(&arm.body, env))
+                    } else {
+                        None
+                    };
+                    env.scopes.pop();
+                    if let Some(result) = result {
+                        return result;
+                    }
+                }
+                error("no `match` arm matched")
+            }
+            Expr::Loop(expr_loop) => loop {
+                self.count_iteration()?;
+                match self.eval_block(&expr_loop.body, env) {
+                    Err(Flow::Break(value)) => return Ok(value),
+                    Err(Flow::Continue) | Ok(_) => {}
+                    Err(flow) => return Err(flow),
+                }
+            },
+            Expr::While(expr_while) => loop {
+                match self.eval(&expr_while.cond, env)? {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => return Ok(Value::Unit),
+                    value => return error(format!("`while` condition is a {}", value.kind())),
+                }
+                self.count_iteration()?;
+                match self.eval_block(&expr_while.body, env) {
+                    Err(Flow::Break(_)) => return Ok(Value::Unit),
+                    Err(Flow::Continue) | Ok(_) => {}
+                    Err(flow) => return Err(flow),
+                }
+            },
+            Expr::ForLoop(for_loop) => {
+                let name = match &for_loop.pat {
+                    Pat::Ident(ident) => Some(ident.ident.to_string()),
+                    Pat::Wild(_) => None,
+                    _ => return error("only simple `for` loop variables are supported"),
+                };
+                for value in self.iterate(&for_loop.expr, env)? {
+                    self.count_iteration()?;
+                    env.scopes.push(HashMap::new());
+                    if let Some(name) = &name {
+                        env.declare(name.clone(), value);
+                    }
+                    let result = self.eval_block(&for_loop.body, env);
+                    env.scopes.pop();
+                    match result {
+                        Err(Flow::Break(_)) => break,
+                        Err(Flow::Continue) | Ok(_) => {}
+                        Err(flow) => return Err(flow),
+                    }
+                }
+                Ok(Value::Unit)
+            }
+            Expr::Break(expr_break) => {
+                if expr_break.label.is_some() {
+                    return error("labeled `break` is not supported");
+                }
+                let value = match &expr_break.expr {
+                    Some(expr) => self.eval(expr, env)?,
+                    None => Value::Unit,
+                };
+                Err(Flow::Break(value))
+            }
+            Expr::Continue(expr_continue) => {
+                if expr_continue.label.is_some() {
+                    return error("labeled `continue` is not supported");
+                }
+                Err(Flow::Continue)
+            }
+            Expr::Return(expr_return) => {
+                let value = match &expr_return.expr {
+                    Some(expr) => self.eval(expr, env)?,
+                    None => Value::Unit,
+                };
+                Err(Flow::Return(value))
+            }
+            Expr::Call(call) => {
+                let mut args = Vec::with_capacity(call.args.len());
+                for arg in &call.args {
+                    args.push(self.eval(arg, env)?);
+                }
+                let path = match call.func.as_ref() {
+                    Expr::Path(path) => &path.path,
+                    _ => return error("only calls to named functions are supported"),
+                };
+                if let Some(ident) = path.get_ident() {
+                    return self.call(&ident.to_string(), args);
+                }
+                associated_fn(&path_string(path), args)
+            }
+            Expr::MethodCall(method_call) => {
+                let mut args = Vec::with_capacity(method_call.args.len());
+                for arg in &method_call.args {
+                    args.push(self.eval(arg, env)?);
+                }
+                let method = method_call.method.to_string();
+                match method.as_str() {
+                    "push_str" | "push" | "clear" => {
+                        let receiver = self.place(&method_call.receiver, env)?;
+                        match (method.as_str(), receiver, args.as_slice()) {
+                            ("push_str", Value::Str(s), [Value::Str(other)]) => s.push_str(other),
+                            ("push", Value::Str(s), [Value::Char(c)]) => s.push(*c),
+                            ("clear", Value::Str(s), []) => s.clear(),
+                            (_, receiver, _) => {
+                                return error(format!(
+                                    "unsupported method `{}` on {}",
+                                    method,
+                                    receiver.kind()
+                                ))
+                            }
+                        }
+                        Ok(Value::Unit)
+                    }
+                    _ => {
+                        let receiver = self.eval(&method_call.receiver, env)?;
+                        method_call_value(&method, receiver, args)
+                    }
+                }
+            }
+            Expr::Macro(expr_macro) => self.eval_macro(&expr_macro.mac, env),
+            Expr::Unsafe(_) => error("`unsafe` is not allowed"),
+            _ => error("unsupported expression"),
+        }
+    }
+
+    /// Resolve an assignable expression to the variable it names
+    fn place<'e>(&mut self, expr: &Expr, env: &'e mut Env) -> Eval<&'e mut Value> {
+        match expr {
+            Expr::Paren(paren) => self.place(&paren.expr, env),
+            Expr::Reference(reference) => self.place(&reference.expr, env),
+            Expr::Unary(unary) if matches!(unary.op, UnOp::Deref(_)) => {
+                self.place(&unary.expr, env)
+            }
+            Expr::Path(path) => match path.path.get_ident() {
+                Some(ident) => env.get_mut(&ident.to_string()),
+                None => error("can only assign to local variables"),
+            },
+            _ => error("can only assign to local variables"),
+        }
+    }
+
+    fn count_iteration(&mut self) -> Eval<()> {
+        self.iterations += 1;
+        if self.iterations > MAX_LOOP_ITERATIONS {
+            return error(format!("exceeded {} loop iterations", MAX_LOOP_ITERATIONS));
+        }
+        Ok(())
+    }
+
+    /// Evaluate the expression of a `for` loop into the values it iterates over
+    fn iterate(&mut self, expr: &Expr, env: &mut Env) -> Eval<Vec<Value>> {
+        match expr {
+            Expr::Paren(paren) => self.iterate(&paren.expr, env),
+            Expr::Range(range) => {
+                let (from, to) = match (&range.from, &range.to) {
+                    (Some(from), Some(to)) => (self.eval(from, env)?, self.eval(to, env)?),
+                    _ => return error("only bounded ranges can be iterated"),
+                };
+                let (from, to, ty) = match (from, to) {
+                    (Value::Int(from, a), Value::Int(to, b)) => (from, to, a.unify(b)),
+                    _ => return error("only integer ranges can be iterated"),
+                };
+                let end = match range.limits {
+                    RangeLimits::HalfOpen(_) => to,
+                    RangeLimits::Closed(_) => to + 1,
+                };
+                if end.saturating_sub(from) > MAX_LOOP_ITERATIONS as i128 {
+                    return error(format!(
+                        "ranges longer than {} are not supported",
+                        MAX_LOOP_ITERATIONS
+                    ));
+                }
+                Ok((from..end).map(|i| Value::Int(i, ty)).collect())
+            }
+            Expr::MethodCall(method_call) => {
+                let method = method_call.method.to_string();
+                match (method.as_str(), method_call.args.len()) {
+                    ("rev", 0) => {
+                        let mut values = self.iterate(&method_call.receiver, env)?;
+                        values.reverse();
+                        Ok(values)
+                    }
+                    ("step_by", 1) => {
+                        let step = match self.eval(&method_call.args[0], env)? {
+                            Value::Int(step, _) if step > 0 => step as usize,
+                            _ => return error("`step_by` requires a positive integer"),
+                        };
+                        let values = self.iterate(&method_call.receiver, env)?;
+                        Ok(values.into_iter().step_by(step).collect())
+                    }
+                    ("chars", 0) => match self.eval(&method_call.receiver, env)? {
+                        Value::Str(s) => Ok(s.chars().map(Value::Char).collect()),
+                        value => error(format!("cannot call `chars` on {}", value.kind())),
+                    },
+                    ("bytes", 0) => match self.eval(&method_call.receiver, env)? {
+                        Value::Str(s) => Ok(s
+                            .bytes()
+                            .map(|b| Value::Int(b as i128, IntType::U8))
+                            .collect()),
+                        value => error(format!("cannot call `bytes` on {}", value.kind())),
+                    },
+                    _ => error(format!("cannot iterate over `{}()`", method)),
+                }
+            }
+            _ => error("`for` loops can only iterate over ranges, `chars()` or `bytes()`"),
+        }
+    }
+
+    fn eval_macro(&mut self, mac: &syn::Macro, env: &mut Env) -> Eval<Value> {
+        let name = path_string(&mac.path);
+        if name != "format" {
+            return error(format!("unsupported macro `{}!`", name));
+        }
+
+        let args = match mac.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated) {
+            Ok(args) => args,
+            Err(e) => return error(format!("invalid `format!` arguments: {}", e)),
+        };
+        let mut args = args.into_iter();
+        let template = match args.next() {
+            Some(Expr::Lit(syn::ExprLit {
+                lit: Lit::Str(s), ..
+            })) => s.value(),
+            _ => return error("`format!` requires a string literal"),
+        };
+
+        let mut positional = Vec::new();
+        let mut named = HashMap::new();
+        for arg in args {
+            match arg {
+                Expr::Assign(assign) => match assign.left.as_ref() {
+                    Expr::Path(path) if path.path.get_ident().is_some() => {
+                        let name = path.path.get_ident().unwrap().to_string();
+                        named.insert(name, self.eval(&assign.right, env)?);
+                    }
+                    _ => return error("invalid named `format!` argument"),
+                },
+                arg => positional.push(self.eval(&arg, env)?),
+            }
+        }
+
+        format_values(&template, &positional, &named).map(Value::Str)
+    }
+}
+
+fn path_string(path: &syn::Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Reference(reference) => type_name(&reference.elem),
+        Type::Paren(paren) => type_name(&paren.elem),
+        Type::Group(group) => type_name(&group.elem),
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Convert `value` to the declared type `ty` of an argument, `let` binding or return value
+fn coerce(value: Value, ty: &Type) -> Eval<Value> {
+    let name = match type_name(ty) {
+        Some(name) => name,
+        None => return error("unsupported type in a const_sql function"),
+    };
+
+    match (IntType::from_name(&name), name.as_str(), value) {
+        (Some(target), _, Value::Int(i, _)) => {
+            if target.in_range(i) {
+                Ok(Value::Int(i, target))
+            } else {
+                error(format!("{} is out of range for `{}`", i, name))
+            }
+        }
+        (None, "f32", Value::Float(f, _)) => Ok(Value::Float(f, FloatType::F32)),
+        (None, "f64", Value::Float(f, _)) => Ok(Value::Float(f, FloatType::F64)),
+        (None, "bool", value @ Value::Bool(_))
+        | (None, "char", value @ Value::Char(_))
+        | (None, "str", value @ Value::Str(_))
+        | (None, "String", value @ Value::Str(_)) => Ok(value),
+        (_, _, value) => error(format!(
+            "expected a value of type `{}`, found {}",
+            name,
+            value.kind()
+        )),
+    }
+}
+
+fn eval_lit(lit: &Lit) -> Eval<Value> {
+    match lit {
+        Lit::Bool(b) => Ok(Value::Bool(b.value)),
+        Lit::Char(c) => Ok(Value::Char(c.value())),
+        Lit::Str(s) => Ok(Value::Str(s.value())),
+        Lit::Byte(b) => Ok(Value::Int(b.value() as i128, IntType::U8)),
+        Lit::Int(i) => match i.suffix() {
+            suffix @ "f32" | suffix @ "f64" => match i.base10_digits().parse::<f64>() {
+                Ok(value) if suffix == "f32" => Ok(Value::Float(value, FloatType::F32)),
+                Ok(value) => Ok(Value::Float(value, FloatType::F64)),
+                Err(e) => error(e.to_string()),
+            },
+            suffix => {
+                let value = match i.base10_parse::<i128>() {
+                    Ok(value) => value,
+                    Err(e) => return error(e.to_string()),
+                };
+                Ok(Value::Int(
+                    value,
+                    IntType::from_name(suffix).unwrap_or(IntType::Unknown),
+                ))
+            }
+        },
+        Lit::Float(f) => {
+            let value = match f.base10_parse::<f64>() {
+                Ok(value) => value,
+                Err(e) => return error(e.to_string()),
+            };
+            match f.suffix() {
+                "f32" => Ok(Value::Float(value, FloatType::F32)),
+                _ => Ok(Value::Float(value, FloatType::F64)),
+            }
+        }
+        _ => error("unsupported literal"),
+    }
+}
+
+fn check_int(value: Option<i128>, ty: IntType, what: &str) -> Eval<Value> {
+    match value {
+        Some(value) if ty.in_range(value) => Ok(Value::Int(value, ty)),
+        _ => error(format!("attempt to {} with overflow", what)),
+    }
+}
+
+fn binary_op(op: BinOp, left: Value, right: Value) -> Eval<Value> {
+    use std::cmp::Ordering;
+
+    let ordering = match (&left, &right) {
+        (Value::Int(a, _), Value::Int(b, _)) => a.partial_cmp(b),
+        (Value::Float(a, _), Value::Float(b, _)) => a.partial_cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(b),
+        (Value::Char(a), Value::Char(b)) => a.partial_cmp(b),
+        (Value::Str(a), Value::Str(b)) => a.partial_cmp(b),
+        _ => None,
+    };
+    match op {
+        BinOp::Eq(_) => return Ok(Value::Bool(ordering == Some(Ordering::Equal))),
+        BinOp::Ne(_) => return Ok(Value::Bool(ordering != Some(Ordering::Equal))),
+        BinOp::Lt(_) => return Ok(Value::Bool(ordering == Some(Ordering::Less))),
+        BinOp::Gt(_) => return Ok(Value::Bool(ordering == Some(Ordering::Greater))),
+        BinOp::Le(_) => {
+            return Ok(Value::Bool(matches!(
+                ordering,
+                Some(Ordering::Less) | Some(Ordering::Equal)
+            )))
+        }
+        BinOp::Ge(_) => {
+            return Ok(Value::Bool(matches!(
+                ordering,
+                Some(Ordering::Greater) | Some(Ordering::Equal)
+            )))
+        }
+        _ => {}
+    }
+
+    match (left, right) {
+        (Value::Int(a, a_ty), Value::Int(b, b_ty)) => {
+            // shifts keep the type of their left operand; everything else needs matching types
+            let ty = match op {
+                BinOp::Shl(_) | BinOp::Shr(_) => a_ty,
+                _ => a_ty.unify(b_ty),
+            };
+            match op {
+                BinOp::Add(_) => check_int(a.checked_add(b), ty, "add"),
+                BinOp::Sub(_) => check_int(a.checked_sub(b), ty, "subtract"),
+                BinOp::Mul(_) => check_int(a.checked_mul(b), ty, "multiply"),
+                BinOp::Div(_) if b == 0 => error("attempt to divide by zero"),
+                BinOp::Div(_) => check_int(a.checked_div(b), ty, "divide"),
+                BinOp::Rem(_) if b == 0 => {
+                    error("attempt to calculate the remainder with a divisor of zero")
+                }
+                BinOp::Rem(_) => check_int(a.checked_rem(b), ty, "calculate the remainder"),
+                BinOp::BitAnd(_) => Ok(Value::Int(a & b, ty)),
+                BinOp::BitOr(_) => Ok(Value::Int(a | b, ty)),
+                BinOp::BitXor(_) => Ok(Value::Int(a ^ b, ty)),
+                BinOp::Shl(_) | BinOp::Shr(_) if b < 0 || b >= ty.bits() as i128 => {
+                    error("attempt to shift with overflow")
+                }
+                BinOp::Shl(_) => Ok(Value::Int(ty.wrap(a << b), ty)),
+                BinOp::Shr(_) => Ok(Value::Int(a >> b, ty)),
+                _ => error("unsupported integer operator"),
+            }
+        }
+        (Value::Float(a, a_ty), Value::Float(b, b_ty)) => {
+            let ty = if a_ty == FloatType::F32 || b_ty == FloatType::F32 {
+                FloatType::F32
+            } else {
+                FloatType::F64
+            };
+            let value = match op {
+                BinOp::Add(_) => a + b,
+                BinOp::Sub(_) => a - b,
+                BinOp::Mul(_) => a * b,
+                BinOp::Div(_) => a / b,
+                BinOp::Rem(_) => a % b,
+                _ => return error("unsupported float operator"),
+            };
+            Ok(Value::Float(value, ty))
+        }
+        (Value::Bool(a), Value::Bool(b)) => match op {
+            BinOp::BitAnd(_) => Ok(Value::Bool(a & b)),
+            BinOp::BitOr(_) => Ok(Value::Bool(a | b)),
+            BinOp::BitXor(_) => Ok(Value::Bool(a ^ b)),
+            _ => error("unsupported bool operator"),
+        },
+        (Value::Str(a), Value::Str(b)) => match op {
+            BinOp::Add(_) => Ok(Value::Str(a + &b)),
+            _ => error("unsupported string operator"),
+        },
+        (left, right) => error(format!(
+            "unsupported operation between {} and {}",
+            left.kind(),
+            right.kind()
+        )),
+    }
+}
+
+fn cast_value(value: Value, ty: &Type) -> Eval<Value> {
+    let name = match type_name(ty) {
+        Some(name) => name,
+        None => return error("unsupported cast"),
+    };
+
+    if let Some(target) = IntType::from_name(&name) {
+        let i = match value {
+            Value::Int(i, _) => i,
+            Value::Bool(b) => b as i128,
+            Value::Char(c) => c as i128,
+            // float to int casts saturate
+            Value::Float(f, _) => {
+                let i = f as i128;
+                if target.bits() == 128 || target.in_range(i) {
+                    i
+                } else if i < 0 {
+                    if target.signed() {
+                        -(1i128 << (target.bits() - 1))
+                    } else {
+                        0
+                    }
+                } else if target.signed() {
+                    (1i128 << (target.bits() - 1)) - 1
+                } else {
+                    (1i128 << target.bits()) - 1
+                }
+            }
+            value => return error(format!("cannot cast {} to `{}`", value.kind(), name)),
+        };
+        return Ok(Value::Int(target.wrap(i), target));
+    }
+
+    match (name.as_str(), value) {
+        ("f32", Value::Int(i, _)) => Ok(Value::Float(i as f32 as f64, FloatType::F32)),
+        ("f32", Value::Float(f, _)) => Ok(Value::Float(f as f32 as f64, FloatType::F32)),
+        ("f64", Value::Int(i, _)) => Ok(Value::Float(i as f64, FloatType::F64)),
+        ("f64", Value::Float(f, _)) => Ok(Value::Float(f, FloatType::F64)),
+        ("char", Value::Int(i, IntType::U8)) => Ok(Value::Char(i as u8 as char)),
+        ("char", value @ Value::Char(_)) => Ok(value),
+        ("bool", value @ Value::Bool(_)) => Ok(value),
+        (_, value) => error(format!("cannot cast {} to `{}`", value.kind(), name)),
+    }
+}
+
+/// Associated constants like `u32::MAX`
+fn associated_const(ty: &str, name: &str) -> Eval<Value> {
+    let int_type = match IntType::from_name(ty) {
+        Some(int_type) => int_type,
+        None => return error(format!("unsupported path `{}::{}`", ty, name)),
+    };
+    let bits = int_type.bits();
+    let value = match (name, int_type.signed()) {
+        ("BITS", _) => return Ok(Value::Int(bits as i128, IntType::U32)),
+        ("MIN", false) => 0,
+        ("MIN", true) => -(1i128 << (bits - 1)),
+        ("MAX", true) => (1i128 << (bits - 1)) - 1,
+        ("MAX", false) if bits == 128 => i128::MAX,
+        ("MAX", false) => (1i128 << bits) - 1,
+        _ => return error(format!("unsupported path `{}::{}`", ty, name)),
+    };
+    Ok(Value::Int(value, int_type))
+}
+
+/// Associated functions like `String::new()` and `u64::from(x)`
+fn associated_fn(path: &str, args: Vec<Value>) -> Eval<Value> {
+    let (ty, name) = match path.rfind("::") {
+        Some(idx) => (&path[..idx], &path[idx + 2..]),
+        None => return error(format!("unsupported function `{}`", path)),
+    };
+
+    match (ty, name, args.as_slice()) {
+        ("String", "new", []) | ("String", "with_capacity", [Value::Int(..)]) => {
+            Ok(Value::Str(String::new()))
+        }
+        ("String", "from", [Value::Str(s)]) => Ok(Value::Str(s.clone())),
+        ("String", "from", [Value::Char(c)]) => Ok(Value::Str(c.to_string())),
+        ("char", "from", [Value::Int(i, IntType::U8)]) => Ok(Value::Char(*i as u8 as char)),
+        ("char", "from_digit", [Value::Int(digit, _), Value::Int(radix, _)]) => {
+            if !(2..=36).contains(radix) {
+                return error("`char::from_digit` radix must be between 2 and 36");
+            }
+            match std::char::from_digit(*digit as u32, *radix as u32) {
+                Some(c) => Ok(Value::Char(c)),
+                None => error(format!("{} is not a digit in radix {}", digit, radix)),
+            }
+        }
+        (ty, "from", [Value::Int(i, _)]) if IntType::from_name(ty).is_some() => {
+            Ok(Value::Int(*i, IntType::from_name(ty).unwrap()))
+        }
+        (ty, "from", [Value::Bool(b)]) if IntType::from_name(ty).is_some() => {
+            Ok(Value::Int(*b as i128, IntType::from_name(ty).unwrap()))
+        }
+        _ => error(format!("unsupported function `{}`", path)),
+    }
+}
+
+fn method_call_value(method: &str, receiver: Value, args: Vec<Value>) -> Eval<Value> {
+    match (receiver, method, args.as_slice()) {
+        (value, "clone", []) | (value, "into", []) => Ok(value),
+        (value, "to_string", []) => Ok(Value::Str(value.to_string())),
+
+        (Value::Str(s), "len", []) => Ok(Value::Int(s.len() as i128, IntType::Usize)),
+        (Value::Str(s), "is_empty", []) => Ok(Value::Bool(s.is_empty())),
+        (Value::Str(s), "to_owned", []) | (Value::Str(s), "as_str", []) => Ok(Value::Str(s)),
+        (Value::Str(s), "to_uppercase", []) => Ok(Value::Str(s.to_uppercase())),
+        (Value::Str(s), "to_lowercase", []) => Ok(Value::Str(s.to_lowercase())),
+        (Value::Str(s), "trim", []) => Ok(Value::Str(s.trim().to_string())),
+        (Value::Str(s), "repeat", [Value::Int(n, _)]) if *n >= 0 => {
+            Ok(Value::Str(s.repeat(*n as usize)))
+        }
+        (Value::Str(s), "replace", [Value::Str(from), Value::Str(to)]) => {
+            Ok(Value::Str(s.replace(from.as_str(), to)))
+        }
+        (Value::Str(s), "starts_with", [Value::Str(prefix)]) => {
+            Ok(Value::Bool(s.starts_with(prefix.as_str())))
+        }
+        (Value::Str(s), "ends_with", [Value::Str(suffix)]) => {
+            Ok(Value::Bool(s.ends_with(suffix.as_str())))
+        }
+        (Value::Str(s), "contains", [Value::Str(needle)]) => {
+            Ok(Value::Bool(s.contains(needle.as_str())))
+        }
+
+        (Value::Int(i, ty), "pow", [Value::Int(exp, _)]) if *exp >= 0 => {
+            check_int(i.checked_pow(*exp as u32), ty, "multiply")
+        }
+        (Value::Int(i, ty), "abs", []) => check_int(i.checked_abs(), ty, "negate"),
+        (Value::Int(a, ty), "min", [Value::Int(b, _)]) => Ok(Value::Int(a.min(*b), ty)),
+        (Value::Int(a, ty), "max", [Value::Int(b, _)]) => Ok(Value::Int(a.max(*b), ty)),
+        (Value::Int(a, ty), "wrapping_add", [Value::Int(b, _)]) => {
+            Ok(Value::Int(ty.wrap(a + b), ty))
+        }
+        (Value::Int(a, ty), "wrapping_sub", [Value::Int(b, _)]) => {
+            Ok(Value::Int(ty.wrap(a - b), ty))
+        }
+        (Value::Int(a, ty), "wrapping_mul", [Value::Int(b, _)]) => {
+            Ok(Value::Int(ty.wrap(a.wrapping_mul(*b)), ty))
+        }
+        (Value::Int(i, ty), "count_ones", []) => {
+            let bits = (i as u128) & (u128::MAX >> (128 - ty.bits()));
+            Ok(Value::Int(bits.count_ones() as i128, IntType::U32))
+        }
+
+        (Value::Float(f, ty), "abs", []) => Ok(Value::Float(f.abs(), ty)),
+        (Value::Float(f, ty), "floor", []) => Ok(Value::Float(f.floor(), ty)),
+        (Value::Float(f, ty), "ceil", []) => Ok(Value::Float(f.ceil(), ty)),
+        (Value::Float(f, ty), "round", []) => Ok(Value::Float(f.round(), ty)),
+        (Value::Float(f, ty), "sqrt", []) => Ok(Value::Float(f.sqrt(), ty)),
+        (Value::Float(f, ty), "powi", [Value::Int(n, _)]) => {
+            Ok(Value::Float(f.powi(*n as i32), ty))
+        }
+        (Value::Float(f, ty), "powf", [Value::Float(n, _)]) => Ok(Value::Float(f.powf(*n), ty)),
+
+        (Value::Char(c), "to_ascii_uppercase", []) => Ok(Value::Char(c.to_ascii_uppercase())),
+        (Value::Char(c), "to_ascii_lowercase", []) => Ok(Value::Char(c.to_ascii_lowercase())),
+        (Value::Char(c), "is_alphanumeric", []) => Ok(Value::Bool(c.is_alphanumeric())),
+        (Value::Char(c), "is_ascii_digit", []) => Ok(Value::Bool(c.is_ascii_digit())),
+
+        (receiver, method, _) => error(format!(
+            "unsupported method `{}` on {}",
+            method,
+            receiver.kind()
+        )),
+    }
+}
+
+fn pattern_matches(pat: &Pat, value: &Value, env: &mut Env) -> Eval<bool> {
+    match pat {
+        Pat::Wild(_) => Ok(true),
+        Pat::Ident(ident) => {
+            if let Some((_, subpat)) = &ident.subpat {
+                if !pattern_matches(subpat, value, env)? {
+                    return Ok(false);
+                }
+            }
+            env.declare(ident.ident.to_string(), value.clone());
+            Ok(true)
+        }
+        Pat::Lit(lit) => {
+            let expected = eval_pattern_bound(&lit.expr)?;
+            Ok(
+                binary_op(BinOp::Eq(Default::default()), value.clone(), expected)?
+                    == Value::Bool(true),
+            )
+        }
+        Pat::Range(range) => {
+            let lo = eval_pattern_bound(&range.lo)?;
+            let hi = eval_pattern_bound(&range.hi)?;
+            let above = binary_op(BinOp::Ge(Default::default()), value.clone(), lo)?;
+            let below = match range.limits {
+                RangeLimits::HalfOpen(_) => {
+                    binary_op(BinOp::Lt(Default::default()), value.clone(), hi)?
+                }
+                RangeLimits::Closed(_) => {
+                    binary_op(BinOp::Le(Default::default()), value.clone(), hi)?
+                }
+            };
+            Ok(above == Value::Bool(true) && below == Value::Bool(true))
+        }
+        Pat::Or(or) => {
+            for case in &or.cases {
+                if pattern_matches(case, value, env)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => error("unsupported `match` pattern"),
+    }
+}
+
+/// Literal (and negated literal) expressions, and integer type constants, inside patterns
+fn eval_pattern_bound(expr: &Expr) -> Eval<Value> {
+    match expr {
+        Expr::Lit(lit) => eval_lit(&lit.lit),
+        Expr::Unary(unary) if matches!(unary.op, UnOp::Neg(_)) => {
+            match eval_pattern_bound(&unary.expr)? {
+                Value::Int(i, ty) => Ok(Value::Int(-i, ty)),
+                Value::Float(f, ty) => Ok(Value::Float(-f, ty)),
+                value => error(format!("cannot negate {}", value.kind())),
+            }
+        }
+        Expr::Path(path) if path.path.segments.len() == 2 => associated_const(
+            &path.path.segments[0].ident.to_string(),
+            &path.path.segments[1].ident.to_string(),
+        ),
+        _ => error("unsupported `match` pattern"),
+    }
+}
+
+/// The subset of `format!` specifications we understand:  `{[arg][:[[fill]align][+][#][0][width][.precision][?|x|X|b|o]]}`
+struct FormatSpec {
+    fill: char,
+    align: Option<char>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+fn parse_format_spec(spec: &str) -> Result<FormatSpec, String> {
+    let chars = spec.chars().collect::<Vec<_>>();
+    let mut result = FormatSpec {
+        fill: ' ',
+        align: None,
+        plus: false,
+        alternate: false,
+        zero: false,
+        width: 0,
+        precision: None,
+        kind: None,
+    };
+    let mut i = 0;
+
+    let is_align = |c: Option<&char>| matches!(c, Some('<') | Some('^') | Some('>'));
+    if chars.len() >= 2 && is_align(chars.get(1)) {
+        result.fill = chars[0];
+        result.align = Some(chars[1]);
+        i = 2;
+    } else if is_align(chars.first()) {
+        result.align = Some(chars[0]);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'+') {
+        result.plus = true;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'#') {
+        result.alternate = true;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        result.zero = true;
+        i += 1;
+    }
+
+    let number = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).map_or(false, |c| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        chars[start..*i]
+            .iter()
+            .collect::<String>()
+            .parse::<usize>()
+            .ok()
+    };
+    result.width = number(&mut i).unwrap_or(0);
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        result.precision = Some(number(&mut i).ok_or("missing precision")?);
+    }
+    match &chars[i..] {
+        [] => {}
+        [kind] if "?xXbo".contains(*kind) => result.kind = Some(*kind),
+        _ => return Err(format!("unsupported format specification `{}`", spec)),
+    }
+
+    Ok(result)
+}
+
+fn format_one(value: &Value, spec: &FormatSpec) -> Result<String, String> {
+    let numeric = matches!(value, Value::Int(..) | Value::Float(..));
+    let (sign, prefix, body) = match (value, spec.kind) {
+        (Value::Int(i, ty), Some(kind)) if kind != '?' => {
+            // like Rust, negative values are shown as their two's complement
+            let bits = (*i as u128) & (u128::MAX >> (128 - ty.bits()));
+            let (prefix, body) = match kind {
+                'x' => ("0x", format!("{:x}", bits)),
+                'X' => ("0x", format!("{:X}", bits)),
+                'b' => ("0b", format!("{:b}", bits)),
+                _ => ("0o", format!("{:o}", bits)),
+            };
+            ("", if spec.alternate { prefix } else { "" }, body)
+        }
+        (_, Some(kind)) if kind != '?' => {
+            return Err(format!("cannot format {} with `{}`", value.kind(), kind))
+        }
+        (Value::Int(i, _), _) => (
+            if *i < 0 {
+                "-"
+            } else if spec.plus {
+                "+"
+            } else {
+                ""
+            },
+            "",
+            i.to_string().trim_start_matches('-').to_string(),
+        ),
+        (Value::Float(f, ty), kind) => {
+            let f = if *ty == FloatType::F32 {
+                (*f as f32) as f64
+            } else {
+                *f
+            };
+            let body = match (spec.precision, kind) {
+                (Some(precision), _) => format!("{:.*}", precision, f.abs()),
+                (None, Some(_)) if *ty == FloatType::F32 => format!("{:?}", f.abs() as f32),
+                (None, Some(_)) => format!("{:?}", f.abs()),
+                (None, None) if *ty == FloatType::F32 => format!("{}", f.abs() as f32),
+                (None, None) => format!("{}", f.abs()),
+            };
+            let negative = f.is_sign_negative() && !f.is_nan();
+            (
+                if negative {
+                    "-"
+                } else if spec.plus {
+                    "+"
+                } else {
+                    ""
+                },
+                "",
+                body,
+            )
+        }
+        (Value::Str(s), Some(_)) => ("", "", format!("{:?}", s)),
+        (Value::Char(c), Some(_)) => ("", "", format!("{:?}", c)),
+        (Value::Str(s), None) => (
+            "",
+            "",
+            match spec.precision {
+                Some(precision) => s.chars().take(precision).collect(),
+                None => s.clone(),
+            },
+        ),
+        (value, _) => ("", "", value.to_string()),
+    };
+
+    let len = sign.chars().count() + prefix.chars().count() + body.chars().count();
+    let padding = spec.width.saturating_sub(len);
+    if spec.zero && numeric {
+        return Ok(format!("{}{}{}{}", sign, prefix, "0".repeat(padding), body));
+    }
+
+    let content = format!("{}{}{}", sign, prefix, body);
+    let fill = |n: usize| spec.fill.to_string().repeat(n);
+    let align = spec.align.unwrap_or(if numeric { '>' } else { '<' });
+    Ok(match align {
+        '<' => format!("{}{}", content, fill(padding)),
+        '^' => format!(
+            "{}{}{}",
+            fill(padding / 2),
+            content,
+            fill(padding - padding / 2)
+        ),
+        _ => format!("{}{}", fill(padding), content),
+    })
+}
+
+fn format_values(
+    template: &str,
+    positional: &[Value],
+    named: &HashMap<String, Value>,
+) -> Eval<String> {
+    let mut output = String::new();
+    let mut chars = template.chars().peekable();
+    let mut next_positional = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return error("unterminated `{` in `format!` string"),
+                    }
+                }
+
+                let (arg, spec) = match placeholder.find(':') {
+                    Some(idx) => (&placeholder[..idx], &placeholder[idx + 1..]),
+                    None => (placeholder.as_str(), ""),
+                };
+                let value = if arg.is_empty() {
+                    next_positional += 1;
+                    positional.get(next_positional - 1)
+                } else if let Ok(idx) = arg.parse::<usize>() {
+                    positional.get(idx)
+                } else {
+                    named.get(arg)
+                };
+                let value = match value {
+                    Some(value) => value,
+                    None => return error(format!("missing `format!` argument for `{{{}}}`", arg)),
+                };
+
+                let spec = match parse_format_spec(spec) {
+                    Ok(spec) => spec,
+                    Err(message) => return error(message),
+                };
+                match format_one(value, &spec) {
+                    Ok(formatted) => output.push_str(&formatted),
+                    Err(message) => return error(message),
+                }
+            }
+            '}' => return error("unmatched `}` in `format!` string"),
+            c => output.push(c),
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConstSqlFunctions;
+
+    fn functions(sources: &[&str]) -> ConstSqlFunctions {
+        let mut functions = ConstSqlFunctions::default();
+        for source in sources {
+            functions
+                .insert(syn::parse_str(source).expect("invalid test function"))
+                .expect("duplicate test function");
+        }
+        functions
+    }
+
+    fn eval(sources: &[&str], placeholder: &str) -> Result<String, String> {
+        functions(sources).evaluate_placeholder(placeholder)
+    }
+
+    #[test]
+    fn expand_without_placeholders() {
+        let sql = "SELECT '{1,2}'::int[];\n";
+        assert_eq!(functions(&[]).expand(sql).ok().as_deref(), Some(sql));
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let functions = functions(&[
+            "fn two() -> i32 { 2 }",
+            "fn double(x: i32) -> i32 { x * 2 }",
+        ]);
+        assert_eq!(
+            functions
+                .expand("SELECT {{ two() }}, {{double(two())}};")
+                .ok()
+                .as_deref(),
+            Some("SELECT 2, 4;")
+        );
+    }
+
+    #[test]
+    fn expand_errors() {
+        let functions = functions(&["fn two() -> i32 { 2 }"]);
+
+        let e = functions
+            .expand("SELECT 1;\nSELECT {{ two()")
+            .err()
+            .unwrap();
+        assert_eq!(e.offset, 17);
+        assert_eq!(e.message, "unterminated `{{` placeholder");
+
+        let e = functions.expand("SELECT {{ 1 + 1 }}").err().unwrap();
+        assert_eq!(e.offset, 7);
+        assert_eq!(
+            e.message,
+            "`{{ 1 + 1 }}`: placeholders must call a #[pg_extern(const_sql)] function"
+        );
+
+        let e = functions.expand("{{ three() }}").err().unwrap();
+        assert_eq!(
+            e.message,
+            "`{{ three() }}`: `three` is not a #[pg_extern(const_sql)] function"
+        );
+
+        let e = functions.expand("{{ two( }}").err().unwrap();
+        assert!(e.message.contains("not a valid Rust expression"));
+    }
+
+    #[test]
+    fn duplicate_functions() {
+        let mut functions = functions(&["fn two() -> i32 { 2 }"]);
+        let again = syn::parse_str("fn two() -> i32 { 3 }").unwrap();
+        assert_eq!(
+            functions.insert(again),
+            Err("#[pg_extern(const_sql)] function `two` is defined more than once".to_string())
+        );
+    }
+
+    #[test]
+    fn arguments() {
+        let source = "fn add(a: i64, b: i64) -> i64 { a + b }";
+        assert_eq!(eval(&[source], "add(40, 2)"), Ok("42".to_string()));
+        assert_eq!(
+            eval(&[source], "add(1)"),
+            Err("`add` takes 2 argument(s) but 1 were supplied".to_string())
+        );
+        assert_eq!(
+            eval(&[source], "add(1, \"two\")"),
+            Err("expected a value of type `i64`, found string".to_string())
+        );
+        assert_eq!(
+            eval(&["fn small(x: u8) -> u8 { x }"], "small(256)"),
+            Err("256 is out of range for `u8`".to_string())
+        );
+    }
+
+    #[test]
+    fn integer_types() {
+        assert_eq!(
+            eval(&["fn f() -> u8 { let x: u8 = 255; x + 1 }"], "f()"),
+            Err("attempt to add with overflow".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> u8 { 255u8.wrapping_add(2) }"], "f()"),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> i32 { -1i32 as u16 as i32 }"], "f()"),
+            Ok("65535".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> i8 { 300.5f64 as i8 }"], "f()"),
+            Ok("127".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> u32 { 1u32 << 31 >> 3 }"], "f()"),
+            Ok("268435456".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> i64 { i64::MAX }"], "f()"),
+            Ok("9223372036854775807".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> i32 { 7 / 0 }"], "f()"),
+            Err("attempt to divide by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = r#"
+            fn classify(n: i32) -> String {
+                let mut kind = String::new();
+                match n {
+                    i32::MIN..=-1 => kind.push_str("negative"),
+                    0 => kind.push_str("zero"),
+                    1 | 2 | 3 => kind.push_str("small"),
+                    n if n % 2 == 0 => kind.push_str("even"),
+                    _ => kind.push_str("odd"),
+                }
+                kind
+            }
+        "#;
+        for (n, kind) in &[
+            ("-5", "negative"),
+            ("0", "zero"),
+            ("2", "small"),
+            ("10", "even"),
+            ("11", "odd"),
+        ] {
+            assert_eq!(
+                eval(&[source], &format!("classify({})", n)),
+                Ok(kind.to_string())
+            );
+        }
+
+        let source = r#"
+            fn first_square_over(limit: i64) -> i64 {
+                let mut i = 0;
+                loop {
+                    i += 1;
+                    if i * i <= limit {
+                        continue;
+                    }
+                    break i * i;
+                }
+            }
+        "#;
+        assert_eq!(
+            eval(&[source], "first_square_over(50)"),
+            Ok("64".to_string())
+        );
+
+        let source = r#"
+            fn sum_evens(n: i64) -> i64 {
+                let mut sum = 0;
+                for i in (0..=n).rev().step_by(2) {
+                    if i % 2 == 1 {
+                        return -1;
+                    }
+                    sum += i;
+                }
+                sum
+            }
+        "#;
+        assert_eq!(eval(&[source], "sum_evens(10)"), Ok("30".to_string()));
+        assert_eq!(eval(&[source], "sum_evens(9)"), Ok("-1".to_string()));
+    }
+
+    #[test]
+    fn strings() {
+        let source = r#"
+            fn shout(s: &str) -> String {
+                let mut out = String::new();
+                for c in s.chars() {
+                    if c.is_alphanumeric() {
+                        out.push(c.to_ascii_uppercase());
+                    }
+                }
+                out + "!"
+            }
+        "#;
+        assert_eq!(eval(&[source], "shout(\"a b-c\")"), Ok("ABC!".to_string()));
+
+        let source = r#"fn quoted(s: &str) -> String { format!("'{}'", s.replace("'", "''")) }"#;
+        assert_eq!(
+            eval(&[source], "quoted(\"it's\")"),
+            Ok("'it''s'".to_string())
+        );
+    }
+
+    #[test]
+    fn format_specs() {
+        let cases = &[
+            (r#"format!("{:#010x}", 255)"#, "0x000000ff"),
+            (r#"format!("{:x}", -1i8)"#, "ff"),
+            (
+                r#"format!("{:>5}|{:<5}|{:^5}", 1, 2, 3)"#,
+                "    1|2    |  3  ",
+            ),
+            (r#"format!("{:*^7}", "ab")"#, "**ab***"),
+            (r#"format!("{:+.2}", 3.14159)"#, "+3.14"),
+            (r#"format!("{:05}", -42)"#, "-0042"),
+            (r#"format!("{:?}", "sql")"#, "\"sql\""),
+            (r#"format!("{0}{1}{0}", "a", "b")"#, "aba"),
+            (r#"format!("{x}-{y}", x = 1, y = 2)"#, "1-2"),
+            (r#"format!("{{{}}}", 1)"#, "{1}"),
+        ];
+        for (expr, expected) in cases {
+            let source = format!("fn f() -> String {{ {} }}", expr);
+            assert_eq!(
+                eval(&[source.as_str()], "f()"),
+                Ok(expected.to_string()),
+                "{}",
+                expr
+            );
+        }
+
+        assert_eq!(
+            eval(&[r#"fn f() -> String { format!("{}") }"#], "f()"),
+            Err("missing `format!` argument for `{}`".to_string())
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let source = "fn fact(n: u64) -> u64 { if n <= 1 { 1 } else { n * fact(n - 1) } }";
+        assert_eq!(
+            eval(&[source], "fact(20)"),
+            Ok("2432902008176640000".to_string())
+        );
+        assert_eq!(
+            eval(&[source], "fact(21)"),
+            Err("attempt to multiply with overflow".to_string())
+        );
+
+        let source = "fn forever(n: i64) -> i64 { forever(n + 1) }";
+        assert_eq!(
+            eval(&[source], "forever(0)"),
+            Err("recursion limit reached calling `forever`".to_string())
+        );
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            eval(&["fn spin() -> i32 { loop {} }"], "spin()"),
+            Err("exceeded 10000000 loop iterations".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> i32 { unsafe { 1 } }"], "f()"),
+            Err("`unsafe` is not allowed".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() { }"], "f()"),
+            Err("function did not return a value".to_string())
+        );
+        assert_eq!(
+            eval(&["fn f() -> i32 { println!(\"hi\"); 1 }"], "f()"),
+            Err("unsupported macro `println!`".to_string())
+        );
+    }
+}
@@ -4,7 +4,7 @@
 use pgx_utils::{exit_with_error, handle_result};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 pub fn get_property(name: &str) -> Option<String> {
@@ -16,11 +16,28 @@ pub fn get_property(name: &str) -> Option<String> {
         return determine_git_hash();
     }
 
-    let control_file = File::open(control_file).unwrap();
+    read_control_properties(&control_file)
+        .into_iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v)
+}
+
+/// The `name = value` parameters of a control file.  Lines that aren't a single `name = value`
+/// are skipped, rather than being an error, and the quotes around a value are removed
+pub(crate) fn read_control_properties(control_file: &Path) -> Vec<(String, String)> {
+    let control_file = handle_result!(
+        File::open(control_file),
+        format!("failed to open {}", control_file.display())
+    );
     let reader = BufReader::new(control_file);
 
+    let mut properties = Vec::new();
     for line in reader.lines() {
         let line = line.unwrap();
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
         let parts: Vec<&str> = line.split('=').collect();
 
         if parts.len() != 2 {
@@ -28,15 +45,12 @@ pub fn get_property(name: &str) -> Option<String> {
         }
 
         let (k, v) = (parts.get(0).unwrap().trim(), parts.get(1).unwrap().trim());
-
-        if k == name {
-            let v = v.trim_start_matches('\'');
-            let v = v.trim_end_matches('\'');
-            return Some(v.trim().to_string());
-        }
+        let v = v.trim_start_matches('\'');
+        let v = v.trim_end_matches('\'');
+        properties.push((k.to_string(), v.trim().to_string()));
     }
 
-    None
+    properties
 }
 
 pub(crate) fn find_control_file() -> (PathBuf, String) {
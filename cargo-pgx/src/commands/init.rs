@@ -73,9 +73,8 @@ pub(crate) fn init_pgx(pgx: &Pgx) -> std::result::Result<(), std::io::Error> {
         validate_pg_config(pg_config)?;
 
         let datadir = pg_config.data_dir()?;
-        let bindir = pg_config.bin_dir()?;
         if !datadir.exists() {
-            initdb(&bindir, &datadir)?;
+            initdb(pg_config, &datadir)?;
         }
     }
 
@@ -328,13 +327,13 @@ fn get_pg_installdir(pgdir: &PathBuf) -> PathBuf {
     dir
 }
 
-pub(crate) fn initdb(bindir: &PathBuf, datadir: &PathBuf) -> Result<(), std::io::Error> {
+pub(crate) fn initdb(pg_config: &PgConfig, datadir: &PathBuf) -> Result<(), std::io::Error> {
     println!(
         " {} data directory at {}",
         "Initializing".bold().green(),
         datadir.display()
     );
-    let mut command = std::process::Command::new(format!("{}/initdb", bindir.display()));
+    let mut command = std::process::Command::new(pg_config.initdb_path()?);
     command
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
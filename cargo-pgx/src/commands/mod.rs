@@ -2,6 +2,7 @@
 // governed by the MIT license that can be found in the LICENSE file.
 
 pub(crate) mod connect;
+pub(crate) mod const_sql;
 pub(crate) mod get;
 pub(crate) mod init;
 pub(crate) mod install;
@@ -1,11 +1,17 @@
 // Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
 // governed by the MIT license that can be found in the LICENSE file.
 
-use crate::commands::get::get_property;
+use crate::commands::const_sql::ConstSqlFunctions;
+use crate::commands::get::{find_control_file, get_property, read_control_properties};
+use pgx_utils::block_extern::*;
+use pgx_utils::control_file::ControlFile;
+use pgx_utils::copy_text_common::*;
+use pgx_utils::create_trigger::CreateTrigger;
+use pgx_utils::function_manifest::{FunctionManifest, FunctionSignature};
 use pgx_utils::operator_common::*;
 use pgx_utils::{
-    categorize_type, exit_with_error, get_named_capture, handle_result, CategorizedType,
-    ExternArgs, FunctionArgs,
+    categorize_type, exit_with_error, extract_result_ok_type, get_named_capture, handle_result,
+    CategorizedType, ExtensionConfigTable, ExternArgs, FunctionArgs,
 };
 use proc_macro2::{Ident, Span, TokenTree};
 use quote::quote;
@@ -57,21 +63,69 @@ enum DeriveMacros {
     PostgresHash,
 }
 
-pub(crate) fn generate_schema(features: &[&str]) -> Result<(), std::io::Error> {
+/// Generate the extension's schema into `./sql/`.
+///
+/// With `dev` (or the `PGX_SCHEMA_DEV` environment variable set), the generated SQL also drops
+/// the functions whose signature changed since the last time the schema was generated, so it can
+/// be re-applied to an existing development database.  The signatures are remembered in
+/// `./sql/functions.manifest`, which is only read and written in dev mode.
+pub(crate) fn generate_schema(features: &[&str], dev: bool) -> Result<(), std::io::Error> {
     let mut features: HashSet<String> = features.into_iter().map(|s| s.to_string()).collect();
     if let Ok(pg_features) = std::env::var("PGX_BUILD_FEATURES") {
         features.extend(pg_features.split(' ').map(|s| s.to_string()))
     }
     let path = PathBuf::from_str("./src").unwrap();
     let files = find_rs_files(&path, Vec::new());
+
+    // the control file is written first, as the rest of the schema depends on it
+    let control = collect_extension_control(&files, &features);
+    if let Some(control) = &control {
+        write_control_file(control);
+    }
     let default_schema = get_property("schema").unwrap_or_else(|| "public".to_string());
 
+    let const_sql = collect_const_sql_functions(&files, &features);
+
+    let dev = dev || std::env::var("PGX_SCHEMA_DEV").is_ok();
+    let manifest_filename = PathBuf::from_str("./sql/functions.manifest").unwrap();
+    let previous_manifest = if dev {
+        match FunctionManifest::read(&manifest_filename) {
+            Ok(manifest) => manifest,
+            Err(e) => exit_with_error!(e),
+        }
+    } else {
+        FunctionManifest::default()
+    };
+
     delete_generated_sql();
 
+    let mut manifest = FunctionManifest::default();
+    let generated = files
+        .iter()
+        .map(|f: &DirEntry| {
+            let statements = generate_sql(
+                f,
+                default_schema.clone(),
+                &features,
+                &const_sql,
+                &mut manifest,
+            );
+            (f, statements)
+        })
+        .collect::<Vec<_>>();
+
+    // we can only know what changed once every file has been generated, as a function can move
+    // between files
+    let mut changes = manifest.changes_since(&previous_manifest);
+
     let mut created = Vec::new();
-    files.iter().for_each(|f: &DirEntry| {
-        let statemets = generate_sql(f, default_schema.clone(), &features);
-        let (did_write, filename) = write_sql_file(f, statemets);
+    generated.into_iter().for_each(|(f, statements)| {
+        let statements = if dev {
+            changes.insert_drops(statements)
+        } else {
+            statements
+        };
+        let (did_write, filename) = write_sql_file(f, statements);
 
         // strip the leading ./sql/ from the filenames we generated
         let mut filename = filename.display().to_string();
@@ -82,6 +136,69 @@ pub(crate) fn generate_schema(features: &[&str]) -> Result<(), std::io::Error> {
         }
     });
 
+    let triggers = collect_triggers(&files, &features);
+    if !triggers.is_empty() {
+        let filename = PathBuf::from_str(&format!("./sql/{}", TRIGGERS_FILENAME)).unwrap();
+        handle_result!(
+            std::fs::write(&filename, triggers.join("\n") + "\n"),
+            format!("failed to write {}", filename.display())
+        );
+        created.push(TRIGGERS_FILENAME.to_string());
+    }
+
+    let config_tables = collect_config_tables(&files, &features);
+    if !config_tables.is_empty() {
+        let filename = PathBuf::from_str(&format!("./sql/{}", CONFIG_TABLES_FILENAME)).unwrap();
+        handle_result!(
+            std::fs::write(&filename, config_tables.join("\n") + "\n"),
+            format!("failed to write {}", filename.display())
+        );
+        created.push(CONFIG_TABLES_FILENAME.to_string());
+    }
+
+    if dev && !changes.removed.is_empty() {
+        let filename = PathBuf::from_str("./sql/dropped-functions.generated.sql").unwrap();
+        let statements = changes
+            .removed
+            .iter()
+            .map(FunctionSignature::drop_statement)
+            .collect::<Vec<_>>();
+        handle_result!(
+            std::fs::write(&filename, statements.join("\n") + "\n"),
+            format!("failed to write {}", filename.display())
+        );
+        created.push("dropped-functions.generated.sql".to_string());
+    }
+
+    if let Some(control) = &control {
+        let violations = created
+            .iter()
+            .flat_map(|filename| {
+                let sql = handle_result!(
+                    std::fs::read_to_string(format!("./sql/{}", filename)),
+                    format!("failed to read ./sql/{}", filename)
+                );
+                control
+                    .relocatable_violations(&sql)
+                    .into_iter()
+                    .map(move |violation| format!("./sql/{}: {}", filename, violation))
+            })
+            .collect::<Vec<_>>();
+        if !violations.is_empty() {
+            exit_with_error!(
+                "the extension is `relocatable`, but its schema can't be relocated:\n{}",
+                violations.join("\n")
+            );
+        }
+    }
+
+    if dev {
+        handle_result!(
+            manifest.write(&manifest_filename),
+            format!("failed to write {}", manifest_filename.display())
+        );
+    }
+
     process_schema_load_order(created);
 
     Ok(())
@@ -104,6 +221,18 @@ fn process_schema_load_order(mut created: Vec<String>) {
     created.sort();
     load_order.append(&mut created);
 
+    // triggers can only be created once their tables and functions have been, wherever that is
+    if let Some(idx) = load_order.iter().position(|v| v == TRIGGERS_FILENAME) {
+        let triggers = load_order.remove(idx);
+        load_order.push(triggers);
+    }
+
+    // configuration tables can only be marked once they've been created, wherever that is
+    if let Some(idx) = load_order.iter().position(|v| v == CONFIG_TABLES_FILENAME) {
+        let config_tables = load_order.remove(idx);
+        load_order.push(config_tables);
+    }
+
     // rewrite the load_order file
     let mut file = handle_result!(
         std::fs::File::create(&filename),
@@ -237,6 +366,291 @@ fn delete_generated_sql() {
     }
 }
 
+/// Find every `#[pg_extern(const_sql)]` function in the extension so `extension_sql!` blocks in any
+/// file can call them
+fn collect_const_sql_functions(
+    files: &[DirEntry],
+    features: &HashSet<String>,
+) -> ConstSqlFunctions {
+    fn collect(items: Vec<Item>, features: &HashSet<String>, const_sql: &mut ConstSqlFunctions) {
+        for item in items {
+            if !is_active(&item, features) {
+                continue;
+            }
+
+            match item {
+                Item::Mod(module) => {
+                    if let Some((_, items)) = module.content {
+                        collect(items, features, const_sql);
+                    }
+                }
+                Item::Fn(func) => {
+                    let is_const_sql = func.attrs.iter().any(|a| {
+                        a.path
+                            .segments
+                            .last()
+                            .map_or(false, |segment| segment.ident == "pg_extern")
+                            && parse_extern_args(a).contains(&ExternArgs::ConstSql)
+                    });
+
+                    if is_const_sql {
+                        if let Err(e) = const_sql.insert(func) {
+                            exit_with_error!(e)
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut const_sql = ConstSqlFunctions::default();
+    for f in files {
+        let file = std::fs::read_to_string(f.path()).unwrap();
+        let ast = syn::parse_file(file.as_str()).unwrap();
+        collect(ast.items, features, &mut const_sql);
+    }
+    const_sql
+}
+
+/// The generated file of `extension_config_table!()` calls, which is always loaded last
+const CONFIG_TABLES_FILENAME: &str = "extension-config.generated.sql";
+
+/// Find every `extension_config_table!()` in the extension, and make the statements that mark
+/// their tables as configuration tables
+fn collect_config_tables(files: &[DirEntry], features: &HashSet<String>) -> Vec<String> {
+    fn collect(
+        rs_file: &DirEntry,
+        items: Vec<Item>,
+        features: &HashSet<String>,
+        sql: &mut Vec<String>,
+    ) {
+        for item in items {
+            if !is_active(&item, features) {
+                continue;
+            }
+
+            match item {
+                Item::Mod(module) => {
+                    if let Some((_, items)) = module.content {
+                        collect(rs_file, items, features, sql);
+                    }
+                }
+                Item::Macro(makro)
+                    if makro
+                        .mac
+                        .path
+                        .segments
+                        .last()
+                        .map_or(false, |segment| segment.ident == "extension_config_table") =>
+                {
+                    let span = makro.mac.span();
+                    match ExtensionConfigTable::parse(makro.mac.tokens) {
+                        Ok(config_table) => {
+                            sql.push(location_comment(rs_file, &span));
+                            sql.push(config_table.to_sql());
+                        }
+                        Err((span, message)) => exit_with_error!(
+                            "{}:{}:{}: {}",
+                            rs_file.path().display(),
+                            span.start().line,
+                            span.start().column + 1,
+                            message
+                        ),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut sql = Vec::new();
+    for f in files {
+        let file = std::fs::read_to_string(f.path()).unwrap();
+        let ast = syn::parse_file(file.as_str()).unwrap();
+        collect(f, ast.items, features, &mut sql);
+    }
+    sql
+}
+
+/// `pgx_fn_stats()` and `pgx_fn_stats_reset()`, whose C functions `fn_stats_functions!()` exports
+fn fn_stats_sql(schema: &str) -> Vec<String> {
+    vec![
+        format!(
+            "CREATE OR REPLACE FUNCTION {}() RETURNS TABLE (name text, calls bigint, total_ms double precision, mean_ms double precision, total_alloc_bytes bigint) VOLATILE PARALLEL RESTRICTED LANGUAGE c AS 'MODULE_PATHNAME', 'pgx_fn_stats_wrapper';",
+            qualify_name(schema, "pgx_fn_stats")
+        ),
+        format!(
+            "CREATE OR REPLACE FUNCTION {}() RETURNS void VOLATILE PARALLEL RESTRICTED LANGUAGE c AS 'MODULE_PATHNAME', 'pgx_fn_stats_reset_wrapper';",
+            qualify_name(schema, "pgx_fn_stats_reset")
+        ),
+    ]
+}
+
+/// The generated file of `create_trigger!()` calls, which is loaded after everything but the
+/// configuration tables
+const TRIGGERS_FILENAME: &str = "triggers.generated.sql";
+
+/// Find every `create_trigger!()` in the extension, and make their `CREATE TRIGGER` statements
+fn collect_triggers(files: &[DirEntry], features: &HashSet<String>) -> Vec<String> {
+    fn collect(
+        rs_file: &DirEntry,
+        items: Vec<Item>,
+        features: &HashSet<String>,
+        sql: &mut Vec<String>,
+    ) {
+        for item in items {
+            if !is_active(&item, features) {
+                continue;
+            }
+
+            match item {
+                Item::Mod(module) => {
+                    if let Some((_, items)) = module.content {
+                        collect(rs_file, items, features, sql);
+                    }
+                }
+                Item::Macro(makro)
+                    if makro
+                        .mac
+                        .path
+                        .segments
+                        .last()
+                        .map_or(false, |segment| segment.ident == "create_trigger") =>
+                {
+                    let span = makro.mac.span();
+                    match CreateTrigger::parse(makro.mac.tokens) {
+                        Ok(trigger) => {
+                            sql.push(location_comment(rs_file, &span));
+                            sql.push(trigger.to_sql());
+                        }
+                        Err((span, message)) => exit_with_error!(
+                            "{}:{}:{}: {}",
+                            rs_file.path().display(),
+                            span.start().line,
+                            span.start().column + 1,
+                            message
+                        ),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut sql = Vec::new();
+    for f in files {
+        let file = std::fs::read_to_string(f.path()).unwrap();
+        let ast = syn::parse_file(file.as_str()).unwrap();
+        collect(f, ast.items, features, &mut sql);
+    }
+    sql
+}
+
+/// Find the extension's `extension_control!()`, if it has one
+fn collect_extension_control(
+    files: &[DirEntry],
+    features: &HashSet<String>,
+) -> Option<ControlFile> {
+    fn collect(
+        rs_file: &DirEntry,
+        items: Vec<Item>,
+        features: &HashSet<String>,
+        control: &mut Option<ControlFile>,
+    ) {
+        for item in items {
+            if !is_active(&item, features) {
+                continue;
+            }
+
+            match item {
+                Item::Mod(module) => {
+                    if let Some((_, items)) = module.content {
+                        collect(rs_file, items, features, control);
+                    }
+                }
+                Item::Macro(makro)
+                    if makro
+                        .mac
+                        .path
+                        .segments
+                        .last()
+                        .map_or(false, |segment| segment.ident == "extension_control") =>
+                {
+                    let span = makro.mac.span();
+                    if control.is_some() {
+                        exit_with_error!(
+                            "{}:{}:{}: an extension can only have one `extension_control!()`",
+                            rs_file.path().display(),
+                            span.start().line,
+                            span.start().column + 1
+                        );
+                    }
+
+                    match ControlFile::from_tokens(makro.mac.tokens) {
+                        Ok(parsed) => *control = Some(parsed),
+                        Err((span, message)) => exit_with_error!(
+                            "{}:{}:{}: {}",
+                            rs_file.path().display(),
+                            span.start().line,
+                            span.start().column + 1,
+                            message
+                        ),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut control = None;
+    for f in files {
+        let file = std::fs::read_to_string(f.path()).unwrap();
+        let ast = syn::parse_file(file.as_str()).unwrap();
+        collect(f, ast.items, features, &mut control);
+    }
+    control
+}
+
+/// Replace the extension's `.control` file with the one `extension_control!()` declares
+fn write_control_file(control: &ControlFile) {
+    let (filename, extname) = find_control_file();
+
+    let mut control = control.clone();
+    if control.module_pathname.is_none() {
+        control.module_pathname = Some(format!("$libdir/{}", extname));
+    }
+
+    // keep the parameters `extension_control!()` doesn't know about, such as `directory` or
+    // `encoding`, that were added to the file by hand
+    let known = [
+        "comment",
+        "default_version",
+        "module_pathname",
+        "schema",
+        "requires",
+        "relocatable",
+        "superuser",
+        "trusted",
+    ];
+    for (name, value) in read_control_properties(&filename) {
+        if !known.contains(&name.as_str()) && !control.other.iter().any(|(k, _)| *k == name) {
+            control.other.push((name, value));
+        }
+    }
+
+    handle_result!(
+        std::fs::write(
+            &filename,
+            format!(
+                "# generated by `cargo pgx schema` from `extension_control!()`, so change that instead\n{}",
+                control
+            )
+        ),
+        format!("failed to write {}", filename.display())
+    );
+}
+
 fn parse_extern_args(att: &Attribute) -> BTreeSet<ExternArgs> {
     pgx_utils::parse_extern_attributes(att.tokens.clone())
         .into_iter()
@@ -247,6 +661,8 @@ fn generate_sql(
     rs_file: &DirEntry,
     default_schema: String,
     features: &HashSet<String>,
+    const_sql: &ConstSqlFunctions,
+    manifest: &mut FunctionManifest,
 ) -> Vec<String> {
     let mut sql = Vec::new();
     let file = std::fs::read_to_string(rs_file.path()).unwrap();
@@ -262,6 +678,8 @@ fn generate_sql(
         &mut schema_stack,
         &default_schema,
         features,
+        const_sql,
+        manifest,
     );
 
     sql
@@ -275,6 +693,8 @@ fn walk_items(
     schema_stack: &mut Vec<String>,
     default_schema: &str,
     features: &HashSet<String>,
+    const_sql: &ConstSqlFunctions,
+    manifest: &mut FunctionManifest,
 ) {
     let mut sql = Vec::new();
     let mut postgres_enums = Vec::new();
@@ -299,6 +719,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
                 schema_stack.pop();
             }
@@ -322,6 +744,12 @@ fn walk_items(
                 }
             }
 
+            // `#[derive(PostgresOrd)]` only gives types with abbreviated keys sort support
+            let has_sort_abbreviation = strct
+                .attrs
+                .iter()
+                .any(|a| a.path.is_ident("sort_abbreviation"));
+
             if derives.contains(&DeriveMacros::PostgresType) {
                 let name = strct.ident.to_string().to_lowercase();
                 postgres_types.push(format!(
@@ -341,6 +769,31 @@ fn walk_items(
                 ));
             }
 
+            if derives.contains(&DeriveMacros::PostgresType)
+                && strct
+                    .attrs
+                    .iter()
+                    .any(|a| a.path.is_ident("copytextformat"))
+            {
+                let pgvarlena = strct
+                    .attrs
+                    .iter()
+                    .any(|a| a.path.is_ident("pgvarlena_inoutfuncs"));
+                walk_items(
+                    rs_file,
+                    &mut sql,
+                    vec![
+                        parse_item(to_copy_text(&strct.ident, pgvarlena)),
+                        parse_item(from_copy_text(&strct.ident, pgvarlena)),
+                    ],
+                    schema_stack,
+                    default_schema,
+                    features,
+                    const_sql,
+                    manifest,
+                );
+            }
+
             if derives.contains(&DeriveMacros::PostgresEq) {
                 walk_items(
                     rs_file,
@@ -349,6 +802,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
                 walk_items(
                     rs_file,
@@ -357,6 +812,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
             }
 
@@ -368,6 +825,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
                 walk_items(
                     rs_file,
@@ -376,6 +835,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
                 walk_items(
                     rs_file,
@@ -384,6 +845,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
                 walk_items(
                     rs_file,
@@ -392,6 +855,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
                 walk_items(
                     rs_file,
@@ -400,7 +865,21 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
+                if has_sort_abbreviation {
+                    walk_items(
+                        rs_file,
+                        &mut operator_sql,
+                        vec![parse_item(sortsupport(&strct.ident))],
+                        schema_stack,
+                        default_schema,
+                        features,
+                        const_sql,
+                        manifest,
+                    );
+                }
             }
 
             if derives.contains(&DeriveMacros::PostgresHash) {
@@ -411,6 +890,8 @@ fn walk_items(
                     schema_stack,
                     default_schema,
                     features,
+                    const_sql,
+                    manifest,
                 );
 
                 let type_name = &strct.ident.to_string().to_lowercase();
@@ -434,6 +915,14 @@ fn walk_items(
                     "CREATE OPERATOR FAMILY {}_btree_ops USING btree;",
                     type_name
                 ));
+                let sortsupport = if has_sort_abbreviation {
+                    format!(
+                        ",\n                      FUNCTION 2 {}_sortsupport(internal)",
+                        type_name
+                    )
+                } else {
+                    String::new()
+                };
                 operator_sql.push(format!(
                     "CREATE OPERATOR CLASS {type_name}_btree_ops DEFAULT FOR TYPE {type_name} USING btree FAMILY {type_name}_btree_ops AS
                       OPERATOR 1 < ,
@@ -441,8 +930,9 @@ fn walk_items(
                       OPERATOR 3 = ,
                       OPERATOR 4 >= ,
                       OPERATOR 5 > ,
-                      FUNCTION 1 {type_name}_cmp({type_name}, {type_name});",
-                    type_name = type_name
+                      FUNCTION 1 {type_name}_cmp({type_name}, {type_name}){sortsupport};",
+                    type_name = type_name,
+                    sortsupport = sortsupport
                 ));
             }
         } else if let Item::Enum(enm) = item {
@@ -480,23 +970,117 @@ fn walk_items(
             };
 
             if name.ends_with("extension_sql") {
-                let string = makro.mac.tokens.to_string();
+                let mut tokens = makro.mac.tokens.clone().into_iter();
+                let string = tokens.next().map(|t| t.to_string()).unwrap_or_default();
                 let string = string.trim();
 
                 if !string.starts_with("r#\"") || !string.ends_with("\"#") {
                     exit_with_error!("extension_sql!{{}} value isn't ia raw string");
                 }
 
+                // `extension_sql!(r#"..."#, const_sql)` opts the block into placeholder expansion
+                let options = tokens
+                    .map(|t| t.to_string())
+                    .filter(|t| t != ",")
+                    .collect::<Vec<_>>();
+                let expand_const_sql = match options.as_slice() {
+                    [] => false,
+                    [option] if option == "const_sql" => true,
+                    _ => exit_with_error!(
+                        "{}:{}:{}: extension_sql!{{}} only accepts `const_sql` after its raw string",
+                        rs_file.path().display(),
+                        makro.mac.span().start().line,
+                        makro.mac.span().start().column + 1
+                    ),
+                };
+
                 // remove the raw string quotes
                 let string = string.trim_start_matches("r#\"");
                 let string = string.trim_end_matches("\"#");
 
+                // splice in the results of any `{{ const_sql_function(...) }}` placeholders
+                let expanded = if expand_const_sql {
+                    const_sql.expand(string)
+                } else {
+                    Ok(string.to_string())
+                };
+                let string = match expanded {
+                    Ok(string) => string,
+                    Err(e) => {
+                        let start = makro.mac.tokens.span().start();
+
+                        // the template begins just after the literal's opening `r#"`
+                        let before = &string[..e.offset];
+                        let line = start.line + before.matches('\n').count();
+                        let column = match before.rfind('\n') {
+                            Some(newline) => before[newline + 1..].chars().count() + 1,
+                            None => start.column + 3 + before.chars().count() + 1,
+                        };
+                        exit_with_error!(
+                            "{}:{}:{}: {}",
+                            rs_file.path().display(),
+                            line,
+                            column,
+                            e.message
+                        )
+                    }
+                };
+                let string = string.as_str();
+
                 // trim off leading/trailing new lines, but preserve other whitespace
                 let string = string.trim_start_matches('\n');
                 let string = string.trim_end_matches('\n');
 
                 // and remember this sql block
                 sql.push(string.to_string());
+            } else if name.ends_with("guc_settings_function") {
+                let function = match syn::parse2::<syn::Ident>(makro.mac.tokens.clone()) {
+                    Ok(function) => function,
+                    Err(e) => exit_with_error!(
+                        "{}:{}:{}: expected the name of the function: {}",
+                        rs_file.path().display(),
+                        e.span().start().line,
+                        e.span().start().column + 1,
+                        e
+                    ),
+                };
+
+                sql.push(location_comment(rs_file, &makro.mac.span()));
+                sql.push(format!(
+                    "CREATE OR REPLACE FUNCTION {}() RETURNS TABLE (name text, setting text, unit text, context text, short_desc text) STABLE PARALLEL RESTRICTED LANGUAGE c AS 'MODULE_PATHNAME', 'pgx_guc_settings_wrapper';",
+                    qualify_name(&current_schema, &function.to_string())
+                ));
+            } else if name.ends_with("fn_stats_functions") {
+                sql.push(location_comment(rs_file, &makro.mac.span()));
+                sql.extend(fn_stats_sql(&current_schema));
+            }
+        } else if let Item::Impl(block) = item {
+            if let Some(attr) = block.attrs.iter().find(|a| is_block_extern_attribute(a)) {
+                // generate the same functions `#[pg_block_extern]` does, and walk them instead
+                match BlockExternArgs::from_attribute(attr)
+                    .and_then(|args| block_extern_functions(&args, &block))
+                {
+                    Ok(functions) => walk_items(
+                        rs_file,
+                        &mut sql,
+                        functions
+                            .into_iter()
+                            .map(|function| Item::Fn(function.func))
+                            .collect(),
+                        schema_stack,
+                        default_schema,
+                        features,
+                        const_sql,
+                        manifest,
+                    ),
+                    Err((span, message)) => exit_with_error!(
+                        "{}:{}:{}: {}",
+                        rs_file.path().display(),
+                        span.start().line,
+                        span.start().column + 1,
+                        message
+                    ),
+                }
             }
         } else if let Item::Fn(func) = item {
             let attributes = collect_attributes(rs_file, &func.sig.ident, &func.attrs);
@@ -518,6 +1102,7 @@ fn walk_items(
                             &current_schema,
                             schema_stack,
                             &funcargs,
+                            manifest,
                         ) {
                             function_sql.push(location_comment(rs_file, &span));
                             function_sql.push(statement);
@@ -537,6 +1122,7 @@ fn walk_items(
                             &current_schema,
                             schema_stack,
                             &funcargs,
+                            manifest,
                         ) {
                             function_sql.push(location_comment(rs_file, &span));
                             function_sql.push(statement);
@@ -557,6 +1143,7 @@ fn walk_items(
                                 &current_schema,
                                 schema_stack,
                                 &funcargs,
+                                manifest,
                             )
                         {
                             if type_names.len() > 2 {
@@ -768,6 +1355,7 @@ fn make_create_function_statement(
     schema: &str,
     _schema_stack: &Vec<String>, // don't use this, but seems to be a good thing to keep around for the future
     funcargs: &Vec<FunctionArgs>,
+    manifest: &mut FunctionManifest,
 ) -> (Option<String>, Option<String>, Option<Vec<String>>) {
     let exported_func_name = format!("{}_wrapper", func.sig.ident.to_string());
 
@@ -776,6 +1364,19 @@ fn make_create_function_statement(
     let mut sql_func_name =
         extract_funcname_attribute(&attributes).unwrap_or_else(|| quote_ident(&func.sig.ident));
     let mut sql_argument_type_names = Vec::new();
+    let mut out_parameter_sql = Vec::new();
+
+    // only #[pg_extern]/#[pg_operator] functions whose arguments we generated ourselves go in
+    // the manifest, as we can't know the argument types of a hand-written #[sql_args]
+    let in_manifest = extern_args.is_some() && sql_func_arg.is_none();
+
+    // a raw function's Datum is whatever `returns = sql_type!(...)` says it is
+    let declared_return_type = extern_args.as_ref().and_then(|extern_args| {
+        extern_args.iter().find_map(|extern_arg| match extern_arg {
+            ExternArgs::Returns(name) | ExternArgs::ReturnsLookup(name) => Some(name.clone()),
+            _ => None,
+        })
+    });
 
     let mut statement = String::new();
 
@@ -791,6 +1392,8 @@ fn make_create_function_statement(
                 FnArg::Receiver(_) => {
                     exit_with_error!("functions that take 'self' are not supported")
                 }
+                // a window function's partition state isn't an argument of the SQL function
+                FnArg::Typed(ty) if is_mut_reference(&ty.ty) => {}
                 FnArg::Typed(ty) => match translate_type(rs_file, &ty.ty) {
                     Some((type_name, _, default_value, variadic)) => {
                         sql_argument_type_names.push(type_name.to_string());
@@ -831,7 +1434,14 @@ fn make_create_function_statement(
             }
         }
 
-        if had_none && i == 0 {
+        // a function that takes only a `pg_sys::FunctionCallInfo` and returns a raw Datum is
+        // declared by hand, unless it says what it returns.  Others, such as triggers and window
+        // functions, take no arguments
+        let returns_datum = match &func.sig.output {
+            ReturnType::Type(_, ty) => format!("{}", quote! {#ty}) == "pg_sys :: Datum",
+            ReturnType::Default => false,
+        } && declared_return_type.is_none();
+        if had_none && i == 0 && returns_datum {
             let span = &func.span();
             eprintln!(
                 "{}:{}:{}: Could not generate function for {} at  -- it contains only pg_sys::FunctionCallData as its only argument",
@@ -842,31 +1452,47 @@ fn make_create_function_statement(
             );
             return (None, None, None);
         }
+
+        // a function that returns a tuple returns a record made of its OUT parameters
+        for (name, type_name) in out_parameters(func, rs_file) {
+            out_parameter_sql.push(format!("OUT {} {}", name, type_name));
+        }
+        for out_parameter in &out_parameter_sql {
+            if i > 0 {
+                statement.push_str(", ");
+            }
+            statement.push_str(out_parameter);
+            i += 1;
+        }
         statement.push(')');
     }
 
     if !has_option_arg {
-        // there were no Option<T> arguments, so the function can be declared STRICT
+        // there were no Option<T> arguments, so the function can be declared STRICT, except for
+        // a window function, which Postgres calls even when its arguments are NULL
         if let Some(extern_args) = extern_args.borrow_mut() {
-            extern_args.insert(ExternArgs::Strict);
+            if !extern_args.contains(&ExternArgs::Window) {
+                extern_args.insert(ExternArgs::Strict);
+            }
         }
     }
 
     // append RETURNS clause
-    match match &func.sig.output {
-        ReturnType::Default => Some(("void".to_string(), false, None, false)),
-        ReturnType::Type(_, ty) => translate_type(rs_file, ty),
+    let return_type = match match (&declared_return_type, &func.sig.output) {
+        (Some(name), _) => Some((name.clone(), false, None, false)),
+        (None, ReturnType::Default) => Some(("void".to_string(), false, None, false)),
+        (None, ReturnType::Type(_, ty)) => translate_type(rs_file, ty),
     } {
-        Some((return_type, _is_option, _, _)) => {
-            statement.push_str(&format!(" RETURNS {}", return_type))
-        }
+        Some((return_type, _is_option, _, _)) => return_type,
         None => exit_with_error!(
             "could not determine return type for function: {}",
             func.sig.ident
         ),
-    }
+    };
+    statement.push_str(&format!(" RETURNS {}", return_type));
 
     let mut custom_schema = None;
+    let mut renamed_from = Vec::new();
     // modifiers
     if let Some(extern_args) = extern_args {
         for extern_arg in extern_args {
@@ -881,20 +1507,44 @@ fn make_create_function_statement(
                 ExternArgs::ParallelRestricted => statement.push_str(" PARALLEL RESTRICTED"),
                 ExternArgs::Error(_) => { /* noop */ }
                 ExternArgs::NoGuard => {}
+                ExternArgs::ConstSql => { /* noop */ }
+                ExternArgs::Materialize => { /* noop */ }
+                ExternArgs::Window => statement.push_str(" WINDOW"),
+                ExternArgs::Instrument => { /* noop */ }
                 ExternArgs::Schema(s) => custom_schema = Some(s),
                 ExternArgs::Name(n) => sql_func_name = n,
+                ExternArgs::RenamedFrom(old_name) => renamed_from.push(old_name),
+                ExternArgs::Returns(_) | ExternArgs::ReturnsLookup(_) => { /* noop */ }
             }
         }
     }
 
-    statement = format!(
-        "CREATE OR REPLACE FUNCTION {}{}",
-        qualify_name(
-            custom_schema.as_ref().map(|s| &**s).unwrap_or(schema),
-            &sql_func_name
-        ),
-        statement
-    );
+    let function_schema = custom_schema.as_ref().map(|s| &**s).unwrap_or(schema);
+    let qualified_name = qualify_name(function_schema, &sql_func_name);
+    statement = format!("CREATE OR REPLACE FUNCTION {}{}", qualified_name, statement);
+
+    if in_manifest {
+        // a function that returns a tuple is identified by its OUT parameters too, as changing
+        // them changes the record it returns
+        let return_type = if out_parameter_sql.is_empty() {
+            return_type
+        } else {
+            format!("{} ({})", return_type, out_parameter_sql.join(", "))
+        };
+        manifest.insert(FunctionSignature::new(
+            &qualified_name,
+            sql_argument_type_names.clone(),
+            &return_type,
+        ));
+
+        // the old function is in the same schema as the new one, and was named either after its
+        // Rust function, which we quote, or with `name = "..."`, which we don't
+        for old_name in renamed_from {
+            for old_name in vec![quote_ident_string(old_name.clone()), old_name] {
+                manifest.rename(&qualify_name(function_schema, &old_name), &qualified_name);
+            }
+        }
+    }
 
     let mut search_path = String::new();
     for arg in funcargs {
@@ -922,6 +1572,9 @@ fn make_create_function_statement(
 fn func_args_have_option(func: &ItemFn, rs_file: &DirEntry) -> bool {
     for arg in &func.sig.inputs {
         if let FnArg::Typed(ty) = arg {
+            if is_mut_reference(&ty.ty) {
+                continue;
+            }
             if let Some((_, is_option, _, _)) = translate_type(rs_file, &ty.ty) {
                 if is_option {
                     return true;
@@ -933,6 +1586,13 @@ fn func_args_have_option(func: &ItemFn, rs_file: &DirEntry) -> bool {
     false
 }
 
+fn is_mut_reference(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(tref) => tref.mutability.is_some(),
+        _ => false,
+    }
+}
+
 fn quote_ident(ident: &Ident) -> String {
     quote_ident_string(ident.to_string())
 }
@@ -966,6 +1626,11 @@ fn translate_type(filename: &DirEntry, ty: &Type) -> Option<(String, bool, Optio
     let mut default_value = None;
     let mut variadic = false;
 
+    // the `E` of a `Result<T, E>` is raised as an ERROR, so it's the `T` that gets translated
+    if let Some(ok_type) = extract_result_ok_type(ty) {
+        return translate_type(filename, ok_type);
+    }
+
     match ty.deref() {
         Type::Path(path) => match categorize_type(ty) {
             CategorizedType::OptionalIterator(types) => {
@@ -977,6 +1642,7 @@ fn translate_type(filename: &DirEntry, ty: &Type) -> Option<(String, bool, Optio
                 rust_type = format!("{}", quote! {#path});
                 span = path.span();
             }
+            CategorizedType::Tuple(_) => return Some(("record".to_string(), true, None, false)),
             _ => exit_with_error!("found unexpected path type: {:?}", ty),
         },
         Type::Reference(tref) => {
@@ -1008,9 +1674,8 @@ fn translate_type(filename: &DirEntry, ty: &Type) -> Option<(String, bool, Optio
             CategorizedType::Default => {
                 exit_with_error!("{:?} isn't an 'impl Trait' type or a Rust Tuple", ty)
             }
-            CategorizedType::Iterator(types)
-            | CategorizedType::OptionalIterator(types)
-            | CategorizedType::Tuple(types) => {
+            CategorizedType::Tuple(_) => return Some(("record".to_string(), false, None, false)),
+            CategorizedType::Iterator(types) | CategorizedType::OptionalIterator(types) => {
                 rust_type = "Iterator".to_string();
                 span = ty.span();
                 subtype = Some(types);
@@ -1052,6 +1717,92 @@ fn deconstruct_macro(as_string: &str) -> Option<(String, Option<String>, bool)>
     }
 }
 
+/// Split a `name!(ident, Type)` tuple member into its (quoted) SQL name and its Rust type
+fn parse_name_macro(ty: &str, filename: &DirEntry, span: &proc_macro2::Span) -> (String, String) {
+    let parsed_type_with_name =
+        proc_macro2::TokenStream::from_str(ty).expect("failed to parse type name");
+    let mut iter = parsed_type_with_name.into_iter();
+    let (name, ty) = if let Some(tree) = iter.next() {
+        match tree {
+            TokenTree::Ident(_ident) if ty.starts_with("name !") => {
+                let _bang = iter.next();
+                let open_paren = iter.next();
+                match open_paren.unwrap() {
+                    TokenTree::Group(group) => {
+                        let mut iter = group.stream().into_iter();
+
+                        let name = iter.next();
+                        let _comma = iter.next();
+                        let mut ty = proc_macro2::TokenStream::new();
+                        for tree in iter {
+                            match tree {
+                                TokenTree::Punct(punc) => {
+                                    if punc.as_char() != ')' {
+                                        ty.extend(quote! {#punc})
+                                    }
+                                }
+                                other => ty.extend(quote! {#other}),
+                            }
+                        }
+
+                        (name.unwrap().to_string(), ty.to_string())
+                    }
+                    _ => exit_with_error!("malformed name!() macro"),
+                }
+            }
+            _ => exit_with_error!(
+                "No name!() macro specified for tuple member of type: {} at {}",
+                ty,
+                location_comment(filename, span)
+            ),
+        }
+    } else {
+        exit_with_error!("malformed name!() macro")
+    };
+
+    let mut quoted = String::new();
+    if !name.starts_with('"') {
+        quoted.push('"');
+    }
+    quoted.push_str(&name);
+    if !name.ends_with('"') {
+        quoted.push('"');
+    }
+
+    (quoted, ty)
+}
+
+/// The `OUT` parameters of a function that returns a tuple, as `(quoted name, SQL type)` pairs
+fn out_parameters(func: &ItemFn, filename: &DirEntry) -> Vec<(String, String)> {
+    let ty = match &func.sig.output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => return Vec::new(),
+    };
+
+    match categorize_type(ty) {
+        CategorizedType::Tuple(types) => {
+            let span = ty.span();
+            types
+                .into_iter()
+                .map(|ty| {
+                    let (name, ty) = parse_name_macro(&ty, filename, &span);
+                    let translated =
+                        translate_type_string(ty, filename, &span, 1, None, false, None)
+                            .unwrap_or_else(|| {
+                                exit_with_error!(
+                                    "could not translate OUT parameter {} at {}",
+                                    name,
+                                    location_comment(filename, &span)
+                                )
+                            });
+                    (name, translated.0)
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
 fn translate_type_string(
     rust_type: String,
     filename: &DirEntry,
@@ -1067,6 +1818,7 @@ fn translate_type_string(
         "i16" => Some(("smallint".to_string(), false, default_value, variadic)),
         "i32" => Some(("integer".to_string(), false, default_value, variadic)),
         "i64" => Some(("bigint".to_string(), false, default_value, variadic)),
+        "i128" => Some(("numeric".to_string(), false, default_value, variadic)),
         "bool" => Some(("bool".to_string(), false, default_value, variadic)),
         "char" => Some(("varchar".to_string(), false, default_value, variadic)), // a Rust "char" is 4 bytes, so we need to represent that as a varchar
         "f32" => Some(("real".to_string(), false, default_value, variadic)),
@@ -1076,9 +1828,27 @@ fn translate_type_string(
             default_value,
             variadic,
         )),
-        "PgRelation" => Some(("regclass".to_string(), false, default_value, variadic)),
+        "PgRelation" | "pgx :: PgRelation" => {
+            Some(("regclass".to_string(), false, default_value, variadic))
+        }
         "Numeric" => Some(("numeric".to_string(), false, default_value, variadic)),
         "Inet" => Some(("inet".to_string(), false, default_value, variadic)),
+        "IpAddr" | "std :: net :: IpAddr" => {
+            Some(("inet".to_string(), false, default_value, variadic))
+        }
+        "IpNetwork" | "ipnetwork :: IpNetwork" => {
+            Some(("inet".to_string(), false, default_value, variadic))
+        }
+        "Cidr" | "pgx :: Cidr" => Some(("cidr".to_string(), false, default_value, variadic)),
+        "PgBitString" | "pgx :: PgBitString" => {
+            Some(("varbit".to_string(), false, default_value, variadic))
+        }
+        "PgHeapTupleHeader" | "pgx :: PgHeapTupleHeader" => {
+            Some(("record".to_string(), false, default_value, variadic))
+        }
+        "TriggerResult" | "pgx :: TriggerResult" => {
+            Some(("trigger".to_string(), false, default_value, variadic))
+        }
         "Json" => Some(("json".to_string(), false, default_value, variadic)),
         "Jsonb" => Some(("jsonb".to_string(), false, default_value, variadic)),
         "& str" | "& 'static str" | "&'static str" | "String" | "& 'static String" | "& String" => {
@@ -1087,6 +1857,12 @@ fn translate_type_string(
         "& [u8]" | "&[u8]" | "& 'static [u8]" | "&'static [u8]" | "Vec < u8 >" => {
             Some(("bytea".to_string(), false, default_value, variadic))
         }
+        "VarlenaReader" | "pgx :: VarlenaReader" => {
+            Some(("bytea".to_string(), false, default_value, variadic))
+        }
+        "TextReader" | "pgx :: TextReader" => {
+            Some(("text".to_string(), false, default_value, variadic))
+        }
         "& std :: ffi :: CStr" => Some(("cstring".to_string(), false, default_value, variadic)),
         "AnyElement" => Some(("anyelement".to_string(), false, default_value, variadic)),
         "AnyArray" => Some(("anyarray".to_string(), false, default_value, variadic)),
@@ -1117,6 +1893,11 @@ fn translate_type_string(
         )),
         "pg_sys :: BOX" | "BOX" => Some(("box".to_string(), false, default_value, variadic)),
         "pg_sys :: Point" | "Point" => Some(("point".to_string(), false, default_value, variadic)),
+        "PgGeoBox" | "pgx :: PgGeoBox" => Some(("box".to_string(), false, default_value, variadic)),
+        "PgPoint" | "pgx :: PgPoint" => Some(("point".to_string(), false, default_value, variadic)),
+        "PgInterval" | "pgx :: PgInterval" => {
+            Some(("interval".to_string(), false, default_value, variadic))
+        }
         "pg_sys :: Oid" => Some(("oid".to_string(), false, default_value, variadic)),
         "pg_sys :: ItemPointerData" => Some(("tid".to_string(), false, default_value, variadic)),
         "pg_sys :: FunctionCallInfo" => None,
@@ -1156,46 +1937,7 @@ fn translate_type_string(
                 .expect("no iterator subtypes where len != 1")
                 .into_iter()
             {
-                let parsed_type_with_name =
-                    proc_macro2::TokenStream::from_str(&ty).expect("failed to parse type name");
-                let mut iter = parsed_type_with_name.into_iter();
-                let (name, ty) = if let Some(tree) = iter.next() {
-                    match tree {
-                        TokenTree::Ident(_ident) if ty.starts_with("name !") => {
-                            let _bang = iter.next();
-                            let open_paren = iter.next();
-                            match open_paren.unwrap() {
-                                TokenTree::Group(group) => {
-                                    let mut iter = group.stream().into_iter();
-
-                                    let name = iter.next();
-                                    let _comma = iter.next();
-                                    let mut ty = proc_macro2::TokenStream::new();
-                                    for tree in iter {
-                                        match tree {
-                                            TokenTree::Punct(punc) => {
-                                                if punc.as_char() != ')' {
-                                                    ty.extend(quote! {#punc})
-                                                }
-                                            }
-                                            other => ty.extend(quote! {#other}),
-                                        }
-                                    }
-
-                                    (name.unwrap().to_string(), ty.to_string())
-                                }
-                                _ => exit_with_error!("malformed name!() macro"),
-                            }
-                        }
-                        _ => exit_with_error!(
-                            "No name!() macro specified for tuple member of type: {} at {}",
-                            ty,
-                            location_comment(filename, span)
-                        ),
-                    }
-                } else {
-                    exit_with_error!("malformed name!() macro")
-                };
+                let (name, ty) = parse_name_macro(&ty, filename, span);
 
                 let translated = translate_type_string(
                     ty,
@@ -1211,14 +1953,7 @@ fn translate_type_string(
                 if !composite_def.is_empty() {
                     composite_def.push_str(", ");
                 }
-                if !name.starts_with('"') {
-                    composite_def.push('"');
-                }
                 composite_def.push_str(&name);
-                if !name.ends_with('"') {
-                    composite_def.push('"');
-                }
-
                 composite_def.push(' ');
                 composite_def.push_str(&translated.0);
             }
@@ -1258,7 +1993,14 @@ fn translate_type_string(
             type_string.push_str("[]");
             Some((type_string, false, default_value, true))
         }
-        _internal if rust_type.starts_with("Internal <") => {
+        "Internal" | "pgx :: Internal" => {
+            Some(("internal".to_string(), false, default_value, variadic))
+        }
+        _internal
+            if rust_type.starts_with("Internal <")
+                || rust_type.starts_with("pgx :: Internal <")
+                || rust_type.starts_with("InternalBox <") =>
+        {
             Some(("internal".to_string(), false, default_value, variadic))
         }
         _boxed if rust_type.starts_with("PgBox <") => translate_type_string(
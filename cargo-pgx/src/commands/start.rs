@@ -12,11 +12,10 @@ use std::process::Stdio;
 pub(crate) fn start_postgres(pg_config: &PgConfig) -> Result<(), std::io::Error> {
     let datadir = pg_config.data_dir()?;
     let logfile = pg_config.log_file()?;
-    let bindir = pg_config.bin_dir()?;
     let port = pg_config.port()?;
 
     if !datadir.exists() {
-        initdb(&bindir, &datadir)?;
+        initdb(pg_config, &datadir)?;
     }
 
     if status_postgres(pg_config)? {
@@ -29,7 +28,7 @@ pub(crate) fn start_postgres(pg_config: &PgConfig) -> Result<(), std::io::Error>
         pg_config.major_version()?,
         port.to_string().bold().cyan()
     );
-    let mut command = std::process::Command::new(format!("{}/pg_ctl", bindir.display()));
+    let mut command = std::process::Command::new(pg_config.pg_ctl_path()?);
     // Unsafe block is for the pre_exec setsid call below
     //
     // This is to work around a bug in PG10 + PG11 which don't call setsid in pg_ctl
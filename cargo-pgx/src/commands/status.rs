@@ -7,7 +7,6 @@ use std::process::Stdio;
 
 pub(crate) fn status_postgres(pg_config: &PgConfig) -> Result<bool, std::io::Error> {
     let datadir = pg_config.data_dir()?;
-    let bindir = pg_config.bin_dir()?;
 
     if !datadir.exists() {
         // Postgres couldn't possibly be running if there's no data directory
@@ -15,7 +14,7 @@ pub(crate) fn status_postgres(pg_config: &PgConfig) -> Result<bool, std::io::Err
         return Ok(false);
     }
 
-    let mut command = std::process::Command::new(format!("{}/pg_ctl", bindir.display()));
+    let mut command = std::process::Command::new(pg_config.pg_ctl_path()?);
     command
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -10,7 +10,6 @@ use std::process::Stdio;
 pub(crate) fn stop_postgres(pg_config: &PgConfig) -> Result<(), std::io::Error> {
     Pgx::home()?;
     let datadir = pg_config.data_dir()?;
-    let bindir = pg_config.bin_dir()?;
 
     if status_postgres(pg_config)? == false {
         // it's not running, no need to stop it
@@ -23,7 +22,7 @@ pub(crate) fn stop_postgres(pg_config: &PgConfig) -> Result<(), std::io::Error>
         pg_config.major_version()?
     );
 
-    let mut command = std::process::Command::new(format!("{}/pg_ctl", bindir.display()));
+    let mut command = std::process::Command::new(pg_config.pg_ctl_path()?);
     command
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -211,7 +211,7 @@ fn do_it() -> std::result::Result<(), std::io::Error> {
                     .values_of("features")
                     .map(|v| v.collect())
                     .unwrap_or(vec![]);
-                generate_schema(&*features)
+                generate_schema(&*features, schema.is_present("dev"))
             }
             ("dump-schema", Some(dump_schema)) => {
                 let dir = dump_schema
@@ -222,7 +222,7 @@ fn do_it() -> std::result::Result<(), std::io::Error> {
                     .values_of("features")
                     .map(|v| v.collect())
                     .unwrap_or(vec![]);
-                generate_schema(&*features)?;
+                generate_schema(&*features, false)?;
                 write_full_schema_file(&dir, None);
                 Ok(())
             }
@@ -46,6 +46,37 @@ fn add_animal(mut animals: Animals, name: String, age: i32) -> Animals {
     animals
 }
 
+// the same kind of API, as methods:  `animals_count(animals)`, `animals_age_of(animals, name)`,
+// `animals_with_animal(animals, name, age)` and `animals_empty()`
+#[pg_block_extern]
+impl Animals {
+    #[pg_extern(immutable, parallel_safe)]
+    fn count(&self) -> i64 {
+        self.names.len() as i64
+    }
+
+    #[pg_extern(immutable, parallel_safe)]
+    fn age_of(&self, name: &str) -> Option<i32> {
+        self.age_lookup
+            .iter()
+            .find(|(_, animal)| animal.as_str() == name)
+            .map(|(age, _)| *age)
+    }
+
+    #[pg_extern(immutable, parallel_safe)]
+    fn with_animal(self, name: String, age: i32) -> Self {
+        add_animal(self, name, age)
+    }
+
+    #[pg_extern(immutable, parallel_safe)]
+    fn empty() -> Self {
+        Animals {
+            names: Vec::new(),
+            age_lookup: HashMap::new(),
+        }
+    }
+}
+
 #[cfg(any(test, feature = "pg_test"))]
 mod tests {
     use crate::complex::{known_animals, Animals};
@@ -66,4 +97,18 @@ mod tests {
             })
         )
     }
+
+    #[pg_test]
+    fn test_animals_methods() {
+        let count = Spi::get_one::<i64>("SELECT animals_count(known_animals());");
+        assert_eq!(count, Some(3));
+
+        let age = Spi::get_one::<i32>("SELECT animals_age_of(known_animals(), 'Brandy');");
+        assert_eq!(age, Some(4));
+
+        let age = Spi::get_one::<i32>(
+            "SELECT animals_age_of(animals_with_animal(animals_empty(), 'Rex', 7), 'Rex');",
+        );
+        assert_eq!(age, Some(7));
+    }
 }
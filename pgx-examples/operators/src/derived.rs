@@ -15,9 +15,20 @@ use serde::{Deserialize, Serialize};
 /// so that the type can be used in indexes `USING btree`
 #[derive(PostgresOrd)]
 
+/// and have ORDER BY compare the first 8 bytes of each `Thing` before deserializing it, with
+/// the `SortAbbreviation` impl below
+#[sort_abbreviation]
+
 /// automatically generate a "_hash" function, and the necessary "opclass" (and family)
 /// so the type can also be used in indexes `USING hash`
 #[derive(PostgresHash)]
 pub struct Thing(String);
 
-// and there's no code to write!
+// and there's no code to write, except to abbreviate a `Thing` for sorting
+impl SortAbbreviation for Thing {
+    const ABBREVIATED: bool = true;
+
+    fn abbreviate(&self) -> i64 {
+        pgx::sortsupport::abbreviate_bytes(self.0.as_bytes())
+    }
+}
@@ -33,7 +33,10 @@ pub fn pg_guard(_attr: TokenStream, item: TokenStream) -> TokenStream {
         // process top-level functions
         // these functions get wrapped as public extern "C" functions with #[no_mangle] so they
         // can also be called from C code
-        Item::Fn(func) => rewriter.item_fn(func, false, false, false).0.into(),
+        Item::Fn(func) => rewriter
+            .item_fn(func, false, false, false, false, false, None)
+            .0
+            .into(),
         _ => {
             panic!("#[pg_guard] can only be applied to extern \"C\" blocks and top-level functions")
         }
@@ -163,20 +166,181 @@ pub fn search_path(_attr: TokenStream, item: TokenStream) -> TokenStream {
 }
 
 /// Declare a function as `#[pg_extern]` to indicate that it can be used by Postgres as a UDF
+///
+/// `#[pg_extern(const_sql)]` additionally lets the function be called from `{{ ... }}` placeholders
+/// in `extension_sql!(r#"..."#, const_sql)` blocks.
+///
+/// A function returning `impl Iterator` is a set-returning function, which returns its rows in
+/// one of two modes:
+///
+/// - by default, "value-per-call" mode, where Postgres calls the function once per row and each
+///   call pulls a single item from the iterator, which is kept in the function's multi-call
+///   context in between.  Rows are produced only as fast as the query consumes them, so the
+///   iterator can be arbitrarily long, or even infinite when the function is called from a
+///   `SELECT` list with a `LIMIT`
+/// - with `#[pg_extern(materialize)]`, "materialize" mode, where the first call drains the whole
+///   iterator into a tuplestore, which spills to disk past `work_mem`, and returns it at once.
+///   This avoids the per-row function call overhead, and suits iterators that hold resources,
+///   such as an SPI cursor, that shouldn't outlive a single call
+///
+/// `#[pg_extern(window)]` declares a window function, whose arguments are read from the current
+/// row of its window partition.  It can also take one `&mut` reference to any `Default` type,
+/// which isn't an argument of the SQL function, as state that lasts for the whole partition.  See
+/// the `pgx::window` module.
+///
+/// `#[pg_extern(instrument)]` counts the function's calls, and how long they take and, on
+/// Postgres 13, how much memory they allocate, for each backend, which the `pgx_fn_stats()` SQL
+/// function that `fn_stats_functions!()` exports reports.  See the `pgx::fn_stats` module.
+///
+/// `#[pg_extern(raw, returns = sql_type!("name"))]` declares the SQL type of the Datum a `raw`
+/// function returns, which is otherwise unknown.  A builtin type's name is checked when it's
+/// compiled, and `returns = sql_type!(lookup "name")` names one that isn't builtin.
 #[proc_macro_attribute]
 pub fn pg_extern(attr: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_extern_attributes(proc_macro2::TokenStream::from(attr));
     let is_raw = args.contains(&ExternArgs::Raw);
     let no_guard = args.contains(&ExternArgs::NoGuard);
+    let materialize = args.contains(&ExternArgs::Materialize);
+    let window = args.contains(&ExternArgs::Window);
+
+    // an instrumented function's statistics are kept under its SQL name
+    let instrument = |func: &ItemFn| {
+        if !args.contains(&ExternArgs::Instrument) {
+            return None;
+        }
+        let name = args.iter().find_map(|arg| match arg {
+            ExternArgs::Name(name) => Some(name.clone()),
+            _ => None,
+        });
+        Some(name.unwrap_or_else(|| func.sig.ident.to_string()))
+    };
+
+    // the SQL type a raw function returns is checked when it's compiled, if it's builtin
+    let returns = args.iter().find_map(|arg| match arg {
+        ExternArgs::Returns(name) => Some(Some(name.clone())),
+        ExternArgs::ReturnsLookup(_) => Some(None),
+        _ => None,
+    });
+    let returns_check = match returns {
+        Some(_) if !is_raw => {
+            return syn::Error::new(
+                Span::call_site(),
+                "`returns = sql_type!(...)` is the SQL type of a `raw` function's Datum, so needs `raw`",
+            )
+            .to_compile_error()
+            .into()
+        }
+        Some(Some(name)) => quote! {
+            const _: pgx::PgBuiltInOids = pgx::sql_type!(#name);
+        },
+        _ => proc_macro2::TokenStream::new(),
+    };
 
     let ast = parse_macro_input!(item as syn::Item);
     match ast {
-        Item::Fn(func) => rewrite_item_fn(func, is_raw, no_guard).into(),
+        Item::Fn(func)
+            if func
+                .sig
+                .inputs
+                .iter()
+                .any(|arg| matches!(arg, syn::FnArg::Receiver(_))) =>
+        {
+            syn::Error::new(
+                func.sig.span(),
+                "#[pg_extern] methods must be in an impl block marked #[pg_block_extern]",
+            )
+            .to_compile_error()
+            .into()
+        }
+        Item::Fn(func) if args.contains(&ExternArgs::ConstSql) => {
+            // `cargo pgx schema` evaluates these without Postgres, so they can't touch pg_sys
+            if let Some((span, message)) = const_sql_violation(&func) {
+                return syn::Error::new(span, message).to_compile_error().into();
+            }
+            let instrument = instrument(&func);
+            let function = rewrite_item_fn(func, is_raw, no_guard, materialize, window, instrument);
+            quote! { #returns_check #function }.into()
+        }
+        Item::Fn(func) => {
+            let instrument = instrument(&func);
+            let function = rewrite_item_fn(func, is_raw, no_guard, materialize, window, instrument);
+            quote! { #returns_check #function }.into()
+        }
         _ => panic!("#[pg_extern] can only be applied to top-level functions"),
     }
 }
 
-fn rewrite_item_fn(mut func: ItemFn, is_raw: bool, no_guard: bool) -> proc_macro2::TokenStream {
+/// Export the `#[pg_extern]` methods of an inherent `impl` block as SQL functions
+///
+/// A method that takes `self` or `&self` becomes a function whose first argument is the type,
+/// named with the lowercased type name unless `receiver = "..."` names it, and an associated
+/// function becomes a function with the same arguments.  Each is named `{prefix}_{method}`, where
+/// the prefix is the lowercased type name unless `prefix = "..."` sets it, or just `{method}` for
+/// a `bare` block.  `#[pg_extern(name = "...")]` names a function explicitly, as usual.
+///
+/// Methods without `#[pg_extern]` aren't exported, and methods can't take `&mut self`, or return
+/// references borrowed from `self`.
+///
+/// ```rust,ignore
+/// #[pg_block_extern]
+/// impl Rectangle {
+///     #[pg_extern(immutable)]
+///     fn area(&self) -> f64 {  // rectangle_area(rectangle Rectangle) RETURNS float8
+///         self.width * self.height
+///     }
+///
+///     #[pg_extern(immutable)]
+///     fn square(side: f64) -> Rectangle {  // rectangle_square(side float8) RETURNS Rectangle
+///         Rectangle { width: side, height: side }
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn pg_block_extern(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut block = parse_macro_input!(item as syn::ItemImpl);
+    let functions = pgx_utils::block_extern::BlockExternArgs::parse(attr.into())
+        .and_then(|args| pgx_utils::block_extern::block_extern_functions(&args, &block));
+    pgx_utils::block_extern::strip_block_extern_attributes(&mut block);
+
+    match functions {
+        Ok(functions) => {
+            // the functions of different blocks can only have the same SQL name if they were
+            // given it with `name = "..."`, so catch that too
+            let markers = functions.iter().filter_map(|function| {
+                let marker = format!("__pgx_block_extern_{}", function.sql_name);
+                let marker = syn::parse_str::<Ident>(&marker).ok()?;
+                Some(quote! {
+                    #[allow(non_upper_case_globals, dead_code)]
+                    const #marker: () = ();
+                })
+            });
+            let functions = functions.iter().map(|function| &function.func);
+            (quote! {
+                #block
+                #(#functions)*
+                #(#markers)*
+            })
+            .into()
+        }
+        Err((span, message)) => {
+            let error = syn::Error::new(span, message).to_compile_error();
+            (quote! {
+                #block
+                #error
+            })
+            .into()
+        }
+    }
+}
+
+fn rewrite_item_fn(
+    mut func: ItemFn,
+    is_raw: bool,
+    no_guard: bool,
+    materialize: bool,
+    window: bool,
+    instrument: Option<String>,
+) -> proc_macro2::TokenStream {
     let finfo_name = syn::Ident::new(
         &format!("pg_finfo_{}_wrapper", func.sig.ident),
         Span::call_site(),
@@ -190,7 +354,15 @@ fn rewrite_item_fn(mut func: ItemFn, is_raw: bool, no_guard: bool) -> proc_macro
     // make the function 'extern "C"' because this is for the #[pg_extern[ macro
     func.sig.abi = Some(syn::parse_str("extern \"C\"").unwrap());
     let func_span = func.span();
-    let (rewritten_func, need_wrapper) = rewriter.item_fn(func, true, is_raw, no_guard);
+    let (rewritten_func, need_wrapper) = rewriter.item_fn(
+        func,
+        true,
+        is_raw,
+        no_guard,
+        materialize,
+        window,
+        instrument,
+    );
 
     if need_wrapper {
         quote_spanned! {func_span=>
@@ -225,18 +397,35 @@ fn impl_postgres_enum(ast: DeriveInput) -> proc_macro2::TokenStream {
         _ => panic!("#[derive(PostgresEnum)] can only be applied to enums"),
     };
 
-    let mut from_datum = proc_macro2::TokenStream::new();
-    let mut into_datum = proc_macro2::TokenStream::new();
+    let mut to_label = proc_macro2::TokenStream::new();
+    let mut from_label = proc_macro2::TokenStream::new();
 
     for d in enum_data.variants {
         let label_ident = &d.ident;
         let label_string = label_ident.to_string();
 
-        from_datum.extend(quote! { #label_string => Some(#enum_ident::#label_ident), });
-        into_datum.extend(quote! { #enum_ident::#label_ident => Some(pgx::lookup_enum_by_label(#enum_name, #label_string)), });
+        to_label.extend(quote! { #enum_ident::#label_ident => #label_string, });
+        from_label.extend(quote! { #label_string => Some(#enum_ident::#label_ident), });
     }
 
     stream.extend(quote! {
+        impl #enum_ident {
+            /// The label of this value in the Postgres enum
+            pub fn sql_label(&self) -> &'static str {
+                match self {
+                    #to_label
+                }
+            }
+
+            /// The value with the Postgres enum label `label`, if there is one
+            pub fn from_sql_label(label: &str) -> Option<#enum_ident> {
+                match label {
+                    #from_label
+                    _ => None,
+                }
+            }
+        }
+
         impl pgx::FromDatum for #enum_ident {
             #[inline]
             unsafe fn from_datum(datum: pgx::pg_sys::Datum, is_null: bool, typeoid: pgx::pg_sys::Oid) -> Option<#enum_ident> {
@@ -244,9 +433,9 @@ fn impl_postgres_enum(ast: DeriveInput) -> proc_macro2::TokenStream {
                     None
                 } else {
                     let (name, _, _) = pgx::lookup_enum_by_oid(datum as pgx::pg_sys::Oid);
-                    match name.as_str() {
-                        #from_datum
-                        _ => panic!("invalid enum value: {}", name)
+                    match #enum_ident::from_sql_label(&name) {
+                        Some(value) => Some(value),
+                        None => panic!("invalid enum value: {}", name),
                     }
                 }
             }
@@ -255,22 +444,26 @@ fn impl_postgres_enum(ast: DeriveInput) -> proc_macro2::TokenStream {
         impl pgx::IntoDatum for #enum_ident {
             #[inline]
             fn into_datum(self) -> Option<pgx::pg_sys::Datum> {
-                match self {
-                    #into_datum
-                }
+                Some(pgx::lookup_enum_by_label(None, #enum_name, self.sql_label()))
             }
 
-            fn type_oid() -> pg_sys::Oid {
-                pgx::regtypein(#enum_name)
+            fn type_oid() -> pgx::pg_sys::Oid {
+                pgx::lookup_enum_type(None, #enum_name).type_oid
             }
 
+            fn array_type_oid() -> pgx::pg_sys::Oid {
+                pgx::lookup_enum_type(None, #enum_name).array_type_oid
+            }
         }
     });
 
     stream
 }
 
-#[proc_macro_derive(PostgresType, attributes(inoutfuncs, pgvarlena_inoutfuncs))]
+#[proc_macro_derive(
+    PostgresType,
+    attributes(inoutfuncs, pgvarlena_inoutfuncs, copytextformat)
+)]
 pub fn postgres_type(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as syn::DeriveInput);
 
@@ -292,7 +485,9 @@ fn impl_postgres_type(ast: DeriveInput) -> proc_macro2::TokenStream {
         _ => panic!("#[derive(PostgresType)] can only be applied to structs"),
     }
 
-    if args.is_empty() {
+    if !args.contains(&PostgresTypeAttribute::InOutFuncs)
+        && !args.contains(&PostgresTypeAttribute::PgVarlenaInOutFuncs)
+    {
         // assume the user wants us to implement the InOutFuncs
         args.insert(PostgresTypeAttribute::Default);
     }
@@ -364,6 +559,13 @@ fn impl_postgres_type(ast: DeriveInput) -> proc_macro2::TokenStream {
         });
     }
 
+    // the type's compact COPY representation, via its CopyTextFormat impl
+    if args.contains(&PostgresTypeAttribute::CopyTextFormat) {
+        let pgvarlena = args.contains(&PostgresTypeAttribute::PgVarlenaInOutFuncs);
+        stream.extend(copy_text_common::to_copy_text(name, pgvarlena));
+        stream.extend(copy_text_common::from_copy_text(name, pgvarlena));
+    }
+
     stream
 }
 
@@ -453,6 +655,7 @@ fn impl_guc_enum(ast: DeriveInput) -> proc_macro2::TokenStream {
 enum PostgresTypeAttribute {
     InOutFuncs,
     PgVarlenaInOutFuncs,
+    CopyTextFormat,
     Default,
 }
 
@@ -471,6 +674,10 @@ fn parse_postgres_type_args(attributes: &[Attribute]) -> HashSet<PostgresTypeAtt
                 categorized_attributes.insert(PostgresTypeAttribute::PgVarlenaInOutFuncs);
             }
 
+            "copytextformat" => {
+                categorized_attributes.insert(PostgresTypeAttribute::CopyTextFormat);
+            }
+
             _ => {
                 // we can just ignore attributes we don't understand
             }
@@ -484,6 +691,12 @@ fn parse_postgres_type_args(attributes: &[Attribute]) -> HashSet<PostgresTypeAtt
 ///
 /// The argument must be as single raw string literal.
 ///
+/// A block written as `extension_sql!(r#"..."#, const_sql)` can contain `{{ function_name(args) }}`
+/// placeholders, which are replaced, when the schema is generated, with the result of calling a
+/// `#[pg_extern(const_sql)]` function.  Such functions must be plain Rust -- they're evaluated by
+/// `cargo pgx schema` itself, so can't use `pg_sys`, `pgx`, or `unsafe`.  Other blocks are left
+/// as they are, so SQL such as `'{{1,2},{3,4}}'::int[]` needs no escaping.
+///
 /// # Example
 /// ```
 /// # #[macro_use]
@@ -491,7 +704,11 @@ fn parse_postgres_type_args(attributes: &[Attribute]) -> HashSet<PostgresTypeAtt
 /// # fn main() {
 /// extension_sql!(r#"
 /// -- sql statements
-/// "#)
+/// "#);
+///
+/// extension_sql!(r#"
+/// CREATE VIEW answer AS SELECT {{ the_answer() }} AS answer;
+/// "#, const_sql);
 /// # }
 /// ```
 
@@ -516,13 +733,147 @@ pub fn extension_sql(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Mark a table the extension creates as one of its configuration tables, whose rows `pg_dump`
+/// dumps along with the extension, by calling `pg_extension_config_dump()` from the generated
+/// extension script.
+///
+/// The first argument is the table, which may be schema-qualified, as an identifier or a string.
+/// The optional second argument is a `WHERE` clause that picks which of the table's rows to dump,
+/// such as the rows users added, as opposed to those the extension's script inserted.  Without
+/// one, all of the table's rows are dumped.
+///
+/// The calls are made at the end of the extension script, after every `extension_sql!` block, so
+/// the table can be created anywhere in the extension.  `CREATE EXTENSION` fails if the table
+/// doesn't exist or isn't a member of the extension.  Tables an extension creates at runtime can
+/// be marked with `pgx::mark_as_config_table()` instead.
+///
+/// # Example
+/// ```
+/// # #[macro_use]
+/// # extern crate pgx_macros;
+/// # fn main() {
+/// extension_sql!(r#"
+/// CREATE TABLE settings (name text PRIMARY KEY, value text, standard bool NOT NULL DEFAULT false);
+/// INSERT INTO settings VALUES ('color', 'blue', true);
+/// "#);
+///
+/// extension_config_table!(settings, "WHERE NOT standard");
+/// # }
+/// ```
+#[proc_macro]
+pub fn extension_config_table(input: TokenStream) -> TokenStream {
+    match ExtensionConfigTable::parse(input.into()) {
+        // the call is made by the generated schema
+        Ok(_) => TokenStream::new(),
+        Err((span, message)) => syn::Error::new(span, message).to_compile_error().into(),
+    }
+}
+
+/// Create a trigger from the generated extension script, including a `CONSTRAINT` trigger whose
+/// firing can be deferred until the end of the transaction.
+///
+/// The first argument is the trigger's name, followed by:
+///
+/// - `table = "..."`, the table or view it's on, which may be schema-qualified
+/// - `function = "..."`, its trigger function, such as a `#[pg_extern]` function that returns a
+///   `TriggerResult`, and optionally `args("...", ...)`, the arguments the function is given
+/// - when it fires:  `before(...)`, `after(...)`, or `instead_of(...)`, with any of `insert`,
+///   `update`, `delete`, and `truncate`
+/// - `for_each_row`, or `for_each_statement`, which is the default
+/// - `when = "..."`, the condition of its `WHEN` clause
+/// - `constraint`, to make it a `CONSTRAINT` trigger, which must be `after(...)` and
+///   `for_each_row`.  It can also be `deferrable`, `initially_deferred` (which implies
+///   `deferrable`), or `initially_immediate`, and have a `from = "..."` table, the table it
+///   references
+///
+/// The triggers are created at the end of the extension script, after every `extension_sql!`
+/// block and function, so the table and function can be created anywhere in the extension.
+///
+/// # Example
+/// ```
+/// # #[macro_use]
+/// # extern crate pgx_macros;
+/// # fn main() {
+/// // raises an ERROR at COMMIT, rather than at each INSERT, if the batch doesn't balance
+/// create_trigger!(
+///     "entries_balance",
+///     table = "entries",
+///     function = "check_balance",
+///     after(insert, update, delete),
+///     for_each_row,
+///     constraint,
+///     initially_deferred
+/// );
+/// # }
+/// ```
+#[proc_macro]
+pub fn create_trigger(input: TokenStream) -> TokenStream {
+    match pgx_utils::create_trigger::CreateTrigger::parse(input.into()) {
+        // the trigger is created by the generated schema
+        Ok(_) => TokenStream::new(),
+        Err((span, message)) => syn::Error::new(span, message).to_compile_error().into(),
+    }
+}
+
+/// Declare the extension's control file, which `cargo pgx schema` then writes to
+/// `<extname>.control`, rather than it being maintained by hand.
+///
+/// The parameters are those of the control file:
+///
+/// - `comment = "..."`, `default_version = "..."`, and `module_pathname = "..."`, which is
+///   `$libdir/<extname>` unless it's given
+/// - `schema = "..."`, which can't be given if the extension is `relocatable`
+/// - `requires("...", ...)`, the extensions that must be installed before this one
+/// - `relocatable`, `superuser`, and `trusted` (Postgres 13 and later), each `true` or `false`
+///
+/// A relocatable extension must create all of its objects in the one schema, so `cargo pgx
+/// schema` raises an error if the generated SQL creates a schema or uses `@extschema@`.
+///
+/// It also creates a function, `ensure_required_extensions()`, which raises an ERROR naming any
+/// of the extensions this one `requires` that are no longer installed.
+///
+/// # Example
+/// ```
+/// # #[macro_use]
+/// # extern crate pgx_macros;
+/// # mod pgx { pub fn ensure_extensions_installed(_: &[&str]) {} }
+/// # fn main() {
+/// extension_control!(
+///     comment = "an example extension",
+///     default_version = "1.0",
+///     requires("plpgsql", "hstore"),
+///     relocatable = false,
+///     superuser = false
+/// );
+/// # }
+/// ```
+#[proc_macro]
+pub fn extension_control(input: TokenStream) -> TokenStream {
+    match pgx_utils::control_file::ControlFile::from_tokens(input.into()) {
+        // the control file is written by the generated schema
+        Ok(control) => {
+            let requires = control.requires;
+            let tokens = quote! {
+                /// Raise an ERROR naming any of the extensions this one `requires` that aren't
+                /// installed
+                #[allow(dead_code)]
+                pub(crate) fn ensure_required_extensions() {
+                    pgx::ensure_extensions_installed(&[#(#requires),*]);
+                }
+            };
+            tokens.into()
+        }
+        Err((span, message)) => syn::Error::new(span, message).to_compile_error().into(),
+    }
+}
+
 #[proc_macro_derive(PostgresEq)]
 pub fn postgres_eq(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as syn::DeriveInput);
     impl_postgres_eq(ast).into()
 }
 
-#[proc_macro_derive(PostgresOrd)]
+#[proc_macro_derive(PostgresOrd, attributes(sort_abbreviation))]
 pub fn postgres_ord(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as syn::DeriveInput);
     impl_postgres_ord(ast).into()
@@ -1,4 +1,5 @@
 use pgx_utils::operator_common::*;
+use quote::quote;
 use syn::DeriveInput;
 
 pub(crate) fn impl_postgres_eq(ast: DeriveInput) -> proc_macro2::TokenStream {
@@ -19,6 +20,16 @@ pub(crate) fn impl_postgres_ord(ast: DeriveInput) -> proc_macro2::TokenStream {
     stream.extend(ge(&ast.ident));
     stream.extend(cmp(&ast.ident));
 
+    // only types marked `#[sort_abbreviation]` get sort support, as they implement
+    // `SortAbbreviation` themselves
+    if ast
+        .attrs
+        .iter()
+        .any(|a| a.path.is_ident("sort_abbreviation"))
+    {
+        stream.extend(sortsupport(&ast.ident));
+    }
+
     stream
 }
 
@@ -3,15 +3,15 @@
 
 extern crate proc_macro;
 
-use pgx_utils::{categorize_return_type, CategorizedType};
+use pgx_utils::{categorize_return_type, extract_result_ok_type, CategorizedType};
 use proc_macro2::{Ident, Span};
 use quote::{quote, quote_spanned, ToTokens};
 use std::ops::Deref;
 use std::str::FromStr;
 use syn::spanned::Spanned;
 use syn::{
-    FnArg, ForeignItem, ForeignItemFn, Generics, ItemFn, ItemForeignMod, Pat, ReturnType,
-    Signature, Type, Visibility,
+    FnArg, ForeignItem, ForeignItemFn, GenericArgument, Generics, ItemFn, ItemForeignMod, Pat,
+    PathArguments, ReturnType, Signature, Type, Visibility,
 };
 
 pub struct PgGuardRewriter();
@@ -37,9 +37,12 @@ impl PgGuardRewriter {
         rewrite_args: bool,
         is_raw: bool,
         no_guard: bool,
+        materialize: bool,
+        window: bool,
+        instrument: Option<String>,
     ) -> (proc_macro2::TokenStream, bool) {
         if rewrite_args {
-            self.item_fn_with_rewrite(func, is_raw, no_guard)
+            self.item_fn_with_rewrite(func, is_raw, no_guard, materialize, window, instrument)
         } else {
             (self.item_fn_without_rewrite(func, no_guard), true)
         }
@@ -50,6 +53,9 @@ impl PgGuardRewriter {
         mut func: ItemFn,
         is_raw: bool,
         no_guard: bool,
+        materialize: bool,
+        window: bool,
+        instrument: Option<String>,
     ) -> (proc_macro2::TokenStream, bool) {
         // remember the original visibility and signature classifications as we want
         // to use those for the outer function
@@ -62,7 +68,7 @@ impl PgGuardRewriter {
         let arg_list = PgGuardRewriter::build_arg_list(&func.sig, true);
         let func_name = &func.sig.ident;
         let func_span = func.span();
-        let rewritten_args = self.rewrite_args(func.clone(), is_raw);
+        let rewritten_args = self.rewrite_args(func.clone(), is_raw, window);
         let rewritten_return_type = self.rewrite_return_type(func.clone());
         let generics = &func.sig.generics;
         let func_name_wrapper = Ident::new(
@@ -70,29 +76,102 @@ impl PgGuardRewriter {
             func_span,
         );
 
-        let returns_void = rewritten_return_type
-            .to_string()
-            .contains("pg_return_void()");
+        let returns_void = match &func.sig.output {
+            ReturnType::Default => true,
+            ReturnType::Type(_, ty) => type_matches(ty, "()"),
+        };
         let result_var_name = if returns_void {
             Ident::new("_", Span::call_site())
         } else {
             Ident::new("result", Span::call_site())
         };
 
+        // an instrumented function's call is timed until the timer is dropped, at the end of
+        // the block it's called in
+        let start_timer = match instrument {
+            Some(name) => quote! {
+                let _pgx_fn_stats_timer = pgx::fn_stats::FnStatsTimer::start(#name);
+            },
+            None => quote! {},
+        };
+
         let func_call = quote! {
             let #result_var_name = {
+                #start_timer
                 #rewritten_args
 
                 #func_name(#arg_list)
             };
         };
 
+        // set-returning functions that return a `Result` raise their error before the first row
+        // is returned, after the function itself has returned (and its locals have been dropped)
+        let srf_func_call = match &func.sig.output {
+            ReturnType::Type(_, ty) if extract_result_ok_type(ty).is_some() => {
+                let raise_error = raise_error_report();
+                quote! {
+                    let result = {
+                        #start_timer
+                        #rewritten_args
+
+                        match #func_name(#arg_list) {
+                            Ok(result) => result,
+                            Err(e) => #raise_error
+                        }
+                    };
+                }
+            }
+            _ => func_call.clone(),
+        };
+
         let prolog = quote! {
             #func
 
             #[allow(unused_variables)]
         };
         match categorize_return_type(&func) {
+            CategorizedType::Iterator(types) if materialize => (
+                PgGuardRewriter::impl_materialized_srf(
+                    types,
+                    func_span,
+                    prolog,
+                    vis,
+                    func_name_wrapper,
+                    generics,
+                    srf_func_call,
+                    false,
+                ),
+                true,
+            ),
+
+            CategorizedType::OptionalIterator(types) if materialize => (
+                PgGuardRewriter::impl_materialized_srf(
+                    types,
+                    func_span,
+                    prolog,
+                    vis,
+                    func_name_wrapper,
+                    generics,
+                    srf_func_call,
+                    true,
+                ),
+                true,
+            ),
+
+            _ if materialize => (
+                syn::Error::new(
+                    func_span,
+                    "`materialize` only applies to functions that return an `impl Iterator`",
+                )
+                .to_compile_error(),
+                false,
+            ),
+
+            _ if window && is_raw => (
+                syn::Error::new(func_span, "`window` functions cannot be `raw`").to_compile_error(),
+                false,
+            ),
+
             CategorizedType::Default => (
                 PgGuardRewriter::impl_standard_udf(
                     func_span,
@@ -107,7 +186,28 @@ impl PgGuardRewriter {
                 true,
             ),
 
-            CategorizedType::Tuple(_types) => (PgGuardRewriter::impl_tuple_udf(func), false),
+            _ if window => (
+                syn::Error::new(
+                    func_span,
+                    "`window` functions return a single value, not a set or a record",
+                )
+                .to_compile_error(),
+                false,
+            ),
+
+            CategorizedType::Tuple(types) => (
+                PgGuardRewriter::impl_record_udf(
+                    types,
+                    func_span,
+                    prolog,
+                    vis,
+                    func_name_wrapper,
+                    generics,
+                    srf_func_call,
+                    returns_option(&func),
+                ),
+                true,
+            ),
 
             CategorizedType::Iterator(types) if types.len() == 1 => (
                 PgGuardRewriter::impl_setof_srf(
@@ -117,7 +217,7 @@ impl PgGuardRewriter {
                     vis,
                     func_name_wrapper,
                     generics,
-                    func_call,
+                    srf_func_call,
                     false,
                 ),
                 true,
@@ -131,7 +231,7 @@ impl PgGuardRewriter {
                     vis,
                     func_name_wrapper,
                     generics,
-                    func_call,
+                    srf_func_call,
                     true,
                 ),
                 true,
@@ -145,7 +245,7 @@ impl PgGuardRewriter {
                     vis,
                     func_name_wrapper,
                     generics,
-                    func_call,
+                    srf_func_call,
                     false,
                 ),
                 true,
@@ -159,7 +259,7 @@ impl PgGuardRewriter {
                     vis,
                     func_name_wrapper,
                     generics,
-                    func_call,
+                    srf_func_call,
                     true,
                 ),
                 true,
@@ -197,21 +297,60 @@ impl PgGuardRewriter {
         }
     }
 
-    fn impl_tuple_udf(mut func: ItemFn) -> proc_macro2::TokenStream {
-        let func_span = func.span();
-        let return_type = func.sig.output;
-        let return_type = format!("{}", quote! {#return_type});
-        let return_type =
-            proc_macro2::TokenStream::from_str(return_type.trim_start_matches("->")).unwrap();
-        let return_type = quote! {impl std::iter::Iterator<Item = #return_type>};
-
-        func.sig.output = ReturnType::Default;
-        let sig = func.sig;
-        let body = func.block;
+    /// A function returning a tuple returns a single record, whose fields are the function's
+    /// `OUT` parameters
+    fn impl_record_udf(
+        types: Vec<String>,
+        func_span: Span,
+        prolog: proc_macro2::TokenStream,
+        vis: Visibility,
+        func_name_wrapper: Ident,
+        generics: &Generics,
+        func_call: proc_macro2::TokenStream,
+        optional: bool,
+    ) -> proc_macro2::TokenStream {
+        let numtypes = types.len();
+        let i = (0..numtypes).map(syn::Index::from);
+
+        let result_handler = if optional {
+            quote! {
+                let result = match result {
+                    Some(result) => result,
+                    None => return pgx::pg_return_null(fcinfo),
+                };
+            }
+        } else {
+            quote! {}
+        };
+
         quote_spanned! {func_span=>
-            #[pg_extern]
-            #sig -> #return_type {
-                Some(#body).into_iter()
+            #prolog
+            #[pg_guard]
+            #vis fn #func_name_wrapper #generics(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+                #func_call
+
+                #result_handler
+
+                let mut datums: [usize; #numtypes] = [0; #numtypes];
+                let mut nulls: [bool; #numtypes] = [false; #numtypes];
+                #(
+                    match result.#i.into_datum() {
+                        Some(datum) => { datums[#i] = datum as usize; },
+                        None => { nulls[#i] = true; }
+                    }
+                )*
+
+                unsafe {
+                    /* Build a tuple descriptor for our OUT parameters */
+                    let mut tupdesc: pgx::pg_sys::TupleDesc = std::ptr::null_mut();
+                    if pgx::pg_sys::get_call_result_type(fcinfo, std::ptr::null_mut(), &mut tupdesc) != pgx::pg_sys::TypeFuncClass_TYPEFUNC_COMPOSITE {
+                        pgx::error!("return type must be a row type");
+                    }
+                    let tupdesc = pgx::pg_sys::BlessTupleDesc(tupdesc);
+
+                    let heap_tuple = pgx::pg_sys::heap_form_tuple(tupdesc, datums.as_mut_ptr(), nulls.as_mut_ptr());
+                    pgx::heap_tuple_get_datum(heap_tuple)
+                }
             }
         }
     }
@@ -360,7 +499,7 @@ impl PgGuardRewriter {
                     funcctx = pgx::srf_first_call_init(fcinfo);
                     funcctx.user_fctx = pgx::PgMemoryContexts::For(funcctx.multi_call_memory_ctx).palloc_struct::<IteratorHolder<#generic_type>>() as void_mut_ptr;
                     funcctx.tuple_desc = pgx::PgMemoryContexts::For(funcctx.multi_call_memory_ctx).switch_to(|_| {
-                        let mut tupdesc: *mut pgx::pg_sys::TupleDescData = std::ptr::null_mut();
+                        let mut tupdesc: pgx::pg_sys::TupleDesc = std::ptr::null_mut();
 
                         unsafe {
                             /* Build a tuple descriptor for our result type */
@@ -407,6 +546,61 @@ impl PgGuardRewriter {
         }
     }
 
+    /// A set-returning function in materialize mode drains its iterator into a tuplestore on
+    /// its only call, rather than returning one row per call
+    fn impl_materialized_srf(
+        types: Vec<String>,
+        func_span: Span,
+        prolog: proc_macro2::TokenStream,
+        vis: Visibility,
+        func_name_wrapper: Ident,
+        generics: &Generics,
+        func_call: proc_macro2::TokenStream,
+        optional: bool,
+    ) -> proc_macro2::TokenStream {
+        let fill_row = if types.len() == 1 {
+            quote! {
+                match result.into_datum() {
+                    Some(datum) => { datums[0] = datum; },
+                    None => { nulls[0] = true; }
+                }
+            }
+        } else {
+            let i = (0..types.len()).map(syn::Index::from);
+            quote! {
+                #(
+                    match result.#i.into_datum() {
+                        Some(datum) => { datums[#i] = datum; },
+                        None => { nulls[#i] = true; }
+                    }
+                )*
+            }
+        };
+
+        // a `None` is an empty set
+        let result_handler = if optional {
+            quote! {
+                let result = result.into_iter().flatten();
+            }
+        } else {
+            quote! {}
+        };
+
+        quote_spanned! {func_span=>
+            #prolog
+            #[pg_guard]
+            #vis fn #func_name_wrapper #generics(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+                #func_call
+
+                #result_handler
+
+                pgx::srf_materialize(fcinfo, result, |result, datums, nulls| {
+                    #fill_row
+                })
+            }
+        }
+    }
+
     fn item_fn_without_rewrite(
         &self,
         mut func: ItemFn,
@@ -616,9 +810,14 @@ impl PgGuardRewriter {
         sig.output.clone()
     }
 
-    pub fn rewrite_args(&self, func: ItemFn, is_raw: bool) -> proc_macro2::TokenStream {
+    pub fn rewrite_args(
+        &self,
+        func: ItemFn,
+        is_raw: bool,
+        window: bool,
+    ) -> proc_macro2::TokenStream {
         let fsr = FunctionSignatureRewriter::new(func);
-        let args = fsr.args(is_raw);
+        let args = fsr.args(is_raw, window);
 
         quote! {
             #args
@@ -653,35 +852,55 @@ impl FunctionSignatureRewriter {
                 });
             }
             ReturnType::Type(_, type_) => {
-                if type_matches(type_, "Option") {
-                    stream.extend(quote! {
-                        match result {
-                            Some(result) => {
-                                result.into_datum().unwrap_or_else(|| panic!("returned Option<T> was NULL"))
-                            },
-                            None => pgx::pg_return_null(fcinfo)
-                        }
-                    });
-                } else if type_matches(type_, "pg_sys :: Datum") {
-                    stream.extend(quote! {
-                        result
-                    });
-                } else if type_matches(type_, "()") {
-                    stream.extend(quote! {
-                       pgx::pg_return_void()
-                    });
-                } else {
-                    stream.extend(quote! {
-                        result.into_datum().unwrap_or_else(|| panic!("returned Datum was NULL"))
-                    });
-                }
+                stream.extend(FunctionSignatureRewriter::convert_type(type_))
             }
         }
 
         stream
     }
 
-    fn args(&self, is_raw: bool) -> proc_macro2::TokenStream {
+    fn convert_type(type_: &Type) -> proc_macro2::TokenStream {
+        let mut stream = proc_macro2::TokenStream::new();
+        if let Some(ok_type) = extract_result_ok_type(type_) {
+            // the function has already returned by the time we get here, so its locals
+            // have been dropped before the error is raised
+            let ok_conversion = FunctionSignatureRewriter::convert_type(ok_type);
+            let raise_error = raise_error_report();
+            stream.extend(quote! {
+                match result {
+                    Ok(result) => { #ok_conversion },
+                    Err(e) => #raise_error
+                }
+            });
+        } else if type_matches(type_, "Option") {
+            stream.extend(return_type_assertion(type_));
+            stream.extend(quote! {
+                match result {
+                    Some(result) => {
+                        result.into_datum().unwrap_or_else(|| panic!("returned Option<T> was NULL"))
+                    },
+                    None => pgx::pg_return_null(fcinfo)
+                }
+            });
+        } else if type_matches(type_, "pg_sys :: Datum") {
+            stream.extend(quote! {
+                result
+            });
+        } else if type_matches(type_, "()") {
+            stream.extend(quote! {
+               pgx::pg_return_void()
+            });
+        } else {
+            stream.extend(return_type_assertion(type_));
+            stream.extend(quote! {
+                result.into_datum().unwrap_or_else(|| panic!("returned Datum was NULL"))
+            });
+        }
+
+        stream
+    }
+
+    fn args(&self, is_raw: bool, window: bool) -> proc_macro2::TokenStream {
         if self.func.sig.inputs.len() == 1 && self.return_type_is_datum() {
             if let FnArg::Typed(ty) = self.func.sig.inputs.first().unwrap() {
                 if type_matches(&ty.ty, "pg_sys :: FunctionCallInfo") {
@@ -693,6 +912,29 @@ impl FunctionSignatureRewriter {
         let mut stream = proc_macro2::TokenStream::new();
         let mut i = 0usize;
         let mut have_fcinfo = false;
+
+        // a window function's arguments are read from the current row of its partition
+        if window {
+            let states = self
+                .func
+                .sig
+                .inputs
+                .iter()
+                .filter(|arg| match arg {
+                    FnArg::Typed(ty) => mut_reference_type(&ty.ty).is_some(),
+                    FnArg::Receiver(_) => false,
+                })
+                .count();
+            if states > 1 {
+                panic!("A window function can only take one `&mut` state argument")
+            }
+
+            stream.extend(quote! {
+                #[allow(unused_mut)]
+                let mut __pgx_window = pgx::PgWindowObject::from_fcinfo(fcinfo);
+            });
+        }
+
         for arg in &self.func.sig.inputs {
             match arg {
                 FnArg::Receiver(_) => panic!("Functions that take self are not supported"),
@@ -706,7 +948,35 @@ impl FunctionSignatureRewriter {
                             panic!("When using `pg_sys::FunctionCallInfo` as an argument it must be the last argument")
                         }
 
-                        let ts = if is_option {
+                        // a window function's `&mut` argument is its partition's state, rather
+                        // than an argument of the SQL function
+                        let state_type = if window {
+                            mut_reference_type(type_)
+                        } else {
+                            None
+                        };
+
+                        // point the error for an argument type that isn't FromDatum at the type
+                        if !is_raw
+                            && state_type.is_none()
+                            && !type_matches(type_, "pg_sys :: FunctionCallInfo")
+                        {
+                            let from_datum_type = option_inner_type(type_).unwrap_or(type_);
+                            stream.extend(quote_spanned! {from_datum_type.span()=>
+                                pgx::pg_extern_argument_must_implement_from_datum::<#from_datum_type>();
+                            });
+                        }
+
+                        let ts = if let Some(state_type) = state_type {
+                            quote_spanned! {ident.span()=>
+                                let #name = unsafe { __pgx_window.partition_state::<#state_type>() };
+                            }
+                        } else if is_option && window {
+                            let option_type = extract_option_type(type_);
+                            quote_spanned! {ident.span()=>
+                                let #name = __pgx_window.arg_current::<#option_type>(#i);
+                            }
+                        } else if is_option {
                             let option_type = extract_option_type(type_);
                             quote_spanned! {ident.span()=>
                                 let #name = pgx::pg_getarg::<#option_type>(fcinfo, #i);
@@ -720,6 +990,10 @@ impl FunctionSignatureRewriter {
                             quote_spanned! {ident.span()=>
                                 let #name = pgx::pg_getarg_datum_raw(fcinfo, #i) as #type_;
                             }
+                        } else if window {
+                            quote_spanned! {ident.span()=>
+                                let #name = __pgx_window.arg_current::<#type_>(#i).unwrap_or_else(|| panic!("{} is null", stringify!{#ident}));
+                            }
                         } else {
                             quote_spanned! {ident.span()=>
                                 let #name = pgx::pg_getarg::<#type_>(fcinfo, #i).unwrap_or_else(|| panic!("{} is null", stringify!{#ident}));
@@ -728,7 +1002,9 @@ impl FunctionSignatureRewriter {
 
                         stream.extend(ts);
 
-                        i += 1;
+                        if state_type.is_none() {
+                            i += 1;
+                        }
                     }
                     _ => panic!("Unrecognized function arg type"),
                 },
@@ -746,6 +1022,65 @@ impl FunctionSignatureRewriter {
     }
 }
 
+/// Asserts that a function's return type is `IntoDatum`, so that the compiler's error points at
+/// the return type rather than at the generated conversion
+fn return_type_assertion(ty: &Type) -> proc_macro2::TokenStream {
+    quote_spanned! {ty.span()=>
+        pgx::pg_extern_return_type_must_implement_into_datum::<#ty>();
+    }
+}
+
+/// The `T` of an `Option<T>`, with its original span
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Path(path) => {
+            let segment = path.path.segments.last()?;
+            if segment.ident != "Option" {
+                return None;
+            }
+
+            match &segment.arguments {
+                PathArguments::AngleBracketed(args) => match args.args.first()? {
+                    GenericArgument::Type(ty) => Some(ty),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Converts the `Err(e)` of a `Result` into a `pgx::ErrorReport` and raises it as an ERROR
+fn raise_error_report() -> proc_macro2::TokenStream {
+    quote! {
+        {
+            let report: pgx::ErrorReport = e.into();
+            report.report(pgx::PgLogLevel::ERROR);
+            unreachable!("ErrorReport::report() failed to raise an ERROR")
+        }
+    }
+}
+
+/// The `T` of a `&mut T`
+fn mut_reference_type(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Reference(tref) if tref.mutability.is_some() => Some(&tref.elem),
+        _ => None,
+    }
+}
+
+/// Does `func` return an `Option<T>` (or a `Result<Option<T>, E>`)?
+fn returns_option(func: &ItemFn) -> bool {
+    match &func.sig.output {
+        ReturnType::Default => false,
+        ReturnType::Type(_, ty) => match extract_result_ok_type(ty) {
+            Some(ok_type) => type_matches(ok_type, "Option"),
+            None => type_matches(ty, "Option"),
+        },
+    }
+}
+
 fn type_matches(ty: &Type, pattern: &str) -> bool {
     let type_string = format!("{}", quote! {#ty});
     type_string.starts_with(pattern)
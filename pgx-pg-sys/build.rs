@@ -5,7 +5,7 @@ extern crate build_deps;
 
 use bindgen::callbacks::MacroParsingBehavior;
 use pgx_utils::pg_config::{PgConfig, PgConfigSelector, Pgx};
-use pgx_utils::{exit_with_error, handle_result, prefix_path};
+use pgx_utils::{bindings, exit_with_error, handle_result, prefix_path};
 use quote::quote;
 use rayon::prelude::*;
 use std::collections::{HashMap, HashSet};
@@ -68,7 +68,7 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     eprintln!("manifest_dir={}", manifest_dir.display());
     eprintln!("shim_dir={}", shim_dir.display());
 
-    let pgx = Pgx::from_config()?;
+    let pgx = handle_result!(Pgx::from_config(), "unable to load the pgx configuration");
 
     build_deps::rerun_if_changed_paths(&Pgx::config_toml()?.display().to_string()).unwrap();
     build_deps::rerun_if_changed_paths("include/*").unwrap();
@@ -106,7 +106,7 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         let mut bindings_file = out_dir.clone();
         bindings_file.push(&format!("pg{}.rs", major_version));
         handle_result!(
-            write_rs_file(
+            write_bindings_file(
                 rewritten_items,
                 &bindings_file,
                 quote! {
@@ -125,7 +125,8 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         let mut oids_file = out_dir.clone();
         oids_file.push(&format!("pg{}_oids.rs", major_version));
         handle_result!(
-            write_rs_file(oids, &oids_file, quote! {}),
+            bindings::rustfmt_source(&oids.to_string())
+                .and_then(|oids| bindings::write_file_atomically(&oids_file, &oids)),
             format!(
                 "Unable to write oids file for pg{} to `{}`",
                 major_version,
@@ -142,42 +143,40 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     Ok(())
 }
 
-fn write_rs_file(
-    code: proc_macro2::TokenStream,
+/// Write the bindings in a stable order and format (see `pgx_utils::bindings`), so that
+/// regenerating them only changes what changed in the headers
+fn write_bindings_file(
+    items: Vec<syn::Item>,
     file: &PathBuf,
     header: proc_macro2::TokenStream,
-) -> Result<(), std::io::Error> {
-    let contents = quote! {
-        #header
-        #code
-    };
-
-    std::fs::write(&file, contents.to_string())?;
-    rust_fmt(&file)
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let contents = bindings::render_bindings(header, items)?;
+    Ok(bindings::write_file_atomically(file, &contents)?)
 }
 
 /// Given a token stream representing a file, apply a series of transformations to munge
 /// the bindgen generated code with some postgres specific enhancements
-fn rewrite_items(
-    file: &syn::File,
-) -> Result<proc_macro2::TokenStream, Box<dyn Error + Send + Sync>> {
+fn rewrite_items(file: &syn::File) -> Result<Vec<syn::Item>, Box<dyn Error + Send + Sync>> {
     let items = apply_pg_guard(&file.items)?;
     let pgnode_impls = impl_pg_node(&items)?;
-
-    let mut stream = proc_macro2::TokenStream::new();
-    for item in items.into_iter().chain(pgnode_impls.into_iter()) {
-        stream.extend(quote! { #item });
-    }
-
-    Ok(stream)
+    let projection_impls = impl_projections(&items)?;
+    let callback_setter_impls = impl_callback_setters(&items)?;
+    let config_defines = pg_config_defines(&items)?;
+
+    Ok(items
+        .into_iter()
+        .chain(pgnode_impls.into_iter())
+        .chain(projection_impls.into_iter())
+        .chain(callback_setter_impls.into_iter())
+        .chain(std::iter::once(config_defines))
+        .collect())
 }
 
 /// Find all the constants that represent Postgres type OID values.
 ///
 /// These are constants of type `u32` whose name ends in the string "OID"
 fn extract_oids(code: &syn::File) -> Result<proc_macro2::TokenStream, Box<dyn Error>> {
-    let mut enum_variants = proc_macro2::TokenStream::new();
-    let mut from_impl = proc_macro2::TokenStream::new();
+    let mut oids = Vec::new();
     for item in &code.items {
         match item {
             Item::Const(c) => {
@@ -187,14 +186,39 @@ fn extract_oids(code: &syn::File) -> Result<proc_macro2::TokenStream, Box<dyn Er
                 let ty = quote! {#ty}.to_string();
 
                 if ty == "u32" && name.ends_with("OID") && name != "HEAP_HASOID" {
-                    enum_variants.extend(quote! {#ident = crate::#ident as isize, });
-                    from_impl.extend(quote! {crate::#ident => Some(crate::PgBuiltInOids::#ident), })
+                    oids.push(ident);
                 }
             }
             _ => {}
         }
     }
 
+    // in a stable order, like the bindings
+    oids.sort();
+    oids.dedup();
+
+    let mut enum_variants = proc_macro2::TokenStream::new();
+    let mut from_impl = proc_macro2::TokenStream::new();
+    let mut sql_type_arms = proc_macro2::TokenStream::new();
+    let mut sql_type_names = HashSet::new();
+    for ident in oids {
+        enum_variants.extend(quote! {#ident = crate::#ident as isize, });
+        from_impl.extend(quote! {crate::#ident => Some(crate::PgBuiltInOids::#ident), });
+
+        for name in pgx_utils::sql_types::sql_type_names(&ident.to_string()) {
+            if sql_type_names.insert(name.clone()) {
+                sql_type_arms.extend(quote! { (#name) => { $crate::PgBuiltInOids::#ident }; });
+            }
+        }
+    }
+    for (serial, integer) in pgx_utils::sql_types::SERIAL_TYPES {
+        let message = format!(
+            "`{}` isn't a type, but shorthand for an `{}` column with a sequence, so use \"{}\"",
+            serial, integer, integer
+        );
+        sql_type_arms.extend(quote! { (#serial) => { compile_error!(#message) }; });
+    }
+
     Ok(quote! {
         #[derive(Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Debug)]
         pub enum PgBuiltInOids {
@@ -209,6 +233,27 @@ fn extract_oids(code: &syn::File) -> Result<proc_macro2::TokenStream, Box<dyn Er
                 }
             }
         }
+
+        /// The `PgBuiltInOids` of a builtin type, by its name, or one of its aliases, as it's
+        /// written in SQL, such as `sql_type!("timestamp with time zone")`.  A name that isn't
+        /// a builtin type is a compile error.
+        ///
+        /// `sql_type!(lookup "name")` is a `SqlTypeLookup`, for types that aren't builtin, whose
+        /// Oid is looked up when it's used
+        #[macro_export]
+        macro_rules! sql_type {
+            #sql_type_arms
+            (lookup $name:literal) => { $crate::SqlTypeLookup::new($name) };
+            ($name:literal) => {
+                compile_error!(concat!(
+                    "unknown builtin SQL type ",
+                    stringify!($name),
+                    ", which must be written in lowercase, as it's named in `pg_type`.  For a type that isn't builtin, use `sql_type!(lookup ",
+                    stringify!($name),
+                    ")`"
+                ))
+            };
+        }
     })
 }
 
@@ -302,6 +347,237 @@ fn impl_pg_node(items: &Vec<syn::Item>) -> Result<Vec<syn::Item>, Box<dyn Error
     Ok(pgnode_impls.into_iter().map(|(_, item)| item).collect())
 }
 
+/// Parent structs and their pointer fields that are commonly projected by wrapper types, along
+/// with the type each field points to.  `None` means the field is a `void *`.
+const PROJECTIONS: &[(&str, &str, Option<&str>)] = &[
+    ("FunctionCallInfoBaseData", "flinfo", Some("FmgrInfo")),
+    ("FunctionCallInfoBaseData", "context", Some("Node")),
+    ("FunctionCallInfoData", "flinfo", Some("FmgrInfo")),
+    ("FunctionCallInfoData", "context", Some("Node")),
+    ("IndexScanDescData", "opaque", None),
+    ("TriggerData", "tg_relation", Some("RelationData")),
+    ("TriggerData", "tg_trigtuple", Some("HeapTupleData")),
+];
+
+/// Generate `field_ref()`/`field_mut()` accessors for the pointer fields in `PROJECTIONS`, so
+/// that the returned reference is typed and its lifetime is tied to the parent struct.
+///
+/// They're `unsafe`, as nothing stops a struct built in Rust from holding a dangling pointer, so
+/// the caller must know the field is NULL or points to a live value of the pointee type
+fn impl_projections(
+    items: &Vec<syn::Item>,
+) -> Result<Vec<syn::Item>, Box<dyn Error + Send + Sync>> {
+    let mut projection_impls = Vec::new();
+
+    for item in items {
+        let struct_ = match item {
+            Item::Struct(struct_) => struct_,
+            _ => continue,
+        };
+        let struct_name = &struct_.ident;
+
+        let mut accessors = proc_macro2::TokenStream::new();
+        for (_, field, pointee) in PROJECTIONS
+            .iter()
+            .filter(|(parent, _, _)| struct_name == parent)
+        {
+            let has_field = struct_.fields.iter().any(|f| match &f.ident {
+                Some(ident) => ident == field,
+                None => false,
+            });
+            if !has_field {
+                continue;
+            }
+
+            let field = syn::Ident::new(field, proc_macro2::Span::call_site());
+            let field_ref = syn::Ident::new(&format!("{}_ref", field), field.span());
+            let field_mut = syn::Ident::new(&format!("{}_mut", field), field.span());
+
+            accessors.extend(match pointee {
+                Some(pointee) => {
+                    let pointee = syn::Ident::new(pointee, proc_macro2::Span::call_site());
+                    quote! {
+                        #[inline]
+                        pub unsafe fn #field_ref(&self) -> Option<&#pointee> {
+                            (self.#field as *const #pointee).as_ref()
+                        }
+                        #[inline]
+                        pub unsafe fn #field_mut(&mut self) -> Option<&mut #pointee> {
+                            (self.#field as *mut #pointee).as_mut()
+                        }
+                    }
+                }
+                None => quote! {
+                    #[inline]
+                    pub unsafe fn #field_ref<T>(&self) -> Option<&T> {
+                        (self.#field as *const T).as_ref()
+                    }
+                    #[inline]
+                    pub unsafe fn #field_mut<T>(&mut self) -> Option<&mut T> {
+                        (self.#field as *mut T).as_mut()
+                    }
+                },
+            });
+        }
+
+        if !accessors.is_empty() {
+            projection_impls.push(syn::parse2(quote! {
+                impl #struct_name {
+                    #accessors
+                }
+            })?);
+        }
+    }
+
+    Ok(projection_impls)
+}
+
+/// Structs that are (mostly) function pointers for Postgres to call, which extensions fill in
+/// to implement an index access method, foreign data wrapper, and so on.  Bindgen's `Default`
+/// for each of these leaves every function pointer as `None`.
+const CALLBACK_STRUCTS: &[&str] = &[
+    "CustomExecMethods",
+    "CustomScanMethods",
+    "FdwRoutine",
+    "IndexAmRoutine",
+    "TableAmRoutine",
+];
+
+/// Generate a `set_field()` method for each function pointer field of the `CALLBACK_STRUCTS`,
+/// which takes exactly the `unsafe extern "C" fn` the field holds.  A function with the wrong
+/// signature, or a plain Rust function with the wrong ABI, then fails to compile rather than
+/// needing a `transmute()`
+fn impl_callback_setters(
+    items: &Vec<syn::Item>,
+) -> Result<Vec<syn::Item>, Box<dyn Error + Send + Sync>> {
+    // most callback fields are declared with a type alias, such as `ambuild_function`
+    let aliases = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Type(alias) => Some((alias.ident.to_string(), alias.ty.as_ref())),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut setter_impls = Vec::new();
+    for item in items {
+        let struct_ = match item {
+            Item::Struct(struct_)
+                if CALLBACK_STRUCTS.contains(&struct_.ident.to_string().as_str()) =>
+            {
+                struct_
+            }
+            _ => continue,
+        };
+        let struct_name = &struct_.ident;
+
+        let mut setters = proc_macro2::TokenStream::new();
+        for field in struct_.fields.iter() {
+            let field_name = match &field.ident {
+                Some(ident) => ident,
+                None => continue,
+            };
+            let ty = match &field.ty {
+                syn::Type::Path(path) if path.qself.is_none() && path.path.segments.len() == 1 => {
+                    let name = path.path.segments[0].ident.to_string();
+                    aliases.get(&name).cloned().unwrap_or(&field.ty)
+                }
+                ty => ty,
+            };
+            let fn_type = match optional_fn_type(ty) {
+                Some(fn_type) => fn_type,
+                None => continue,
+            };
+
+            let setter = syn::Ident::new(&format!("set_{}", field_name), field_name.span());
+            setters.extend(quote! {
+                #[inline]
+                pub fn #setter(&mut self, f: #fn_type) -> &mut Self {
+                    self.#field_name = Some(f);
+                    self
+                }
+            });
+        }
+
+        if !setters.is_empty() {
+            setter_impls.push(syn::parse2(quote! {
+                impl #struct_name {
+                    #setters
+                }
+            })?);
+        }
+    }
+
+    Ok(setter_impls)
+}
+
+/// The `unsafe extern "C" fn(...)` of a `::std::option::Option<unsafe extern "C" fn(...)>`
+fn optional_fn_type(ty: &syn::Type) -> Option<&syn::TypeBareFn> {
+    let segment = match ty {
+        syn::Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(syn::Type::BareFn(fn_type)) => Some(fn_type),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Optional build features that `pg_config.h` either `#define`s or leaves undefined.  Bindgen only
+/// emits constants for the ones that are defined, so we generate a `bool` for each of them
+const PG_CONFIG_DEFINES: &[&str] = &[
+    "ENABLE_NLS",
+    "ENABLE_THREAD_SAFETY",
+    "HAVE_LIBZ",
+    "USE_ASSERT_CHECKING",
+    "USE_BONJOUR",
+    "USE_BSD_AUTH",
+    "USE_ICU",
+    "USE_LDAP",
+    "USE_LIBXML",
+    "USE_LIBXSLT",
+    "USE_LLVM",
+    "USE_OPENSSL",
+    "USE_PAM",
+    "USE_SYSTEMD",
+];
+
+/// Generate a `pg_config_defines` module with a `bool` constant for each of `PG_CONFIG_DEFINES`,
+/// so that code can check for a build feature without it failing to compile when it's missing
+fn pg_config_defines(items: &Vec<syn::Item>) -> Result<syn::Item, Box<dyn Error + Send + Sync>> {
+    let defined = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Const(const_) => Some(const_.ident.to_string()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let mut consts = proc_macro2::TokenStream::new();
+    for name in PG_CONFIG_DEFINES {
+        let is_defined = defined.contains(*name);
+        let name = syn::Ident::new(name, proc_macro2::Span::call_site());
+        consts.extend(quote! {
+            pub const #name: bool = #is_defined;
+        });
+    }
+
+    Ok(syn::parse2(quote! {
+        #[doc = " Whether each optional build feature was `#define`d in the `pg_config.h` these bindings were"]
+        #[doc = " generated from"]
+        pub mod pg_config_defines {
+            #consts
+        }
+    })?)
+}
+
 /// Given a root node, dfs_find_nodes adds all its children nodes to `node_set`.
 fn dfs_find_nodes<'graph>(
     node: &'graph StructDescriptor<'graph>,
@@ -599,12 +875,3 @@ fn apply_pg_guard(items: &Vec<syn::Item>) -> Result<Vec<syn::Item>, Box<dyn Erro
 
     Ok(out)
 }
-
-fn rust_fmt(path: &PathBuf) -> Result<(), std::io::Error> {
-    run_command(
-        Command::new("rustfmt").arg(path).current_dir("."),
-        "[bindings_diff]",
-    )?;
-
-    Ok(())
-}
@@ -12,16 +12,28 @@
 
 #include "access/htup.h"
 #include "access/htup_details.h"
+#if !IS_PG_10
+#include "catalog/objectaddress.h"
+#endif
 #include "catalog/pg_type.h"
+#include "commands/copy.h"
+#include "miscadmin.h"
 #if IS_PG_10 || IS_PG_11
 #include "nodes/relation.h"
 #else
 #include "nodes/pathnodes.h"
 #endif
 #include "nodes/pg_list.h"
+#include "parser/parse_relation.h"
 #include "parser/parsetree.h"
+#include "port/atomics.h"
+#include "replication/logical.h"
+#include "storage/spin.h"
+#include "utils/acl.h"
 #include "utils/memutils.h"
 #include "utils/builtins.h"
+#include "utils/rel.h"
+#include "utils/rls.h"
 
 
 PGDLLEXPORT MemoryContext pgx_GetMemoryContextChunk(void *ptr);
@@ -29,6 +41,13 @@ MemoryContext pgx_GetMemoryContextChunk(void *ptr) {
     return GetMemoryChunkContext(ptr);
 }
 
+PGDLLEXPORT void pgx_OutputPluginWriteBytes(LogicalDecodingContext *ctx, const char *data, Size len, bool last_write);
+void pgx_OutputPluginWriteBytes(LogicalDecodingContext *ctx, const char *data, Size len, bool last_write) {
+    OutputPluginPrepareWrite(ctx, last_write);
+    appendBinaryStringInfo(ctx->out, data, len);
+    OutputPluginWrite(ctx, last_write);
+}
+
 PGDLLEXPORT void pgx_elog(int32 level, char *message);
 void pgx_elog(int32 level, char *message) {
     elog(level, "%s", message);
@@ -46,6 +65,15 @@ void pgx_ereport(int level, int code, char *message, char *file, int lineno, int
                     errmsg("%s", message), errcontext_msg("%s:%d:%d", file, lineno, colno)));
 }
 
+PGDLLEXPORT void pgx_ereport_report(int level, int code, char *message, char *detail, char *hint);
+void pgx_ereport_report(int level, int code, char *message, char *detail, char *hint) {
+    ereport(level,
+            (errcode(code),
+                    errmsg("%s", message),
+                    detail != NULL ? errdetail("%s", detail) : 0,
+                    hint != NULL ? errhint("%s", hint) : 0));
+}
+
 PGDLLEXPORT void pgx_SET_VARSIZE(struct varlena *ptr, int size);
 void pgx_SET_VARSIZE(struct varlena *ptr, int size) {
     SET_VARSIZE(ptr, size);
@@ -107,3 +135,125 @@ PGDLLEXPORT char *pgx_GETSTRUCT(HeapTuple tuple);
 char *pgx_GETSTRUCT(HeapTuple tuple) {
     return GETSTRUCT(tuple);
 }
+
+PGDLLEXPORT uint64 pgx_copy_from(Relation rel, List *attnamelist, List *options, copy_data_source_cb data_source_cb);
+uint64 pgx_copy_from(Relation rel, List *attnamelist, List *options, copy_data_source_cb data_source_cb) {
+    ParseState *pstate;
+    CopyState cstate;
+    AclResult aclresult;
+    uint64 processed;
+
+    /* the same checks DoCopy() makes of a COPY FROM */
+    aclresult = pg_class_aclcheck(RelationGetRelid(rel), GetUserId(), ACL_INSERT);
+    if (aclresult != ACLCHECK_OK)
+#if IS_PG_10
+        aclcheck_error(aclresult, ACL_KIND_CLASS, RelationGetRelationName(rel));
+#else
+        aclcheck_error(aclresult, get_relkind_objtype(rel->rd_rel->relkind), RelationGetRelationName(rel));
+#endif
+
+    if (check_enable_rls(RelationGetRelid(rel), InvalidOid, false) == RLS_ENABLED)
+        ereport(ERROR,
+                (errcode(ERRCODE_FEATURE_NOT_SUPPORTED),
+                        errmsg("COPY FROM not supported with row-level security"),
+                        errhint("Use INSERT statements instead.")));
+
+    pstate = make_parsestate(NULL);
+
+#if IS_PG_12 || IS_PG_13
+    /* same as logical replication's table sync, CopyFrom() needs the relation in the range table */
+    addRangeTableEntryForRelation(pstate, rel, RowExclusiveLock, NULL, false, false);
+#endif
+
+    cstate = BeginCopyFrom(pstate, rel, NULL, false, data_source_cb, attnamelist, options);
+    processed = CopyFrom(cstate);
+    EndCopyFrom(cstate);
+    free_parsestate(pstate);
+
+    return processed;
+}
+
+PGDLLEXPORT void pgx_SpinLockInit(volatile slock_t *lock);
+void pgx_SpinLockInit(volatile slock_t *lock) {
+    SpinLockInit(lock);
+}
+
+PGDLLEXPORT void pgx_SpinLockAcquire(volatile slock_t *lock);
+void pgx_SpinLockAcquire(volatile slock_t *lock) {
+    SpinLockAcquire(lock);
+}
+
+PGDLLEXPORT void pgx_SpinLockRelease(volatile slock_t *lock);
+void pgx_SpinLockRelease(volatile slock_t *lock) {
+    SpinLockRelease(lock);
+}
+
+PGDLLEXPORT void pgx_pg_atomic_init_u32(volatile pg_atomic_uint32 *ptr, uint32 val);
+void pgx_pg_atomic_init_u32(volatile pg_atomic_uint32 *ptr, uint32 val) {
+    pg_atomic_init_u32(ptr, val);
+}
+
+PGDLLEXPORT uint32 pgx_pg_atomic_read_u32(volatile pg_atomic_uint32 *ptr);
+uint32 pgx_pg_atomic_read_u32(volatile pg_atomic_uint32 *ptr) {
+    return pg_atomic_read_u32(ptr);
+}
+
+PGDLLEXPORT void pgx_pg_atomic_write_u32(volatile pg_atomic_uint32 *ptr, uint32 val);
+void pgx_pg_atomic_write_u32(volatile pg_atomic_uint32 *ptr, uint32 val) {
+    pg_atomic_write_u32(ptr, val);
+}
+
+PGDLLEXPORT uint32 pgx_pg_atomic_exchange_u32(volatile pg_atomic_uint32 *ptr, uint32 newval);
+uint32 pgx_pg_atomic_exchange_u32(volatile pg_atomic_uint32 *ptr, uint32 newval) {
+    return pg_atomic_exchange_u32(ptr, newval);
+}
+
+PGDLLEXPORT bool pgx_pg_atomic_compare_exchange_u32(volatile pg_atomic_uint32 *ptr, uint32 *expected, uint32 newval);
+bool pgx_pg_atomic_compare_exchange_u32(volatile pg_atomic_uint32 *ptr, uint32 *expected, uint32 newval) {
+    return pg_atomic_compare_exchange_u32(ptr, expected, newval);
+}
+
+PGDLLEXPORT uint32 pgx_pg_atomic_fetch_add_u32(volatile pg_atomic_uint32 *ptr, int32 add_);
+uint32 pgx_pg_atomic_fetch_add_u32(volatile pg_atomic_uint32 *ptr, int32 add_) {
+    return pg_atomic_fetch_add_u32(ptr, add_);
+}
+
+PGDLLEXPORT uint32 pgx_pg_atomic_fetch_sub_u32(volatile pg_atomic_uint32 *ptr, int32 sub_);
+uint32 pgx_pg_atomic_fetch_sub_u32(volatile pg_atomic_uint32 *ptr, int32 sub_) {
+    return pg_atomic_fetch_sub_u32(ptr, sub_);
+}
+
+PGDLLEXPORT void pgx_pg_atomic_init_u64(volatile pg_atomic_uint64 *ptr, uint64 val);
+void pgx_pg_atomic_init_u64(volatile pg_atomic_uint64 *ptr, uint64 val) {
+    pg_atomic_init_u64(ptr, val);
+}
+
+PGDLLEXPORT uint64 pgx_pg_atomic_read_u64(volatile pg_atomic_uint64 *ptr);
+uint64 pgx_pg_atomic_read_u64(volatile pg_atomic_uint64 *ptr) {
+    return pg_atomic_read_u64(ptr);
+}
+
+PGDLLEXPORT void pgx_pg_atomic_write_u64(volatile pg_atomic_uint64 *ptr, uint64 val);
+void pgx_pg_atomic_write_u64(volatile pg_atomic_uint64 *ptr, uint64 val) {
+    pg_atomic_write_u64(ptr, val);
+}
+
+PGDLLEXPORT uint64 pgx_pg_atomic_exchange_u64(volatile pg_atomic_uint64 *ptr, uint64 newval);
+uint64 pgx_pg_atomic_exchange_u64(volatile pg_atomic_uint64 *ptr, uint64 newval) {
+    return pg_atomic_exchange_u64(ptr, newval);
+}
+
+PGDLLEXPORT bool pgx_pg_atomic_compare_exchange_u64(volatile pg_atomic_uint64 *ptr, uint64 *expected, uint64 newval);
+bool pgx_pg_atomic_compare_exchange_u64(volatile pg_atomic_uint64 *ptr, uint64 *expected, uint64 newval) {
+    return pg_atomic_compare_exchange_u64(ptr, expected, newval);
+}
+
+PGDLLEXPORT uint64 pgx_pg_atomic_fetch_add_u64(volatile pg_atomic_uint64 *ptr, int64 add_);
+uint64 pgx_pg_atomic_fetch_add_u64(volatile pg_atomic_uint64 *ptr, int64 add_) {
+    return pg_atomic_fetch_add_u64(ptr, add_);
+}
+
+PGDLLEXPORT uint64 pgx_pg_atomic_fetch_sub_u64(volatile pg_atomic_uint64 *ptr, int64 sub_);
+uint64 pgx_pg_atomic_fetch_sub_u64(volatile pg_atomic_uint64 *ptr, int64 sub_) {
+    return pg_atomic_fetch_sub_u64(ptr, sub_);
+}
@@ -173,6 +173,34 @@ mod all_versions {
         pub fn pgx_list_nth_oid(list: *mut super::List, nth: i32) -> super::Oid;
         pub fn pgx_list_nth_cell(list: *mut super::List, nth: i32) -> *mut super::ListCell;
         pub fn pgx_GETSTRUCT(tuple: pg_sys::HeapTuple) -> *mut std::os::raw::c_char;
+        pub fn pgx_OutputPluginWriteBytes(
+            ctx: *mut super::LogicalDecodingContext,
+            data: *const std::os::raw::c_char,
+            len: super::Size,
+            last_write: bool,
+        );
+    }
+
+    // guarded so that an ERROR raised through them unwinds the Rust stack like any other,
+    // rather than longjmp'ing over it
+    #[pgx_macros::pg_guard]
+    extern "C" {
+        pub fn pgx_elog(level: i32, message: *const std::os::raw::c_char);
+        pub fn pgx_ereport(
+            level: i32,
+            code: i32,
+            message: *const std::os::raw::c_char,
+            file: *const std::os::raw::c_char,
+            lineno: i32,
+            colno: i32,
+        );
+        pub fn pgx_ereport_report(
+            level: i32,
+            code: i32,
+            message: *const std::os::raw::c_char,
+            detail: *const std::os::raw::c_char,
+            hint: *const std::os::raw::c_char,
+        );
     }
 
     #[inline]
@@ -196,3 +196,397 @@ impl PgBuiltInOids {
         }
     }
 }
+
+/// The `PgBuiltInOids` of a builtin type, by its name, or one of its aliases, as it's
+/// written in SQL, such as `sql_type!("timestamp with time zone")`.  A name that isn't
+/// a builtin type is a compile error.
+///
+/// `sql_type!(lookup "name")` is a `SqlTypeLookup`, for types that aren't builtin, whose
+/// Oid is looked up when it's used
+#[macro_export]
+macro_rules! sql_type {
+    ("abstime") => {
+        $crate::PgBuiltInOids::ABSTIMEOID
+    };
+    ("aclitem") => {
+        $crate::PgBuiltInOids::ACLITEMOID
+    };
+    ("anyarray") => {
+        $crate::PgBuiltInOids::ANYARRAYOID
+    };
+    ("anyelement") => {
+        $crate::PgBuiltInOids::ANYELEMENTOID
+    };
+    ("anyenum") => {
+        $crate::PgBuiltInOids::ANYENUMOID
+    };
+    ("anynonarray") => {
+        $crate::PgBuiltInOids::ANYNONARRAYOID
+    };
+    ("\"any\"") => {
+        $crate::PgBuiltInOids::ANYOID
+    };
+    ("anyrange") => {
+        $crate::PgBuiltInOids::ANYRANGEOID
+    };
+    ("bit") => {
+        $crate::PgBuiltInOids::BITOID
+    };
+    ("bool") => {
+        $crate::PgBuiltInOids::BOOLOID
+    };
+    ("boolean") => {
+        $crate::PgBuiltInOids::BOOLOID
+    };
+    ("box") => {
+        $crate::PgBuiltInOids::BOXOID
+    };
+    ("bpchar") => {
+        $crate::PgBuiltInOids::BPCHAROID
+    };
+    ("character") => {
+        $crate::PgBuiltInOids::BPCHAROID
+    };
+    ("char") => {
+        $crate::PgBuiltInOids::BPCHAROID
+    };
+    ("bytea") => {
+        $crate::PgBuiltInOids::BYTEAOID
+    };
+    ("money") => {
+        $crate::PgBuiltInOids::CASHOID
+    };
+    ("\"char\"") => {
+        $crate::PgBuiltInOids::CHAROID
+    };
+    ("cid") => {
+        $crate::PgBuiltInOids::CIDOID
+    };
+    ("cidr") => {
+        $crate::PgBuiltInOids::CIDROID
+    };
+    ("circle") => {
+        $crate::PgBuiltInOids::CIRCLEOID
+    };
+    ("_cstring") => {
+        $crate::PgBuiltInOids::CSTRINGARRAYOID
+    };
+    ("cstring[]") => {
+        $crate::PgBuiltInOids::CSTRINGARRAYOID
+    };
+    ("cstring") => {
+        $crate::PgBuiltInOids::CSTRINGOID
+    };
+    ("date") => {
+        $crate::PgBuiltInOids::DATEOID
+    };
+    ("event_trigger") => {
+        $crate::PgBuiltInOids::EVTTRIGGEROID
+    };
+    ("fdw_handler") => {
+        $crate::PgBuiltInOids::FDW_HANDLEROID
+    };
+    ("_float4") => {
+        $crate::PgBuiltInOids::FLOAT4ARRAYOID
+    };
+    ("float4[]") => {
+        $crate::PgBuiltInOids::FLOAT4ARRAYOID
+    };
+    ("real[]") => {
+        $crate::PgBuiltInOids::FLOAT4ARRAYOID
+    };
+    ("float4") => {
+        $crate::PgBuiltInOids::FLOAT4OID
+    };
+    ("real") => {
+        $crate::PgBuiltInOids::FLOAT4OID
+    };
+    ("float8") => {
+        $crate::PgBuiltInOids::FLOAT8OID
+    };
+    ("double precision") => {
+        $crate::PgBuiltInOids::FLOAT8OID
+    };
+    ("gtsvector") => {
+        $crate::PgBuiltInOids::GTSVECTOROID
+    };
+    ("index_am_handler") => {
+        $crate::PgBuiltInOids::INDEX_AM_HANDLEROID
+    };
+    ("inet") => {
+        $crate::PgBuiltInOids::INETOID
+    };
+    ("_int2") => {
+        $crate::PgBuiltInOids::INT2ARRAYOID
+    };
+    ("int2[]") => {
+        $crate::PgBuiltInOids::INT2ARRAYOID
+    };
+    ("smallint[]") => {
+        $crate::PgBuiltInOids::INT2ARRAYOID
+    };
+    ("int2") => {
+        $crate::PgBuiltInOids::INT2OID
+    };
+    ("smallint") => {
+        $crate::PgBuiltInOids::INT2OID
+    };
+    ("int2vector") => {
+        $crate::PgBuiltInOids::INT2VECTOROID
+    };
+    ("_int4") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("int4[]") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("integer[]") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("int[]") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("int4") => {
+        $crate::PgBuiltInOids::INT4OID
+    };
+    ("integer") => {
+        $crate::PgBuiltInOids::INT4OID
+    };
+    ("int") => {
+        $crate::PgBuiltInOids::INT4OID
+    };
+    ("int4range") => {
+        $crate::PgBuiltInOids::INT4RANGEOID
+    };
+    ("int8") => {
+        $crate::PgBuiltInOids::INT8OID
+    };
+    ("bigint") => {
+        $crate::PgBuiltInOids::INT8OID
+    };
+    ("internal") => {
+        $crate::PgBuiltInOids::INTERNALOID
+    };
+    ("interval") => {
+        $crate::PgBuiltInOids::INTERVALOID
+    };
+    ("jsonb") => {
+        $crate::PgBuiltInOids::JSONBOID
+    };
+    ("json") => {
+        $crate::PgBuiltInOids::JSONOID
+    };
+    ("language_handler") => {
+        $crate::PgBuiltInOids::LANGUAGE_HANDLEROID
+    };
+    ("line") => {
+        $crate::PgBuiltInOids::LINEOID
+    };
+    ("lseg") => {
+        $crate::PgBuiltInOids::LSEGOID
+    };
+    ("pg_lsn") => {
+        $crate::PgBuiltInOids::LSNOID
+    };
+    ("macaddr8") => {
+        $crate::PgBuiltInOids::MACADDR8OID
+    };
+    ("macaddr") => {
+        $crate::PgBuiltInOids::MACADDROID
+    };
+    ("name") => {
+        $crate::PgBuiltInOids::NAMEOID
+    };
+    ("numeric") => {
+        $crate::PgBuiltInOids::NUMERICOID
+    };
+    ("decimal") => {
+        $crate::PgBuiltInOids::NUMERICOID
+    };
+    ("_oid") => {
+        $crate::PgBuiltInOids::OIDARRAYOID
+    };
+    ("oid[]") => {
+        $crate::PgBuiltInOids::OIDARRAYOID
+    };
+    ("oid") => {
+        $crate::PgBuiltInOids::OIDOID
+    };
+    ("oidvector") => {
+        $crate::PgBuiltInOids::OIDVECTOROID
+    };
+    ("opaque") => {
+        $crate::PgBuiltInOids::OPAQUEOID
+    };
+    ("path") => {
+        $crate::PgBuiltInOids::PATHOID
+    };
+    ("pg_ddl_command") => {
+        $crate::PgBuiltInOids::PGDDLCOMMANDOID
+    };
+    ("pg_dependencies") => {
+        $crate::PgBuiltInOids::PGDEPENDENCIESOID
+    };
+    ("pg_ndistinct") => {
+        $crate::PgBuiltInOids::PGNDISTINCTOID
+    };
+    ("pg_node_tree") => {
+        $crate::PgBuiltInOids::PGNODETREEOID
+    };
+    ("point") => {
+        $crate::PgBuiltInOids::POINTOID
+    };
+    ("polygon") => {
+        $crate::PgBuiltInOids::POLYGONOID
+    };
+    ("_record") => {
+        $crate::PgBuiltInOids::RECORDARRAYOID
+    };
+    ("record[]") => {
+        $crate::PgBuiltInOids::RECORDARRAYOID
+    };
+    ("record") => {
+        $crate::PgBuiltInOids::RECORDOID
+    };
+    ("refcursor") => {
+        $crate::PgBuiltInOids::REFCURSOROID
+    };
+    ("regclass") => {
+        $crate::PgBuiltInOids::REGCLASSOID
+    };
+    ("regconfig") => {
+        $crate::PgBuiltInOids::REGCONFIGOID
+    };
+    ("regdictionary") => {
+        $crate::PgBuiltInOids::REGDICTIONARYOID
+    };
+    ("regnamespace") => {
+        $crate::PgBuiltInOids::REGNAMESPACEOID
+    };
+    ("regoperator") => {
+        $crate::PgBuiltInOids::REGOPERATOROID
+    };
+    ("regoper") => {
+        $crate::PgBuiltInOids::REGOPEROID
+    };
+    ("regprocedure") => {
+        $crate::PgBuiltInOids::REGPROCEDUREOID
+    };
+    ("regproc") => {
+        $crate::PgBuiltInOids::REGPROCOID
+    };
+    ("regrole") => {
+        $crate::PgBuiltInOids::REGROLEOID
+    };
+    ("_regtype") => {
+        $crate::PgBuiltInOids::REGTYPEARRAYOID
+    };
+    ("regtype[]") => {
+        $crate::PgBuiltInOids::REGTYPEARRAYOID
+    };
+    ("regtype") => {
+        $crate::PgBuiltInOids::REGTYPEOID
+    };
+    ("reltime") => {
+        $crate::PgBuiltInOids::RELTIMEOID
+    };
+    ("_text") => {
+        $crate::PgBuiltInOids::TEXTARRAYOID
+    };
+    ("text[]") => {
+        $crate::PgBuiltInOids::TEXTARRAYOID
+    };
+    ("text") => {
+        $crate::PgBuiltInOids::TEXTOID
+    };
+    ("tid") => {
+        $crate::PgBuiltInOids::TIDOID
+    };
+    ("time") => {
+        $crate::PgBuiltInOids::TIMEOID
+    };
+    ("time without time zone") => {
+        $crate::PgBuiltInOids::TIMEOID
+    };
+    ("timestamp") => {
+        $crate::PgBuiltInOids::TIMESTAMPOID
+    };
+    ("timestamp without time zone") => {
+        $crate::PgBuiltInOids::TIMESTAMPOID
+    };
+    ("timestamptz") => {
+        $crate::PgBuiltInOids::TIMESTAMPTZOID
+    };
+    ("timestamp with time zone") => {
+        $crate::PgBuiltInOids::TIMESTAMPTZOID
+    };
+    ("timetz") => {
+        $crate::PgBuiltInOids::TIMETZOID
+    };
+    ("time with time zone") => {
+        $crate::PgBuiltInOids::TIMETZOID
+    };
+    ("tinterval") => {
+        $crate::PgBuiltInOids::TINTERVALOID
+    };
+    ("trigger") => {
+        $crate::PgBuiltInOids::TRIGGEROID
+    };
+    ("tsm_handler") => {
+        $crate::PgBuiltInOids::TSM_HANDLEROID
+    };
+    ("tsquery") => {
+        $crate::PgBuiltInOids::TSQUERYOID
+    };
+    ("tsvector") => {
+        $crate::PgBuiltInOids::TSVECTOROID
+    };
+    ("unknown") => {
+        $crate::PgBuiltInOids::UNKNOWNOID
+    };
+    ("uuid") => {
+        $crate::PgBuiltInOids::UUIDOID
+    };
+    ("varbit") => {
+        $crate::PgBuiltInOids::VARBITOID
+    };
+    ("bit varying") => {
+        $crate::PgBuiltInOids::VARBITOID
+    };
+    ("varchar") => {
+        $crate::PgBuiltInOids::VARCHAROID
+    };
+    ("character varying") => {
+        $crate::PgBuiltInOids::VARCHAROID
+    };
+    ("void") => {
+        $crate::PgBuiltInOids::VOIDOID
+    };
+    ("xid") => {
+        $crate::PgBuiltInOids::XIDOID
+    };
+    ("xml") => {
+        $crate::PgBuiltInOids::XMLOID
+    };
+    ("smallserial") => {
+        compile_error!("`smallserial` isn't a type, but shorthand for an `smallint` column with a sequence, so use \"smallint\"")
+    };
+    ("serial") => {
+        compile_error!("`serial` isn't a type, but shorthand for an `integer` column with a sequence, so use \"integer\"")
+    };
+    ("bigserial") => {
+        compile_error!("`bigserial` isn't a type, but shorthand for an `bigint` column with a sequence, so use \"bigint\"")
+    };
+    (lookup $name:literal) => {
+        $crate::SqlTypeLookup::new($name)
+    };
+    ($name:literal) => {
+        compile_error!(concat!(
+            "unknown builtin SQL type ",
+            stringify!($name),
+            ", which must be written in lowercase, as it's named in `pg_type`.  For a type that isn't builtin, use `sql_type!(lookup ",
+            stringify!($name),
+            ")`"
+        ))
+    };
+}
@@ -332,3 +332,817 @@ impl PgBuiltInOids {
         }
     }
 }
+
+/// The `PgBuiltInOids` of a builtin type, by its name, or one of its aliases, as it's
+/// written in SQL, such as `sql_type!("timestamp with time zone")`.  A name that isn't
+/// a builtin type is a compile error.
+///
+/// `sql_type!(lookup "name")` is a `SqlTypeLookup`, for types that aren't builtin, whose
+/// Oid is looked up when it's used
+#[macro_export]
+macro_rules! sql_type {
+    ("_aclitem") => {
+        $crate::PgBuiltInOids::ACLITEMARRAYOID
+    };
+    ("aclitem[]") => {
+        $crate::PgBuiltInOids::ACLITEMARRAYOID
+    };
+    ("aclitem") => {
+        $crate::PgBuiltInOids::ACLITEMOID
+    };
+    ("anyarray") => {
+        $crate::PgBuiltInOids::ANYARRAYOID
+    };
+    ("anyelement") => {
+        $crate::PgBuiltInOids::ANYELEMENTOID
+    };
+    ("anyenum") => {
+        $crate::PgBuiltInOids::ANYENUMOID
+    };
+    ("anynonarray") => {
+        $crate::PgBuiltInOids::ANYNONARRAYOID
+    };
+    ("\"any\"") => {
+        $crate::PgBuiltInOids::ANYOID
+    };
+    ("anyrange") => {
+        $crate::PgBuiltInOids::ANYRANGEOID
+    };
+    ("_bit") => {
+        $crate::PgBuiltInOids::BITARRAYOID
+    };
+    ("bit[]") => {
+        $crate::PgBuiltInOids::BITARRAYOID
+    };
+    ("bit") => {
+        $crate::PgBuiltInOids::BITOID
+    };
+    ("_bool") => {
+        $crate::PgBuiltInOids::BOOLARRAYOID
+    };
+    ("bool[]") => {
+        $crate::PgBuiltInOids::BOOLARRAYOID
+    };
+    ("boolean[]") => {
+        $crate::PgBuiltInOids::BOOLARRAYOID
+    };
+    ("bool") => {
+        $crate::PgBuiltInOids::BOOLOID
+    };
+    ("boolean") => {
+        $crate::PgBuiltInOids::BOOLOID
+    };
+    ("_box") => {
+        $crate::PgBuiltInOids::BOXARRAYOID
+    };
+    ("box[]") => {
+        $crate::PgBuiltInOids::BOXARRAYOID
+    };
+    ("box") => {
+        $crate::PgBuiltInOids::BOXOID
+    };
+    ("_bpchar") => {
+        $crate::PgBuiltInOids::BPCHARARRAYOID
+    };
+    ("bpchar[]") => {
+        $crate::PgBuiltInOids::BPCHARARRAYOID
+    };
+    ("character[]") => {
+        $crate::PgBuiltInOids::BPCHARARRAYOID
+    };
+    ("char[]") => {
+        $crate::PgBuiltInOids::BPCHARARRAYOID
+    };
+    ("bpchar") => {
+        $crate::PgBuiltInOids::BPCHAROID
+    };
+    ("character") => {
+        $crate::PgBuiltInOids::BPCHAROID
+    };
+    ("char") => {
+        $crate::PgBuiltInOids::BPCHAROID
+    };
+    ("_bytea") => {
+        $crate::PgBuiltInOids::BYTEAARRAYOID
+    };
+    ("bytea[]") => {
+        $crate::PgBuiltInOids::BYTEAARRAYOID
+    };
+    ("bytea") => {
+        $crate::PgBuiltInOids::BYTEAOID
+    };
+    ("money") => {
+        $crate::PgBuiltInOids::CASHOID
+    };
+    ("_char") => {
+        $crate::PgBuiltInOids::CHARARRAYOID
+    };
+    ("\"char\"[]") => {
+        $crate::PgBuiltInOids::CHARARRAYOID
+    };
+    ("\"char\"") => {
+        $crate::PgBuiltInOids::CHAROID
+    };
+    ("_cid") => {
+        $crate::PgBuiltInOids::CIDARRAYOID
+    };
+    ("cid[]") => {
+        $crate::PgBuiltInOids::CIDARRAYOID
+    };
+    ("cid") => {
+        $crate::PgBuiltInOids::CIDOID
+    };
+    ("_cidr") => {
+        $crate::PgBuiltInOids::CIDRARRAYOID
+    };
+    ("cidr[]") => {
+        $crate::PgBuiltInOids::CIDRARRAYOID
+    };
+    ("cidr") => {
+        $crate::PgBuiltInOids::CIDROID
+    };
+    ("_circle") => {
+        $crate::PgBuiltInOids::CIRCLEARRAYOID
+    };
+    ("circle[]") => {
+        $crate::PgBuiltInOids::CIRCLEARRAYOID
+    };
+    ("circle") => {
+        $crate::PgBuiltInOids::CIRCLEOID
+    };
+    ("_cstring") => {
+        $crate::PgBuiltInOids::CSTRINGARRAYOID
+    };
+    ("cstring[]") => {
+        $crate::PgBuiltInOids::CSTRINGARRAYOID
+    };
+    ("cstring") => {
+        $crate::PgBuiltInOids::CSTRINGOID
+    };
+    ("_date") => {
+        $crate::PgBuiltInOids::DATEARRAYOID
+    };
+    ("date[]") => {
+        $crate::PgBuiltInOids::DATEARRAYOID
+    };
+    ("date") => {
+        $crate::PgBuiltInOids::DATEOID
+    };
+    ("_daterange") => {
+        $crate::PgBuiltInOids::DATERANGEARRAYOID
+    };
+    ("daterange[]") => {
+        $crate::PgBuiltInOids::DATERANGEARRAYOID
+    };
+    ("daterange") => {
+        $crate::PgBuiltInOids::DATERANGEOID
+    };
+    ("event_trigger") => {
+        $crate::PgBuiltInOids::EVTTRIGGEROID
+    };
+    ("fdw_handler") => {
+        $crate::PgBuiltInOids::FDW_HANDLEROID
+    };
+    ("_float4") => {
+        $crate::PgBuiltInOids::FLOAT4ARRAYOID
+    };
+    ("float4[]") => {
+        $crate::PgBuiltInOids::FLOAT4ARRAYOID
+    };
+    ("real[]") => {
+        $crate::PgBuiltInOids::FLOAT4ARRAYOID
+    };
+    ("float4") => {
+        $crate::PgBuiltInOids::FLOAT4OID
+    };
+    ("real") => {
+        $crate::PgBuiltInOids::FLOAT4OID
+    };
+    ("_float8") => {
+        $crate::PgBuiltInOids::FLOAT8ARRAYOID
+    };
+    ("float8[]") => {
+        $crate::PgBuiltInOids::FLOAT8ARRAYOID
+    };
+    ("double precision[]") => {
+        $crate::PgBuiltInOids::FLOAT8ARRAYOID
+    };
+    ("float8") => {
+        $crate::PgBuiltInOids::FLOAT8OID
+    };
+    ("double precision") => {
+        $crate::PgBuiltInOids::FLOAT8OID
+    };
+    ("_gtsvector") => {
+        $crate::PgBuiltInOids::GTSVECTORARRAYOID
+    };
+    ("gtsvector[]") => {
+        $crate::PgBuiltInOids::GTSVECTORARRAYOID
+    };
+    ("gtsvector") => {
+        $crate::PgBuiltInOids::GTSVECTOROID
+    };
+    ("index_am_handler") => {
+        $crate::PgBuiltInOids::INDEX_AM_HANDLEROID
+    };
+    ("_inet") => {
+        $crate::PgBuiltInOids::INETARRAYOID
+    };
+    ("inet[]") => {
+        $crate::PgBuiltInOids::INETARRAYOID
+    };
+    ("inet") => {
+        $crate::PgBuiltInOids::INETOID
+    };
+    ("_int2") => {
+        $crate::PgBuiltInOids::INT2ARRAYOID
+    };
+    ("int2[]") => {
+        $crate::PgBuiltInOids::INT2ARRAYOID
+    };
+    ("smallint[]") => {
+        $crate::PgBuiltInOids::INT2ARRAYOID
+    };
+    ("int2") => {
+        $crate::PgBuiltInOids::INT2OID
+    };
+    ("smallint") => {
+        $crate::PgBuiltInOids::INT2OID
+    };
+    ("_int2vector") => {
+        $crate::PgBuiltInOids::INT2VECTORARRAYOID
+    };
+    ("int2vector[]") => {
+        $crate::PgBuiltInOids::INT2VECTORARRAYOID
+    };
+    ("int2vector") => {
+        $crate::PgBuiltInOids::INT2VECTOROID
+    };
+    ("_int4") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("int4[]") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("integer[]") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("int[]") => {
+        $crate::PgBuiltInOids::INT4ARRAYOID
+    };
+    ("int4") => {
+        $crate::PgBuiltInOids::INT4OID
+    };
+    ("integer") => {
+        $crate::PgBuiltInOids::INT4OID
+    };
+    ("int") => {
+        $crate::PgBuiltInOids::INT4OID
+    };
+    ("_int4range") => {
+        $crate::PgBuiltInOids::INT4RANGEARRAYOID
+    };
+    ("int4range[]") => {
+        $crate::PgBuiltInOids::INT4RANGEARRAYOID
+    };
+    ("int4range") => {
+        $crate::PgBuiltInOids::INT4RANGEOID
+    };
+    ("_int8") => {
+        $crate::PgBuiltInOids::INT8ARRAYOID
+    };
+    ("int8[]") => {
+        $crate::PgBuiltInOids::INT8ARRAYOID
+    };
+    ("bigint[]") => {
+        $crate::PgBuiltInOids::INT8ARRAYOID
+    };
+    ("int8") => {
+        $crate::PgBuiltInOids::INT8OID
+    };
+    ("bigint") => {
+        $crate::PgBuiltInOids::INT8OID
+    };
+    ("_int8range") => {
+        $crate::PgBuiltInOids::INT8RANGEARRAYOID
+    };
+    ("int8range[]") => {
+        $crate::PgBuiltInOids::INT8RANGEARRAYOID
+    };
+    ("int8range") => {
+        $crate::PgBuiltInOids::INT8RANGEOID
+    };
+    ("internal") => {
+        $crate::PgBuiltInOids::INTERNALOID
+    };
+    ("_interval") => {
+        $crate::PgBuiltInOids::INTERVALARRAYOID
+    };
+    ("interval[]") => {
+        $crate::PgBuiltInOids::INTERVALARRAYOID
+    };
+    ("interval") => {
+        $crate::PgBuiltInOids::INTERVALOID
+    };
+    ("_json") => {
+        $crate::PgBuiltInOids::JSONARRAYOID
+    };
+    ("json[]") => {
+        $crate::PgBuiltInOids::JSONARRAYOID
+    };
+    ("_jsonb") => {
+        $crate::PgBuiltInOids::JSONBARRAYOID
+    };
+    ("jsonb[]") => {
+        $crate::PgBuiltInOids::JSONBARRAYOID
+    };
+    ("jsonb") => {
+        $crate::PgBuiltInOids::JSONBOID
+    };
+    ("json") => {
+        $crate::PgBuiltInOids::JSONOID
+    };
+    ("_jsonpath") => {
+        $crate::PgBuiltInOids::JSONPATHARRAYOID
+    };
+    ("jsonpath[]") => {
+        $crate::PgBuiltInOids::JSONPATHARRAYOID
+    };
+    ("jsonpath") => {
+        $crate::PgBuiltInOids::JSONPATHOID
+    };
+    ("language_handler") => {
+        $crate::PgBuiltInOids::LANGUAGE_HANDLEROID
+    };
+    ("_line") => {
+        $crate::PgBuiltInOids::LINEARRAYOID
+    };
+    ("line[]") => {
+        $crate::PgBuiltInOids::LINEARRAYOID
+    };
+    ("line") => {
+        $crate::PgBuiltInOids::LINEOID
+    };
+    ("_lseg") => {
+        $crate::PgBuiltInOids::LSEGARRAYOID
+    };
+    ("lseg[]") => {
+        $crate::PgBuiltInOids::LSEGARRAYOID
+    };
+    ("lseg") => {
+        $crate::PgBuiltInOids::LSEGOID
+    };
+    ("pg_lsn") => {
+        $crate::PgBuiltInOids::LSNOID
+    };
+    ("_macaddr8") => {
+        $crate::PgBuiltInOids::MACADDR8ARRAYOID
+    };
+    ("macaddr8[]") => {
+        $crate::PgBuiltInOids::MACADDR8ARRAYOID
+    };
+    ("macaddr8") => {
+        $crate::PgBuiltInOids::MACADDR8OID
+    };
+    ("_macaddr") => {
+        $crate::PgBuiltInOids::MACADDRARRAYOID
+    };
+    ("macaddr[]") => {
+        $crate::PgBuiltInOids::MACADDRARRAYOID
+    };
+    ("macaddr") => {
+        $crate::PgBuiltInOids::MACADDROID
+    };
+    ("_money") => {
+        $crate::PgBuiltInOids::MONEYARRAYOID
+    };
+    ("money[]") => {
+        $crate::PgBuiltInOids::MONEYARRAYOID
+    };
+    ("_name") => {
+        $crate::PgBuiltInOids::NAMEARRAYOID
+    };
+    ("name[]") => {
+        $crate::PgBuiltInOids::NAMEARRAYOID
+    };
+    ("name") => {
+        $crate::PgBuiltInOids::NAMEOID
+    };
+    ("_numeric") => {
+        $crate::PgBuiltInOids::NUMERICARRAYOID
+    };
+    ("numeric[]") => {
+        $crate::PgBuiltInOids::NUMERICARRAYOID
+    };
+    ("decimal[]") => {
+        $crate::PgBuiltInOids::NUMERICARRAYOID
+    };
+    ("numeric") => {
+        $crate::PgBuiltInOids::NUMERICOID
+    };
+    ("decimal") => {
+        $crate::PgBuiltInOids::NUMERICOID
+    };
+    ("_numrange") => {
+        $crate::PgBuiltInOids::NUMRANGEARRAYOID
+    };
+    ("numrange[]") => {
+        $crate::PgBuiltInOids::NUMRANGEARRAYOID
+    };
+    ("numrange") => {
+        $crate::PgBuiltInOids::NUMRANGEOID
+    };
+    ("_oid") => {
+        $crate::PgBuiltInOids::OIDARRAYOID
+    };
+    ("oid[]") => {
+        $crate::PgBuiltInOids::OIDARRAYOID
+    };
+    ("oid") => {
+        $crate::PgBuiltInOids::OIDOID
+    };
+    ("_oidvector") => {
+        $crate::PgBuiltInOids::OIDVECTORARRAYOID
+    };
+    ("oidvector[]") => {
+        $crate::PgBuiltInOids::OIDVECTORARRAYOID
+    };
+    ("oidvector") => {
+        $crate::PgBuiltInOids::OIDVECTOROID
+    };
+    ("opaque") => {
+        $crate::PgBuiltInOids::OPAQUEOID
+    };
+    ("_path") => {
+        $crate::PgBuiltInOids::PATHARRAYOID
+    };
+    ("path[]") => {
+        $crate::PgBuiltInOids::PATHARRAYOID
+    };
+    ("path") => {
+        $crate::PgBuiltInOids::PATHOID
+    };
+    ("pg_ddl_command") => {
+        $crate::PgBuiltInOids::PGDDLCOMMANDOID
+    };
+    ("pg_dependencies") => {
+        $crate::PgBuiltInOids::PGDEPENDENCIESOID
+    };
+    ("pg_mcv_list") => {
+        $crate::PgBuiltInOids::PGMCVLISTOID
+    };
+    ("pg_ndistinct") => {
+        $crate::PgBuiltInOids::PGNDISTINCTOID
+    };
+    ("pg_node_tree") => {
+        $crate::PgBuiltInOids::PGNODETREEOID
+    };
+    ("_pg_lsn") => {
+        $crate::PgBuiltInOids::PG_LSNARRAYOID
+    };
+    ("pg_lsn[]") => {
+        $crate::PgBuiltInOids::PG_LSNARRAYOID
+    };
+    ("_point") => {
+        $crate::PgBuiltInOids::POINTARRAYOID
+    };
+    ("point[]") => {
+        $crate::PgBuiltInOids::POINTARRAYOID
+    };
+    ("point") => {
+        $crate::PgBuiltInOids::POINTOID
+    };
+    ("_polygon") => {
+        $crate::PgBuiltInOids::POLYGONARRAYOID
+    };
+    ("polygon[]") => {
+        $crate::PgBuiltInOids::POLYGONARRAYOID
+    };
+    ("polygon") => {
+        $crate::PgBuiltInOids::POLYGONOID
+    };
+    ("_record") => {
+        $crate::PgBuiltInOids::RECORDARRAYOID
+    };
+    ("record[]") => {
+        $crate::PgBuiltInOids::RECORDARRAYOID
+    };
+    ("record") => {
+        $crate::PgBuiltInOids::RECORDOID
+    };
+    ("_refcursor") => {
+        $crate::PgBuiltInOids::REFCURSORARRAYOID
+    };
+    ("refcursor[]") => {
+        $crate::PgBuiltInOids::REFCURSORARRAYOID
+    };
+    ("refcursor") => {
+        $crate::PgBuiltInOids::REFCURSOROID
+    };
+    ("_regclass") => {
+        $crate::PgBuiltInOids::REGCLASSARRAYOID
+    };
+    ("regclass[]") => {
+        $crate::PgBuiltInOids::REGCLASSARRAYOID
+    };
+    ("regclass") => {
+        $crate::PgBuiltInOids::REGCLASSOID
+    };
+    ("_regconfig") => {
+        $crate::PgBuiltInOids::REGCONFIGARRAYOID
+    };
+    ("regconfig[]") => {
+        $crate::PgBuiltInOids::REGCONFIGARRAYOID
+    };
+    ("regconfig") => {
+        $crate::PgBuiltInOids::REGCONFIGOID
+    };
+    ("_regdictionary") => {
+        $crate::PgBuiltInOids::REGDICTIONARYARRAYOID
+    };
+    ("regdictionary[]") => {
+        $crate::PgBuiltInOids::REGDICTIONARYARRAYOID
+    };
+    ("regdictionary") => {
+        $crate::PgBuiltInOids::REGDICTIONARYOID
+    };
+    ("_regnamespace") => {
+        $crate::PgBuiltInOids::REGNAMESPACEARRAYOID
+    };
+    ("regnamespace[]") => {
+        $crate::PgBuiltInOids::REGNAMESPACEARRAYOID
+    };
+    ("regnamespace") => {
+        $crate::PgBuiltInOids::REGNAMESPACEOID
+    };
+    ("_regoper") => {
+        $crate::PgBuiltInOids::REGOPERARRAYOID
+    };
+    ("regoper[]") => {
+        $crate::PgBuiltInOids::REGOPERARRAYOID
+    };
+    ("_regoperator") => {
+        $crate::PgBuiltInOids::REGOPERATORARRAYOID
+    };
+    ("regoperator[]") => {
+        $crate::PgBuiltInOids::REGOPERATORARRAYOID
+    };
+    ("regoperator") => {
+        $crate::PgBuiltInOids::REGOPERATOROID
+    };
+    ("regoper") => {
+        $crate::PgBuiltInOids::REGOPEROID
+    };
+    ("_regproc") => {
+        $crate::PgBuiltInOids::REGPROCARRAYOID
+    };
+    ("regproc[]") => {
+        $crate::PgBuiltInOids::REGPROCARRAYOID
+    };
+    ("_regprocedure") => {
+        $crate::PgBuiltInOids::REGPROCEDUREARRAYOID
+    };
+    ("regprocedure[]") => {
+        $crate::PgBuiltInOids::REGPROCEDUREARRAYOID
+    };
+    ("regprocedure") => {
+        $crate::PgBuiltInOids::REGPROCEDUREOID
+    };
+    ("regproc") => {
+        $crate::PgBuiltInOids::REGPROCOID
+    };
+    ("_regrole") => {
+        $crate::PgBuiltInOids::REGROLEARRAYOID
+    };
+    ("regrole[]") => {
+        $crate::PgBuiltInOids::REGROLEARRAYOID
+    };
+    ("regrole") => {
+        $crate::PgBuiltInOids::REGROLEOID
+    };
+    ("_regtype") => {
+        $crate::PgBuiltInOids::REGTYPEARRAYOID
+    };
+    ("regtype[]") => {
+        $crate::PgBuiltInOids::REGTYPEARRAYOID
+    };
+    ("regtype") => {
+        $crate::PgBuiltInOids::REGTYPEOID
+    };
+    ("table_am_handler") => {
+        $crate::PgBuiltInOids::TABLE_AM_HANDLEROID
+    };
+    ("_text") => {
+        $crate::PgBuiltInOids::TEXTARRAYOID
+    };
+    ("text[]") => {
+        $crate::PgBuiltInOids::TEXTARRAYOID
+    };
+    ("text") => {
+        $crate::PgBuiltInOids::TEXTOID
+    };
+    ("_tid") => {
+        $crate::PgBuiltInOids::TIDARRAYOID
+    };
+    ("tid[]") => {
+        $crate::PgBuiltInOids::TIDARRAYOID
+    };
+    ("tid") => {
+        $crate::PgBuiltInOids::TIDOID
+    };
+    ("_time") => {
+        $crate::PgBuiltInOids::TIMEARRAYOID
+    };
+    ("time[]") => {
+        $crate::PgBuiltInOids::TIMEARRAYOID
+    };
+    ("time without time zone[]") => {
+        $crate::PgBuiltInOids::TIMEARRAYOID
+    };
+    ("time") => {
+        $crate::PgBuiltInOids::TIMEOID
+    };
+    ("time without time zone") => {
+        $crate::PgBuiltInOids::TIMEOID
+    };
+    ("_timestamp") => {
+        $crate::PgBuiltInOids::TIMESTAMPARRAYOID
+    };
+    ("timestamp[]") => {
+        $crate::PgBuiltInOids::TIMESTAMPARRAYOID
+    };
+    ("timestamp without time zone[]") => {
+        $crate::PgBuiltInOids::TIMESTAMPARRAYOID
+    };
+    ("timestamp") => {
+        $crate::PgBuiltInOids::TIMESTAMPOID
+    };
+    ("timestamp without time zone") => {
+        $crate::PgBuiltInOids::TIMESTAMPOID
+    };
+    ("_timestamptz") => {
+        $crate::PgBuiltInOids::TIMESTAMPTZARRAYOID
+    };
+    ("timestamptz[]") => {
+        $crate::PgBuiltInOids::TIMESTAMPTZARRAYOID
+    };
+    ("timestamp with time zone[]") => {
+        $crate::PgBuiltInOids::TIMESTAMPTZARRAYOID
+    };
+    ("timestamptz") => {
+        $crate::PgBuiltInOids::TIMESTAMPTZOID
+    };
+    ("timestamp with time zone") => {
+        $crate::PgBuiltInOids::TIMESTAMPTZOID
+    };
+    ("_timetz") => {
+        $crate::PgBuiltInOids::TIMETZARRAYOID
+    };
+    ("timetz[]") => {
+        $crate::PgBuiltInOids::TIMETZARRAYOID
+    };
+    ("time with time zone[]") => {
+        $crate::PgBuiltInOids::TIMETZARRAYOID
+    };
+    ("timetz") => {
+        $crate::PgBuiltInOids::TIMETZOID
+    };
+    ("time with time zone") => {
+        $crate::PgBuiltInOids::TIMETZOID
+    };
+    ("trigger") => {
+        $crate::PgBuiltInOids::TRIGGEROID
+    };
+    ("tsm_handler") => {
+        $crate::PgBuiltInOids::TSM_HANDLEROID
+    };
+    ("_tsquery") => {
+        $crate::PgBuiltInOids::TSQUERYARRAYOID
+    };
+    ("tsquery[]") => {
+        $crate::PgBuiltInOids::TSQUERYARRAYOID
+    };
+    ("tsquery") => {
+        $crate::PgBuiltInOids::TSQUERYOID
+    };
+    ("_tsrange") => {
+        $crate::PgBuiltInOids::TSRANGEARRAYOID
+    };
+    ("tsrange[]") => {
+        $crate::PgBuiltInOids::TSRANGEARRAYOID
+    };
+    ("tsrange") => {
+        $crate::PgBuiltInOids::TSRANGEOID
+    };
+    ("_tstzrange") => {
+        $crate::PgBuiltInOids::TSTZRANGEARRAYOID
+    };
+    ("tstzrange[]") => {
+        $crate::PgBuiltInOids::TSTZRANGEARRAYOID
+    };
+    ("tstzrange") => {
+        $crate::PgBuiltInOids::TSTZRANGEOID
+    };
+    ("_tsvector") => {
+        $crate::PgBuiltInOids::TSVECTORARRAYOID
+    };
+    ("tsvector[]") => {
+        $crate::PgBuiltInOids::TSVECTORARRAYOID
+    };
+    ("tsvector") => {
+        $crate::PgBuiltInOids::TSVECTOROID
+    };
+    ("_txid_snapshot") => {
+        $crate::PgBuiltInOids::TXID_SNAPSHOTARRAYOID
+    };
+    ("txid_snapshot[]") => {
+        $crate::PgBuiltInOids::TXID_SNAPSHOTARRAYOID
+    };
+    ("txid_snapshot") => {
+        $crate::PgBuiltInOids::TXID_SNAPSHOTOID
+    };
+    ("unknown") => {
+        $crate::PgBuiltInOids::UNKNOWNOID
+    };
+    ("_uuid") => {
+        $crate::PgBuiltInOids::UUIDARRAYOID
+    };
+    ("uuid[]") => {
+        $crate::PgBuiltInOids::UUIDARRAYOID
+    };
+    ("uuid") => {
+        $crate::PgBuiltInOids::UUIDOID
+    };
+    ("_varbit") => {
+        $crate::PgBuiltInOids::VARBITARRAYOID
+    };
+    ("varbit[]") => {
+        $crate::PgBuiltInOids::VARBITARRAYOID
+    };
+    ("bit varying[]") => {
+        $crate::PgBuiltInOids::VARBITARRAYOID
+    };
+    ("varbit") => {
+        $crate::PgBuiltInOids::VARBITOID
+    };
+    ("bit varying") => {
+        $crate::PgBuiltInOids::VARBITOID
+    };
+    ("_varchar") => {
+        $crate::PgBuiltInOids::VARCHARARRAYOID
+    };
+    ("varchar[]") => {
+        $crate::PgBuiltInOids::VARCHARARRAYOID
+    };
+    ("character varying[]") => {
+        $crate::PgBuiltInOids::VARCHARARRAYOID
+    };
+    ("varchar") => {
+        $crate::PgBuiltInOids::VARCHAROID
+    };
+    ("character varying") => {
+        $crate::PgBuiltInOids::VARCHAROID
+    };
+    ("void") => {
+        $crate::PgBuiltInOids::VOIDOID
+    };
+    ("_xid") => {
+        $crate::PgBuiltInOids::XIDARRAYOID
+    };
+    ("xid[]") => {
+        $crate::PgBuiltInOids::XIDARRAYOID
+    };
+    ("xid") => {
+        $crate::PgBuiltInOids::XIDOID
+    };
+    ("_xml") => {
+        $crate::PgBuiltInOids::XMLARRAYOID
+    };
+    ("xml[]") => {
+        $crate::PgBuiltInOids::XMLARRAYOID
+    };
+    ("xml") => {
+        $crate::PgBuiltInOids::XMLOID
+    };
+    ("smallserial") => {
+        compile_error!("`smallserial` isn't a type, but shorthand for an `smallint` column with a sequence, so use \"smallint\"")
+    };
+    ("serial") => {
+        compile_error!("`serial` isn't a type, but shorthand for an `integer` column with a sequence, so use \"integer\"")
+    };
+    ("bigserial") => {
+        compile_error!("`bigserial` isn't a type, but shorthand for an `bigint` column with a sequence, so use \"bigint\"")
+    };
+    (lookup $name:literal) => {
+        $crate::SqlTypeLookup::new($name)
+    };
+    ($name:literal) => {
+        compile_error!(concat!(
+            "unknown builtin SQL type ",
+            stringify!($name),
+            ", which must be written in lowercase, as it's named in `pg_type`.  For a type that isn't builtin, use `sql_type!(lookup ",
+            stringify!($name),
+            ")`"
+        ))
+    };
+}
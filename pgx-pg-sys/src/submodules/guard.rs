@@ -5,8 +5,8 @@
 
 use crate::FlushErrorState;
 use std::any::Any;
-use std::cell::Cell;
-use std::panic::catch_unwind;
+use std::cell::{Cell, RefCell};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 
 extern "C" {
     fn pg_re_throw();
@@ -51,6 +51,13 @@ struct PanicLocation {
 
 thread_local! { static PANIC_LOCATION: Cell<Option<PanicLocation>> = Cell::new(None) }
 
+thread_local! {
+    /// The context of the error context frames a Rust panic has unwound out of, innermost first,
+    /// to report along with it once it's converted into an ERROR.  `None` unless a panic, rather
+    /// than a Postgres ERROR, is unwinding
+    static PANIC_CONTEXT: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
 fn take_panic_location() -> PanicLocation {
     PANIC_LOCATION.with(|p| match p.take() {
         Some(location) => location,
@@ -64,6 +71,60 @@ fn take_panic_location() -> PanicLocation {
     })
 }
 
+fn take_panic_context() -> Vec<String> {
+    PANIC_CONTEXT.with(|context| context.take().unwrap_or_default())
+}
+
+/// Is a Rust panic unwinding that wants the context of the error context frames it unwinds out of?
+#[doc(hidden)]
+pub fn wants_panic_context() -> bool {
+    PANIC_CONTEXT.with(|context| context.borrow().is_some())
+}
+
+/// Add the context of an error context frame the unwinding panic is leaving.  If `context()`
+/// panics too, that frame's context is dropped rather than its panic replacing the original
+#[doc(hidden)]
+pub fn push_panic_context<F: FnOnce() -> String>(context: F) {
+    let mut collected = match PANIC_CONTEXT.with(|context| context.take()) {
+        Some(collected) => collected,
+        None => return,
+    };
+
+    // set aside what's been collected, as the panic hook resets it for a new panic
+    if let Ok(line) = catch_unwind(AssertUnwindSafe(context)) {
+        collected.push(line);
+    }
+    PANIC_CONTEXT.with(|context| context.replace(Some(collected)));
+}
+
+/// Forget about a panic that was caught without being reported
+#[doc(hidden)]
+pub fn forget_panic() {
+    take_panic_location();
+    take_panic_context();
+}
+
+/// An error context callback that reports a panic's context, with `arg` being its `Vec<String>`
+unsafe extern "C" fn report_panic_context(arg: *mut std::os::raw::c_void) {
+    let context = &*(arg as *const Vec<String>);
+    for line in context {
+        errcontext(line);
+    }
+}
+
+/// Add `line` to the context of the error being reported, as `errcontext()` does.  This can only
+/// be called from an error context callback
+#[doc(hidden)]
+pub unsafe fn errcontext(line: &str) {
+    // errcontext_msg() copies it into ErrorContext
+    let line = std::ffi::CString::new(line.replace('\0', "")).unwrap();
+    crate::set_errcontext_domain(std::ptr::null());
+    crate::errcontext_msg(
+        b"%s\0".as_ptr() as *const std::os::raw::c_char,
+        line.as_ptr(),
+    );
+}
+
 // via pg_module_magic!() this gets set to Some(()) for the "main" thread, and remains at None
 // for all other threads.
 #[cfg(debug_assertions)]
@@ -97,6 +158,15 @@ pub fn register_pg_guard_panic_handler() {
             }
         }
 
+        // a Postgres ERROR already has its context, but a panic collects it while unwinding
+        PANIC_CONTEXT.with(|context| {
+            context.replace(if info.payload().is::<JumpContext>() {
+                None
+            } else {
+                Some(Vec::new())
+            })
+        });
+
         PANIC_LOCATION.with(|p| {
             let existing = p.take();
 
@@ -116,6 +186,84 @@ pub fn register_pg_guard_panic_handler() {
     }))
 }
 
+/// The Postgres state on entry to a guard, which is restored when the guard catches an error.
+/// It's kept on the guard's own stack frame, so taking it costs only a few reads
+#[derive(Debug, Clone, Copy)]
+struct GuardSnapshot {
+    exception_stack: *mut crate::sigjmp_buf,
+    error_context_stack: *mut crate::ErrorContextCallback,
+    memory_context: crate::MemoryContext,
+    spi_depth: usize,
+}
+
+impl GuardSnapshot {
+    fn take() -> Self {
+        unsafe {
+            GuardSnapshot {
+                exception_stack: crate::PG_exception_stack,
+                error_context_stack: crate::error_context_stack,
+                memory_context: crate::CurrentMemoryContext,
+                spi_depth: SPI_DEPTH.with(|depth| depth.get()),
+            }
+        }
+    }
+
+    /// After an error, unwind everything back to how it was on entry to the guard.  Postgres'
+    /// `errfinish()` leaves `CurrentMemoryContext` set to `ErrorContext`, which is reset by the
+    /// next `FlushErrorState()`, so continuing in it would lead to use-after-free bugs.
+    ///
+    /// SPI connections that `Spi::connect()` made inside the guard, and that a Postgres ERROR
+    /// unwound out of, are forgotten about but not finished.  There may be connections made by
+    /// C code, such as a PL/pgSQL function, on top of them, and `SPI_finish()` always finishes
+    /// the innermost connection.  Only aborting the (sub)transaction they were made in cleans
+    /// them all up, as `pg_try_subtransaction()` does.
+    unsafe fn restore_after_error(&self) {
+        crate::PG_exception_stack = self.exception_stack;
+        crate::error_context_stack = self.error_context_stack;
+        SPI_DEPTH.with(|depth| depth.set(self.spi_depth));
+        crate::CurrentMemoryContext = self.memory_context;
+    }
+}
+
+thread_local! {
+    /// How many guards (`guard()`, `pg_try()`, `#[pg_guard]`, `#[pg_extern]`) we're currently
+    /// inside of
+    static GUARD_DEPTH: Cell<usize> = Cell::new(0);
+
+    /// How many SPI connections `Spi::connect()` currently has open
+    static SPI_DEPTH: Cell<usize> = Cell::new(0);
+}
+
+/// How many guards (`guard()`, `pg_try()`, `#[pg_guard]`, `#[pg_extern]`) this thread is currently
+/// inside of
+pub fn guard_depth() -> usize {
+    GUARD_DEPTH.with(|depth| depth.get())
+}
+
+/// How many SPI connections `Spi::connect()` currently has open
+pub fn spi_depth() -> usize {
+    SPI_DEPTH.with(|depth| depth.get())
+}
+
+/// Record that `Spi::connect()` has called `SPI_connect()`
+#[doc(hidden)]
+pub fn push_spi_connection() {
+    SPI_DEPTH.with(|depth| depth.set(depth.get() + 1));
+}
+
+/// Record that `Spi::connect()` has called `SPI_finish()`
+#[doc(hidden)]
+pub fn pop_spi_connection() {
+    SPI_DEPTH.with(|depth| depth.set(depth.get() - 1));
+}
+
+/// Is this thread unwinding from a Postgres ERROR, rather than a Rust panic?  The ERROR may have
+/// longjmp'd over C code that's now in an inconsistent state, such as its SPI connections
+#[doc(hidden)]
+pub fn unwinding_from_error() -> bool {
+    std::thread::panicking() && !wants_panic_context()
+}
+
 /// A `std::result::Result`-type value returned from `pg_try()` that allows for performing cleanup
 /// work after a closure raised an error and before it is possibly rethrown
 #[must_use = "this `PgTryResult` may be be holding a Postgres ERROR.  It must be consumed or rethrown"]
@@ -127,6 +275,18 @@ impl<T> PgTryResult<T> {
         self.unwrap_or_rethrow(|| {})
     }
 
+    /// The SQLSTATE (such as `PgSqlErrorCode::ERRCODE_CHECK_VIOLATION as i32`) of the Postgres
+    /// ERROR the try block raised.  `None` if it returned normally or was a Rust panic
+    pub fn sqlerrcode(&self) -> Option<i32> {
+        match &self.0 {
+            Err(e) if e.downcast_ref::<JumpContext>().is_some() => {
+                // the caught ERROR stays on Postgres' error stack until it's flushed or rethrown
+                Some(unsafe { crate::geterrcode() })
+            }
+            _ => None,
+        }
+    }
+
     /// ## Safety
     ///
     /// This function is unsafe because you might be ignoring a caught Postgres ERROR (or Rust panic)
@@ -139,6 +299,7 @@ impl<T> PgTryResult<T> {
             Ok(result) => result,
             Err(_) => {
                 FlushErrorState();
+                forget_panic();
                 value
             }
         }
@@ -161,6 +322,7 @@ impl<T> PgTryResult<T> {
             Ok(result) => result,
             Err(_) => {
                 FlushErrorState();
+                forget_panic();
                 cleanup()
             }
         }
@@ -175,10 +337,7 @@ impl<T> PgTryResult<T> {
     {
         match self.0 {
             Ok(result) => result,
-            Err(e) => {
-                catch_guard(e, cleanup);
-                unreachable!("failed to rethrow ERROR during pg_try().unwrap_or_rethrow()")
-            }
+            Err(e) => rethrow(e, cleanup),
         }
     }
 
@@ -195,10 +354,7 @@ impl<T> PgTryResult<T> {
                 finally_block();
                 result
             }
-            Err(e) => {
-                catch_guard(e, finally_block);
-                unreachable!("failed to rethrow ERROR during pg_try().finally_or_rethrow()")
-            }
+            Err(e) => rethrow(e, finally_block),
         }
     }
 }
@@ -215,7 +371,13 @@ pub fn guard<Func, R>(f: Func) -> R
 where
     Func: FnOnce() -> R + std::panic::UnwindSafe + std::panic::RefUnwindSafe,
 {
-    pg_try(f).unwrap()
+    match try_guard(f).0 {
+        Ok(result) => result,
+        Err(e) => {
+            catch_guard(e, || {});
+            unreachable!("failed to rethrow ERROR during guard()")
+        }
+    }
 }
 
 /// Similar to `guard`, but allows the caller to unwrap the result in various ways, possibly
@@ -227,19 +389,84 @@ where
     try_guard(try_func)
 }
 
+/// Similar to `pg_try()`, but runs `try_func` in a subtransaction, as PL/pgSQL does for a
+/// `BEGIN ... EXCEPTION` block.
+///
+/// If `try_func` raises an error the subtransaction is rolled back, which releases everything it
+/// acquired before the error: snapshots, buffer pins, locks, and SPI connections, including any
+/// made by C code such as a PL/pgSQL function it called.  That makes it possible to ignore the
+/// error and continue the transaction, which isn't the case after `pg_try()`.  If it returns,
+/// the subtransaction is released, and what it acquired belongs to the enclosing transaction.
+///
+/// It must be called inside a transaction, but not in parallel mode, where subtransactions
+/// can't be started.
+pub fn pg_try_subtransaction<Try, R>(try_func: Try) -> PgTryResult<R>
+where
+    Try: FnOnce() -> R + std::panic::UnwindSafe + std::panic::RefUnwindSafe,
+{
+    unsafe {
+        let (context, owner) = (crate::CurrentMemoryContext, crate::CurrentResourceOwner);
+        crate::BeginInternalSubTransaction(std::ptr::null_mut());
+        crate::CurrentMemoryContext = context;
+
+        let result = try_guard(try_func);
+
+        // a caught ERROR stays on the error stack while the subtransaction is rolled back, as it
+        // does while Postgres aborts a transaction, so it can still be inspected or rethrown
+        if result.0.is_ok() {
+            crate::ReleaseCurrentSubTransaction();
+        } else {
+            crate::RollbackAndReleaseCurrentSubTransaction();
+        }
+        crate::CurrentMemoryContext = context;
+        crate::CurrentResourceOwner = owner;
+
+        result
+    }
+}
+
 fn try_guard<Try, R>(try_func: Try) -> PgTryResult<R>
 where
     Try: FnOnce() -> R + std::panic::UnwindSafe + std::panic::RefUnwindSafe,
 {
+    let snapshot = GuardSnapshot::take();
+    let depth = GUARD_DEPTH.with(|depth| depth.replace(depth.get() + 1));
+
     // run try_func() in a catch_unwind, as we never want a Rust panic! to leak
     // from this function.  It's imperative that we nevery try to panic! across
     // FFI (extern "C") function boundaries
     let result = catch_unwind(try_func);
 
+    // guards inside of try_func() that rethrew an error by longjmp'ing into Postgres never
+    // got to leave, so reset the depth to ours rather than counting down
+    GUARD_DEPTH.with(|guard_depth| guard_depth.set(depth));
+    if result.is_err() {
+        unsafe { snapshot.restore_after_error() }
+    }
+
     // return our result -- it could be Ok(), or it could be an Err()
     PgTryResult(result)
 }
 
+/// Rethrow an error caught by `pg_try()` after running `cleanup`.
+///
+/// Inside of another guard, which is where `pg_try()` is normally used, the error continues
+/// unwinding the Rust stack up to that guard, so that every guard in between restores its
+/// snapshot.  Only a guard at the boundary with Postgres (`guard()`, and so `#[pg_guard]` and
+/// `#[pg_extern]`) rethrows it into Postgres.
+fn rethrow<Cleanup>(error: Box<dyn Any + std::marker::Send>, cleanup: Cleanup) -> !
+where
+    Cleanup: FnOnce(),
+{
+    if guard_depth() > 0 {
+        cleanup();
+        std::panic::resume_unwind(error)
+    } else {
+        catch_guard(error, cleanup);
+        unreachable!("failed to rethrow ERROR during pg_try()")
+    }
+}
+
 fn catch_guard<Catch>(error: Box<dyn Any + std::marker::Send>, catch_func: Catch)
 where
     Catch: FnOnce(),
@@ -255,10 +482,21 @@ where
         // the panic!()
         Ok(message) => {
             let location = take_panic_location();
+            let context = take_panic_context();
             let c_message = std::ffi::CString::new(message.clone()).unwrap();
             let c_file = std::ffi::CString::new(location.file).unwrap();
 
             unsafe {
+                // the error context frames the panic unwound out of are the innermost context
+                let mut callback = crate::ErrorContextCallback {
+                    previous: crate::error_context_stack,
+                    callback: Some(report_panic_context),
+                    arg: &context as *const Vec<String> as *mut std::os::raw::c_void,
+                };
+                if !context.is_empty() {
+                    crate::error_context_stack = &mut callback;
+                }
+
                 pgx_ereport(
                     crate::ERROR as i32,
                     2600, // ERRCODE_INTERNAL_ERROR
@@ -273,6 +511,7 @@ where
 
         // the error is a JumpContext, so we need to longjmp back into Postgres
         Err(_) => unsafe {
+            take_panic_context();
             pg_re_throw();
             unreachable!("siglongjmp failed");
         },
@@ -43,3 +43,32 @@ impl PgOid {
         }
     }
 }
+
+/// A type that isn't builtin, named by `sql_type!(lookup "name")`, whose Oid is looked up when
+/// it's used, rather than when it's compiled.  It's a different type from the `PgBuiltInOids` of
+/// `sql_type!("name")` so that the lookup is plain to see.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SqlTypeLookup(&'static str);
+
+impl SqlTypeLookup {
+    pub const fn new(name: &'static str) -> Self {
+        SqlTypeLookup(name)
+    }
+
+    /// The name of the type, as it's written in SQL
+    pub fn name(self) -> &'static str {
+        self.0
+    }
+
+    /// The Oid of the type, which is resolved like any type name in SQL, so can be
+    /// schema-qualified, or found on the `search_path`.  Raises an ERROR if there's no such type
+    pub fn oid(self) -> PgOid {
+        let name = std::ffi::CString::new(self.0).expect("type name contained a null byte");
+        let mut oid = pg_sys::InvalidOid;
+        let mut typmod = 0;
+        unsafe {
+            pg_sys::parseTypeString(name.as_ptr(), &mut oid, &mut typmod, false);
+        }
+        PgOid::from(oid)
+    }
+}
@@ -7,10 +7,16 @@ use crate as pg_sys;
 /// Converts a `pg_sys::NameData` struct into a `&str`.  
 ///
 /// This is a zero-copy operation and the returned `&str` is tied to the lifetime
-/// of the provided `pg_sys::NameData`
+/// of the provided `pg_sys::NameData`.  The name ends at its first NUL, or after `NAMEDATALEN`
+/// bytes if it has none, and whatever follows that NUL isn't looked at.
+///
+/// ## Panics
+///
+/// If the name isn't valid UTF-8
 #[inline]
 pub fn name_data_to_str(name_data: &pg_sys::NameData) -> &str {
-    unsafe { std::ffi::CStr::from_ptr(name_data.data.as_ptr()) }
-        .to_str()
-        .unwrap()
+    let data = &name_data.data;
+    let len = data.iter().position(|c| *c == 0).unwrap_or(data.len());
+    let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, len) };
+    std::str::from_utf8(bytes).expect("NameData is not valid UTF-8")
 }
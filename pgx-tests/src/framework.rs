@@ -136,6 +136,14 @@ pub fn run_test(
     }
 }
 
+/// Start Postgres, if a test hasn't already, and connect a new session to it, for a plain
+/// `#[test]` that needs sessions of its own, such as to commit, or to hold a lock that another
+/// session waits on
+pub fn start_and_connect(postgresql_conf: Vec<&'static str>) -> (postgres::Client, String) {
+    initialize_test_framework(postgresql_conf);
+    client()
+}
+
 fn format_loglines(session_id: &str, loglines: &LogLines) -> String {
     let mut result = String::new();
 
@@ -180,7 +188,7 @@ fn initialize_test_framework(postgresql_conf: Vec<&'static str>) -> (LogLines, S
 }
 
 fn get_pg_config() -> PgConfig {
-    let pgx = Pgx::from_config().expect("Unable to load pgx config");
+    let pgx = Pgx::from_config().unwrap_or_else(|e| panic!("Unable to load pgx config: {}", e));
     pgx.get(&format!("pg{}", pg_sys::get_pg_major_version_num()))
         .expect("not a valid postgres version")
         .clone()
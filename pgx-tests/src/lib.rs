@@ -14,6 +14,17 @@ pub mod pg_test {
     }
 
     pub fn postgresql_conf_options() -> Vec<&'static str> {
-        vec![]
+        let mut options = vec![
+            // for PREPARE TRANSACTION in xact_tests
+            "max_prepared_transactions = 10",
+        ];
+
+        // wal_tests only decode the messages they write with `wal_level = logical`, which
+        // changes what every other test writes to the WAL, so it's opt-in
+        if std::env::var("PGX_TESTS_LOGICAL_DECODING").is_ok() {
+            options.push("wal_level = logical");
+        }
+
+        options
     }
 }
@@ -211,4 +211,67 @@ mod tests {
             .expect("failed to get SPI result");
         assert!(rc)
     }
+
+    /// The text of `datum`, an array of type `array_type`, along with its dimensions
+    fn array_text_and_dims(array_type: PgBuiltInOids, datum: pg_sys::Datum) -> Option<String> {
+        Spi::get_one_with_args::<String>(
+            "SELECT $1::text || ' ' || coalesce(array_dims($1), '[]')",
+            vec![(array_type.oid(), Some(datum))],
+        )
+    }
+
+    #[pg_test]
+    fn test_array_builder_two_dimensions() {
+        let mut builder = PgArrayBuilder::<i32>::new();
+        builder.push_dimension(2).push_dimension(3);
+        for element in vec![Some(1), Some(2), Some(3), Some(4), None, Some(6)] {
+            builder.push_element(element);
+        }
+
+        assert_eq!(
+            array_text_and_dims(PgBuiltInOids::INT4ARRAYOID, builder.build()),
+            Some("{{1,2,3},{4,NULL,6}} [1:2][1:3]".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_array_builder_lower_bounds() {
+        let mut builder = PgArrayBuilder::<&str>::new();
+        builder
+            .push_dimension_with_lower_bound(1, 0)
+            .push_dimension_with_lower_bound(2, -1);
+        builder.push_element(Some("a")).push_element(Some("b"));
+
+        assert_eq!(
+            array_text_and_dims(PgBuiltInOids::TEXTARRAYOID, builder.build()),
+            Some("[0:0][-1:0]={{a,b}} [0:0][-1:0]".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_array_builder_without_dimensions() {
+        let mut builder = PgArrayBuilder::<i32>::new();
+        builder.push_element(Some(1)).push_element(None);
+        assert_eq!(
+            array_text_and_dims(PgBuiltInOids::INT4ARRAYOID, builder.build()),
+            Some("{1,NULL} [1:2]".to_string())
+        );
+
+        let mut builder = PgArrayBuilder::<i32>::new();
+        builder.push_dimension(0).push_dimension(3);
+        assert_eq!(
+            array_text_and_dims(PgBuiltInOids::INT4ARRAYOID, builder.build()),
+            Some("{} []".to_string())
+        );
+    }
+
+    #[pg_test(error = "array of dimensions [2, 2] needs 4 elements, but 3 were pushed")]
+    fn test_array_builder_wrong_element_count() {
+        let mut builder = PgArrayBuilder::<i32>::new();
+        builder.push_dimension(2).push_dimension(2);
+        for i in 0..3 {
+            builder.push_element(Some(i));
+        }
+        builder.build();
+    }
 }
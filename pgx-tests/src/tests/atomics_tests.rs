@@ -0,0 +1,188 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+/// Counters that concurrent backends increment in a dynamic shared memory segment
+#[derive(Default)]
+struct StressCounters {
+    atomic: PgAtomicU64,
+    spinlock: PgSpinLock<u64>,
+}
+
+fn with_stress_counters<R, F: FnOnce(&StressCounters) -> R>(handle: i64, f: F) -> R {
+    unsafe {
+        let segment = pg_sys::dsm_attach(handle as pg_sys::dsm_handle);
+        if segment.is_null() {
+            error!(
+                "could not attach to dynamic shared memory segment {}",
+                handle
+            );
+        }
+
+        let result = f(&*(pg_sys::dsm_segment_address(segment) as *const StressCounters));
+        pg_sys::dsm_detach(segment);
+        result
+    }
+}
+
+#[pg_extern]
+fn atomics_stress_create() -> i64 {
+    unsafe {
+        let segment = pg_sys::dsm_create(std::mem::size_of::<StressCounters>(), 0);
+        std::ptr::write(
+            pg_sys::dsm_segment_address(segment) as *mut StressCounters,
+            StressCounters::default(),
+        );
+
+        // keep the segment around after we detach, for the other backends
+        pg_sys::dsm_pin_segment(segment);
+        let handle = pg_sys::dsm_segment_handle(segment);
+        pg_sys::dsm_detach(segment);
+        handle as i64
+    }
+}
+
+#[pg_extern]
+fn atomics_stress_increment(handle: i64, iterations: i32) {
+    with_stress_counters(handle, |counters| {
+        for _ in 0..iterations {
+            counters.atomic.fetch_add(1);
+            *counters.spinlock.lock() += 1;
+        }
+    })
+}
+
+#[pg_extern]
+fn atomics_stress_finish(handle: i64) -> (name!(atomic, i64), name!(spinlock, i64)) {
+    let result = with_stress_counters(handle, |counters| {
+        (
+            counters.atomic.read() as i64,
+            *counters.spinlock.lock() as i64,
+        )
+    });
+    unsafe {
+        pg_sys::dsm_unpin_segment(handle as pg_sys::dsm_handle);
+    }
+    result
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_pg_atomic_u32() {
+        let atomic = PgAtomicU32::new(42);
+        assert_eq!(atomic.read(), 42);
+
+        atomic.write(7);
+        assert_eq!(atomic.read(), 7);
+
+        assert_eq!(atomic.fetch_add(3), 7);
+        assert_eq!(atomic.fetch_sub(5), 10);
+        assert_eq!(atomic.fetch_add(-1), 5);
+        assert_eq!(atomic.exchange(100), 4);
+
+        assert_eq!(atomic.compare_exchange(100, 200), Ok(100));
+        assert_eq!(atomic.compare_exchange(100, 300), Err(200));
+        assert_eq!(atomic.read(), 200);
+
+        // wraps like Postgres does
+        atomic.write(u32::MAX);
+        assert_eq!(atomic.fetch_add(1), u32::MAX);
+        assert_eq!(atomic.read(), 0);
+
+        assert_eq!(PgAtomicU32::default().read(), 0);
+    }
+
+    #[pg_test]
+    fn test_pg_atomic_u64() {
+        let atomic = PgAtomicU64::new(u32::MAX as u64);
+        assert_eq!(atomic.fetch_add(1), u32::MAX as u64);
+        assert_eq!(atomic.read(), u32::MAX as u64 + 1);
+
+        assert_eq!(atomic.fetch_sub(2), u32::MAX as u64 + 1);
+        assert_eq!(atomic.exchange(1), u32::MAX as u64 - 1);
+
+        assert_eq!(atomic.compare_exchange(1, u64::MAX), Ok(1));
+        assert_eq!(atomic.compare_exchange(1, 2), Err(u64::MAX));
+        assert_eq!(atomic.fetch_add(1), u64::MAX);
+        assert_eq!(atomic.read(), 0);
+
+        assert_eq!(PgAtomicU64::default().read(), 0);
+    }
+
+    #[pg_test]
+    fn test_pg_spinlock() {
+        let spinlock = PgSpinLock::new(vec![1, 2]);
+        assert!(!holding_spinlock());
+
+        {
+            let mut guard = spinlock.lock();
+            assert!(holding_spinlock());
+            guard.push(3);
+        }
+        assert!(!holding_spinlock());
+
+        // it can be locked again once the guard is dropped
+        assert_eq!(*spinlock.lock(), vec![1, 2, 3]);
+        assert!(!holding_spinlock());
+
+        let mut spinlock = PgSpinLock::<i32>::default();
+        *spinlock.get_mut() = 42;
+        assert_eq!(*spinlock.lock(), 42);
+    }
+
+    #[cfg(debug_assertions)]
+    #[pg_test(error = "cannot log while holding a spinlock")]
+    fn test_pg_spinlock_forbids_logging() {
+        let spinlock = PgSpinLock::new(0);
+        let _guard = spinlock.lock();
+        info!("this isn't allowed");
+    }
+
+    /// Several backends concurrently increment the same counters in shared memory
+    #[test]
+    fn test_atomics_concurrent_backends() {
+        const BACKENDS: usize = 8;
+        const ITERATIONS: i32 = 25_000;
+
+        let (mut client, _) =
+            pgx_tests::start_and_connect(crate::pg_test::postgresql_conf_options());
+        let handle: i64 = client
+            .query_one("SELECT atomics_stress_create()", &[])
+            .expect("failed to create shared counters")
+            .get(0);
+
+        let backends = (0..BACKENDS)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    let (mut client, _) = pgx_tests::client();
+                    client
+                        .execute(
+                            "SELECT atomics_stress_increment($1, $2)",
+                            &[&handle, &ITERATIONS],
+                        )
+                        .expect("failed to increment shared counters");
+                })
+            })
+            .collect::<Vec<_>>();
+        for backend in backends {
+            backend.join().expect("backend thread panicked");
+        }
+
+        let row = client
+            .query_one(
+                "SELECT atomic, spinlock FROM atomics_stress_finish($1)",
+                &[&handle],
+            )
+            .expect("failed to read shared counters");
+        let expected = BACKENDS as i64 * ITERATIONS as i64;
+        assert_eq!(row.get::<_, i64>("atomic"), expected);
+        assert_eq!(row.get::<_, i64>("spinlock"), expected);
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(PostgresType, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Rectangle {
+    width: f64,
+    height: f64,
+}
+
+#[pg_block_extern]
+impl Rectangle {
+    #[pg_extern(immutable, parallel_safe)]
+    fn area(&self) -> f64 {
+        self.width * self.height
+    }
+
+    #[pg_extern(immutable, parallel_safe)]
+    fn scaled(self, factor: f64) -> Self {
+        Rectangle {
+            width: self.width * factor,
+            height: self.height * factor,
+        }
+    }
+
+    #[pg_extern(immutable, parallel_safe)]
+    fn square(side: f64) -> Self {
+        Rectangle {
+            width: side,
+            height: side,
+        }
+    }
+
+    #[pg_extern(immutable, parallel_safe, name = "is_square_rectangle")]
+    fn is_square(&self) -> bool {
+        self.width == self.height
+    }
+
+    #[allow(dead_code)]
+    fn perimeter(&self) -> f64 {
+        2.0 * (self.width + self.height)
+    }
+}
+
+#[pg_block_extern(prefix = "rect", receiver = "r")]
+impl Rectangle {
+    #[pg_extern(immutable, parallel_safe)]
+    fn contains(&self, rectangle: Rectangle) -> bool {
+        rectangle.width <= self.width && rectangle.height <= self.height
+    }
+}
+
+#[pg_block_extern(bare)]
+impl Rectangle {
+    #[pg_extern(immutable, parallel_safe)]
+    fn unit_rectangle() -> Self {
+        Rectangle::square(1.0)
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use crate::tests::block_extern_tests::Rectangle;
+    use pgx::*;
+
+    #[pg_test]
+    fn test_method_on_column() {
+        Spi::run("CREATE TABLE rectangles (r Rectangle)");
+        Spi::run(
+            r#"INSERT INTO rectangles VALUES ('{"width": 2.0, "height": 3.0}'), ('{"width": 4.0, "height": 0.5}')"#,
+        );
+
+        let total = Spi::get_one::<f64>("SELECT sum(rectangle_area(r)) FROM rectangles");
+        assert_eq!(total, Some(8.0));
+    }
+
+    #[pg_test]
+    fn test_consuming_method() {
+        let scaled = Spi::get_one::<Rectangle>(
+            r#"SELECT rectangle_scaled('{"width": 2.0, "height": 3.0}', 2)"#,
+        );
+        assert_eq!(
+            scaled,
+            Some(Rectangle {
+                width: 4.0,
+                height: 6.0
+            })
+        );
+    }
+
+    #[pg_test]
+    fn test_associated_function() {
+        let area = Spi::get_one::<f64>("SELECT rectangle_area(rectangle_square(3))");
+        assert_eq!(area, Some(9.0));
+    }
+
+    #[pg_test]
+    fn test_explicit_name() {
+        let is_square = Spi::get_one::<bool>("SELECT is_square_rectangle(rectangle_square(2))");
+        assert_eq!(is_square, Some(true));
+
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM pg_proc WHERE proname = 'rectangle_is_square'",
+        );
+        assert_eq!(count, Some(0));
+    }
+
+    #[pg_test]
+    fn test_unmarked_method_isnt_exported() {
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM pg_proc WHERE proname = 'rectangle_perimeter'",
+        );
+        assert_eq!(count, Some(0));
+    }
+
+    #[pg_test]
+    fn test_prefix_and_receiver() {
+        let contains = Spi::get_one::<bool>(
+            "SELECT rect_contains(r => rectangle_square(2), rectangle => unit_rectangle())",
+        );
+        assert_eq!(contains, Some(true));
+
+        let contains =
+            Spi::get_one::<bool>("SELECT rect_contains(unit_rectangle(), rectangle_square(2))");
+        assert_eq!(contains, Some(false));
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::pg_sys::pg_config_defines;
+    use pgx::*;
+
+    #[pg_test]
+    fn test_pg_config_defines_exist() {
+        // every version's bindings define every constant, whether or not the feature was built
+        let defines = [
+            pg_config_defines::ENABLE_NLS,
+            pg_config_defines::ENABLE_THREAD_SAFETY,
+            pg_config_defines::HAVE_LIBZ,
+            pg_config_defines::USE_ASSERT_CHECKING,
+            pg_config_defines::USE_BONJOUR,
+            pg_config_defines::USE_BSD_AUTH,
+            pg_config_defines::USE_ICU,
+            pg_config_defines::USE_LDAP,
+            pg_config_defines::USE_LIBXML,
+            pg_config_defines::USE_LIBXSLT,
+            pg_config_defines::USE_LLVM,
+            pg_config_defines::USE_OPENSSL,
+            pg_config_defines::USE_PAM,
+            pg_config_defines::USE_SYSTEMD,
+        ];
+        assert_eq!(defines.len(), 14);
+    }
+
+    #[pg_test]
+    fn test_build_info() {
+        let info = build_info();
+
+        let version = Spi::get_one::<&str>("SELECT current_setting('server_version')");
+        assert_eq!(Some(info.version.as_str()), version);
+
+        let version_num =
+            Spi::get_one::<&str>("SELECT current_setting('server_version_num')").unwrap();
+        assert_eq!(info.version_num, version_num.parse::<i32>().unwrap());
+        assert_eq!(
+            info.version_num / 10000,
+            pg_sys::PG_VERSION_NUM as i32 / 10000
+        );
+
+        assert_eq!(info.icu, has_icu());
+        assert_eq!(info.asserts_enabled, asserts_enabled());
+        assert_eq!(info.jit_available, jit_available());
+    }
+
+    #[pg_test]
+    fn test_asserts_enabled() {
+        let debug_assertions =
+            Spi::get_one::<bool>("SELECT current_setting('debug_assertions')::bool");
+        assert_eq!(Some(asserts_enabled()), debug_assertions);
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test]
+    fn test_jit_available() {
+        let available = Spi::get_one::<bool>("SELECT pg_jit_available()");
+        assert_eq!(Some(jit_available()), available);
+
+        // a server built without LLVM can't have a loadable jit provider
+        if !pg_config_defines::USE_LLVM {
+            assert!(!jit_available());
+        }
+    }
+
+    #[cfg(feature = "pg10")]
+    #[pg_test]
+    fn test_jit_available() {
+        assert!(!jit_available());
+        assert!(!build_info().llvm);
+    }
+}
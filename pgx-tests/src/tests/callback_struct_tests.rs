@@ -0,0 +1,68 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    // each of these only compiles if its signature matches the callback's exactly
+
+    #[pg_guard]
+    unsafe extern "C" fn validate(opclassoid: pg_sys::Oid) -> bool {
+        opclassoid == 42
+    }
+
+    #[pg_guard]
+    unsafe extern "C" fn get_rel_size(
+        _root: *mut pg_sys::PlannerInfo,
+        baserel: *mut pg_sys::RelOptInfo,
+        _foreigntableid: pg_sys::Oid,
+    ) {
+        (*baserel).rows = 42.0;
+    }
+
+    #[pg_guard]
+    unsafe extern "C" fn iterate(
+        _node: *mut pg_sys::ForeignScanState,
+    ) -> *mut pg_sys::TupleTableSlot {
+        std::ptr::null_mut()
+    }
+
+    #[pg_test]
+    fn test_index_am_routine_setters() {
+        let mut routine = pg_sys::IndexAmRoutine::default();
+        assert!(routine.amvalidate.is_none());
+        assert!(routine.ambuild.is_none());
+
+        routine.set_amvalidate(validate);
+        let amvalidate = routine.amvalidate.expect("amvalidate wasn't set");
+        assert!(unsafe { amvalidate(42) });
+        assert!(!unsafe { amvalidate(43) });
+        assert!(routine.ambuild.is_none());
+    }
+
+    #[pg_test]
+    fn test_fdw_routine_setters() {
+        let mut routine = pg_sys::FdwRoutine::default();
+        routine
+            .set_GetForeignRelSize(get_rel_size)
+            .set_IterateForeignScan(iterate);
+
+        assert!(routine.GetForeignRelSize.is_some());
+        assert!(routine.IterateForeignScan.is_some());
+        assert!(routine.GetForeignPaths.is_none());
+
+        let baserel = PgBox::<pg_sys::RelOptInfo>::alloc0();
+        unsafe {
+            routine.GetForeignRelSize.unwrap()(
+                std::ptr::null_mut(),
+                baserel.as_ptr(),
+                pg_sys::InvalidOid,
+            );
+        }
+        assert_eq!(baserel.rows as i64, 42);
+    }
+}
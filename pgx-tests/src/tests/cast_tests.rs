@@ -0,0 +1,274 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[pg_extern]
+fn checked_text_to_integer(value: &str) -> Result<i32, cast::CastError> {
+    cast::text_to_i32(value)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::ffi::CStr;
+
+    /// What the SQL expression `expr` evaluates to, as text, or the SQLSTATE and message of the
+    /// ERROR it raises
+    fn sql(expr: &str) -> Result<String, (i32, String)> {
+        let query = format!("SELECT ({})::text", expr);
+        let result = pg_try_subtransaction(|| Spi::get_one::<String>(&query));
+        if result.sqlerrcode().is_none() {
+            return Ok(result.unwrap().expect("cast returned NULL"));
+        }
+
+        unsafe {
+            let edata = pg_sys::CopyErrorData();
+            let error = (
+                (*edata).sqlerrcode,
+                CStr::from_ptr((*edata).message)
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+            pg_sys::FreeErrorData(edata);
+            result.unwrap_or(None);
+            Err(error)
+        }
+    }
+
+    /// `result` in the same form as `sql()`'s
+    fn rust<T: ToString>(result: Result<T, cast::CastError>) -> Result<String, (i32, String)> {
+        result
+            .map(|value| value.to_string())
+            .map_err(|e| (e.sqlerrcode() as i32, e.message().to_string()))
+    }
+
+    fn float8_literal(value: f64) -> String {
+        if value.is_nan() {
+            "'NaN'::float8".to_string()
+        } else if value.is_infinite() {
+            format!("'{}Infinity'::float8", if value < 0.0 { "-" } else { "" })
+        } else {
+            format!("'{}'::float8", value)
+        }
+    }
+
+    const FLOAT_EDGES: &[f64] = &[
+        0.0,
+        -0.0,
+        0.5,
+        -0.5,
+        1.5,
+        2.5,
+        -2.5,
+        -3.5,
+        0.49999999999999994,
+        4503599627370495.5,
+        -4503599627370495.5,
+        f64::NAN,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::MAX,
+        f64::MIN,
+        f64::MIN_POSITIVE,
+        f64::EPSILON,
+    ];
+
+    #[pg_test]
+    fn test_f64_to_i32() {
+        let max = i32::MAX as f64;
+        let min = i32::MIN as f64;
+        let edges = [
+            max,
+            max - 0.5,
+            max + 0.5,
+            max + 1.0,
+            min,
+            min + 0.5,
+            min - 0.5,
+            min - 1.0,
+            min - 1.5,
+        ];
+
+        for value in FLOAT_EDGES.iter().chain(edges.iter()) {
+            assert_eq!(
+                rust(cast::f64_to_i32(*value)),
+                sql(&format!("{}::integer", float8_literal(*value))),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_f64_to_i64() {
+        let max = i64::MAX as f64;
+        let min = i64::MIN as f64;
+        let edges = [
+            max,
+            -max,
+            min,
+            min * 2.0,
+            9223372036854774784.0,
+            -9223372036854774784.0,
+            i32::MAX as f64 + 0.5,
+            i32::MIN as f64 - 0.5,
+        ];
+
+        for value in FLOAT_EDGES.iter().chain(edges.iter()) {
+            assert_eq!(
+                rust(cast::f64_to_i64(*value)),
+                sql(&format!("{}::bigint", float8_literal(*value))),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_i64_to_i32() {
+        let edges = [
+            0,
+            -1,
+            i32::MAX as i64,
+            i32::MAX as i64 + 1,
+            i32::MIN as i64,
+            i32::MIN as i64 - 1,
+            i64::MAX,
+            i64::MIN,
+        ];
+
+        for value in edges.iter() {
+            assert_eq!(
+                rust(cast::i64_to_i32(*value)),
+                sql(&format!("'{}'::bigint::integer", value)),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_numeric_to_i64() {
+        let edges = [
+            "0",
+            "-0",
+            "0.5",
+            "-0.5",
+            "0.49",
+            "1.5",
+            "2.5",
+            "-2.5",
+            "0.05",
+            "00012.50",
+            "  42  ",
+            "+7",
+            "5.",
+            ".5",
+            "9223372036854775807",
+            "9223372036854775807.4",
+            "9223372036854775807.5",
+            "9223372036854775808",
+            "-9223372036854775808",
+            "-9223372036854775808.4",
+            "-9223372036854775808.5",
+            "-9223372036854775809",
+            "1e18",
+            "9.3e18",
+            "9.223372036854775807E18",
+            "15e-1",
+            "5e-1",
+            "4e-1",
+            "1e-1000",
+            "1e1000",
+            "1e 3",
+            "NaN",
+            "nan",
+            "",
+            ".",
+            "-",
+            "e5",
+            "1e",
+            "1.2.3",
+            "1 2",
+            "abc",
+            "Infinity",
+        ];
+
+        for value in edges.iter() {
+            assert_eq!(
+                rust(cast::numeric_to_i64(&Numeric(value.to_string()))),
+                sql(&format!("'{}'::numeric::bigint", value)),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_text_to_i32() {
+        let edges = [
+            "0",
+            "42",
+            "-42",
+            "+42",
+            "007",
+            "  42  ",
+            "\t42\n",
+            "2147483647",
+            "2147483648",
+            "-2147483648",
+            "-2147483649",
+            "99999999999999999999",
+            "99999999999x",
+            "",
+            " ",
+            "-",
+            "+",
+            "--1",
+            "4 2",
+            "42x",
+            "0x1A",
+            "1.0",
+            "1e3",
+        ];
+
+        for value in edges.iter() {
+            let expected = sql(&format!("'{}'::text::integer", value));
+            assert_eq!(rust(cast::text_to_i32(value)), expected, "{:?}", value);
+
+            // and raised as an ERROR by a #[pg_extern] function, through ErrorReport
+            assert_eq!(
+                sql(&format!("checked_text_to_integer('{}')", value)),
+                expected,
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_from_datum_narrows_like_sql() {
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT 2147483647::bigint"),
+            Some(2147483647)
+        );
+        assert_eq!(Spi::get_one::<i32>("SELECT 2.5::float8"), Some(2));
+        assert_eq!(Spi::get_one::<i32>("SELECT 3.5::float8"), Some(4));
+        assert_eq!(Spi::get_one::<i64>("SELECT 12.5::numeric"), Some(13));
+        assert_eq!(Spi::get_one::<i64>("SELECT (-12.5)::float8"), Some(-12));
+    }
+
+    #[pg_test(error = "integer out of range")]
+    fn test_from_datum_bigint_out_of_range() {
+        Spi::get_one::<i32>("SELECT 2147483648::bigint");
+    }
+
+    #[pg_test(error = "bigint out of range")]
+    fn test_from_datum_numeric_out_of_range() {
+        Spi::get_one::<i64>("SELECT 1e19::numeric");
+    }
+}
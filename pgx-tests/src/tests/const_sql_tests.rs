@@ -0,0 +1,87 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+/// The CRC-32 (IEEE 802.3) lookup table entry for the byte `n`
+#[pg_extern(immutable, parallel_safe, const_sql)]
+fn crc32_table_entry(n: i64) -> i64 {
+    let mut crc = n as u32;
+    for _ in 0..8 {
+        if crc & 1 == 1 {
+            crc = (crc >> 1) ^ 0xEDB8_8320;
+        } else {
+            crc >>= 1;
+        }
+    }
+    crc as i64
+}
+
+/// `VALUES` rows for every entry of the CRC-32 lookup table, evaluated by `cargo pgx schema`
+#[pg_extern(immutable, parallel_safe, const_sql)]
+fn crc32_table_values() -> String {
+    let mut values = String::new();
+    for n in 0..256 {
+        if n > 0 {
+            values.push_str(",\n");
+        }
+        let crc = crc32_table_entry(n);
+        values.push_str(&format!("({}, {}, '{:#010x}')", n, crc, crc));
+    }
+    values
+}
+
+extension_sql! { r#"
+CREATE TABLE crc32_table (
+    n    int PRIMARY KEY,
+    crc  bigint NOT NULL,
+    hex  text NOT NULL
+);
+INSERT INTO crc32_table (n, crc, hex) VALUES
+{{ crc32_table_values() }};
+
+CREATE VIEW crc32_known_entry AS SELECT {{ crc32_table_entry(1) }}::bigint AS crc;
+"#, const_sql}
+
+extension_sql! { r#"
+CREATE VIEW const_sql_not_expanded AS SELECT '{{1,2},{3,4}}'::int[] AS matrix;
+"#}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_const_sql_table_populated() {
+        let cnt = Spi::get_one::<i64>("SELECT count(*) FROM crc32_table");
+        assert_eq!(cnt, Some(256));
+
+        let crc = Spi::get_one::<i64>("SELECT crc FROM crc32_table WHERE n = 1");
+        assert_eq!(crc, Some(0x7707_3096));
+        let hex = Spi::get_one::<&str>("SELECT hex FROM crc32_table WHERE n = 255");
+        assert_eq!(hex, Some("0x2d02ef8d"));
+    }
+
+    #[pg_test]
+    fn test_const_sql_matches_runtime() {
+        // the values spliced in at schema generation time match calling the function for real
+        let mismatches = Spi::get_one::<i64>(
+            "SELECT count(*) FROM crc32_table
+              WHERE crc <> crc32_table_entry(n)
+                 OR hex <> '0x' || lpad(to_hex(crc32_table_entry(n)), 8, '0')",
+        );
+        assert_eq!(mismatches, Some(0));
+
+        let crc = Spi::get_one::<i64>("SELECT crc FROM crc32_known_entry");
+        assert_eq!(crc, Some(super::crc32_table_entry(1)));
+    }
+
+    #[pg_test]
+    fn test_const_sql_only_expands_opted_in_blocks() {
+        let matrix = Spi::get_one::<String>("SELECT matrix::text FROM const_sql_not_expanded");
+        assert_eq!(matrix, Some("{{1,2},{3,4}}".to_string()));
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+extension_control!(
+    comment = "tests:  Created by pgx",
+    default_version = "1.0",
+    requires("plpgsql"),
+    relocatable = false,
+    superuser = false
+);
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_control_file_is_generated() {
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT array_to_string(requires, ',') FROM pg_available_extension_versions
+                  WHERE name = 'pgx_tests' AND version = '1.0'"
+            ),
+            Some("plpgsql".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_ensure_required_extensions() {
+        super::ensure_required_extensions();
+    }
+
+    #[pg_test(error = "required extension \"no_such_extension\" is not installed")]
+    fn test_ensure_missing_extension() {
+        pgx::ensure_extensions_installed(&["plpgsql", "no_such_extension"]);
+    }
+
+    #[pg_test(
+        error = "required extensions \"no_such_extension\", \"nor_this_one\" are not installed"
+    )]
+    fn test_ensure_missing_extensions() {
+        pgx::ensure_extensions_installed(&["no_such_extension", "nor_this_one"]);
+    }
+}
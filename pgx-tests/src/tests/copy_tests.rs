@@ -0,0 +1,242 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_extern]
+    fn copy_in_bad_fixture() {
+        Spi::copy_in(
+            "copy_errors",
+            &["id", "value"],
+            vec![
+                vec![Some("1".to_string()), Some("one".to_string())],
+                vec![Some("2".to_string()), Some("two".to_string())],
+                vec![Some("three".to_string()), Some("three".to_string())],
+            ]
+            .into_iter(),
+        );
+    }
+
+    #[pg_test]
+    fn test_copy_in() {
+        Spi::run("CREATE TABLE copy_in (id int, value text)");
+        let cnt = Spi::copy_in(
+            "copy_in",
+            &["id", "value"],
+            (1..=10).map(|i| vec![Some(i.to_string()), Some(format!("value {}", i))]),
+        );
+        assert_eq!(cnt, 10);
+
+        let sum = Spi::get_one::<i64>("SELECT sum(id) FROM copy_in");
+        assert_eq!(sum, Some(55));
+        let value = Spi::get_one::<&str>("SELECT value FROM copy_in WHERE id = 7");
+        assert_eq!(value, Some("value 7"));
+    }
+
+    #[pg_test]
+    fn test_copy_in_all_columns() {
+        Spi::run("CREATE TABLE copy_in_all (id int, value text)");
+        Spi::copy_in(
+            "copy_in_all",
+            &[],
+            vec![vec![Some("1".to_string()), Some("one".to_string())]].into_iter(),
+        );
+
+        let value = Spi::get_one::<&str>("SELECT value FROM copy_in_all WHERE id = 1");
+        assert_eq!(value, Some("one"));
+    }
+
+    #[pg_test]
+    fn test_copy_in_special_characters() {
+        let values = vec![
+            "tab\there",
+            "newline\nhere",
+            "carriage\rreturn",
+            "back\\slash",
+            "\\N",
+            "\\.",
+            "",
+        ];
+
+        Spi::run("CREATE TABLE copy_special (id int, value text)");
+        Spi::copy_in(
+            "copy_special",
+            &["id", "value"],
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| vec![Some(i.to_string()), Some(value.to_string())])
+                .chain(std::iter::once(vec![Some(values.len().to_string()), None])),
+        );
+
+        for (i, value) in values.iter().enumerate() {
+            let loaded = Spi::get_one_with_args::<&str>(
+                "SELECT value FROM copy_special WHERE id = $1",
+                vec![(PgBuiltInOids::INT4OID.oid(), (i as i32).into_datum())],
+            );
+            assert_eq!(loaded, Some(*value));
+        }
+
+        let nulls = Spi::get_one::<i64>("SELECT count(*) FROM copy_special WHERE value IS NULL");
+        assert_eq!(nulls, Some(1));
+    }
+
+    #[pg_test]
+    fn test_copy_in_error_has_line_number() {
+        Spi::run("CREATE TABLE copy_errors (id int, value text)");
+        Spi::run(
+            "DO $$
+             DECLARE
+                context text;
+             BEGIN
+                PERFORM tests.copy_in_bad_fixture();
+                RAISE EXCEPTION 'copy_in() did not raise an ERROR';
+             EXCEPTION WHEN invalid_text_representation THEN
+                GET STACKED DIAGNOSTICS context = PG_EXCEPTION_CONTEXT;
+                IF context NOT LIKE '%COPY copy_errors, line 3%' THEN
+                    RAISE EXCEPTION 'unexpected error context: %', context;
+                END IF;
+             END $$;",
+        );
+    }
+
+    #[pg_test(error = "relation \"copy_does_not_exist\" does not exist")]
+    fn test_copy_in_no_such_table() {
+        Spi::copy_in("copy_does_not_exist", &[], std::iter::empty());
+    }
+
+    #[pg_test]
+    fn test_copy_in_binary() {
+        Spi::run("CREATE TABLE copy_binary (id int, value text, flag bool)");
+        let cnt = Spi::copy_in_binary(
+            "copy_binary",
+            &["id", "value", "flag"],
+            (1..=10).map(|i| {
+                vec![
+                    (PgBuiltInOids::INT4OID.oid(), (i as i32).into_datum()),
+                    (
+                        PgBuiltInOids::TEXTOID.oid(),
+                        format!("tab\t{}", i).into_datum(),
+                    ),
+                    (
+                        PgBuiltInOids::BOOLOID.oid(),
+                        if i % 2 == 0 { true.into_datum() } else { None },
+                    ),
+                ]
+            }),
+        );
+        assert_eq!(cnt, 10);
+
+        let sum = Spi::get_one::<i64>("SELECT sum(id) FROM copy_binary");
+        assert_eq!(sum, Some(55));
+        let value = Spi::get_one::<&str>("SELECT value FROM copy_binary WHERE id = 3");
+        assert_eq!(value, Some("tab\t3"));
+        let nulls = Spi::get_one::<i64>("SELECT count(*) FROM copy_binary WHERE flag IS NULL");
+        assert_eq!(nulls, Some(5));
+    }
+
+    #[pg_test]
+    fn test_copy_in_many_rows() {
+        // far more than COPY reads from its data source at once
+        const ROWS: i32 = 100_000;
+
+        Spi::run("CREATE TABLE copy_many (id int, value text)");
+        let cnt = Spi::copy_in(
+            "copy_many",
+            &["id", "value"],
+            (0..ROWS).map(|i| vec![Some(i.to_string()), Some(format!("row\t{}\\\n", i))]),
+        );
+        assert_eq!(cnt, ROWS as u64);
+
+        let mismatches = Spi::get_one::<i64>(&format!(
+            "SELECT count(*) FROM copy_many c
+               FULL OUTER JOIN (SELECT i AS id, E'row\\t' || i || E'\\\\\\n' AS value
+                                  FROM generate_series(0, {}) i) expected USING (id)
+              WHERE c.value IS DISTINCT FROM expected.value",
+            ROWS - 1
+        ));
+        assert_eq!(mismatches, Some(0));
+    }
+
+    #[pg_test]
+    fn test_copy_in_nested_error() {
+        // more than COPY reads from its data source at once, so it's still reading after the
+        // trigger's COPY raised an ERROR
+        const ROWS: i32 = 20_000;
+
+        Spi::run("CREATE TABLE copy_errors (id int, value text)");
+        Spi::run("CREATE TABLE copy_nested (id int, value text)");
+        Spi::run(
+            "CREATE FUNCTION copy_nested_trigger() RETURNS trigger LANGUAGE plpgsql AS $$
+             BEGIN
+                IF NEW.id = 1 THEN
+                    BEGIN
+                        PERFORM tests.copy_in_bad_fixture();
+                    EXCEPTION WHEN invalid_text_representation THEN
+                        NULL;
+                    END;
+                END IF;
+                RETURN NEW;
+             END $$",
+        );
+        Spi::run(
+            "CREATE TRIGGER copy_nested BEFORE INSERT ON copy_nested
+                FOR EACH ROW EXECUTE PROCEDURE copy_nested_trigger()",
+        );
+
+        let cnt = Spi::copy_in(
+            "copy_nested",
+            &["id", "value"],
+            (0..ROWS).map(|i| vec![Some(i.to_string()), Some(format!("value {}", i))]),
+        );
+        assert_eq!(cnt, ROWS as u64);
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM copy_nested"),
+            Some(ROWS as i64)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM copy_errors"),
+            Some(0)
+        );
+    }
+
+    fn copy_in_as_outsider(table: &str) {
+        Spi::run(&format!("CREATE TABLE {} (id int)", table));
+        Spi::run("CREATE ROLE copy_outsider NOLOGIN");
+        Spi::run("GRANT USAGE ON SCHEMA tests TO copy_outsider");
+        Spi::run("SET ROLE copy_outsider");
+        Spi::copy_in(table, &[], vec![vec![Some("1".to_string())]].into_iter());
+    }
+
+    #[cfg(feature = "pg10")]
+    #[pg_test(error = "permission denied for relation copy_private")]
+    fn test_copy_in_without_insert_privilege() {
+        copy_in_as_outsider("copy_private");
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test(error = "permission denied for table copy_private")]
+    fn test_copy_in_without_insert_privilege() {
+        copy_in_as_outsider("copy_private");
+    }
+
+    #[pg_test(error = "COPY FROM not supported with row-level security")]
+    fn test_copy_in_row_level_security() {
+        Spi::run("CREATE TABLE copy_rls (id int)");
+        Spi::run("ALTER TABLE copy_rls ENABLE ROW LEVEL SECURITY");
+        Spi::run("CREATE ROLE copy_rls_user NOLOGIN");
+        Spi::run("GRANT USAGE ON SCHEMA tests TO copy_rls_user");
+        Spi::run("GRANT INSERT ON copy_rls TO copy_rls_user");
+        Spi::run("SET ROLE copy_rls_user");
+        Spi::copy_in(
+            "copy_rls",
+            &[],
+            vec![vec![Some("1".to_string())]].into_iter(),
+        );
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, PostgresType)]
+#[copytextformat]
+pub struct CopyTextPoint {
+    horizontal_position: i64,
+    vertical_position: i64,
+    descriptive_label: String,
+}
+
+impl CopyTextFormat for CopyTextPoint {
+    fn to_copy_text(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&format!(
+            "{},{},{}",
+            self.horizontal_position, self.vertical_position, self.descriptive_label
+        ))
+    }
+
+    fn from_copy_text(input: &str) -> Self {
+        let mut iter = input.splitn(3, ',');
+        let (h, v, label) = (iter.next(), iter.next(), iter.next());
+
+        CopyTextPoint {
+            horizontal_position: i64::from_str(h.unwrap()).expect("h is not a valid i64"),
+            vertical_position: i64::from_str(v.unwrap()).expect("v is not a valid i64"),
+            descriptive_label: label.expect("missing label").to_string(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PostgresType)]
+#[pgvarlena_inoutfuncs]
+#[copytextformat]
+pub struct CopyTextVarlena {
+    a: i32,
+    b: i32,
+}
+
+impl PgVarlenaInOutFuncs for CopyTextVarlena {
+    fn input(input: &std::ffi::CStr) -> PgVarlena<Self> {
+        let mut iter = input.to_str().unwrap().split(',');
+        let mut result = PgVarlena::<CopyTextVarlena>::new();
+        result.a = i32::from_str(iter.next().unwrap()).expect("a is not a valid i32");
+        result.b = i32::from_str(iter.next().unwrap()).expect("b is not a valid i32");
+        result
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&format!("{},{}", self.a, self.b))
+    }
+}
+
+impl CopyTextFormat for CopyTextVarlena {
+    fn to_copy_text(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&format!("{}/{}", self.a, self.b))
+    }
+
+    fn from_copy_text(input: &str) -> Self {
+        let mut iter = input.split('/');
+        CopyTextVarlena {
+            a: i32::from_str(iter.next().unwrap()).expect("a is not a valid i32"),
+            b: i32::from_str(iter.next().unwrap()).expect("b is not a valid i32"),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::CopyTextPoint;
+    use pgx::*;
+
+    fn file_size(path: &std::path::Path) -> u64 {
+        std::fs::metadata(path)
+            .expect("failed to stat COPY output")
+            .len()
+    }
+
+    #[pg_test]
+    fn test_copy_text_functions() {
+        let compact = Spi::get_one::<&str>(
+            r#"SELECT copytextpoint_to_copy_text('{"horizontal_position":1,"vertical_position":-2,"descriptive_label":"a,b"}'::copytextpoint)"#,
+        );
+        assert_eq!(compact, Some("1,-2,a,b"));
+
+        let json = Spi::get_one::<&str>("SELECT copytextpoint_from_copy_text('3,4,label')::text");
+        assert_eq!(
+            json,
+            Some(r#"{"horizontal_position":3,"vertical_position":4,"descriptive_label":"label"}"#)
+        );
+    }
+
+    #[pg_test]
+    fn test_copy_text_functions_pgvarlena() {
+        let compact =
+            Spi::get_one::<&str>("SELECT copytextvarlena_to_copy_text('1,2'::copytextvarlena)");
+        assert_eq!(compact, Some("1/2"));
+
+        let text = Spi::get_one::<&str>("SELECT copytextvarlena_from_copy_text('3/4')::text");
+        assert_eq!(text, Some("3,4"));
+    }
+
+    #[pg_test]
+    fn test_copy_helper_view_roundtrip() {
+        Spi::run("CREATE TABLE copy_points (id int, point copytextpoint, note text)");
+        Spi::run(
+            r#"INSERT INTO copy_points
+               SELECT i,
+                      format('{"horizontal_position":%s,"vertical_position":%s,"descriptive_label":"point %s"}', i, -i, i)::copytextpoint,
+                      CASE WHEN i % 10 = 0 THEN NULL ELSE 'note ' || i END
+                 FROM generate_series(1, 10000) i"#,
+        );
+        Spi::run(
+            "CREATE TABLE copy_points_before AS SELECT id, point::text, note FROM copy_points",
+        );
+
+        let view = copy_helper_view!(CopyTextPoint, "copy_points");
+        assert_eq!(view, "public.copy_points_copy");
+
+        let dir = std::env::temp_dir();
+        let raw = dir.join(format!("pgx_copy_text_raw_{}", std::process::id()));
+        let compact = dir.join(format!("pgx_copy_text_compact_{}", std::process::id()));
+        let raw_literal = quote::quote_literal(raw.to_str().unwrap());
+        let compact_literal = quote::quote_literal(compact.to_str().unwrap());
+
+        Spi::run(&format!("COPY copy_points TO {}", raw_literal));
+        Spi::run(&format!(
+            "COPY (SELECT * FROM {}) TO {}",
+            view, compact_literal
+        ));
+
+        // the JSON field names dominate the regular text representation
+        let (raw_size, compact_size) = (file_size(&raw), file_size(&compact));
+        assert!(
+            compact_size * 2 < raw_size,
+            "compact: {}, raw: {}",
+            compact_size,
+            raw_size
+        );
+
+        // and loading back through the view restores the original values
+        Spi::run("TRUNCATE copy_points");
+        Spi::run(&format!("COPY {} FROM {}", view, compact_literal));
+        std::fs::remove_file(&raw).ok();
+        std::fs::remove_file(&compact).ok();
+
+        let matching = Spi::get_one::<i64>(
+            "SELECT count(*)
+               FROM copy_points a
+               JOIN copy_points_before b USING (id)
+              WHERE a.point::text = b.point
+                AND a.note IS NOT DISTINCT FROM b.note",
+        );
+        assert_eq!(matching, Some(10000));
+    }
+
+    #[pg_test]
+    fn test_copy_helper_view_named() {
+        Spi::run("CREATE TABLE copy_points_named (point copytextpoint)");
+        let view = copy_helper_view!(CopyTextPoint, "copy_points_named", "Compact Points");
+        assert_eq!(view, "public.\"Compact Points\"");
+
+        Spi::run(r#"INSERT INTO "Compact Points" VALUES ('5,6,seven')"#);
+        let h = Spi::get_one::<&str>(
+            "SELECT point::text::json->>'descriptive_label' FROM copy_points_named",
+        );
+        assert_eq!(h, Some("seven"));
+    }
+
+    #[pg_test(error = "relation \"copy_no_points\" has no columns of type copytextpoint")]
+    fn test_copy_helper_view_no_columns() {
+        Spi::run("CREATE TABLE copy_no_points (id int)");
+        copy_helper_view!(CopyTextPoint, "copy_no_points");
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, PostgresType)]
+pub struct CorruptibleType {
+    value: i32,
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::CorruptibleType;
+    use pgx::*;
+    use std::cell::RefCell;
+    use std::ffi::CStr;
+
+    thread_local! {
+        static SEEN: RefCell<Vec<CorruptionInfo>> = RefCell::new(Vec::new());
+    }
+
+    /// A palloc'd copy of `bytes`, as a varlena `Datum`
+    fn malformed(bytes: &[u8]) -> pg_sys::Datum {
+        unsafe {
+            let ptr = pg_sys::palloc(bytes.len()) as *mut u8;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+            ptr as pg_sys::Datum
+        }
+    }
+
+    /// The SQLSTATE and message of the ERROR that `f` raises
+    fn caught_error<F: FnOnce() + std::panic::UnwindSafe>(f: F) -> (i32, String) {
+        let result = pg_try(f);
+        assert!(result.sqlerrcode().is_some(), "no ERROR was raised");
+
+        unsafe {
+            let edata = pg_sys::CopyErrorData();
+            let error = (
+                (*edata).sqlerrcode,
+                CStr::from_ptr((*edata).message)
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+            pg_sys::FreeErrorData(edata);
+            result.unwrap_or(());
+            error
+        }
+    }
+
+    #[pg_test]
+    fn test_valid_varlenas() {
+        let text = "hello, world".into_datum().unwrap();
+        assert_eq!(
+            unsafe { <&str>::from_datum(text, false, pg_sys::TEXTOID) },
+            Some("hello, world")
+        );
+
+        // a short, 1-byte header
+        let short = malformed(&[(6 << 1) | 0x01, b'h', b'e', b'l', b'l', b'o']);
+        assert_eq!(
+            unsafe { <&str>::from_datum(short, false, pg_sys::TEXTOID) },
+            Some("hello")
+        );
+
+        let custom = CorruptibleType { value: 42 }.into_datum().unwrap();
+        let custom = unsafe { <CorruptibleType as FromDatum>::from_datum(custom, false, 0) };
+        assert_eq!(custom.unwrap().value, 42);
+    }
+
+    #[pg_test(
+        error = "corrupted text value: its header says it's 2 bytes, which is smaller than the header"
+    )]
+    fn test_text_too_small() {
+        let datum = malformed(&[2 << 2, 0, 0, 0]);
+        unsafe { <&str>::from_datum(datum, false, pg_sys::TEXTOID) };
+    }
+
+    #[pg_test(error = "corrupted text value: it has an unrecognized TOAST tag of 42")]
+    fn test_text_unrecognized_toast_tag() {
+        let datum = malformed(&[0x01, 42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        unsafe { <&str>::from_datum(datum, false, pg_sys::TEXTOID) };
+    }
+
+    #[pg_test(error = "corrupted bytea value: it's compressed with an unrecognized method of 3")]
+    fn test_bytea_unrecognized_compression() {
+        let header = (12 << 2) | 0x02;
+        let datum = malformed(&[header, 0, 0, 0, 0x10, 0, 0, 0xc0, 1, 2, 3, 4]);
+        unsafe { <&[u8]>::from_datum(datum, false, pg_sys::BYTEAOID) };
+    }
+
+    #[pg_test]
+    fn test_bytea_larger_than_its_chunk() {
+        // says it's 1000 bytes, in a 16-byte allocation
+        let mut bytes = [0u8; 16];
+        bytes[..4].copy_from_slice(&(1000u32 << 2).to_le_bytes());
+        let datum = malformed(&bytes);
+
+        // the header alone looks fine
+        assert_eq!(
+            unsafe { varlena_corruption(datum as *const pg_sys::varlena) },
+            None
+        );
+        let reason = unsafe { varlena_chunk_corruption(datum as *const pg_sys::varlena) };
+        assert!(reason
+            .unwrap()
+            .starts_with("its header says it's 1000 bytes, but it was allocated with"));
+
+        if !cfg!(debug_assertions) {
+            // decoding only checks the chunk in debug builds
+            return;
+        }
+
+        let (sqlerrcode, message) = caught_error(|| unsafe {
+            <&[u8]>::from_datum_in_memory_context(
+                PgMemoryContexts::CurrentMemoryContext,
+                datum,
+                false,
+                pg_sys::BYTEAOID,
+            );
+        });
+        assert_eq!(sqlerrcode, PgSqlErrorCode::ERRCODE_DATA_CORRUPTED as i32);
+        assert!(message.starts_with("corrupted bytea value: its header says it's 1000 bytes"));
+    }
+
+    #[pg_test]
+    fn test_bad_toast_pointer() {
+        let mut bytes = [0u8; 18];
+        bytes[0] = 0x01;
+        bytes[1] = pg_sys::vartag_external_VARTAG_ONDISK as u8;
+        bytes[2..6].copy_from_slice(&(-5i32).to_ne_bytes());
+        let datum = malformed(&bytes);
+
+        let reason = unsafe { varlena_corruption(datum as *const pg_sys::varlena) };
+        assert_eq!(
+            reason.as_deref(),
+            Some("its TOAST pointer has a size of -5 bytes")
+        );
+    }
+
+    #[pg_test(
+        error = "corrupted corruptibletype value: its header says it's 3 bytes, which is smaller than the header"
+    )]
+    fn test_custom_type_too_small() {
+        let datum = malformed(&[3 << 2, 0, 0, 0]);
+        unsafe { <CorruptibleType as FromDatum>::from_datum(datum, false, 0) };
+    }
+
+    #[pg_test]
+    fn test_custom_type_invalid_cbor() {
+        let datum = malformed(&[8 << 2, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        let (sqlerrcode, message) = caught_error(|| unsafe {
+            <CorruptibleType as FromDatum>::from_datum(datum, false, 0);
+        });
+        assert_eq!(sqlerrcode, PgSqlErrorCode::ERRCODE_DATA_CORRUPTED as i32);
+        assert!(message.starts_with("corrupted corruptibletype value: it isn't valid CBOR"));
+    }
+
+    #[pg_test]
+    fn test_corruption_hook() {
+        corruption_hook(|info| SEEN.with(|seen| seen.borrow_mut().push(info.clone())));
+        corruption_hook(|_| panic!("a hook that panics doesn't stop the others"));
+        corruption_hook(|info| SEEN.with(|seen| seen.borrow_mut().push(info.clone())));
+        SEEN.with(|seen| seen.borrow_mut().clear());
+
+        let datum = malformed(&[2 << 2, 0, 0, 0]);
+        let (sqlerrcode, _) = caught_error(|| {
+            let _table = error_context::push(|| "while scanning tests.documents".to_string());
+            let _tid = error_context::push(|| "at tid (0,5)".to_string());
+            unsafe { <&str>::from_datum(datum, false, pg_sys::TEXTOID) };
+        });
+        assert_eq!(sqlerrcode, PgSqlErrorCode::ERRCODE_DATA_CORRUPTED as i32);
+
+        let seen = SEEN.with(|seen| seen.replace(Vec::new()));
+        assert_eq!(seen.len(), 2);
+        for info in seen {
+            assert_eq!(info.type_name, "text");
+            assert_eq!(
+                info.reason,
+                "its header says it's 2 bytes, which is smaller than the header"
+            );
+            assert_eq!(
+                info.context,
+                vec![
+                    "at tid (0,5)".to_string(),
+                    "while scanning tests.documents".to_string()
+                ]
+            );
+        }
+    }
+}
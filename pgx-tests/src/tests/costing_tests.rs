@@ -0,0 +1,268 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::costing::*;
+use pgx::*;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// How many times `costing_btree_costestimate()` has been called
+static COST_ESTIMATES: AtomicUsize = AtomicUsize::new(0);
+
+/// The bits of the correlation `costing_btree_costestimate()` last estimated
+static LAST_CORRELATION: AtomicU64 = AtomicU64::new(0);
+
+// btree, but costed by `costing_btree_costestimate()`
+extension_sql! { r#"
+CREATE FUNCTION costing_btree_handler(internal) RETURNS index_am_handler
+    LANGUAGE c AS 'MODULE_PATHNAME', 'costing_btree_handler';
+CREATE ACCESS METHOD costing_btree TYPE INDEX HANDLER costing_btree_handler;
+CREATE OPERATOR CLASS costing_btree_int4_ops DEFAULT FOR TYPE int4 USING costing_btree AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 btint4cmp(int4, int4);
+"#}
+
+#[no_mangle]
+pub extern "C" fn pg_finfo_costing_btree_handler() -> &'static pg_sys::Pg_finfo_record {
+    const V1_API: pg_sys::Pg_finfo_record = pg_sys::Pg_finfo_record { api_version: 1 };
+    &V1_API
+}
+
+#[pg_guard]
+#[no_mangle]
+pub unsafe extern "C" fn costing_btree_handler(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+    let routine = pg_sys::bthandler(fcinfo) as *mut pg_sys::IndexAmRoutine;
+    (*routine).amcostestimate = Some(costing_btree_costestimate);
+    routine as pg_sys::Datum
+}
+
+#[pg_guard]
+unsafe extern "C" fn costing_btree_costestimate(
+    root: *mut pg_sys::PlannerInfo,
+    path: *mut pg_sys::IndexPath,
+    _loop_count: f64,
+    index_startup_cost: *mut pg_sys::Cost,
+    index_total_cost: *mut pg_sys::Cost,
+    index_selectivity: *mut pg_sys::Selectivity,
+    index_correlation: *mut f64,
+    index_pages: *mut f64,
+) {
+    COST_ESTIMATES.fetch_add(1, Ordering::SeqCst);
+
+    let root = PgBox::from_pg(root);
+    let index = PgBox::from_pg((*path).indexinfo);
+    let rel = PgBox::from_pg(index.rel);
+
+    // the tests' queries only have clauses this index can use
+    let clauses = PgList::<pg_sys::Node>::from_pg(rel.baserestrictinfo);
+    let selectivity = clauselist_selectivity(
+        &root,
+        &clauses,
+        rel.relid as i32,
+        pg_sys::JoinType_JOIN_INNER,
+        None,
+    );
+    let tuples = (selectivity * index.tuples).max(1.0);
+    let pages = (selectivity * index.pages as f64).ceil().max(1.0);
+    let correlation = pgx::costing::index_correlation(&root, &index);
+    LAST_CORRELATION.store(correlation.to_bits(), Ordering::SeqCst);
+
+    CostBuilder::new()
+        .io(pages, random_page_cost())
+        .cpu(tuples, cpu_index_tuple_cost() + cpu_operator_cost())
+        .selectivity(selectivity)
+        .correlation(correlation)
+        .pages(pages)
+        .write_index_cost(
+            index_startup_cost,
+            index_total_cost,
+            index_selectivity,
+            index_correlation,
+            index_pages,
+        );
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::{COST_ESTIMATES, LAST_CORRELATION};
+    use pgx::costing::*;
+    use pgx::*;
+    use std::sync::atomic::Ordering;
+
+    #[pg_test]
+    fn test_cost_gucs() {
+        Spi::run("SET seq_page_cost = 1.5");
+        Spi::run("SET random_page_cost = 2.5");
+        Spi::run("SET cpu_tuple_cost = 0.02");
+        Spi::run("SET cpu_index_tuple_cost = 0.004");
+        Spi::run("SET cpu_operator_cost = 0.003");
+        Spi::run("SET parallel_tuple_cost = 0.2");
+        Spi::run("SET parallel_setup_cost = 500");
+
+        assert_eq!(seq_page_cost(), 1.5);
+        assert_eq!(random_page_cost(), 2.5);
+        assert_eq!(cpu_tuple_cost(), 0.02);
+        assert_eq!(cpu_index_tuple_cost(), 0.004);
+        assert_eq!(cpu_operator_cost(), 0.003);
+        assert_eq!(parallel_tuple_cost(), 0.2);
+        assert_eq!(parallel_setup_cost(), 500.0);
+    }
+
+    #[pg_test]
+    fn test_effective_cache_size_is_in_pages() {
+        Spi::run("SET effective_cache_size = '64MB'");
+        assert_eq!(
+            effective_cache_size() as i64,
+            64 * 1024 * 1024 / pg_sys::BLCKSZ as i64
+        );
+    }
+
+    #[pg_test]
+    fn test_estimates_without_clauses() {
+        let root = PgBox::<pg_sys::PlannerInfo>::alloc_node(pg_sys::NodeTag_T_PlannerInfo);
+        let empty = PgList::<pg_sys::Node>::new();
+
+        assert_eq!(
+            clauselist_selectivity(&root, &empty, 0, pg_sys::JoinType_JOIN_INNER, None),
+            1.0
+        );
+        assert_eq!(estimate_num_groups(&root, &empty, 1000.0), 1.0);
+    }
+
+    #[pg_test]
+    fn test_cost_builder() {
+        let costs = CostBuilder::new()
+            .startup(1.0)
+            .startup(0.5)
+            .io(10.0, 4.0)
+            .cpu(100.0, 0.01)
+            .run(2.0);
+
+        assert_eq!(costs.startup_cost(), 1.5);
+        assert_eq!(costs.total_cost(), 1.5 + 40.0 + 1.0 + 2.0);
+        assert_eq!(CostBuilder::default(), CostBuilder::new());
+    }
+
+    #[pg_test]
+    fn test_cost_builder_write_index_cost() {
+        let (mut startup, mut total, mut selectivity, mut correlation, mut pages) =
+            (0.0, 0.0, 0.0, 0.0, 0.0);
+
+        unsafe {
+            CostBuilder::new()
+                .startup(1.0)
+                .run(9.0)
+                .selectivity(0.25)
+                .correlation(-0.5)
+                .pages(3.0)
+                .write_index_cost(
+                    &mut startup,
+                    &mut total,
+                    &mut selectivity,
+                    &mut correlation,
+                    &mut pages,
+                );
+        }
+
+        assert_eq!(startup, 1.0);
+        assert_eq!(total, 10.0);
+        assert_eq!(selectivity, 0.25);
+        assert_eq!(correlation, -0.5);
+        assert_eq!(pages, 3.0);
+    }
+
+    #[pg_test]
+    fn test_cost_builder_apply_to_custom_path() {
+        let mut path = PgBox::<pg_sys::CustomPath>::alloc_node(pg_sys::NodeTag_T_CustomPath);
+        let target = PgBox::<pg_sys::PathTarget>::alloc_node(pg_sys::NodeTag_T_PathTarget);
+        path.path.pathtarget = target.as_ptr();
+
+        CostBuilder::new()
+            .startup(2.0)
+            .run(8.0)
+            .rows(42.0)
+            .width(16)
+            .apply_to_custom_path(&mut path);
+
+        assert_eq!(path.path.startup_cost, 2.0);
+        assert_eq!(path.path.total_cost, 10.0);
+        assert_eq!(path.path.rows, 42.0);
+        assert_eq!(unsafe { (*path.path.pathtarget).width }, 16);
+
+        // the target, which other paths may share, was copied rather than changed
+        assert_ne!(path.path.pathtarget, target.as_ptr());
+        assert_eq!(target.width, 0);
+
+        // a path without a target only gets its costs and rows
+        let mut path = PgBox::<pg_sys::Path>::alloc_node(pg_sys::NodeTag_T_Path);
+        CostBuilder::new()
+            .run(1.0)
+            .rows(1.0)
+            .width(8)
+            .apply_to_path(&mut path);
+        assert_eq!(path.total_cost, 1.0);
+        assert_eq!(path.rows, 1.0);
+    }
+
+    fn explain(query: &str) -> String {
+        Spi::explain(query).0.to_string()
+    }
+
+    #[pg_test]
+    fn test_cost_builder_plan_choice() {
+        Spi::run(
+            "CREATE TABLE costing (id serial, kind int);
+             INSERT INTO costing (kind)
+                SELECT CASE WHEN i <= 10 THEN 2 ELSE 1 END FROM generate_series(1, 10000) i;
+             CREATE INDEX costing_kind_idx ON costing USING costing_btree (kind);
+             ANALYZE costing;",
+        );
+        let before = COST_ESTIMATES.load(Ordering::SeqCst);
+
+        // the index when it's selective, and a sequential scan when it isn't
+        let rare = explain("SELECT * FROM costing WHERE kind = 2");
+        assert!(rare.contains("costing_kind_idx"), "{}", rare);
+        let common = explain("SELECT * FROM costing WHERE kind = 1");
+        assert!(common.contains("Seq Scan"), "{}", common);
+        assert!(!common.contains("costing_kind_idx"), "{}", common);
+        assert!(COST_ESTIMATES.load(Ordering::SeqCst) > before);
+
+        // and once the statistics say the value is common, the same query doesn't use the index
+        Spi::run("UPDATE costing SET kind = 2; ANALYZE costing;");
+        let now_common = explain("SELECT * FROM costing WHERE kind = 2");
+        assert!(now_common.contains("Seq Scan"), "{}", now_common);
+        assert!(!now_common.contains("costing_kind_idx"), "{}", now_common);
+    }
+
+    #[pg_test]
+    fn test_index_correlation() {
+        Spi::run(
+            "CREATE TABLE costing_order AS
+                SELECT i AS ascending, -i AS descending FROM generate_series(1, 1000) i;
+             CREATE INDEX costing_ascending_idx ON costing_order USING costing_btree (ascending);
+             CREATE INDEX costing_descending_idx ON costing_order USING costing_btree (descending);
+             ANALYZE costing_order;",
+        );
+        let last_correlation = || f64::from_bits(LAST_CORRELATION.load(Ordering::SeqCst));
+
+        explain("SELECT * FROM costing_order WHERE ascending < 10");
+        assert_eq!(last_correlation(), 1.0);
+
+        explain("SELECT * FROM costing_order WHERE descending > -10");
+        assert_eq!(last_correlation(), -1.0);
+
+        // without statistics
+        Spi::run(
+            "CREATE TABLE costing_unanalyzed (id int);
+             CREATE INDEX costing_unanalyzed_idx ON costing_unanalyzed USING costing_btree (id);",
+        );
+        explain("SELECT * FROM costing_unanalyzed WHERE id = 1");
+        assert_eq!(last_correlation(), 0.0);
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::pg_sys::AsPgCStr;
+    use pgx::*;
+    use std::os::raw::c_char;
+
+    #[pg_test]
+    fn test_pgcstr_accessors() {
+        let cstr = unsafe { PgCStr::from_ptr("hello, world".as_pg_cstr()) }.unwrap();
+        assert_eq!(cstr.len(), 12);
+        assert!(!cstr.is_empty());
+        assert_eq!(cstr.as_str(), "hello, world");
+        assert_eq!(cstr.to_str_lossy(), "hello, world");
+        assert_eq!(cstr.to_string(), "hello, world");
+        assert_eq!(format!("{:?}", cstr), "\"hello, world\"");
+        assert_eq!(cstr, "hello, world");
+    }
+
+    #[pg_test]
+    fn test_pgcstr_empty_and_null() {
+        let empty = unsafe { PgCStr::from_ptr("".as_pg_cstr()) }.unwrap();
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+        assert_eq!(empty.as_str(), "");
+
+        assert!(unsafe { PgCStr::from_ptr(std::ptr::null()) }.is_none());
+    }
+
+    #[pg_test]
+    fn test_pgcstr_invalid_utf8() {
+        let bytes = b"abc\xFFdef\0";
+        let cstr = unsafe { PgCStr::from_ptr(bytes.as_ptr() as *const c_char) }.unwrap();
+        assert_eq!(cstr.len(), 7);
+        assert!(cstr.to_str().is_err());
+        assert_eq!(cstr.to_str_lossy(), "abc\u{FFFD}def");
+    }
+
+    #[pg_test(error = "PgCStr is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 1")]
+    fn test_pgcstr_as_str_panics_on_invalid_utf8() {
+        let bytes = b"a\xFF\0";
+        let cstr = unsafe { PgCStr::from_ptr(bytes.as_ptr() as *const c_char) }.unwrap();
+        cstr.as_str();
+    }
+
+    #[pg_test]
+    fn test_pgcstr_pgbox_conversions() {
+        let boxed = PgBox::from_pg("boxed".as_pg_cstr());
+        let cstr = unsafe { PgCStr::from_pgbox(&boxed) }.unwrap();
+        assert_eq!(cstr.as_str(), "boxed");
+
+        // back to a pointer-to-char, which sees only the first character
+        let first: PgBox<c_char> = cstr.into();
+        assert_eq!(first.as_ptr() as *const c_char, cstr.as_ptr());
+        assert_eq!(*first as u8, b'b');
+
+        assert!(unsafe { PgCStr::from_pgbox(&PgBox::null()) }.is_none());
+    }
+
+    #[pg_test]
+    fn test_pgcstr_cstr_conversions() {
+        let owned = std::ffi::CString::new("from rust").unwrap();
+        let cstr: PgCStr = owned.as_c_str().into();
+        assert_eq!(cstr.as_str(), "from rust");
+
+        let back: &std::ffi::CStr = cstr.into();
+        assert_eq!(back, owned.as_c_str());
+    }
+}
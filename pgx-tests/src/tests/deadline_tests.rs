@@ -0,0 +1,106 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::deadline::DeadlineGuard;
+    use pgx::*;
+    use std::time::{Duration, Instant};
+
+    /// Spins, without ever checking for interrupts itself, until the statement times out
+    #[pg_extern]
+    fn deadline_busy_loop() {
+        let guard = DeadlineGuard::new();
+        loop {
+            guard.check();
+        }
+    }
+
+    #[pg_test(error = "canceling statement due to statement timeout")]
+    fn test_busy_loop_times_out() {
+        // set by the running statement, so Postgres' own timer isn't armed
+        Spi::run("SET LOCAL statement_timeout = '100ms'");
+        deadline_busy_loop();
+    }
+
+    #[pg_test]
+    fn test_busy_loop_sqlstate() {
+        Spi::run(
+            "CREATE FUNCTION tests.deadline_sqlstate() RETURNS text LANGUAGE plpgsql AS $$
+             BEGIN
+                 PERFORM tests.deadline_busy_loop();
+                 RETURN 'no error';
+             EXCEPTION WHEN query_canceled THEN
+                 RETURN SQLSTATE;
+             END
+             $$",
+        );
+        Spi::run("SET LOCAL statement_timeout = '100ms'");
+
+        let deadline = pgx::deadline::statement_deadline().expect("no statement deadline");
+        let sqlstate = Spi::get_one::<String>("SELECT tests.deadline_sqlstate()");
+        let now = Instant::now();
+
+        assert_eq!(sqlstate, Some("57014".to_string()));
+        assert!(now >= deadline);
+        assert!(
+            now - deadline < Duration::from_secs(1),
+            "stopped {:?} after the deadline",
+            now - deadline
+        );
+    }
+
+    #[pg_test]
+    fn test_remaining() {
+        Spi::run("SET LOCAL statement_timeout = 0");
+        assert_eq!(pgx::deadline::statement_timeout(), None);
+        assert_eq!(pgx::deadline::statement_deadline(), None);
+        assert_eq!(pgx::deadline::remaining(), None);
+
+        let guard = DeadlineGuard::new();
+        assert_eq!(guard.deadline(), None);
+        assert!(!guard.is_expired());
+        guard.check();
+
+        Spi::run("SET LOCAL statement_timeout = '1h'");
+        assert_eq!(
+            pgx::deadline::statement_timeout(),
+            Some(Duration::from_secs(3600))
+        );
+        let remaining = pgx::deadline::remaining().expect("no time remaining");
+        assert!(remaining <= Duration::from_secs(3600));
+        assert!(remaining > Duration::from_secs(3540));
+    }
+
+    #[pg_test]
+    fn test_deadline_is_from_statement_start() {
+        Spi::run("SET LOCAL statement_timeout = '1h'");
+        let before = pgx::deadline::statement_deadline().unwrap();
+
+        // queries run through SPI are part of the same statement
+        Spi::run("SELECT pg_sleep(0.05)");
+        let after = pgx::deadline::statement_deadline().unwrap();
+
+        let drift = if after > before {
+            after - before
+        } else {
+            before - after
+        };
+        assert!(drift < Duration::from_millis(10), "drifted {:?}", drift);
+    }
+
+    #[pg_test]
+    fn test_lock_timeout() {
+        Spi::run("SET LOCAL lock_timeout = 0");
+        assert_eq!(pgx::deadline::lock_timeout(), None);
+
+        Spi::run("SET LOCAL lock_timeout = '250ms'");
+        assert_eq!(
+            pgx::deadline::lock_timeout(),
+            Some(Duration::from_millis(250))
+        );
+    }
+}
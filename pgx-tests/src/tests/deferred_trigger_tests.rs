@@ -0,0 +1,162 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+// a double-entry ledger, where each batch of entries must add up to zero.  That can't be checked
+// after each statement, as a batch is inserted one entry at a time, so it's checked at COMMIT
+extension_sql! { r#"
+CREATE TABLE ledger_entries (
+    id serial PRIMARY KEY,
+    batch int NOT NULL,
+    amount int NOT NULL
+);
+CREATE TABLE ledger_flags (
+    is_constraint bool,
+    is_deferrable bool,
+    is_initially_deferred bool,
+    referenced regclass
+);
+"# }
+
+create_trigger!(
+    "ledger_balanced",
+    table = "ledger_entries",
+    function = "ledger_check_balanced",
+    after(insert, update, delete),
+    for_each_row,
+    constraint,
+    initially_deferred
+);
+
+create_trigger!(
+    "ledger_record_flags",
+    table = "ledger_entries",
+    function = "ledger_record_flags",
+    after(insert),
+    for_each_row,
+    constraint,
+    deferrable,
+    from = "ledger_flags",
+    when = "NEW.batch < 0"
+);
+
+/// Raises an ERROR if a batch changed by the event doesn't add up to zero
+#[pg_extern]
+fn ledger_check_balanced(fcinfo: pg_sys::FunctionCallInfo) -> TriggerResult {
+    let trigger = PgTrigger::from_fcinfo(fcinfo);
+
+    // the rows may have changed since the event, so check the batches as they are now
+    for row in trigger.new_row().into_iter().chain(trigger.old_row()) {
+        let batch = row.get_by_name::<i32>("batch").expect("batch is NULL");
+        let total = Spi::get_one_with_args::<i64>(
+            "SELECT coalesce(sum(amount), 0) FROM ledger_entries WHERE batch = $1",
+            vec![(PgBuiltInOids::INT4OID.oid(), batch.into_datum())],
+        )
+        .expect("sum is NULL");
+
+        if total != 0 {
+            error!("ledger batch {} is unbalanced by {}", batch, total);
+        }
+    }
+    TriggerResult::Skip
+}
+
+/// Records how the trigger that fired it was created
+#[pg_extern]
+fn ledger_record_flags(fcinfo: pg_sys::FunctionCallInfo) -> TriggerResult {
+    let trigger = PgTrigger::from_fcinfo(fcinfo);
+    Spi::run(&format!(
+        "INSERT INTO ledger_flags VALUES ({}, {}, {}, {})",
+        trigger.is_constraint(),
+        trigger.is_deferrable(),
+        trigger.is_initially_deferred(),
+        trigger
+            .referenced_relation_oid()
+            .map_or("NULL".to_string(), |oid| oid.to_string())
+    ));
+    TriggerResult::Skip
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_balanced_batch() {
+        Spi::run("INSERT INTO ledger_entries (batch, amount) VALUES (1, 10)");
+        Spi::run("INSERT INTO ledger_entries (batch, amount) VALUES (1, -4), (1, -6)");
+        Spi::run("SET CONSTRAINTS ALL IMMEDIATE");
+    }
+
+    #[pg_test(error = "ledger batch 2 is unbalanced by 10")]
+    fn test_unbalanced_batch() {
+        // the check is deferred, so the statement succeeds ...
+        Spi::run("INSERT INTO ledger_entries (batch, amount) VALUES (2, 10)");
+
+        // ... until the check is made, which is at COMMIT unless it's asked for sooner
+        Spi::run("SET CONSTRAINTS ledger_balanced IMMEDIATE");
+    }
+
+    #[pg_test]
+    fn test_rows_changed_since_the_event() {
+        // the INSERT event's row has an amount of 10, but the batch is balanced by the time the
+        // trigger is fired for it
+        Spi::run("INSERT INTO ledger_entries (batch, amount) VALUES (3, 10)");
+        Spi::run("UPDATE ledger_entries SET amount = 0 WHERE batch = 3");
+        Spi::run("SET CONSTRAINTS ALL IMMEDIATE");
+    }
+
+    #[pg_test]
+    fn test_trigger_flags() {
+        // the WHEN clause only fires the trigger for negative batches
+        Spi::run("INSERT INTO ledger_entries (batch, amount) VALUES (4, 0), (-4, 0)");
+        Spi::run("SET CONSTRAINTS ALL IMMEDIATE");
+
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM ledger_flags"),
+            Some(1)
+        );
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT is_constraint AND is_deferrable AND NOT is_initially_deferred
+                        AND referenced = 'ledger_flags'::regclass
+                   FROM ledger_flags"
+            ),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_violation_raised_at_commit() {
+        let (mut client, _) =
+            pgx_tests::start_and_connect(crate::pg_test::postgresql_conf_options());
+        client
+            .batch_execute(
+                "BEGIN;
+                 INSERT INTO ledger_entries (batch, amount) VALUES (100, 5);",
+            )
+            .expect("the unbalanced INSERT failed before COMMIT");
+
+        let error = client
+            .batch_execute("COMMIT")
+            .expect_err("the unbalanced batch was committed");
+        assert_eq!(
+            error.as_db_error().map(|e| e.message()),
+            Some("ledger batch 100 is unbalanced by 5")
+        );
+
+        client
+            .batch_execute(
+                "BEGIN;
+                 INSERT INTO ledger_entries (batch, amount) VALUES (101, 5);
+                 INSERT INTO ledger_entries (batch, amount) VALUES (101, -5);
+                 COMMIT;
+                 DELETE FROM ledger_entries WHERE batch = 101;",
+            )
+            .expect("the balanced batch wasn't committed");
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn table(name: &str) -> ObjectAddress {
+        Spi::run(&format!("CREATE TABLE {} (id int, value text)", name));
+        let relation = PgRelation::open_with_name_and_share_lock(name).unwrap();
+        ObjectAddress::relation(&relation)
+    }
+
+    fn table_exists(name: &str) -> bool {
+        Spi::get_one::<bool>(&format!("SELECT to_regclass('{}') IS NOT NULL", name)).unwrap()
+    }
+
+    /// `source` and `derived`, where `derived` depends on `source`
+    fn dependent_tables() -> (ObjectAddress, ObjectAddress) {
+        let source = table("source");
+        let derived = table("derived");
+        record_dependency(derived, source, DependencyType::Normal);
+        (source, derived)
+    }
+
+    #[pg_test]
+    fn test_dependencies_of() {
+        let (source, derived) = dependent_tables();
+
+        assert!(dependencies_of(derived).any(|dependency| dependency
+            == Dependency {
+                object: source,
+                deptype: DependencyType::Normal,
+            }));
+        assert!(dependents_of(source).any(|dependency| dependency
+            == Dependency {
+                object: derived,
+                deptype: DependencyType::Normal,
+            }));
+
+        // it's one-way
+        assert!(!dependencies_of(source).any(|dependency| dependency.object == derived));
+        assert!(!dependents_of(derived).any(|dependency| dependency.object == source));
+    }
+
+    #[pg_test]
+    fn test_column_dependencies() {
+        let source = table("source");
+        let derived = table("derived");
+        let value = ObjectAddress::new(source.classid, source.objid, 2);
+        record_dependency(derived, value, DependencyType::Normal);
+
+        // the whole table includes its columns
+        assert!(dependents_of(source).any(|dependency| dependency.object == derived));
+        assert!(dependents_of(value).any(|dependency| dependency.object == derived));
+
+        let id = ObjectAddress::new(source.classid, source.objid, 1);
+        assert_eq!(dependents_of(id).count(), 0);
+
+        assert_eq!(value.description(), "column value of table source");
+    }
+
+    #[pg_test(error = "cannot drop table source because other objects depend on it")]
+    fn test_drop_with_dependents() {
+        dependent_tables();
+        Spi::run("DROP TABLE source");
+    }
+
+    #[pg_test]
+    fn test_drop_cascade() {
+        let (source, derived) = dependent_tables();
+
+        Spi::run("DROP TABLE source CASCADE");
+        assert!(!table_exists("source"));
+        assert!(!table_exists("derived"));
+        assert_eq!(dependents_of(source).count(), 0);
+        assert_eq!(dependencies_of(derived).count(), 0);
+    }
+
+    #[pg_test]
+    fn test_drop_after_deleting_dependencies() {
+        let (source, derived) = dependent_tables();
+
+        assert!(delete_dependencies_of(derived) > 0);
+        assert!(!dependents_of(source).any(|dependency| dependency.object == derived));
+
+        Spi::run("DROP TABLE source");
+        assert!(table_exists("derived"));
+    }
+
+    #[pg_test(error = "cannot drop table member because extension pgx_tests requires it")]
+    fn test_extension_member() {
+        let member = table("member");
+        let extension = ObjectAddress::extension("pgx_tests").expect("pgx_tests isn't installed");
+        record_dependency(member, extension, DependencyType::Extension);
+
+        assert!(dependencies_of(member).any(|dependency| dependency
+            == Dependency {
+                object: extension,
+                deptype: DependencyType::Extension,
+            }));
+        Spi::run("DROP TABLE member");
+    }
+
+    #[pg_test]
+    fn test_extension_member_released() {
+        let member = table("member");
+        let extension = ObjectAddress::extension("pgx_tests").expect("pgx_tests isn't installed");
+        record_dependency(member, extension, DependencyType::Extension);
+
+        delete_dependencies_of(member);
+        Spi::run("DROP TABLE member");
+        assert!(!table_exists("member"));
+    }
+
+    #[pg_test]
+    fn test_no_such_extension() {
+        assert_eq!(ObjectAddress::extension("no_such_extension"), None);
+    }
+
+    #[pg_test(
+        error = "record_extension_dependency() can only be used by CREATE EXTENSION or ALTER EXTENSION ... UPDATE"
+    )]
+    fn test_record_extension_dependency_outside_create_extension() {
+        record_extension_dependency(table("member"));
+    }
+
+    #[pg_test]
+    fn test_comments() {
+        let source = table("source");
+        assert_eq!(get_comment(source), None);
+        assert_eq!(source.description(), "table source");
+
+        set_comment(source, Some("the source of it all"));
+        assert_eq!(
+            get_comment(source),
+            Some("the source of it all".to_string())
+        );
+        assert_eq!(
+            Spi::get_one::<&str>("SELECT obj_description('source'::regclass, 'pg_class')"),
+            Some("the source of it all")
+        );
+
+        // and COMMENT ON sees the same thing
+        Spi::run("COMMENT ON TABLE source IS 'changed'");
+        assert_eq!(get_comment(source), Some("changed".to_string()));
+
+        set_comment(source, None);
+        assert_eq!(get_comment(source), None);
+    }
+
+    #[pg_test]
+    fn test_function_address() {
+        let oid = Spi::get_one::<pg_sys::Oid>("SELECT 'now()'::regprocedure::oid").unwrap();
+        assert_eq!(ObjectAddress::function(oid).description(), "function now()");
+    }
+}
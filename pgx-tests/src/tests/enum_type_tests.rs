@@ -17,12 +17,31 @@ fn take_foo_enum(value: Foo) -> Foo {
     Foo::Three
 }
 
+#[derive(PostgresEnum, PartialEq, Debug, Clone, Copy)]
+pub enum BearSpecies {
+    Black,
+    Brown,
+    Polar,
+}
+
+#[pg_extern]
+fn add_brown_bear(bears: Vec<BearSpecies>) -> Vec<BearSpecies> {
+    let mut bears = bears;
+    bears.push(BearSpecies::Brown);
+    bears
+}
+
+#[pg_extern]
+fn same_bears(bears: Array<BearSpecies>) -> Array<BearSpecies> {
+    bears
+}
+
 #[cfg(any(test, feature = "pg_test"))]
 mod tests {
     #[allow(unused_imports)]
     use crate as pgx_tests;
 
-    use crate::tests::enum_type_tests::Foo;
+    use crate::tests::enum_type_tests::{BearSpecies, Foo};
     use pgx::*;
 
     #[test]
@@ -34,4 +53,82 @@ mod tests {
             Spi::get_one::<Foo>("SELECT take_foo_enum('One');").expect("failed to get SPI result");
         assert_eq!(Foo::Three, result);
     }
+
+    #[pg_test]
+    fn test_enum_sql_labels() {
+        assert_eq!(BearSpecies::Polar.sql_label(), "Polar");
+        assert_eq!(
+            BearSpecies::from_sql_label("Black"),
+            Some(BearSpecies::Black)
+        );
+        assert_eq!(BearSpecies::from_sql_label("black"), None);
+        assert_eq!(BearSpecies::from_sql_label("Panda"), None);
+    }
+
+    #[pg_test]
+    fn test_enum_type_oids() {
+        assert_eq!(BearSpecies::type_oid(), regtypein("bearspecies"));
+        assert_eq!(BearSpecies::array_type_oid(), regtypein("bearspecies[]"));
+        assert_eq!(Vec::<BearSpecies>::type_oid(), regtypein("bearspecies[]"));
+    }
+
+    #[pg_test]
+    fn test_enum_vec_roundtrip() {
+        let result = Spi::get_one::<Vec<BearSpecies>>(
+            "SELECT add_brown_bear(ARRAY['Polar','Black']::bearspecies[]);",
+        )
+        .expect("failed to get SPI result");
+        assert_eq!(
+            result,
+            vec![BearSpecies::Polar, BearSpecies::Black, BearSpecies::Brown]
+        );
+    }
+
+    #[pg_test]
+    fn test_enum_vec_return_type() {
+        let result = Spi::get_one::<bool>(
+            "SELECT pg_typeof(add_brown_bear(ARRAY['Polar']::bearspecies[])) = 'bearspecies[]'::regtype;",
+        )
+        .expect("failed to get SPI result");
+        assert!(result);
+    }
+
+    #[pg_test]
+    fn test_enum_array_roundtrip() {
+        let result = Spi::get_one::<Vec<BearSpecies>>(
+            "SELECT same_bears(ARRAY['Polar','Black']::bearspecies[]);",
+        )
+        .expect("failed to get SPI result");
+        assert_eq!(result, vec![BearSpecies::Polar, BearSpecies::Black]);
+    }
+
+    #[pg_test]
+    fn test_enum_type_follows_search_path() {
+        Spi::run("CREATE SCHEMA other_bears");
+        Spi::run("CREATE TYPE other_bears.bearspecies AS ENUM ('Koala')");
+
+        // cached before the search_path changes
+        let original = BearSpecies::type_oid();
+        let schema = Spi::get_one::<String>(&format!(
+            "SELECT typnamespace::regnamespace::text FROM pg_type WHERE oid = {}",
+            original
+        ))
+        .expect("no schema");
+
+        Spi::run(
+            "SELECT set_config('search_path', 'other_bears, ' || current_setting('search_path'), true)",
+        );
+        let other = regtypein("other_bears.bearspecies");
+        assert_ne!(other, original);
+        assert_eq!(BearSpecies::type_oid(), other);
+        assert_eq!(lookup_enum_type(None, "bearspecies").type_oid, other);
+        assert_eq!(
+            lookup_enum_type(Some(&schema), "bearspecies").type_oid,
+            original
+        );
+        assert_eq!(
+            lookup_enum_type(Some("other_bears"), "bearspecies").type_oid,
+            other
+        );
+    }
 }
@@ -0,0 +1,165 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CONTEXT_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Raises an ERROR about `row` inside of two error context frames, as a Postgres ERROR if
+/// `ereport` is true, else as a Rust panic
+#[pg_extern]
+fn fail_in_context(row: i32, ereport: bool) {
+    let _table = error_context::push(|| "while processing table t".to_string());
+    let _row = error_context::push(move || format!("while processing row {} of table t", row));
+
+    if ereport {
+        ErrorReport::new(PgSqlErrorCode::ERRCODE_DATA_EXCEPTION, "bad row")
+            .report(PgLogLevel::ERROR);
+    } else {
+        panic!("bad row");
+    }
+}
+
+/// Processes `rows` rows in an error context frame each, without any errors
+#[pg_extern]
+fn succeed_in_context(rows: i32) -> i32 {
+    let mut processed = 0;
+    for row in 1..=rows {
+        let _row = error_context::push(move || {
+            CONTEXT_CALLS.fetch_add(1, Ordering::SeqCst);
+            format!("while processing row {}", row)
+        });
+        processed += 1;
+    }
+    processed
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::CONTEXT_CALLS;
+    use pgx::*;
+    use std::ffi::CStr;
+    use std::sync::atomic::Ordering;
+
+    /// The lines of the `CONTEXT` of the ERROR that `sql` raises
+    fn caught_context(sql: &str) -> Vec<String> {
+        let result = pg_try_subtransaction(|| Spi::run(sql));
+        assert!(
+            result.sqlerrcode().is_some(),
+            "`{}` didn't raise an ERROR",
+            sql
+        );
+
+        let context = unsafe {
+            let edata = pg_sys::CopyErrorData();
+            let context = (*edata).context;
+            let context = if context.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(context).to_string_lossy().into_owned()
+            };
+            pg_sys::FreeErrorData(edata);
+            result.unwrap_or(());
+            context
+        };
+
+        context.lines().map(str::to_string).collect()
+    }
+
+    #[pg_test]
+    fn test_nested_frames_with_ereport() {
+        let context = caught_context("SELECT fail_in_context(5, true)");
+        assert_eq!(
+            &context[..2],
+            [
+                "while processing row 5 of table t",
+                "while processing table t"
+            ]
+        );
+    }
+
+    #[pg_test]
+    fn test_nested_frames_with_panic() {
+        let context = caught_context("SELECT fail_in_context(7, false)");
+        assert_eq!(
+            &context[..2],
+            [
+                "while processing row 7 of table t",
+                "while processing table t"
+            ]
+        );
+    }
+
+    #[pg_test]
+    fn test_frame_outside_of_postgres_context() {
+        let _frame = error_context::push(|| "while dividing".to_string());
+        let context = caught_context("SELECT 1 / 0");
+        assert_eq!(
+            &context[..2],
+            ["SQL statement \"SELECT 1 / 0\"", "while dividing"]
+        );
+    }
+
+    #[pg_test]
+    fn test_context_is_lazy() {
+        CONTEXT_CALLS.store(0, Ordering::SeqCst);
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT succeed_in_context(1000)"),
+            Some(1000)
+        );
+        assert_eq!(CONTEXT_CALLS.load(Ordering::SeqCst), 0);
+    }
+
+    #[pg_test]
+    fn test_frames_are_popped() {
+        let stack = unsafe { pg_sys::error_context_stack };
+        {
+            let _outer = error_context::push(|| "outer".to_string());
+            let _inner = error_context::push(|| "inner".to_string());
+            assert_ne!(unsafe { pg_sys::error_context_stack }, stack);
+        }
+        assert_eq!(unsafe { pg_sys::error_context_stack }, stack);
+
+        // out of order
+        let outer = error_context::push(|| "outer".to_string());
+        let inner = error_context::push(|| "inner".to_string());
+        drop(outer);
+        let context = caught_context("SELECT 1 / 0");
+        assert_eq!(&context[..2], ["SQL statement \"SELECT 1 / 0\"", "inner"]);
+        assert!(!context.contains(&"outer".to_string()));
+        drop(inner);
+        assert_eq!(unsafe { pg_sys::error_context_stack }, stack);
+    }
+
+    #[pg_test]
+    fn test_forgotten_frame() {
+        let stack = unsafe { pg_sys::error_context_stack };
+        std::mem::forget(error_context::push(|| "forgotten".to_string()));
+
+        // it's leaked rather than freed, so its context can still be made
+        assert_eq!(error_context::current(), vec!["forgotten".to_string()]);
+        unsafe {
+            pg_sys::error_context_stack = stack;
+        }
+    }
+
+    #[pg_test]
+    fn test_no_context_after_frames() {
+        assert_eq!(
+            caught_context("SELECT fail_in_context(1, false)")
+                .iter()
+                .filter(|line| line.contains("table t"))
+                .count(),
+            2
+        );
+
+        // the caught panic's context isn't reported with the next one
+        let context = caught_context("SELECT 1 / 0");
+        assert_eq!(context[0], "SQL statement \"SELECT 1 / 0\"");
+        assert!(!context.iter().any(|line| line.contains("table t")));
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+extension_config_table!(config_settings, "WHERE NOT standard");
+
+// the table is created after the `extension_config_table!()` that names it
+extension_sql! { r#"
+CREATE TABLE config_settings (
+    name text PRIMARY KEY,
+    value text,
+    standard bool NOT NULL DEFAULT false
+);
+INSERT INTO config_settings VALUES ('color', 'blue', true), ('size', 'large', true);
+"# }
+
+/// Creates a table while the extension is being created, and marks it as a configuration table
+#[pg_extern]
+fn create_dynamic_config_table() {
+    Spi::run("CREATE TABLE dynamic_config (name text, value text)");
+    let relation = PgRelation::open_with_name_and_share_lock("dynamic_config").unwrap();
+    mark_as_config_table(&relation, None);
+}
+
+extension_sql! { r#"
+SELECT create_dynamic_config_table();
+"# }
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    /// The `pg_dump` filter `pg_extension` has for the configuration table `table`, if it is one
+    fn extcondition(table: &str) -> Option<String> {
+        Spi::get_one::<String>(&format!(
+            "SELECT extcondition[array_position(extconfig, '{}'::regclass::oid)]
+               FROM pg_extension WHERE extname = 'pgx_tests'",
+            table
+        ))
+    }
+
+    #[pg_test]
+    fn test_config_table() {
+        Spi::run("INSERT INTO config_settings (name, value) VALUES ('shape', 'round')");
+
+        let filter = extcondition("config_settings").expect("config_settings isn't a config table");
+        assert_eq!(filter, "WHERE NOT standard");
+
+        // only the user's row is dumped
+        assert_eq!(
+            Spi::get_one::<String>(&format!(
+                "SELECT string_agg(name, ',') FROM config_settings {}",
+                filter
+            )),
+            Some("shape".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_runtime_config_table() {
+        assert_eq!(extcondition("dynamic_config"), Some("".to_string()));
+    }
+
+    #[pg_test]
+    fn test_not_a_config_table() {
+        Spi::run("CREATE TABLE not_config (id int)");
+        assert_eq!(extcondition("not_config"), None);
+    }
+
+    #[pg_test(
+        error = "mark_as_config_table() can only be used by CREATE EXTENSION or ALTER EXTENSION ... UPDATE"
+    )]
+    fn test_mark_as_config_table_outside_create_extension() {
+        Spi::run("CREATE TABLE late_config (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("late_config").unwrap();
+        mark_as_config_table(&relation, Some("WHERE id > 0"));
+    }
+}
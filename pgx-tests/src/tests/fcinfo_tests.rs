@@ -91,6 +91,34 @@ fn returns_tuple() -> (name!(id, i32), name!(title, String)) {
     (42, "pgx".into())
 }
 
+#[pg_extern]
+fn returns_tuple_with_args(
+    value: i32,
+    label: &str,
+) -> (
+    name!(doubled, i32),
+    name!(upper, String),
+    name!(missing, Option<i64>),
+) {
+    (value * 2, label.to_uppercase(), None)
+}
+
+#[pg_extern]
+fn returns_optional_tuple(value: i32) -> Option<(name!(id, i32), name!(title, String))> {
+    if value > 0 {
+        Some((value, format!("title {}", value)))
+    } else {
+        None
+    }
+}
+
+#[pg_extern]
+fn returns_result_tuple(
+    value: &str,
+) -> Result<(name!(id, i32), name!(valid, bool)), std::num::ParseIntError> {
+    Ok((value.parse()?, true))
+}
+
 #[pg_extern]
 fn same_name(same_name: &str) -> &str {
     same_name
@@ -245,10 +273,144 @@ mod tests {
         assert_eq!((Some(42), Some("pgx".into())), result)
     }
 
+    #[pg_test]
+    fn test_returns_tuple_is_out_params() {
+        let (retset, rettype) = Spi::get_two::<bool, &str>(
+            "SELECT proretset, prorettype::regtype::text FROM pg_proc WHERE proname = 'returns_tuple_with_args'",
+        );
+        assert_eq!(retset, Some(false));
+        assert_eq!(rettype, Some("record"));
+
+        let args = Spi::get_one::<&str>(
+            "SELECT pg_get_function_arguments(oid) FROM pg_proc WHERE proname = 'returns_tuple_with_args'",
+        );
+        assert_eq!(
+            args,
+            Some("value integer, label text, OUT doubled integer, OUT upper text, OUT missing bigint")
+        );
+    }
+
+    #[pg_test]
+    fn test_returns_tuple_with_args() {
+        let result = Spi::get_three::<i32, String, i64>(
+            "SELECT doubled, upper, missing FROM returns_tuple_with_args(21, 'pgx');",
+        );
+        assert_eq!((Some(42), Some("PGX".into()), None), result);
+
+        // the record can also be used as a value
+        let result = Spi::get_one::<&str>("SELECT returns_tuple_with_args(1, 'a')::text;");
+        assert_eq!(result, Some("(2,A,)"));
+    }
+
+    #[pg_test]
+    fn test_returns_optional_tuple() {
+        let result = Spi::get_two::<i32, String>("SELECT * FROM returns_optional_tuple(7);");
+        assert_eq!((Some(7), Some("title 7".into())), result);
+
+        let is_null = Spi::get_one::<bool>("SELECT returns_optional_tuple(0) IS NULL;");
+        assert_eq!(is_null, Some(true));
+    }
+
+    #[pg_test]
+    fn test_returns_result_tuple() {
+        let result = Spi::get_two::<i32, bool>("SELECT * FROM returns_result_tuple('42');");
+        assert_eq!((Some(42), Some(true)), result);
+    }
+
+    #[pg_test(error = "invalid digit found in string")]
+    fn test_returns_result_tuple_err() {
+        Spi::get_two::<i32, bool>("SELECT * FROM returns_result_tuple('nope');");
+    }
+
     /// ensures that we can have a `#[pg_extern]` function with an argument that
     /// shares its name
     #[pg_test]
     fn test_same_name() {
         assert_eq!("test", same_name("test"));
     }
+
+    /// a palloc'd `fcinfo` for a call with `args`, built by hand in this version's layout
+    #[cfg(any(feature = "pg10", feature = "pg11"))]
+    fn make_fcinfo(args: &[(pg_sys::Datum, bool)]) -> pg_sys::FunctionCallInfo {
+        let mut fcinfo = PgBox::<pg_sys::FunctionCallInfoData>::alloc0();
+        fcinfo.nargs = args.len() as i16;
+        for (i, &(value, isnull)) in args.iter().enumerate() {
+            fcinfo.arg[i] = value;
+            fcinfo.argnull[i] = isnull;
+        }
+        fcinfo.into_pg()
+    }
+
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    fn make_fcinfo(args: &[(pg_sys::Datum, bool)]) -> pg_sys::FunctionCallInfo {
+        unsafe {
+            let fcinfo = pg_sys::palloc0(
+                std::mem::size_of::<pg_sys::FunctionCallInfoBaseData>()
+                    + args.len() * std::mem::size_of::<pg_sys::NullableDatum>(),
+            ) as pg_sys::FunctionCallInfo;
+            (*fcinfo).nargs = args.len() as i16;
+            for (i, &(value, isnull)) in args.iter().enumerate() {
+                (*fcinfo)
+                    .args
+                    .as_mut_ptr()
+                    .add(i)
+                    .write(pg_sys::NullableDatum { value, isnull });
+            }
+            fcinfo
+        }
+    }
+
+    #[cfg(any(feature = "pg10", feature = "pg11"))]
+    fn read_arg_fields(fcinfo: pg_sys::FunctionCallInfo, i: usize) -> (pg_sys::Datum, bool) {
+        let fcinfo = unsafe { fcinfo.as_ref() }.unwrap();
+        (fcinfo.arg[i], fcinfo.argnull[i])
+    }
+
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    fn read_arg_fields(fcinfo: pg_sys::FunctionCallInfo, i: usize) -> (pg_sys::Datum, bool) {
+        let arg = unsafe { (*fcinfo).args.as_ptr().add(i).read() };
+        (arg.value, arg.isnull)
+    }
+
+    #[pg_test]
+    fn test_fcinfo_args_of_every_arity() {
+        for nargs in 0..=pg_sys::FUNC_MAX_ARGS as usize {
+            let expected = (0..nargs)
+                .map(|i| ((i * 7 + 1) as pg_sys::Datum, i % 3 == 0))
+                .collect::<Vec<_>>();
+            let fcinfo = make_fcinfo(&expected);
+            let fcinfo_ref = unsafe { PgFunctionCallInfo::from_ptr(fcinfo) };
+
+            assert_eq!(fcinfo_ref.num_args(), nargs);
+            let args = fcinfo_ref.args();
+            assert_eq!(args.len(), nargs);
+            for (i, &(value, isnull)) in expected.iter().enumerate() {
+                assert_eq!(read_arg_fields(fcinfo, i), (value, isnull));
+                assert_eq!((args[i].value, args[i].isnull), (value, isnull));
+                assert_eq!(fcinfo_ref.arg_is_null(i), Some(isnull));
+                assert_eq!(pg_arg_is_null(fcinfo, i), isnull);
+                assert_eq!(pg_getarg_datum_raw(fcinfo, i), value);
+
+                let datum = if isnull { None } else { Some(value) };
+                assert_eq!(fcinfo_ref.get_arg_datum(i), datum);
+                assert_eq!(pg_getarg_datum(fcinfo, i), datum);
+            }
+
+            assert!(fcinfo_ref.arg(nargs).is_none());
+            assert_eq!(fcinfo_ref.arg_is_null(nargs), None);
+        }
+    }
+
+    #[pg_test(error = "argument 2 is out of range, the function was called with 2 arguments")]
+    fn test_fcinfo_arg_out_of_range() {
+        let fcinfo = make_fcinfo(&[(1, false), (2, false)]);
+        pg_getarg_datum_raw(fcinfo, 2);
+    }
+
+    #[pg_test(error = "function call info has an invalid number of arguments: -1")]
+    fn test_fcinfo_invalid_nargs() {
+        let fcinfo = make_fcinfo(&[]);
+        unsafe { (*fcinfo).nargs = -1 };
+        unsafe { PgFunctionCallInfo::from_ptr(fcinfo) }.args();
+    }
 }
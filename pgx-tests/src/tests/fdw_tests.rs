@@ -0,0 +1,149 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::collections::HashMap;
+
+    fn options(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// A wrapper without a handler can't be queried through, but its catalog entries are real
+    fn create_server() -> ForeignServer {
+        Spi::run("CREATE FOREIGN DATA WRAPPER test_fdw OPTIONS (level 'fdw', wrapper 'yes')");
+        Spi::run(
+            "CREATE SERVER test_server TYPE 'remote' VERSION '1.0' FOREIGN DATA WRAPPER test_fdw
+                 OPTIONS (level 'server', host 'localhost')",
+        );
+        ForeignServer::from_name("test_server").expect("test_server doesn't exist")
+    }
+
+    #[pg_test]
+    fn test_foreign_server() {
+        let server = create_server();
+
+        assert_eq!(server.name(), "test_server");
+        assert_eq!(
+            Some(server.oid()),
+            Spi::get_one::<pg_sys::Oid>(
+                "SELECT oid FROM pg_foreign_server WHERE srvname = 'test_server'"
+            )
+        );
+        assert_eq!(
+            Some(server.fdw_oid()),
+            Spi::get_one::<pg_sys::Oid>(
+                "SELECT oid FROM pg_foreign_data_wrapper WHERE fdwname = 'test_fdw'"
+            )
+        );
+        assert_eq!(server.owner(), unsafe { pg_sys::GetUserId() });
+        assert_eq!(server.server_type(), Some("remote"));
+        assert_eq!(server.server_version(), Some("1.0"));
+        assert_eq!(
+            server.options(),
+            options(&[("level", "server"), ("host", "localhost")])
+        );
+        assert_eq!(
+            server.fdw_options(),
+            options(&[("level", "fdw"), ("wrapper", "yes")])
+        );
+
+        assert_eq!(ForeignServer::from_oid(server.oid()).name(), "test_server");
+        assert!(ForeignServer::from_name("no_such_server").is_none());
+    }
+
+    #[pg_test]
+    fn test_user_mapping() {
+        let server = create_server();
+        let userid = unsafe { pg_sys::GetUserId() };
+
+        assert_eq!(
+            server.user_mapping(userid).err(),
+            Some(UserMappingNotFound {
+                userid,
+                serverid: server.oid()
+            })
+        );
+
+        // falls back to the PUBLIC mapping
+        Spi::run("CREATE USER MAPPING FOR PUBLIC SERVER test_server OPTIONS (user 'public')");
+        let mapping = server.user_mapping(userid).unwrap();
+        assert!(mapping.is_public());
+        assert_eq!(mapping.userid(), pg_sys::InvalidOid);
+        assert_eq!(mapping.serverid(), server.oid());
+        assert_eq!(mapping.options(), options(&[("user", "public")]));
+
+        // but prefers the user's own
+        Spi::run(
+            "CREATE USER MAPPING FOR CURRENT_USER SERVER test_server OPTIONS (user 'me', password 'secret')",
+        );
+        let mapping = UserMapping::for_user_and_server(userid, server.oid()).unwrap();
+        assert!(!mapping.is_public());
+        assert_eq!(mapping.userid(), userid);
+        assert_eq!(
+            mapping.options(),
+            options(&[("user", "me"), ("password", "secret")])
+        );
+    }
+
+    #[pg_test]
+    fn test_foreign_table_merged_options() {
+        let server = create_server();
+        Spi::run(
+            "CREATE FOREIGN TABLE test_table (id int) SERVER test_server
+                 OPTIONS (level 'table', table_name 'remote_table')",
+        );
+        let relation = PgRelation::open_with_name_and_share_lock("test_table").unwrap();
+        let table = ForeignTable::from_relation(&relation);
+
+        assert_eq!(table.relid(), relation.oid());
+        assert_eq!(table.server().oid(), server.oid());
+        assert_eq!(
+            table.options(),
+            options(&[("level", "table"), ("table_name", "remote_table")])
+        );
+
+        // without a user mapping, the table's options override the server's, which override the
+        // wrapper's
+        assert!(table.user_mapping().is_err());
+        assert_eq!(
+            table.merged_options(),
+            options(&[
+                ("level", "table"),
+                ("wrapper", "yes"),
+                ("host", "localhost"),
+                ("table_name", "remote_table"),
+            ])
+        );
+
+        // a user mapping's options override the server's, but not the table's
+        Spi::run(
+            "CREATE USER MAPPING FOR CURRENT_USER SERVER test_server
+                 OPTIONS (host 'elsewhere', table_name 'ignored', user 'me')",
+        );
+        assert_eq!(
+            table.merged_options(),
+            options(&[
+                ("level", "table"),
+                ("wrapper", "yes"),
+                ("host", "elsewhere"),
+                ("table_name", "remote_table"),
+                ("user", "me"),
+            ])
+        );
+    }
+
+    #[pg_test(error = "\"not_foreign\" is not a foreign table")]
+    fn test_foreign_table_from_regular_table() {
+        Spi::run("CREATE TABLE not_foreign (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("not_foreign").unwrap();
+        ForeignTable::from_relation(&relation);
+    }
+}
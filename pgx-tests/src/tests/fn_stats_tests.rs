@@ -0,0 +1,190 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn_stats_functions!();
+
+    #[pg_extern(instrument)]
+    fn fn_stats_add(a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    #[pg_extern]
+    fn fn_stats_plain_add(a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    #[pg_extern(instrument, name = "fn_stats_renamed")]
+    fn fn_stats_named(s: &str) -> String {
+        s.repeat(1000)
+    }
+
+    #[pg_extern(instrument)]
+    fn fn_stats_series(n: i32) -> impl std::iter::Iterator<Item = i32> {
+        0..n
+    }
+
+    #[pg_extern(instrument)]
+    fn fn_stats_error() {
+        error!("fn_stats_error")
+    }
+
+    #[pg_test]
+    fn test_calls_are_counted() {
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        for i in 0..10 {
+            assert_eq!(
+                Spi::get_one::<i32>(&format!("SELECT tests.fn_stats_add({}, 1)", i)),
+                Some(i + 1)
+            );
+        }
+
+        let stats = pgx::fn_stats::get("fn_stats_add").expect("no stats for fn_stats_add");
+        assert_eq!(stats.calls, 10);
+        assert!(stats.mean_time() <= stats.total_time);
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT calls FROM tests.pgx_fn_stats() WHERE name = 'fn_stats_add'"
+            ),
+            Some(10)
+        );
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT total_ms >= mean_ms AND mean_ms >= 0 FROM tests.pgx_fn_stats() WHERE name = 'fn_stats_add'"
+            ),
+            Some(true)
+        );
+    }
+
+    #[pg_test]
+    fn test_stats_are_reset() {
+        Spi::run("SELECT tests.fn_stats_add(1, 2)");
+        assert!(pgx::fn_stats::get("fn_stats_add").is_some());
+
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        assert!(pgx::fn_stats::get("fn_stats_add").is_none());
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM tests.pgx_fn_stats()"),
+            Some(0)
+        );
+    }
+
+    #[pg_test]
+    fn test_stats_use_the_sql_name() {
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        Spi::run("SELECT tests.fn_stats_renamed('abc')");
+        Spi::run("SELECT tests.fn_stats_renamed('def')");
+
+        assert_eq!(
+            pgx::fn_stats::get("fn_stats_renamed").map(|s| s.calls),
+            Some(2)
+        );
+        assert!(pgx::fn_stats::get("fn_stats_named").is_none());
+    }
+
+    #[pg_test]
+    fn test_set_returning_function_is_counted_once() {
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM tests.fn_stats_series(100)"),
+            Some(100)
+        );
+
+        assert_eq!(
+            pgx::fn_stats::get("fn_stats_series").map(|s| s.calls),
+            Some(1)
+        );
+    }
+
+    #[pg_test]
+    fn test_uninstrumented_functions_are_not_counted() {
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        Spi::run("SELECT tests.fn_stats_add(1, 2)");
+
+        let names = pgx::fn_stats::snapshot()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["fn_stats_add"]);
+    }
+
+    #[pg_test]
+    fn test_errors_are_counted() {
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        let result = pg_try_subtransaction(|| Spi::run("SELECT tests.fn_stats_error()"));
+        assert!(result.sqlerrcode().is_some());
+        unsafe { result.unwrap_or(()) };
+
+        assert_eq!(
+            pgx::fn_stats::get("fn_stats_error").map(|s| s.calls),
+            Some(1)
+        );
+    }
+
+    #[cfg(feature = "pg13")]
+    #[pg_test]
+    fn test_allocations_are_measured() {
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        Spi::run("SELECT tests.fn_stats_renamed(repeat('x', 1000))");
+
+        let stats = pgx::fn_stats::get("fn_stats_renamed").unwrap();
+        assert!(stats.total_alloc_bytes.is_some());
+    }
+
+    #[cfg(not(feature = "pg13"))]
+    #[pg_test]
+    fn test_allocations_are_not_measured() {
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        Spi::run("SELECT tests.fn_stats_add(1, 2)");
+
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT total_alloc_bytes IS NULL FROM tests.pgx_fn_stats() WHERE name = 'fn_stats_add'"
+            ),
+            Some(true)
+        );
+    }
+
+    /// The time `instrument` adds to each call, as the difference between calling an
+    /// instrumented function and the same function without it, which is reported as an INFO
+    /// message.  Build with `--release` for a meaningful number
+    #[pg_test]
+    fn bench_instrument_overhead() {
+        const CALLS: u32 = 200_000;
+        let time = |function: &str| {
+            (0..3)
+                .map(|_| {
+                    let start = std::time::Instant::now();
+                    Spi::run(&format!(
+                        "SELECT count(tests.{}(i, 1)) FROM generate_series(1, {}) i",
+                        function, CALLS
+                    ));
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+
+        Spi::run("SELECT tests.pgx_fn_stats_reset()");
+        let plain = time("fn_stats_plain_add");
+        let instrumented = time("fn_stats_add");
+        let overhead_ns = (instrumented.as_nanos() as f64 - plain.as_nanos() as f64) / CALLS as f64;
+        info!(
+            "#[pg_extern(instrument)] adds {:.0}ns per call: {:?} for {} instrumented calls, {:?} without",
+            overhead_ns, instrumented, CALLS, plain
+        );
+
+        assert_eq!(
+            pgx::fn_stats::get("fn_stats_add").map(|s| s.calls),
+            Some(3 * CALLS as u64)
+        );
+        // far more than it should be, so it's only a gross regression that fails
+        assert!(overhead_ns < 10_000.0, "{:.0}ns per call", overhead_ns);
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[pg_extern]
+fn geo_point_roundtrip(point: PgPoint) -> PgPoint {
+    point
+}
+
+#[pg_extern]
+fn geo_box_from_corners(a: PgPoint, b: PgPoint) -> PgGeoBox {
+    PgGeoBox::new(a, b)
+}
+
+#[pg_extern]
+fn geo_box_contains(the_box: PgGeoBox, point: PgPoint) -> bool {
+    the_box.contains(&point)
+}
+
+#[pg_extern]
+fn geo_raw_box_roundtrip(the_box: pg_sys::BOX) -> pg_sys::BOX {
+    the_box
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_point_from_datum() {
+        let point = Spi::get_one::<PgPoint>("SELECT point(1.5, -2.25)");
+        assert_eq!(point, Some(PgPoint::new(1.5, -2.25)));
+    }
+
+    #[pg_test]
+    fn test_point_into_datum() {
+        let rc = Spi::get_one_with_args::<bool>(
+            "SELECT $1 ~= point(3, 4) AND $1[0] = 3 AND $1[1] = 4",
+            vec![(
+                PgBuiltInOids::POINTOID.oid(),
+                PgPoint::new(3.0, 4.0).into_datum(),
+            )],
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_point_roundtrip() {
+        let rc = Spi::get_one::<bool>(
+            "SELECT bool_and(geo_point_roundtrip(p) ~= p)
+               FROM (VALUES (point(0, 0)), (point(-1e300, 1e-300)), (point(1.5, -7))) v(p)",
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_box_from_datum() {
+        // Postgres puts the upper right corner first, whichever order the corners are given in
+        let the_box = Spi::get_one::<PgGeoBox>("SELECT box(point(3, 1), point(0, 4))").unwrap();
+        assert_eq!(the_box.high, PgPoint::new(3.0, 4.0));
+        assert_eq!(the_box.low, PgPoint::new(0.0, 1.0));
+        assert_eq!(the_box.width(), 3.0);
+        assert_eq!(the_box.height(), 3.0);
+    }
+
+    #[pg_test]
+    fn test_box_into_datum() {
+        let the_box = PgGeoBox::new(PgPoint::new(3.0, 1.0), PgPoint::new(0.0, 4.0));
+        let rc = Spi::get_one_with_args::<bool>(
+            "SELECT $1 ~= box '((0,1),(3,4))' AND area($1) = 9",
+            vec![(PgBuiltInOids::BOXOID.oid(), the_box.into_datum())],
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_box_functions_match_sql() {
+        let rc = Spi::get_one::<bool>(
+            "SELECT bool_and(geo_box_from_corners(a, b) ~= box(a, b)
+                         AND geo_box_contains(box(a, b), p) = (box(a, b) @> p)
+                         AND geo_raw_box_roundtrip(box(a, b)) ~= box(a, b))
+               FROM (VALUES (point(0, 0), point(2, 2), point(1, 1)),
+                            (point(2, 0), point(0, 2), point(2, 2)),
+                            (point(-1, -1), point(1, 1), point(1, 1.5))) v(a, b, p)",
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_raw_geo_types() {
+        let point = Spi::get_one::<pg_sys::Point>("SELECT point(5, 6)").unwrap();
+        assert_eq!((point.x, point.y), (5.0, 6.0));
+
+        let rc = Spi::get_one_with_args::<bool>(
+            "SELECT $1 ~= point(5, 6)",
+            vec![(PgBuiltInOids::POINTOID.oid(), point.into_datum())],
+        );
+        assert_eq!(rc, Some(true));
+    }
+}
@@ -1,6 +1,10 @@
 // Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
 // governed by the MIT license that can be found in the LICENSE file.
 
+use pgx::*;
+
+guc_settings_function!(guc_settings);
+
 #[cfg(any(test, feature = "pg_test"))]
 mod tests {
     #[allow(unused_imports)]
@@ -135,4 +139,96 @@ mod tests {
         Spi::run("SET test.enum = 'three'");
         assert_eq!(GUC.get(), TestEnum::Three);
     }
+
+    #[pg_test]
+    fn test_registry_iter() {
+        static INT: GucSetting<i32> = GucSetting::new(5);
+        static STRING: GucSetting<Option<&'static str>> = GucSetting::new(Some("boot"));
+
+        // set before it's defined, so it's a placeholder until then
+        Spi::run("SET test.settings.pending = 'set early'");
+
+        GucRegistry::define_int_guc(
+            "test.settings.int",
+            "an int",
+            "an int",
+            &INT,
+            0,
+            10,
+            GucContext::Suset,
+        );
+        GucRegistry::define_string_guc(
+            "test.settings.pending",
+            "a pending string",
+            "a pending string",
+            &STRING,
+            GucContext::Userset,
+        );
+        Spi::run("SET test.settings.int = 7");
+
+        let gucs = GucRegistry::iter()
+            .filter(|guc| guc.name.starts_with("test.settings."))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            gucs,
+            vec![
+                GucInfo {
+                    name: "test.settings.int".to_string(),
+                    setting: Some("7".to_string()),
+                    unit: None,
+                    context: GucContext::Suset,
+                    short_description: "an int".to_string(),
+                },
+                GucInfo {
+                    name: "test.settings.pending".to_string(),
+                    setting: Some("set early".to_string()),
+                    unit: None,
+                    context: GucContext::Userset,
+                    short_description: "a pending string".to_string(),
+                },
+            ]
+        );
+        assert_eq!(STRING.get().as_deref(), Some("set early"));
+    }
+
+    #[pg_test]
+    fn test_settings_function() {
+        static BOOL: GucSetting<bool> = GucSetting::new(false);
+        GucRegistry::define_bool_guc(
+            "test.settings.bool",
+            "a bool",
+            "a bool",
+            &BOOL,
+            GucContext::Userset,
+        );
+        Spi::run("SET test.settings.bool = on");
+
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT format('%s|%s|%s|%s', setting, unit IS NULL, context, short_desc)
+                   FROM tests.guc_settings() WHERE name = 'test.settings.bool'"
+            ),
+            Some("on|t|user|a bool".to_string())
+        );
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM tests.guc_settings() WHERE name NOT LIKE 'test.%'"
+            ),
+            Some(0)
+        );
+    }
+
+    #[pg_test]
+    fn test_validate_all_passes() {
+        GucRegistry::validate_all(&[|| Ok(()), || Ok(())]);
+    }
+
+    #[pg_test(error = "2 of 3 configuration checks failed")]
+    fn test_validate_all_reports_every_violation() {
+        GucRegistry::validate_all(&[
+            || Err("the first check failed".to_string()),
+            || Ok(()),
+            || Err("the third check failed".to_string()),
+        ]);
+    }
 }
@@ -0,0 +1,69 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[pg_extern]
+fn composite_field_by_name(row: PgHeapTupleHeader, field: &str) -> Option<String> {
+    row.get_by_name(field)
+}
+
+#[pg_extern]
+fn composite_field_by_index(row: PgHeapTupleHeader, i: i32) -> Option<String> {
+    row.get_by_index(i as usize)
+}
+
+#[pg_extern]
+fn composite_id(row: PgHeapTupleHeader) -> Option<i32> {
+    row.get_by_name("id")
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_composite_get_by_name() {
+        Spi::run("CREATE TYPE entity AS (id int, name text)");
+        let name = Spi::get_one::<String>(
+            "SELECT composite_field_by_name(ROW(42, 'hello')::entity, 'name')",
+        );
+        assert_eq!(name, Some("hello".to_string()));
+
+        let id = Spi::get_one::<i32>("SELECT composite_id(ROW(42, 'hello')::entity)");
+        assert_eq!(id, Some(42));
+    }
+
+    #[pg_test]
+    fn test_composite_get_null_field() {
+        Spi::run("CREATE TYPE entity AS (id int, name text)");
+        let name =
+            Spi::get_one::<String>("SELECT composite_field_by_name(ROW(42, NULL)::entity, 'name')");
+        assert_eq!(name, None);
+    }
+
+    #[pg_test]
+    fn test_composite_skips_dropped_fields() {
+        Spi::run("CREATE TABLE entity (id int, dropped text, name text)");
+        Spi::run("ALTER TABLE entity DROP COLUMN dropped");
+        Spi::run("INSERT INTO entity VALUES (42, 'hello')");
+        let name =
+            Spi::get_one::<String>("SELECT composite_field_by_name(entity, 'name') FROM entity");
+        assert_eq!(name, Some("hello".to_string()));
+    }
+
+    #[pg_test]
+    fn test_anonymous_record_get_by_index() {
+        let value =
+            Spi::get_one::<String>("SELECT composite_field_by_index(ROW(1, 'anonymous'), 1)");
+        assert_eq!(value, Some("anonymous".to_string()));
+    }
+
+    #[pg_test(error = "composite type has no field named 'missing'")]
+    fn test_composite_missing_field() {
+        Spi::get_one::<String>("SELECT composite_field_by_name(ROW(1, 'a'), 'missing')");
+    }
+}
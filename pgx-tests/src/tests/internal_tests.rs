@@ -0,0 +1,146 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[derive(Default)]
+struct MedianState {
+    values: Vec<i32>,
+}
+
+#[pg_extern]
+fn rust_median_state(
+    state: Option<Internal>,
+    value: Option<i32>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Internal {
+    let mut state = state.unwrap_or_else(|| {
+        let mut agg_context = std::ptr::null_mut();
+        if unsafe { pg_sys::AggCheckCallContext(fcinfo, &mut agg_context) } == 0 {
+            panic!("rust_median_state() called in non-aggregate context");
+        }
+        unsafe {
+            Internal::new_unbounded(MedianState::default(), PgMemoryContexts::For(agg_context))
+        }
+    });
+
+    if let Some(value) = value {
+        unsafe { state.get_mut::<MedianState>() }
+            .expect("aggregate state is not a MedianState")
+            .values
+            .push(value);
+    }
+    state
+}
+
+#[pg_extern]
+fn rust_median_final(mut state: Internal) -> Option<i32> {
+    let state =
+        unsafe { state.get_mut::<MedianState>() }.expect("aggregate state is not a MedianState");
+    state.values.sort_unstable();
+    state.values.get(state.values.len() / 2).cloned()
+}
+
+extension_sql! { r#"
+CREATE AGGREGATE rust_median(integer) (
+    sfunc = rust_median_state,
+    stype = internal,
+    finalfunc = rust_median_final
+);
+"#}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct TestObject {
+        did_drop: Arc<AtomicBool>,
+    }
+
+    impl Drop for TestObject {
+        fn drop(&mut self) {
+            self.did_drop.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[pg_test]
+    fn test_internal_aggregate_state() {
+        let median = Spi::get_one::<i32>(
+            "SELECT rust_median(x) FROM (VALUES (5), (1), (NULL), (4), (2), (3)) v(x)",
+        );
+        assert_eq!(median, Some(3));
+    }
+
+    #[pg_test]
+    fn test_internal_aggregate_state_no_rows() {
+        let median = Spi::get_one::<i32>("SELECT rust_median(x) FROM generate_series(1, 0) x");
+        assert_eq!(median, None);
+    }
+
+    #[pg_test]
+    fn test_internal_type_check() {
+        let context = PgMemoryContexts::CurrentMemoryContext;
+        let mut internal = Internal::new(42_i32, &context);
+        unsafe {
+            assert_eq!(internal.get::<i32>(), Some(&42));
+            assert!(internal.get::<i64>().is_none());
+            assert!(internal.get_mut::<String>().is_none());
+
+            *internal.get_mut::<i32>().unwrap() = 43;
+            assert_eq!(internal.get::<i32>(), Some(&43));
+        }
+    }
+
+    #[pg_test]
+    fn test_internal_round_trip_datum() {
+        let context = PgMemoryContexts::CurrentMemoryContext;
+        let internal = Internal::new("value".to_string(), &context);
+        let datum = internal.into_datum().expect("Internal datum was NULL");
+        let internal = unsafe { Internal::from_datum(datum, false, pg_sys::INTERNALOID) }
+            .expect("Internal was NULL");
+        assert_eq!(
+            unsafe { internal.get::<String>() }.map(|s| s.as_str()),
+            Some("value")
+        );
+    }
+
+    #[pg_test]
+    #[allow(deprecated)]
+    fn test_internal_box() {
+        let context = PgMemoryContexts::CurrentMemoryContext;
+        let datum = Internal::new(42_i32, &context).into_datum().unwrap();
+        let internal = unsafe { Internal::from_datum(datum, false, pg_sys::INTERNALOID) }.unwrap();
+        let boxed = unsafe { InternalBox::<pg_sys::Datum>::from_datum(datum, false, 0) }.unwrap();
+        assert_eq!(boxed.0.as_ptr() as void_mut_ptr, internal.as_ptr());
+    }
+
+    #[pg_test]
+    fn test_internal_drops_with_context() {
+        let did_drop = Arc::new(AtomicBool::new(false));
+
+        PgMemoryContexts::Transient {
+            parent: PgMemoryContexts::CurrentMemoryContext.value(),
+            name: "test",
+            min_context_size: 4096,
+            initial_block_size: 4096,
+            max_block_size: 4096,
+        }
+        .switch_to(|context| {
+            let context = PgMemoryContexts::For(context.value());
+            let internal = Internal::new(
+                TestObject {
+                    did_drop: did_drop.clone(),
+                },
+                &context,
+            );
+            assert!(unsafe { internal.get::<TestObject>() }.is_some());
+        });
+
+        assert!(did_drop.load(Ordering::SeqCst))
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[pg_extern]
+fn interval_roundtrip(interval: PgInterval) -> PgInterval {
+    interval
+}
+
+#[pg_extern]
+fn interval_seconds(interval: PgInterval) -> Option<f64> {
+    interval.to_duration().map(|d| d.as_secs_f64())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::time::Duration;
+
+    #[pg_test]
+    fn test_interval_from_datum() {
+        let interval =
+            Spi::get_one::<PgInterval>("SELECT interval '1 year 2 months 3 days 04:05:06.789'");
+        assert_eq!(
+            interval,
+            Some(PgInterval::new(
+                14,
+                3,
+                ((4 * 60 + 5) * 60 + 6) * 1_000_000 + 789_000
+            ))
+        );
+
+        // the parts are kept separately, and can have different signs
+        let interval = Spi::get_one::<PgInterval>("SELECT interval '1 month -1 day 36 hours'");
+        assert_eq!(
+            interval,
+            Some(PgInterval::new(1, -1, 36 * 3600 * 1_000_000))
+        );
+    }
+
+    #[pg_test]
+    fn test_interval_into_datum() {
+        let rc = Spi::get_one_with_args::<bool>(
+            "SELECT $1 = interval '2 months 10 days 00:00:01' AND extract(day FROM $1) = 10",
+            vec![(
+                PgBuiltInOids::INTERVALOID.oid(),
+                PgInterval::new(2, 10, 1_000_000).into_datum(),
+            )],
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_interval_roundtrip() {
+        let rc = Spi::get_one::<bool>(
+            "SELECT bool_and(interval_roundtrip(i)::text = i::text)
+               FROM (VALUES (interval '0'), (interval '-3 months 2 days -00:00:00.000001'),
+                            (interval '178000000 years'), (interval '1 day 24 hours')) v(i)",
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_interval_to_duration() {
+        assert_eq!(
+            PgInterval::new(0, 0, 1_500_000).to_duration(),
+            Some(Duration::from_millis(1500))
+        );
+
+        // a month is counted as 30 days, and a day as 24 hours
+        assert_eq!(
+            PgInterval::new(1, 1, 0).to_duration(),
+            Some(Duration::from_secs(31 * 86_400))
+        );
+        assert_eq!(
+            Spi::get_one::<f64>("SELECT interval_seconds(interval '1 month -1 day 1 hour')"),
+            Some((29 * 86_400 + 3600) as f64)
+        );
+
+        // but a Duration can't be negative
+        assert_eq!(PgInterval::new(0, 1, -86_400_000_001).to_duration(), None);
+        assert_eq!(
+            Spi::get_one::<f64>("SELECT interval_seconds(interval '-1 second')"),
+            None
+        );
+    }
+
+    #[pg_test]
+    fn test_interval_from_duration() {
+        let interval = PgInterval::from_duration(Duration::new(90_000, 1_999)).unwrap();
+        assert_eq!(interval, PgInterval::new(0, 0, 90_000_000_001));
+        assert_eq!(interval.to_duration(), Some(Duration::new(90_000, 1_000)));
+
+        assert_eq!(
+            PgInterval::from_duration(Duration::from_secs(u64::MAX)),
+            None
+        );
+    }
+}
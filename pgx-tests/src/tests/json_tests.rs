@@ -53,4 +53,91 @@ mod tests {
         assert_eq!(user.first_name, "Blah");
         assert_eq!(user.last_name, "McBlahFace");
     }
+
+    /// Our conversion of the one row of `table`, and Postgres' own `row_to_json()` of it
+    fn both_row_to_json(table: &str) -> (serde_json::Value, serde_json::Value) {
+        let relation = PgRelation::open_with_name_and_share_lock(table).unwrap();
+        let tupdesc = relation.tuple_desc();
+        let row = relation.scan_rows().next().expect("no rows");
+        let ours = unsafe { pgx::json::row_to_json(&tupdesc, &row) };
+
+        let theirs = Spi::get_one::<Json>(&format!("SELECT row_to_json(t) FROM {} t", table))
+            .unwrap()
+            .0;
+        (ours, theirs)
+    }
+
+    #[pg_test]
+    fn test_row_to_json() {
+        Spi::run("CREATE TYPE json_pair AS (a int, b text)");
+        Spi::run("CREATE DOMAIN json_positive AS int CHECK (VALUE > 0)");
+        Spi::run(
+            "CREATE TABLE json_row (
+                b bool, i2 int2, i4 int4, i8 int8, o oid, f4 float4, f8 float8, n numeric,
+                t text, vc varchar(10), d date, ip inet, u uuid, j json, jb jsonb,
+                ints int[], grid int[], texts text[], empty int[], pair json_pair,
+                pairs json_pair[], positive json_positive, nothing text
+            )",
+        );
+        Spi::run(
+            "INSERT INTO json_row VALUES (
+                true, -2, 4, 9223372036854775807, 42, 1.5, 0.1, 12.50,
+                'hello \"world\"', 'short', '2021-02-03', '192.168.0.1/24',
+                'a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11', '{\"x\": [1, null]}', '{\"y\": {\"z\": true}}',
+                ARRAY[1, NULL, 3], ARRAY[[1, 2], [3, 4], [5, 6]], ARRAY['a', NULL], '{}',
+                ROW(1, 'one'), ARRAY[ROW(2, 'two')::json_pair, NULL], 7, NULL
+            )",
+        );
+
+        let (ours, theirs) = both_row_to_json("json_row");
+        assert_eq!(ours, theirs);
+        assert_eq!(ours["grid"], serde_json::json!([[1, 2], [3, 4], [5, 6]]));
+        assert_eq!(ours["pair"], serde_json::json!({"a": 1, "b": "one"}));
+        assert_eq!(ours["nothing"], serde_json::Value::Null);
+    }
+
+    #[pg_test]
+    fn test_row_to_json_special_floats() {
+        Spi::run("CREATE TABLE json_floats (f8 float8, n numeric)");
+        Spi::run("INSERT INTO json_floats VALUES ('-Infinity', 'NaN')");
+
+        let (ours, theirs) = both_row_to_json("json_floats");
+        assert_eq!(ours, theirs);
+        assert_eq!(ours, serde_json::json!({"f8": "-Infinity", "n": "NaN"}));
+    }
+
+    #[pg_test]
+    fn test_row_to_json_dropped_column() {
+        Spi::run("CREATE TABLE json_dropped (id int, dropped text, value text)");
+        Spi::run("ALTER TABLE json_dropped DROP COLUMN dropped");
+        Spi::run("INSERT INTO json_dropped VALUES (1, 'one')");
+
+        let (ours, theirs) = both_row_to_json("json_dropped");
+        assert_eq!(ours, theirs);
+        assert_eq!(ours, serde_json::json!({"id": 1, "value": "one"}));
+    }
+
+    #[pg_test]
+    fn test_row_to_json_fallback() {
+        // types without a JSON equivalent are their text output, which differs from Postgres'
+        // ISO 8601 timestamps
+        Spi::run("SET LOCAL timezone TO 'UTC'");
+        Spi::run("CREATE TABLE json_fallback (ts timestamptz, iv interval, pt point)");
+        Spi::run("INSERT INTO json_fallback VALUES ('2021-02-03 04:05:06+00', '1 day', '(1,2)')");
+
+        let (ours, _) = both_row_to_json("json_fallback");
+        assert_eq!(
+            ours,
+            serde_json::json!({"ts": "2021-02-03 04:05:06+00", "iv": "1 day", "pt": "(1,2)"})
+        );
+    }
+
+    #[pg_test(error = "row_to_json() was given 1 values for a tuple descriptor with 2 attributes")]
+    fn test_row_to_json_wrong_number_of_values() {
+        let tupdesc = PgTupleDesc::builder()
+            .column_for::<i32>("a")
+            .column_for::<i32>("b")
+            .build();
+        unsafe { pgx::json::row_to_json(&tupdesc, &[1i32.into_datum()]) };
+    }
 }
@@ -0,0 +1,319 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn make_node(value: i32) -> *mut pg_sys::Value {
+        unsafe { pg_sys::makeInteger(value as _) }
+    }
+
+    fn node_values(list: &PgList<pg_sys::Value>) -> Vec<i64> {
+        list.iter_ptr()
+            .map(|value| unsafe { (*value).val.ival as i64 })
+            .collect()
+    }
+
+    #[pg_test]
+    fn test_insert_ptr() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        list.insert_ptr(0, make_node(2));
+        list.insert_ptr(0, make_node(0));
+        list.insert_ptr(1, make_node(1));
+        list.insert_ptr(3, make_node(4));
+        list.insert_ptr(3, make_node(3));
+
+        assert_eq!(list.len(), 5);
+        assert_eq!(node_values(&list), vec![0, 1, 2, 3, 4]);
+        assert_eq!(unsafe { (*list.tail().unwrap()).val.ival }, 4);
+    }
+
+    #[pg_test]
+    fn test_insert_at_len_is_push() {
+        let mut pushed = PgList::<pg_sys::Value>::new();
+        let mut inserted = PgList::<pg_sys::Value>::new();
+        for i in 0..5 {
+            pushed.push(make_node(i));
+            inserted.insert_ptr(inserted.len(), make_node(i));
+        }
+
+        assert_eq!(node_values(&pushed), node_values(&inserted));
+
+        // and the tail is still correct for a subsequent push
+        inserted.push(make_node(5));
+        assert_eq!(node_values(&inserted), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[pg_test]
+    fn test_pop() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        assert_eq!(list.len_i32(), 0);
+        assert!(list.pop().is_none());
+
+        list.push(make_node(0));
+        list.push(make_node(1));
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.len_i32(), 2);
+
+        assert_eq!(unsafe { (*list.pop().unwrap()).val.ival }, 1);
+        assert_eq!(unsafe { (*list.pop().unwrap()).val.ival }, 0);
+        assert!(list.is_empty());
+        assert!(list.tail().is_none());
+        assert!(list.pop().is_none());
+    }
+
+    #[pg_test]
+    fn test_insert_int() {
+        let mut list = PgList::<i32>::new();
+        list.insert_int(0, 3);
+        list.insert_int(0, 1);
+        list.insert_int(1, 2);
+        list.insert_int(3, 4);
+
+        assert_eq!(list.iter_int().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[pg_test]
+    fn test_insert_oid() {
+        let mut list = PgList::<pg_sys::Oid>::new();
+        list.insert_oid(0, pg_sys::TEXTOID);
+        list.insert_oid(0, pg_sys::INT4OID);
+        list.insert_oid(2, pg_sys::BOOLOID);
+        list.insert_oid(1, pg_sys::INT8OID);
+
+        assert_eq!(
+            list.iter_oid().collect::<Vec<_>>(),
+            vec![
+                pg_sys::INT4OID,
+                pg_sys::INT8OID,
+                pg_sys::TEXTOID,
+                pg_sys::BOOLOID
+            ]
+        );
+    }
+
+    #[pg_test(error = "insert position 2 is out of bounds for a List of length 1")]
+    fn test_insert_out_of_bounds() {
+        let mut list = PgList::<i32>::new();
+        list.insert_int(0, 1);
+        list.insert_int(2, 2);
+    }
+
+    #[pg_test]
+    fn test_replace_after_growing() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        list.push(make_node(0));
+        list.replace_ptr(0, make_node(100));
+
+        // enough elements that, on Postgres 13, the cell array is reallocated several times
+        for i in 1..100 {
+            list.push(make_node(i));
+        }
+        list.replace_ptr(0, make_node(-1));
+        list.replace_ptr(99, make_node(-99));
+
+        let values = node_values(&list);
+        assert_eq!(values.len(), 100);
+        assert_eq!(values[0], -1);
+        assert_eq!(values[1..99], (1..99).collect::<Vec<_>>()[..]);
+        assert_eq!(values[99], -99);
+    }
+
+    #[pg_test]
+    fn test_replace_after_insert() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        for i in 0..3 {
+            list.push(make_node(i));
+        }
+
+        // inserting at the front shifts every element over, so position 1 is now the old 0
+        list.insert_ptr(0, make_node(-1));
+        list.replace_ptr(1, make_node(42));
+        assert_eq!(node_values(&list), vec![-1, 42, 1, 2]);
+
+        list.pop();
+        list.replace_ptr(2, make_node(43));
+        assert_eq!(node_values(&list), vec![-1, 42, 43]);
+    }
+
+    #[pg_test]
+    fn test_replace_int_and_oid() {
+        let mut ints = PgList::<i32>::new();
+        let mut oids = PgList::<pg_sys::Oid>::new();
+        for i in 0..10 {
+            ints.insert_int(0, i);
+            oids.insert_oid(0, pg_sys::INT4OID);
+        }
+        ints.replace_int(0, 42);
+        ints.replace_int(9, 43);
+        oids.replace_oid(5, pg_sys::TEXTOID);
+
+        assert_eq!(
+            ints.iter_int().collect::<Vec<_>>(),
+            vec![42, 8, 7, 6, 5, 4, 3, 2, 1, 43]
+        );
+        assert_eq!(oids.get_oid(5), Some(pg_sys::TEXTOID));
+        assert_eq!(
+            oids.iter_oid()
+                .filter(|oid| *oid == pg_sys::INT4OID)
+                .count(),
+            9
+        );
+    }
+
+    #[pg_test(error = "index 3 is out of bounds for a List of length 3")]
+    fn test_replace_out_of_bounds() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        for i in 0..3 {
+            list.push(make_node(i));
+        }
+        list.replace_ptr(3, make_node(3));
+    }
+
+    #[pg_test(error = "index 0 is out of bounds for a List of length 0")]
+    fn test_replace_empty() {
+        let mut list = PgList::<i32>::new();
+        list.replace_int(0, 1);
+    }
+
+    #[pg_test(error = "PgList does not contain oids")]
+    fn test_replace_wrong_type() {
+        let mut list = PgList::<i32>::new();
+        list.insert_int(0, 1);
+        list.replace_oid(0, pg_sys::INT4OID);
+    }
+
+    #[pg_test]
+    fn test_copy_is_shallow() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        for i in 0..3 {
+            list.push(make_node(i));
+        }
+
+        let mut copy = list.copy();
+        assert_ne!(copy.as_ptr(), list.as_ptr());
+        assert_eq!(node_values(&copy), vec![0, 1, 2]);
+
+        // changing the copy's cells doesn't change the original
+        copy.push(make_node(3));
+        copy.pop();
+        copy.pop();
+        assert_eq!(node_values(&copy), vec![0, 1]);
+        assert_eq!(node_values(&list), vec![0, 1, 2]);
+
+        // but the elements are shared
+        assert_eq!(copy.head(), list.head());
+        unsafe {
+            (*copy.head().unwrap()).val.ival = 42;
+        }
+        assert_eq!(node_values(&list), vec![42, 1, 2]);
+    }
+
+    #[pg_test]
+    fn test_copy_deep() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        for i in 0..3 {
+            list.push(make_node(i));
+        }
+
+        let copy = list.copy_deep();
+        assert_eq!(node_values(&copy), vec![0, 1, 2]);
+
+        // the elements are copied too
+        assert_ne!(copy.head(), list.head());
+        unsafe {
+            (*copy.head().unwrap()).val.ival = 42;
+        }
+        assert_eq!(node_values(&copy), vec![42, 1, 2]);
+        assert_eq!(node_values(&list), vec![0, 1, 2]);
+    }
+
+    #[pg_test]
+    fn test_copy_int_and_empty_lists() {
+        let mut ints = PgList::<i32>::new();
+        ints.insert_int(0, 1);
+        ints.insert_int(1, 2);
+
+        assert_eq!(ints.copy().iter_int().collect::<Vec<_>>(), vec![1, 2]);
+
+        let empty = PgList::<pg_sys::Value>::new();
+        assert!(empty.copy().is_empty());
+        assert!(empty.copy_deep().is_empty());
+    }
+
+    #[pg_test]
+    fn test_split_at() {
+        let mut list = PgList::<pg_sys::Value>::new();
+        for i in 0..5 {
+            list.push(make_node(i));
+        }
+
+        let (head, tail) = list.split_at(2);
+        assert_eq!(node_values(&head), vec![0, 1]);
+        assert_eq!(node_values(&tail), vec![2, 3, 4]);
+        assert_eq!(node_values(&list), vec![0, 1, 2, 3, 4]);
+
+        // the halves have their own cells, but share the elements
+        assert_ne!(head.as_ptr(), list.as_ptr());
+        assert_eq!(tail.head(), list.get_ptr(2));
+
+        let (head, tail) = list.split_at(0);
+        assert!(head.is_empty());
+        assert_eq!(node_values(&tail), vec![0, 1, 2, 3, 4]);
+
+        for i in &[5, 6] {
+            let (head, tail) = list.split_at(*i);
+            assert_eq!(node_values(&head), vec![0, 1, 2, 3, 4]);
+            assert!(tail.is_empty());
+        }
+    }
+
+    #[pg_test]
+    fn test_split_int_and_empty_lists() {
+        let mut ints = PgList::<i32>::new();
+        for i in 0..4 {
+            ints.insert_int(ints.len(), i);
+        }
+
+        let (head, tail) = ints.split_at(1);
+        assert_eq!(head.iter_int().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(tail.iter_int().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let (head, tail) = PgList::<pg_sys::Value>::new().split_at(1);
+        assert!(head.is_empty());
+        assert!(tail.is_empty());
+    }
+
+    #[pg_test]
+    fn test_to_oid_set() {
+        let mut oids = PgList::<pg_sys::Oid>::new();
+        for oid in &[
+            pg_sys::INT4OID,
+            pg_sys::TEXTOID,
+            pg_sys::INT4OID,
+            pg_sys::BOOLOID,
+        ] {
+            oids.insert_oid(oids.len(), *oid);
+        }
+
+        let set = oids.to_oid_set();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&pg_sys::INT4OID));
+        assert!(set.contains(&pg_sys::TEXTOID));
+        assert!(set.contains(&pg_sys::BOOLOID));
+        assert!(!set.contains(&pg_sys::INT8OID));
+
+        assert!(PgList::<pg_sys::Oid>::new().to_oid_set().is_empty());
+    }
+
+    #[pg_test(error = "PgList does not contain oids")]
+    fn test_to_oid_set_of_ints() {
+        let mut ints = PgList::<i32>::new();
+        ints.insert_int(0, 1);
+        ints.to_oid_set();
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use std::time::Duration;
+
+fn lock_tests_oid(relname: &str) -> pg_sys::Oid {
+    unsafe { direct_function_call::<pg_sys::Oid>(pg_sys::to_regclass, vec![relname.into_datum()]) }
+        .unwrap_or_else(|| panic!("no such relation: {}", relname))
+}
+
+#[pg_extern]
+fn lock_tests_try_with_lock(relname: &str) -> bool {
+    PgRelation::try_with_lock(
+        lock_tests_oid(relname),
+        pg_sys::AccessShareLock as pg_sys::LOCKMODE,
+    )
+    .is_ok()
+}
+
+#[pg_extern]
+fn lock_tests_with_lock_timeout(relname: &str, timeout_ms: i64) -> bool {
+    PgRelation::with_lock_timeout(
+        lock_tests_oid(relname),
+        pg_sys::AccessShareLock as pg_sys::LOCKMODE,
+        Duration::from_millis(timeout_ms as u64),
+    )
+    .is_ok()
+}
+
+#[pg_extern]
+fn lock_tests_try_advisory_lock(key: i64) -> bool {
+    try_lock_object(
+        PgLockTag::advisory(key),
+        pg_sys::ExclusiveLock as pg_sys::LOCKMODE,
+    )
+    .is_ok()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::time::{Duration, Instant};
+
+    fn locks_held(oid: pg_sys::Oid, mode: &str) -> i64 {
+        Spi::get_one(&format!(
+            "SELECT count(*)
+               FROM pg_locks
+              WHERE relation = {}
+                AND pid = pg_backend_pid()
+                AND mode = '{}'",
+            oid, mode
+        ))
+        .unwrap()
+    }
+
+    fn advisory_locks_held(key: i64) -> i64 {
+        Spi::get_one(&format!(
+            "SELECT count(*)
+               FROM pg_locks
+              WHERE locktype = 'advisory'
+                AND pid = pg_backend_pid()
+                AND ((classid::bigint << 32) | objid::bigint) = {}
+                AND objsubid = 1",
+            key
+        ))
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn test_try_with_lock() {
+        Spi::run("CREATE TABLE try_with_lock (id int)");
+        let oid = super::lock_tests_oid("try_with_lock");
+
+        let relation =
+            PgRelation::try_with_lock(oid, pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE)
+                .expect("the relation is not locked by anyone else");
+        assert_eq!(relation.name(), "try_with_lock");
+        assert_eq!(locks_held(oid, "ShareUpdateExclusiveLock"), 1);
+
+        drop(relation);
+        assert_eq!(locks_held(oid, "ShareUpdateExclusiveLock"), 0);
+    }
+
+    #[pg_test]
+    fn test_with_lock_timeout() {
+        Spi::run("CREATE TABLE with_lock_timeout (id int)");
+        Spi::run("SET LOCAL lock_timeout = '5s'");
+        let oid = super::lock_tests_oid("with_lock_timeout");
+
+        let relation = PgRelation::with_lock_timeout(
+            oid,
+            pg_sys::ShareUpdateExclusiveLock as pg_sys::LOCKMODE,
+            Duration::from_millis(100),
+        )
+        .expect("the relation is not locked by anyone else");
+        assert_eq!(relation.name(), "with_lock_timeout");
+        assert_eq!(locks_held(oid, "ShareUpdateExclusiveLock"), 1);
+        assert_eq!(pgx::deadline::lock_timeout(), Some(Duration::from_secs(5)));
+
+        drop(relation);
+        assert_eq!(locks_held(oid, "ShareUpdateExclusiveLock"), 0);
+    }
+
+    #[pg_test(error = "could not open relation with OID 0")]
+    fn test_with_lock_timeout_missing_relation() {
+        let _ = PgRelation::with_lock_timeout(
+            pg_sys::InvalidOid,
+            pg_sys::AccessShareLock as pg_sys::LOCKMODE,
+            Duration::from_millis(100),
+        );
+    }
+
+    #[pg_test]
+    fn test_try_lock_object() {
+        let guard = try_lock_object(
+            PgLockTag::advisory(238),
+            pg_sys::ExclusiveLock as pg_sys::LOCKMODE,
+        )
+        .expect("the advisory lock is not held by anyone else");
+        assert_eq!(advisory_locks_held(238), 1);
+
+        drop(guard);
+        assert_eq!(advisory_locks_held(238), 0);
+    }
+
+    /// Another session holds the locks, and the try/timeout variants give up rather than wait
+    #[test]
+    fn test_locks_held_by_another_session() {
+        let (mut holder, _) =
+            pgx_tests::start_and_connect(crate::pg_test::postgresql_conf_options());
+        let (mut waiter, _) = pgx_tests::client();
+        holder
+            .batch_execute(
+                "DROP TABLE IF EXISTS lock_tests_busy; CREATE TABLE lock_tests_busy (id int);",
+            )
+            .expect("failed to create lock_tests_busy");
+
+        let mut transaction = holder.transaction().expect("failed to begin a transaction");
+        transaction
+            .batch_execute(
+                "LOCK TABLE lock_tests_busy IN ACCESS EXCLUSIVE MODE;
+                 SELECT pg_advisory_xact_lock(238);",
+            )
+            .expect("failed to take the locks");
+
+        let started = Instant::now();
+        let locked: bool = waiter
+            .query_one("SELECT lock_tests_try_with_lock('lock_tests_busy')", &[])
+            .expect("failed to try the lock")
+            .get(0);
+        assert!(!locked);
+        let locked: bool = waiter
+            .query_one("SELECT lock_tests_try_advisory_lock(238)", &[])
+            .expect("failed to try the advisory lock")
+            .get(0);
+        assert!(!locked);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let started = Instant::now();
+        let locked: bool = waiter
+            .query_one(
+                "SELECT lock_tests_with_lock_timeout('lock_tests_busy', 200)",
+                &[],
+            )
+            .expect("failed to wait for the lock")
+            .get(0);
+        assert!(!locked);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // the session's own lock_timeout is back, and it's still usable
+        let lock_timeout: String = waiter
+            .query_one("SHOW lock_timeout", &[])
+            .expect("failed to show lock_timeout")
+            .get(0);
+        assert_eq!(lock_timeout, "0");
+
+        transaction.rollback().expect("failed to release the locks");
+
+        let locked: bool = waiter
+            .query_one("SELECT lock_tests_try_with_lock('lock_tests_busy')", &[])
+            .expect("failed to try the lock")
+            .get(0);
+        assert!(locked);
+        let locked: bool = waiter
+            .query_one(
+                "SELECT lock_tests_with_lock_timeout('lock_tests_busy', 200)",
+                &[],
+            )
+            .expect("failed to wait for the lock")
+            .get(0);
+        assert!(locked);
+        let locked: bool = waiter
+            .query_one("SELECT lock_tests_try_advisory_lock(238)", &[])
+            .expect("failed to try the advisory lock")
+            .get(0);
+        assert!(locked);
+
+        holder
+            .batch_execute("DROP TABLE lock_tests_busy")
+            .expect("failed to drop lock_tests_busy");
+    }
+}
@@ -7,7 +7,9 @@ mod tests {
     use crate as pgx_tests;
 
     use pgx::*;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
     use std::sync::Arc;
 
     struct TestObject {
@@ -40,4 +42,320 @@ mod tests {
 
         assert!(did_drop.load(Ordering::SeqCst))
     }
+
+    /// Reads a palloc'd buffer when it's dropped
+    struct BufferReader {
+        buffer: *mut i64,
+        read: Arc<AtomicI64>,
+    }
+
+    impl Drop for BufferReader {
+        fn drop(&mut self) {
+            self.read.store(unsafe { *self.buffer }, Ordering::SeqCst);
+        }
+    }
+
+    /// A context that Postgres deletes along with `parent`
+    fn child_of(parent: &PgMemoryContexts) -> PgMemoryContexts {
+        PgMemoryContexts::For(unsafe {
+            pg_sys::AllocSetContextCreateExtended(
+                parent.value(),
+                b"child\0".as_ptr() as *const std::os::raw::c_char,
+                pg_sys::ALLOCSET_DEFAULT_MINSIZE as usize,
+                pg_sys::ALLOCSET_DEFAULT_INITSIZE as usize,
+                pg_sys::ALLOCSET_DEFAULT_MAXSIZE as usize,
+            )
+        })
+    }
+
+    #[pg_test]
+    fn test_leak_and_drop_reads_sibling_allocation() {
+        let read = Arc::new(AtomicI64::new(0));
+
+        let mut context = PgMemoryContexts::new("test");
+        let buffer = context.palloc_struct::<i64>();
+        unsafe { *buffer = 42 };
+        context.leak_and_drop_on_delete(BufferReader {
+            buffer,
+            read: read.clone(),
+        });
+
+        // callbacks run before the context's memory is released
+        drop(context);
+        assert_eq!(read.load(Ordering::SeqCst), 42);
+    }
+
+    #[pg_test]
+    fn test_leak_and_drop_in_child_context() {
+        let read = Arc::new(AtomicI64::new(0));
+
+        let mut parent = PgMemoryContexts::new("parent");
+        let buffer = parent.palloc_struct::<i64>();
+        unsafe { *buffer = 42 };
+        PgMemoryContexts::leak_and_drop_on_delete_in(
+            BufferReader {
+                buffer,
+                read: read.clone(),
+            },
+            &PgMemoryContexts::Of(buffer as void_ptr),
+            &mut child_of(&parent),
+        );
+
+        // the child, and its callback, go before the parent's memory is released
+        drop(parent);
+        assert_eq!(read.load(Ordering::SeqCst), 42);
+    }
+
+    #[cfg(debug_assertions)]
+    #[pg_test(
+        error = "the callback context must be the value's context, or one of its descendants, for the value to be dropped before its memory is free'd"
+    )]
+    fn test_leak_and_drop_in_parent_context() {
+        let read = Arc::new(AtomicI64::new(0));
+
+        // deleting the parent deletes the child, and its memory, before the parent's callbacks run
+        let mut parent = PgMemoryContexts::new("parent");
+        let mut child = child_of(&parent);
+        let buffer = child.palloc_struct::<i64>();
+        PgMemoryContexts::leak_and_drop_on_delete_in(
+            BufferReader { buffer, read },
+            &PgMemoryContexts::Of(buffer as void_ptr),
+            &mut parent,
+        );
+    }
+
+    /// Counts the calls of a reset callback, and whether it was dropped
+    #[derive(Clone, Default)]
+    struct CallbackCounter {
+        calls: Rc<Cell<u32>>,
+        dropped: Rc<Cell<bool>>,
+    }
+
+    impl CallbackCounter {
+        fn register(&self, context: &mut PgMemoryContexts) -> CallbackHandle {
+            let calls = self.calls.clone();
+            let drop_flag = DropFlag(self.dropped.clone());
+            context.register_reset_callback(move || {
+                let _drop_flag = drop_flag;
+                calls.set(calls.get() + 1);
+            })
+        }
+    }
+
+    struct DropFlag(Rc<Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[pg_test]
+    fn test_reset_callback() {
+        let mut context = PgMemoryContexts::new("callbacks");
+        let counter = CallbackCounter::default();
+        let handle = counter.register(&mut context);
+        assert!(handle.is_pending());
+
+        context.reset();
+        assert_eq!(counter.calls.get(), 1);
+        assert!(counter.dropped.get());
+        assert!(!handle.is_pending());
+
+        // it's only called once, and can't be cancelled once it has been
+        context.reset();
+        assert_eq!(counter.calls.get(), 1);
+        handle.cancel();
+    }
+
+    #[pg_test]
+    fn test_reset_callback_cancel() {
+        let mut context = PgMemoryContexts::new("callbacks");
+        let first = CallbackCounter::default();
+        let second = CallbackCounter::default();
+        let third = CallbackCounter::default();
+        let _first_handle = first.register(&mut context);
+        let second_handle = second.register(&mut context);
+        let _third_handle = third.register(&mut context);
+
+        // cancelling it drops it right away, without calling it
+        second_handle.cancel();
+        assert!(second.dropped.get());
+        assert_eq!(second.calls.get(), 0);
+
+        // and the callbacks either side of it are still called
+        drop(context);
+        assert_eq!(first.calls.get(), 1);
+        assert_eq!(second.calls.get(), 0);
+        assert_eq!(third.calls.get(), 1);
+    }
+
+    #[pg_test]
+    fn test_reset_callback_cancelled_by_another() {
+        let mut context = PgMemoryContexts::new("callbacks");
+        let counter = CallbackCounter::default();
+        let handle = counter.register(&mut context);
+
+        // Postgres calls the most recently registered callback first
+        context.register_reset_callback(move || handle.cancel());
+        context.reset();
+        assert_eq!(counter.calls.get(), 0);
+        assert!(counter.dropped.get());
+    }
+
+    #[pg_test]
+    fn test_is_descendant_of() {
+        PgMemoryContexts::Transient {
+            parent: PgMemoryContexts::CurrentMemoryContext.value(),
+            name: "test",
+            min_context_size: 4096,
+            initial_block_size: 4096,
+            max_block_size: 4096,
+        }
+        .switch_to(|context| {
+            let ptr = context.palloc(64);
+            let owner = PgMemoryContexts::Of(ptr);
+
+            assert!(owner.is_descendant_of(context));
+            assert!(owner.is_descendant_of(&PgMemoryContexts::TopMemoryContext));
+            assert!(!PgMemoryContexts::TopMemoryContext.is_descendant_of(context));
+            assert!(!owner.is_descendant_of(&PgMemoryContexts::CacheMemoryContext));
+        });
+    }
+
+    #[pg_test]
+    fn test_reset_each() {
+        let outer = PgMemoryContexts::CurrentMemoryContext.value();
+        let per_element = PgMemoryContexts::new("per-element");
+        let per_element_context = per_element.value();
+
+        let mut seen = Vec::new();
+        for i in reset_each(vec![1, 2, 3], per_element) {
+            // we're in a freshly reset "per-element" for each element
+            assert_eq!(
+                PgMemoryContexts::CurrentMemoryContext.value(),
+                per_element_context
+            );
+            assert!(unsafe { (*per_element_context).isReset });
+
+            let ptr = unsafe { pg_sys::palloc(64) };
+            assert_eq!(PgMemoryContexts::Of(ptr).value(), per_element_context);
+            assert!(!unsafe { (*per_element_context).isReset });
+
+            seen.push(i);
+        }
+
+        assert_eq!(seen, vec![1, 2, 3]);
+        assert_eq!(PgMemoryContexts::CurrentMemoryContext.value(), outer);
+    }
+
+    #[pg_test]
+    fn test_reset_each_early_exit() {
+        let outer = PgMemoryContexts::CurrentMemoryContext.value();
+
+        for i in reset_each(0.., PgMemoryContexts::new("per-element")) {
+            if i == 2 {
+                break;
+            }
+        }
+
+        // dropping the iterator restores the context we started in
+        assert_eq!(PgMemoryContexts::CurrentMemoryContext.value(), outer);
+    }
+
+    // palloc()'s limit is MaxAllocSize, which is one byte less than 1GB
+    const ONE_GB: usize = 1024 * 1024 * 1024;
+
+    #[pg_test(error = "invalid memory alloc request size 1073741824")]
+    fn test_palloc_over_max_alloc_size() {
+        PgMemoryContexts::CurrentMemoryContext.palloc(ONE_GB);
+    }
+
+    #[pg_test]
+    fn test_palloc_huge() {
+        PgMemoryContexts::CurrentMemoryContext.switch_to(|context| {
+            let buffer = context.palloc_huge::<u8>(ONE_GB + 1);
+            assert!(!buffer.is_null());
+
+            unsafe {
+                *buffer = 1;
+                *buffer.add(ONE_GB) = 2;
+                assert_eq!(*buffer, 1);
+                assert_eq!(*buffer.add(ONE_GB), 2);
+                pg_sys::pfree(buffer as void_mut_ptr);
+            }
+        });
+    }
+
+    #[pg_test]
+    fn test_palloc_huge0() {
+        let len = 1024 * 1024;
+        let buffer = PgMemoryContexts::CurrentMemoryContext.palloc_huge0::<u8>(len);
+        let buffer = unsafe { std::slice::from_raw_parts(buffer, len) };
+        assert!(buffer.iter().all(|b| *b == 0));
+    }
+
+    #[pg_test]
+    fn test_pstrndup() {
+        let mut context = PgMemoryContexts::new("pstrndup");
+        let copy = context.pstrndup(b"caf\xe9 au lait");
+        assert_eq!(
+            unsafe { std::ffi::CStr::from_ptr(copy) }.to_bytes(),
+            b"caf\xe9 au lait"
+        );
+        assert!(PgMemoryContexts::Of(copy as void_ptr).is_descendant_of(&context));
+
+        let empty = context.pstrndup(b"");
+        assert_eq!(unsafe { *empty }, 0);
+    }
+
+    #[pg_test]
+    fn test_switch_to_mut() {
+        let mut names = Vec::new();
+        let mut calls = 0;
+        let last = PgMemoryContexts::TopTransactionContext.switch_to_mut(|context| {
+            calls += 1;
+            for name in &["a", "b", "c"] {
+                names.push(context.pstrdup(name));
+            }
+            names.len()
+        });
+
+        assert_eq!(calls, 1);
+        assert_eq!(last, 3);
+        for name in names {
+            assert!(PgMemoryContexts::Of(name as void_ptr)
+                .is_descendant_of(&PgMemoryContexts::TopTransactionContext));
+        }
+    }
+
+    #[pg_test]
+    fn test_switch_to_mut_restores_context_on_panic() {
+        /// Records `CurrentMemoryContext` when it's dropped, as the panic unwinds past it
+        struct ContextOnUnwind(Rc<Cell<pg_sys::MemoryContext>>);
+
+        impl Drop for ContextOnUnwind {
+            fn drop(&mut self) {
+                self.0.set(unsafe { pg_sys::CurrentMemoryContext });
+            }
+        }
+
+        let before = unsafe { pg_sys::CurrentMemoryContext };
+        let on_unwind = Rc::new(Cell::new(std::ptr::null_mut()));
+        let mut allocated = 0;
+
+        let result = pg_try(std::panic::AssertUnwindSafe(|| {
+            let _recorder = ContextOnUnwind(Rc::clone(&on_unwind));
+            PgMemoryContexts::TopTransactionContext.switch_to_mut(|context| {
+                context.palloc(16);
+                allocated += 1;
+                panic!("panicked mid-way");
+            })
+        }));
+        unsafe { result.unwrap_or_else(|| ()) };
+
+        assert_eq!(allocated, 1);
+        assert_eq!(on_unwind.get(), before);
+        assert_eq!(unsafe { pg_sys::CurrentMemoryContext }, before);
+    }
 }
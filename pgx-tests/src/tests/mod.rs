@@ -3,30 +3,81 @@
 
 mod anyarray_tests;
 mod array_tests;
+mod atomics_tests;
+mod block_extern_tests;
+mod build_info_tests;
 mod bytea_tests;
+mod callback_struct_tests;
+mod cast_tests;
 mod cfg_tests;
+mod const_sql_tests;
+mod control_file_tests;
+mod copy_tests;
+mod copy_text_tests;
+mod corruption_tests;
+mod costing_tests;
+mod cstr_tests;
 mod datetime_tests;
+mod deadline_tests;
 mod default_arg_value_tests;
+mod deferred_trigger_tests;
+mod deps_tests;
 mod derive_pgtype_lifetimes;
 mod enum_type_tests;
+mod error_context_tests;
+mod extension_config_tests;
 mod fcinfo_tests;
+mod fdw_tests;
+mod fn_stats_tests;
+mod geo_tests;
 mod guc_tests;
+mod heap_tuple_header_tests;
 mod hooks_tests;
 mod inet_tests;
+mod internal_tests;
+mod interval_tests;
 mod json_tests;
+mod list_tests;
+mod lock_tests;
 mod log_tests;
 mod memcxt_tests;
+mod name_data_tests;
 mod name_tests;
+#[cfg(feature = "network")]
+mod network_tests;
+mod numeric_accumulator_tests;
 mod numeric_tests;
+mod partition_tests;
 mod pg_extern_args_tests;
 mod pg_try_tests;
+mod pgbox_tests;
+mod planner_tests;
 mod postgres_type_tests;
+mod quote_tests;
+mod recovery_tests;
+mod rel_tests;
+mod result_tests;
+mod retry_tests;
 mod schema_tests;
+mod session_state_tests;
+mod slot_tests;
+mod sortsupport_tests;
 mod spi_tests;
+mod sql_type_tests;
 mod srf_tests;
 mod struct_type_tests;
+mod systable_tests;
+mod tableam_tests;
+mod temp_tests;
+mod trigger_tests;
+mod tupdesc_tests;
+mod varbit_tests;
 mod variadic_tests;
+mod varlena_reader_tests;
+mod wal_tests;
+mod window_tests;
 mod xact_callback_tests;
+mod xact_tests;
 mod xid64_tests;
 
 pgx::pg_module_magic!();
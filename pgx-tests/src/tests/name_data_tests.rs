@@ -0,0 +1,64 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    /// A `NameData` holding `bytes`, with every byte after them set to `fill`
+    fn name_data(bytes: &[u8], fill: u8) -> pg_sys::NameData {
+        let mut name = pg_sys::NameData {
+            data: [fill as std::os::raw::c_char; pg_sys::NAMEDATALEN as usize],
+        };
+        for (i, b) in bytes.iter().enumerate() {
+            name.data[i] = *b as std::os::raw::c_char;
+        }
+        name
+    }
+
+    #[pg_test]
+    fn test_short_names() {
+        assert_eq!(name_data_to_str(&name_data(b"id\0", 0)), "id");
+        assert_eq!(name_data_to_str(&name_data(b"\0", 0)), "");
+        assert_eq!(name_data_to_str(&name_data("é\0".as_bytes(), 0)), "é");
+    }
+
+    #[pg_test]
+    fn test_garbage_after_the_nul() {
+        assert_eq!(name_data_to_str(&name_data(b"id\0", b'x')), "id");
+
+        // not even UTF-8
+        assert_eq!(name_data_to_str(&name_data(b"id\0", 0xff)), "id");
+    }
+
+    #[pg_test]
+    fn test_longest_name() {
+        let longest = "a".repeat(pg_sys::NAMEDATALEN as usize - 1);
+        let name = name_data(format!("{}\0", longest).as_bytes(), b'x');
+        assert_eq!(name_data_to_str(&name), longest);
+    }
+
+    #[pg_test]
+    fn test_name_without_a_nul() {
+        let name = name_data(b"", b'x');
+        assert_eq!(
+            name_data_to_str(&name),
+            "x".repeat(pg_sys::NAMEDATALEN as usize)
+        );
+    }
+
+    #[pg_test(error = "NameData is not valid UTF-8")]
+    fn test_invalid_utf8_name() {
+        name_data_to_str(&name_data(b"\xff\0", 0));
+    }
+
+    #[pg_test]
+    fn test_relname_round_trip() {
+        Spi::run("CREATE TABLE tests.short_name (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("tests.short_name").unwrap();
+        assert_eq!(relation.name(), "short_name");
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(feature = "network")]
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::ipnetwork::IpNetwork;
+    use pgx::*;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    #[pg_extern]
+    fn take_and_return_ip_addr(addr: IpAddr) -> IpAddr {
+        addr
+    }
+
+    #[pg_extern]
+    fn take_and_return_cidr(cidr: Cidr) -> Cidr {
+        cidr
+    }
+
+    #[pg_test]
+    fn test_ip_addr_from_datum() {
+        let v4 = Spi::get_one::<IpAddr>("SELECT '192.168.0.1'::inet");
+        assert_eq!(v4, Some(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))));
+
+        let v6 = Spi::get_one::<IpAddr>("SELECT '::1'::inet");
+        assert_eq!(v6, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    }
+
+    #[pg_test]
+    fn test_take_and_return_ip_addr() {
+        let rc = Spi::get_one::<bool>(
+            "SELECT tests.take_and_return_ip_addr('10.1.2.3') = '10.1.2.3'::inet \
+                AND tests.take_and_return_ip_addr('fe80::1') = 'fe80::1'::inet",
+        )
+        .expect("failed to get SPI result");
+        assert!(rc)
+    }
+
+    #[pg_test]
+    fn test_cidr_from_datum() {
+        let cidr = Spi::get_one::<Cidr>("SELECT '10.0.0.0/8'::cidr").expect("cidr was NULL");
+        assert_eq!(cidr.address(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)));
+        assert_eq!(cidr.netmask(), 8);
+
+        let cidr = Spi::get_one::<Cidr>("SELECT '2001:db8::/32'::cidr").expect("cidr was NULL");
+        assert_eq!(cidr.to_string(), "2001:db8::/32");
+    }
+
+    #[pg_test]
+    fn test_take_and_return_cidr() {
+        let rc = Spi::get_one::<bool>(
+            "SELECT tests.take_and_return_cidr('192.168.0.0/16') = '192.168.0.0/16'::cidr \
+                AND tests.take_and_return_cidr('2001:db8::/32') = '2001:db8::/32'::cidr",
+        )
+        .expect("failed to get SPI result");
+        assert!(rc)
+    }
+
+    #[pg_test]
+    fn test_cidr_into_datum() {
+        let cidr = Cidr::new(IpAddr::V4(Ipv4Addr::new(172, 16, 0, 0)), 12).unwrap();
+        let rc = Spi::get_one_with_args::<bool>(
+            "SELECT $1 = '172.16.0.0/12'::cidr",
+            vec![(PgBuiltInOids::CIDROID.oid(), cidr.into_datum())],
+        )
+        .expect("failed to get SPI result");
+        assert!(rc)
+    }
+
+    #[pg_test]
+    fn test_cidr_validation() {
+        let addr = IpAddr::V4(Ipv4Addr::new(10, 1, 0, 0));
+        assert!(Cidr::new(addr, 16).is_ok());
+        assert!(Cidr::new(addr, 8).is_err());
+        assert!(Cidr::new(addr, 33).is_err());
+        assert!(Cidr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 128).is_ok());
+    }
+
+    #[pg_extern]
+    fn take_and_return_ip_network(network: IpNetwork) -> IpNetwork {
+        network
+    }
+
+    #[pg_test]
+    fn test_ip_network_from_datum() {
+        let network =
+            Spi::get_one::<IpNetwork>("SELECT '192.168.0.1/24'::inet").expect("inet was NULL");
+        assert_eq!(network.ip(), IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)));
+        assert_eq!(network.prefix(), 24);
+
+        let network =
+            Spi::get_one::<IpNetwork>("SELECT '2001:db8::/32'::cidr").expect("cidr was NULL");
+        assert_eq!(network.to_string(), "2001:db8::/32");
+
+        let network = Spi::get_one::<IpNetwork>("SELECT '::1'::inet").expect("inet was NULL");
+        assert_eq!(network.prefix(), 128);
+    }
+
+    #[pg_test]
+    fn test_take_and_return_ip_network() {
+        let rc = Spi::get_one::<bool>(
+            "SELECT tests.take_and_return_ip_network('10.1.2.3/8') = '10.1.2.3/8'::inet \
+                AND tests.take_and_return_ip_network('fe80::1/64') = 'fe80::1/64'::inet",
+        )
+        .expect("failed to get SPI result");
+        assert!(rc)
+    }
+
+    #[pg_test(error = "inet value 10.1.2.3/8 has a netmask, so can't be converted to an IpAddr")]
+    fn test_ip_addr_with_netmask() {
+        Spi::get_one::<IpAddr>("SELECT '10.1.2.3/8'::inet");
+    }
+
+    #[pg_test]
+    fn test_inet_from_table() {
+        // stored with a short varlena header, so it's decoded in place
+        Spi::run("CREATE TABLE tests.stored_inet (id int, address inet)");
+        Spi::run("INSERT INTO tests.stored_inet VALUES (1, 'fe80::1/64')");
+        let network = Spi::get_one::<IpNetwork>("SELECT address FROM tests.stored_inet")
+            .expect("inet was NULL");
+        assert_eq!(network.to_string(), "fe80::1/64");
+    }
+}
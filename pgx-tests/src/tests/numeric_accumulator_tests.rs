@@ -0,0 +1,277 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+fn accumulator(state: Option<Internal>, fcinfo: pg_sys::FunctionCallInfo) -> Internal {
+    state.unwrap_or_else(|| {
+        let mut agg_context = std::ptr::null_mut();
+        if unsafe { pg_sys::AggCheckCallContext(fcinfo, &mut agg_context) } == 0 {
+            panic!("rust_numeric_accum() called in non-aggregate context");
+        }
+        unsafe {
+            Internal::new_unbounded(
+                NumericAccumulator::new_in(PgMemoryContexts::For(agg_context)),
+                PgMemoryContexts::For(agg_context),
+            )
+        }
+    })
+}
+
+#[pg_extern]
+fn rust_numeric_accum(
+    state: Option<Internal>,
+    value: Option<Numeric>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Internal {
+    let mut state = accumulator(state, fcinfo);
+    if let Some(value) = value {
+        unsafe { state.get_mut::<NumericAccumulator>() }
+            .expect("aggregate state is not a NumericAccumulator")
+            .add(&value);
+    }
+    state
+}
+
+#[pg_extern]
+fn rust_numeric_accum_inv(
+    state: Option<Internal>,
+    value: Option<Numeric>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Internal {
+    let mut state = accumulator(state, fcinfo);
+    if let Some(value) = value {
+        unsafe { state.get_mut::<NumericAccumulator>() }
+            .expect("aggregate state is not a NumericAccumulator")
+            .remove(&value);
+    }
+    state
+}
+
+fn final_state<'a>(state: &'a Internal) -> &'a NumericAccumulator {
+    unsafe { state.get::<NumericAccumulator>() }
+        .expect("aggregate state is not a NumericAccumulator")
+}
+
+#[pg_extern]
+fn rust_numeric_sum_final(state: Internal) -> Option<Numeric> {
+    final_state(&state).sum()
+}
+
+#[pg_extern]
+fn rust_numeric_avg_final(state: Internal) -> Option<Numeric> {
+    final_state(&state).avg()
+}
+
+#[pg_extern]
+fn rust_numeric_var_samp_final(state: Internal) -> Option<Numeric> {
+    final_state(&state).variance(true)
+}
+
+#[pg_extern]
+fn rust_numeric_var_pop_final(state: Internal) -> Option<Numeric> {
+    final_state(&state).variance(false)
+}
+
+#[pg_extern]
+fn rust_numeric_stddev_samp_final(state: Internal) -> Option<Numeric> {
+    final_state(&state).stddev(true)
+}
+
+#[pg_extern]
+fn rust_numeric_stddev_pop_final(state: Internal) -> Option<Numeric> {
+    final_state(&state).stddev(false)
+}
+
+extension_sql! { r#"
+CREATE AGGREGATE rust_numeric_sum(numeric) (
+    sfunc = rust_numeric_accum, stype = internal, finalfunc = rust_numeric_sum_final,
+    msfunc = rust_numeric_accum, minvfunc = rust_numeric_accum_inv, mstype = internal, mfinalfunc = rust_numeric_sum_final
+);
+CREATE AGGREGATE rust_numeric_avg(numeric) (
+    sfunc = rust_numeric_accum, stype = internal, finalfunc = rust_numeric_avg_final,
+    msfunc = rust_numeric_accum, minvfunc = rust_numeric_accum_inv, mstype = internal, mfinalfunc = rust_numeric_avg_final
+);
+CREATE AGGREGATE rust_numeric_var_samp(numeric) (
+    sfunc = rust_numeric_accum, stype = internal, finalfunc = rust_numeric_var_samp_final,
+    msfunc = rust_numeric_accum, minvfunc = rust_numeric_accum_inv, mstype = internal, mfinalfunc = rust_numeric_var_samp_final
+);
+CREATE AGGREGATE rust_numeric_var_pop(numeric) (
+    sfunc = rust_numeric_accum, stype = internal, finalfunc = rust_numeric_var_pop_final,
+    msfunc = rust_numeric_accum, minvfunc = rust_numeric_accum_inv, mstype = internal, mfinalfunc = rust_numeric_var_pop_final
+);
+CREATE AGGREGATE rust_numeric_stddev_samp(numeric) (
+    sfunc = rust_numeric_accum, stype = internal, finalfunc = rust_numeric_stddev_samp_final,
+    msfunc = rust_numeric_accum, minvfunc = rust_numeric_accum_inv, mstype = internal, mfinalfunc = rust_numeric_stddev_samp_final
+);
+CREATE AGGREGATE rust_numeric_stddev_pop(numeric) (
+    sfunc = rust_numeric_accum, stype = internal, finalfunc = rust_numeric_stddev_pop_final,
+    msfunc = rust_numeric_accum, minvfunc = rust_numeric_accum_inv, mstype = internal, mfinalfunc = rust_numeric_stddev_pop_final
+);
+"#}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    const AGGREGATES: &[&str] = &[
+        "sum",
+        "avg",
+        "var_samp",
+        "var_pop",
+        "stddev_samp",
+        "stddev_pop",
+    ];
+
+    /// A table of random `numeric`s: integers that do and don't fit in an `i64`, and decimals
+    /// with huge and tiny exponents
+    fn create_random_numbers(rows: usize) {
+        Spi::run("SELECT setseed(0.42)");
+        Spi::run("CREATE TABLE numbers (id serial, v numeric)");
+        Spi::run(&format!(
+            "INSERT INTO numbers (v)
+             SELECT CASE floor(random() * 4)
+                        WHEN 0 THEN (random() * 2e18 - 1e18)::bigint::numeric
+                        WHEN 1 THEN (random() * 1e30)::numeric(40, 0)
+                        ELSE (round((random() * 2 - 1)::numeric, 6)::text
+                              || 'e' || (floor(random() * 231) - 115)::int)::numeric
+                    END
+               FROM generate_series(1, {})",
+            rows
+        ));
+    }
+
+    /// Compare each of our aggregates with the builtin of the same name, with the `over` clause
+    fn assert_same_as_builtins(over: &str) {
+        for aggregate in AGGREGATES {
+            let mismatches = Spi::get_one::<i64>(&format!(
+                "SELECT count(*) FROM (
+                    SELECT {agg}(v) {over} AS expected, rust_numeric_{agg}(v) {over} AS actual
+                      FROM numbers
+                 ) results
+                 WHERE expected::text IS DISTINCT FROM actual::text",
+                agg = aggregate,
+                over = over
+            ));
+            assert_eq!(mismatches, Some(0), "{}(numeric) {}", aggregate, over);
+        }
+    }
+
+    #[pg_test]
+    fn test_random_numbers() {
+        create_random_numbers(1000);
+        assert_same_as_builtins("");
+    }
+
+    #[pg_test]
+    fn test_moving_aggregate() {
+        create_random_numbers(500);
+
+        // with frames that drop rows off the front, our remove() is used rather than restarting
+        assert_same_as_builtins("OVER (ORDER BY id ROWS BETWEEN 5 PRECEDING AND CURRENT ROW)");
+        assert_same_as_builtins("OVER (ORDER BY id ROWS BETWEEN 50 PRECEDING AND 10 FOLLOWING)");
+        assert_same_as_builtins("OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND 1 FOLLOWING)");
+    }
+
+    #[pg_test]
+    fn test_i128_overflow() {
+        // the sum of squares overflows an i128 after a couple of these
+        Spi::run("CREATE TABLE numbers (id serial, v numeric)");
+        Spi::run(
+            "INSERT INTO numbers (v)
+             SELECT CASE WHEN i % 3 = 0 THEN -9223372036854775808 ELSE 9223372036854775807 END
+               FROM generate_series(1, 100) i",
+        );
+        assert_same_as_builtins("");
+        assert_same_as_builtins("OVER (ORDER BY id ROWS BETWEEN 3 PRECEDING AND CURRENT ROW)");
+
+        let mut accumulator = NumericAccumulator::new();
+        for _ in 0..10 {
+            accumulator.add_i64(std::i64::MAX);
+        }
+        assert_eq!(
+            accumulator.sum().unwrap().0,
+            (std::i64::MAX as i128 * 10).to_string()
+        );
+        assert_eq!(accumulator.variance(true).unwrap().0, "0");
+    }
+
+    #[pg_test]
+    fn test_nan() {
+        Spi::run("CREATE TABLE numbers (id serial, v numeric)");
+        Spi::run("INSERT INTO numbers (v) VALUES (1), (2.5), ('NaN'), (4), (5), (NULL), (6)");
+        assert_same_as_builtins("");
+        assert_same_as_builtins("OVER (ORDER BY id ROWS BETWEEN 2 PRECEDING AND CURRENT ROW)");
+
+        let mut accumulator = NumericAccumulator::new();
+        accumulator.add(&Numeric("NaN".to_string()));
+        assert_eq!(accumulator.count(), 1);
+        assert_eq!(accumulator.variance(true).unwrap().0, "NaN");
+        accumulator.remove(&Numeric("NaN".to_string()));
+        assert!(accumulator.is_empty());
+        assert!(accumulator.sum().is_none());
+    }
+
+    #[pg_test]
+    fn test_too_few_values() {
+        let mut accumulator = NumericAccumulator::new();
+        assert!(accumulator.sum().is_none());
+        assert!(accumulator.avg().is_none());
+        assert!(accumulator.variance(false).is_none());
+
+        accumulator.add(&Numeric("1.50".to_string()));
+        assert_eq!(accumulator.sum().unwrap().0, "1.50");
+        assert_eq!(accumulator.variance(false).unwrap().0, "0");
+        assert!(accumulator.variance(true).is_none());
+        assert!(accumulator.stddev(true).is_none());
+    }
+
+    #[pg_test]
+    fn test_scale_after_remove() {
+        let mut accumulator = NumericAccumulator::new();
+        accumulator.add(&Numeric("1.000".to_string()));
+        accumulator.add(&Numeric("2.5".to_string()));
+        assert_eq!(accumulator.sum().unwrap().0, "3.500");
+
+        accumulator.remove(&Numeric("1.000".to_string()));
+        assert_eq!(accumulator.sum().unwrap().0, "2.5");
+    }
+
+    #[pg_test(error = "cannot remove a value that was never added to a NumericAccumulator")]
+    fn test_remove_from_empty() {
+        NumericAccumulator::new().remove_i64(42);
+    }
+
+    #[pg_extern]
+    fn i128_identity(value: i128) -> i128 {
+        value
+    }
+
+    #[pg_test]
+    fn test_i128_round_trip() {
+        for value in &[
+            "0",
+            "-1",
+            "170141183460469231731687303715884105727",
+            "-170141183460469231731687303715884105728",
+        ] {
+            assert_eq!(
+                Spi::get_one::<i128>(&format!("SELECT tests.i128_identity({})", value)),
+                Some(value.parse().unwrap())
+            );
+        }
+        assert_eq!(
+            Spi::get_one::<i128>("SELECT 2.5::numeric"),
+            Some(3),
+            "rounds like a cast to bigint"
+        );
+    }
+
+    #[pg_test(error = "numeric value out of range for i128")]
+    fn test_i128_out_of_range() {
+        Spi::get_one::<i128>("SELECT tests.i128_identity(170141183460469231731687303715884105728)");
+    }
+}
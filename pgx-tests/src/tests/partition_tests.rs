@@ -0,0 +1,218 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn names(relations: Vec<PgRelation>) -> Vec<String> {
+        relations
+            .iter()
+            .map(|relation| relation.name().to_string())
+            .collect()
+    }
+
+    fn create_measurements() {
+        Spi::run("CREATE TABLE measurements (id int, kind text) PARTITION BY RANGE (id)");
+        Spi::run(
+            "CREATE TABLE measurements_1 PARTITION OF measurements FOR VALUES FROM (1) TO (100)",
+        );
+        Spi::run(
+            "CREATE TABLE measurements_100 PARTITION OF measurements FOR VALUES FROM (100) TO (200)",
+        );
+        Spi::run(
+            "CREATE TABLE measurements_200 PARTITION OF measurements FOR VALUES FROM (200) TO (300)
+                 PARTITION BY LIST (kind)",
+        );
+        Spi::run(
+            "CREATE TABLE measurements_200_a PARTITION OF measurements_200 FOR VALUES IN ('a')",
+        );
+    }
+
+    #[pg_test]
+    fn test_partitions() {
+        create_measurements();
+        let parent = PgRelation::open_with_name_and_share_lock("measurements").unwrap();
+
+        assert_eq!(
+            names(parent.partitions(pg_sys::AccessShareLock as pg_sys::LOCKMODE)),
+            vec!["measurements_1", "measurements_100", "measurements_200"]
+        );
+        assert_eq!(
+            names(parent.all_leaf_partitions(pg_sys::AccessShareLock as pg_sys::LOCKMODE)),
+            vec!["measurements_1", "measurements_100", "measurements_200_a"]
+        );
+
+        // a leaf has no partitions of its own
+        let leaf = PgRelation::open_with_name_and_share_lock("measurements_1").unwrap();
+        assert!(leaf
+            .partitions(pg_sys::AccessShareLock as pg_sys::LOCKMODE)
+            .is_empty());
+    }
+
+    #[pg_test]
+    fn test_open_all_partitions() {
+        create_measurements();
+        let parent = PgRelation::open_with_name_and_share_lock("measurements").unwrap();
+
+        assert_eq!(
+            names(parent.open_all_partitions(pg_sys::AccessShareLock as pg_sys::LOCKMODE, false)),
+            vec![
+                "measurements_1",
+                "measurements_100",
+                "measurements_200",
+                "measurements_200_a"
+            ]
+        );
+        assert_eq!(
+            names(parent.open_all_partitions(pg_sys::AccessShareLock as pg_sys::LOCKMODE, true)),
+            vec!["measurements_1", "measurements_100", "measurements_200_a"]
+        );
+
+        let sub = PgRelation::open_with_name_and_share_lock("measurements_200").unwrap();
+        assert_eq!(
+            names(sub.open_all_partitions(pg_sys::AccessShareLock as pg_sys::LOCKMODE, false)),
+            vec!["measurements_200_a"]
+        );
+    }
+
+    #[pg_test]
+    fn test_open_all_partitions_of_unpartitioned_tables() {
+        Spi::run("CREATE TABLE inherited_parent (id int)");
+        Spi::run("CREATE TABLE inherited_child () INHERITS (inherited_parent)");
+
+        let parent = PgRelation::open_with_name_and_share_lock("inherited_parent").unwrap();
+        assert!(parent
+            .open_all_partitions(pg_sys::AccessShareLock as pg_sys::LOCKMODE, false)
+            .is_empty());
+    }
+
+    #[pg_test]
+    fn test_partition_text() {
+        create_measurements();
+
+        let parent = PgRelation::open_with_name_and_share_lock("measurements").unwrap();
+        assert_eq!(parent.partition_key_text(), Some("RANGE (id)".to_string()));
+        assert_eq!(parent.partition_bounds_text(), None);
+
+        let sub = PgRelation::open_with_name_and_share_lock("measurements_200").unwrap();
+        assert_eq!(sub.partition_key_text(), Some("LIST (kind)".to_string()));
+        assert_eq!(
+            sub.partition_bounds_text(),
+            Some("FOR VALUES FROM (200) TO (300)".to_string())
+        );
+
+        let leaf = PgRelation::open_with_name_and_share_lock("measurements_200_a").unwrap();
+        assert_eq!(leaf.partition_key_text(), None);
+        assert_eq!(
+            leaf.partition_bounds_text(),
+            Some("FOR VALUES IN ('a')".to_string())
+        );
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    fn route(router: &mut PartitionRouter, id: i32, kind: &str) -> String {
+        match unsafe { router.route(&[id.into_datum(), kind.into_datum()]) } {
+            PartitionRoute::Partition(partition) => partition.name().to_string(),
+            PartitionRoute::Default(partition) => format!("default {}", partition.name()),
+            PartitionRoute::NoPartition => "none".to_string(),
+        }
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    fn inserted_into(id: i32, kind: &str) -> String {
+        Spi::run(&format!(
+            "INSERT INTO measurements VALUES ({}, '{}')",
+            id, kind
+        ));
+        Spi::get_one::<&str>(&format!(
+            "SELECT tableoid::regclass::text FROM measurements WHERE id = {} AND kind = '{}'",
+            id, kind
+        ))
+        .unwrap()
+        .to_string()
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test]
+    fn test_partition_router() {
+        create_measurements();
+        Spi::run("CREATE TABLE measurements_200_other PARTITION OF measurements_200 DEFAULT");
+
+        let parent = PgRelation::open_with_name_and_share_lock("measurements").unwrap();
+        assert_eq!(
+            PgRelation::open_with_name_and_share_lock("measurements_200_other")
+                .unwrap()
+                .partition_bounds_text(),
+            Some("DEFAULT".to_string())
+        );
+
+        let mut router = PartitionRouter::new(&parent);
+        let rows = vec![
+            // the lower bound is inclusive, and the upper bound exclusive
+            (1, "a", "measurements_1"),
+            (99, "a", "measurements_1"),
+            (100, "a", "measurements_100"),
+            (199, "a", "measurements_100"),
+            // through the sub-partitioned table
+            (200, "a", "measurements_200_a"),
+            (299, "b", "default measurements_200_other"),
+        ];
+        for (id, kind, expected) in rows {
+            assert_eq!(route(&mut router, id, kind), expected);
+
+            // and INSERT puts it in the same place
+            assert_eq!(
+                inserted_into(id, kind),
+                expected.trim_start_matches("default ")
+            );
+        }
+
+        // below all the bounds, and above them
+        assert_eq!(route(&mut router, 0, "a"), "none");
+        assert_eq!(route(&mut router, 300, "a"), "none");
+
+        // and the router still works after a row with no partition
+        assert_eq!(route(&mut router, 150, "a"), "measurements_100");
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test]
+    fn test_partition_router_no_partition_in_sub_partition() {
+        create_measurements();
+        let parent = PgRelation::open_with_name_and_share_lock("measurements").unwrap();
+        let mut router = PartitionRouter::new(&parent);
+
+        // the sub-partitioned table is opened while routing a row that's rolled back, and is
+        // still usable for the rows after it
+        assert_eq!(route(&mut router, 250, "b"), "none");
+        assert_eq!(route(&mut router, 250, "a"), "measurements_200_a");
+        assert_eq!(route(&mut router, 251, "b"), "none");
+        assert_eq!(route(&mut router, 251, "a"), "measurements_200_a");
+
+        // which closes the partitions it opened, including the one opened for the rolled back row
+        drop(router);
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test(error = "no partition of relation \"measurements\" found for row")]
+    fn test_partition_router_agrees_with_insert() {
+        create_measurements();
+        let parent = PgRelation::open_with_name_and_share_lock("measurements").unwrap();
+        let mut router = PartitionRouter::new(&parent);
+        assert_eq!(route(&mut router, 0, "a"), "none");
+
+        Spi::run("INSERT INTO measurements VALUES (0, 'a')");
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test(error = "\"measurements_1\" is not a partitioned table")]
+    fn test_partition_router_not_partitioned() {
+        create_measurements();
+        let leaf = PgRelation::open_with_name_and_share_lock("measurements_1").unwrap();
+        PartitionRouter::new(&leaf);
+    }
+}
@@ -1,6 +1,91 @@
 // Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
 // governed by the MIT license that can be found in the LICENSE file.
 
+use pgx::*;
+
+/// Calls itself through SPI until `level` 3, raising an ERROR at `fail_at` and catching it with
+/// `pg_try_subtransaction()` at `catch_at`.  A level that catches the error returns `level * 100`,
+/// and each level above it adds 1
+#[pg_extern]
+fn guard_nested(level: i32, fail_at: i32, catch_at: i32) -> i32 {
+    let memory_context = unsafe { pg_sys::CurrentMemoryContext };
+    let spi_depth = pg_sys::guard::spi_depth();
+    let guard_depth = pg_sys::guard::guard_depth();
+
+    let try_func = || {
+        if level == fail_at {
+            // a Postgres ERROR, rather than a Rust panic
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_RAISE_EXCEPTION,
+                format!("failed at level {}", level),
+            )
+            .report(PgLogLevel::ERROR);
+        }
+
+        if level == 3 {
+            0
+        } else {
+            Spi::get_one::<i32>(&format!(
+                "SELECT guard_nested({}, {}, {})",
+                level + 1,
+                fail_at,
+                catch_at
+            ))
+            .expect("guard_nested() returned NULL")
+                + 1
+        }
+    };
+
+    let value = if level == catch_at {
+        unsafe { pg_try_subtransaction(try_func).unwrap_or(level * 100) }
+    } else {
+        pg_try(try_func).unwrap()
+    };
+
+    // the state we were in before the guard is restored, whether or not it caught an error
+    assert_eq!(unsafe { pg_sys::CurrentMemoryContext }, memory_context);
+    assert_eq!(pg_sys::guard::spi_depth(), spi_depth);
+    assert_eq!(pg_sys::guard::guard_depth(), guard_depth);
+
+    // and SPI still works
+    assert_eq!(Spi::get_one::<i32>("SELECT 1"), Some(1));
+
+    value
+}
+
+extension_sql! { r#"
+CREATE FUNCTION guard_nested_plpgsql(level integer, fail_at integer, catch_at integer)
+    RETURNS integer LANGUAGE plpgsql AS $$
+BEGIN
+    RETURN guard_nested(level, fail_at, catch_at);
+END;
+$$;
+"#}
+
+/// Catches an ERROR raised by `guard_nested()` from under a PL/pgSQL function, which has its own
+/// SPI connection on top of ours, and then uses our connection
+#[pg_extern]
+fn guard_through_plpgsql() -> i32 {
+    Spi::connect(|client| {
+        let result = pg_try_subtransaction(|| {
+            client
+                .select("SELECT guard_nested_plpgsql(2, 3, 0)", None, None)
+                .first()
+                .get_one::<i32>()
+        });
+        let value = unsafe { result.unwrap_or(Some(-1)) };
+        assert_eq!(pg_sys::guard::spi_depth(), 1);
+
+        let one = client
+            .select("SELECT 1", None, None)
+            .first()
+            .get_one::<i32>();
+        assert_eq!(one, Some(1));
+        Ok(value)
+    })
+    .expect("guard_through_plpgsql() returned NULL")
+}
+
 #[cfg(any(test, feature = "pg_test"))]
 mod tests {
     #[allow(unused_imports)]
@@ -65,4 +150,115 @@ mod tests {
     fn test_pg_try_unwrap_or_rethrow_with_error_in_rethrow() {
         pg_try(|| panic!("rethrow a panic")).unwrap_or_rethrow(|| panic!("panic in rethrow"));
     }
+
+    #[pg_test]
+    fn test_nested_guards_without_error() {
+        assert_eq!(Spi::get_one::<i32>("SELECT guard_nested(1, 0, 0)"), Some(2));
+    }
+
+    #[pg_test]
+    fn test_nested_guards_catch_at_each_level() {
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT guard_nested(1, 3, 3)"),
+            Some(302)
+        );
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT guard_nested(1, 3, 2)"),
+            Some(201)
+        );
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT guard_nested(1, 3, 1)"),
+            Some(100)
+        );
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT guard_nested(1, 2, 1)"),
+            Some(100)
+        );
+    }
+
+    #[pg_test]
+    fn test_nested_guards_catch_in_test() {
+        let memory_context = unsafe { pg_sys::CurrentMemoryContext };
+        let result = unsafe {
+            pg_try_subtransaction(|| Spi::get_one::<i32>("SELECT guard_nested(1, 3, 0)"))
+                .unwrap_or(Some(-1))
+        };
+        assert_eq!(result, Some(-1));
+        assert_eq!(unsafe { pg_sys::CurrentMemoryContext }, memory_context);
+        assert_eq!(pg_sys::guard::spi_depth(), 0);
+    }
+
+    #[pg_test(error = "failed at level 3")]
+    fn test_nested_guards_uncaught() {
+        Spi::get_one::<i32>("SELECT guard_nested(1, 3, 0)");
+    }
+
+    #[pg_test]
+    fn test_error_in_catch_handler() {
+        let memory_context = unsafe { pg_sys::CurrentMemoryContext };
+
+        // more times than Postgres' ERRORDATA_STACK_SIZE, to show that caught errors don't
+        // accumulate on its error stack
+        for _ in 0..10 {
+            let result = unsafe {
+                pg_try_subtransaction(|| {
+                    pg_try(|| Spi::get_one::<i32>("SELECT guard_nested(1, 3, 0)"))
+                        .unwrap_or_rethrow(|| Spi::run("SELECT 1 / 0"))
+                })
+                .unwrap_or(Some(-1))
+            };
+            assert_eq!(result, Some(-1));
+            assert_eq!(unsafe { pg_sys::CurrentMemoryContext }, memory_context);
+            assert_eq!(pg_sys::guard::spi_depth(), 0);
+        }
+
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT guard_nested(1, 3, 2)"),
+            Some(201)
+        );
+    }
+
+    #[pg_test]
+    fn test_catch_under_plpgsql() {
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT guard_nested_plpgsql(1, 3, 2)"),
+            Some(201)
+        );
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT guard_through_plpgsql()"),
+            Some(-1)
+        );
+        assert_eq!(pg_sys::guard::spi_depth(), 0);
+    }
+
+    #[pg_test]
+    fn test_subtransaction_releases_locks() {
+        Spi::run("CREATE TABLE tests.pg_try_locked (id int)");
+        let relid = Spi::get_one::<pg_sys::Oid>("SELECT 'tests.pg_try_locked'::regclass::oid")
+            .expect("no relid");
+
+        let result = pg_try_subtransaction(|| {
+            // CREATE TABLE took an AccessExclusiveLock, which isn't released until we commit
+            Spi::run("LOCK TABLE tests.pg_try_locked IN SHARE ROW EXCLUSIVE MODE");
+            Spi::run("SELECT 1 / 0");
+        });
+        assert_eq!(
+            result.sqlerrcode(),
+            Some(PgSqlErrorCode::ERRCODE_DIVISION_BY_ZERO as i32)
+        );
+        unsafe { result.unwrap_or(()) };
+
+        let locked = Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS(SELECT 1 FROM pg_locks \
+                WHERE relation = {} AND mode = 'ShareRowExclusiveLock' AND pid = pg_backend_pid())",
+            relid
+        ));
+        assert_eq!(locked, Some(false));
+    }
+
+    #[pg_test(error = "division by zero")]
+    fn test_error_in_catch_handler_is_rethrown() {
+        pg_try(|| Spi::get_one::<i32>("SELECT guard_nested(1, 3, 0)"))
+            .unwrap_or_rethrow(|| Spi::run("SELECT 1 / 0"));
+    }
 }
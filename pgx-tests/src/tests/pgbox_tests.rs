@@ -0,0 +1,145 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[pg_extern]
+fn pgbox_flinfo_oid(fcinfo: pg_sys::FunctionCallInfo) -> i64 {
+    let fcinfo = PgBox::from_pg(fcinfo);
+    let flinfo = unsafe { fcinfo.flinfo_ref() }.expect("flinfo is NULL");
+    flinfo.fn_oid as i64
+}
+
+#[pg_extern]
+fn pgbox_called_as_trigger(fcinfo: pg_sys::FunctionCallInfo) -> bool {
+    called_as_trigger(fcinfo)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_pgbox_project() {
+        let mut flinfo = PgBox::<pg_sys::FmgrInfo>::alloc0();
+
+        let (fn_oid, fn_nargs) = pgbox_project!(flinfo => fn_oid, fn_nargs);
+        *fn_oid = 42;
+        *fn_nargs = 2;
+
+        let fn_strict = pgbox_project!(flinfo => fn_strict);
+        *fn_strict = true;
+
+        assert_eq!(flinfo.fn_oid, 42);
+        assert_eq!(flinfo.fn_nargs, 2);
+        assert!(flinfo.fn_strict);
+    }
+
+    #[pg_test]
+    fn test_flinfo_projection() {
+        let matches = Spi::get_one::<bool>(
+            "SELECT pgbox_flinfo_oid() = 'pgbox_flinfo_oid'::regproc::oid::bigint",
+        );
+        assert_eq!(matches, Some(true));
+    }
+
+    #[pg_test]
+    fn test_context_projection() {
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT pgbox_called_as_trigger()"),
+            Some(false)
+        );
+    }
+
+    #[pg_test]
+    fn test_pgbox_chunk_space() {
+        let boxed = PgBox::<pg_sys::ItemPointerData>::alloc0();
+        assert!(boxed.chunk_space() >= std::mem::size_of::<pg_sys::ItemPointerData>());
+
+        let big = PgBox::from_pg(unsafe { pg_sys::palloc(1000) } as *mut u8);
+        let space = big.chunk_space();
+        assert!(space >= 1000);
+        assert_eq!(
+            space,
+            unsafe { pg_sys::GetMemoryChunkSpace(big.as_ptr() as void_mut_ptr) } as usize
+        );
+
+        let in_context =
+            PgBox::<pg_sys::FmgrInfo>::alloc0_in_context(PgMemoryContexts::TopTransactionContext);
+        assert!(in_context.chunk_space() >= std::mem::size_of::<pg_sys::FmgrInfo>());
+    }
+
+    #[pg_test(error = "PgBox::chunk_space() called on a NULL pointer")]
+    fn test_pgbox_chunk_space_null() {
+        PgBox::<i64>::null().chunk_space();
+    }
+
+    #[pg_test]
+    fn test_pgbox_chunk_space_not_a_chunk() {
+        // the middle of a zeroed chunk is MAXALIGN'd, but isn't preceded by a memory context
+        let chunk = unsafe { pg_sys::palloc0(64) } as *mut i64;
+        let interior = PgBox::from_pg(unsafe { chunk.add(2) });
+
+        let result = std::panic::catch_unwind(|| interior.chunk_space());
+        assert!(result.is_err());
+
+        let misaligned = PgBox::from_pg(unsafe { (chunk as *mut u8).add(1) });
+        let result = std::panic::catch_unwind(|| misaligned.chunk_space());
+        assert!(result.is_err());
+    }
+
+    #[pg_test]
+    fn test_pgbox_try_deref() {
+        let mut null = PgBox::<i64>::null();
+        assert!(null.try_deref().is_none());
+        assert!(null.try_deref_mut().is_none());
+
+        let mut boxed = PgBox::<i64>::alloc0();
+        *boxed.try_deref_mut().unwrap() = 42;
+        assert_eq!(boxed.try_deref(), Some(&42));
+        assert_eq!(*boxed, 42);
+    }
+
+    #[pg_test]
+    fn test_pgbox_clone_into_context() {
+        let name = PgMemoryContexts::TopTransactionContext.pstrdup("original");
+        let mut flinfo = PgBox::<pg_sys::FmgrInfo>::alloc0();
+        flinfo.fn_oid = 42;
+        flinfo.fn_nargs = 2;
+        flinfo.fn_extra = name as void_mut_ptr;
+
+        let mut context = PgMemoryContexts::new("clone_into_context");
+        let mut copy = flinfo.clone_into_context(&mut context);
+        assert_ne!(copy.as_ptr(), flinfo.as_ptr());
+        assert_eq!(copy.fn_oid, 42);
+        assert_eq!(copy.fn_nargs, 2);
+        assert!(unsafe {
+            pg_sys::MemoryContextContains(context.value(), copy.as_ptr() as void_mut_ptr)
+        });
+
+        // it's a copy...
+        copy.fn_oid = 43;
+        assert_eq!(flinfo.fn_oid, 42);
+
+        // ...but a shallow one
+        assert_eq!(copy.fn_extra, flinfo.fn_extra);
+
+        // and outlives the original
+        drop(flinfo);
+        assert_eq!(copy.fn_nargs, 2);
+    }
+
+    #[pg_test(error = "PgBox::clone_into_context() called on a NULL pointer")]
+    fn test_pgbox_clone_null_into_context() {
+        PgBox::<i64>::null().clone_into_context(&mut PgMemoryContexts::CurrentMemoryContext);
+    }
+
+    #[pg_test(error = "Attempt to dereference null pointer during Deref of PgBox")]
+    fn test_pgbox_deref_null() {
+        let null = PgBox::<i64>::null();
+        let _value: i64 = *null;
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::cell::RefCell;
+
+    /// What `record_rel_estimates()` saw for one relation
+    #[derive(Debug)]
+    struct RelEstimate {
+        name: String,
+        relid: pg_sys::Oid,
+        rows: f64,
+        width: i32,
+        restrictions: usize,
+        paths: usize,
+        rels_in_query: usize,
+    }
+
+    thread_local! {
+        static ESTIMATES: RefCell<Vec<RelEstimate>> = RefCell::new(Vec::new());
+    }
+
+    #[pg_guard]
+    unsafe extern "C" fn record_rel_estimates(
+        root: *mut pg_sys::PlannerInfo,
+        rel: *mut pg_sys::RelOptInfo,
+        rti: pg_sys::Index,
+        _rte: *mut pg_sys::RangeTblEntry,
+    ) {
+        let root = PgPlannerInfo::from_pg(root);
+        let rel = PgRelOptInfo::from_pg(rel);
+        let rte = root.rte(rti).expect("no range table entry");
+        assert_eq!(rel.relid(), rti);
+        assert_eq!(root.rel(rti).unwrap().as_ptr(), rel.as_ptr());
+
+        // every path has a cost
+        for path in rel.pathlist() {
+            assert!(path.total_cost >= path.startup_cost);
+        }
+
+        ESTIMATES.with(|estimates| {
+            estimates.borrow_mut().push(RelEstimate {
+                name: rte.name().unwrap().to_string(),
+                relid: rte.relid().unwrap(),
+                rows: rel.rows(),
+                width: rel.width(),
+                restrictions: rel.baserestrictinfo().len(),
+                paths: rel.pathlist().len(),
+                rels_in_query: root.rel_array().count(),
+            })
+        });
+
+        info!(
+            "{}: {} rows of {} bytes",
+            rte.name().unwrap(),
+            rel.rows(),
+            rel.width()
+        );
+    }
+
+    #[pg_test]
+    fn test_rel_estimates_for_three_way_join() {
+        Spi::run("CREATE TABLE planner_a (id int, b_id int)");
+        Spi::run("CREATE TABLE planner_b (id int, c_id int, label text)");
+        Spi::run("CREATE TABLE planner_c (id int)");
+        Spi::run("INSERT INTO planner_a SELECT i, i % 1000 FROM generate_series(1, 100) i");
+        Spi::run(
+            "INSERT INTO planner_b SELECT i, i % 10, 'b' || i FROM generate_series(1, 1000) i",
+        );
+        Spi::run("INSERT INTO planner_c SELECT i FROM generate_series(1, 10) i");
+        Spi::run("ANALYZE planner_a, planner_b, planner_c");
+
+        unsafe {
+            pg_sys::set_rel_pathlist_hook = Some(record_rel_estimates);
+        }
+        Spi::run(
+            "EXPLAIN SELECT *
+               FROM planner_a a, planner_b b, planner_c
+              WHERE a.b_id = b.id
+                AND b.c_id = planner_c.id
+                AND a.id <= 50",
+        );
+        unsafe {
+            pg_sys::set_rel_pathlist_hook = None;
+        }
+
+        let estimates = ESTIMATES.with(|estimates| estimates.replace(Vec::new()));
+        let names = estimates
+            .iter()
+            .map(|estimate| estimate.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b", "planner_c"]);
+
+        for (estimate, table) in estimates
+            .iter()
+            .zip(&["planner_a", "planner_b", "planner_c"])
+        {
+            let relation = PgRelation::open_with_name_and_share_lock(table).unwrap();
+            assert_eq!(estimate.relid, relation.oid());
+            assert_eq!(estimate.rels_in_query, 3);
+            assert!(estimate.width > 0);
+            assert!(estimate.paths > 0);
+        }
+
+        // only "a" has a restriction of its own, which halves its rows
+        let a = &estimates[0];
+        assert_eq!(a.restrictions, 1);
+        assert!(a.rows > 25.0 && a.rows < 75.0, "{:?}", a);
+
+        let b = &estimates[1];
+        assert_eq!(b.restrictions, 0);
+        assert_eq!(b.rows, 1000.0);
+
+        let c = &estimates[2];
+        assert_eq!(c.restrictions, 0);
+        assert_eq!(c.rows, 10.0);
+    }
+
+    #[pg_guard]
+    unsafe extern "C" fn check_planner_info(
+        root: *mut pg_sys::PlannerInfo,
+        _rel: *mut pg_sys::RelOptInfo,
+        _rti: pg_sys::Index,
+        _rte: *mut pg_sys::RangeTblEntry,
+    ) {
+        let root = PgPlannerInfo::from_pg(root);
+        assert_eq!(root.query().commandType, pg_sys::CmdType_CMD_SELECT);
+        assert_eq!(root.rel_array_size(), 1);
+        assert_eq!(root.rel_array().count(), 1);
+        assert!(root.rte(0).is_none());
+        assert!(root.rte(2).is_none());
+        assert!(root.rel(2).is_none());
+
+        let rte = root.rte(1).unwrap();
+        assert!(rte.is_relation());
+        assert_eq!(rte.alias(), Some("t"));
+        assert_eq!(rte.name(), Some("t"));
+
+        ESTIMATES.with(|estimates| {
+            estimates.borrow_mut().push(RelEstimate {
+                name: rte.name().unwrap().to_string(),
+                relid: rte.relid().unwrap(),
+                rows: 0.0,
+                width: 0,
+                restrictions: 0,
+                paths: 0,
+                rels_in_query: 1,
+            })
+        });
+    }
+
+    #[pg_test]
+    fn test_planner_info_views() {
+        Spi::run("CREATE TABLE planner_views (id int)");
+        unsafe {
+            pg_sys::set_rel_pathlist_hook = Some(check_planner_info);
+        }
+        Spi::run("EXPLAIN SELECT * FROM planner_views t");
+        unsafe {
+            pg_sys::set_rel_pathlist_hook = None;
+        }
+
+        // the hook's assertions would have raised an ERROR, so just make sure it ran
+        let estimates = ESTIMATES.with(|estimates| estimates.replace(Vec::new()));
+        assert_eq!(estimates.len(), 1);
+        assert_eq!(estimates[0].name, "t");
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::quote::*;
+    use pgx::*;
+
+    const CORPUS: &[&str] = &[
+        "simple",
+        "MixedCase",
+        "UPPER",
+        "with space",
+        "with\"quote",
+        "\"",
+        "it's",
+        "back\\slash",
+        "back\\\\slashes and 'quotes'",
+        "1leading_digit",
+        "_leading_underscore",
+        "trailing$",
+        "a$b",
+        "select",
+        "user",
+        "table",
+        "between",
+        "ünïcödé",
+        "日本語",
+        "emoji 🐘",
+        "tab\there",
+        "new\nline",
+        "",
+    ];
+
+    fn sql_function(function: &str, value: &str) -> String {
+        Spi::get_one_with_args::<String>(
+            &format!("SELECT {}($1)", function),
+            vec![(PgBuiltInOids::TEXTOID.oid(), value.into_datum())],
+        )
+        .expect("SQL quoting function returned NULL")
+    }
+
+    #[pg_test]
+    fn test_quote_identifier_matches_sql() {
+        for value in CORPUS {
+            assert_eq!(
+                quote_identifier(value),
+                sql_function("quote_ident", value),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_quote_literal_matches_sql() {
+        for value in CORPUS {
+            assert_eq!(
+                quote_literal(value),
+                sql_function("quote_literal", value),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_quoted_values_round_trip() {
+        for value in CORPUS {
+            let sql = format!(
+                "SELECT {} AS {}",
+                quote_literal(value),
+                quote_identifier(if value.is_empty() { "empty" } else { value })
+            );
+            assert_eq!(
+                Spi::get_one::<String>(&sql).as_deref(),
+                Some(*value),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("my_table"), "my_table");
+        assert_eq!(quote_identifier("MyTable"), "\"MyTable\"");
+        assert_eq!(quote_identifier("select"), "\"select\"");
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+    }
+
+    #[pg_test]
+    fn test_quote_qualified_identifier() {
+        assert_eq!(
+            quote_qualified_identifier(Some("public"), "my_table"),
+            "public.my_table"
+        );
+        assert_eq!(
+            quote_qualified_identifier(Some("My Schema"), "select"),
+            "\"My Schema\".\"select\""
+        );
+        assert_eq!(quote_qualified_identifier(None, "MyTable"), "\"MyTable\"");
+    }
+
+    #[pg_test]
+    fn test_quote_literal() {
+        assert_eq!(quote_literal("it's"), "'it''s'");
+        assert_eq!(quote_literal("C:\\"), "E'C:\\\\'");
+    }
+
+    #[pg_test]
+    fn test_quote_nullable() {
+        assert_eq!(quote_nullable(Some("it's")), "'it''s'");
+        assert_eq!(quote_nullable(None), "NULL");
+        assert_eq!(
+            Spi::get_one::<String>("SELECT quote_nullable(NULL::text)").as_deref(),
+            Some("NULL")
+        );
+    }
+
+    #[pg_test]
+    fn test_quote_literal_datum() {
+        unsafe {
+            assert_eq!(
+                quote_literal_datum(42i32.into_datum(), pg_sys::INT4OID),
+                "'42'"
+            );
+            assert_eq!(
+                quote_literal_datum(true.into_datum(), pg_sys::BOOLOID),
+                "'t'"
+            );
+            assert_eq!(
+                quote_literal_datum("it's".into_datum(), pg_sys::TEXTOID),
+                "'it''s'"
+            );
+            assert_eq!(quote_literal_datum(None, pg_sys::INT4OID), "NULL");
+        }
+
+        let array = Spi::get_one::<String>(&format!("SELECT {}::text[]::text", unsafe {
+            quote_literal_datum(vec!["a'b", "c\\d"].into_datum(), pg_sys::TEXTARRAYOID)
+        }));
+        assert_eq!(array.as_deref(), Some("{a'b,\"c\\\\d\"}"));
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[pg_extern]
+fn recovery_check_in_progress() -> bool {
+    recovery_in_progress()
+}
+
+#[pg_extern]
+fn recovery_check_operation(operation: &str, simulate_recovery: bool) {
+    if simulate_recovery {
+        override_recovery_in_progress(Some(true));
+    }
+    ensure_not_in_recovery(operation);
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_test]
+    fn test_recovery_state_on_primary() {
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT pg_is_in_recovery()"),
+            Some(recovery_in_progress())
+        );
+        assert!(!recovery_in_progress());
+        assert!(!hot_standby_active());
+
+        // doesn't raise an ERROR
+        ensure_not_in_recovery("a test");
+    }
+
+    #[pg_test]
+    fn test_backup_in_progress() {
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT pg_is_in_backup()"),
+            Some(backup_in_progress())
+        );
+    }
+
+    #[pg_test]
+    fn test_override_recovery_in_progress() {
+        override_recovery_in_progress(Some(true));
+        assert!(recovery_in_progress());
+
+        override_recovery_in_progress(Some(false));
+        assert!(!recovery_in_progress());
+
+        override_recovery_in_progress(None);
+        assert!(!recovery_in_progress());
+    }
+
+    #[pg_test(error = "cannot execute frobnicate during recovery")]
+    fn test_ensure_not_in_recovery() {
+        override_recovery_in_progress(Some(true));
+        ensure_not_in_recovery("frobnicate");
+    }
+
+    #[pg_test]
+    fn test_ensure_not_in_recovery_sqlstate() {
+        Spi::run(
+            "DO $$
+             BEGIN
+                PERFORM recovery_check_operation('frobnicate', true);
+                RAISE EXCEPTION 'ensure_not_in_recovery() did not raise an ERROR';
+             EXCEPTION WHEN read_only_sql_transaction THEN
+                NULL;
+             END $$;",
+        );
+    }
+
+    #[pg_test(error = "cannot execute COPY FROM during recovery")]
+    fn test_copy_in_during_recovery() {
+        Spi::run("CREATE TABLE recovery_copy (id int)");
+        override_recovery_in_progress(Some(true));
+        Spi::copy_in(
+            "recovery_copy",
+            &["id"],
+            vec![vec![Some("1".to_string())]].into_iter(),
+        );
+    }
+
+    /// Runs the real checks against a hot standby of a server with the `pgx_tests` extension
+    /// installed.  Run with `PGX_TEST_STANDBY_URL=postgres://... cargo pgx test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_recovery_on_standby() {
+        let url = std::env::var("PGX_TEST_STANDBY_URL")
+            .expect("PGX_TEST_STANDBY_URL must be set to a hot standby's connection string");
+        let mut client =
+            postgres::Client::connect(&url, postgres::NoTls).expect("failed to connect to standby");
+
+        let in_recovery: bool = client
+            .query_one("SELECT recovery_check_in_progress()", &[])
+            .expect("failed to check recovery state")
+            .get(0);
+        assert!(in_recovery);
+
+        let error = client
+            .execute("SELECT recovery_check_operation('frobnicate', false)", &[])
+            .expect_err("ensure_not_in_recovery() did not raise an ERROR on a standby");
+        assert_eq!(
+            error.code(),
+            Some(&postgres::error::SqlState::READ_ONLY_SQL_TRANSACTION)
+        );
+    }
+}
@@ -0,0 +1,576 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    #[pg_extern]
+    fn rel_heap_relation(index: PgRelation) -> Option<PgRelation> {
+        index.heap_relation()
+    }
+
+    #[pg_extern]
+    fn rel_name(relation: PgRelation) -> String {
+        relation.name().to_string()
+    }
+
+    #[pg_test]
+    fn test_scan_rows() {
+        Spi::run("CREATE TABLE scan_rows (id int, dropped int, value text)");
+        Spi::run("ALTER TABLE scan_rows DROP COLUMN dropped");
+        Spi::run("INSERT INTO scan_rows SELECT i, 'value ' || i FROM generate_series(1, 1000) i");
+        Spi::run("INSERT INTO scan_rows VALUES (NULL, NULL)");
+
+        let relation = PgRelation::open_with_name_and_share_lock("scan_rows").unwrap();
+        let rows = relation.scan_rows().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 1001);
+
+        let mut sum = 0;
+        for row in &rows {
+            // sized by relnatts, including the dropped attribute
+            assert_eq!(row.len(), 3);
+            assert!(row[1].is_none());
+
+            match row[0] {
+                Some(datum) => {
+                    let id = unsafe { i32::from_datum(datum, false, pg_sys::INT4OID) }.unwrap();
+                    let value =
+                        unsafe { String::from_datum(row[2].unwrap(), false, pg_sys::TEXTOID) };
+                    assert_eq!(value, Some(format!("value {}", id)));
+                    sum += id as i64;
+                }
+                None => assert!(row[2].is_none()),
+            }
+        }
+        assert_eq!(sum, 500500);
+    }
+
+    #[pg_test]
+    fn test_scan_rows_sees_own_writes() {
+        Spi::run("CREATE TABLE scan_rows_visibility (id int)");
+        Spi::run("INSERT INTO scan_rows_visibility VALUES (1), (2)");
+        Spi::run("DELETE FROM scan_rows_visibility WHERE id = 1");
+
+        let relation = PgRelation::open_with_name_and_share_lock("scan_rows_visibility").unwrap();
+        let ids = relation
+            .scan_rows()
+            .map(|row| unsafe { i32::from_datum(row[0].unwrap(), false, pg_sys::INT4OID) })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some(2)]);
+    }
+
+    #[pg_test(error = "relation \"scan_rows_view\" is not a table or materialized view")]
+    fn test_scan_rows_view() {
+        Spi::run("CREATE VIEW scan_rows_view AS SELECT 1");
+        let relation = PgRelation::open_with_name_and_share_lock("scan_rows_view").unwrap();
+        relation.scan_rows().count();
+    }
+
+    fn share_locks_held(oid: pg_sys::Oid) -> i64 {
+        Spi::get_one(&format!(
+            "SELECT count(*)
+               FROM pg_locks
+              WHERE relation = {}
+                AND pid = pg_backend_pid()
+                AND mode = 'AccessShareLock'",
+            oid
+        ))
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn test_open_no_lock() {
+        Spi::run("CREATE TABLE open_no_lock (id int)");
+
+        let locked = PgRelation::open_with_name_and_share_lock("open_no_lock").unwrap();
+        let oid = locked.oid();
+        assert_eq!(share_locks_held(oid), 1);
+
+        let relation = unsafe { PgRelation::open_no_lock(oid) };
+        assert_eq!(relation.name(), "open_no_lock");
+        assert_eq!(share_locks_held(oid), 1);
+
+        // closing it doesn't release the lock we were already holding...
+        drop(relation);
+        assert_eq!(share_locks_held(oid), 1);
+
+        // ...but closing the relation that took the lock does
+        drop(locked);
+        assert_eq!(share_locks_held(oid), 0);
+    }
+
+    #[pg_test(error = "could not open relation with OID 0")]
+    fn test_open_no_lock_missing_relation() {
+        unsafe { PgRelation::open_no_lock(pg_sys::InvalidOid) };
+    }
+
+    #[pg_test]
+    fn test_pg_extern_regclass_signature() {
+        // the ::regprocedure cast fails if the argument isn't a regclass
+        let rettype = Spi::get_one::<&str>(
+            "SELECT prorettype::regtype::text
+               FROM pg_proc
+              WHERE oid = 'tests.rel_heap_relation(regclass)'::regprocedure",
+        );
+        assert_eq!(rettype, Some("regclass"));
+    }
+
+    #[pg_test]
+    fn test_pg_extern_regclass_round_trip() {
+        Spi::run("CREATE TABLE regclass_heap (id int)");
+        Spi::run("CREATE INDEX regclass_idx ON regclass_heap (id)");
+
+        // regclass in, regclass out
+        let heap =
+            Spi::get_one::<&str>("SELECT tests.rel_heap_relation('regclass_idx'::regclass)::text");
+        assert_eq!(heap, Some("regclass_heap"));
+
+        // and a returned regclass can be read back as a PgRelation
+        let heap =
+            Spi::get_one::<PgRelation>("SELECT tests.rel_heap_relation('regclass_idx'::regclass)")
+                .expect("heap relation was NULL");
+        assert_eq!(heap.name(), "regclass_heap");
+
+        // a table isn't an index, so it has no heap relation
+        let is_null = Spi::get_one::<bool>(
+            "SELECT tests.rel_heap_relation('regclass_heap'::regclass) IS NULL",
+        );
+        assert_eq!(is_null, Some(true));
+
+        // and a PgRelation can be passed as an SPI argument
+        let name = Spi::get_one_with_args::<&str>(
+            "SELECT tests.rel_name($1)",
+            vec![(PgBuiltInOids::REGCLASSOID.oid(), heap.into_datum())],
+        );
+        assert_eq!(name, Some("regclass_heap"));
+    }
+
+    #[pg_test]
+    fn test_relation_from_spi() {
+        Spi::run("CREATE TABLE relation_from_spi (id int, name text)");
+
+        let relation = Spi::get_one::<PgRelation>("SELECT 'relation_from_spi'::regclass")
+            .expect("relation was NULL");
+
+        // SPI is finished, but the relation is still open, and locked
+        assert_eq!(relation.name(), "relation_from_spi");
+        assert_eq!(relation.tuple_desc().len(), 2);
+        assert_eq!(share_locks_held(relation.oid()), 1);
+
+        let attribute = relation.attribute_form(2).expect("no second attribute");
+        assert_eq!(attribute.atttypid, pg_sys::TEXTOID);
+
+        // the lock taken while reading the SPI result is kept until the end of the transaction,
+        // so that the relation can't change before it was opened again outside of SPI
+        let oid = relation.oid();
+        drop(relation);
+        assert_eq!(share_locks_held(oid), 1);
+    }
+
+    #[pg_test]
+    fn test_index_form() {
+        Spi::run("CREATE TABLE index_form (a int PRIMARY KEY, b int, c text)");
+        Spi::run("CREATE UNIQUE INDEX index_form_c_a ON index_form (c, a)");
+        Spi::run("CREATE INDEX index_form_expr ON index_form (b, lower(c))");
+
+        let primary = PgRelation::open_with_name_and_share_lock("index_form_pkey").unwrap();
+        let form = primary
+            .index_form()
+            .expect("no pg_index entry for the primary key");
+        assert!(form.indisprimary);
+        assert!(form.indisunique);
+        assert!(form.indisvalid);
+        assert_eq!(form.indrelid, primary.heap_relation().unwrap().oid());
+        assert_eq!(primary.index_key_attnums(), vec![1]);
+
+        let unique = PgRelation::open_with_name_and_share_lock("index_form_c_a").unwrap();
+        let form = unique.index_form().unwrap();
+        assert!(form.indisunique);
+        assert!(!form.indisprimary);
+        assert_eq!(unique.index_key_attnums(), vec![3, 1]);
+
+        // expression columns have an attribute number of zero
+        let expr = PgRelation::open_with_name_and_share_lock("index_form_expr").unwrap();
+        assert!(!expr.index_form().unwrap().indisunique);
+        assert_eq!(expr.index_key_attnums(), vec![2, 0]);
+    }
+
+    #[pg_test]
+    fn test_attribute_form() {
+        Spi::run(
+            "CREATE TABLE attribute_form (
+                id bigint NOT NULL,
+                dropped int,
+                name varchar(32) COLLATE \"C\",
+                tags text[]
+            )",
+        );
+        Spi::run("ALTER TABLE attribute_form DROP COLUMN dropped");
+
+        let relation = PgRelation::open_with_name_and_share_lock("attribute_form").unwrap();
+
+        let id = relation.attribute_form(1).unwrap();
+        assert_eq!(name_data_to_str(&id.attname), "id");
+        assert_eq!(id.atttypid, pg_sys::INT8OID);
+        assert!(id.attnotnull);
+        assert!(!id.attisdropped);
+
+        assert!(relation.attribute_form(2).unwrap().attisdropped);
+
+        let name = relation.attribute_form(3).unwrap();
+        assert_eq!(name.atttypid, pg_sys::VARCHAROID);
+        assert_eq!(name.atttypmod, 32 + pg_sys::VARHDRSZ as i32);
+        assert!(!name.attnotnull);
+        assert_eq!(
+            Some(name.attcollation),
+            Spi::get_one::<pg_sys::Oid>("SELECT oid FROM pg_collation WHERE collname = 'C'")
+        );
+
+        let tags = relation.attribute_form(4).unwrap();
+        assert_eq!(tags.atttypid, pg_sys::TEXTARRAYOID);
+        assert_eq!(tags.attndims, 1);
+
+        // system attributes, and those past the end, aren't in the tuple descriptor
+        assert!(relation.attribute_form(0).is_none());
+        assert!(relation.attribute_form(-1).is_none());
+        assert!(relation.attribute_form(5).is_none());
+    }
+
+    #[pg_test]
+    fn test_index_form_not_an_index() {
+        Spi::run("CREATE TABLE index_form_heap (a int)");
+
+        let heap = PgRelation::open_with_name_and_share_lock("index_form_heap").unwrap();
+        assert!(heap.index_form().is_none());
+        assert!(heap.index_key_attnums().is_empty());
+    }
+
+    #[pg_test]
+    fn test_index_expressions() {
+        Spi::run("CREATE TABLE index_exprs (a int, b int, c text)");
+        Spi::run("CREATE INDEX index_exprs_expr ON index_exprs (b, lower(c), (a + b))");
+        Spi::run(
+            "CREATE INDEX index_exprs_partial ON index_exprs (a) WHERE b > 0 AND c IS NOT NULL",
+        );
+
+        let expr = PgRelation::open_with_name_and_share_lock("index_exprs_expr").unwrap();
+        let expressions = expr.index_expressions();
+        assert_eq!(expressions.len(), 2);
+        assert!(is_a(
+            expressions.get_ptr(0).unwrap(),
+            pg_sys::NodeTag_T_FuncExpr
+        ));
+        assert!(is_a(
+            expressions.get_ptr(1).unwrap(),
+            pg_sys::NodeTag_T_OpExpr
+        ));
+        assert!(expr.index_predicate().is_empty());
+
+        let partial = PgRelation::open_with_name_and_share_lock("index_exprs_partial").unwrap();
+        assert!(partial.index_expressions().is_empty());
+
+        // the predicate's top-level AND is split into its conditions
+        let predicate = partial.index_predicate();
+        assert_eq!(predicate.len(), 2);
+        assert!(is_a(
+            predicate.get_ptr(0).unwrap(),
+            pg_sys::NodeTag_T_OpExpr
+        ));
+        assert!(is_a(
+            predicate.get_ptr(1).unwrap(),
+            pg_sys::NodeTag_T_NullTest
+        ));
+    }
+
+    #[pg_test(error = "\"index_exprs_heap\" is not an index")]
+    fn test_index_expressions_not_an_index() {
+        Spi::run("CREATE TABLE index_exprs_heap (a int)");
+
+        let heap = PgRelation::open_with_name_and_share_lock("index_exprs_heap").unwrap();
+        heap.index_expressions();
+    }
+
+    #[pg_test]
+    fn test_replica_identity() {
+        Spi::run("CREATE TABLE replica_identity (id int PRIMARY KEY, code text NOT NULL)");
+        Spi::run("CREATE UNIQUE INDEX replica_identity_code ON replica_identity (code)");
+
+        // the primary key, by default
+        let heap = PgRelation::open_with_name_and_share_lock("replica_identity").unwrap();
+        assert_eq!(heap.replica_identity_type(), ReplicaIdentity::Default);
+        let index = heap
+            .replica_identity_index()
+            .expect("no replica identity index");
+        assert_eq!(index.name(), "replica_identity_pkey");
+        assert_eq!(index.heap_relation().unwrap().oid(), heap.oid());
+        drop(index);
+        drop(heap);
+
+        Spi::run("ALTER TABLE replica_identity REPLICA IDENTITY USING INDEX replica_identity_code");
+        let heap = PgRelation::open_with_name_and_share_lock("replica_identity").unwrap();
+        assert_eq!(heap.replica_identity_type(), ReplicaIdentity::Index);
+        let index = heap
+            .replica_identity_index()
+            .expect("no replica identity index");
+        assert_eq!(index.name(), "replica_identity_code");
+        assert_eq!(index.index_key_attnums(), vec![2]);
+        drop(index);
+        drop(heap);
+
+        Spi::run("ALTER TABLE replica_identity REPLICA IDENTITY FULL");
+        let heap = PgRelation::open_with_name_and_share_lock("replica_identity").unwrap();
+        assert_eq!(heap.replica_identity_type(), ReplicaIdentity::Full);
+        assert!(heap.replica_identity_index().is_none());
+        drop(heap);
+
+        Spi::run("ALTER TABLE replica_identity REPLICA IDENTITY NOTHING");
+        let heap = PgRelation::open_with_name_and_share_lock("replica_identity").unwrap();
+        assert_eq!(heap.replica_identity_type(), ReplicaIdentity::Nothing);
+        assert!(heap.replica_identity_index().is_none());
+    }
+
+    #[pg_test]
+    fn test_replica_identity_without_primary_key() {
+        Spi::run("CREATE TABLE replica_identity_no_pkey (id int)");
+        Spi::run(
+            "CREATE UNIQUE INDEX replica_identity_no_pkey_id ON replica_identity_no_pkey (id)",
+        );
+
+        // a unique index isn't used unless it's chosen explicitly
+        let heap = PgRelation::open_with_name_and_share_lock("replica_identity_no_pkey").unwrap();
+        assert_eq!(heap.replica_identity_type(), ReplicaIdentity::Default);
+        assert!(heap.replica_identity_index().is_none());
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test]
+    fn test_index_key_attnums_include() {
+        Spi::run("CREATE TABLE index_form_include (a int, b int, c int)");
+        Spi::run("CREATE INDEX index_form_include_idx ON index_form_include (c) INCLUDE (a, b)");
+
+        let index = PgRelation::open_with_name_and_share_lock("index_form_include_idx").unwrap();
+        assert_eq!(index.index_form().unwrap().indnkeyatts, 1);
+        assert_eq!(index.index_key_attnums(), vec![3, 1, 2]);
+    }
+
+    fn lock_tuple_table() -> (PgRelation, pg_sys::ItemPointerData) {
+        Spi::run("CREATE TABLE lock_tuple (id int)");
+        Spi::run("INSERT INTO lock_tuple VALUES (1)");
+        let tid = Spi::get_one::<pg_sys::ItemPointerData>("SELECT ctid FROM lock_tuple").unwrap();
+        let relation = PgRelation::open_with_name_and_share_lock("lock_tuple").unwrap();
+        (relation, tid)
+    }
+
+    #[pg_test]
+    fn test_lock_tuple() {
+        let (relation, tid) = lock_tuple_table();
+        assert!(matches!(
+            relation.lock_tuple(tid, pg_sys::LockTupleMode_LockTupleExclusive),
+            LockTupleResult::Ok
+        ));
+
+        // locking a tuple sets its xmax to the locking transaction
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT xmax::text = (txid_current() % 4294967296)::text FROM lock_tuple"
+            ),
+            Some(true)
+        );
+
+        // and our own lock doesn't conflict with ourselves
+        assert!(matches!(
+            relation.try_lock_tuple(tid, pg_sys::LockTupleMode_LockTupleKeyShare),
+            LockTupleResult::Ok
+        ));
+        Spi::run("SELECT * FROM lock_tuple FOR UPDATE NOWAIT");
+    }
+
+    #[pg_test]
+    fn test_lock_deleted_tuple() {
+        let (relation, tid) = lock_tuple_table();
+        Spi::run("DELETE FROM lock_tuple");
+
+        // deleted by an earlier command of this transaction
+        assert!(matches!(
+            relation.lock_tuple(tid, pg_sys::LockTupleMode_LockTupleShare),
+            LockTupleResult::Invisible
+        ));
+    }
+
+    #[pg_test(error = "relation \"lock_tuple_view\" is not a table or materialized view")]
+    fn test_lock_tuple_view() {
+        let (_, tid) = lock_tuple_table();
+        Spi::run("CREATE VIEW lock_tuple_view AS SELECT * FROM lock_tuple");
+        let view = PgRelation::open_with_name_and_share_lock("lock_tuple_view").unwrap();
+        view.lock_tuple(tid, pg_sys::LockTupleMode_LockTupleExclusive);
+    }
+
+    #[cfg(debug_assertions)]
+    #[pg_test(
+        error = "relation \"invalidated\" was invalidated after it was opened; re-open it or call PgRelation::revalidate()"
+    )]
+    fn test_invalidated_relation() {
+        Spi::run("CREATE TABLE invalidated (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("invalidated").unwrap();
+
+        // changing the table's pg_class row invalidates its relcache entry
+        Spi::run("GRANT SELECT ON invalidated TO PUBLIC");
+        relation.name();
+    }
+
+    #[pg_test]
+    fn test_revalidate() {
+        Spi::run("CREATE TABLE revalidated (id int)");
+        let mut relation = PgRelation::open_with_name_and_share_lock("revalidated").unwrap();
+        assert_eq!(relation.reltuples(), None);
+
+        Spi::run("INSERT INTO revalidated SELECT generate_series(1, 100)");
+        Spi::run("ANALYZE revalidated");
+        relation.revalidate();
+        assert_eq!(
+            relation.reltuples().map(|reltuples| reltuples as i64),
+            Some(100)
+        );
+    }
+
+    #[pg_test]
+    fn test_revalidate_without_lock() {
+        Spi::run("CREATE TABLE revalidated (id int)");
+        let mut relation = unsafe { PgRelation::open_with_name("revalidated") }.unwrap();
+        unsafe {
+            pg_sys::CacheInvalidateRelcacheByRelid(relation.oid());
+            pg_sys::CommandCounterIncrement();
+        }
+
+        relation.revalidate();
+        assert_eq!(relation.name(), "revalidated");
+    }
+
+    #[pg_test]
+    fn test_number_of_blocks() {
+        Spi::run("CREATE TABLE sized (id int, value text)");
+        Spi::run("CREATE INDEX sized_idx ON sized (id)");
+        let relation = PgRelation::open_with_name_and_share_lock("sized").unwrap();
+        assert_eq!(relation.number_of_blocks(), 0);
+        assert_eq!(relation.size_in_bytes(), 0);
+
+        Spi::run("INSERT INTO sized SELECT i, repeat('x', 100) FROM generate_series(1, 10000) i");
+        assert!(relation.number_of_blocks() > 1);
+        assert_eq!(
+            relation.size_in_bytes() as i64,
+            Spi::get_one::<i64>("SELECT pg_relation_size('sized')").unwrap()
+        );
+
+        let index = PgRelation::open_with_name_and_share_lock("sized_idx").unwrap();
+        assert_eq!(
+            index.size_in_bytes() as i64,
+            Spi::get_one::<i64>("SELECT pg_relation_size('sized_idx')").unwrap()
+        );
+    }
+
+    #[pg_test]
+    fn test_number_of_blocks_in_missing_fork() {
+        Spi::run("CREATE TABLE sized (id int)");
+        Spi::run("INSERT INTO sized VALUES (1)");
+        let relation = PgRelation::open_with_name_and_share_lock("sized").unwrap();
+
+        // never vacuumed, so there's no visibility map
+        assert_eq!(
+            relation.number_of_blocks_in_fork(pg_sys::ForkNumber_VISIBILITYMAP_FORKNUM),
+            0
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT pg_relation_size('sized', 'vm')"),
+            Some(0)
+        );
+    }
+
+    #[pg_test(error = "relation \"sized_view\" has no storage")]
+    fn test_number_of_blocks_view() {
+        Spi::run("CREATE VIEW sized_view AS SELECT 1 AS id");
+        let view = PgRelation::open_with_name_and_share_lock("sized_view").unwrap();
+        view.number_of_blocks();
+    }
+
+    #[pg_test]
+    fn test_analyze() {
+        Spi::run("CREATE TABLE analyzed (id int)");
+        Spi::run("INSERT INTO analyzed SELECT generate_series(1, 100)");
+        let mut relation = PgRelation::open_with_name_and_share_lock("analyzed").unwrap();
+        assert_eq!(relation.reltuples(), None);
+
+        // runs in our transaction, and revalidates the relation
+        relation.analyze();
+        assert_eq!(
+            relation.reltuples().map(|reltuples| reltuples as i64),
+            Some(100)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM pg_stats WHERE tablename = 'analyzed' AND attname = 'id'"
+            ),
+            Some(1)
+        );
+    }
+
+    #[pg_test]
+    fn test_owner_oid() {
+        Spi::run("CREATE TABLE owned (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("owned").unwrap();
+        assert_eq!(relation.owner_oid(), unsafe { pg_sys::GetUserId() });
+        assert_eq!(
+            Spi::get_one::<pg_sys::Oid>("SELECT relowner FROM pg_class WHERE relname = 'owned'"),
+            Some(relation.owner_oid())
+        );
+
+        // we're a superuser
+        assert!(relation.is_owned_by_current_user());
+    }
+
+    #[pg_test]
+    fn test_is_owned_by_current_user() {
+        Spi::run("CREATE ROLE rel_owner NOLOGIN");
+        Spi::run("CREATE ROLE rel_owner_member NOLOGIN IN ROLE rel_owner");
+        Spi::run("CREATE ROLE rel_outsider NOLOGIN");
+        Spi::run("CREATE TABLE owned_by_role (id int)");
+        Spi::run("ALTER TABLE owned_by_role OWNER TO rel_owner");
+
+        let relation = PgRelation::open_with_name_and_share_lock("owned_by_role").unwrap();
+        assert_eq!(
+            Spi::get_one::<pg_sys::Oid>("SELECT 'rel_owner'::regrole::oid"),
+            Some(relation.owner_oid())
+        );
+
+        Spi::run("SET ROLE rel_owner");
+        assert!(relation.is_owned_by_current_user());
+
+        Spi::run("SET ROLE rel_owner_member");
+        assert!(relation.is_owned_by_current_user());
+
+        Spi::run("SET ROLE rel_outsider");
+        assert!(!relation.is_owned_by_current_user());
+
+        Spi::run("RESET ROLE");
+        assert!(relation.is_owned_by_current_user());
+    }
+
+    #[cfg(feature = "pg10")]
+    #[pg_test(error = "VACUUM cannot be executed from a function or multi-command string")]
+    fn test_vacuum_in_function() {
+        Spi::run("CREATE TABLE vacuumed (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("vacuumed").unwrap();
+        relation.vacuum(VacuumOptions::empty());
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test(error = "VACUUM cannot be executed from a function")]
+    fn test_vacuum_in_function() {
+        Spi::run("CREATE TABLE vacuumed (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("vacuumed").unwrap();
+        relation.vacuum(VacuumOptions::FREEZE | VacuumOptions::ANALYZE);
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DID_DROP: AtomicBool = AtomicBool::new(false);
+
+struct DropFlag;
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        DID_DROP.store(true, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug)]
+enum ExampleError {
+    TooBig(i32),
+    Negative(i32),
+}
+
+impl From<ExampleError> for ErrorReport {
+    fn from(e: ExampleError) -> Self {
+        match e {
+            ExampleError::TooBig(value) => ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_NUMERIC_VALUE_OUT_OF_RANGE,
+                format!("value {} is too big", value),
+            )
+            .with_hint("use a value less than 100"),
+            ExampleError::Negative(value) => ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                format!("value {} is negative", value),
+            ),
+        }
+    }
+}
+
+fn check_value(value: i32) -> Result<i32, ExampleError> {
+    if value >= 100 {
+        Err(ExampleError::TooBig(value))
+    } else if value < 0 {
+        Err(ExampleError::Negative(value))
+    } else {
+        Ok(value)
+    }
+}
+
+#[pg_extern]
+fn result_check_value(value: i32) -> Result<i32, ExampleError> {
+    let _flag = DropFlag;
+    check_value(value)
+}
+
+#[pg_extern]
+fn result_optional_value(value: i32) -> Result<Option<i32>, ExampleError> {
+    check_value(value).map(|value| if value == 0 { None } else { Some(value) })
+}
+
+#[pg_extern]
+fn result_parse_int(input: &str) -> Result<i32, std::num::ParseIntError> {
+    input.parse()
+}
+
+#[pg_extern]
+fn result_setof(count: i32) -> Result<impl std::iter::Iterator<Item = i32>, ExampleError> {
+    Ok(1..=check_value(count)?)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::DID_DROP;
+    use pgx::*;
+    use std::sync::atomic::Ordering;
+
+    #[pg_test]
+    fn test_result_ok() {
+        let value = Spi::get_one::<i32>("SELECT result_check_value(42)");
+        assert_eq!(value, Some(42));
+    }
+
+    #[pg_test(error = "value 142 is too big")]
+    fn test_result_err() {
+        Spi::get_one::<i32>("SELECT result_check_value(142)");
+    }
+
+    #[pg_test]
+    fn test_result_err_sqlstate() {
+        // the DO block only catches the SQLSTATE we expect.  Anything else aborts the test
+        Spi::run(
+            "DO $$
+             BEGIN
+                PERFORM result_check_value(142);
+                RAISE EXCEPTION 'result_check_value() did not raise an ERROR';
+             EXCEPTION WHEN numeric_value_out_of_range THEN
+                NULL;
+             END $$;",
+        );
+        Spi::run(
+            "DO $$
+             BEGIN
+                PERFORM result_check_value(-1);
+                RAISE EXCEPTION 'result_check_value() did not raise an ERROR';
+             EXCEPTION WHEN invalid_parameter_value THEN
+                NULL;
+             END $$;",
+        );
+    }
+
+    #[pg_test]
+    fn test_result_err_drops_locals() {
+        DID_DROP.store(false, Ordering::SeqCst);
+        Spi::run(
+            "DO $$
+             BEGIN
+                PERFORM result_check_value(142);
+             EXCEPTION WHEN numeric_value_out_of_range THEN
+                NULL;
+             END $$;",
+        );
+        assert!(DID_DROP.load(Ordering::SeqCst));
+    }
+
+    #[pg_test]
+    fn test_result_option() {
+        assert_eq!(Spi::get_one::<i32>("SELECT result_optional_value(0)"), None);
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT result_optional_value(1)"),
+            Some(1)
+        );
+    }
+
+    #[pg_test]
+    fn test_result_std_error_sqlstate() {
+        Spi::run(
+            "DO $$
+             BEGIN
+                PERFORM result_parse_int('not a number');
+                RAISE EXCEPTION 'result_parse_int() did not raise an ERROR';
+             EXCEPTION WHEN SQLSTATE '22000' THEN
+                NULL;
+             END $$;",
+        );
+    }
+
+    #[pg_test]
+    fn test_result_setof() {
+        let cnt = Spi::get_one::<i64>("SELECT count(*) FROM result_setof(10)");
+        assert_eq!(cnt, Some(10));
+    }
+
+    #[pg_test(error = "value 500 is too big")]
+    fn test_result_setof_err() {
+        Spi::get_one::<i64>("SELECT count(*) FROM result_setof(500)");
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// Waits of 10ms, 20ms, 40ms, ..., without jitter
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts)
+            .with_backoff(Duration::from_millis(10), Duration::from_secs(1))
+            .with_jitter(0.0)
+    }
+
+    /// An operation that fails with a transient error until its `failures` have been used up
+    fn flaky(failures: u32) -> impl FnMut(&Attempt) -> Result<u32, AttemptError<String>> {
+        let mut calls = 0;
+        move |attempt| {
+            calls += 1;
+            assert_eq!(attempt.number(), calls);
+            if calls <= failures {
+                Err(AttemptError::Transient(format!("failure #{}", calls)))
+            } else {
+                Ok(calls)
+            }
+        }
+    }
+
+    fn set_latch() {
+        unsafe { pg_sys::SetLatch(pg_sys::MyLatch) }
+    }
+
+    #[pg_test]
+    fn test_sleep_elapsed() {
+        let start = Instant::now();
+        assert_eq!(sleep(Duration::from_millis(20)), WakeReason::Elapsed);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[pg_test]
+    fn test_sleep_latch_set() {
+        set_latch();
+        let start = Instant::now();
+        assert_eq!(sleep(Duration::from_secs(60)), WakeReason::LatchSet);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // and the latch was reset
+        assert_eq!(sleep(Duration::from_millis(1)), WakeReason::Elapsed);
+    }
+
+    #[pg_test]
+    fn test_retry_succeeds() {
+        let start = Instant::now();
+        assert_eq!(retry(&policy(5), flaky(2)), Ok(3));
+
+        // waited 10ms and then 20ms
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(30), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    }
+
+    #[pg_test]
+    fn test_retry_exhausted() {
+        let start = Instant::now();
+        assert_eq!(
+            retry(&policy(4), flaky(10)),
+            Err(RetryError::Exhausted {
+                attempts: 4,
+                last: Some("failure #4".to_string())
+            })
+        );
+        assert!(start.elapsed() >= Duration::from_millis(70));
+    }
+
+    #[pg_test]
+    fn test_retry_permanent_error() {
+        let mut calls = 0;
+        let result = retry(&policy(5), |_| -> Result<(), _> {
+            calls += 1;
+            Err(AttemptError::Permanent("nope"))
+        });
+        assert_eq!(result, Err(RetryError::Permanent("nope")));
+        assert_eq!(calls, 1);
+    }
+
+    #[pg_test]
+    fn test_retry_doesnt_retry_postgres_errors() {
+        static CALLS: AtomicU32 = AtomicU32::new(0);
+
+        let result = pg_try(|| {
+            retry(&policy(5), |_| -> Result<(), AttemptError<()>> {
+                CALLS.fetch_add(1, Ordering::SeqCst);
+                error!("the resource is on fire")
+            })
+        });
+        assert!(result.sqlerrcode().is_some());
+        unsafe { result.unwrap_or(Ok(())) }.unwrap();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[pg_test]
+    fn test_retry_continues_after_latch_set() {
+        // a latch set for some other reason doesn't cut the wait short
+        let mut op = flaky(2);
+        let start = Instant::now();
+        let result = retry(&policy(3), |attempt| {
+            set_latch();
+            op(attempt)
+        });
+        assert_eq!(result, Ok(3));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[cfg(any(feature = "pg10", feature = "pg11"))]
+    unsafe fn set_proc_die_pending(pending: bool) {
+        pg_sys::ProcDiePending = pending;
+    }
+
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    unsafe fn set_proc_die_pending(pending: bool) {
+        pg_sys::ProcDiePending = pending as pg_sys::sig_atomic_t;
+    }
+
+    #[pg_test]
+    fn test_retry_interrupted_by_shutdown() {
+        // as if we'd been sent a SIGTERM while holding interrupts, so it isn't acted on for us
+        unsafe {
+            pg_sys::InterruptHoldoffCount += 1;
+            set_proc_die_pending(true);
+        }
+        set_latch();
+
+        let start = Instant::now();
+        let policy =
+            RetryPolicy::new(3).with_backoff(Duration::from_secs(60), Duration::from_secs(60));
+        let result = retry(&policy, flaky(10));
+        let elapsed = start.elapsed();
+
+        unsafe {
+            set_proc_die_pending(false);
+            pg_sys::InterruptHoldoffCount -= 1;
+        }
+
+        assert_eq!(
+            result,
+            Err(RetryError::Interrupted {
+                attempts: 1,
+                last: Some("failure #1".to_string())
+            })
+        );
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    }
+
+    #[pg_test]
+    fn test_retry_attempt_timeout() {
+        let policy = policy(2).with_attempt_timeout(Duration::from_secs(30));
+        let result = retry(&policy, |attempt| -> Result<(), _> {
+            let remaining = attempt.remaining().unwrap();
+            assert!(remaining <= Duration::from_secs(30));
+            assert!(remaining > Duration::from_secs(25));
+            Err(AttemptError::Transient(attempt.deadline().unwrap()))
+        });
+
+        // each attempt gets its own deadline
+        match result {
+            Err(RetryError::Exhausted {
+                attempts: 2,
+                last: Some(last),
+            }) => assert!(last > Instant::now() + Duration::from_secs(25)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(policy.backoff(1), Duration::from_millis(10));
+        assert_eq!(policy.backoff(3), Duration::from_millis(40));
+        assert_eq!(policy.backoff(20), Duration::from_secs(1));
+    }
+
+    #[pg_test]
+    fn test_retry_late_attempts_time_out() {
+        let policy = policy(3).with_attempt_timeout(Duration::from_millis(10));
+
+        // the first attempt's success came too late, so it's tried again
+        let result = retry(&policy, |attempt| -> Result<u32, AttemptError<()>> {
+            if attempt.number() == 1 {
+                sleep(Duration::from_millis(20));
+            }
+            Ok(attempt.number())
+        });
+        assert_eq!(result, Ok(2));
+
+        let result = retry(&policy, |_| -> Result<(), _> {
+            sleep(Duration::from_millis(20));
+            Err(AttemptError::Transient("too slow".to_string()))
+        });
+        assert_eq!(
+            result,
+            Err(RetryError::Exhausted {
+                attempts: 3,
+                last: None
+            })
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "gave up after 3 attempts: the last attempt timed out"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DROPPED_CACHES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+struct WordCounts {
+    counts: HashMap<String, i64>,
+}
+
+impl Drop for WordCounts {
+    fn drop(&mut self) {
+        DROPPED_CACHES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// How many times `word` has been counted in this session
+#[pg_extern]
+fn session_count_word(word: &str) -> i64 {
+    session_state::with_or_init(WordCounts::default, |cache| {
+        let count = cache.counts.entry(word.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    })
+}
+
+/// The address of the session's `WordCounts`
+#[pg_extern]
+fn session_word_counts_address() -> i64 {
+    session_state::with_or_init(WordCounts::default, |counts| {
+        counts as *mut WordCounts as i64
+    })
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::{WordCounts, DROPPED_CACHES};
+    use pgx::*;
+    use std::sync::atomic::Ordering;
+
+    #[pg_test]
+    fn test_session_state_persists_across_calls() {
+        drop(session_state::remove::<WordCounts>());
+
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT session_count_word('a')"),
+            Some(1)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT session_count_word('a')"),
+            Some(2)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT session_count_word('b')"),
+            Some(1)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT max(session_count_word('a')) FROM generate_series(1, 3)"),
+            Some(5)
+        );
+
+        session_state::with(|counts: &mut WordCounts| {
+            assert_eq!(counts.counts.get("a"), Some(&5));
+            assert_eq!(counts.counts.get("b"), Some(&1));
+        })
+        .expect("no WordCounts");
+    }
+
+    #[pg_test]
+    fn test_session_state_identity() {
+        let first = Spi::get_one::<i64>("SELECT session_word_counts_address()");
+        let second = Spi::get_one::<i64>("SELECT session_word_counts_address()");
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            session_state::with(|counts: &mut WordCounts| counts as *mut WordCounts as i64)
+        );
+    }
+
+    #[pg_test]
+    fn test_session_state_remove() {
+        session_state::with_or_init(WordCounts::default, |counts| {
+            counts.counts.insert("removed".to_string(), 42)
+        });
+
+        let before = DROPPED_CACHES.load(Ordering::SeqCst);
+        let removed = session_state::remove::<WordCounts>().expect("no WordCounts");
+        assert_eq!(removed.counts.get("removed"), Some(&42));
+        assert!(!session_state::contains::<WordCounts>());
+        drop(removed);
+        assert_eq!(DROPPED_CACHES.load(Ordering::SeqCst) - before, 1);
+
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT session_count_word('removed')"),
+            Some(1)
+        );
+    }
+
+    #[pg_test]
+    fn test_session_state_clear_all() {
+        session_state::with_or_init(WordCounts::default, |_| ());
+        session_state::with_or_init(|| 0u64, |value| *value = 7);
+        let allocated = session_state::memory_context().palloc(1024);
+        assert!(PgMemoryContexts::Of(allocated as void_ptr)
+            .is_descendant_of(&session_state::memory_context()));
+
+        let usage = session_state::memory_usage();
+        assert!(usage.contains(&("u64", 8)));
+        assert!(usage.iter().any(|(name, _)| name.ends_with("WordCounts")));
+
+        let before = DROPPED_CACHES.load(Ordering::SeqCst);
+        session_state::clear_all();
+        assert_eq!(DROPPED_CACHES.load(Ordering::SeqCst) - before, 1);
+        assert!(!session_state::contains::<WordCounts>());
+        assert!(!session_state::contains::<u64>());
+        assert!(session_state::memory_usage().is_empty());
+
+        // and it's usable again
+        assert_eq!(session_state::with_or_init(|| 1u64, |value| *value), 1);
+    }
+
+    #[pg_test]
+    fn test_session_state_nested() {
+        drop(session_state::remove::<u32>());
+        drop(session_state::remove::<u64>());
+
+        // the rest of the session state can be used while a value is lent out
+        let sum = session_state::with_or_init(
+            || 1u32,
+            |small| {
+                *small += 1;
+                session_state::with_or_init(|| 40u64, |big| *big + *small as u64)
+            },
+        );
+        assert_eq!(sum, 42);
+        assert_eq!(session_state::with(|small: &mut u32| *small), Some(2));
+    }
+
+    #[pg_test(error = "u32 is already in use")]
+    fn test_session_state_no_aliasing() {
+        session_state::with_or_init(|| 1u32, |_| session_state::with(|again: &mut u32| *again));
+    }
+
+    #[pg_test(error = "u32 is in use, so can't be removed")]
+    fn test_session_state_no_remove_while_in_use() {
+        session_state::with_or_init(|| 1u32, |_| session_state::remove::<u32>());
+    }
+
+    #[pg_test]
+    fn test_session_state_returned_after_panic() {
+        session_state::with_or_init(|| 1u32, |value| *value = 1);
+        let result = pg_try(|| session_state::with(|_: &mut u32| panic!("while lent out")));
+        assert!(result.sqlerrcode().is_none());
+        unsafe { result.unwrap_or(None) };
+        assert_eq!(session_state::with(|value: &mut u32| *value), Some(1));
+    }
+
+    #[pg_test(error = "session state can only be used from the backend's main thread")]
+    fn test_session_state_main_thread_only() {
+        // the first thread to use the session state is taken to be the main thread
+        session_state::contains::<u64>();
+
+        let result = std::thread::spawn(|| {
+            session_state::with_or_init(|| 0u64, |_| ());
+        })
+        .join();
+
+        if let Err(e) = result {
+            std::panic::resume_unwind(e);
+        }
+    }
+}
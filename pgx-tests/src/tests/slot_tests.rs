@@ -0,0 +1,108 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn tupdesc() -> PgTupleDesc<'static> {
+        PgTupleDesc::builder()
+            .column_for::<i32>("id")
+            .column_for::<String>("name")
+            .column_for::<bool>("flag")
+            .bless()
+            .build()
+    }
+
+    #[pg_test]
+    fn test_store_virtual() {
+        let tupdesc = tupdesc();
+        let mut slot = PgTupleTableSlot::new(&tupdesc);
+        assert!(slot.is_empty());
+        assert_eq!(slot.natts(), 3);
+
+        let name = "virtual".into_datum();
+        unsafe { slot.store_virtual(&[42i32.into_datum(), name, None]) };
+        assert!(!slot.is_empty());
+        assert_eq!(slot.get::<i32>(1), Some(42));
+        assert_eq!(slot.get::<String>(2), Some("virtual".to_string()));
+        assert_eq!(slot.get::<bool>(3), None);
+        assert_eq!(slot.get_datum(3), None);
+
+        // storing again replaces the values
+        unsafe { slot.store_virtual(&[None, name, true.into_datum()]) };
+        assert_eq!(slot.get::<i32>(1), None);
+        assert_eq!(slot.get::<bool>(3), Some(true));
+
+        slot.clear();
+        assert!(slot.is_empty());
+    }
+
+    #[pg_test]
+    fn test_store_heap_tuple() {
+        let tupdesc = tupdesc();
+        let mut slot = PgTupleTableSlot::new(&tupdesc);
+
+        slot.store_heap_tuple(tupdesc.form_tuple(&[
+            1i32.into_datum(),
+            "first".into_datum(),
+            false.into_datum(),
+        ]));
+        assert!(!slot.is_empty());
+
+        // attributes can be read out of order, deforming only as far as needed
+        assert_eq!(slot.get::<String>(2), Some("first".to_string()));
+        assert_eq!(slot.get::<i32>(1), Some(1));
+        assert_eq!(slot.get::<bool>(3), Some(false));
+
+        // the slot frees the first tuple when the second is stored
+        slot.store_heap_tuple(tupdesc.form_tuple(&[2i32.into_datum(), None, None]));
+        assert_eq!(slot.get::<i32>(1), Some(2));
+        assert_eq!(slot.get::<String>(2), None);
+
+        // and a heap tuple slot can hold a virtual tuple
+        unsafe { slot.store_virtual(&[3i32.into_datum(), None, None]) };
+        assert_eq!(slot.get::<i32>(1), Some(3));
+
+        slot.clear();
+        assert!(slot.is_empty());
+    }
+
+    #[pg_test]
+    fn test_from_pg_isnt_dropped() {
+        let tupdesc = tupdesc();
+        let owner = PgTupleTableSlot::new(&tupdesc);
+
+        {
+            let mut borrowed = unsafe { PgTupleTableSlot::from_pg(owner.as_ptr()) };
+            unsafe { borrowed.store_virtual(&[7i32.into_datum(), None, None]) };
+        }
+
+        assert_eq!(owner.get::<i32>(1), Some(7));
+    }
+
+    #[pg_test(error = "cannot get an attribute from an empty TupleTableSlot")]
+    fn test_get_from_empty_slot() {
+        let tupdesc = tupdesc();
+        let slot = PgTupleTableSlot::new(&tupdesc);
+        slot.get_datum(1);
+    }
+
+    #[pg_test(error = "attribute number 4 is out of bounds for a tuple with 3 attributes")]
+    fn test_get_out_of_bounds() {
+        let tupdesc = tupdesc();
+        let mut slot = PgTupleTableSlot::new(&tupdesc);
+        unsafe { slot.store_virtual(&[None, None, None]) };
+        slot.get_datum(4);
+    }
+
+    #[pg_test(error = "tuple descriptor has 3 attributes but 1 values were provided")]
+    fn test_store_virtual_wrong_length() {
+        let tupdesc = tupdesc();
+        let mut slot = PgTupleTableSlot::new(&tupdesc);
+        unsafe { slot.store_virtual(&[None]) };
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use serde::{Deserialize, Serialize};
+
+/// Abbreviated by its first 8 bytes, so values sharing them need a full comparison
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    PostgresType,
+    PostgresEq,
+    PostgresOrd,
+)]
+#[sort_abbreviation]
+pub struct SortName(String);
+
+impl SortAbbreviation for SortName {
+    const ABBREVIATED: bool = true;
+
+    fn abbreviate(&self) -> i64 {
+        pgx::sortsupport::abbreviate_bytes(self.0.as_bytes())
+    }
+}
+
+/// Not abbreviated, so it has no sort support, and is only compared with its `_cmp` function
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    PostgresType,
+    PostgresEq,
+    PostgresOrd,
+)]
+pub struct SortPlain(String);
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::{SortName, SortPlain};
+    use pgx::*;
+
+    /// Sort the values of `SELECT` in SQL, and check they're in the same order Rust sorts them in
+    fn assert_sorted_like_rust(select: &str) {
+        Spi::run(&format!("CREATE TABLE sort_names AS {}", select));
+
+        let sorted = Spi::get_one::<Vec<SortName>>(
+            "SELECT array_agg(name) FROM (SELECT name FROM sort_names ORDER BY name) s",
+        )
+        .expect("no names");
+        let mut expected = sorted.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+
+        let descending = Spi::get_one::<Vec<SortName>>(
+            "SELECT array_agg(name ORDER BY name DESC) FROM sort_names",
+        )
+        .expect("no names");
+        expected.reverse();
+        assert_eq!(descending, expected);
+    }
+
+    #[pg_test]
+    fn test_abbreviate_bytes() {
+        use pgx::sortsupport::abbreviate_bytes;
+
+        assert!(abbreviate_bytes(b"") < abbreviate_bytes(b"a"));
+        assert!(abbreviate_bytes(b"a") < abbreviate_bytes(b"b"));
+        assert!(abbreviate_bytes(b"\x7f") < abbreviate_bytes(b"\x80"));
+        assert!(abbreviate_bytes(b"\x80") < abbreviate_bytes(b"\xff"));
+        assert_eq!(
+            abbreviate_bytes(b"abcdefgh-1"),
+            abbreviate_bytes(b"abcdefgh-2")
+        );
+    }
+
+    #[pg_test]
+    fn test_opclass_has_sortsupport() {
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT p.amproc::text FROM pg_amproc p JOIN pg_opfamily f ON f.oid = p.amprocfamily
+                  WHERE f.opfname = 'sortname_btree_ops' AND p.amprocnum = 2"
+            ),
+            Some("tests.sortname_sortsupport".to_string())
+        );
+
+        // only types with abbreviated keys have sort support
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM pg_amproc p JOIN pg_opfamily f ON f.oid = p.amprocfamily
+                  WHERE f.opfname = 'sortplain_btree_ops' AND p.amprocnum = 2"
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT EXISTS(SELECT 1 FROM pg_proc WHERE proname = 'sortplain_sortsupport')"
+            ),
+            Some(false)
+        );
+    }
+
+    #[pg_test]
+    fn test_sort_distinct_prefixes() {
+        assert_sorted_like_rust(
+            "SELECT ('\"' || md5(i::text) || '\"')::tests.sortname AS name
+               FROM generate_series(1, 20000) i",
+        );
+    }
+
+    #[pg_test]
+    fn test_sort_ties_need_full_comparison() {
+        // only 10 distinct abbreviated keys, each shared by 1,000 different names
+        assert_sorted_like_rust(
+            "SELECT ('\"prefix-' || i % 10 || '-' || md5(i::text) || '\"')::tests.sortname AS name
+               FROM generate_series(1, 10000) i",
+        );
+    }
+
+    #[pg_test]
+    fn test_sort_duplicates() {
+        // ties between equal values are found from their bytes
+        assert_sorted_like_rust(
+            "SELECT ('\"prefix-' || i % 10 || '-' || md5((i % 100)::text) || '\"')::tests.sortname AS name
+               FROM generate_series(1, 10000) i",
+        );
+    }
+
+    #[pg_test]
+    fn test_sort_after_abbreviation_is_aborted() {
+        // every name has the same abbreviated key, so they stop being made part way through
+        assert_sorted_like_rust(
+            "SELECT ('\"samesame' || md5(i::text) || '\"')::tests.sortname AS name
+               FROM generate_series(1, 30000) i",
+        );
+    }
+
+    #[pg_test]
+    fn test_sort_unabbreviated_type() {
+        Spi::run(
+            "CREATE TABLE sort_plain AS
+                 SELECT ('\"' || md5(i::text) || '\"')::tests.sortplain AS value
+                   FROM generate_series(1, 1000) i",
+        );
+
+        let sorted = Spi::get_one::<Vec<SortPlain>>(
+            "SELECT array_agg(value ORDER BY value) FROM sort_plain",
+        )
+        .expect("no values");
+        let mut expected = sorted.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[pg_test]
+    fn test_sorted_index() {
+        assert_sorted_like_rust(
+            "SELECT ('\"' || (i * 7919) % 5000 || '\"')::tests.sortname AS name
+               FROM generate_series(1, 5000) i",
+        );
+
+        // building a btree index sorts with the same sort support
+        Spi::run("CREATE INDEX sort_names_idx ON sort_names (name)");
+        Spi::run("SET enable_seqscan = off");
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM sort_names WHERE name = '\"42\"'::tests.sortname"
+            ),
+            Some(1)
+        );
+    }
+
+    /// The time to sort a million values with abbreviated keys, and the same values of a type
+    /// without sort support, which is reported as an INFO message.  Build with `--release` for
+    /// meaningful numbers
+    #[pg_test]
+    fn bench_sort_abbreviation() {
+        const VALUES: u32 = 1_000_000;
+        Spi::run("SET LOCAL work_mem = '1GB'");
+        Spi::run(&format!(
+            "CREATE TABLE sort_bench AS
+                 SELECT ('\"' || md5(i::text) || '\"')::tests.sortname AS name,
+                        ('\"' || md5(i::text) || '\"')::tests.sortplain AS plain
+                   FROM generate_series(1, {}) i",
+            VALUES
+        ));
+
+        // the last of the sorted values, so every value is sorted
+        let time = |column: &str| {
+            let start = std::time::Instant::now();
+            Spi::run(&format!(
+                "SELECT {column} FROM sort_bench ORDER BY {column} OFFSET {offset} LIMIT 1",
+                column = column,
+                offset = VALUES - 1
+            ));
+            start.elapsed()
+        };
+        let abbreviated = time("name");
+        let plain = time("plain");
+        info!(
+            "sorting {} values took {:?} with abbreviated keys, and {:?} without sort support",
+            VALUES, abbreviated, plain
+        );
+
+        assert!(abbreviated < plain);
+    }
+}
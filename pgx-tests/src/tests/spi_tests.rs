@@ -108,6 +108,60 @@ mod tests {
         });
     }
 
+    #[pg_test]
+    fn test_spi_get_one_row() {
+        let row = Spi::get_one_row(
+            "SELECT 1::int4 AS a, 'two'::text AS b, NULL::int8 AS c, 4.5::float8 AS d, true AS e",
+        )
+        .expect("no row");
+
+        assert_eq!(row.len(), 5);
+        assert_eq!(row.get::<i32>(1), Ok(Some(1)));
+        assert_eq!(row.get::<String>(2), Ok(Some("two".to_string())));
+        assert_eq!(row.get::<i64>(3), Ok(None));
+        assert_eq!(row.get_by_name::<f64>("d"), Ok(Some(4.5)));
+        assert_eq!(row.get_by_name::<bool>("e"), Ok(Some(true)));
+        assert_eq!(row.get_by_name::<&str>("b"), Ok(Some("two")));
+    }
+
+    #[pg_test]
+    fn test_spi_get_one_row_first_only() {
+        let row = Spi::get_one_row("SELECT x, x * 2 AS doubled FROM generate_series(1, 10) x")
+            .expect("no row");
+        assert_eq!(row.get::<i32>(1), Ok(Some(1)));
+        assert_eq!(row.get_by_name::<i32>("doubled"), Ok(Some(2)));
+    }
+
+    #[pg_test]
+    fn test_spi_get_one_row_no_attribute() {
+        let row = Spi::get_one_row("SELECT 1 AS a").expect("no row");
+        assert_eq!(row.get::<i32>(0), Err(SpiError::Noattribute));
+        assert_eq!(row.get::<i32>(2), Err(SpiError::Noattribute));
+        assert_eq!(row.get_by_name::<i32>("b"), Err(SpiError::Noattribute));
+    }
+
+    #[pg_test]
+    fn test_spi_get_one_row_zero_rows() {
+        assert!(Spi::get_one_row("SELECT 1 WHERE false").is_none());
+    }
+
+    #[pg_test]
+    fn test_spi_get_one_row_with_args() {
+        let row = Spi::get_one_row_with_args(
+            "SELECT $1 + 1 AS next, upper($2) AS shout",
+            vec![
+                (PgBuiltInOids::INT4OID.oid(), 41.into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), "hi".into_datum()),
+            ],
+        )
+        .expect("no row");
+        assert_eq!(row.get_by_name::<i32>("next"), Ok(Some(42)));
+        assert_eq!(
+            row.get_by_name::<String>("shout"),
+            Ok(Some("HI".to_string()))
+        );
+    }
+
     #[pg_test]
     fn test_spi_get_two_with_failure() {
         Spi::execute(|client| {
@@ -149,4 +203,293 @@ mod tests {
     fn test_panic_via_spi() {
         Spi::run("SELECT tests.do_panic();");
     }
+
+    fn create_skewed_table() {
+        Spi::run(
+            "CREATE TABLE skewed (id serial, kind int);
+             INSERT INTO skewed (kind)
+                SELECT CASE WHEN i <= 10 THEN 2 ELSE 1 END FROM generate_series(1, 10000) i;
+             CREATE INDEX idx_skewed_kind ON skewed (kind);
+             ANALYZE skewed;",
+        );
+    }
+
+    #[pg_test]
+    fn test_spi_execute_plan() {
+        create_skewed_table();
+        Spi::execute(|mut client| {
+            let plan = client.prepare(
+                "SELECT id FROM skewed WHERE kind = $1",
+                Some(vec![PgBuiltInOids::INT4OID.oid()]),
+            );
+            assert_eq!(plan.nargs(), 1);
+
+            let table = client.execute_plan(&plan, None, Some(vec![2.into_datum()]));
+            assert_eq!(table.len(), 10);
+
+            let table = client.execute_plan(&plan, None, Some(vec![1.into_datum()]));
+            assert_eq!(table.len(), 9990);
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_kept_plan() {
+        let plan = std::cell::RefCell::new(None);
+        let plan = std::panic::AssertUnwindSafe(&plan);
+        Spi::execute(|client| {
+            plan.replace(Some(client.prepare("SELECT 42", None).keep()));
+        });
+
+        // a kept plan outlives the connection that prepared it
+        Spi::execute(|mut client| {
+            let plan = plan.borrow();
+            let table = client.execute_plan(plan.as_ref().unwrap(), None, None);
+            assert_eq!(table.first().get_one::<i32>(), Some(42));
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_plan_explain_custom_vs_generic() {
+        create_skewed_table();
+        Spi::execute(|client| {
+            let mut plan = client.prepare(
+                "SELECT id FROM skewed WHERE kind = $1",
+                Some(vec![PgBuiltInOids::INT4OID.oid()]),
+            );
+
+            // custom plans see the argument values, and plan the rare and common values differently
+            let rare = plan.explain(ExplainFormat::Text, Some(vec![2.into_datum()]));
+            assert!(rare.contains("kind = 2"), "{}", rare);
+            assert!(!rare.contains("Seq Scan"), "{}", rare);
+
+            let common = plan.explain(ExplainFormat::Text, Some(vec![1.into_datum()]));
+            assert!(common.contains("Seq Scan"), "{}", common);
+
+            // the generic plan only knows about the parameter
+            let generic = plan.explain(ExplainFormat::Text, None);
+            assert!(generic.contains("$1"), "{}", generic);
+            assert_eq!(plan.uses_generic_plan(), Some(false));
+
+            plan.set_cursor_options(pg_sys::CURSOR_OPT_GENERIC_PLAN as i32);
+            assert_eq!(plan.uses_generic_plan(), Some(true));
+            let forced = plan.explain(ExplainFormat::Text, Some(vec![2.into_datum()]));
+            assert!(forced.contains("$1"), "{}", forced);
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_plan_explain_json() {
+        Spi::execute(|client| {
+            let plan = client.prepare("SELECT 1", None);
+            let json = plan.explain(ExplainFormat::Json, None);
+            let json: serde_json::Value = serde_json::from_str(&json).expect("invalid json");
+            assert!(json[0]["Plan"].is_object());
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_update() {
+        Spi::run("CREATE TABLE spi_update (id int, value text)");
+
+        let inserted = Spi::update(
+            "INSERT INTO spi_update SELECT i, 'value ' || i FROM generate_series(1, $1) i",
+            vec![(PgBuiltInOids::INT4OID.oid(), 10.into_datum())],
+        );
+        assert_eq!(inserted, 10);
+
+        let updated = Spi::update("UPDATE spi_update SET value = NULL WHERE id > 5", vec![]);
+        assert_eq!(updated, 5);
+
+        let deleted = Spi::update(
+            "DELETE FROM spi_update WHERE value IS NULL OR id = $1",
+            vec![(PgBuiltInOids::INT4OID.oid(), 1.into_datum())],
+        );
+        assert_eq!(deleted, 6);
+
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM spi_update"),
+            Some(4)
+        );
+    }
+
+    #[pg_test]
+    fn test_spi_select_read_only_snapshot() {
+        Spi::run("CREATE TABLE spi_read_only (id int)");
+        Spi::update("INSERT INTO spi_read_only VALUES (1), (2), (3)", vec![]);
+
+        Spi::execute(|client| {
+            // the read/write path sees the rows inserted above...
+            let count = client
+                .select("SELECT count(*) FROM spi_read_only", None, None)
+                .first()
+                .get_one::<i64>();
+            assert_eq!(count, Some(3));
+
+            // ... but the read-only path uses the calling statement's snapshot, which predates them
+            let count = client
+                .select_read_only("SELECT count(*) FROM spi_read_only", None, None)
+                .first()
+                .get_one::<i64>();
+            assert_eq!(count, Some(0));
+        });
+    }
+
+    #[pg_test(error = "INSERT is not allowed in a non-volatile function")]
+    fn test_spi_select_read_only_rejects_writes() {
+        Spi::run("CREATE TABLE spi_read_only_writes (id int)");
+        Spi::execute(|client| {
+            client.select_read_only("INSERT INTO spi_read_only_writes VALUES (1)", None, None);
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_update_returning() {
+        Spi::run("CREATE TABLE spi_returning (id int)");
+
+        Spi::execute(|mut client| {
+            // more rows than are fetched at once
+            let mut rows = client.update_returning(
+                "INSERT INTO spi_returning SELECT i FROM generate_series(1, $1) i RETURNING id",
+                Some(vec![(PgBuiltInOids::INT4OID.oid(), 250.into_datum())]),
+            );
+            assert_eq!(rows.rows_affected(), None);
+
+            let ids = rows
+                .by_ref()
+                .map(|row| row.by_ordinal(1).unwrap().value::<i32>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(ids, (1..=250).collect::<Vec<_>>());
+            assert_eq!(rows.rows_affected(), Some(250));
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_update_returning_on_conflict() {
+        Spi::run("CREATE TABLE spi_claims (name text PRIMARY KEY, claimed_by text)");
+
+        let claim = |name: &str, by: &str| -> Option<String> {
+            Spi::connect(|mut client| {
+                let mut rows = client.update_returning(
+                    "INSERT INTO spi_claims VALUES ($1, $2)
+                         ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+                         RETURNING claimed_by",
+                    Some(vec![
+                        (PgBuiltInOids::TEXTOID.oid(), name.into_datum()),
+                        (PgBuiltInOids::TEXTOID.oid(), by.into_datum()),
+                    ]),
+                );
+                let claimed_by = rows
+                    .next()
+                    .and_then(|row| row.by_name("claimed_by").unwrap().value::<String>());
+                assert!(rows.next().is_none());
+                assert_eq!(rows.rows_affected(), Some(1));
+                Ok(claimed_by)
+            })
+        };
+
+        assert_eq!(claim("job", "alice"), Some("alice".to_string()));
+        assert_eq!(claim("job", "bob"), Some("alice".to_string()));
+    }
+
+    #[pg_test]
+    fn test_spi_update_returning_no_rows() {
+        Spi::run("CREATE TABLE spi_returning_none (id int)");
+
+        Spi::execute(|mut client| {
+            let mut rows =
+                client.update_returning("DELETE FROM spi_returning_none RETURNING id", None);
+            assert_eq!(rows.rows_affected(), Some(0));
+            assert!(rows.next().is_none());
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_update_returning_runs_unread_statement() {
+        Spi::run("CREATE TABLE spi_returning_unread (id int)");
+
+        Spi::execute(|mut client| {
+            client.update_returning(
+                "INSERT INTO spi_returning_unread VALUES (1), (2) RETURNING id",
+                None,
+            );
+        });
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM spi_returning_unread"),
+            Some(2)
+        );
+    }
+
+    #[pg_test(error = "update_returning() requires a RETURNING clause, but the statement has none")]
+    fn test_spi_update_returning_without_returning() {
+        Spi::run("CREATE TABLE spi_not_returning (id int)");
+        Spi::execute(|mut client| {
+            client.update_returning("INSERT INTO spi_not_returning VALUES (1)", None);
+        });
+    }
+
+    #[pg_test(error = "update_returning() requires an INSERT, UPDATE, or DELETE statement")]
+    fn test_spi_update_returning_select() {
+        Spi::execute(|mut client| {
+            client.update_returning("SELECT 1", None);
+        });
+    }
+
+    #[pg_test]
+    fn test_spi_query_builder() {
+        Spi::run("CREATE TABLE spi_query (id int, name text)");
+
+        let inserted =
+            Query::new("INSERT INTO spi_query SELECT i, $1 || i FROM generate_series(1, $2) i")
+                .bind("name ")
+                .bind(10)
+                .execute();
+        assert_eq!(inserted, 10);
+
+        assert_eq!(
+            Query::new("SELECT name FROM spi_query WHERE id = $1")
+                .bind(3)
+                .get_one::<String>(),
+            Some("name 3".to_string())
+        );
+        assert_eq!(
+            Query::new("SELECT id, name FROM spi_query WHERE id > $1 AND name LIKE $2 ORDER BY id")
+                .bind(4)
+                .bind("%5")
+                .get_two::<i32, String>(),
+            (Some(5), Some("name 5".to_string()))
+        );
+
+        let row = Query::new("SELECT id, name FROM spi_query WHERE id = $1")
+            .bind(7)
+            .get_one_row()
+            .expect("no row");
+        assert_eq!(
+            row.get_by_name::<String>("name"),
+            Ok(Some("name 7".to_string()))
+        );
+
+        let ids = Query::new("SELECT id FROM spi_query WHERE id <= $1 ORDER BY id DESC")
+            .bind(3)
+            .iter()
+            .map(|row| row.get::<i32>(1).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 2, 1]);
+    }
+
+    #[pg_test]
+    fn test_spi_query_builder_binds_null() {
+        assert_eq!(
+            Query::new("SELECT $1 IS NULL")
+                .bind(None::<i32>)
+                .get_one::<bool>(),
+            Some(true)
+        );
+        assert_eq!(
+            Query::new("SELECT $1 IS NULL")
+                .bind(Some(1))
+                .get_one::<bool>(),
+            Some(false)
+        );
+        assert_eq!(Query::new("SELECT 1 WHERE false").iter().count(), 0);
+    }
 }
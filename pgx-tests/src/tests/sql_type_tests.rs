@@ -0,0 +1,96 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    /// Midnight on 2000-01-01 UTC, which is Postgres' epoch, so zero
+    #[pg_extern(raw, immutable, returns = sql_type!("timestamp with time zone"))]
+    fn sql_type_postgres_epoch() -> pg_sys::Datum {
+        0 as pg_sys::Datum
+    }
+
+    #[pg_test]
+    fn test_sql_type_builtin() {
+        assert_eq!(sql_type!("text"), PgBuiltInOids::TEXTOID);
+        assert_eq!(sql_type!("int4"), PgBuiltInOids::INT4OID);
+        assert_eq!(sql_type!("integer"), PgBuiltInOids::INT4OID);
+        assert_eq!(sql_type!("double precision"), PgBuiltInOids::FLOAT8OID);
+        assert_eq!(sql_type!("\"char\""), PgBuiltInOids::CHAROID);
+        assert_eq!(sql_type!("char"), PgBuiltInOids::BPCHAROID);
+        assert_eq!(
+            sql_type!("timestamp with time zone"),
+            PgBuiltInOids::TIMESTAMPTZOID
+        );
+    }
+
+    #[pg_test]
+    fn test_sql_type_names_match_postgres() {
+        for (name, oid) in vec![
+            ("text", sql_type!("text")),
+            ("bigint", sql_type!("bigint")),
+            ("character varying", sql_type!("character varying")),
+            ("time with time zone", sql_type!("time with time zone")),
+            ("money", sql_type!("money")),
+            ("pg_lsn", sql_type!("pg_lsn")),
+            ("\"char\"", sql_type!("\"char\"")),
+        ] {
+            assert_eq!(
+                Spi::get_one::<pg_sys::Oid>(&format!("SELECT '{}'::regtype::oid", name)),
+                Some(oid.value()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[cfg(not(feature = "pg10"))]
+    #[pg_test]
+    fn test_sql_type_arrays() {
+        assert_eq!(sql_type!("_int4"), PgBuiltInOids::INT4ARRAYOID);
+        assert_eq!(sql_type!("integer[]"), PgBuiltInOids::INT4ARRAYOID);
+        assert_eq!(
+            Spi::get_one::<pg_sys::Oid>("SELECT 'text[]'::regtype::oid"),
+            Some(sql_type!("text[]").value())
+        );
+    }
+
+    #[pg_test]
+    fn test_sql_type_lookup() {
+        let lookup: SqlTypeLookup = sql_type!(lookup "tests.sortname");
+        assert_eq!(lookup.name(), "tests.sortname");
+        assert_eq!(
+            Spi::get_one::<pg_sys::Oid>("SELECT 'tests.sortname'::regtype::oid"),
+            Some(lookup.oid().value())
+        );
+
+        // builtin types can be looked up too
+        assert_eq!(
+            sql_type!(lookup "integer").oid(),
+            PgOid::BuiltIn(PgBuiltInOids::INT4OID)
+        );
+    }
+
+    #[pg_test(error = "type \"no_such_type\" does not exist")]
+    fn test_sql_type_lookup_missing() {
+        sql_type!(lookup "no_such_type").oid();
+    }
+
+    #[pg_test]
+    fn test_raw_function_returns() {
+        assert_eq!(
+            Spi::get_one::<String>("SELECT pg_typeof(tests.sql_type_postgres_epoch())::text"),
+            Some("timestamp with time zone".to_string())
+        );
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT tests.sql_type_postgres_epoch() = '2000-01-01 00:00:00+00'::timestamptz"
+            ),
+            Some(true)
+        );
+    }
+}
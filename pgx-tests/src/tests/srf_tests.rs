@@ -61,6 +61,35 @@ fn return_none_setof_iterator() -> Option<impl std::iter::Iterator<Item = i32>>
     }
 }
 
+/// Never ends, so can only be called in value-per-call mode, where Postgres pulls one row at a time
+#[pg_extern]
+fn example_infinite_series() -> impl std::iter::Iterator<Item = i64> {
+    1..
+}
+
+#[pg_extern(materialize)]
+fn example_materialized_series(start: i32, end: i32) -> impl std::iter::Iterator<Item = i32> {
+    start..=end
+}
+
+#[pg_extern(materialize)]
+fn example_materialized_composite_set(
+) -> impl std::iter::Iterator<Item = (name!(idx, i32), name!(value, &'static str))> {
+    vec!["a", "b", "c"]
+        .into_iter()
+        .enumerate()
+        .map(|(idx, value)| ((idx + 1) as i32, value))
+}
+
+#[pg_extern(materialize)]
+fn return_none_materialized_iterator() -> Option<impl std::iter::Iterator<Item = i32>> {
+    if true {
+        None
+    } else {
+        Some(vec![1, 2, 3].into_iter())
+    }
+}
+
 #[cfg(any(test, feature = "pg_test"))]
 mod tests {
     #[allow(unused_imports)]
@@ -158,4 +187,56 @@ mod tests {
 
         assert_eq!(cnt, Some(0))
     }
+
+    #[pg_test]
+    fn test_infinite_series() {
+        // a `LIMIT` stops pulling rows from a set-returning function in the `SELECT` list
+        let sum = Spi::get_one::<i64>(
+            "SELECT sum(n)::bigint FROM (SELECT example_infinite_series() AS n LIMIT 5) series",
+        );
+        assert_eq!(sum, Some(15));
+    }
+
+    #[pg_test]
+    fn test_materialized_series() {
+        let expected = Some(vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            Spi::get_one::<Vec<i32>>(
+                "SELECT array_agg(n) FROM example_materialized_series(1, 5) n"
+            ),
+            expected
+        );
+        assert_eq!(
+            Spi::get_one::<Vec<i32>>(
+                "SELECT array_agg(n) FROM (SELECT example_materialized_series(1, 5) AS n) series"
+            ),
+            expected
+        );
+    }
+
+    #[pg_test]
+    fn test_materialized_series_spills_to_disk() {
+        Spi::run("SET LOCAL work_mem = '64kB'");
+        let sum = Spi::get_one::<i64>(
+            "SELECT sum(n)::bigint FROM example_materialized_series(1, 200000) n",
+        );
+        assert_eq!(sum, Some(200000 * 200001 / 2));
+    }
+
+    #[pg_test]
+    fn test_materialized_composite_set() {
+        let values = Spi::get_one::<Vec<String>>(
+            "SELECT array_agg(value ORDER BY idx) FROM example_materialized_composite_set()",
+        );
+        assert_eq!(
+            values,
+            Some(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        );
+    }
+
+    #[pg_test]
+    fn test_return_none_materialized_iterator() {
+        let cnt = Spi::get_one::<i64>("SELECT count(*) FROM return_none_materialized_iterator()");
+        assert_eq!(cnt, Some(0));
+    }
 }
@@ -0,0 +1,186 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn catalog(relid: pg_sys::Oid) -> PgRelation {
+        PgRelation::with_lock(relid, pg_sys::AccessShareLock as pg_sys::LOCKMODE)
+    }
+
+    fn create_table() -> pg_sys::Oid {
+        Spi::run(
+            "CREATE TABLE systable_cols (
+                id int PRIMARY KEY,
+                name text,
+                dropped int,
+                amount numeric(8, 2) CHECK (amount > 0)
+            )",
+        );
+        Spi::run("ALTER TABLE systable_cols DROP COLUMN dropped");
+        Spi::get_one::<pg_sys::Oid>("SELECT 'systable_cols'::regclass::oid").unwrap()
+    }
+
+    /// The (name, type) of each of the table's columns, according to `information_schema`
+    fn information_schema_columns() -> Vec<(String, pg_sys::Oid)> {
+        let names = Spi::get_one::<Vec<String>>(
+            "SELECT array_agg(column_name::text ORDER BY ordinal_position)
+               FROM information_schema.columns WHERE table_name = 'systable_cols'",
+        )
+        .unwrap();
+        let types = Spi::get_one::<Vec<pg_sys::Oid>>(
+            "SELECT array_agg(udt_name::text::regtype::oid ORDER BY ordinal_position)
+               FROM information_schema.columns WHERE table_name = 'systable_cols'",
+        )
+        .unwrap();
+        names.into_iter().zip(types).collect()
+    }
+
+    fn scan_columns(
+        relid: pg_sys::Oid,
+        index_oid: Option<pg_sys::Oid>,
+    ) -> Vec<(String, pg_sys::Oid)> {
+        let pg_attribute = catalog(pg_sys::AttributeRelationId);
+        let mut columns = SysTableScan::new(
+            &pg_attribute,
+            index_oid,
+            None,
+            &[
+                ScanKeyInit::equal(pg_sys::Anum_pg_attribute_attrelid, relid),
+                // an int4 compared with the int2 attnum
+                ScanKeyInit::new(
+                    pg_sys::Anum_pg_attribute_attnum,
+                    pg_sys::BTGreaterStrategyNumber,
+                    0i32,
+                ),
+            ],
+        )
+        .filter(|tuple| {
+            !tuple
+                .get_struct::<pg_sys::FormData_pg_attribute>()
+                .is_dropped()
+        })
+        .map(|tuple| {
+            let attr = tuple.get_struct::<pg_sys::FormData_pg_attribute>();
+            (attr.num(), attr.name().to_string(), attr.atttypid)
+        })
+        .collect::<Vec<_>>();
+
+        // a sequential scan's rows aren't in attnum order
+        columns.sort();
+        columns
+            .into_iter()
+            .map(|(_, name, typoid)| (name, typoid))
+            .collect()
+    }
+
+    #[pg_test]
+    fn test_scan_pg_attribute_with_index() {
+        let relid = create_table();
+        assert_eq!(
+            scan_columns(relid, Some(pg_sys::AttributeRelidNumIndexId)),
+            information_schema_columns()
+        );
+    }
+
+    #[pg_test]
+    fn test_scan_pg_attribute_without_index() {
+        let relid = create_table();
+        assert_eq!(scan_columns(relid, None), information_schema_columns());
+    }
+
+    #[pg_test]
+    fn test_get_by_name() {
+        let relid = create_table();
+        let pg_attribute = catalog(pg_sys::AttributeRelationId);
+        let tuple = SysTableScan::new(
+            &pg_attribute,
+            Some(pg_sys::AttributeRelidNumIndexId),
+            None,
+            &[
+                ScanKeyInit::equal(pg_sys::Anum_pg_attribute_attrelid, relid),
+                ScanKeyInit::equal(pg_sys::Anum_pg_attribute_attnum, 4i16),
+            ],
+        )
+        .next()
+        .expect("no attribute 4");
+
+        assert_eq!(tuple.get_by_name::<pg_sys::Oid>("attrelid"), Some(relid));
+        assert_eq!(tuple.get_by_name::<i16>("attnum"), Some(4));
+        assert_eq!(
+            tuple.get_by_name::<pg_sys::Oid>("atttypid"),
+            Some(pg_sys::NUMERICOID)
+        );
+        assert_eq!(tuple.get_by_name::<bool>("attnotnull"), Some(false));
+    }
+
+    #[pg_test]
+    fn test_get_by_name_after_dropped_column() {
+        create_table();
+        Spi::run("INSERT INTO systable_cols VALUES (1, 'one', 1.5)");
+
+        let relation = PgRelation::open_with_name_and_share_lock("systable_cols").unwrap();
+        let tuple = TableScan::begin(&relation, &[]).next().expect("no rows");
+        assert_eq!(tuple.get_by_name::<i32>("id"), Some(1));
+        assert_eq!(tuple.get_by_name::<String>("name"), Some("one".to_string()));
+        assert_eq!(
+            tuple.get_by_name::<Numeric>("amount").map(|n| n.0),
+            Some("1.50".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_scan_pg_constraint() {
+        let relid = create_table();
+        let pg_constraint = catalog(pg_sys::ConstraintRelationId);
+        let mut constraints = SysTableScan::new(
+            &pg_constraint,
+            None,
+            None,
+            &[ScanKeyInit::equal(
+                pg_sys::Anum_pg_constraint_conrelid,
+                relid,
+            )],
+        )
+        .map(|tuple| {
+            let constraint = tuple.get_struct::<pg_sys::FormData_pg_constraint>();
+            (
+                name_data_to_str(&constraint.conname).to_string(),
+                constraint.contype as u8,
+            )
+        })
+        .collect::<Vec<_>>();
+        constraints.sort();
+
+        assert_eq!(
+            constraints,
+            vec![
+                (
+                    "systable_cols_amount_check".to_string(),
+                    pg_sys::CONSTRAINT_CHECK
+                ),
+                ("systable_cols_pkey".to_string(), pg_sys::CONSTRAINT_PRIMARY),
+            ]
+        );
+    }
+
+    #[pg_test(
+        error = "no btree operator with strategy 3 can compare catalog attribute \"attrelid\" with a value of type 25"
+    )]
+    fn test_incomparable_key() {
+        let pg_attribute = catalog(pg_sys::AttributeRelationId);
+        SysTableScan::new(
+            &pg_attribute,
+            None,
+            None,
+            &[ScanKeyInit::equal(
+                pg_sys::Anum_pg_attribute_attrelid,
+                "systable_cols",
+            )],
+        );
+    }
+}
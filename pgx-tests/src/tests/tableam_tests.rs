@@ -0,0 +1,201 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn open_for_write(name: &str) -> PgRelation {
+        let oid = Spi::get_one::<pg_sys::Oid>(&format!("SELECT '{}'::regclass::oid", name))
+            .expect("relation oid was NULL");
+        PgRelation::with_lock(oid, pg_sys::RowExclusiveLock as pg_sys::LOCKMODE)
+    }
+
+    fn create_source() {
+        Spi::run("CREATE TABLE tableam_source (id int, value text)");
+        Spi::run(
+            "INSERT INTO tableam_source SELECT i, 'value ' || i FROM generate_series(1, 1000) i",
+        );
+    }
+
+    fn tid_of(tuple: &PgHeapTuple) -> pg_sys::ItemPointerData {
+        unsafe { (*tuple.as_ptr()).t_self }
+    }
+
+    #[pg_test]
+    fn test_scan_and_insert() {
+        create_source();
+        Spi::run("CREATE TABLE tableam_target (id int, value text)");
+
+        let source = PgRelation::open_with_name_and_share_lock("tableam_source").unwrap();
+        let target = open_for_write("tableam_target");
+        let tupdesc = target.tuple_desc();
+        let mut row = PgTupleTableSlot::new(&tupdesc);
+
+        let mut scan = TableScan::begin(&source, &[]);
+        while let Some(slot) = scan.next_slot() {
+            let id = slot.get::<i32>(1).unwrap();
+            unsafe { row.store_virtual(&[(id * 2).into_datum(), slot.get_datum(2)]) };
+            unsafe { tuple_insert(&target, &mut row) };
+        }
+        scan.end();
+
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT sum(id) FROM tableam_target"),
+            Some(1001000)
+        );
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM tableam_target t JOIN tableam_source s ON s.id * 2 = t.id
+                  WHERE s.value = t.value"
+            ),
+            Some(1000)
+        );
+    }
+
+    #[pg_test]
+    fn test_insert_returns_tid() {
+        Spi::run("CREATE TABLE tableam_tids (id int)");
+        let relation = open_for_write("tableam_tids");
+        let tupdesc = relation.tuple_desc();
+        let mut row = PgTupleTableSlot::new(&tupdesc);
+
+        row.store_heap_tuple(tupdesc.form_tuple(&[7i32.into_datum()]));
+        let tid = unsafe { tuple_insert(&relation, &mut row) };
+        let (blockno, offno) = item_pointer_get_both(tid);
+
+        assert_eq!(
+            Spi::get_one::<i32>(&format!(
+                "SELECT id FROM tableam_tids WHERE ctid = '({},{})'",
+                blockno, offno
+            )),
+            Some(7)
+        );
+    }
+
+    #[pg_test]
+    fn test_scan_with_keys() {
+        create_source();
+        let source = PgRelation::open_with_name_and_share_lock("tableam_source").unwrap();
+        let values = TableScan::begin(
+            &source,
+            &[
+                ScanKeyInit::new(1, pg_sys::BTGreaterStrategyNumber, 990i32),
+                // an int8 compared with the int4 id
+                ScanKeyInit::new(1, pg_sys::BTLessEqualStrategyNumber, 992i64),
+            ],
+        )
+        .map(|tuple| tuple.get_by_name::<String>("value").unwrap())
+        .collect::<Vec<_>>();
+
+        assert_eq!(values, vec!["value 991", "value 992"]);
+    }
+
+    #[pg_test]
+    fn test_update_and_delete() {
+        create_source();
+        let relation = open_for_write("tableam_source");
+        let tupdesc = relation.tuple_desc();
+        let mut row = PgTupleTableSlot::new(&tupdesc);
+
+        let tids = TableScan::begin(
+            &relation,
+            &[ScanKeyInit::new(1, pg_sys::BTLessEqualStrategyNumber, 2i32)],
+        )
+        .map(|tuple| (tuple.get_by_name::<i32>("id").unwrap(), tid_of(&tuple)))
+        .collect::<Vec<_>>();
+        assert_eq!(tids.len(), 2);
+
+        for (id, tid) in tids {
+            if id == 1 {
+                unsafe { tuple_delete(&relation, tid) };
+            } else {
+                unsafe { row.store_virtual(&[id.into_datum(), "updated".into_datum()]) };
+                let new_tid = unsafe { tuple_update(&relation, tid, &mut row) };
+                assert_ne!(item_pointer_get_both(new_tid), item_pointer_get_both(tid));
+            }
+        }
+
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM tableam_source"),
+            Some(999)
+        );
+        assert_eq!(
+            Spi::get_one::<String>("SELECT value FROM tableam_source WHERE id = 2"),
+            Some("updated".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_scan_empty_table() {
+        Spi::run("CREATE TABLE tableam_empty (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("tableam_empty").unwrap();
+        let mut scan = TableScan::begin(&relation, &[]);
+        assert!(scan.next_slot().is_none());
+        assert!(scan.next_tuple().is_none());
+    }
+
+    #[pg_test(error = "relation \"tableam_view\" is not a table or materialized view")]
+    fn test_scan_view() {
+        Spi::run("CREATE VIEW tableam_view AS SELECT 1 AS id");
+        let relation = PgRelation::open_with_name_and_share_lock("tableam_view").unwrap();
+        TableScan::begin(&relation, &[]);
+    }
+
+    #[pg_test(error = "cannot write an empty TupleTableSlot")]
+    fn test_insert_empty_slot() {
+        Spi::run("CREATE TABLE tableam_empty_slot (id int)");
+        let relation = open_for_write("tableam_empty_slot");
+        let tupdesc = relation.tuple_desc();
+        let mut row = PgTupleTableSlot::new(&tupdesc);
+        unsafe { tuple_insert(&relation, &mut row) };
+    }
+
+    #[pg_test]
+    fn test_scan_with_snapshot() {
+        create_source();
+        let source = PgRelation::open_with_name_and_share_lock("tableam_source").unwrap();
+        let snapshot = unsafe { pg_sys::RegisterSnapshot(pg_sys::GetTransactionSnapshot()) };
+
+        // rows inserted after the snapshot was taken aren't visible to it
+        Spi::run("INSERT INTO tableam_source VALUES (1001, 'value 1001')");
+        let count = unsafe { TableScan::begin_with_snapshot(&source, Some(snapshot), &[]) }.count();
+        unsafe { pg_sys::UnregisterSnapshot(snapshot) };
+        assert_eq!(count, 1000);
+        assert_eq!(TableScan::begin(&source, &[]).count(), 1001);
+    }
+
+    #[pg_test(error = "relation \"tableam_partitioned\" is not a table")]
+    fn test_insert_partitioned_table() {
+        Spi::run("CREATE TABLE tableam_partitioned (id int) PARTITION BY RANGE (id)");
+        let relation = open_for_write("tableam_partitioned");
+        let tupdesc = relation.tuple_desc();
+        let mut row = PgTupleTableSlot::new(&tupdesc);
+        row.store_heap_tuple(tupdesc.form_tuple(&[1i32.into_datum()]));
+        unsafe { tuple_insert(&relation, &mut row) };
+    }
+
+    #[pg_test(error = "relation \"tableam_view_write\" is not a table")]
+    fn test_delete_view() {
+        Spi::run("CREATE VIEW tableam_view_write AS SELECT 1 AS id");
+        let relation = open_for_write("tableam_view_write");
+        unsafe { tuple_delete(&relation, pg_sys::ItemPointerData::default()) };
+    }
+
+    #[pg_test(
+        error = "attribute 1 of relation \"tableam_types\" has type oid 23 but the TupleTableSlot's has type oid 25"
+    )]
+    fn test_insert_mismatched_types() {
+        Spi::run("CREATE TABLE tableam_types (id int)");
+        let relation = open_for_write("tableam_types");
+        let tupdesc = PgTupleDesc::builder()
+            .column("id", PgOid::from(pg_sys::TEXTOID), -1)
+            .build();
+        let mut row = PgTupleTableSlot::new(&tupdesc);
+        row.store_heap_tuple(tupdesc.form_tuple(&["1".into_datum()]));
+        unsafe { tuple_insert(&relation, &mut row) };
+    }
+}
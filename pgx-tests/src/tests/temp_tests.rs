@@ -0,0 +1,184 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::temp::{create_temp_table, OnCommit, TempTableColumns};
+    use pgx::*;
+
+    fn temp_table_exists(name: &str) -> bool {
+        Spi::get_one::<bool>(&format!(
+            "SELECT to_regclass({}) IS NOT NULL",
+            quote::quote_literal(&format!("pg_temp.{}", quote::quote_identifier(name)))
+        ))
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn test_create_temp_table() {
+        let name = {
+            let table = create_temp_table(
+                "temp_basic",
+                TempTableColumns::Columns(&[("id", "int"), ("value", "text")]),
+                OnCommit::Drop,
+            );
+            assert_eq!(table.name(), "temp_basic");
+            assert_eq!(table.qualified_name(), "pg_temp.temp_basic");
+            assert_eq!(table.on_commit(), OnCommit::Drop);
+
+            // derefs to the opened relation
+            assert_eq!(table.oid(), table.rd_id);
+            assert_eq!(table.name(), PgRelation::name(&table));
+
+            let cnt = table.copy_in(
+                &["id", "value"],
+                (1..=100).map(|i| vec![Some(i.to_string()), Some(format!("value {}", i))]),
+            );
+            assert_eq!(cnt, 100);
+            assert_eq!(
+                Spi::get_one::<i64>(&format!("SELECT sum(id) FROM {}", table.qualified_name())),
+                Some(5050)
+            );
+            table.name().to_string()
+        };
+
+        // dropping the TempTable drops the table
+        assert!(!temp_table_exists(&name));
+    }
+
+    #[pg_test]
+    fn test_temp_table_name_collision() {
+        Spi::run("CREATE TEMPORARY TABLE temp_collision (id int)");
+
+        let first = create_temp_table("temp_collision", &[("id", "int")][..], OnCommit::Drop);
+        let second = create_temp_table("temp_collision", &[("id", "int")][..], OnCommit::Drop);
+        assert_eq!(first.name(), "temp_collision_1");
+        assert_eq!(second.name(), "temp_collision_2");
+        assert_ne!(first.oid(), second.oid());
+
+        drop(first);
+        assert!(temp_table_exists("temp_collision"));
+        assert!(!temp_table_exists("temp_collision_1"));
+        assert!(temp_table_exists("temp_collision_2"));
+
+        // the freed name is reused
+        let third = create_temp_table("temp_collision", &[("id", "int")][..], OnCommit::Drop);
+        assert_eq!(third.name(), "temp_collision_1");
+    }
+
+    #[pg_test]
+    fn test_temp_table_name_sanitized() {
+        let table = create_temp_table("My Table!", &[("id", "int")][..], OnCommit::Drop);
+        assert_eq!(table.name(), "my_table_");
+
+        let long_hint = "x".repeat(100);
+        let long = create_temp_table(&long_hint, &[("id", "int")][..], OnCommit::Drop);
+        let longer = create_temp_table(&long_hint, &[("id", "int")][..], OnCommit::Drop);
+        assert_eq!(long.name().len(), pg_sys::NAMEDATALEN as usize - 1);
+        assert_eq!(longer.name().len(), pg_sys::NAMEDATALEN as usize - 1);
+        assert!(longer.name().ends_with("_1"));
+    }
+
+    #[pg_test]
+    fn test_temp_table_from_tupdesc() {
+        Spi::run("CREATE TABLE temp_source (id bigint, dropped int, name varchar(10), amount numeric(8, 2))");
+        Spi::run("ALTER TABLE temp_source DROP COLUMN dropped");
+
+        let source = PgRelation::open_with_name_and_share_lock("temp_source").unwrap();
+        let tupdesc = source.tuple_desc();
+        let table = create_temp_table("temp_copy", &tupdesc, OnCommit::Drop);
+
+        let columns = Spi::get_one::<&str>(&format!(
+            "SELECT string_agg(attname || ' ' || format_type(atttypid, atttypmod), ', ' ORDER BY attnum)
+               FROM pg_attribute
+              WHERE attrelid = {} AND attnum > 0",
+            table.oid()
+        ));
+        assert_eq!(
+            columns,
+            Some("id bigint, name character varying(10), amount numeric(8,2)")
+        );
+    }
+
+    #[pg_test]
+    fn test_temp_table_on_commit_variants() {
+        for on_commit in &[OnCommit::Drop, OnCommit::DeleteRows, OnCommit::Preserve] {
+            let table = create_temp_table("temp_on_commit", &[("id", "int")][..], *on_commit);
+            assert_eq!(table.on_commit(), *on_commit);
+            assert!(temp_table_exists(table.name()));
+        }
+        assert!(!temp_table_exists("temp_on_commit"));
+    }
+
+    #[pg_test]
+    fn test_temp_table_keep() {
+        let table = create_temp_table("temp_keep", &[("id", "int")][..], OnCommit::Drop);
+        let name = table.keep();
+        assert_eq!(name, "temp_keep");
+        assert!(temp_table_exists(&name));
+    }
+
+    #[pg_test]
+    fn test_temp_table_aborted_subtransaction() {
+        let (context, owner) =
+            unsafe { (pg_sys::CurrentMemoryContext, pg_sys::CurrentResourceOwner) };
+
+        unsafe { pg_sys::BeginInternalSubTransaction(std::ptr::null_mut()) };
+        let table = create_temp_table("temp_subxact", &[("id", "int")][..], OnCommit::Preserve);
+        assert!(temp_table_exists("temp_subxact"));
+        unsafe {
+            pg_sys::RollbackAndReleaseCurrentSubTransaction();
+            pg_sys::CurrentMemoryContext = context;
+            pg_sys::CurrentResourceOwner = owner;
+        }
+
+        // the table went away with the subtransaction, and dropping it again is a no-op
+        assert!(!temp_table_exists("temp_subxact"));
+        drop(table);
+    }
+
+    #[pg_test]
+    fn test_temp_table_committed_subtransaction() {
+        let (context, owner) =
+            unsafe { (pg_sys::CurrentMemoryContext, pg_sys::CurrentResourceOwner) };
+
+        unsafe { pg_sys::BeginInternalSubTransaction(std::ptr::null_mut()) };
+        let table = create_temp_table("temp_subxact", &[("id", "int")][..], OnCommit::Preserve);
+        unsafe {
+            pg_sys::ReleaseCurrentSubTransaction();
+            pg_sys::CurrentMemoryContext = context;
+            pg_sys::CurrentResourceOwner = owner;
+        }
+
+        // now owned by the outer transaction
+        assert!(temp_table_exists("temp_subxact"));
+        drop(table);
+        assert!(!temp_table_exists("temp_subxact"));
+    }
+
+    #[pg_test(error = "invalid type name \"int, injected\"")]
+    fn test_temp_table_invalid_type() {
+        create_temp_table(
+            "temp_injected",
+            &[("id", "int, injected")][..],
+            OnCommit::Drop,
+        );
+    }
+
+    #[pg_test]
+    fn test_temp_table_dropped_while_unwinding() {
+        let table = create_temp_table("temp_unwinding", &[("id", "int")][..], OnCommit::Preserve);
+        let table = std::panic::AssertUnwindSafe(table);
+        let result = pg_try_subtransaction(move || {
+            let _table = table;
+            panic!("while the table is in use");
+        });
+        unsafe { result.unwrap_or(()) };
+
+        // it's left to be dropped when the transaction commits
+        assert!(temp_table_exists("temp_unwinding"));
+    }
+}
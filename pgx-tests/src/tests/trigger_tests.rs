@@ -0,0 +1,296 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+fn int_arg(value: Option<i32>) -> (PgOid, Option<pg_sys::Datum>) {
+    (PgBuiltInOids::INT4OID.oid(), value.into_datum())
+}
+
+fn text_arg(value: Option<&str>) -> (PgOid, Option<pg_sys::Datum>) {
+    (PgBuiltInOids::TEXTOID.oid(), value.into_datum())
+}
+
+/// The id of the customer named `name`, who is created if they don't exist yet
+fn customer_id(name: &str) -> i32 {
+    Spi::get_one_with_args::<i32>(
+        "SELECT id FROM customers WHERE name = $1",
+        vec![text_arg(Some(name))],
+    )
+    .or_else(|| {
+        Spi::get_one_with_args::<i32>(
+            "INSERT INTO customers (name) VALUES ($1) RETURNING id",
+            vec![text_arg(Some(name))],
+        )
+    })
+    .expect("customer id was NULL")
+}
+
+/// Makes the `order_details` view, a join of `orders` and `customers`, updatable:  DML on the
+/// view is applied to the `orders` table, and customers are created as they're named
+#[pg_extern]
+fn order_details_dml(fcinfo: pg_sys::FunctionCallInfo) -> TriggerResult {
+    let trigger = PgTrigger::from_fcinfo(fcinfo);
+    if !trigger.fired_instead() || !trigger.fired_for_row() {
+        panic!("order_details_dml() must be fired INSTEAD OF each row");
+    }
+
+    if trigger.fired_by_delete() {
+        let old = trigger.old_row().expect("DELETE has no OLD row");
+        let deleted = Spi::update(
+            "DELETE FROM orders WHERE id = $1",
+            vec![int_arg(old.get_by_name("id"))],
+        );
+        return if deleted == 0 {
+            TriggerResult::Skip
+        } else {
+            trigger.processed()
+        };
+    }
+
+    let new = trigger.new_row().expect("INSERT or UPDATE has no NEW row");
+    let customer = new
+        .get_by_name::<String>("customer")
+        .expect("customer is NULL");
+    let item = new.get_by_name::<String>("item");
+    let quantity = new.get_by_name::<i32>("quantity");
+    let customer_id = customer_id(&customer);
+
+    if trigger.fired_by_insert() {
+        let id = Spi::get_one_with_args::<i32>(
+            "INSERT INTO orders (customer_id, item, quantity) VALUES ($1, $2, $3) RETURNING id",
+            vec![
+                int_arg(Some(customer_id)),
+                text_arg(item.as_deref()),
+                int_arg(quantity),
+            ],
+        )
+        .expect("order id was NULL");
+
+        // the view's row, with the generated id for a RETURNING clause
+        unsafe {
+            trigger.processed_with(&[
+                id.into_datum(),
+                customer.into_datum(),
+                item.into_datum(),
+                quantity.into_datum(),
+            ])
+        }
+    } else {
+        let old = trigger.old_row().expect("UPDATE has no OLD row");
+        let updated = Spi::update(
+            "UPDATE orders SET customer_id = $1, item = $2, quantity = $3 WHERE id = $4",
+            vec![
+                int_arg(Some(customer_id)),
+                text_arg(item.as_deref()),
+                int_arg(quantity),
+                int_arg(old.get_by_name("id")),
+            ],
+        );
+        if updated == 0 {
+            TriggerResult::Skip
+        } else {
+            trigger.processed()
+        }
+    }
+}
+
+/// A `BEFORE INSERT` trigger that skips rows whose column named by the trigger's first argument
+/// is an empty string
+#[pg_extern]
+fn skip_empty_values(fcinfo: pg_sys::FunctionCallInfo) -> TriggerResult {
+    let trigger = PgTrigger::from_fcinfo(fcinfo);
+    let column = trigger.args()[0];
+    let new = trigger.new_row().expect("not a row-level INSERT trigger");
+    match new.get_by_name::<&str>(column) {
+        Some("") => TriggerResult::Skip,
+        _ => TriggerResult::from(new),
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn create_order_details() {
+        Spi::run("CREATE TABLE customers (id serial PRIMARY KEY, name text NOT NULL UNIQUE)");
+        Spi::run(
+            "CREATE TABLE orders (
+                id serial PRIMARY KEY,
+                customer_id int NOT NULL REFERENCES customers,
+                item text,
+                quantity int
+            )",
+        );
+
+        // a join isn't automatically updatable
+        Spi::run(
+            "CREATE VIEW order_details AS
+             SELECT o.id, c.name AS customer, o.item, o.quantity
+               FROM orders o JOIN customers c ON c.id = o.customer_id",
+        );
+        Spi::run(
+            "CREATE TRIGGER order_details_dml INSTEAD OF INSERT OR UPDATE OR DELETE ON order_details
+             FOR EACH ROW EXECUTE PROCEDURE order_details_dml()",
+        );
+
+        Spi::run(
+            "INSERT INTO order_details (customer, item, quantity)
+             VALUES ('alice', 'apple', 3), ('bob', 'pear', 1), ('alice', 'plum', 2)",
+        );
+    }
+
+    /// The contents of the base tables, in order id order
+    fn orders() -> Option<String> {
+        Spi::get_one::<String>(
+            "SELECT string_agg(format('%s:%s:%s', c.name, o.item, o.quantity), ', ' ORDER BY o.id)
+               FROM orders o JOIN customers c ON c.id = o.customer_id",
+        )
+    }
+
+    #[pg_test]
+    fn test_insert_through_view() {
+        create_order_details();
+        assert_eq!(
+            orders(),
+            Some("alice:apple:3, bob:pear:1, alice:plum:2".to_string())
+        );
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM customers"),
+            Some(2)
+        );
+    }
+
+    #[pg_test]
+    fn test_insert_returning() {
+        create_order_details();
+        let id = Spi::get_one::<i32>(
+            "INSERT INTO order_details (customer, item, quantity) VALUES ('carol', 'fig', 5)
+             RETURNING id",
+        );
+        assert_eq!(id, Spi::get_one::<i32>("SELECT max(id) FROM orders"));
+        assert_eq!(
+            Spi::get_one::<String>(&format!(
+                "SELECT customer FROM order_details WHERE id = {}",
+                id.unwrap()
+            )),
+            Some("carol".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_update_through_view() {
+        create_order_details();
+        let updated = Spi::update(
+            "UPDATE order_details SET quantity = quantity + 10, customer = 'carol'
+              WHERE customer = 'alice'",
+            vec![],
+        );
+        assert_eq!(updated, 2);
+        assert_eq!(
+            orders(),
+            Some("carol:apple:13, bob:pear:1, carol:plum:12".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_delete_through_view() {
+        create_order_details();
+        let deleted = Spi::update("DELETE FROM order_details WHERE item <> 'pear'", vec![]);
+        assert_eq!(deleted, 2);
+        assert_eq!(orders(), Some("bob:pear:1".to_string()));
+
+        // customers aren't deleted with their orders
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM customers"),
+            Some(2)
+        );
+    }
+
+    #[pg_test]
+    fn test_skipped_rows() {
+        Spi::run("CREATE TABLE names (name text)");
+        Spi::run(
+            "CREATE TRIGGER skip_empty_names BEFORE INSERT ON names
+             FOR EACH ROW EXECUTE PROCEDURE skip_empty_values('name')",
+        );
+
+        let inserted = Spi::update(
+            "INSERT INTO names VALUES ('a'), (''), (NULL), ('b')",
+            vec![],
+        );
+        assert_eq!(inserted, 3);
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM names WHERE name IS NOT NULL"),
+            Some(2)
+        );
+    }
+
+    #[pg_test]
+    fn test_relation_triggers() {
+        create_order_details();
+        Spi::run(
+            "CREATE TRIGGER b_skip_empty_items BEFORE INSERT OR UPDATE ON orders
+             FOR EACH ROW EXECUTE PROCEDURE skip_empty_values('item')",
+        );
+        Spi::run(
+            "CREATE TRIGGER a_orders_truncate AFTER TRUNCATE ON orders
+             FOR EACH STATEMENT EXECUTE PROCEDURE skip_empty_values('item', 'ignored')",
+        );
+
+        let orders = PgRelation::open_with_name_and_share_lock("orders").unwrap();
+        let triggers = orders
+            .triggers()
+            .into_iter()
+            .filter(|trigger| !trigger.is_internal)
+            .collect::<Vec<_>>();
+        let names = triggers.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["a_orders_truncate", "b_skip_empty_items"]);
+
+        let truncate = &triggers[0];
+        assert!(!truncate.is_row());
+        assert!(truncate.is_after());
+        assert!(truncate.on_truncate());
+        assert!(!truncate.on_insert() && !truncate.on_update() && !truncate.on_delete());
+        assert_eq!(truncate.args, vec!["item", "ignored"]);
+
+        let skip = &triggers[1];
+        assert!(skip.is_row());
+        assert!(skip.is_before());
+        assert!(skip.on_insert() && skip.on_update());
+        assert!(!skip.on_delete() && !skip.on_truncate());
+        assert_eq!(
+            Some(skip.function_oid),
+            Spi::get_one::<pg_sys::Oid>("SELECT 'skip_empty_values'::regproc::oid")
+        );
+
+        // the foreign key to customers is enforced by internal triggers
+        assert!(orders.triggers().iter().any(|trigger| trigger.is_internal));
+
+        let view = PgRelation::open_with_name_and_share_lock("order_details").unwrap();
+        let view_triggers = view.triggers();
+        assert_eq!(view_triggers.len(), 1);
+        assert!(view_triggers[0].is_instead());
+        assert!(view_triggers[0].on_insert() && view_triggers[0].on_delete());
+    }
+
+    #[pg_test]
+    fn test_relation_without_triggers() {
+        Spi::run("CREATE TABLE no_triggers (id int)");
+        let relation = PgRelation::open_with_name_and_share_lock("no_triggers").unwrap();
+        assert!(relation.triggers().is_empty());
+    }
+
+    #[pg_extern]
+    fn trigger_name(fcinfo: pg_sys::FunctionCallInfo) -> String {
+        PgTrigger::from_fcinfo(fcinfo).name().to_string()
+    }
+
+    #[pg_test(error = "function was not called by the trigger manager")]
+    fn test_not_a_trigger() {
+        Spi::get_one::<String>("SELECT tests.trigger_name()");
+    }
+}
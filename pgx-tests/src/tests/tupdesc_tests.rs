@@ -0,0 +1,246 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    fn three_column_builder() -> PgTupleDescBuilder {
+        PgTupleDesc::builder()
+            .column_for::<i64>("id")
+            .column_for::<String>("name")
+            .column(
+                "price",
+                PgBuiltInOids::NUMERICOID.oid(),
+                // numeric(10, 2)
+                ((10 << 16) | 2) + pg_sys::VARHDRSZ as i32,
+            )
+    }
+
+    #[cfg(any(feature = "pg10", feature = "pg11"))]
+    unsafe fn make_slot(tupdesc: &PgTupleDesc) -> *mut pg_sys::TupleTableSlot {
+        pg_sys::MakeSingleTupleTableSlot(tupdesc.as_ptr())
+    }
+
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    unsafe fn make_slot(tupdesc: &PgTupleDesc) -> *mut pg_sys::TupleTableSlot {
+        pg_sys::MakeSingleTupleTableSlot(tupdesc.as_ptr(), &pg_sys::TTSOpsMinimalTuple)
+    }
+
+    #[cfg(any(feature = "pg10", feature = "pg11"))]
+    unsafe fn fetch_slot_datum(slot: *mut pg_sys::TupleTableSlot) -> pg_sys::Datum {
+        pg_sys::ExecFetchSlotTupleDatum(slot)
+    }
+
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    unsafe fn fetch_slot_datum(slot: *mut pg_sys::TupleTableSlot) -> pg_sys::Datum {
+        pg_sys::ExecFetchSlotHeapTupleDatum(slot)
+    }
+
+    #[pg_test]
+    fn test_tupdesc_builder() {
+        let tupdesc = three_column_builder().build();
+        assert_eq!(tupdesc.len(), 3);
+        assert_eq!(tupdesc.oid(), pg_sys::RECORDOID);
+        assert!(!tupdesc.is_blessed());
+
+        let columns = tupdesc
+            .iter()
+            .map(|attr| {
+                (
+                    name_data_to_str(&attr.attname).to_string(),
+                    attr.atttypid,
+                    attr.atttypmod,
+                    attr.attcollation,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            vec![
+                ("id".to_string(), pg_sys::INT8OID, -1, pg_sys::InvalidOid),
+                ("name".to_string(), pg_sys::TEXTOID, -1, unsafe {
+                    pg_sys::get_typcollation(pg_sys::TEXTOID)
+                }),
+                (
+                    "price".to_string(),
+                    pg_sys::NUMERICOID,
+                    ((10 << 16) | 2) + pg_sys::VARHDRSZ as i32,
+                    pg_sys::InvalidOid
+                ),
+            ]
+        );
+        assert_ne!(columns[1].3, pg_sys::InvalidOid);
+    }
+
+    #[pg_test]
+    fn test_tupdesc_builder_collation() {
+        let c_collation =
+            Spi::get_one::<i64>("SELECT oid::bigint FROM pg_collation WHERE collname = 'C'")
+                .unwrap() as pg_sys::Oid;
+        let tupdesc = PgTupleDesc::builder()
+            .column_for::<String>("default_collation")
+            .column_for::<String>("c_collation")
+            .collation(c_collation)
+            .build();
+
+        assert_ne!(tupdesc.get(0).unwrap().attcollation, c_collation);
+        assert_eq!(tupdesc.get(1).unwrap().attcollation, c_collation);
+    }
+
+    #[pg_test]
+    fn test_tupdesc_builder_bless() {
+        let tupdesc = three_column_builder().bless().build();
+        assert_eq!(tupdesc.oid(), pg_sys::RECORDOID);
+        assert!(tupdesc.typmod() >= 0);
+        assert!(tupdesc.is_blessed());
+    }
+
+    #[pg_test]
+    fn test_tupdesc_builder_tuplestore_roundtrip() {
+        let tupdesc = three_column_builder().bless().build();
+
+        unsafe {
+            let store = pg_sys::tuplestore_begin_heap(false, false, pg_sys::work_mem);
+            for i in 1..=10i64 {
+                let name = if i % 2 == 0 {
+                    Some(format!("item {}", i))
+                } else {
+                    None
+                };
+                let price = Numeric(format!("{}.25", i)).into_datum();
+                let tuple = tupdesc.form_tuple(&[i.into_datum(), name.into_datum(), price]);
+                pg_sys::tuplestore_puttuple(store, tuple.as_ptr());
+            }
+
+            let slot = make_slot(&tupdesc);
+            let mut rows = Vec::new();
+            while pg_sys::tuplestore_gettupleslot(store, true, false, slot) {
+                let row = PgTupleDesc::from_composite(fetch_slot_datum(slot));
+                assert_eq!(row.typmod(), tupdesc.typmod());
+                rows.push((
+                    row.get_attr::<i64>(0),
+                    row.get_attr::<String>(1),
+                    row.get_attr::<Numeric>(2).map(|n| n.0),
+                ));
+            }
+            pg_sys::ExecDropSingleTupleTableSlot(slot);
+            pg_sys::tuplestore_end(store);
+
+            assert_eq!(rows.len(), 10);
+            assert_eq!(rows[0], (Some(1), None, Some("1.25".to_string())));
+            assert_eq!(
+                rows[9],
+                (
+                    Some(10),
+                    Some("item 10".to_string()),
+                    Some("10.25".to_string())
+                )
+            );
+        }
+    }
+
+    #[pg_test(
+        error = "cannot form a tuple from a RECORD tuple descriptor that has not been blessed"
+    )]
+    fn test_tupdesc_form_tuple_unblessed() {
+        let tupdesc = three_column_builder().build();
+        tupdesc.form_tuple(&[1i64.into_datum(), None, None]);
+    }
+
+    #[pg_test(error = "tuple descriptor has 3 attributes but 2 values were provided")]
+    fn test_tupdesc_form_tuple_wrong_arity() {
+        let tupdesc = three_column_builder().bless().build();
+        tupdesc.form_tuple(&[1i64.into_datum(), None]);
+    }
+
+    #[pg_test(error = "column \"id\" specified more than once")]
+    fn test_tupdesc_builder_duplicate_name() {
+        three_column_builder().column_for::<i32>("id").build();
+    }
+
+    #[pg_test(error = "tuple descriptors can have at most 1664 columns, not 1665")]
+    fn test_tupdesc_builder_too_many_columns() {
+        let mut builder = PgTupleDesc::builder();
+        for i in 0..1665 {
+            builder = builder.column_for::<i32>(&format!("c{}", i));
+        }
+        builder.build();
+    }
+
+    #[pg_test]
+    fn test_tupdesc_iter_skips_dropped() {
+        Spi::run(
+            "CREATE TABLE tupdesc_dropped (id bigint, dropped int, name varchar(32), flag bool)",
+        );
+        Spi::run("ALTER TABLE tupdesc_dropped DROP COLUMN dropped");
+
+        let relation = PgRelation::open_with_name_and_share_lock("tupdesc_dropped").unwrap();
+        let tupdesc = relation.tuple_desc();
+        assert_eq!(tupdesc.len(), 4);
+
+        let columns = tupdesc
+            .iter()
+            .map(|attr| {
+                (
+                    attr.name().to_string(),
+                    attr.num(),
+                    attr.type_oid(),
+                    attr.type_mod(),
+                    attr.len(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            vec![
+                ("id".to_string(), 1, pg_sys::INT8OID, -1, 8),
+                (
+                    "name".to_string(),
+                    3,
+                    pg_sys::VARCHAROID,
+                    32 + pg_sys::VARHDRSZ as i32,
+                    -1
+                ),
+                ("flag".to_string(), 4, pg_sys::BOOLOID, -1, 1),
+            ]
+        );
+        assert!(tupdesc.iter().all(|attr| !attr.is_dropped()));
+
+        // every attribute, which is one per value of the table's tuples
+        let all = tupdesc.iter_all().collect::<Vec<_>>();
+        assert_eq!(all.len(), 4);
+        assert_eq!(
+            all.iter().map(|attr| attr.num()).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(all[1].is_dropped());
+        assert_eq!(all[1].attrelid, relation.oid());
+
+        // as does into_iter()
+        let nums = relation
+            .tuple_desc()
+            .into_iter()
+            .map(|attr| attr.attnum)
+            .collect::<Vec<_>>();
+        assert_eq!(nums, vec![1, 3, 4]);
+    }
+
+    #[pg_test]
+    fn test_tupdesc_get() {
+        Spi::run("CREATE TABLE tupdesc_get (id bigint, dropped int)");
+        Spi::run("ALTER TABLE tupdesc_get DROP COLUMN dropped");
+
+        let relation = PgRelation::open_with_name_and_share_lock("tupdesc_get").unwrap();
+        let tupdesc = relation.tuple_desc();
+        assert_eq!(tupdesc.get(0).map(|attr| attr.name()), Some("id"));
+
+        // dropped attributes are still there, by number
+        assert!(tupdesc.get(1).unwrap().is_dropped());
+        assert!(tupdesc.get(2).is_none());
+        assert!(tupdesc.get(usize::MAX).is_none());
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+
+#[pg_extern]
+fn varbit_roundtrip(bits: PgBitString) -> PgBitString {
+    bits
+}
+
+#[pg_extern]
+fn varbit_invert(bits: PgBitString) -> PgBitString {
+    bits.iter().map(|bit| !bit).collect()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+
+    /// Every third bit set, starting with the first
+    fn every_third_bit(len: usize) -> PgBitString {
+        (0..len).map(|i| i % 3 == 0).collect()
+    }
+
+    #[pg_test]
+    fn test_varbit_into_datum() {
+        for len in &[0, 1, 7, 8, 9, 15, 16, 17, 33] {
+            let bits = every_third_bit(*len);
+            let expected = bits.to_string();
+            let rc = Spi::get_one_with_args::<bool>(
+                "SELECT $1 = $2::varbit AND length($1) = $3",
+                vec![
+                    (PgBuiltInOids::VARBITOID.oid(), bits.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), expected.clone().into_datum()),
+                    (PgBuiltInOids::INT4OID.oid(), (*len as i32).into_datum()),
+                ],
+            );
+            assert_eq!(rc, Some(true), "{}", expected);
+        }
+    }
+
+    #[pg_test]
+    fn test_varbit_from_datum() {
+        for len in &[0, 1, 7, 8, 9, 15, 16, 17, 33] {
+            let expected = every_third_bit(*len);
+            let bits = Spi::get_one_with_args::<PgBitString>(
+                "SELECT $1::varbit",
+                vec![(
+                    PgBuiltInOids::TEXTOID.oid(),
+                    expected.to_string().into_datum(),
+                )],
+            );
+            assert_eq!(bits.as_ref().map(PgBitString::len_bits), Some(*len));
+            assert_eq!(bits, Some(expected));
+        }
+    }
+
+    #[pg_test]
+    fn test_fixed_length_bit() {
+        let bits = Spi::get_one::<PgBitString>("SELECT B'101'::bit(5)").unwrap();
+        assert_eq!(bits.len_bits(), 5);
+        assert_eq!(bits.to_string(), "10100");
+        assert!(bits.get_bit(0));
+        assert!(!bits.get_bit(1));
+        assert!(bits.get_bit(2));
+        assert_eq!(bits.as_bytes(), &[0b1010_0000]);
+
+        Spi::run("CREATE TABLE bit_table (flags bit(5))");
+        Spi::run("INSERT INTO bit_table VALUES (varbit_roundtrip(B'10011'))");
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT flags = B'10011' FROM bit_table"),
+            Some(true)
+        );
+    }
+
+    #[pg_test]
+    fn test_varbit_roundtrip() {
+        let rc = Spi::get_one::<bool>(
+            "SELECT bool_and(varbit_roundtrip(b) = b AND varbit_invert(b) = ~b)
+               FROM (VALUES (B''), (B'1'), (B'0110101'), (B'10110011'), (B'101100111')) v(b)",
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_varbit_padding_is_cleared() {
+        // Postgres compares the unused bits of the last byte, so they have to be zeroed
+        let bits = PgBitString::from_bytes(&[0xFF, 0xFF], 11);
+        assert_eq!(bits.as_bytes(), &[0xFF, 0b1110_0000]);
+
+        let rc = Spi::get_one_with_args::<bool>(
+            "SELECT $1 = B'11111111111'",
+            vec![(PgBuiltInOids::VARBITOID.oid(), bits.into_datum())],
+        );
+        assert_eq!(rc, Some(true));
+    }
+
+    #[pg_test]
+    fn test_set_bit() {
+        let mut bits = PgBitString::zeros(10);
+        bits.set_bit(9, true);
+        bits.set_bit(0, true);
+        bits.set_bit(0, false);
+        assert_eq!(bits.to_string(), "0000000001");
+    }
+
+    #[pg_test(error = "bit index 9 is out of bounds for a bit string of 9 bits")]
+    fn test_get_bit_out_of_bounds() {
+        every_third_bit(9).get_bit(9);
+    }
+
+    #[cfg(feature = "bitvec")]
+    #[pg_test]
+    fn test_bitvec_conversions() {
+        use pgx::bitvec::prelude::*;
+
+        let bits = every_third_bit(13);
+        let vec: BitVec<u8, Msb0> = bits.clone().into();
+        assert_eq!(vec.len(), 13);
+        assert!(vec.iter().enumerate().all(|(i, bit)| *bit == (i % 3 == 0)));
+        assert_eq!(PgBitString::from(vec.clone()), bits);
+
+        let lsb0 = vec.iter().map(|bit| *bit).collect::<BitVec<u64, Lsb0>>();
+        assert_eq!(PgBitString::from(lsb0.as_bitslice()), bits);
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+    use std::io::{Read, Seek, SeekFrom};
+
+    const READ_SIZE: usize = 64 * 1024;
+
+    #[pg_extern]
+    fn streaming_hash(data: VarlenaReader, chunk_size: i32) -> i64 {
+        let mut data = data.with_chunk_size(chunk_size as usize);
+        let mut hasher = DefaultHasher::new();
+        let mut buffer = vec![0; READ_SIZE];
+        loop {
+            let n = data.read(&mut buffer).expect("failed to read");
+            if n == 0 {
+                break;
+            }
+            hasher.write(&buffer[..n]);
+        }
+        hasher.finish() as i64
+    }
+
+    #[pg_extern]
+    fn in_memory_hash(data: &[u8]) -> i64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(data);
+        hasher.finish() as i64
+    }
+
+    #[pg_extern]
+    fn is_chunked(data: VarlenaReader) -> bool {
+        data.is_chunked()
+    }
+
+    #[pg_extern]
+    fn text_is_chunked(data: TextReader) -> bool {
+        data.is_chunked()
+    }
+
+    #[pg_extern]
+    fn read_text(mut data: TextReader) -> String {
+        let mut s = String::new();
+        data.read_to_string(&mut s).expect("failed to read");
+        s
+    }
+
+    #[pg_extern]
+    fn read_at(mut data: VarlenaReader, offset: i64, len: i32) -> Vec<u8> {
+        let mut buffer = vec![0; len as usize];
+        if offset < 0 {
+            data.seek(SeekFrom::End(offset)).expect("failed to seek");
+        } else {
+            data.seek(SeekFrom::Start(offset as u64))
+                .expect("failed to seek");
+        }
+        data.read_exact(&mut buffer)
+            .unwrap_or_else(|e| panic!("failed to read: {}", e));
+        buffer
+    }
+
+    /// The most memory allocated in the `CurrentMemoryContext`, beyond what it started with,
+    /// while reading the whole value
+    #[cfg(feature = "pg13")]
+    #[pg_extern]
+    fn streaming_peak_memory(data: VarlenaReader, chunk_size: i32) -> i64 {
+        let allocated = || unsafe {
+            pg_sys::MemoryContextMemAllocated(pg_sys::CurrentMemoryContext, true) as i64
+        };
+
+        let mut data = data.with_chunk_size(chunk_size as usize);
+        let mut buffer = vec![0; READ_SIZE];
+        let baseline = allocated();
+        let mut peak = 0;
+        loop {
+            let n = data.read(&mut buffer).expect("failed to read");
+            peak = std::cmp::max(peak, allocated() - baseline);
+            if n == 0 {
+                break;
+            }
+        }
+        peak
+    }
+
+    /// A table whose values are stored uncompressed in its TOAST table
+    fn create_external_blobs(size: usize) {
+        Spi::run("CREATE TABLE blobs (id int, data bytea)");
+        Spi::run("ALTER TABLE blobs ALTER COLUMN data SET STORAGE EXTERNAL");
+        Spi::run(&format!(
+            "INSERT INTO blobs VALUES (1, convert_to(repeat('0123456789abcdef', {}), 'UTF8'))",
+            size / 16
+        ));
+    }
+
+    #[pg_test]
+    fn test_external_value_is_chunked() {
+        create_external_blobs(1024 * 1024);
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT tests.is_chunked(data) FROM blobs"),
+            Some(true)
+        );
+        assert_eq!(
+            Spi::get_one::<i32>("SELECT length(data) FROM blobs"),
+            Some(1024 * 1024)
+        );
+    }
+
+    #[pg_test]
+    fn test_100mb_external_value() {
+        create_external_blobs(100 * 1024 * 1024);
+        assert_eq!(
+            Spi::get_one::<bool>(&format!(
+                "SELECT tests.streaming_hash(data, {}) = tests.in_memory_hash(data) FROM blobs",
+                VarlenaReader::DEFAULT_CHUNK_SIZE
+            )),
+            Some(true)
+        );
+
+        assert_eq!(
+            Spi::get_one::<&[u8]>(
+                "SELECT tests.read_at(data, 100 * 1024 * 1024 - 20, 4) FROM blobs"
+            ),
+            Some(&b"cdef"[..])
+        );
+    }
+
+    #[cfg(feature = "pg13")]
+    #[pg_test]
+    fn test_100mb_external_value_memory() {
+        create_external_blobs(100 * 1024 * 1024);
+        let chunk_size = 256 * 1024;
+        let peak = Spi::get_one::<i64>(&format!(
+            "SELECT tests.streaming_peak_memory(data, {}) FROM blobs",
+            chunk_size
+        ))
+        .unwrap();
+
+        // one chunk, plus a little overhead for each fetch from the TOAST table, rather than the
+        // whole 100MB
+        assert!(peak >= chunk_size);
+        assert!(
+            peak < 4 * chunk_size,
+            "peak memory was {} bytes for a chunk size of {}",
+            peak,
+            chunk_size
+        );
+    }
+
+    #[pg_test]
+    fn test_chunk_boundaries() {
+        create_external_blobs(1024 * 1024);
+
+        // chunks that neither divide the reads nor the TOAST table's own chunks
+        for chunk_size in &[1000, 1999, READ_SIZE + 1, 1024 * 1024, 2 * 1024 * 1024] {
+            assert_eq!(
+                Spi::get_one::<bool>(&format!(
+                    "SELECT tests.streaming_hash(data, {}) = tests.in_memory_hash(data) FROM blobs",
+                    chunk_size
+                )),
+                Some(true),
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_seek() {
+        create_external_blobs(1024 * 1024);
+
+        for (offset, expected) in &[
+            (0, "01234567"),
+            (13, "def01234"),
+            (1024 * 1024 - 8, "89abcdef"),
+            (-8, "89abcdef"),
+            (-1024 * 1024, "01234567"),
+        ] {
+            assert_eq!(
+                Spi::get_one::<&[u8]>(&format!(
+                    "SELECT tests.read_at(data, {}, 8) FROM blobs",
+                    offset
+                )),
+                Some(expected.as_bytes()),
+                "offset {}",
+                offset
+            );
+        }
+    }
+
+    #[pg_test(error = "failed to read: failed to fill whole buffer")]
+    fn test_read_past_end() {
+        create_external_blobs(1024 * 1024);
+        Spi::get_one::<&[u8]>("SELECT tests.read_at(data, 1024 * 1024 - 4, 8) FROM blobs");
+    }
+
+    #[pg_test]
+    fn test_in_memory_values() {
+        // short, and compressed in-line
+        Spi::run("CREATE TABLE small_values (id int, data bytea)");
+        Spi::run(
+            "INSERT INTO small_values VALUES (1, 'abc'::bytea), (2, convert_to(repeat('a', 100000), 'UTF8'))",
+        );
+        assert_eq!(
+            Spi::get_one::<i32>(
+                "SELECT count(*)::int FROM small_values WHERE pg_column_size(data) < length(data)"
+            ),
+            Some(1)
+        );
+
+        for id in 1..=2 {
+            assert_eq!(
+                Spi::get_two::<bool, bool>(&format!(
+                    "SELECT tests.is_chunked(data),
+                            tests.streaming_hash(data, 1000) = tests.in_memory_hash(data)
+                       FROM small_values WHERE id = {}",
+                    id
+                )),
+                (Some(false), Some(true))
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_text_reader() {
+        assert_eq!(
+            Spi::get_one::<&str>("SELECT tests.read_text('hello, world')"),
+            Some("hello, world")
+        );
+
+        Spi::run("CREATE TABLE texts (data text)");
+        Spi::run("ALTER TABLE texts ALTER COLUMN data SET STORAGE EXTERNAL");
+        Spi::run("INSERT INTO texts VALUES (repeat('ü', 100000))");
+        assert_eq!(
+            Spi::get_two::<bool, bool>(
+                "SELECT tests.text_is_chunked(data), tests.read_text(data) = data FROM texts"
+            ),
+            (Some(true), Some(true))
+        );
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::wal::{LogicalMessage, WalMessage};
+use pgx::*;
+
+struct TestMessage(String);
+
+impl WalMessage for TestMessage {
+    const PREFIX: &'static str = "pgx_tests.wal";
+
+    fn to_payload(&self) -> Vec<u8> {
+        self.0.as_bytes().to_vec()
+    }
+
+    fn from_payload(payload: &[u8]) -> Option<Self> {
+        String::from_utf8(payload.to_vec()).ok().map(TestMessage)
+    }
+}
+
+#[pg_extern]
+fn wal_tests_log(message: String, transactional: bool) -> i64 {
+    pgx::wal::log_message(&TestMessage(message), transactional) as i64
+}
+
+// the tests decode with this library as the output plugin, `pgx_tests`, which outputs the
+// `TestMessage`s it decodes as "T <message>" or "N <message>", for whether they're transactional
+#[pg_guard]
+#[no_mangle]
+pub unsafe extern "C" fn _PG_output_plugin_init(cb: *mut pg_sys::OutputPluginCallbacks) {
+    (*cb).startup_cb = Some(startup);
+    (*cb).begin_cb = Some(begin);
+    (*cb).change_cb = Some(change);
+    (*cb).commit_cb = Some(commit);
+    (*cb).message_cb = Some(message);
+}
+
+#[pg_guard]
+unsafe extern "C" fn startup(
+    _ctx: *mut pg_sys::LogicalDecodingContext,
+    options: *mut pg_sys::OutputPluginOptions,
+    _is_init: bool,
+) {
+    (*options).output_type = pg_sys::OutputPluginOutputType_OUTPUT_PLUGIN_BINARY_OUTPUT;
+}
+
+#[pg_guard]
+unsafe extern "C" fn begin(
+    _ctx: *mut pg_sys::LogicalDecodingContext,
+    _txn: *mut pg_sys::ReorderBufferTXN,
+) {
+}
+
+#[pg_guard]
+unsafe extern "C" fn change(
+    _ctx: *mut pg_sys::LogicalDecodingContext,
+    _txn: *mut pg_sys::ReorderBufferTXN,
+    _relation: pg_sys::Relation,
+    _change: *mut pg_sys::ReorderBufferChange,
+) {
+}
+
+#[pg_guard]
+unsafe extern "C" fn commit(
+    _ctx: *mut pg_sys::LogicalDecodingContext,
+    _txn: *mut pg_sys::ReorderBufferTXN,
+    _commit_lsn: pg_sys::XLogRecPtr,
+) {
+}
+
+#[pg_guard]
+unsafe extern "C" fn message(
+    ctx: *mut pg_sys::LogicalDecodingContext,
+    _txn: *mut pg_sys::ReorderBufferTXN,
+    message_lsn: pg_sys::XLogRecPtr,
+    transactional: bool,
+    prefix: *const std::os::raw::c_char,
+    message_size: pg_sys::Size,
+    message: *const std::os::raw::c_char,
+) {
+    let message =
+        LogicalMessage::from_callback(message_lsn, transactional, prefix, message_size, message);
+    if let Some(TestMessage(text)) = message.decode::<TestMessage>() {
+        let kind = if message.transactional { "T" } else { "N" };
+        pgx::wal::write_output(ctx, format!("{} {}", kind, text).as_bytes(), true);
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use crate::tests::wal_tests::TestMessage;
+    use pgx::wal::WalMessage;
+    use pgx::*;
+
+    const PEEK_MESSAGES: &str = "SELECT coalesce(string_agg(convert_from(data, 'UTF8'), '|'), '')
+                                   FROM pg_logical_slot_peek_binary_changes($1, NULL, NULL)";
+
+    /// Whether the messages can be decoded, which needs `wal_level = logical`.  The test cluster
+    /// only runs with it when `PGX_TESTS_LOGICAL_DECODING` is set
+    fn logical_decoding_enabled() -> bool {
+        Spi::get_one::<&str>("SHOW wal_level") == Some("logical")
+    }
+
+    #[pg_test]
+    fn test_log_message() {
+        let decoding = logical_decoding_enabled();
+        if decoding {
+            Spi::run("SELECT pg_create_logical_replication_slot('wal_tests', 'pgx_tests', true)");
+        }
+
+        // messages are written whatever the wal_level
+        let transactional = pgx::wal::log_message(&TestMessage("transactional".into()), true);
+        let immediate = pgx::wal::log_message(&TestMessage("immediate".into()), false);
+        assert!(immediate > transactional);
+        if !decoding {
+            return;
+        }
+
+        // another prefix, which isn't a TestMessage
+        Spi::run("SELECT pg_logical_emit_message(false, 'pgx_tests.other', 'other')");
+
+        // this transaction hasn't committed, and won't, so only the non-transactional message
+        // has been decoded
+        let messages = Spi::get_one_with_args::<&str>(
+            PEEK_MESSAGES,
+            vec![(PgBuiltInOids::TEXTOID.oid(), "wal_tests".into_datum())],
+        );
+        assert_eq!(messages, Some("N immediate"));
+
+        Spi::run("SELECT pg_drop_replication_slot('wal_tests')");
+    }
+
+    #[pg_test]
+    fn test_decode_by_prefix() {
+        let payload = TestMessage("payload".into()).to_payload();
+        let message = pgx::wal::LogicalMessage {
+            lsn: 0,
+            transactional: false,
+            prefix: std::ffi::CStr::from_bytes_with_nul(b"pgx_tests.wal\0").unwrap(),
+            payload: &payload,
+        };
+        assert_eq!(
+            message.decode::<TestMessage>().map(|m| m.0),
+            Some("payload".to_string())
+        );
+
+        let other = pgx::wal::LogicalMessage {
+            prefix: std::ffi::CStr::from_bytes_with_nul(b"pgx_tests.other\0").unwrap(),
+            ..message
+        };
+        assert!(other.decode::<TestMessage>().is_none());
+    }
+
+    #[pg_test(error = "WAL message prefix contains a NUL")]
+    fn test_prefix_with_nul() {
+        pgx::wal::log_custom_message("pgx\0tests", b"payload", false);
+    }
+
+    /// Transactional messages are decoded when their transaction commits, and not at all if it
+    /// aborts, and non-transactional ones either way
+    #[test]
+    fn test_committed_and_aborted_messages() {
+        let (mut client, _) =
+            pgx_tests::start_and_connect(crate::pg_test::postgresql_conf_options());
+        let wal_level: String = client
+            .query_one("SHOW wal_level", &[])
+            .expect("couldn't get the wal_level")
+            .get(0);
+        if wal_level != "logical" {
+            return;
+        }
+
+        client
+            .batch_execute(
+                "SELECT pg_create_logical_replication_slot('wal_tests_sessions', 'pgx_tests', true)",
+            )
+            .expect("couldn't create the replication slot");
+        client
+            .batch_execute(
+                "BEGIN;
+                 SELECT wal_tests_log('committed', true);
+                 SELECT wal_tests_log('committed, immediate', false);
+                 COMMIT;
+                 BEGIN;
+                 SELECT wal_tests_log('aborted', true);
+                 SELECT wal_tests_log('aborted, immediate', false);
+                 ROLLBACK;",
+            )
+            .expect("couldn't log the messages");
+
+        let messages: String = client
+            .query_one(PEEK_MESSAGES, &[&"wal_tests_sessions"])
+            .expect("couldn't peek at the replication slot")
+            .get(0);
+        assert_eq!(
+            messages,
+            "N committed, immediate|T committed|N aborted, immediate"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DROPPED_STATES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+struct DistinctValues {
+    seen: HashSet<i64>,
+}
+
+impl Drop for DistinctValues {
+    fn drop(&mut self) {
+        DROPPED_STATES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// How many distinct non-NULL values the partition has had, up to and including the current row
+#[pg_extern(window)]
+fn distinct_so_far(value: Option<i64>, state: &mut DistinctValues) -> i64 {
+    if let Some(value) = value {
+        state.seen.insert(value);
+    }
+    state.seen.len() as i64
+}
+
+/// `row_number()`, from a counter kept for each partition
+#[pg_extern(window)]
+fn count_rows(counter: &mut i64) -> i64 {
+    *counter += 1;
+    *counter
+}
+
+/// `lag(value)`
+#[pg_extern(window)]
+fn previous_value(_value: Option<i32>, fcinfo: pg_sys::FunctionCallInfo) -> Option<i32> {
+    PgWindowObject::from_fcinfo(fcinfo)
+        .arg_in_partition::<i32>(0, -1, WindowSeek::Current, false)
+        .flatten()
+}
+
+/// `sum(value)` over the current row's window frame
+#[pg_extern(window)]
+fn frame_sum(_value: Option<i64>, fcinfo: pg_sys::FunctionCallInfo) -> i64 {
+    let window = PgWindowObject::from_fcinfo(fcinfo);
+    let mut sum = 0;
+    let mut relpos = 0;
+    while let Some(value) = window.arg_in_frame::<i64>(0, relpos, WindowSeek::Head, false) {
+        sum += value.unwrap_or(0);
+        relpos += 1;
+    }
+    sum
+}
+
+/// The current row's position, out of the number of rows in the partition
+#[pg_extern(window)]
+fn position_of(_value: Option<i32>, fcinfo: pg_sys::FunctionCallInfo) -> String {
+    let window = PgWindowObject::from_fcinfo(fcinfo);
+    format!(
+        "{}/{}",
+        window.current_position() + 1,
+        window.partition_row_count()
+    )
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use super::DROPPED_STATES;
+    use pgx::*;
+    use std::sync::atomic::Ordering;
+
+    /// The results of the window function call `expr`, over a window of the rows of each of three
+    /// groups, ordered by `n`
+    fn over_groups(expr: &str) -> Option<String> {
+        Spi::get_one::<String>(&format!(
+            "SELECT string_agg(coalesce(result::text, 'NULL'), ',' ORDER BY grp, n) FROM (
+                SELECT grp, n, {} OVER (PARTITION BY grp ORDER BY n) AS result
+                  FROM (VALUES ('a', 1, 10), ('a', 2, 10), ('a', 3, NULL), ('a', 4, 20),
+                               ('b', 1, 30), ('b', 2, 30),
+                               ('c', 1, NULL)) v(grp, n, x)
+             ) w",
+            expr
+        ))
+    }
+
+    #[pg_test]
+    fn test_partition_state() {
+        assert_eq!(
+            over_groups("distinct_so_far(x)"),
+            Some("1,1,1,2,1,1,0".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_partition_state_without_arguments() {
+        assert_eq!(over_groups("count_rows()"), over_groups("row_number()"));
+    }
+
+    #[pg_test]
+    fn test_partition_state_is_dropped() {
+        let before = DROPPED_STATES.load(Ordering::SeqCst);
+        over_groups("distinct_so_far(x)");
+        assert_eq!(DROPPED_STATES.load(Ordering::SeqCst) - before, 3);
+    }
+
+    #[pg_test]
+    fn test_arg_in_partition() {
+        assert_eq!(over_groups("previous_value(x)"), over_groups("lag(x)"));
+    }
+
+    #[pg_test]
+    fn test_arg_in_frame() {
+        // unlike `sum()`, the sum of a frame of only NULLs is zero
+        assert_eq!(
+            over_groups("frame_sum(x)"),
+            Some("10,20,20,40,30,60,0".to_string())
+        );
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT string_agg(s::text, ',' ORDER BY n) FROM (
+                    SELECT n, frame_sum(n::bigint) OVER (ORDER BY n ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) s
+                      FROM generate_series(1, 4) n
+                 ) w"
+            ),
+            Some("1,3,5,7".to_string())
+        );
+    }
+
+    #[pg_test]
+    fn test_position() {
+        assert_eq!(
+            over_groups("position_of(x)"),
+            Some("1/4,2/4,3/4,4/4,1/2,2/2,1/1".to_string())
+        );
+    }
+
+    #[pg_extern]
+    fn not_a_window_function(fcinfo: pg_sys::FunctionCallInfo) -> i64 {
+        PgWindowObject::from_fcinfo(fcinfo).current_position()
+    }
+
+    #[pg_test(error = "function was not called as a window function")]
+    fn test_not_a_window_function() {
+        Spi::get_one::<i64>("SELECT tests.not_a_window_function()");
+    }
+}
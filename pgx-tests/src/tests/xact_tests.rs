@@ -0,0 +1,223 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use pgx::*;
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static HELD: RefCell<Option<String>> = RefCell::new(None);
+    static VETO_REGISTERED: Cell<bool> = Cell::new(false);
+}
+
+/// Holds onto `what` across transactions, until `xact_release()`, which the backend's veto
+/// refuses to prepare a transaction while it does
+#[pg_extern]
+fn xact_hold(what: String) {
+    if !VETO_REGISTERED.with(|registered| registered.replace(true)) {
+        pgx::xact::register_precommit_veto(|| match HELD.with(|held| held.borrow().clone()) {
+            None => Ok(()),
+            Some(what) => Err(format!(
+                "pgx_tests does not support prepared transactions while holding {}",
+                what
+            )),
+        });
+    }
+    HELD.with(|held| held.replace(Some(what)));
+}
+
+#[pg_extern]
+fn xact_release() {
+    HELD.with(|held| held.replace(None));
+}
+
+#[pg_extern]
+fn xact_forbid_prepare(reason: &str) {
+    pgx::xact::forbid_prepare(reason);
+}
+
+/// Raises an ERROR when the transaction is prepared, unless it's known to be being prepared
+#[pg_extern]
+fn xact_expect_preparing() {
+    register_xact_callback(PgXactCallbackEvent::PrePrepare, || {
+        if !pgx::xact::in_prepared_transaction_context() {
+            error!("PrePrepare isn't in a prepared transaction context");
+        }
+    });
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+mod tests {
+    #[allow(unused_imports)]
+    use crate as pgx_tests;
+
+    use pgx::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Records when the closure that owns it is dropped, called or not
+    struct DropFlag(Rc<Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    fn in_subtransaction<F: FnOnce()>(commit: bool, f: F) {
+        let (context, owner) =
+            unsafe { (pg_sys::CurrentMemoryContext, pg_sys::CurrentResourceOwner) };
+
+        unsafe { pg_sys::BeginInternalSubTransaction(std::ptr::null_mut()) };
+        f();
+        unsafe {
+            if commit {
+                pg_sys::ReleaseCurrentSubTransaction();
+            } else {
+                pg_sys::RollbackAndReleaseCurrentSubTransaction();
+            }
+            pg_sys::CurrentMemoryContext = context;
+            pg_sys::CurrentResourceOwner = owner;
+        }
+    }
+
+    #[pg_test]
+    fn test_at_commit_survives_aborted_subtransaction() {
+        let dropped = Rc::new(Cell::new(false));
+        let flag = DropFlag(Rc::clone(&dropped));
+        pgx::xact::at_commit(move || drop(flag));
+
+        in_subtransaction(false, || {});
+        assert!(!dropped.get());
+    }
+
+    #[pg_test]
+    fn test_at_commit_in_aborted_subtransaction() {
+        let dropped = Rc::new(Cell::new(false));
+        in_subtransaction(false, || {
+            let flag = DropFlag(Rc::clone(&dropped));
+            pgx::xact::at_commit(move || drop(flag));
+        });
+        assert!(dropped.get());
+    }
+
+    #[pg_test]
+    fn test_at_commit_in_committed_subtransaction() {
+        let dropped = Rc::new(Cell::new(false));
+        in_subtransaction(true, || {
+            let flag = DropFlag(Rc::clone(&dropped));
+            pgx::xact::at_commit(move || drop(flag));
+        });
+        assert!(!dropped.get());
+    }
+
+    #[pg_test]
+    fn test_at_commit_in_committed_subtransaction_of_aborted_one() {
+        let dropped = Rc::new(Cell::new(false));
+        in_subtransaction(false, || {
+            in_subtransaction(true, || {
+                let flag = DropFlag(Rc::clone(&dropped));
+                pgx::xact::at_commit(move || drop(flag));
+            });
+            assert!(!dropped.get());
+        });
+        assert!(dropped.get());
+    }
+
+    /// How many transactions are prepared as `gid`
+    fn prepared(client: &mut postgres::Client, gid: &str) -> i64 {
+        client
+            .query_one(
+                "SELECT count(*) FROM pg_prepared_xacts WHERE gid = $1",
+                &[&gid],
+            )
+            .expect("couldn't count the prepared transactions")
+            .get(0)
+    }
+
+    /// The SQLSTATE and message of `error`
+    fn db_error(error: postgres::Error) -> (String, String) {
+        let error = error.as_db_error().expect("not an ERROR from Postgres");
+        (error.code().code().to_string(), error.message().to_string())
+    }
+
+    #[pg_test]
+    fn test_not_preparing() {
+        assert!(!pgx::xact::in_prepared_transaction_context());
+    }
+
+    #[test]
+    fn test_prepare_transaction_veto() {
+        let (mut client, _) =
+            pgx_tests::start_and_connect(crate::pg_test::postgresql_conf_options());
+        client
+            .batch_execute("BEGIN; SELECT xact_hold('a session registry');")
+            .expect("couldn't hold the session registry");
+        let error = client
+            .batch_execute("PREPARE TRANSACTION 'pgx_vetoed'")
+            .expect_err("the transaction was prepared despite the veto");
+        assert_eq!(
+            db_error(error),
+            (
+                "0A000".to_string(),
+                "pgx_tests does not support prepared transactions while holding a session registry"
+                    .to_string()
+            )
+        );
+        assert_eq!(prepared(&mut client, "pgx_vetoed"), 0);
+
+        // once it's released, the veto lets the transaction be prepared
+        client
+            .batch_execute("SELECT xact_release()")
+            .expect("couldn't release the session registry");
+        client
+            .batch_execute(
+                "BEGIN;
+                 SELECT xact_expect_preparing();
+                 PREPARE TRANSACTION 'pgx_allowed';",
+            )
+            .expect("the transaction wasn't prepared");
+        assert_eq!(prepared(&mut client, "pgx_allowed"), 1);
+        client
+            .batch_execute("ROLLBACK PREPARED 'pgx_allowed'")
+            .expect("couldn't roll back the prepared transaction");
+    }
+
+    #[test]
+    fn test_forbid_prepare() {
+        let (mut client, _) =
+            pgx_tests::start_and_connect(crate::pg_test::postgresql_conf_options());
+        client
+            .batch_execute("BEGIN; SELECT xact_forbid_prepare('has opened a socket');")
+            .expect("couldn't forbid PREPARE");
+        let error = client
+            .batch_execute("PREPARE TRANSACTION 'pgx_forbidden'")
+            .expect_err("the forbidden transaction was prepared");
+        assert_eq!(
+            db_error(error),
+            (
+                "0A000".to_string(),
+                "cannot PREPARE a transaction that has opened a socket".to_string()
+            )
+        );
+        assert_eq!(prepared(&mut client, "pgx_forbidden"), 0);
+
+        // only that transaction was forbidden
+        client
+            .batch_execute("BEGIN; PREPARE TRANSACTION 'pgx_not_forbidden';")
+            .expect("the next transaction wasn't prepared");
+        assert_eq!(prepared(&mut client, "pgx_not_forbidden"), 1);
+        client
+            .batch_execute("ROLLBACK PREPARED 'pgx_not_forbidden'")
+            .expect("couldn't roll back the prepared transaction");
+    }
+
+    #[pg_test]
+    fn test_at_abort_in_aborted_subtransaction() {
+        let dropped = Rc::new(Cell::new(false));
+        in_subtransaction(false, || {
+            let flag = DropFlag(Rc::clone(&dropped));
+            pgx::xact::at_abort(move || drop(flag));
+        });
+        assert!(!dropped.get());
+    }
+}
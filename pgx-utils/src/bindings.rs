@@ -0,0 +1,183 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Rendering of the generated Postgres bindings, `pgx-pg-sys/src/pgNN.rs`, so that regenerating
+//! them from the same headers produces the same file, and a header change only changes the lines
+//! of the symbols it touches.
+//!
+//! bindgen emits items in the order it traverses the headers, which moves around from one
+//! Postgres release to the next, so the items are sorted into sections (types, then constants,
+//! then foreign functions and statics), each alphabetically, and the file is then formatted with
+//! `rustfmt`.
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use syn::{ForeignItem, Item, Type};
+
+/// The sections of a bindings file, in the order they're written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Section {
+    /// Structs, unions, enums and type aliases, each followed by its `impl` blocks
+    Types,
+    /// Constants, including those bindgen makes for the variants of C enums
+    Constants,
+    /// `extern "C"` blocks, each of which declares a single function or static
+    ForeignItems,
+    /// Anything else, such as the modules that pgx adds
+    Other,
+}
+
+impl Section {
+    pub fn all() -> &'static [Section] {
+        &[
+            Section::Types,
+            Section::Constants,
+            Section::ForeignItems,
+            Section::Other,
+        ]
+    }
+
+    /// The comment that starts this section in the bindings file
+    pub fn banner(&self) -> &'static str {
+        match self {
+            Section::Types => "// ---- Types ----",
+            Section::Constants => "// ---- Constants ----",
+            Section::ForeignItems => "// ---- Foreign functions and statics ----",
+            Section::Other => "// ---- Other items ----",
+        }
+    }
+}
+
+/// Sort `items` into sections, and each section by name.
+///
+/// Items of the same name, such as a type and its `impl`s, are kept in a fixed order, with any
+/// remaining ties broken by their tokens, so the result doesn't depend on the order of `items`
+pub fn sort_items(items: Vec<Item>) -> Vec<(Section, Vec<Item>)> {
+    let mut keyed = items
+        .into_iter()
+        .map(|item| (sort_key(&item), item))
+        .collect::<Vec<_>>();
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut sections = Section::all()
+        .iter()
+        .map(|section| (*section, Vec::new()))
+        .collect::<Vec<_>>();
+    for ((section, ..), item) in keyed {
+        sections
+            .iter_mut()
+            .find(|(s, _)| *s == section)
+            .unwrap()
+            .1
+            .push(item);
+    }
+    sections
+}
+
+/// Render a bindings file:  `header` (such as its `use` statements) and then each section of
+/// `items`, sorted by `sort_items()` and formatted with `rustfmt_source()`
+pub fn render_bindings(
+    header: TokenStream,
+    items: Vec<Item>,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut source = header.to_string();
+    source.push('\n');
+    for (section, items) in sort_items(items) {
+        source.push('\n');
+        source.push_str(section.banner());
+        source.push('\n');
+        for item in items {
+            source.push_str(&quote! { #item }.to_string());
+            source.push('\n');
+        }
+    }
+
+    Ok(rustfmt_source(&source)?)
+}
+
+/// Format Rust `source` by piping it through `rustfmt`
+pub fn rustfmt_source(source: &str) -> Result<String, std::io::Error> {
+    let mut child = Command::new("rustfmt")
+        .arg("--edition")
+        .arg("2018")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // written from another thread, so that rustfmt can't block on a full stdout pipe while we're
+    // blocked on a full stdin pipe
+    let mut stdin = child.stdin.take().expect("rustfmt has no stdin");
+    let input = source.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child.wait_with_output()?;
+    writer.join().expect("writing to rustfmt panicked")?;
+    if !output.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "rustfmt failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        ));
+    }
+
+    String::from_utf8(output.stdout)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Write `contents` to `path` by way of a temporary file in the same directory, so that an
+/// interrupted build can't leave a truncated file behind
+pub fn write_file_atomically(path: &Path, contents: &str) -> Result<(), std::io::Error> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    std::fs::write(&temp_path, contents)?;
+    std::fs::rename(&temp_path, path)
+}
+
+/// The section of `item`, its name, its rank among items of the same name, and its tokens
+fn sort_key(item: &Item) -> (Section, String, u8, String) {
+    let (section, name, rank) = match item {
+        Item::Struct(item) => (Section::Types, item.ident.to_string(), 0),
+        Item::Union(item) => (Section::Types, item.ident.to_string(), 0),
+        Item::Enum(item) => (Section::Types, item.ident.to_string(), 0),
+        Item::Type(item) => (Section::Types, item.ident.to_string(), 0),
+        Item::Impl(item) => match type_name(&item.self_ty) {
+            Some(name) => (Section::Types, name, 1),
+            None => (Section::Other, String::new(), 0),
+        },
+        Item::Const(item) => (Section::Constants, item.ident.to_string(), 0),
+        Item::Static(item) => (Section::Constants, item.ident.to_string(), 0),
+        Item::ForeignMod(item) => {
+            let name = item.items.first().and_then(|item| match item {
+                ForeignItem::Fn(item) => Some(item.sig.ident.to_string()),
+                ForeignItem::Static(item) => Some(item.ident.to_string()),
+                ForeignItem::Type(item) => Some(item.ident.to_string()),
+                _ => None,
+            });
+            (Section::ForeignItems, name.unwrap_or_default(), 0)
+        }
+        Item::Mod(item) => (Section::Other, item.ident.to_string(), 0),
+        _ => (Section::Other, String::new(), 0),
+    };
+
+    (section, name, rank, quote! { #item }.to_string())
+}
+
+/// The name of the type an `impl` block is for, such as `Foo` for `impl<T> Trait for Foo<T>`
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+// `#[pg_block_extern]` exports the `#[pg_extern]` methods of an `impl` block by generating a free
+// `#[pg_extern]` function for each of them.  Both the macro and `cargo pgx schema` generate them
+// here, so that the functions the extension exports and the SQL that creates them agree
+
+use crate::{parse_extern_attributes, ExternArgs};
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use std::collections::HashMap;
+use syn::fold::Fold;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, FnArg, GenericParam, Ident, ImplItem, ImplItemMethod, ItemFn, ItemImpl, Lit, Meta,
+    NestedMeta, Pat, ReturnType, Token, Type,
+};
+
+/// The arguments of `#[pg_block_extern(...)]`:  `bare`, `prefix = "..."` and `receiver = "..."`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockExternArgs {
+    /// Don't prefix the function names at all
+    pub bare: bool,
+
+    /// Prefix the function names with this, rather than the lowercased type name
+    pub prefix: Option<String>,
+
+    /// Name the argument `self` is passed as this, rather than the lowercased type name
+    pub receiver: Option<String>,
+}
+
+impl BlockExternArgs {
+    /// Parse the arguments as an attribute macro gets them, without the parentheses
+    pub fn parse(attr: TokenStream) -> Result<Self, (Span, String)> {
+        let metas = Punctuated::<NestedMeta, Token![,]>::parse_terminated
+            .parse2(attr)
+            .map_err(|e| (e.span(), e.to_string()))?;
+        BlockExternArgs::from_metas(metas.iter())
+    }
+
+    /// Parse the arguments of `attr`, which is a `#[pg_block_extern]` attribute
+    pub fn from_attribute(attr: &Attribute) -> Result<Self, (Span, String)> {
+        match attr.parse_meta().map_err(|e| (e.span(), e.to_string()))? {
+            Meta::Path(_) => Ok(BlockExternArgs::default()),
+            Meta::List(list) => BlockExternArgs::from_metas(list.nested.iter()),
+            other => Err((
+                other.span(),
+                "malformed #[pg_block_extern] attribute".to_string(),
+            )),
+        }
+    }
+
+    fn from_metas<'a>(metas: impl Iterator<Item = &'a NestedMeta>) -> Result<Self, (Span, String)> {
+        let mut args = BlockExternArgs::default();
+        for meta in metas {
+            match meta {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("bare") => args.bare = true,
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("prefix") => {
+                    args.prefix = Some(lit_str(&nv.lit)?)
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("receiver") => {
+                    let receiver = lit_str(&nv.lit)?;
+                    if syn::parse_str::<Ident>(&receiver).is_err() || receiver == "self" {
+                        return Err((
+                            nv.lit.span(),
+                            format!("`{}` isn't a valid argument name", receiver),
+                        ));
+                    }
+                    args.receiver = Some(receiver)
+                }
+                other => {
+                    return Err((
+                        other.span(),
+                        "unrecognized #[pg_block_extern] argument, expected `bare`, `prefix = \"...\"` or `receiver = \"...\"`".to_string(),
+                    ))
+                }
+            }
+        }
+
+        if args.bare && args.prefix.is_some() {
+            return Err((
+                Span::call_site(),
+                "#[pg_block_extern] can't be both `bare` and have a `prefix`".to_string(),
+            ));
+        }
+        Ok(args)
+    }
+}
+
+fn lit_str(lit: &Lit) -> Result<String, (Span, String)> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        other => Err((other.span(), "expected a string literal".to_string())),
+    }
+}
+
+/// Is `attr` `#[pg_block_extern]`?
+pub fn is_block_extern_attribute(attr: &Attribute) -> bool {
+    last_segment_is(attr, "pg_block_extern")
+}
+
+fn is_pg_extern_attribute(attr: &Attribute) -> bool {
+    last_segment_is(attr, "pg_extern")
+}
+
+fn last_segment_is(attr: &Attribute, name: &str) -> bool {
+    attr.path
+        .segments
+        .last()
+        .map_or(false, |segment| segment.ident == name)
+}
+
+/// A `#[pg_extern]` function, and the name of the SQL function it becomes
+pub struct BlockExternFunction {
+    pub sql_name: String,
+    pub func: ItemFn,
+}
+
+/// The `#[pg_extern]` functions that export the `#[pg_extern]` methods of `block`.
+///
+/// A method that takes `self` or `&self` becomes a function whose first argument is the type,
+/// and an associated function becomes a function with the same arguments.  Each is named with
+/// the prefix (the lowercased type name, by default), an underscore, and the method's name,
+/// unless the method's `#[pg_extern(name = "...")]` names it
+pub fn block_extern_functions(
+    args: &BlockExternArgs,
+    block: &ItemImpl,
+) -> Result<Vec<BlockExternFunction>, (Span, String)> {
+    if let Some((_, path, _)) = &block.trait_ {
+        return Err((
+            path.span(),
+            "#[pg_block_extern] can only be applied to inherent impl blocks, not trait impls"
+                .to_string(),
+        ));
+    }
+    if let Some(param) = block
+        .generics
+        .params
+        .iter()
+        .find(|param| !matches!(param, GenericParam::Lifetime(_)))
+    {
+        return Err((
+            param.span(),
+            "#[pg_block_extern] can't export the methods of a generic type".to_string(),
+        ));
+    }
+
+    let type_name = match &*block.self_ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string().to_lowercase()),
+        _ => None,
+    };
+    let type_name = match type_name {
+        Some(type_name) => type_name,
+        None => {
+            return Err((
+                block.self_ty.span(),
+                "#[pg_block_extern] can only be applied to an impl block of a named type"
+                    .to_string(),
+            ))
+        }
+    };
+    let prefix = if args.bare {
+        None
+    } else {
+        Some(args.prefix.clone().unwrap_or_else(|| type_name.clone()))
+    };
+    let receiver = format_ident!("{}", args.receiver.clone().unwrap_or(type_name));
+
+    let mut functions = Vec::new();
+    let mut sql_names = HashMap::new();
+    for item in &block.items {
+        let method = match item {
+            ImplItem::Method(method) if method.attrs.iter().any(is_pg_extern_attribute) => method,
+            _ => continue,
+        };
+
+        let function = method_function(block, method, prefix.as_deref(), &receiver)?;
+        if let Some(other) = sql_names.insert(function.sql_name.clone(), &method.sig.ident) {
+            return Err((
+                method.sig.ident.span(),
+                format!(
+                    "`{}` and `{}` would both be exported as the SQL function `{}`",
+                    other, method.sig.ident, function.sql_name
+                ),
+            ));
+        }
+        functions.push(function);
+    }
+    Ok(functions)
+}
+
+/// Remove the attributes from the methods of `block` that only apply to the functions
+/// `block_extern_functions()` generates
+pub fn strip_block_extern_attributes(block: &mut ItemImpl) {
+    for item in &mut block.items {
+        if let ImplItem::Method(method) = item {
+            if method.attrs.iter().any(is_pg_extern_attribute) {
+                method.attrs.retain(|attr| {
+                    !is_pg_extern_attribute(attr) && !attr.path.is_ident("search_path")
+                });
+            }
+        }
+    }
+}
+
+fn method_function(
+    block: &ItemImpl,
+    method: &ImplItemMethod,
+    prefix: Option<&str>,
+    receiver: &Ident,
+) -> Result<BlockExternFunction, (Span, String)> {
+    let sig = &method.sig;
+    let method_name = &sig.ident;
+    if sig.generics.type_params().next().is_some() || sig.generics.const_params().next().is_some() {
+        return Err((
+            sig.generics.span(),
+            "generic methods can't be exported with #[pg_extern]".to_string(),
+        ));
+    }
+    if let Some(unsafety) = &sig.unsafety {
+        return Err((
+            unsafety.span(),
+            "unsafe methods can't be exported with #[pg_extern]".to_string(),
+        ));
+    }
+    if let Some(asyncness) = &sig.asyncness {
+        return Err((
+            asyncness.span(),
+            "async methods can't be exported with #[pg_extern]".to_string(),
+        ));
+    }
+
+    let self_ty = &*block.self_ty;
+    let mut replace_self = ReplaceSelf(self_ty);
+    let mut has_receiver = false;
+    let mut inputs = Vec::new();
+    let mut call_args = Vec::new();
+    for input in &sig.inputs {
+        match input {
+            FnArg::Receiver(r) if r.mutability.is_some() && r.reference.is_some() => {
+                return Err((
+                    r.span(),
+                    "#[pg_extern] methods can't take `&mut self`, as Postgres would never see the changes".to_string(),
+                ))
+            }
+            FnArg::Receiver(_) => {
+                has_receiver = true;
+                inputs.push(quote! { #receiver: #self_ty });
+            }
+            FnArg::Typed(typed) => {
+                let arg_name = match &*typed.pat {
+                    Pat::Ident(pat) if pat.ident == "self" => {
+                        return Err((
+                            typed.span(),
+                            "#[pg_extern] methods must take `self` or `&self`, not a typed receiver".to_string(),
+                        ))
+                    }
+                    Pat::Ident(pat) if pat.subpat.is_none() => &pat.ident,
+                    other => {
+                        return Err((
+                            other.span(),
+                            "the arguments of #[pg_extern] methods must be plain names".to_string(),
+                        ))
+                    }
+                };
+                if arg_name == receiver {
+                    return Err((
+                        arg_name.span(),
+                        format!("the argument `{}` has the same name as the argument `self` is passed as, which can be changed with #[pg_block_extern(receiver = \"...\")]", arg_name),
+                    ));
+                }
+
+                let attrs = &typed.attrs;
+                let ty = replace_self.fold_type((*typed.ty).clone());
+                inputs.push(quote! { #(#attrs)* #arg_name: #ty });
+                call_args.push(arg_name);
+            }
+        }
+    }
+
+    let output = match replace_self.fold_return_type(sig.output.clone()) {
+        ReturnType::Default => quote! {},
+        ReturnType::Type(arrow, ty) => quote! { #arrow #ty },
+    };
+    let call = if has_receiver {
+        quote! { #receiver.#method_name(#(#call_args),*) }
+    } else {
+        quote! { <#self_ty>::#method_name(#(#call_args),*) }
+    };
+
+    let name = match prefix {
+        Some(prefix) => format_ident!("{}_{}", prefix, method_name),
+        None => method_name.clone(),
+    };
+    let mut sql_name = name.to_string();
+    let attrs = method
+        .attrs
+        .iter()
+        .filter(|attr| {
+            is_pg_extern_attribute(attr)
+                || attr.path.is_ident("search_path")
+                || attr.path.is_ident("doc")
+                || attr.path.is_ident("cfg")
+        })
+        .collect::<Vec<_>>();
+    for attr in attrs.iter().filter(|attr| is_pg_extern_attribute(attr)) {
+        for arg in parse_extern_attributes(attr.tokens.clone()) {
+            if let ExternArgs::Name(explicit) = arg {
+                sql_name = explicit;
+            }
+        }
+    }
+
+    // the block can only have lifetime parameters, which the methods share
+    let vis = &method.vis;
+    let lifetimes = block
+        .generics
+        .lifetimes()
+        .chain(sig.generics.lifetimes())
+        .collect::<Vec<_>>();
+    let generics = if lifetimes.is_empty() {
+        quote! {}
+    } else {
+        quote! { <#(#lifetimes),*> }
+    };
+    let predicates = block
+        .generics
+        .where_clause
+        .iter()
+        .chain(sig.generics.where_clause.iter())
+        .flat_map(|where_clause| where_clause.predicates.iter())
+        .collect::<Vec<_>>();
+    let where_clause = if predicates.is_empty() {
+        quote! {}
+    } else {
+        quote! { where #(#predicates),* }
+    };
+    let func = quote! {
+        #(#attrs)*
+        #[allow(non_snake_case)]
+        #vis fn #name #generics (#(#inputs),*) #output #where_clause {
+            #call
+        }
+    };
+
+    match syn::parse2(func) {
+        Ok(func) => Ok(BlockExternFunction { sql_name, func }),
+        Err(e) => Err((e.span(), e.to_string())),
+    }
+}
+
+// `Self` doesn't mean anything outside of the impl block
+struct ReplaceSelf<'a>(&'a Type);
+
+impl Fold for ReplaceSelf<'_> {
+    fn fold_type(&mut self, ty: Type) -> Type {
+        match ty {
+            Type::Path(path) if path.qself.is_none() && path.path.is_ident("Self") => {
+                self.0.clone()
+            }
+            other => syn::fold::fold_type(self, other),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! An extension's `.control` file, which `cargo pgx schema` generates from `extension_control!()`
+use proc_macro2::{Span, TokenStream};
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Lit, Meta, NestedMeta, Token};
+
+/// The parameters of a `.control` file.  Those pgx doesn't know about, such as `directory` or
+/// `encoding`, are kept as they're written, in `other`
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ControlFile {
+    pub comment: Option<String>,
+    pub default_version: Option<String>,
+    pub module_pathname: Option<String>,
+
+    /// The schema the extension is created in, which only non-relocatable extensions can have
+    pub schema: Option<String>,
+
+    /// The extensions that must be installed before this one
+    pub requires: Vec<String>,
+    pub relocatable: Option<bool>,
+    pub superuser: Option<bool>,
+
+    /// Can non-superusers install it, on Postgres 13 and later?
+    pub trusted: Option<bool>,
+    pub other: Vec<(String, String)>,
+}
+
+impl ControlFile {
+    /// Parse a `.control` file, which has the same syntax as `postgresql.conf`:  a
+    /// `name = value` on each line, where `=` is optional, and the value is either a single
+    /// quoted string or a bare word or number.  Comments begin with `#`
+    pub fn parse(input: &str) -> Result<Self, String> {
+        fn skip_whitespace(chars: &mut Peekable<Chars>) {
+            while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                chars.next();
+            }
+        }
+
+        let mut control = ControlFile::default();
+        let mut seen = HashSet::new();
+
+        for (idx, line) in input.lines().enumerate() {
+            let lineno = idx + 1;
+            let error = |message: &str| Err(format!("line {}: {}", lineno, message));
+
+            let mut chars = line.chars().peekable();
+
+            skip_whitespace(&mut chars);
+            if chars.peek().map_or(true, |c| *c == '#') {
+                continue;
+            }
+
+            let mut name = String::new();
+            while let Some(c) = chars.peek() {
+                if c.is_ascii_alphanumeric() || *c == '_' || *c == '.' {
+                    name.push(*c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                return error("expected a parameter name");
+            }
+
+            skip_whitespace(&mut chars);
+            if chars.peek() == Some(&'=') {
+                chars.next();
+                skip_whitespace(&mut chars);
+            }
+
+            let mut value = String::new();
+            if chars.peek() == Some(&'\'') {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            value.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('b') => value.push('\u{8}'),
+                            Some('f') => value.push('\u{c}'),
+                            Some('n') => value.push('\n'),
+                            Some('r') => value.push('\r'),
+                            Some('t') => value.push('\t'),
+                            Some(c) => value.push(c),
+                            None => return error("unterminated quoted string"),
+                        },
+                        Some(c) => value.push(c),
+                        None => return error("unterminated quoted string"),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || *c == '#' {
+                        break;
+                    }
+                    value.push(*c);
+                    chars.next();
+                }
+                if value.is_empty() {
+                    return error(&format!("expected a value for `{}`", name));
+                }
+            }
+
+            skip_whitespace(&mut chars);
+            if chars.peek().map_or(false, |c| *c != '#') {
+                return error(&format!("unexpected text after the value of `{}`", name));
+            }
+
+            if !seen.insert(name.clone()) {
+                return error(&format!("`{}` is given more than once", name));
+            }
+
+            let boolean = |value: &str| match parse_bool(value) {
+                Some(b) => Ok(Some(b)),
+                None => Err(format!(
+                    "line {}: `{}` must be a boolean, not `{}`",
+                    lineno, name, value
+                )),
+            };
+            match name.as_str() {
+                "comment" => control.comment = Some(value),
+                "default_version" => control.default_version = Some(value),
+                "module_pathname" => control.module_pathname = Some(value),
+                "schema" => control.schema = Some(value),
+                "requires" => {
+                    control.requires = value
+                        .split(',')
+                        .map(|name| name.trim().to_string())
+                        .filter(|name| !name.is_empty())
+                        .collect()
+                }
+                "relocatable" => control.relocatable = boolean(&value)?,
+                "superuser" => control.superuser = boolean(&value)?,
+                "trusted" => control.trusted = boolean(&value)?,
+                _ => control.other.push((name, value)),
+            }
+        }
+
+        Ok(control)
+    }
+
+    /// Parse the arguments of `extension_control!()`, such as
+    /// `comment = "...", default_version = "1.0", requires("hstore"), relocatable = false`
+    pub fn from_tokens(input: TokenStream) -> Result<Self, (Span, String)> {
+        fn error<T>(span: Span, message: &str) -> Result<T, (Span, String)> {
+            Err((span, message.to_string()))
+        }
+
+        let metas = Punctuated::<NestedMeta, Token![,]>::parse_terminated
+            .parse2(input)
+            .map_err(|e| (e.span(), e.to_string()))?;
+
+        let mut control = ControlFile::default();
+        let mut seen = HashSet::new();
+        for meta in metas {
+            let meta = match meta {
+                NestedMeta::Meta(meta) => meta,
+                NestedMeta::Lit(lit) => return error(lit.span(), "unexpected literal"),
+            };
+            let name = match meta.path().get_ident() {
+                Some(ident) => ident.to_string(),
+                None => return error(meta.span(), "unknown control file parameter"),
+            };
+            if !seen.insert(name.clone()) {
+                return error(meta.span(), &format!("`{}` is given more than once", name));
+            }
+
+            match (name.as_str(), &meta) {
+                ("requires", Meta::List(list)) => {
+                    for nested in &list.nested {
+                        match nested {
+                            NestedMeta::Lit(Lit::Str(s)) => control.requires.push(s.value()),
+                            _ => {
+                                return error(
+                                    nested.span(),
+                                    "expected the name of an extension, as a string",
+                                )
+                            }
+                        }
+                    }
+                }
+                (_, Meta::NameValue(nv)) => match (name.as_str(), &nv.lit) {
+                    ("comment", Lit::Str(s)) => control.comment = Some(s.value()),
+                    ("default_version", Lit::Str(s)) => control.default_version = Some(s.value()),
+                    ("module_pathname", Lit::Str(s)) => control.module_pathname = Some(s.value()),
+                    ("schema", Lit::Str(s)) => control.schema = Some(s.value()),
+                    ("relocatable", Lit::Bool(b)) => control.relocatable = Some(b.value),
+                    ("superuser", Lit::Bool(b)) => control.superuser = Some(b.value),
+                    ("trusted", Lit::Bool(b)) => control.trusted = Some(b.value),
+                    ("comment", _)
+                    | ("default_version", _)
+                    | ("module_pathname", _)
+                    | ("schema", _) => return error(nv.lit.span(), "expected a string literal"),
+                    ("relocatable", _) | ("superuser", _) | ("trusted", _) => {
+                        return error(nv.lit.span(), "expected `true` or `false`")
+                    }
+                    ("requires", _) => {
+                        return error(meta.span(), "expected `requires(\"extension\", ...)`")
+                    }
+                    _ => {
+                        return error(
+                            meta.span(),
+                            &format!("unknown control file parameter `{}`", name),
+                        )
+                    }
+                },
+                _ => {
+                    return error(
+                        meta.span(),
+                        &format!("unknown control file parameter `{}`", name),
+                    )
+                }
+            }
+        }
+
+        control.validate().map_err(|e| (Span::call_site(), e))?;
+        Ok(control)
+    }
+
+    /// Check the parameters are consistent, as Postgres does when the extension is created
+    pub fn validate(&self) -> Result<(), String> {
+        if self.relocatable == Some(true) && self.schema.is_some() {
+            return Err("a `relocatable` extension can't have a `schema`".to_string());
+        }
+        if let Some(name) = self
+            .requires
+            .iter()
+            .find(|name| name.is_empty() || name.contains(','))
+        {
+            return Err(format!("`{}` isn't the name of an extension", name));
+        }
+        Ok(())
+    }
+
+    /// The things in the extension's SQL that stop it being relocated by
+    /// `ALTER EXTENSION ... SET SCHEMA`, as messages naming the line they're on.  Always empty
+    /// unless it's `relocatable`
+    pub fn relocatable_violations(&self, sql: &str) -> Vec<String> {
+        if self.relocatable != Some(true) {
+            return Vec::new();
+        }
+
+        let mut violations = Vec::new();
+        for (idx, line) in sql.lines().enumerate() {
+            // ignoring what's commented out
+            let code = line.split("--").next().unwrap_or_default();
+            let lowercase = code.to_lowercase();
+            if lowercase.contains("@extschema@") {
+                violations.push(format!(
+                    "line {}: `@extschema@` can't be used by a relocatable extension",
+                    idx + 1
+                ));
+            }
+            if lowercase
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .windows(2)
+                .any(|words| words == ["create", "schema"])
+            {
+                violations.push(format!(
+                    "line {}: a relocatable extension must create all of its objects in one schema, so can't `CREATE SCHEMA`",
+                    idx + 1
+                ));
+            }
+        }
+        violations
+    }
+}
+
+impl std::fmt::Display for ControlFile {
+    /// The `.control` file, one parameter per line
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn quote(s: &str) -> String {
+            let s = s.replace('\\', "\\\\").replace('\n', "\\n");
+            format!("'{}'", s.replace('\'', "''"))
+        }
+
+        let strings = [
+            ("comment", &self.comment),
+            ("default_version", &self.default_version),
+            ("module_pathname", &self.module_pathname),
+            ("schema", &self.schema),
+        ];
+        for (name, value) in strings.iter() {
+            if let Some(value) = value {
+                writeln!(f, "{} = {}", name, quote(value))?;
+            }
+        }
+        if !self.requires.is_empty() {
+            writeln!(f, "requires = {}", quote(&self.requires.join(", ")))?;
+        }
+
+        let booleans = [
+            ("relocatable", self.relocatable),
+            ("superuser", self.superuser),
+            ("trusted", self.trusted),
+        ];
+        for (name, value) in booleans.iter() {
+            if let Some(value) = value {
+                writeln!(f, "{} = {}", name, value)?;
+            }
+        }
+
+        for (name, value) in &self.other {
+            writeln!(f, "{} = {}", name, quote(value))?;
+        }
+        Ok(())
+    }
+}
+
+/// A boolean, as Postgres' `parse_bool()` understands them:  any unique prefix of `true`,
+/// `false`, `yes`, `no`, `on`, or `off`, or `1` or `0`, in any case
+fn parse_bool(value: &str) -> Option<bool> {
+    let value = value.to_lowercase();
+    let is_prefix = |word: &str, min_len: usize| value.len() >= min_len && word.starts_with(&value);
+
+    if is_prefix("true", 1) || is_prefix("yes", 1) || is_prefix("on", 2) || value == "1" {
+        Some(true)
+    } else if is_prefix("false", 1) || is_prefix("no", 1) || is_prefix("off", 2) || value == "0" {
+        Some(false)
+    } else {
+        None
+    }
+}
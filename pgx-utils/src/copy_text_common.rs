@@ -0,0 +1,55 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use proc_macro2::Ident;
+use quote::quote;
+
+// `#[derive(PostgresType)]` generates these `#[pg_extern]` functions for types with the
+// `#[copytextformat]` attribute.  `pgvarlena` is true if the type is `#[pgvarlena_inoutfuncs]`
+
+pub fn to_copy_text(type_name: &Ident, pgvarlena: bool) -> proc_macro2::TokenStream {
+    let pg_name = Ident::new(
+        &format!("{}_to_copy_text", type_name).to_lowercase(),
+        type_name.span(),
+    );
+    let (input_type, value) = if pgvarlena {
+        (quote! { pgx::PgVarlena<#type_name> }, quote! { &*input })
+    } else {
+        (quote! { #type_name }, quote! { &input })
+    };
+    quote! {
+        #[allow(non_snake_case)]
+        #[pg_extern(immutable, parallel_safe)]
+        fn #pg_name(input: #input_type) -> String {
+            let mut buffer = pgx::StringInfo::new();
+            pgx::CopyTextFormat::to_copy_text(#value, &mut buffer);
+            buffer.to_string()
+        }
+    }
+}
+
+pub fn from_copy_text(type_name: &Ident, pgvarlena: bool) -> proc_macro2::TokenStream {
+    let pg_name = Ident::new(
+        &format!("{}_from_copy_text", type_name).to_lowercase(),
+        type_name.span(),
+    );
+    if pgvarlena {
+        quote! {
+            #[allow(non_snake_case)]
+            #[pg_extern(immutable, parallel_safe)]
+            fn #pg_name(input: &str) -> pgx::PgVarlena<#type_name> {
+                let mut result = pgx::PgVarlena::<#type_name>::new();
+                *result = <#type_name as pgx::CopyTextFormat>::from_copy_text(input);
+                result
+            }
+        }
+    } else {
+        quote! {
+            #[allow(non_snake_case)]
+            #[pg_extern(immutable, parallel_safe)]
+            fn #pg_name(input: &str) -> #type_name {
+                <#type_name as pgx::CopyTextFormat>::from_copy_text(input)
+            }
+        }
+    }
+}
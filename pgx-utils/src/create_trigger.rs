@@ -0,0 +1,283 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! The `CREATE TRIGGER` statements of `create_trigger!()`, which `cargo pgx schema` generates
+use proc_macro2::{Span, TokenStream};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Lit, Meta, NestedMeta, Token};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TriggerTiming {
+    Before,
+    After,
+    InsteadOf,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TriggerEvent {
+    Insert,
+    Update,
+    Delete,
+    Truncate,
+}
+
+/// The arguments of a `create_trigger!()`
+#[derive(Debug, Eq, PartialEq)]
+pub struct CreateTrigger {
+    pub name: String,
+
+    /// The table or view the trigger is on, which may be schema-qualified
+    pub table: String,
+
+    /// The trigger function, which may be schema-qualified
+    pub function: String,
+
+    /// The arguments the trigger function is given
+    pub args: Vec<String>,
+
+    pub timing: TriggerTiming,
+    pub events: Vec<TriggerEvent>,
+    pub for_each_row: bool,
+
+    /// Is this a `CONSTRAINT` trigger, whose firing can be deferred?
+    pub constraint: bool,
+    pub deferrable: bool,
+    pub initially_deferred: bool,
+
+    /// The table referenced by a constraint trigger, such as by a foreign key
+    pub from: Option<String>,
+
+    /// The condition of the `WHEN` clause
+    pub when: Option<String>,
+}
+
+impl CreateTrigger {
+    /// Parse the name of the trigger, followed by its options, such as
+    /// `"name", table = "orders", function = "check_order", after(insert, update), for_each_row`
+    pub fn parse(input: TokenStream) -> Result<Self, (Span, String)> {
+        fn error<T>(span: Span, message: &str) -> Result<T, (Span, String)> {
+            Err((span, message.to_string()))
+        }
+
+        fn string(lit: &Lit) -> Result<String, (Span, String)> {
+            match lit {
+                Lit::Str(s) => Ok(s.value()),
+                _ => error(lit.span(), "expected a string literal"),
+            }
+        }
+
+        let metas = Punctuated::<NestedMeta, Token![,]>::parse_terminated
+            .parse2(input)
+            .map_err(|e| (e.span(), e.to_string()))?;
+        let mut metas = metas.into_iter();
+
+        let name = match metas.next() {
+            Some(NestedMeta::Lit(lit)) => string(&lit)?,
+            Some(meta) => {
+                return error(meta.span(), "expected the name of the trigger, as a string")
+            }
+            None => return error(Span::call_site(), "expected the name of the trigger"),
+        };
+
+        let mut table = None;
+        let mut function = None;
+        let mut args = Vec::new();
+        let mut timing = None;
+        let mut events = Vec::new();
+        let mut for_each_row = false;
+        let mut constraint = false;
+        let mut deferrable = false;
+        let mut initially_deferred = false;
+        let mut from = None;
+        let mut when = None;
+
+        for meta in metas {
+            let meta = match meta {
+                NestedMeta::Meta(meta) => meta,
+                NestedMeta::Lit(lit) => return error(lit.span(), "unexpected literal"),
+            };
+            let ident = match meta.path().get_ident() {
+                Some(ident) => ident.to_string(),
+                None => return error(meta.span(), "unknown trigger option"),
+            };
+
+            match (ident.as_str(), &meta) {
+                ("table", Meta::NameValue(nv)) => table = Some(string(&nv.lit)?),
+                ("function", Meta::NameValue(nv)) => function = Some(string(&nv.lit)?),
+                ("from", Meta::NameValue(nv)) => from = Some(string(&nv.lit)?),
+                ("when", Meta::NameValue(nv)) => when = Some(string(&nv.lit)?),
+                ("args", Meta::List(list)) => {
+                    for arg in &list.nested {
+                        match arg {
+                            NestedMeta::Lit(lit) => args.push(string(lit)?),
+                            _ => return error(arg.span(), "expected a string literal"),
+                        }
+                    }
+                }
+                ("before", Meta::List(list))
+                | ("after", Meta::List(list))
+                | ("instead_of", Meta::List(list)) => {
+                    if timing.is_some() {
+                        return error(
+                            meta.span(),
+                            "a trigger can only have one of `before`, `after`, and `instead_of`",
+                        );
+                    }
+                    timing = Some(match ident.as_str() {
+                        "before" => TriggerTiming::Before,
+                        "after" => TriggerTiming::After,
+                        _ => TriggerTiming::InsteadOf,
+                    });
+
+                    for event in &list.nested {
+                        let event_name = match event {
+                            NestedMeta::Meta(Meta::Path(path)) => {
+                                path.get_ident().map(|i| i.to_string())
+                            }
+                            _ => None,
+                        };
+                        let event =
+                            match event_name.as_deref() {
+                                Some("insert") => TriggerEvent::Insert,
+                                Some("update") => TriggerEvent::Update,
+                                Some("delete") => TriggerEvent::Delete,
+                                Some("truncate") => TriggerEvent::Truncate,
+                                _ => return error(
+                                    event.span(),
+                                    "expected one of `insert`, `update`, `delete`, or `truncate`",
+                                ),
+                            };
+                        if !events.contains(&event) {
+                            events.push(event);
+                        }
+                    }
+                }
+                ("for_each_row", Meta::Path(_)) => for_each_row = true,
+                ("for_each_statement", Meta::Path(_)) => for_each_row = false,
+                ("constraint", Meta::Path(_)) => constraint = true,
+                ("deferrable", Meta::Path(_)) => deferrable = true,
+                ("initially_deferred", Meta::Path(_)) => initially_deferred = true,
+                ("initially_immediate", Meta::Path(_)) => initially_deferred = false,
+                _ => return error(meta.span(), &format!("unknown trigger option `{}`", ident)),
+            }
+        }
+
+        let span = Span::call_site();
+        let table = table.ok_or_else(|| (span, "expected `table = \"...\"`".to_string()))?;
+        let function =
+            function.ok_or_else(|| (span, "expected `function = \"...\"`".to_string()))?;
+        let timing = timing.ok_or_else(|| {
+            (
+                span,
+                "expected when the trigger fires, such as `after(insert, update)`".to_string(),
+            )
+        })?;
+        if events.is_empty() {
+            return error(
+                span,
+                "expected at least one of `insert`, `update`, `delete`, or `truncate`",
+            );
+        }
+
+        // the same rules Postgres has, so they're caught at compile time
+        if timing == TriggerTiming::InsteadOf && !for_each_row {
+            return error(span, "`instead_of` triggers must be `for_each_row`");
+        }
+        if for_each_row && events.contains(&TriggerEvent::Truncate) {
+            return error(span, "`truncate` triggers can't be `for_each_row`");
+        }
+        if constraint && (timing != TriggerTiming::After || !for_each_row) {
+            return error(
+                span,
+                "`constraint` triggers must be `after(...)` and `for_each_row`",
+            );
+        }
+        if !constraint && (deferrable || initially_deferred || from.is_some()) {
+            return error(
+                span,
+                "only `constraint` triggers can be `deferrable`, `initially_deferred`, or have a `from` table",
+            );
+        }
+
+        Ok(CreateTrigger {
+            name,
+            table,
+            function,
+            args,
+            timing,
+            events,
+            for_each_row,
+            constraint,
+            // a trigger that's initially deferred can be deferred
+            deferrable: deferrable || initially_deferred,
+            initially_deferred,
+            from,
+            when,
+        })
+    }
+
+    /// The `CREATE TRIGGER` statement, to run from the extension script
+    pub fn to_sql(&self) -> String {
+        fn literal(s: &str) -> String {
+            format!("'{}'", s.replace('\'', "''"))
+        }
+
+        let timing = match self.timing {
+            TriggerTiming::Before => "BEFORE",
+            TriggerTiming::After => "AFTER",
+            TriggerTiming::InsteadOf => "INSTEAD OF",
+        };
+        let events = self
+            .events
+            .iter()
+            .map(|event| match event {
+                TriggerEvent::Insert => "INSERT",
+                TriggerEvent::Update => "UPDATE",
+                TriggerEvent::Delete => "DELETE",
+                TriggerEvent::Truncate => "TRUNCATE",
+            })
+            .collect::<Vec<_>>()
+            .join(" OR ");
+
+        let mut sql = format!(
+            "CREATE {}TRIGGER {} {} {} ON {}",
+            if self.constraint { "CONSTRAINT " } else { "" },
+            self.name,
+            timing,
+            events,
+            self.table
+        );
+        if let Some(from) = &self.from {
+            sql.push_str(&format!(" FROM {}", from));
+        }
+        if self.constraint {
+            sql.push_str(match (self.deferrable, self.initially_deferred) {
+                (true, true) => " DEFERRABLE INITIALLY DEFERRED",
+                (true, false) => " DEFERRABLE INITIALLY IMMEDIATE",
+                (false, _) => " NOT DEFERRABLE",
+            });
+        }
+        sql.push_str(if self.for_each_row {
+            " FOR EACH ROW"
+        } else {
+            " FOR EACH STATEMENT"
+        });
+        if let Some(when) = &self.when {
+            sql.push_str(&format!(" WHEN ({})", when));
+        }
+
+        // `EXECUTE FUNCTION` is only understood from Postgres 11 on
+        sql.push_str(&format!(
+            " EXECUTE PROCEDURE {}({});",
+            self.function,
+            self.args
+                .iter()
+                .map(|arg| literal(arg))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+        sql
+    }
+}
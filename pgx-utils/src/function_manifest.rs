@@ -0,0 +1,344 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Tracks the signature of every function `cargo pgx schema` generates, so the next generation
+//! can drop the functions whose signature changed before they're re-created.
+//!
+//! `CREATE OR REPLACE FUNCTION` can't change a function's return type, and when its argument
+//! types change it creates a new overload, leaving the old one behind.
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// What identifies a generated function, plus what `CREATE OR REPLACE` can't change about it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FunctionSignature {
+    /// The schema-qualified name, as it appears in the generated SQL
+    pub name: String,
+
+    /// The argument types, not including `OUT` parameters
+    pub arg_types: Vec<String>,
+
+    /// The return type, including any `OUT` parameters
+    pub return_type: String,
+}
+
+impl FunctionSignature {
+    pub fn new(name: &str, arg_types: Vec<String>, return_type: &str) -> Self {
+        FunctionSignature {
+            name: name.to_string(),
+            arg_types,
+            return_type: return_type.to_string(),
+        }
+    }
+
+    /// The function's identity, as `DROP FUNCTION` wants it
+    pub fn identity(&self) -> String {
+        format!("{}({})", self.name, self.arg_types.join(", "))
+    }
+
+    pub fn drop_statement(&self) -> String {
+        format!("DROP FUNCTION IF EXISTS {};", self.identity())
+    }
+}
+
+/// The functions generated for an extension, stored one per line as tab-separated fields: the
+/// name, the return type, and then each argument type
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FunctionManifest {
+    signatures: BTreeSet<FunctionSignature>,
+
+    /// new name -> old names, from `#[pg_extern(renamed_from = "...")]`.  These aren't persisted
+    renames: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// The drops needed to go from one `FunctionManifest` to another
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ManifestChanges {
+    /// Keyed by the name of the function that must be (re)created after them
+    pub before_create: BTreeMap<String, Vec<FunctionSignature>>,
+
+    /// Functions that no longer exist at all
+    pub removed: Vec<FunctionSignature>,
+}
+
+impl FunctionManifest {
+    /// Read the manifest at `path`.  A missing file is an empty manifest
+    pub fn read(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                FunctionManifest::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(FunctionManifest::default()),
+            Err(e) => Err(format!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut manifest = FunctionManifest::default();
+        for (lineno, line) in contents.lines().enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split('\t');
+            match (fields.next(), fields.next()) {
+                (Some(name), Some(return_type)) => manifest.insert(FunctionSignature::new(
+                    name,
+                    fields.map(|arg_type| arg_type.to_string()).collect(),
+                    return_type,
+                )),
+                _ => return Err(format!("line {}: malformed function signature", lineno + 1)),
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn insert(&mut self, signature: FunctionSignature) {
+        self.signatures.insert(signature);
+    }
+
+    /// Record that the function now named `new_name` used to be named `old_name`
+    pub fn rename(&mut self, old_name: &str, new_name: &str) {
+        self.renames
+            .entry(new_name.to_string())
+            .or_default()
+            .insert(old_name.to_string());
+    }
+
+    pub fn signatures(&self) -> impl Iterator<Item = &FunctionSignature> {
+        self.signatures.iter()
+    }
+
+    fn has_function_named(&self, name: &str) -> bool {
+        self.signatures
+            .iter()
+            .any(|signature| signature.name == name)
+    }
+
+    /// Find the functions in `previous` that this manifest doesn't have exactly, and so need to
+    /// be dropped:  those whose argument or return types changed, which were renamed, or which
+    /// were removed.  Unchanged functions are left alone.
+    pub fn changes_since(&self, previous: &FunctionManifest) -> ManifestChanges {
+        let mut changes = ManifestChanges::default();
+        for stale in previous.signatures.difference(&self.signatures) {
+            let recreated_as = if self.has_function_named(&stale.name) {
+                Some(stale.name.clone())
+            } else {
+                self.renames
+                    .iter()
+                    .find(|(_, old_names)| old_names.contains(&stale.name))
+                    .map(|(new_name, _)| new_name.clone())
+            };
+
+            match recreated_as {
+                Some(name) => changes
+                    .before_create
+                    .entry(name)
+                    .or_default()
+                    .push(stale.clone()),
+                None => changes.removed.push(stale.clone()),
+            }
+        }
+        changes
+    }
+}
+
+impl std::fmt::Display for FunctionManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "# functions generated by `cargo pgx schema`: name, return type, argument types"
+        )?;
+        for signature in &self.signatures {
+            write!(f, "{}\t{}", signature.name, signature.return_type)?;
+            for arg_type in &signature.arg_types {
+                write!(f, "\t{}", arg_type)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl ManifestChanges {
+    pub fn is_empty(&self) -> bool {
+        self.before_create.is_empty() && self.removed.is_empty()
+    }
+
+    /// Put a `DROP FUNCTION IF EXISTS` for each stale signature in front of the first
+    /// `CREATE OR REPLACE FUNCTION` statement in `statements` for the function replacing it
+    pub fn insert_drops(&mut self, statements: Vec<String>) -> Vec<String> {
+        let mut with_drops = Vec::with_capacity(statements.len());
+        for statement in statements {
+            let created = statement
+                .strip_prefix("CREATE OR REPLACE FUNCTION ")
+                .and_then(|rest| rest.split('(').next());
+
+            if let Some(stale) = created.and_then(|name| self.before_create.remove(name)) {
+                with_drops.extend(stale.iter().map(FunctionSignature::drop_statement));
+            }
+            with_drops.push(statement);
+        }
+        with_drops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::function_manifest::{FunctionManifest, FunctionSignature};
+
+    fn manifest(signatures: &[(&str, &[&str], &str)]) -> FunctionManifest {
+        let mut manifest = FunctionManifest::default();
+        for (name, arg_types, return_type) in signatures {
+            manifest.insert(FunctionSignature::new(
+                name,
+                arg_types.iter().map(|t| t.to_string()).collect(),
+                return_type,
+            ));
+        }
+        manifest
+    }
+
+    fn identities(signatures: &[FunctionSignature]) -> Vec<String> {
+        signatures.iter().map(|s| s.identity()).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let before = manifest(&[
+            ("add", &["integer", "integer"], "integer"),
+            ("tests.now", &[], "timestamp with time zone"),
+            ("split", &["text"], "SETOF text"),
+        ]);
+        let after = FunctionManifest::parse(&before.to_string()).unwrap();
+        assert_eq!(before, after);
+        assert_eq!(
+            FunctionManifest::parse("# comment\n\nadd\tinteger\tinteger\n").unwrap(),
+            manifest(&[("add", &["integer"], "integer")])
+        );
+        assert!(FunctionManifest::parse("add\n").is_err());
+    }
+
+    #[test]
+    fn unchanged_and_added() {
+        let previous = manifest(&[("add", &["integer", "integer"], "integer")]);
+        let current = manifest(&[
+            ("add", &["integer", "integer"], "integer"),
+            ("sub", &["integer", "integer"], "integer"),
+        ]);
+        assert!(current.changes_since(&previous).is_empty());
+        assert!(current
+            .changes_since(&FunctionManifest::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn changed_signature() {
+        let previous = manifest(&[
+            ("add", &["integer", "integer"], "integer"),
+            ("half", &["integer"], "integer"),
+            ("overloaded", &["integer"], "integer"),
+            ("overloaded", &["text"], "text"),
+        ]);
+        let current = manifest(&[
+            // changed argument types
+            ("add", &["bigint", "bigint"], "bigint"),
+            // changed return type
+            ("half", &["integer"], "real"),
+            // an untouched overload isn't dropped
+            ("overloaded", &["integer"], "integer"),
+            ("overloaded", &["text", "text"], "text"),
+        ]);
+
+        let changes = current.changes_since(&previous);
+        assert!(changes.removed.is_empty());
+        assert_eq!(
+            identities(&changes.before_create["add"]),
+            vec!["add(integer, integer)"]
+        );
+        assert_eq!(
+            identities(&changes.before_create["half"]),
+            vec!["half(integer)"]
+        );
+        assert_eq!(
+            identities(&changes.before_create["overloaded"]),
+            vec!["overloaded(text)"]
+        );
+    }
+
+    #[test]
+    fn renamed() {
+        let previous = manifest(&[("tests.old_name", &["text"], "text")]);
+        let mut current = manifest(&[("tests.new_name", &["text"], "text")]);
+
+        // without renamed_from, the old function looks removed
+        let changes = current.changes_since(&previous);
+        assert!(changes.before_create.is_empty());
+        assert_eq!(identities(&changes.removed), vec!["tests.old_name(text)"]);
+
+        current.rename("tests.old_name", "tests.new_name");
+        let changes = current.changes_since(&previous);
+        assert!(changes.removed.is_empty());
+        assert_eq!(
+            identities(&changes.before_create["tests.new_name"]),
+            vec!["tests.old_name(text)"]
+        );
+    }
+
+    #[test]
+    fn removed() {
+        let previous = manifest(&[
+            ("add", &["integer", "integer"], "integer"),
+            ("gone", &[], "void"),
+        ]);
+        let current = manifest(&[("add", &["integer", "integer"], "integer")]);
+
+        let changes = current.changes_since(&previous);
+        assert!(changes.before_create.is_empty());
+        assert_eq!(
+            changes
+                .removed
+                .iter()
+                .map(FunctionSignature::drop_statement)
+                .collect::<Vec<_>>(),
+            vec!["DROP FUNCTION IF EXISTS gone();"]
+        );
+    }
+
+    #[test]
+    fn insert_drops() {
+        let previous = manifest(&[
+            ("add", &["integer", "integer"], "integer"),
+            ("other", &["integer"], "integer"),
+        ]);
+        let current = manifest(&[
+            ("add", &["bigint", "bigint"], "bigint"),
+            ("other", &["integer"], "integer"),
+        ]);
+        let mut changes = current.changes_since(&previous);
+
+        let statements = vec![
+            "-- src/lib.rs:10".to_string(),
+            "CREATE OR REPLACE FUNCTION other(i integer) RETURNS integer".to_string(),
+            "CREATE OR REPLACE FUNCTION add(a bigint, b bigint) RETURNS bigint".to_string(),
+            "CREATE OR REPLACE FUNCTION add(a text) RETURNS text".to_string(),
+        ];
+        assert_eq!(
+            changes.insert_drops(statements),
+            vec![
+                "-- src/lib.rs:10",
+                "CREATE OR REPLACE FUNCTION other(i integer) RETURNS integer",
+                "DROP FUNCTION IF EXISTS add(integer, integer);",
+                "CREATE OR REPLACE FUNCTION add(a bigint, b bigint) RETURNS bigint",
+                "CREATE OR REPLACE FUNCTION add(a text) RETURNS text",
+            ]
+        );
+
+        // and each drop is only emitted once
+        assert!(changes.before_create.is_empty());
+    }
+}
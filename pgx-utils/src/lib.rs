@@ -13,8 +13,15 @@ use std::process::{Command, Stdio};
 use std::str::FromStr;
 use syn::{GenericArgument, ItemFn, PathArguments, ReturnType, Type, TypeParamBound};
 
+pub mod bindings;
+pub mod block_extern;
+pub mod control_file;
+pub mod copy_text_common;
+pub mod create_trigger;
+pub mod function_manifest;
 pub mod operator_common;
 pub mod pg_config;
+pub mod sql_types;
 
 pub static BASE_POSTGRES_PORT_NO: u16 = 28800;
 pub static BASE_POSTGRES_TESTING_PORT_NO: u16 = 32200;
@@ -193,9 +200,16 @@ pub enum ExternArgs {
     ParallelSafe,
     ParallelUnsafe,
     ParallelRestricted,
+    ConstSql,
+    Materialize,
+    Window,
+    Instrument,
     Error(String),
     Schema(String),
     Name(String),
+    RenamedFrom(String),
+    Returns(String),
+    ReturnsLookup(String),
 }
 
 #[derive(Debug, Hash, Ord, PartialOrd, Eq, PartialEq)]
@@ -233,6 +247,10 @@ pub fn parse_extern_attributes(attr: TokenStream) -> HashSet<ExternArgs> {
                     "parallel_safe" => args.insert(ExternArgs::ParallelSafe),
                     "parallel_unsafe" => args.insert(ExternArgs::ParallelUnsafe),
                     "parallel_restricted" => args.insert(ExternArgs::ParallelRestricted),
+                    "const_sql" => args.insert(ExternArgs::ConstSql),
+                    "materialize" => args.insert(ExternArgs::Materialize),
+                    "window" => args.insert(ExternArgs::Window),
+                    "instrument" => args.insert(ExternArgs::Instrument),
                     "error" => {
                         let _punc = itr.next().unwrap();
                         let literal = itr.next().unwrap();
@@ -263,6 +281,34 @@ pub fn parse_extern_attributes(attr: TokenStream) -> HashSet<ExternArgs> {
                         let name = name[1..name.len() - 1].to_string();
                         args.insert(ExternArgs::Name(name.to_string()))
                     }
+                    "renamed_from" => {
+                        let _punc = itr.next().unwrap();
+                        let literal = itr.next().unwrap();
+                        let name = literal.to_string();
+                        let name = unescape::unescape(&name).expect("failed to unescape");
+
+                        // trim leading/trailing quotes around the literal
+                        let name = name[1..name.len() - 1].to_string();
+                        args.insert(ExternArgs::RenamedFrom(name.to_string()))
+                    }
+                    "returns" => {
+                        let _punc = itr.next().unwrap();
+                        let sql_type = match (itr.next(), itr.next(), itr.next()) {
+                            (
+                                Some(TokenTree::Ident(mac)),
+                                Some(TokenTree::Punct(bang)),
+                                Some(TokenTree::Group(group)),
+                            ) if mac == "sql_type" && bang.as_char() == '!' => {
+                                sql_types::parse_sql_type_macro(group.stream())
+                            }
+                            _ => panic!("expected `returns = sql_type!(\"name\")`"),
+                        };
+                        match sql_type {
+                            Ok((name, true)) => args.insert(ExternArgs::Returns(name)),
+                            Ok((name, false)) => args.insert(ExternArgs::ReturnsLookup(name)),
+                            Err((_, message)) => panic!("{}", message),
+                        }
+                    }
                     _ => false,
                 };
             }
@@ -273,6 +319,134 @@ pub fn parse_extern_attributes(attr: TokenStream) -> HashSet<ExternArgs> {
     args
 }
 
+/// The arguments of an `extension_config_table!()`:  a table, which may be schema-qualified, and
+/// the optional `WHERE` clause that picks which of its rows `pg_dump` dumps
+#[derive(Debug, Eq, PartialEq)]
+pub struct ExtensionConfigTable {
+    pub table: String,
+    pub filter: Option<String>,
+}
+
+impl ExtensionConfigTable {
+    /// Parse `table_name` or `"table_name"`, optionally followed by `, "WHERE ..."`
+    pub fn parse(input: TokenStream) -> Result<Self, (proc_macro2::Span, String)> {
+        fn string_literal(tt: TokenTree) -> Result<String, (proc_macro2::Span, String)> {
+            let span = tt.span();
+            syn::parse2::<syn::LitStr>(tt.into())
+                .map(|lit| lit.value())
+                .map_err(|_| (span, "expected a string literal".to_string()))
+        }
+
+        let mut tokens = input.into_iter().peekable();
+        let table = match tokens.next() {
+            Some(TokenTree::Ident(ident)) => {
+                let mut table = ident.to_string();
+                while matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '.') {
+                    let dot = tokens.next().unwrap();
+                    match tokens.next() {
+                        Some(TokenTree::Ident(ident)) => {
+                            table.push('.');
+                            table.push_str(&ident.to_string());
+                        }
+                        _ => return Err((dot.span(), "expected a name after `.`".to_string())),
+                    }
+                }
+                table
+            }
+            Some(tt @ TokenTree::Literal(_)) => string_literal(tt)?,
+            Some(tt) => return Err((tt.span(), "expected the name of a table".to_string())),
+            None => {
+                return Err((
+                    proc_macro2::Span::call_site(),
+                    "expected the name of a table".to_string(),
+                ))
+            }
+        };
+
+        let filter = match tokens.next() {
+            None => None,
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => match tokens.next() {
+                None => None,
+                Some(tt) => {
+                    let span = tt.span();
+                    let filter = string_literal(tt)?;
+                    let filter = filter.trim();
+                    if filter.is_empty() {
+                        None
+                    } else if filter.len() < 6 || !filter[..6].eq_ignore_ascii_case("where ") {
+                        return Err((
+                            span,
+                            "the filter must be a `WHERE` clause, such as \"WHERE NOT standard\""
+                                .to_string(),
+                        ));
+                    } else {
+                        Some(filter.to_string())
+                    }
+                }
+            },
+            Some(tt) => return Err((tt.span(), "expected `,`".to_string())),
+        };
+
+        if let Some(tt) = tokens.next() {
+            return Err((tt.span(), "unexpected token".to_string()));
+        }
+
+        Ok(ExtensionConfigTable { table, filter })
+    }
+
+    /// The statement that marks the table as a configuration table, when run by the extension's
+    /// `CREATE EXTENSION` script
+    pub fn to_sql(&self) -> String {
+        fn literal(s: &str) -> String {
+            format!("'{}'", s.replace('\'', "''"))
+        }
+
+        format!(
+            "SELECT pg_catalog.pg_extension_config_dump({}::regclass, {});",
+            literal(&self.table),
+            literal(self.filter.as_deref().unwrap_or(""))
+        )
+    }
+}
+
+/// Tokens that may not appear anywhere in a `#[pg_extern(const_sql)]` function.  Such functions
+/// are evaluated by `cargo pgx schema` without a running Postgres, so they must be plain Rust.
+const CONST_SQL_FORBIDDEN: [&str; 4] = ["pg_sys", "pgx", "unsafe", "extern"];
+
+/// Find the first token in `func` that prevents it from being evaluated at schema generation
+/// time, returning its span and a description of the problem
+pub fn const_sql_violation(func: &ItemFn) -> Option<(proc_macro2::Span, String)> {
+    fn walk(stream: TokenStream) -> Option<(proc_macro2::Span, String)> {
+        for tt in stream {
+            match tt {
+                TokenTree::Group(g) => {
+                    if let Some(violation) = walk(g.stream()) {
+                        return Some(violation);
+                    }
+                }
+                TokenTree::Ident(i) => {
+                    let name = i.to_string();
+                    if CONST_SQL_FORBIDDEN.contains(&name.as_str()) {
+                        return Some((
+                            i.span(),
+                            format!(
+                                "`{}` cannot be used in a #[pg_extern(const_sql)] function",
+                                name
+                            ),
+                        ));
+                    }
+                }
+                TokenTree::Punct(_) | TokenTree::Literal(_) => {}
+            }
+        }
+        None
+    }
+
+    let sig = &func.sig;
+    let block = &func.block;
+    walk(quote! { #sig #block })
+}
+
 pub fn categorize_return_type(func: &ItemFn) -> CategorizedType {
     let rt = &func.sig.output;
 
@@ -282,7 +456,28 @@ pub fn categorize_return_type(func: &ItemFn) -> CategorizedType {
     }
 }
 
+/// If `ty` is a `Result<T, E>`, returns the `T`
+pub fn extract_result_ok_type(ty: &Type) -> Option<&Type> {
+    if let Type::Path(path) = ty {
+        let segment = path.path.segments.last()?;
+        if segment.ident == "Result" {
+            if let PathArguments::AngleBracketed(a) = &segment.arguments {
+                if let Some(GenericArgument::Type(ty)) = a.args.first() {
+                    return Some(ty);
+                }
+            }
+        }
+    }
+
+    None
+}
+
 pub fn categorize_type(ty: &Type) -> CategorizedType {
+    // a `Result<T, E>` is categorized by its `T`.  The `E` is raised as an ERROR
+    if let Some(ok_type) = extract_result_ok_type(ty) {
+        return categorize_type(ok_type);
+    }
+
     match ty {
         Type::Path(ty) => {
             let segments = &ty.path.segments;
@@ -394,7 +589,14 @@ pub fn categorize_type(ty: &Type) -> CategorizedType {
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse_extern_attributes, ExternArgs};
+    use crate::bindings::{self, Section};
+    use crate::block_extern::{block_extern_functions, BlockExternArgs};
+    use crate::control_file::ControlFile;
+    use crate::create_trigger::{CreateTrigger, TriggerEvent};
+    use crate::{
+        categorize_type, const_sql_violation, parse_extern_attributes, CategorizedType,
+        ExtensionConfigTable, ExternArgs,
+    };
     use std::str::FromStr;
 
     #[test]
@@ -407,4 +609,564 @@ mod tests {
             "syntax error at or near \"THIS\"".to_string()
         )));
     }
+
+    #[test]
+    fn parse_const_sql() {
+        let ts = proc_macro2::TokenStream::from_str("immutable, const_sql").unwrap();
+
+        let args = parse_extern_attributes(ts);
+        assert!(args.contains(&ExternArgs::ConstSql));
+        assert!(args.contains(&ExternArgs::Immutable));
+    }
+
+    #[test]
+    fn parse_renamed_from() {
+        let ts =
+            proc_macro2::TokenStream::from_str("immutable, renamed_from = \"old_name\"").unwrap();
+
+        let args = parse_extern_attributes(ts);
+        assert!(args.contains(&ExternArgs::RenamedFrom("old_name".to_string())));
+        assert!(args.contains(&ExternArgs::Immutable));
+    }
+
+    #[test]
+    fn parse_returns() {
+        let ts = proc_macro2::TokenStream::from_str(
+            "raw, returns = sql_type!(\"timestamp with time zone\")",
+        )
+        .unwrap();
+        let args = parse_extern_attributes(ts);
+        assert!(args.contains(&ExternArgs::Raw));
+        assert!(args.contains(&ExternArgs::Returns("timestamp with time zone".to_string())));
+
+        let ts = proc_macro2::TokenStream::from_str("raw, returns = sql_type!(lookup \"hstore\")")
+            .unwrap();
+        let args = parse_extern_attributes(ts);
+        assert!(args.contains(&ExternArgs::ReturnsLookup("hstore".to_string())));
+    }
+
+    #[test]
+    fn sql_type_names() {
+        use crate::sql_types::sql_type_names;
+
+        assert_eq!(sql_type_names("TEXTOID"), vec!["text"]);
+        assert_eq!(sql_type_names("INT4OID"), vec!["int4", "integer", "int"]);
+        assert_eq!(
+            sql_type_names("TIMESTAMPTZOID"),
+            vec!["timestamptz", "timestamp with time zone"]
+        );
+        assert_eq!(
+            sql_type_names("INT8ARRAYOID"),
+            vec!["_int8", "int8[]", "bigint[]"]
+        );
+        assert_eq!(sql_type_names("CHAROID"), vec!["\"char\""]);
+        assert_eq!(sql_type_names("CHARARRAYOID"), vec!["_char", "\"char\"[]"]);
+        assert_eq!(
+            sql_type_names("BPCHAROID"),
+            vec!["bpchar", "character", "char"]
+        );
+        assert_eq!(sql_type_names("CASHOID"), vec!["money"]);
+        assert_eq!(sql_type_names("ANYARRAYOID"), vec!["anyarray"]);
+        assert_eq!(
+            sql_type_names("PG_LSNARRAYOID"),
+            vec!["_pg_lsn", "pg_lsn[]"]
+        );
+        assert!(sql_type_names("HEAP_TABLE_AM_HANDLER_OID").is_empty());
+    }
+
+    #[test]
+    fn parse_window() {
+        let ts = proc_macro2::TokenStream::from_str("window, immutable").unwrap();
+
+        let args = parse_extern_attributes(ts);
+        assert!(args.contains(&ExternArgs::Window));
+        assert!(args.contains(&ExternArgs::Immutable));
+    }
+
+    #[test]
+    fn parse_instrument() {
+        let ts = proc_macro2::TokenStream::from_str("instrument, immutable").unwrap();
+
+        let args = parse_extern_attributes(ts);
+        assert!(args.contains(&ExternArgs::Instrument));
+        assert!(args.contains(&ExternArgs::Immutable));
+    }
+
+    #[test]
+    fn parse_extension_config_table() {
+        let parse = |s: &str| {
+            ExtensionConfigTable::parse(proc_macro2::TokenStream::from_str(s).unwrap())
+                .map_err(|(_, message)| message)
+        };
+
+        let config = parse("settings").unwrap();
+        assert_eq!(config.table, "settings");
+        assert_eq!(config.filter, None);
+        assert_eq!(
+            config.to_sql(),
+            "SELECT pg_catalog.pg_extension_config_dump('settings'::regclass, '');"
+        );
+
+        let config = parse("myschema.settings, \"where not standard and name <> 'x'\"").unwrap();
+        assert_eq!(config.table, "myschema.settings");
+        assert_eq!(
+            config.to_sql(),
+            "SELECT pg_catalog.pg_extension_config_dump('myschema.settings'::regclass, 'where not standard and name <> ''x''');"
+        );
+
+        let config = parse("r#\"Mixed Case\"#, \"\"").unwrap();
+        assert_eq!(config.table, "Mixed Case");
+        assert_eq!(config.filter, None);
+
+        assert!(parse("settings, \"NOT standard\"")
+            .unwrap_err()
+            .contains("`WHERE` clause"));
+        assert!(parse("settings, standard").is_err());
+        assert!(parse("settings \"WHERE x\"").is_err());
+        assert!(parse("settings.").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn parse_create_trigger() {
+        let parse = |s: &str| {
+            CreateTrigger::parse(proc_macro2::TokenStream::from_str(s).unwrap())
+                .map_err(|(_, message)| message)
+        };
+
+        let trigger = parse(
+            r#""skip_empty", table = "names", function = "skip_empty_values", args("name"),
+               before(insert, update, insert), for_each_row"#,
+        )
+        .unwrap();
+        assert_eq!(
+            trigger.events,
+            vec![TriggerEvent::Insert, TriggerEvent::Update]
+        );
+        assert_eq!(
+            trigger.to_sql(),
+            "CREATE TRIGGER skip_empty BEFORE INSERT OR UPDATE ON names FOR EACH ROW EXECUTE PROCEDURE skip_empty_values('name');"
+        );
+
+        let trigger = parse(
+            r#""balanced", table = "s.entries", function = "s.check", after(insert, delete),
+               for_each_row, constraint, initially_deferred, from = "s.batches",
+               when = "NEW.amount <> 0", args("it's", "")"#,
+        )
+        .unwrap();
+        assert!(trigger.deferrable);
+        assert_eq!(
+            trigger.to_sql(),
+            "CREATE CONSTRAINT TRIGGER balanced AFTER INSERT OR DELETE ON s.entries FROM s.batches DEFERRABLE INITIALLY DEFERRED FOR EACH ROW WHEN (NEW.amount <> 0) EXECUTE PROCEDURE s.check('it''s', '');"
+        );
+
+        let trigger = parse(
+            r#""audit", table = "t", function = "f", after(truncate), constraint, for_each_row"#,
+        );
+        assert!(trigger.unwrap_err().contains("`truncate`"));
+
+        let trigger =
+            parse(r#""t", table = "t", function = "f", after(insert), for_each_row, constraint"#)
+                .unwrap();
+        assert!(trigger.to_sql().contains(" NOT DEFERRABLE FOR EACH ROW "));
+        let trigger = parse(r#""t", table = "t", function = "f", after(truncate)"#).unwrap();
+        assert!(trigger
+            .to_sql()
+            .contains(" AFTER TRUNCATE ON t FOR EACH STATEMENT "));
+
+        assert!(
+            parse(r#""t", table = "t", function = "f", after(insert), deferrable"#)
+                .unwrap_err()
+                .contains("only `constraint` triggers")
+        );
+        assert!(parse(
+            r#""t", table = "t", function = "f", before(insert), for_each_row, constraint"#
+        )
+        .unwrap_err()
+        .contains("must be `after(...)`"));
+        assert!(
+            parse(r#""t", table = "t", function = "f", instead_of(insert)"#)
+                .unwrap_err()
+                .contains("must be `for_each_row`")
+        );
+        assert!(
+            parse(r#""t", table = "t", function = "f", before(insert), after(update)"#).is_err()
+        );
+        assert!(parse(r#""t", table = "t", function = "f", before(select)"#).is_err());
+        assert!(parse(r#""t", table = "t", function = "f", before()"#).is_err());
+        assert!(parse(r#""t", table = "t", after(insert)"#).is_err());
+        assert!(parse(r#""t", function = "f", after(insert)"#).is_err());
+        assert!(parse(r#"t, table = "t", function = "f", after(insert)"#).is_err());
+        assert!(parse(r#""t", table = t, function = "f", after(insert)"#).is_err());
+        assert!(parse(r#""t", table = "t", function = "f", after(insert), sometimes"#).is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn const_sql_restrictions() {
+        let func = syn::parse_str::<syn::ItemFn>(
+            "fn crc(i: i64) -> i64 { let mut c = i; for _ in 0..8 { c >>= 1; } c }",
+        )
+        .unwrap();
+        assert!(const_sql_violation(&func).is_none());
+
+        let func = syn::parse_str::<syn::ItemFn>(
+            "fn oid() -> String { format!(\"{}\", pg_sys::InvalidOid) }",
+        )
+        .unwrap();
+        let (_, message) = const_sql_violation(&func).unwrap();
+        assert!(message.contains("`pg_sys`"));
+
+        let func = syn::parse_str::<syn::ItemFn>("fn f() -> i32 { unsafe { 1 } }").unwrap();
+        assert!(const_sql_violation(&func).is_some());
+    }
+
+    fn block_functions(args: &str, block: &str) -> Result<Vec<(String, syn::ItemFn)>, String> {
+        let args = BlockExternArgs::parse(proc_macro2::TokenStream::from_str(args).unwrap())
+            .map_err(|(_, message)| message)?;
+        let block = syn::parse_str::<syn::ItemImpl>(block).unwrap();
+        block_extern_functions(&args, &block)
+            .map(|functions| {
+                functions
+                    .into_iter()
+                    .map(|f| (f.sql_name, f.func))
+                    .collect()
+            })
+            .map_err(|(_, message)| message)
+    }
+
+    fn assert_same_fn(func: &syn::ItemFn, expected: &str) {
+        let expected = syn::parse_str::<syn::ItemFn>(expected).unwrap();
+        assert_eq!(func.sig, expected.sig);
+        assert_eq!(func.block, expected.block);
+    }
+
+    #[test]
+    fn block_extern() {
+        let functions = block_functions(
+            "",
+            "impl Rectangle {
+                #[pg_extern] fn area(&self) -> f64 { self.w * self.h }
+                #[pg_extern(name = \"square_rect\")] fn square(side: f64) -> Self { todo!() }
+                fn perimeter(&self) -> f64 { todo!() }
+            }",
+        )
+        .unwrap();
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0].0, "rectangle_area");
+        assert_same_fn(
+            &functions[0].1,
+            "fn rectangle_area(rectangle: Rectangle) -> f64 { rectangle.area() }",
+        );
+        assert_eq!(functions[1].0, "square_rect");
+        assert_same_fn(
+            &functions[1].1,
+            "fn rectangle_square(side: f64) -> Rectangle { <Rectangle>::square(side) }",
+        );
+
+        let functions = block_functions(
+            "bare, receiver = \"r\"",
+            "impl Rectangle { #[pg_extern] fn scaled(self, by: f64) -> Vec<Self> { todo!() } }",
+        )
+        .unwrap();
+        assert_eq!(functions[0].0, "scaled");
+        assert_same_fn(
+            &functions[0].1,
+            "fn scaled(r: Rectangle, by: f64) -> Vec<Rectangle> { r.scaled(by) }",
+        );
+    }
+
+    #[test]
+    fn block_extern_errors() {
+        let collision = block_functions(
+            "prefix = \"rect\"",
+            "impl Rectangle {
+                #[pg_extern] fn area(&self) -> f64 { todo!() }
+                #[pg_extern(name = \"rect_area\")] fn size(&self) -> f64 { todo!() }
+            }",
+        );
+        assert!(collision.unwrap_err().contains("SQL function `rect_area`"));
+
+        let mut_self = block_functions("", "impl Rectangle { #[pg_extern] fn grow(&mut self) {} }");
+        assert!(mut_self.unwrap_err().contains("`&mut self`"));
+
+        let receiver_name = block_functions(
+            "",
+            "impl Rectangle { #[pg_extern] fn eq(&self, rectangle: Self) -> bool { todo!() } }",
+        );
+        assert!(receiver_name.unwrap_err().contains("receiver = "));
+
+        let trait_impl = block_functions(
+            "",
+            "impl Default for Rectangle { #[pg_extern] fn default() -> Self { todo!() } }",
+        );
+        assert!(trait_impl.is_err());
+        assert!(block_functions("bare, prefix = \"rect\"", "impl Rectangle {}").is_err());
+    }
+
+    #[test]
+    fn categorize_result() {
+        let ty = syn::parse_str::<syn::Type>("Result<Option<i32>, String>").unwrap();
+        assert!(matches!(categorize_type(&ty), CategorizedType::Default));
+
+        let ty = syn::parse_str::<syn::Type>("Result<impl Iterator<Item = i32>, String>").unwrap();
+        assert!(matches!(categorize_type(&ty), CategorizedType::Iterator(_)));
+    }
+
+    /// A small imitation of bindgen's output, after `apply_pg_guard()`
+    const SYNTHETIC_BINDINGS: &str = r#"
+        pub type Oid = ::std::os::raw::c_uint;
+        #[repr(C)]
+        #[derive(Debug, Default, Copy, Clone)]
+        pub struct List { pub type_: NodeTag, pub length: ::std::os::raw::c_int }
+        pub const NodeTag_T_List: NodeTag = 656;
+        pub type NodeTag = ::std::os::raw::c_uint;
+        #[pg_guard]
+        extern "C" { pub fn list_length(list: *mut List) -> ::std::os::raw::c_int; }
+        impl pg_sys::PgNode for List {}
+        pub const InvalidOid: u32 = 0;
+        #[repr(C)]
+        #[derive(Debug, Default, Copy, Clone)]
+        pub struct Datum__Bindgen { pub value: usize }
+        #[pg_guard]
+        extern "C" { pub static mut work_mem: ::std::os::raw::c_int; }
+        impl Datum__Bindgen { pub fn value(&self) -> usize { self.value } }
+        pub mod pg_config_defines { pub const USE_ICU: bool = false; }
+        #[pg_guard]
+        extern "C" { pub fn lappend(list: *mut List, datum: *mut ::std::os::raw::c_void) -> *mut List; }
+        impl ::std::fmt::Display for List {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result { write!(f, "List") }
+        }
+    "#;
+
+    fn render_synthetic(reorder: impl FnOnce(&mut Vec<syn::Item>)) -> String {
+        let mut items = syn::parse_file(SYNTHETIC_BINDINGS).unwrap().items;
+        reorder(&mut items);
+        bindings::render_bindings(quote::quote! { use crate as pg_sys; }, items).unwrap()
+    }
+
+    #[test]
+    fn bindings_are_rendered_deterministically() {
+        let first = render_synthetic(|_| {});
+        let second = render_synthetic(|_| {});
+        assert_eq!(first, second);
+
+        // as if the headers had been traversed in another order
+        assert_eq!(render_synthetic(|items| items.reverse()), first);
+        assert_eq!(render_synthetic(|items| items.rotate_left(5)), first);
+        assert_eq!(
+            render_synthetic(|items| items.sort_by_key(|item| quote::quote! { #item }.to_string())),
+            first
+        );
+    }
+
+    #[test]
+    fn bindings_are_sorted_into_sections() {
+        let rendered = render_synthetic(|_| {});
+        let position = |needle: &str| {
+            rendered
+                .find(needle)
+                .unwrap_or_else(|| panic!("`{}` not found in:\n{}", needle, rendered))
+        };
+
+        let order = [
+            "use crate as pg_sys;",
+            Section::Types.banner(),
+            "pub struct Datum__Bindgen",
+            "impl Datum__Bindgen",
+            "pub struct List",
+            "impl ::std::fmt::Display for List",
+            "impl pg_sys::PgNode for List",
+            "pub type NodeTag",
+            "pub type Oid",
+            Section::Constants.banner(),
+            "pub const InvalidOid",
+            "pub const NodeTag_T_List",
+            Section::ForeignItems.banner(),
+            "pub fn lappend",
+            "pub fn list_length",
+            "pub static mut work_mem",
+            Section::Other.banner(),
+            "pub mod pg_config_defines",
+        ];
+        for pair in order.windows(2) {
+            assert!(
+                position(pair[0]) < position(pair[1]),
+                "`{}` should come before `{}` in:\n{}",
+                pair[0],
+                pair[1],
+                rendered
+            );
+        }
+
+        // and it's formatted
+        assert!(rendered.contains("pub struct List {\n    pub type_: NodeTag,\n"));
+    }
+
+    #[test]
+    fn checked_in_bindings_are_rendered() {
+        // rendering is idempotent, so re-rendering the checked-in bindings changes nothing if they
+        // were written by `render_bindings()`
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../pgx-pg-sys/src");
+        for version in &[10, 11, 12, 13] {
+            let path = dir.join(format!("pg{}.rs", version));
+            let source = std::fs::read_to_string(&path).unwrap();
+            let items = syn::parse_file(&source)
+                .unwrap()
+                .items
+                .into_iter()
+                .filter(|item| !matches!(item, syn::Item::Use(_)))
+                .collect();
+            let rendered = bindings::render_bindings(
+                quote::quote! {
+                    use crate as pg_sys;
+                    use pgx_macros::*;
+                    use crate::PgNode;
+                },
+                items,
+            )
+            .unwrap();
+            assert!(
+                rendered == source,
+                "{} isn't in its rendered form",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn write_bindings_atomically() {
+        let dir = std::env::temp_dir().join(format!("pgx-utils-bindings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pg13.rs");
+
+        bindings::write_file_atomically(&path, "first").unwrap();
+        bindings::write_file_atomically(&path, "second").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert!(!dir.join("pg13.rs.tmp").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_control_file() {
+        let control = ControlFile::parse(
+            "# a comment
+comment = 'it''s \\\\ an extension'   # trailing comment
+default_version '1.0'
+module_pathname = '$libdir/ext'
+requires = 'plpgsql, hstore ,'
+relocatable = off
+superuser = t
+encoding = UTF8
+",
+        )
+        .unwrap();
+        assert_eq!(control.comment.as_deref(), Some("it's \\ an extension"));
+        assert_eq!(control.default_version.as_deref(), Some("1.0"));
+        assert_eq!(control.module_pathname.as_deref(), Some("$libdir/ext"));
+        assert_eq!(control.schema, None);
+        assert_eq!(control.requires, vec!["plpgsql", "hstore"]);
+        assert_eq!(control.relocatable, Some(false));
+        assert_eq!(control.superuser, Some(true));
+        assert_eq!(control.trusted, None);
+        assert_eq!(
+            control.other,
+            vec![("encoding".to_string(), "UTF8".to_string())]
+        );
+
+        assert!(ControlFile::parse("relocatable = maybe")
+            .unwrap_err()
+            .contains("must be a boolean"));
+        assert!(ControlFile::parse("comment = 'unterminated")
+            .unwrap_err()
+            .starts_with("line 1:"));
+        assert!(ControlFile::parse("\nschema = a b")
+            .unwrap_err()
+            .starts_with("line 2: unexpected text"));
+        assert!(ControlFile::parse("schema = a\nschema = b")
+            .unwrap_err()
+            .contains("more than once"));
+    }
+
+    #[test]
+    fn control_file_round_trip() {
+        let control = ControlFile {
+            comment: Some("quotes ' and \\ backslashes\nand lines".to_string()),
+            default_version: Some("1.2.3".to_string()),
+            module_pathname: Some("$libdir/ext".to_string()),
+            schema: Some("ext".to_string()),
+            requires: vec!["plpgsql".to_string(), "hstore".to_string()],
+            relocatable: Some(false),
+            superuser: Some(false),
+            trusted: Some(true),
+            other: vec![("directory".to_string(), "ext dir".to_string())],
+        };
+        let text = control.to_string();
+        assert_eq!(ControlFile::parse(&text).unwrap(), control);
+        assert!(text.contains("requires = 'plpgsql, hstore'\n"));
+        assert!(text.contains("relocatable = false\n"));
+
+        let empty = ControlFile::default();
+        assert_eq!(empty.to_string(), "");
+        assert_eq!(ControlFile::parse(&empty.to_string()).unwrap(), empty);
+    }
+
+    #[test]
+    fn control_file_from_tokens() {
+        let parse = |s: &str| {
+            ControlFile::from_tokens(proc_macro2::TokenStream::from_str(s).unwrap())
+                .map_err(|(_, message)| message)
+        };
+
+        let control = parse(
+            r#"comment = "an extension", default_version = "1.0", requires("plpgsql", "hstore"),
+               relocatable = true, superuser = false"#,
+        )
+        .unwrap();
+        assert_eq!(control.comment.as_deref(), Some("an extension"));
+        assert_eq!(control.requires, vec!["plpgsql", "hstore"]);
+        assert_eq!(control.relocatable, Some(true));
+        assert_eq!(control.superuser, Some(false));
+        assert_eq!(ControlFile::parse(&control.to_string()).unwrap(), control);
+
+        assert!(parse(r#"relocatable = "no""#)
+            .unwrap_err()
+            .contains("`true` or `false`"));
+        assert!(parse(r#"requires = "hstore""#)
+            .unwrap_err()
+            .contains("requires("));
+        assert!(parse(r#"encoding = "UTF8""#)
+            .unwrap_err()
+            .contains("unknown control file parameter `encoding`"));
+        assert!(parse(r#"schema = "a", schema = "b""#)
+            .unwrap_err()
+            .contains("more than once"));
+        assert!(parse(r#"schema = "ext", relocatable = true"#)
+            .unwrap_err()
+            .contains("can't have a `schema`"));
+    }
+
+    #[test]
+    fn relocatable_violations() {
+        let sql = "CREATE FUNCTION f() RETURNS int LANGUAGE sql AS 'SELECT 1';
+CREATE SCHEMA IF NOT EXISTS other;
+CREATE TABLE t (id int DEFAULT @extschema@.f());
+-- but CREATE SCHEMA and @extschema@ are fine in a comment";
+
+        let relocatable = ControlFile {
+            relocatable: Some(true),
+            ..Default::default()
+        };
+        let violations = relocatable.relocatable_violations(sql);
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].starts_with("line 2:"));
+        assert!(violations[1].starts_with("line 3: `@extschema@`"));
+
+        let fixed = ControlFile {
+            relocatable: Some(false),
+            ..Default::default()
+        };
+        assert!(fixed.relocatable_violations(sql).is_empty());
+    }
 }
@@ -130,6 +130,24 @@ pub fn cmp(type_name: &Ident) -> proc_macro2::TokenStream {
     }
 }
 
+pub fn sortsupport(type_name: &Ident) -> proc_macro2::TokenStream {
+    let pg_name = Ident::new(
+        &format!("{}_sortsupport", type_name).to_lowercase(),
+        type_name.span(),
+    );
+    quote! {
+        #[allow(non_snake_case)]
+        #[pg_extern(immutable, parallel_safe)]
+        fn #pg_name(ssup: pgx::Internal) {
+            unsafe {
+                pgx::sortsupport::prepare_sort_support::<#type_name>(
+                    ssup.as_ptr() as pgx::pg_sys::SortSupport,
+                )
+            }
+        }
+    }
+}
+
 pub fn hash(type_name: &Ident) -> proc_macro2::TokenStream {
     let pg_name = Ident::new(
         &format!("{}_hash", type_name).to_lowercase(),
@@ -5,6 +5,7 @@ use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use url::Url;
 
 #[derive(Clone)]
@@ -24,6 +25,9 @@ impl Display for PgVersion {
 pub struct PgConfig {
     version: Option<PgVersion>,
     pg_config: Option<PathBuf>,
+
+    /// `pg_config`'s output for each argument it has been run with
+    cache: Arc<Mutex<HashMap<String, String>>>,
 }
 
 impl Display for PgConfig {
@@ -47,6 +51,7 @@ impl Default for PgConfig {
         PgConfig {
             version: None,
             pg_config: None,
+            cache: Default::default(),
         }
     }
 }
@@ -56,6 +61,7 @@ impl PgConfig {
         PgConfig {
             version: None,
             pg_config: Some(pg_config),
+            cache: Default::default(),
         }
     }
 
@@ -160,6 +166,18 @@ impl PgConfig {
         Ok(Path::new(&self.run("--bindir")?).to_path_buf())
     }
 
+    pub fn postgres_path(&self) -> Result<PathBuf, std::io::Error> {
+        let mut path = self.bin_dir()?;
+        path.push("postgres");
+        Ok(path)
+    }
+
+    pub fn pg_ctl_path(&self) -> Result<PathBuf, std::io::Error> {
+        let mut path = self.bin_dir()?;
+        path.push("pg_ctl");
+        Ok(path)
+    }
+
     pub fn postmaster_path(&self) -> Result<PathBuf, std::io::Error> {
         let mut path = self.bin_dir()?;
         path.push("postmaster");
@@ -206,6 +224,10 @@ impl PgConfig {
         Ok(self.run("--includedir-server")?.into())
     }
 
+    pub fn libdir(&self) -> Result<PathBuf, std::io::Error> {
+        Ok(self.run("--libdir")?.into())
+    }
+
     pub fn pkglibdir(&self) -> Result<PathBuf, std::io::Error> {
         Ok(self.run("--pkglibdir")?.into())
     }
@@ -220,7 +242,12 @@ impl PgConfig {
         Ok(path)
     }
 
+    /// Run `pg_config` with `arg`, remembering its output for next time
     fn run(&self, arg: &str) -> Result<String, std::io::Error> {
+        if let Some(output) = self.cache.lock().unwrap().get(arg) {
+            return Ok(output.clone());
+        }
+
         let pg_config = self.pg_config.clone().unwrap_or_else(|| {
             std::env::var("PG_CONFIG")
                 .unwrap_or_else(|_| "pg_config".to_string())
@@ -228,7 +255,14 @@ impl PgConfig {
         });
 
         match Command::new(&pg_config).arg(arg).output() {
-            Ok(output) => Ok(String::from_utf8(output.stdout).unwrap().trim().to_string()),
+            Ok(output) => {
+                let output = String::from_utf8(output.stdout).unwrap().trim().to_string();
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(arg.to_string(), output.clone());
+                Ok(output)
+            }
             Err(e) => match e.kind() {
                 ErrorKind::NotFound => Err(std::io::Error::new(
                     ErrorKind::NotFound,
@@ -253,6 +287,80 @@ struct ConfigToml {
     configs: HashMap<String, PathBuf>,
 }
 
+/// Why `Pgx::from_config()` couldn't load cargo-pgx' `config.toml`
+#[derive(Debug)]
+pub enum PgxConfigError {
+    /// `config.toml` doesn't exist, likely because `cargo pgx init` hasn't been run
+    ConfigNotFound { path: PathBuf },
+    /// `config.toml` exists but isn't valid
+    ParseError {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    /// `config.toml` doesn't list any `pg_config` paths
+    NoVersionsConfigured { path: PathBuf },
+    /// `$PGX_HOME` or `config.toml` couldn't be accessed
+    Io(std::io::Error),
+}
+
+impl Display for PgxConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let init = "cargo pgx init".bold().yellow();
+        match self {
+            PgxConfigError::ConfigNotFound { path } => write!(
+                f,
+                "{} not found.  Have you run `{}` yet?",
+                path.display(),
+                init
+            ),
+            PgxConfigError::ParseError { path, error } => write!(
+                f,
+                "{} could not be parsed: {}.  Run `{}` to recreate it",
+                path.display(),
+                error,
+                init
+            ),
+            PgxConfigError::NoVersionsConfigured { path } => write!(
+                f,
+                "{} doesn't configure any Postgres versions.  Run `{}` to configure them",
+                path.display(),
+                init
+            ),
+            PgxConfigError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PgxConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PgxConfigError::ParseError { error, .. } => Some(error),
+            PgxConfigError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PgxConfigError {
+    fn from(e: std::io::Error) -> Self {
+        PgxConfigError::Io(e)
+    }
+}
+
+impl From<PgxConfigError> for std::io::Error {
+    fn from(e: PgxConfigError) -> Self {
+        match e {
+            PgxConfigError::Io(e) => e,
+            PgxConfigError::ConfigNotFound { .. } | PgxConfigError::NoVersionsConfigured { .. } => {
+                std::io::Error::new(ErrorKind::NotFound, e.to_string())
+            }
+            PgxConfigError::ParseError { .. } => {
+                std::io::Error::new(ErrorKind::InvalidData, e.to_string())
+            }
+        }
+    }
+}
+
 pub enum PgConfigSelector<'a> {
     All,
     Specific(&'a str),
@@ -280,12 +388,13 @@ impl Pgx {
                 .map(|version| PgConfig {
                     version: Some(version),
                     pg_config: None,
+                    cache: Default::default(),
                 })
                 .collect(),
         })
     }
 
-    pub fn from_config() -> Result<Self, std::io::Error> {
+    pub fn from_config() -> Result<Self, PgxConfigError> {
         match std::env::var("PGX_PG_CONFIG_PATH") {
             Ok(pg_config) => {
                 // we have an environment variable that tells us the pg_config to use
@@ -297,18 +406,16 @@ impl Pgx {
                 // we'll get what we need from cargo-pgx' config.toml file
                 let path = Pgx::config_toml()?;
                 if !path.exists() {
-                    return Err(std::io::Error::new(
-                        ErrorKind::NotFound,
-                        format!(
-                            "{} not found.  Have you run `{}` yet?",
-                            path.display(),
-                            "cargo pgx init".bold().yellow()
-                        ),
-                    ));
+                    return Err(PgxConfigError::ConfigNotFound { path });
                 }
 
-                match toml::from_str::<ConfigToml>(&std::fs::read_to_string(path)?) {
+                let contents = std::fs::read_to_string(&path)?;
+                match toml::from_str::<ConfigToml>(&contents) {
                     Ok(configs) => {
+                        if configs.configs.is_empty() {
+                            return Err(PgxConfigError::NoVersionsConfigured { path });
+                        }
+
                         let mut pgx = Pgx::new();
 
                         for (_, v) in configs.configs {
@@ -316,7 +423,7 @@ impl Pgx {
                         }
                         Ok(pgx)
                     }
-                    Err(e) => Err(std::io::Error::new(ErrorKind::InvalidInput, e)),
+                    Err(error) => Err(PgxConfigError::ParseError { path, error }),
                 }
             }
         }
@@ -0,0 +1,131 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! The SQL names of Postgres' builtin types, derived from the `*OID` constants in its bindings.
+//! `pgx-pg-sys`'s build script generates the `sql_type!()` macro from these, alongside the
+//! `PgBuiltInOids` enum
+use proc_macro2::TokenTree;
+
+/// Constants that end in `OID` but aren't the Oid of a type
+const NOT_TYPES: &[&str] = &["AT_REWRITE_ALTER_OID", "HEAP_TABLE_AM_HANDLER_OID"];
+
+/// Types whose name isn't their constant's, lowercased
+const RENAMED: &[(&str, &str)] = &[
+    ("ANY", "\"any\""),
+    ("CASH", "money"),
+    ("CHAR", "\"char\""),
+    ("EVTTRIGGER", "event_trigger"),
+    ("LSN", "pg_lsn"),
+    ("PGDDLCOMMAND", "pg_ddl_command"),
+    ("PGDEPENDENCIES", "pg_dependencies"),
+    ("PGMCVLIST", "pg_mcv_list"),
+    ("PGNDISTINCT", "pg_ndistinct"),
+    ("PGNODETREE", "pg_node_tree"),
+];
+
+/// The other names the SQL standard, and Postgres' grammar, give to some types
+const ALIASES: &[(&str, &[&str])] = &[
+    ("bool", &["boolean"]),
+    ("bpchar", &["character", "char"]),
+    ("float4", &["real"]),
+    ("float8", &["double precision"]),
+    ("int2", &["smallint"]),
+    ("int4", &["integer", "int"]),
+    ("int8", &["bigint"]),
+    ("numeric", &["decimal"]),
+    ("time", &["time without time zone"]),
+    ("timestamp", &["timestamp without time zone"]),
+    ("timestamptz", &["timestamp with time zone"]),
+    ("timetz", &["time with time zone"]),
+    ("varbit", &["bit varying"]),
+    ("varchar", &["character varying"]),
+];
+
+/// The `serial` types, which aren't types at all, but shorthand for an integer column whose
+/// default is the next value of a sequence, and the type they're shorthand for
+pub const SERIAL_TYPES: &[(&str, &str)] = &[
+    ("smallserial", "smallint"),
+    ("serial", "integer"),
+    ("bigserial", "bigint"),
+];
+
+/// The names of the type whose Oid is the bindings' `oid_constant`, such as `INT4OID`, with its
+/// own name first.  Empty if the constant isn't the Oid of a type.
+///
+/// Arrays are named both `_int4` and `int4[]`
+pub fn sql_type_names(oid_constant: &str) -> Vec<String> {
+    if NOT_TYPES.contains(&oid_constant) || !oid_constant.ends_with("OID") {
+        return Vec::new();
+    }
+
+    let base = &oid_constant[..oid_constant.len() - "OID".len()];
+    let is_array = base.ends_with("ARRAY") && !base.starts_with("ANY");
+    let base = if is_array {
+        &base[..base.len() - "ARRAY".len()]
+    } else {
+        base
+    };
+
+    let name = RENAMED
+        .iter()
+        .find(|(constant, _)| *constant == base)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| base.to_lowercase());
+    let aliases = ALIASES
+        .iter()
+        .find(|(typname, _)| *typname == name)
+        .map_or(&[][..], |(_, aliases)| aliases);
+
+    if is_array {
+        let mut names = vec![
+            format!("_{}", name.trim_matches('"')),
+            format!("{}[]", name),
+        ];
+        names.extend(aliases.iter().map(|alias| format!("{}[]", alias)));
+        names
+    } else {
+        let mut names = vec![name];
+        names.extend(aliases.iter().map(|alias| alias.to_string()));
+        names
+    }
+}
+
+/// The SQL type named by the arguments of a `sql_type!()`:  either `"name"`, for a builtin type,
+/// or `lookup "name"`, for one that's looked up at runtime.  Returns the name, and whether it's
+/// builtin
+pub fn parse_sql_type_macro(
+    input: proc_macro2::TokenStream,
+) -> Result<(String, bool), (proc_macro2::Span, String)> {
+    let mut tokens = input.into_iter();
+    let (literal, builtin) = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Ident(ident)), Some(literal)) if ident == "lookup" => (literal, false),
+        (Some(literal), None) => (literal, true),
+        (Some(tt), _) => {
+            return Err((
+                tt.span(),
+                "expected `sql_type!(\"name\")` or `sql_type!(lookup \"name\")`".to_string(),
+            ))
+        }
+        (None, _) => {
+            return Err((
+                proc_macro2::Span::call_site(),
+                "expected the name of a SQL type".to_string(),
+            ))
+        }
+    };
+    if let Some(tt) = tokens.next() {
+        return Err((
+            tt.span(),
+            "unexpected tokens after the type name".to_string(),
+        ));
+    }
+
+    let span = literal.span();
+    match syn::parse2::<syn::LitStr>(literal.into()) {
+        Ok(lit) => Ok((lit.value(), builtin)),
+        Err(_) => Err((
+            span,
+            "expected the name of a SQL type, as a string".to_string(),
+        )),
+    }
+}
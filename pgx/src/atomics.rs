@@ -1,4 +1,6 @@
+use crate::{pg_sys, PGXSharedMemory};
 use once_cell::sync::OnceCell;
+use std::cell::UnsafeCell;
 
 pub struct PgAtomic<T> {
     inner: OnceCell<*mut T>,
@@ -35,3 +37,143 @@ where
 
 unsafe impl<T> Send for PgAtomic<T> where T: atomic_traits::Atomic + Default {}
 unsafe impl<T> Sync for PgAtomic<T> where T: atomic_traits::Atomic + Default {}
+
+extern "C" {
+    fn pgx_pg_atomic_init_u32(ptr: *mut pg_sys::pg_atomic_uint32, val: u32);
+    fn pgx_pg_atomic_read_u32(ptr: *mut pg_sys::pg_atomic_uint32) -> u32;
+    fn pgx_pg_atomic_write_u32(ptr: *mut pg_sys::pg_atomic_uint32, val: u32);
+    fn pgx_pg_atomic_exchange_u32(ptr: *mut pg_sys::pg_atomic_uint32, newval: u32) -> u32;
+    fn pgx_pg_atomic_compare_exchange_u32(
+        ptr: *mut pg_sys::pg_atomic_uint32,
+        expected: *mut u32,
+        newval: u32,
+    ) -> bool;
+    fn pgx_pg_atomic_fetch_add_u32(ptr: *mut pg_sys::pg_atomic_uint32, add_: i32) -> u32;
+    fn pgx_pg_atomic_fetch_sub_u32(ptr: *mut pg_sys::pg_atomic_uint32, sub_: i32) -> u32;
+
+    fn pgx_pg_atomic_init_u64(ptr: *mut pg_sys::pg_atomic_uint64, val: u64);
+    fn pgx_pg_atomic_read_u64(ptr: *mut pg_sys::pg_atomic_uint64) -> u64;
+    fn pgx_pg_atomic_write_u64(ptr: *mut pg_sys::pg_atomic_uint64, val: u64);
+    fn pgx_pg_atomic_exchange_u64(ptr: *mut pg_sys::pg_atomic_uint64, newval: u64) -> u64;
+    fn pgx_pg_atomic_compare_exchange_u64(
+        ptr: *mut pg_sys::pg_atomic_uint64,
+        expected: *mut u64,
+        newval: u64,
+    ) -> bool;
+    fn pgx_pg_atomic_fetch_add_u64(ptr: *mut pg_sys::pg_atomic_uint64, add_: i64) -> u64;
+    fn pgx_pg_atomic_fetch_sub_u64(ptr: *mut pg_sys::pg_atomic_uint64, sub_: i64) -> u64;
+}
+
+macro_rules! pg_atomic_impl {
+    (
+        $(#[$meta:meta])*
+        $name:ident, $pg_type:ident, $value:ty, $delta:ty,
+        $init:ident, $read:ident, $write:ident, $exchange:ident, $compare_exchange:ident,
+        $fetch_add:ident, $fetch_sub:ident
+    ) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        pub struct $name(UnsafeCell<pg_sys::$pg_type>);
+
+        unsafe impl Send for $name {}
+        unsafe impl Sync for $name {}
+        unsafe impl PGXSharedMemory for $name {}
+
+        impl $name {
+            /// Create a new atomic with the initial value `value`
+            pub fn new(value: $value) -> Self {
+                let atomic = $name(UnsafeCell::new(pg_sys::$pg_type::default()));
+                unsafe { $init(atomic.0.get(), value) }
+                atomic
+            }
+
+            /// The current value
+            pub fn read(&self) -> $value {
+                unsafe { $read(self.0.get()) }
+            }
+
+            /// Set the value.  This is not a barrier, so it can be reordered with other writes
+            pub fn write(&self, value: $value) {
+                unsafe { $write(self.0.get(), value) }
+            }
+
+            /// Set the value, returning the previous value
+            pub fn exchange(&self, value: $value) -> $value {
+                unsafe { $exchange(self.0.get(), value) }
+            }
+
+            /// Set the value to `new` if it's currently `current`.  Returns `Ok` with the previous
+            /// value if it was, or `Err` with the actual current value if it wasn't
+            pub fn compare_exchange(&self, current: $value, new: $value) -> Result<$value, $value> {
+                let mut expected = current;
+                if unsafe { $compare_exchange(self.0.get(), &mut expected, new) } {
+                    Ok(current)
+                } else {
+                    Err(expected)
+                }
+            }
+
+            /// Add `delta` (which may be negative) to the value, wrapping on overflow, and return
+            /// the previous value
+            pub fn fetch_add(&self, delta: $delta) -> $value {
+                unsafe { $fetch_add(self.0.get(), delta) }
+            }
+
+            /// Subtract `delta` from the value, wrapping on overflow, and return the previous
+            /// value
+            pub fn fetch_sub(&self, delta: $delta) -> $value {
+                unsafe { $fetch_sub(self.0.get(), delta) }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name::new(0)
+            }
+        }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_tuple(stringify!($name)).field(&self.read()).finish()
+            }
+        }
+    };
+}
+
+pg_atomic_impl!(
+    /// A Postgres `pg_atomic_uint32`, meant to be embedded in a struct that lives in shared memory.
+    ///
+    /// Operations go through Postgres' `pg_atomic_*_u32()` functions, so they also work on
+    /// platforms where Postgres emulates atomics with spinlocks.  Except for `read()` and
+    /// `write()`, every operation is a full memory barrier.
+    PgAtomicU32,
+    pg_atomic_uint32,
+    u32,
+    i32,
+    pgx_pg_atomic_init_u32,
+    pgx_pg_atomic_read_u32,
+    pgx_pg_atomic_write_u32,
+    pgx_pg_atomic_exchange_u32,
+    pgx_pg_atomic_compare_exchange_u32,
+    pgx_pg_atomic_fetch_add_u32,
+    pgx_pg_atomic_fetch_sub_u32
+);
+
+pg_atomic_impl!(
+    /// A Postgres `pg_atomic_uint64`, meant to be embedded in a struct that lives in shared memory.
+    ///
+    /// Operations go through Postgres' `pg_atomic_*_u64()` functions, so they also work on
+    /// platforms where Postgres emulates 64bit atomics.  Except for `read()` and `write()`, every
+    /// operation is a full memory barrier.
+    PgAtomicU64,
+    pg_atomic_uint64,
+    u64,
+    i64,
+    pgx_pg_atomic_init_u64,
+    pgx_pg_atomic_read_u64,
+    pgx_pg_atomic_write_u64,
+    pgx_pg_atomic_exchange_u64,
+    pgx_pg_atomic_compare_exchange_u64,
+    pgx_pg_atomic_fetch_add_u64,
+    pgx_pg_atomic_fetch_sub_u64
+);
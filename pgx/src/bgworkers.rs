@@ -100,6 +100,11 @@ impl BackgroundWorker {
         GOT_SIGTERM.swap(false, Ordering::SeqCst)
     }
 
+    /// Has a SIGTERM been received, without clearing it as `sigterm_received()` does?
+    pub(crate) fn sigterm_pending() -> bool {
+        GOT_SIGTERM.load(Ordering::SeqCst)
+    }
+
     /// Wait for the specified amount of time on the background worker's latch
     ///
     /// Returns true if we're still supposed to be alive and haven't received a SIGTERM
@@ -0,0 +1,95 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Information about how the running Postgres server was built, so extensions can degrade
+//! gracefully when an optional feature (ICU, JIT, SSL, ...) isn't available
+use crate::pg_sys::pg_config_defines;
+use crate::{pg_sys, PgCStr};
+
+/// How the running Postgres server was built.
+///
+/// Runtime values come from the server's read-only GUCs.  The rest come from the `pg_config.h`
+/// of the Postgres installation `pgx` was compiled against (see `pg_sys::pg_config_defines`),
+/// which is normally the same installation the extension is loaded into.
+#[derive(Debug, Clone)]
+pub struct PgBuildInfo {
+    /// The `server_version` GUC, ie "13.2"
+    pub version: String,
+    /// The `server_version_num` GUC, ie 130002
+    pub version_num: i32,
+    /// Was the server built with `--enable-cassert`?  (the `debug_assertions` GUC)
+    pub asserts_enabled: bool,
+    /// Can the `jit_provider` library be loaded?
+    pub jit_available: bool,
+    /// Built `--with-icu`
+    pub icu: bool,
+    /// Built `--with-llvm`, for JIT compilation (Postgres 11+)
+    pub llvm: bool,
+    /// Built `--with-openssl`
+    pub openssl: bool,
+    /// Built `--with-libxml`
+    pub libxml: bool,
+    /// Built `--with-ldap`
+    pub ldap: bool,
+    /// Built `--enable-nls`
+    pub nls: bool,
+    /// Built with zlib
+    pub zlib: bool,
+}
+
+/// Describe how the running Postgres server was built
+pub fn build_info() -> PgBuildInfo {
+    PgBuildInfo {
+        version: config_option("server_version").expect("server_version GUC is missing"),
+        version_num: config_option("server_version_num")
+            .expect("server_version_num GUC is missing")
+            .parse()
+            .expect("server_version_num is not an integer"),
+        asserts_enabled: asserts_enabled(),
+        jit_available: jit_available(),
+        icu: has_icu(),
+        llvm: pg_config_defines::USE_LLVM,
+        openssl: pg_config_defines::USE_OPENSSL,
+        libxml: pg_config_defines::USE_LIBXML,
+        ldap: pg_config_defines::USE_LDAP,
+        nls: pg_config_defines::ENABLE_NLS,
+        zlib: pg_config_defines::HAVE_LIBZ,
+    }
+}
+
+/// Was Postgres built with ICU collation support?
+#[inline]
+pub fn has_icu() -> bool {
+    pg_config_defines::USE_ICU
+}
+
+/// Was the running server built with assertions enabled (`--enable-cassert`)?
+pub fn asserts_enabled() -> bool {
+    config_option("debug_assertions").as_deref() == Some("on")
+}
+
+/// Can JIT compilation be used?  This is `pg_jit_available()`, which attempts to load the
+/// `jit_provider` library.  It doesn't consider whether the `jit` GUC is on.
+///
+/// JIT compilation doesn't exist prior to Postgres 11, where this is always false.
+#[cfg(feature = "pg10")]
+pub fn jit_available() -> bool {
+    false
+}
+
+/// Can JIT compilation be used?  This is `pg_jit_available()`, which attempts to load the
+/// `jit_provider` library.  It doesn't consider whether the `jit` GUC is on.
+///
+/// JIT compilation doesn't exist prior to Postgres 11, where this is always false.
+#[cfg(any(feature = "pg11", feature = "pg12", feature = "pg13"))]
+pub fn jit_available() -> bool {
+    unsafe { crate::direct_function_call::<bool>(pg_sys::pg_jit_available, vec![]) }
+        .unwrap_or(false)
+}
+
+/// The current value of the GUC `name`, or `None` if there is no such GUC
+fn config_option(name: &str) -> Option<String> {
+    let name = std::ffi::CString::new(name).expect("GUC name contains a NUL byte");
+    unsafe { PgCStr::from_ptr(pg_sys::GetConfigOption(name.as_ptr(), true, false)) }
+        .map(|value| value.to_str_lossy().into_owned())
+}
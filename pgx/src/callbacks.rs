@@ -149,69 +149,6 @@ pub fn register_xact_callback<F>(which_event: PgXactCallbackEvent, f: F) -> Xact
 where
     F: FnOnce() + std::panic::UnwindSafe + std::panic::RefUnwindSafe + 'static,
 {
-    // our map of xact callbacks.  It starts as None and gets initialized below in maybe_initialize()
-    static mut XACT_HOOKS: Option<CallbackMap> = None;
-
-    // internal function that we register as an XactCallback
-    unsafe extern "C" fn callback(event: pg_sys::XactEvent, _arg: *mut ::std::os::raw::c_void) {
-        let which_event = PgXactCallbackEvent::translate_pg_event(event);
-
-        let hooks = match which_event {
-            // pgx's XactCallbacks are per-transaction, so when the transaction is over
-            // (that's either Commit or Abort, which are mutually exclusive), we replace our
-            // const XACT_HOOKS with a new, empty Map so that subsequent transactions won't accidentally run
-            // these hooks again.
-            //
-            // Note that we still run any hooks that are registered for these events in this xact
-            PgXactCallbackEvent::Commit
-            | PgXactCallbackEvent::Abort
-            | PgXactCallbackEvent::ParallelCommit
-            | PgXactCallbackEvent::ParallelAbort => XACT_HOOKS
-                .replace(HashMap::new())
-                .expect("XACT_HOOKS was None during Commit/Abort")
-                .remove(&which_event),
-
-            // not in a transaction-end event, so just borrow our map
-            _ => XACT_HOOKS
-                .as_mut()
-                .expect("XACT_HOOKS was None")
-                .remove(&which_event),
-        };
-
-        // if we have a vec of hooks for this event they're consumed here and executed
-        // in the order they were registered
-        if let Some(hooks) = hooks {
-            for hook in hooks.into_iter() {
-                // TODO:  do we need to catch panics and do something with them?  They'll cause
-                //  the Postgres backend to Abort() if we're handling XactEvent::Commit/Abort events
-
-                // effectively 'take' the hook from the internal RefCell
-                if let Some(hook) = hook.replace(None) {
-                    // and execute it under guard for proper panic/elog(ERROR) handling
-                    crate::guard::guard(hook.0);
-                }
-            }
-        }
-    }
-
-    // internal function to manage initialization of our transaction callback
-    fn maybe_initialize<'a>() -> &'a mut CallbackMap {
-        unsafe {
-            // if this is our first time here since the Postgres backend started, XACT_HOOKS will be None
-            if XACT_HOOKS.is_none() {
-                // so lets swap it out with a new HashMap, which will live for the duration of the backend
-                XACT_HOOKS.replace(HashMap::new());
-
-                // and register our single callback function (internally defined above)
-                pg_sys::RegisterXactCallback(Some(callback), std::ptr::null_mut());
-            }
-
-            XACT_HOOKS
-                .as_mut()
-                .expect("XACT_HOOKS was None during maybe_initialize") // this should never happen
-        }
-    }
-
     // get a mutable reference to XACT_HOOKS
     let hooks = maybe_initialize();
 
@@ -226,6 +163,90 @@ where
     XactCallbackReceipt(wrapped_func)
 }
 
+// our map of xact callbacks.  It starts as None and gets initialized below in maybe_initialize()
+static mut XACT_HOOKS: Option<CallbackMap> = None;
+
+// internal function that we register as an XactCallback
+unsafe extern "C" fn callback(event: pg_sys::XactEvent, _arg: *mut ::std::os::raw::c_void) {
+    let which_event = PgXactCallbackEvent::translate_pg_event(event);
+
+    match which_event {
+        // the vetoes of `pgx::xact::register_precommit_veto()` are called before any hooks, and
+        // raise an ERROR that aborts the PREPARE
+        PgXactCallbackEvent::PrePrepare => {
+            crate::xact::set_preparing(true);
+            crate::guard::guard(crate::xact::check_prepare_vetoes);
+        }
+        PgXactCallbackEvent::Abort => crate::xact::set_preparing(false),
+        _ => {}
+    }
+
+    let hooks = match which_event {
+        // pgx's XactCallbacks are per-transaction, so when the transaction is over
+        // (that's either Commit, Abort or Prepare, which are mutually exclusive), we replace our
+        // const XACT_HOOKS with a new, empty Map so that subsequent transactions won't accidentally run
+        // these hooks again.
+        //
+        // Note that we still run any hooks that are registered for these events in this xact
+        PgXactCallbackEvent::Commit
+        | PgXactCallbackEvent::Abort
+        | PgXactCallbackEvent::Prepare
+        | PgXactCallbackEvent::ParallelCommit
+        | PgXactCallbackEvent::ParallelAbort => XACT_HOOKS
+            .replace(HashMap::new())
+            .expect("XACT_HOOKS was None during Commit/Abort/Prepare")
+            .remove(&which_event),
+
+        // not in a transaction-end event, so just borrow our map
+        _ => XACT_HOOKS
+            .as_mut()
+            .expect("XACT_HOOKS was None")
+            .remove(&which_event),
+    };
+
+    // if we have a vec of hooks for this event they're consumed here and executed
+    // in the order they were registered
+    if let Some(hooks) = hooks {
+        for hook in hooks.into_iter() {
+            // TODO:  do we need to catch panics and do something with them?  They'll cause
+            //  the Postgres backend to Abort() if we're handling XactEvent::Commit/Abort events
+
+            // effectively 'take' the hook from the internal RefCell
+            if let Some(hook) = hook.replace(None) {
+                // and execute it under guard for proper panic/elog(ERROR) handling
+                crate::guard::guard(hook.0);
+            }
+        }
+    }
+
+    if which_event == PgXactCallbackEvent::Prepare {
+        crate::xact::set_preparing(false);
+    }
+}
+
+// internal function to manage initialization of our transaction callback
+fn maybe_initialize<'a>() -> &'a mut CallbackMap {
+    unsafe {
+        // if this is our first time here since the Postgres backend started, XACT_HOOKS will be None
+        if XACT_HOOKS.is_none() {
+            // so lets swap it out with a new HashMap, which will live for the duration of the backend
+            XACT_HOOKS.replace(HashMap::new());
+
+            // and register our single callback function (internally defined above)
+            pg_sys::RegisterXactCallback(Some(callback), std::ptr::null_mut());
+        }
+
+        XACT_HOOKS
+            .as_mut()
+            .expect("XACT_HOOKS was None during maybe_initialize") // this should never happen
+    }
+}
+
+/// Make sure our `XactCallback` is registered, so that it sees every transaction event from now on
+pub(crate) fn ensure_xact_callback() {
+    maybe_initialize();
+}
+
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
 pub enum PgSubXactCallbackEvent {
     /// Fired when a subtransaction is aborted.  While Rust `panic!()`s and Postgres `ereport(ERROR)`s
@@ -355,6 +376,10 @@ where
                     // reset SUB_HOOKS to None on outer transaction ABORT
                     SUB_HOOKS.take();
                 });
+                register_xact_callback(PgXactCallbackEvent::Prepare, || {
+                    // reset SUB_HOOKS to None on outer transaction PREPARE
+                    SUB_HOOKS.take();
+                });
             }
 
             SUB_HOOKS
@@ -0,0 +1,303 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Checked conversions between numeric types, which accept and reject exactly the values the
+//! equivalent SQL casts do.
+//!
+//! Where a Rust `as` cast silently truncates or saturates, these return a `CastError` carrying
+//! the same SQLSTATE and message that Postgres' cast would have raised, such as
+//! `integer out of range`.  `CastError` converts into an `ErrorReport`, so a `#[pg_extern]`
+//! function returning `Result<T, CastError>` raises exactly the ERROR the SQL cast would.
+//!
+//! ```rust,no_run
+//! use pgx::cast;
+//!
+//! // rounded half to even, like `2.5::float8::integer`
+//! assert_eq!(cast::f64_to_i32(2.5), Ok(2));
+//! assert!(cast::i64_to_i32(i64::MAX).is_err());
+//! ```
+use crate::{ErrorReport, Numeric, PgLogLevel, PgSqlErrorCode};
+use std::fmt;
+
+/// The ERROR a SQL cast would have raised for a value
+#[derive(Clone)]
+pub struct CastError {
+    sqlerrcode: PgSqlErrorCode,
+    message: String,
+}
+
+impl CastError {
+    fn new<S: Into<String>>(sqlerrcode: PgSqlErrorCode, message: S) -> Self {
+        CastError {
+            sqlerrcode,
+            message: message.into(),
+        }
+    }
+
+    fn out_of_range(type_name: &str) -> Self {
+        CastError::new(
+            PgSqlErrorCode::ERRCODE_NUMERIC_VALUE_OUT_OF_RANGE,
+            format!("{} out of range", type_name),
+        )
+    }
+
+    fn invalid_syntax(type_name: &str, input: &str) -> Self {
+        CastError::new(
+            PgSqlErrorCode::ERRCODE_INVALID_TEXT_REPRESENTATION,
+            invalid_syntax_message(type_name, input),
+        )
+    }
+
+    pub fn sqlerrcode(&self) -> PgSqlErrorCode {
+        self.sqlerrcode
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Debug for CastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CastError")
+            .field("sqlerrcode", &(self.sqlerrcode as i32))
+            .field("message", &self.message)
+            .finish()
+    }
+}
+
+impl fmt::Display for CastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl PartialEq for CastError {
+    fn eq(&self, other: &Self) -> bool {
+        self.sqlerrcode as i32 == other.sqlerrcode as i32 && self.message == other.message
+    }
+}
+
+impl From<CastError> for ErrorReport {
+    fn from(e: CastError) -> Self {
+        ErrorReport::new(e.sqlerrcode, e.message)
+    }
+}
+
+/// The value of a successful cast, or its `CastError` raised as an ERROR
+pub(crate) fn or_raise<T>(result: Result<T, CastError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            ErrorReport::from(e).report(PgLogLevel::ERROR);
+            unreachable!("ERROR didn't abort the transaction")
+        }
+    }
+}
+
+/// `value::float8::integer`:  rounded half to even, and then checked to be in range
+pub fn f64_to_i32(value: f64) -> Result<i32, CastError> {
+    let rounded = rint(value);
+
+    // NaN fails both comparisons
+    if rounded >= i32::MIN as f64 && rounded < -(i32::MIN as f64) {
+        Ok(rounded as i32)
+    } else {
+        Err(CastError::out_of_range("integer"))
+    }
+}
+
+/// `value::float8::bigint`:  rounded half to even, and then checked to be in range
+pub fn f64_to_i64(value: f64) -> Result<i64, CastError> {
+    let rounded = rint(value);
+
+    // NaN fails both comparisons
+    if rounded >= i64::MIN as f64 && rounded < -(i64::MIN as f64) {
+        Ok(rounded as i64)
+    } else {
+        Err(CastError::out_of_range("bigint"))
+    }
+}
+
+/// `value::bigint::integer`
+pub fn i64_to_i32(value: i64) -> Result<i32, CastError> {
+    if value >= i32::MIN as i64 && value <= i32::MAX as i64 {
+        Ok(value as i32)
+    } else {
+        Err(CastError::out_of_range("integer"))
+    }
+}
+
+/// `value::numeric::bigint`:  rounded half away from zero, and then checked to be in range.
+///
+/// `value` is parsed the way Postgres' `numeric_in()` would, so it's an error for it not to be a
+/// valid `numeric`.
+pub fn numeric_to_i64(value: &Numeric) -> Result<i64, CastError> {
+    let input = value.0.as_str();
+    let trimmed = input.trim_matches(is_c_space);
+    if trimmed.eq_ignore_ascii_case("nan") {
+        return Err(CastError::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "cannot convert NaN to bigint",
+        ));
+    }
+
+    let invalid_syntax = || CastError::invalid_syntax("numeric", input);
+    let (negative, unsigned) = split_sign(trimmed);
+    let (mantissa, exponent) = match unsigned.find(|c| c == 'e' || c == 'E') {
+        Some(e) => (&unsigned[..e], Some(&unsigned[e + 1..])),
+        None => (unsigned, None),
+    };
+    let (integral, fractional) = match mantissa.find('.') {
+        Some(point) => (&mantissa[..point], &mantissa[point + 1..]),
+        None => (mantissa, ""),
+    };
+    if integral.len() + fractional.len() == 0
+        || !integral
+            .bytes()
+            .chain(fractional.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid_syntax());
+    }
+
+    let exponent = match exponent {
+        None => 0,
+        Some(exponent) => {
+            // it's parsed with strtol(), which also skips leading whitespace
+            let (exponent_negative, digits) = split_sign(exponent.trim_start_matches(is_c_space));
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid_syntax());
+            }
+
+            // numeric_in() rejects exponents beyond NUMERIC_MAX_PRECISION
+            match digits.parse::<i64>() {
+                Ok(exponent) if exponent <= 1000 => {
+                    if exponent_negative {
+                        -exponent
+                    } else {
+                        exponent
+                    }
+                }
+                _ => return Err(invalid_syntax()),
+            }
+        }
+    };
+
+    // all of the digits, without leading zeros, and where the decimal point falls among them
+    let digits = integral
+        .bytes()
+        .chain(fractional.bytes())
+        .collect::<Vec<_>>();
+    let zeros = digits.iter().take_while(|b| **b == b'0').count();
+    let digits = &digits[zeros..];
+    let point = integral.len() as i64 - zeros as i64 + exponent;
+    if digits.is_empty() || point < 0 {
+        return Ok(0);
+    } else if point > 19 {
+        return Err(CastError::out_of_range("bigint"));
+    }
+
+    let point = point as usize;
+    let mut magnitude = 0i128;
+    for i in 0..point {
+        magnitude = magnitude * 10 + digits.get(i).map_or(0, |b| (b - b'0') as i128);
+    }
+    if digits.get(point).map_or(false, |b| *b >= b'5') {
+        magnitude += 1;
+    }
+
+    let value = if negative { -magnitude } else { magnitude };
+    if value >= i64::MIN as i128 && value <= i64::MAX as i128 {
+        Ok(value as i64)
+    } else {
+        Err(CastError::out_of_range("bigint"))
+    }
+}
+
+/// `value::text::integer`:  optional whitespace and sign, decimal digits, and optional
+/// whitespace, which must be in range
+pub fn text_to_i32(value: &str) -> Result<i32, CastError> {
+    let (negative, unsigned) = split_sign(value.trim_start_matches(is_c_space));
+    let digits_len = unsigned
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or_else(|| unsigned.len());
+    if digits_len == 0 {
+        return Err(CastError::invalid_syntax("integer", value));
+    }
+
+    // accumulated as a negative number, as that's the larger half of the range
+    let mut result = 0i32;
+    for digit in unsigned[..digits_len].bytes() {
+        result = match result
+            .checked_mul(10)
+            .and_then(|result| result.checked_sub((digit - b'0') as i32))
+        {
+            Some(result) => result,
+            None => {
+                return Err(CastError::new(
+                    PgSqlErrorCode::ERRCODE_NUMERIC_VALUE_OUT_OF_RANGE,
+                    format!("value \"{}\" is out of range for type integer", value),
+                ))
+            }
+        };
+    }
+
+    if !unsigned[digits_len..]
+        .trim_start_matches(is_c_space)
+        .is_empty()
+    {
+        return Err(CastError::invalid_syntax("integer", value));
+    }
+
+    if negative {
+        Ok(result)
+    } else {
+        result.checked_neg().ok_or_else(|| {
+            CastError::new(
+                PgSqlErrorCode::ERRCODE_NUMERIC_VALUE_OUT_OF_RANGE,
+                format!("value \"{}\" is out of range for type integer", value),
+            )
+        })
+    }
+}
+
+/// C's `rint()` in its default rounding mode:  to the nearest integer, with halves rounded to the
+/// even one
+fn rint(value: f64) -> f64 {
+    let rounded = value.round();
+    if (rounded - value).abs() == 0.5 {
+        (value / 2.0).round() * 2.0
+    } else {
+        rounded
+    }
+}
+
+/// C's `isspace()` in the "C" locale
+fn is_c_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\x0B' | '\x0C' | '\r')
+}
+
+fn split_sign(value: &str) -> (bool, &str) {
+    if let Some(unsigned) = value.strip_prefix('-') {
+        (true, unsigned)
+    } else if let Some(unsigned) = value.strip_prefix('+') {
+        (false, unsigned)
+    } else {
+        (false, value)
+    }
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+fn invalid_syntax_message(type_name: &str, input: &str) -> String {
+    if type_name == "integer" {
+        format!("invalid input syntax for integer: \"{}\"", input)
+    } else {
+        format!("invalid input syntax for type {}: \"{}\"", type_name, input)
+    }
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+fn invalid_syntax_message(type_name: &str, input: &str) -> String {
+    format!("invalid input syntax for type {}: \"{}\"", type_name, input)
+}
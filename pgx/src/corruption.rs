@@ -0,0 +1,263 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Checks for corrupted varlenas, made before they're detoasted.
+//!
+//! A varlena with a bad header, whether from on-disk corruption or a buggy `send`/`recv`
+//! function, would otherwise crash the backend, or fail deep inside detoasting with an error
+//! such as `invalid memory alloc request size`.  The `FromDatum` implementations of `&str`,
+//! `&[u8]`, and `#[derive(PostgresType)]` types check the header first, and raise an ERROR
+//! with `ERRCODE_DATA_CORRUPTED` that names the type being decoded.
+//!
+//! Only the header itself is checked on every decode.  Checking that a varlena fits in the
+//! palloc'd chunk it's in walks the memory context tree, so it's only done in builds with
+//! `debug_assertions`, or when [`varlena_chunk_corruption()`] is called explicitly.
+//!
+//! Before that ERROR is raised, every function registered with [`corruption_hook()`] is called,
+//! so a monitoring extension can record where the corrupted value came from.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//!
+//! #[pg_guard]
+//! pub extern "C" fn _PG_init() {
+//!     corruption_hook(|info| {
+//!         // such as "while scanning tid (0,5) of public.documents", if the code that was
+//!         // decoding the value pushed an `error_context` frame
+//!         warning!("{} value is corrupted: {} {:?}", info.type_name, info.reason, info.context);
+//!     });
+//! }
+//! ```
+use crate::{error_context, pg_sys, pgbox::palloc_chunk_space, varlena::*, PgLogLevel};
+use crate::{ErrorReport, PgSqlErrorCode};
+use std::cell::RefCell;
+use std::panic::AssertUnwindSafe;
+
+/// `MaxAllocSize`, the most `palloc()` will allocate
+const MAX_ALLOC_SIZE: usize = 0x3fff_ffff;
+
+/// The two bits of a compressed varlena's raw size that Postgres 14 uses for its compression
+/// method, and that must be zero before then
+const COMPRESSION_METHOD_MASK: u32 = 0xc000_0000;
+
+/// A corrupted varlena, as passed to the functions registered with [`corruption_hook()`]
+#[derive(Debug, Clone)]
+pub struct CorruptionInfo {
+    /// The type that was being decoded, such as `text`
+    pub type_name: String,
+
+    /// What's wrong with its header
+    pub reason: String,
+
+    /// The context of the `error_context` frames in place when it was found, innermost first.
+    /// This is where the calling code can say which relation and tuple the value came from
+    pub context: Vec<String>,
+}
+
+type CorruptionHookFn = Box<dyn Fn(&CorruptionInfo)>;
+
+thread_local! {
+    static HOOKS: RefCell<Vec<CorruptionHookFn>> = RefCell::new(Vec::new());
+}
+
+/// Register a function that's called with every corrupted varlena that's found, before the
+/// ERROR about it is raised.  Hooks are called in the order they were registered, for the life
+/// of the backend, so they're typically registered in `_PG_init()`.
+///
+/// A hook must not raise an ERROR.  If it `panic!()`s, the panic is ignored and the other hooks
+/// are still called
+pub fn corruption_hook<F: Fn(&CorruptionInfo) + 'static>(hook: F) {
+    HOOKS.with(|hooks| hooks.borrow_mut().push(Box::new(hook)));
+}
+
+/// What's wrong with the header of the varlena at `ptr`, or `None` if it looks consistent.
+///
+/// This only reads the header, so it's cheap enough to call before every decode.
+///
+/// ## Safety
+///
+/// `ptr` must be NULL or point to a readable varlena header
+pub unsafe fn varlena_corruption(ptr: *const pg_sys::varlena) -> Option<String> {
+    if ptr.is_null() {
+        return Some("it's a NULL pointer".to_string());
+    }
+
+    if varatt_is_1b_e(ptr) {
+        let tag = vartag_1b_e(ptr) as pg_sys::vartag_external;
+        if tag == pg_sys::vartag_external_VARTAG_ONDISK {
+            // the TOAST pointer follows the header byte and the tag, unaligned
+            let external =
+                std::ptr::read_unaligned(vardata_1b_e(ptr) as *const pg_sys::varatt_external);
+            if external.va_rawsize < pg_sys::VARHDRSZ as i32
+                || external.va_rawsize as usize > MAX_ALLOC_SIZE
+            {
+                return Some(format!(
+                    "its TOAST pointer has a size of {} bytes",
+                    external.va_rawsize
+                ));
+            } else if external.va_extsize < 0
+                || external.va_extsize > external.va_rawsize - pg_sys::VARHDRSZ as i32
+            {
+                return Some(format!(
+                    "its TOAST pointer stores {} bytes, but it's only {} bytes",
+                    external.va_extsize, external.va_rawsize
+                ));
+            } else if external.va_valueid == pg_sys::InvalidOid
+                || external.va_toastrelid == pg_sys::InvalidOid
+            {
+                return Some("its TOAST pointer doesn't name a TOAST value".to_string());
+            }
+        } else if tag != pg_sys::vartag_external_VARTAG_INDIRECT && !vartag_is_expanded(tag) {
+            return Some(format!("it has an unrecognized TOAST tag of {}", tag));
+        }
+        return None;
+    }
+
+    if varatt_is_4b(ptr) {
+        let size = varsize_4b(ptr);
+        if size < pg_sys::VARHDRSZ {
+            return Some(format!(
+                "its header says it's {} bytes, which is smaller than the header",
+                size
+            ));
+        }
+
+        if varatt_is_b8_c(ptr) {
+            if size < pg_sys::VARHDRSZ + std::mem::size_of::<u32>() {
+                return Some(format!(
+                    "it's compressed, but only {} bytes, which is too small to be",
+                    size
+                ));
+            }
+
+            let info = std::ptr::read_unaligned(vardata_4b(ptr) as *const u32);
+            if info & COMPRESSION_METHOD_MASK != 0 {
+                return Some(format!(
+                    "it's compressed with an unrecognized method of {}",
+                    info >> 30
+                ));
+            } else if info as usize + pg_sys::VARHDRSZ > MAX_ALLOC_SIZE {
+                return Some(format!(
+                    "it decompresses to {} bytes, which is more than can be allocated",
+                    info
+                ));
+            }
+        }
+    }
+
+    None
+}
+
+/// Whether the varlena at `ptr`, if it's the start of a palloc'd chunk, claims to be larger than
+/// that chunk, which its header alone can't show.  `None` if it fits, or isn't palloc'd.
+///
+/// This walks the whole memory context tree to find the chunk's context, so unlike
+/// [`varlena_corruption()`] it's too slow to call on every decode.  [`check_varlena()`] only
+/// calls it in builds with `debug_assertions`.
+///
+/// ## Safety
+///
+/// `ptr` must be NULL or point to a readable varlena header, and if it's MAXALIGN'd, the 8 bytes
+/// before it must be readable too, as that's where a palloc'd chunk's context pointer would be
+pub unsafe fn varlena_chunk_corruption(ptr: *const pg_sys::varlena) -> Option<String> {
+    if ptr.is_null() || varatt_is_1b_e(ptr) {
+        return None;
+    }
+
+    let space = palloc_chunk_space(ptr as *mut std::os::raw::c_void)?;
+    let size = varsize_any(ptr);
+    if size > space {
+        Some(format!(
+            "its header says it's {} bytes, but it was allocated with {}",
+            size, space
+        ))
+    } else {
+        None
+    }
+}
+
+/// Raise an ERROR with `ERRCODE_DATA_CORRUPTED` if the varlena at `ptr`, a `type_name`, is
+/// corrupted, after calling every hook registered with [`corruption_hook()`].  With
+/// `debug_assertions`, this also checks it with [`varlena_chunk_corruption()`]
+///
+/// ## Safety
+///
+/// See [`varlena_corruption()`], and [`varlena_chunk_corruption()`] with `debug_assertions`
+pub unsafe fn check_varlena(ptr: *const pg_sys::varlena, type_name: &str) {
+    if let Some(reason) = varlena_corruption(ptr) {
+        report_corruption(type_name, reason);
+    }
+
+    if cfg!(debug_assertions) {
+        if let Some(reason) = varlena_chunk_corruption(ptr) {
+            report_corruption(type_name, reason);
+        }
+    }
+}
+
+/// The SQL name `#[derive(PostgresType)]` gives `T`, which is its Rust name without its path,
+/// lowercased, for naming it in corruption reports
+pub(crate) fn sql_type_name<T>() -> String {
+    let type_name = std::any::type_name::<T>();
+    let type_name = type_name.split('<').next().unwrap_or(type_name);
+    type_name
+        .rsplit("::")
+        .next()
+        .unwrap_or(type_name)
+        .to_lowercase()
+}
+
+/// `pg_detoast_datum_packed()`, after checking that `ptr`, a `type_name`, isn't corrupted
+///
+/// ## Safety
+///
+/// See [`varlena_corruption()`]
+pub unsafe fn detoast_datum_packed_checked(
+    ptr: *mut pg_sys::varlena,
+    type_name: &str,
+) -> *mut pg_sys::varlena {
+    check_varlena(ptr, type_name);
+    pg_sys::pg_detoast_datum_packed(ptr)
+}
+
+/// `pg_detoast_datum_copy()`, after checking that `ptr`, a `type_name`, isn't corrupted
+///
+/// ## Safety
+///
+/// See [`varlena_corruption()`]
+pub unsafe fn detoast_datum_copy_checked(
+    ptr: *mut pg_sys::varlena,
+    type_name: &str,
+) -> *mut pg_sys::varlena {
+    check_varlena(ptr, type_name);
+    pg_sys::pg_detoast_datum_copy(ptr)
+}
+
+/// Call the registered hooks, then raise an ERROR with `ERRCODE_DATA_CORRUPTED`, because a
+/// `type_name` value is corrupted
+pub fn report_corruption(type_name: &str, reason: String) -> ! {
+    let info = CorruptionInfo {
+        type_name: type_name.to_string(),
+        reason,
+        context: error_context::current(),
+    };
+
+    HOOKS.with(|hooks| {
+        if let Ok(hooks) = hooks.try_borrow() {
+            for hook in hooks.iter() {
+                if std::panic::catch_unwind(AssertUnwindSafe(|| hook(&info))).is_err() {
+                    pg_sys::guard::forget_panic();
+                }
+            }
+        }
+    });
+
+    ErrorReport::new(
+        PgSqlErrorCode::ERRCODE_DATA_CORRUPTED,
+        format!("corrupted {} value: {}", info.type_name, info.reason),
+    )
+    .report(PgLogLevel::ERROR);
+    unreachable!("an ERROR was reported")
+}
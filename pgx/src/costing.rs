@@ -0,0 +1,329 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Planner cost parameters and estimation helpers, for `amcostestimate` functions and custom
+//! paths
+use crate::{pg_sys, PgBox, PgList};
+
+/// The `seq_page_cost` GUC: the cost of a sequentially-fetched disk page
+#[inline]
+pub fn seq_page_cost() -> pg_sys::Cost {
+    unsafe { pg_sys::seq_page_cost }
+}
+
+/// The `random_page_cost` GUC: the cost of a non-sequentially-fetched disk page
+#[inline]
+pub fn random_page_cost() -> pg_sys::Cost {
+    unsafe { pg_sys::random_page_cost }
+}
+
+/// The `cpu_tuple_cost` GUC: the cost of processing each tuple
+#[inline]
+pub fn cpu_tuple_cost() -> pg_sys::Cost {
+    unsafe { pg_sys::cpu_tuple_cost }
+}
+
+/// The `cpu_index_tuple_cost` GUC: the cost of processing each index entry
+#[inline]
+pub fn cpu_index_tuple_cost() -> pg_sys::Cost {
+    unsafe { pg_sys::cpu_index_tuple_cost }
+}
+
+/// The `cpu_operator_cost` GUC: the cost of evaluating each operator or function
+#[inline]
+pub fn cpu_operator_cost() -> pg_sys::Cost {
+    unsafe { pg_sys::cpu_operator_cost }
+}
+
+/// The `parallel_tuple_cost` GUC: the cost of passing a tuple from a worker to the leader
+#[inline]
+pub fn parallel_tuple_cost() -> pg_sys::Cost {
+    unsafe { pg_sys::parallel_tuple_cost }
+}
+
+/// The `parallel_setup_cost` GUC: the cost of starting parallel workers
+#[inline]
+pub fn parallel_setup_cost() -> pg_sys::Cost {
+    unsafe { pg_sys::parallel_setup_cost }
+}
+
+/// The `effective_cache_size` GUC, in pages (not bytes)
+#[inline]
+pub fn effective_cache_size() -> i32 {
+    unsafe { pg_sys::effective_cache_size }
+}
+
+/// Estimate the fraction of rows that satisfy all of `clauses` (an implicitly-AND'd list of
+/// `RestrictInfo`s or bare expressions).
+///
+/// `varrelid` is the range table index of the relation to restrict the estimate to, or `0` to
+/// consider all of them.  `sjinfo` is only needed when estimating join clauses.
+pub fn clauselist_selectivity(
+    root: &PgBox<pg_sys::PlannerInfo>,
+    clauses: &PgList<pg_sys::Node>,
+    varrelid: i32,
+    jointype: pg_sys::JoinType,
+    sjinfo: Option<&PgBox<pg_sys::SpecialJoinInfo>>,
+) -> pg_sys::Selectivity {
+    unsafe {
+        pg_sys::clauselist_selectivity(
+            root.as_ptr(),
+            clauses.as_ptr(),
+            varrelid,
+            jointype,
+            sjinfo.map_or(std::ptr::null_mut(), |sjinfo| sjinfo.as_ptr()),
+        )
+    }
+}
+
+/// Estimate the number of distinct groups `input_rows` rows form when grouped by `group_exprs`,
+/// as used for `GROUP BY` and `DISTINCT`.  An empty list is always one group.
+pub fn estimate_num_groups(
+    root: &PgBox<pg_sys::PlannerInfo>,
+    group_exprs: &PgList<pg_sys::Node>,
+    input_rows: f64,
+) -> f64 {
+    unsafe {
+        pg_sys::estimate_num_groups(
+            root.as_ptr(),
+            group_exprs.as_ptr(),
+            input_rows,
+            std::ptr::null_mut(),
+        )
+    }
+}
+
+/// The correlation between the order of the rows in the heap and the order of the index's
+/// leading column, from `-1.0` to `1.0`, for `CostBuilder::correlation()`.  It comes from the
+/// statistics `ANALYZE` gathers for the column, or for the index itself if its leading column
+/// is an expression, and is `0.0` if there aren't any.
+///
+/// `btcostestimate()` further scales this by `0.75` for a multi-column index, as the later
+/// columns make the index order a poorer predictor of the heap order.  That's left to the caller
+pub fn index_correlation(
+    root: &PgBox<pg_sys::PlannerInfo>,
+    index: &PgBox<pg_sys::IndexOptInfo>,
+) -> f64 {
+    unsafe {
+        let (relid, attnum, inh) = if *index.indexkeys == 0 {
+            (index.indexoid, 1, false)
+        } else {
+            let rte = *root.simple_rte_array.add((*index.rel).relid as usize);
+            ((*rte).relid, *index.indexkeys, (*rte).inh)
+        };
+
+        let stats = pg_sys::SearchSysCache(
+            pg_sys::SysCacheIdentifier_STATRELATTINH as i32,
+            relid as pg_sys::Datum,
+            attnum as i16 as pg_sys::Datum,
+            inh as pg_sys::Datum,
+            0,
+        );
+        if stats.is_null() {
+            return 0.0;
+        }
+
+        // a column only has the one correlation, for the sort operator ANALYZE used, so any
+        // operator will do
+        let mut slot = pg_sys::AttStatsSlot::default();
+        let mut correlation = 0.0;
+        if pg_sys::get_attstatsslot(
+            &mut slot,
+            stats,
+            pg_sys::STATISTIC_KIND_CORRELATION as i32,
+            pg_sys::InvalidOid,
+            pg_sys::ATTSTATSSLOT_NUMBERS as i32,
+        ) {
+            if slot.nnumbers > 0 {
+                correlation = *slot.numbers as f64;
+            }
+            pg_sys::free_attstatsslot(&mut slot);
+        }
+        pg_sys::ReleaseSysCache(stats);
+
+        // only indexes that can return rows in order have a sort direction
+        if !index.reverse_sort.is_null() && *index.reverse_sort {
+            -correlation
+        } else {
+            correlation
+        }
+    }
+}
+
+/// Accumulates the estimated costs, row count, and row width of a scan, and writes them out to
+/// wherever the planner expects them.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+/// use pgx::costing::*;
+///
+/// #[pg_guard]
+/// unsafe extern "C" fn amcostestimate(
+///     root: *mut pg_sys::PlannerInfo,
+///     path: *mut pg_sys::IndexPath,
+///     _loop_count: f64,
+///     index_startup_cost: *mut pg_sys::Cost,
+///     index_total_cost: *mut pg_sys::Cost,
+///     index_selectivity: *mut pg_sys::Selectivity,
+///     index_correlation: *mut f64,
+///     index_pages: *mut f64,
+/// ) {
+///     let root = PgBox::from_pg(root);
+///     let index = PgBox::from_pg((*path).indexinfo);
+///     let pages = index.pages as f64;
+///     let tuples = index.tuples * 0.01;
+///
+///     CostBuilder::new()
+///         .startup(random_page_cost())
+///         .io(pages, random_page_cost())
+///         .cpu(tuples, cpu_index_tuple_cost() + cpu_operator_cost())
+///         .rows(tuples)
+///         .selectivity(0.01)
+///         .correlation(index_correlation(&root, &index))
+///         .pages(pages)
+///         .write_index_cost(
+///             index_startup_cost,
+///             index_total_cost,
+///             index_selectivity,
+///             index_correlation,
+///             index_pages,
+///         );
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostBuilder {
+    startup_cost: pg_sys::Cost,
+    run_cost: pg_sys::Cost,
+    rows: f64,
+    width: i32,
+    selectivity: pg_sys::Selectivity,
+    correlation: f64,
+    pages: f64,
+}
+
+impl Default for CostBuilder {
+    fn default() -> Self {
+        CostBuilder::new()
+    }
+}
+
+impl CostBuilder {
+    /// A zero cost that selects every row
+    pub fn new() -> Self {
+        CostBuilder {
+            startup_cost: 0.0,
+            run_cost: 0.0,
+            rows: 0.0,
+            width: 0,
+            selectivity: 1.0,
+            correlation: 0.0,
+            pages: 0.0,
+        }
+    }
+
+    /// Add to the cost incurred before the first row can be returned
+    pub fn startup(mut self, cost: pg_sys::Cost) -> Self {
+        self.startup_cost += cost;
+        self
+    }
+
+    /// Add to the cost of returning all the rows, beyond the startup cost
+    pub fn run(mut self, cost: pg_sys::Cost) -> Self {
+        self.run_cost += cost;
+        self
+    }
+
+    /// Add the run cost of reading `pages` pages at `cost_per_page`, such as `seq_page_cost()`
+    pub fn io(self, pages: f64, cost_per_page: pg_sys::Cost) -> Self {
+        self.run(pages * cost_per_page)
+    }
+
+    /// Add the run cost of processing `tuples` tuples at `cost_per_tuple`, such as
+    /// `cpu_tuple_cost()`
+    pub fn cpu(self, tuples: f64, cost_per_tuple: pg_sys::Cost) -> Self {
+        self.run(tuples * cost_per_tuple)
+    }
+
+    /// Set the estimated number of rows returned
+    pub fn rows(mut self, rows: f64) -> Self {
+        self.rows = rows;
+        self
+    }
+
+    /// Set the estimated average width, in bytes, of the rows returned
+    pub fn width(mut self, width: i32) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Set the fraction of the index's rows that will be returned
+    pub fn selectivity(mut self, selectivity: pg_sys::Selectivity) -> Self {
+        self.selectivity = selectivity;
+        self
+    }
+
+    /// Set the correlation between index order and heap order, from `-1.0` to `1.0`
+    pub fn correlation(mut self, correlation: f64) -> Self {
+        self.correlation = correlation;
+        self
+    }
+
+    /// Set the number of index pages that will be read
+    pub fn pages(mut self, pages: f64) -> Self {
+        self.pages = pages;
+        self
+    }
+
+    /// The cost incurred before the first row can be returned
+    pub fn startup_cost(&self) -> pg_sys::Cost {
+        self.startup_cost
+    }
+
+    /// The startup cost plus the run cost
+    pub fn total_cost(&self) -> pg_sys::Cost {
+        self.startup_cost + self.run_cost
+    }
+
+    /// Write the estimates to the out-parameters of an index access method's `amcostestimate`
+    /// function.  They're all required, as Postgres always passes them.
+    pub unsafe fn write_index_cost(
+        &self,
+        index_startup_cost: *mut pg_sys::Cost,
+        index_total_cost: *mut pg_sys::Cost,
+        index_selectivity: *mut pg_sys::Selectivity,
+        index_correlation: *mut f64,
+        index_pages: *mut f64,
+    ) {
+        *index_startup_cost = self.startup_cost;
+        *index_total_cost = self.total_cost();
+        *index_selectivity = self.selectivity;
+        *index_correlation = self.correlation;
+        *index_pages = self.pages;
+    }
+
+    /// Set the costs and row count of a `Path`, and the width of its target if it has one.
+    ///
+    /// A path's target is usually its relation's `reltarget`, which the relation's other paths
+    /// share, so the path is given a copy of it, allocated in `CurrentMemoryContext`, rather
+    /// than changing theirs
+    pub fn apply_to_path(&self, path: &mut PgBox<pg_sys::Path>) {
+        path.startup_cost = self.startup_cost;
+        path.total_cost = self.total_cost();
+        path.rows = self.rows;
+
+        if !path.pathtarget.is_null() {
+            unsafe {
+                path.pathtarget = pg_sys::copy_pathtarget(path.pathtarget);
+                (*path.pathtarget).width = self.width;
+            }
+        }
+    }
+
+    /// Set the costs, row count, and width of a `CustomPath`
+    pub fn apply_to_custom_path(&self, path: &mut PgBox<pg_sys::CustomPath>) {
+        let mut inner = PgBox::from_pg(&mut path.path as *mut pg_sys::Path);
+        self.apply_to_path(&mut inner);
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! A borrowed, null-terminated C string owned by Postgres
+use crate::{pg_sys, PgBox};
+use std::borrow::Cow;
+use std::ffi::CStr;
+use std::fmt::{Debug, Display, Formatter};
+use std::marker::PhantomData;
+use std::os::raw::c_char;
+
+/// A borrowed pointer to a null-terminated C string, such as those Postgres hands out for names,
+/// `cstring` Datums, and error messages.
+///
+/// Where a `PgBox<c_char>` is a pointer to a single `c_char`, a `PgCStr` means "string": it is
+/// never NULL, always ends in a `\0`, and knows its length.  It is `#[repr(transparent)]` over
+/// `*const c_char`, so it can appear directly in FFI signatures.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+/// use pgx::pg_sys::AsPgCStr;
+///
+/// let version = unsafe { PgCStr::from_ptr(pg_sys::GetConfigOptionByName(
+///     "server_version".as_pg_cstr(),
+///     std::ptr::null_mut(),
+///     false,
+/// )) }
+/// .expect("server_version is NULL");
+/// assert!(!version.is_empty());
+/// println!("running on Postgres {} ({} bytes)", version, version.len());
+/// ```
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct PgCStr<'a> {
+    ptr: *const c_char,
+    __marker: PhantomData<&'a c_char>,
+}
+
+impl<'a> PgCStr<'a> {
+    /// Wrap a pointer to a null-terminated string, or return `None` if it's NULL
+    ///
+    /// ## Safety
+    ///
+    /// The caller must ensure that a non-NULL `ptr` points to a null-terminated string that
+    /// outlives `'a` and isn't modified while borrowed
+    #[inline]
+    pub unsafe fn from_ptr(ptr: *const c_char) -> Option<PgCStr<'a>> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(PgCStr {
+                ptr,
+                __marker: PhantomData,
+            })
+        }
+    }
+
+    /// Treat the `c_char` a `PgBox<c_char>` points to as the start of a null-terminated string,
+    /// or return `None` if the box is NULL
+    ///
+    /// ## Safety
+    ///
+    /// The caller must ensure the boxed pointer is really the start of a null-terminated string
+    #[inline]
+    pub unsafe fn from_pgbox(boxed: &'a PgBox<c_char>) -> Option<PgCStr<'a>> {
+        PgCStr::from_ptr(boxed.as_ptr())
+    }
+
+    /// The underlying pointer, for passing back to Postgres
+    #[inline]
+    pub fn as_ptr(&self) -> *const c_char {
+        self.ptr
+    }
+
+    /// The string's length in bytes, not counting its null terminator
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { pg_sys::strlen(self.ptr) as usize }
+    }
+
+    /// Is this the empty string?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        unsafe { *self.ptr == 0 }
+    }
+
+    /// This string as a `&CStr`
+    #[inline]
+    pub fn as_cstr(&self) -> &'a CStr {
+        unsafe { CStr::from_ptr(self.ptr) }
+    }
+
+    /// The bytes of this string, without its null terminator
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len()) }
+    }
+
+    /// This string as a `&str`
+    ///
+    /// ## Panics
+    ///
+    /// If the string is not valid UTF-8
+    pub fn as_str(&self) -> &'a str {
+        match std::str::from_utf8(self.as_bytes()) {
+            Ok(s) => s,
+            Err(e) => panic!("PgCStr is not valid UTF-8: {}", e),
+        }
+    }
+
+    /// This string as a `&str`, or a `Utf8Error` if it is not valid UTF-8
+    #[inline]
+    pub fn to_str(&self) -> Result<&'a str, std::str::Utf8Error> {
+        std::str::from_utf8(self.as_bytes())
+    }
+
+    /// This string as a `&str`, replacing any invalid UTF-8 sequences with `U+FFFD`
+    #[inline]
+    pub fn to_str_lossy(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+
+    /// Box the string's pointer as a `PgBox<c_char>`, which Postgres still owns
+    #[inline]
+    pub fn into_pgbox(self) -> PgBox<c_char> {
+        PgBox::from_pg(self.ptr as *mut c_char)
+    }
+}
+
+impl<'a> From<&'a CStr> for PgCStr<'a> {
+    #[inline]
+    fn from(cstr: &'a CStr) -> Self {
+        PgCStr {
+            ptr: cstr.as_ptr(),
+            __marker: PhantomData,
+        }
+    }
+}
+
+impl<'a> From<PgCStr<'a>> for &'a CStr {
+    #[inline]
+    fn from(cstr: PgCStr<'a>) -> Self {
+        cstr.as_cstr()
+    }
+}
+
+impl<'a> From<PgCStr<'a>> for PgBox<c_char> {
+    #[inline]
+    fn from(cstr: PgCStr<'a>) -> Self {
+        cstr.into_pgbox()
+    }
+}
+
+impl<'a> PartialEq for PgCStr<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a> PartialEq<str> for PgCStr<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a> PartialEq<&str> for PgCStr<'a> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a> Display for PgCStr<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_str_lossy())
+    }
+}
+
+impl<'a> Debug for PgCStr<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_cstr(), f)
+    }
+}
@@ -2,11 +2,25 @@
 // governed by the MIT license that can be found in the LICENSE file.
 
 use crate::{pg_sys, FromDatum, IntoDatum};
+use std::marker::PhantomData;
 
+/// Represents Postgres' `anyarray` pseudo-type, as a Datum and the Oid of its actual type.
+///
+/// The Datum is usually a pointer into memory Postgres allocated for the current backend, so an
+/// `AnyArray` is neither `Send` nor `Sync`, and can't be moved to another thread:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// fn escape(value: AnyArray) {
+///     std::thread::spawn(move || value.datum());
+/// }
+/// ```
 #[derive(Debug, Clone, Copy)]
 pub struct AnyArray {
     datum: pg_sys::Datum,
     typoid: pg_sys::Oid,
+    _not_send: PhantomData<*const ()>,
 }
 
 impl AnyArray {
@@ -34,7 +48,11 @@ impl FromDatum for AnyArray {
         if is_null {
             None
         } else {
-            Some(AnyArray { datum, typoid })
+            Some(AnyArray {
+                datum,
+                typoid,
+                _not_send: PhantomData,
+            })
         }
     }
 }
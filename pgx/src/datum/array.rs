@@ -373,39 +373,196 @@ where
     T: IntoDatum,
 {
     fn into_datum(self) -> Option<pg_sys::Datum> {
-        let mut state = unsafe {
-            pg_sys::initArrayResult(
-                T::type_oid(),
-                PgMemoryContexts::CurrentMemoryContext.value(),
-                false,
-            )
-        };
-        for s in self {
-            let datum = s.into_datum();
-            let isnull = datum.is_none();
+        make_array_result(
+            T::type_oid(),
+            self.into_iter().map(|element| element.into_datum()),
+        )
+    }
 
-            unsafe {
-                state = pg_sys::accumArrayResult(
-                    state,
-                    datum.unwrap_or(0usize),
-                    isnull,
-                    T::type_oid(),
-                    PgMemoryContexts::CurrentMemoryContext.value(),
-                );
-            }
-        }
+    fn type_oid() -> u32 {
+        T::array_type_oid()
+    }
+}
 
-        if state.is_null() {
-            // shoudln't happen
-            None
+impl<'a, T> IntoDatum for Array<'a, T>
+where
+    T: FromDatum + IntoDatum,
+{
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        if self.array_type.is_null() {
+            // made by `Array::over()`, so there's no Postgres array to hand back yet
+            let elements = self
+                .elem_slice
+                .iter()
+                .zip(self.null_slice.iter())
+                .map(|(datum, is_null)| if *is_null { None } else { Some(*datum) })
+                .collect::<Vec<_>>();
+            make_array_result(T::type_oid(), elements.into_iter())
         } else {
-            Some(unsafe {
-                pg_sys::makeArrayResult(state, PgMemoryContexts::CurrentMemoryContext.value())
-            })
+            Some(self.into_array_type() as pg_sys::Datum)
         }
     }
 
     fn type_oid() -> u32 {
-        unsafe { pg_sys::get_array_type(T::type_oid()) }
+        T::array_type_oid()
+    }
+}
+
+/// Build a one-dimensional Postgres array of `element_type` from `elements`, where `None` is NULL
+fn make_array_result<I: Iterator<Item = Option<pg_sys::Datum>>>(
+    element_type: pg_sys::Oid,
+    elements: I,
+) -> Option<pg_sys::Datum> {
+    let mut state = unsafe {
+        pg_sys::initArrayResult(
+            element_type,
+            PgMemoryContexts::CurrentMemoryContext.value(),
+            false,
+        )
+    };
+    for datum in elements {
+        let isnull = datum.is_none();
+
+        unsafe {
+            state = pg_sys::accumArrayResult(
+                state,
+                datum.unwrap_or(0usize),
+                isnull,
+                element_type,
+                PgMemoryContexts::CurrentMemoryContext.value(),
+            );
+        }
+    }
+
+    if state.is_null() {
+        // shoudln't happen
+        None
+    } else {
+        Some(unsafe {
+            pg_sys::makeArrayResult(state, PgMemoryContexts::CurrentMemoryContext.value())
+        })
+    }
+}
+
+/// Builds a Postgres array of any number of dimensions, from the length of each of its dimensions
+/// and then its elements, in order with the last dimension varying the fastest.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// // '{{1,2,3},{4,NULL,6}}'::integer[]
+/// let mut builder = PgArrayBuilder::<i32>::new();
+/// builder.push_dimension(2).push_dimension(3);
+/// for element in vec![Some(1), Some(2), Some(3), Some(4), None, Some(6)] {
+///     builder.push_element(element);
+/// }
+/// let datum = builder.build();
+/// ```
+pub struct PgArrayBuilder<T: IntoDatum> {
+    dims: Vec<i32>,
+    lbounds: Vec<i32>,
+    elements: Vec<pg_sys::Datum>,
+    nulls: Vec<bool>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: IntoDatum> Default for PgArrayBuilder<T> {
+    fn default() -> Self {
+        PgArrayBuilder::new()
+    }
+}
+
+impl<T: IntoDatum> PgArrayBuilder<T> {
+    pub fn new() -> Self {
+        PgArrayBuilder {
+            dims: Vec::new(),
+            lbounds: Vec::new(),
+            elements: Vec::new(),
+            nulls: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add a dimension of `len` elements, indexed from 1, inside of those already pushed
+    pub fn push_dimension(&mut self, len: usize) -> &mut Self {
+        self.push_dimension_with_lower_bound(len, 1)
+    }
+
+    /// Add a dimension of `len` elements, indexed from `lower_bound`, inside of those already pushed
+    pub fn push_dimension_with_lower_bound(&mut self, len: usize, lower_bound: i32) -> &mut Self {
+        if len > i32::MAX as usize {
+            panic!("array dimension of {} elements is too large", len);
+        }
+        self.dims.push(len as i32);
+        self.lbounds.push(lower_bound);
+        self
+    }
+
+    /// Add the next element, which is NULL if it's `None`
+    pub fn push_element(&mut self, element: Option<T>) -> &mut Self {
+        match element.and_then(|element| element.into_datum()) {
+            Some(datum) => {
+                self.elements.push(datum);
+                self.nulls.push(false);
+            }
+            None => {
+                self.elements.push(0);
+                self.nulls.push(true);
+            }
+        }
+        self
+    }
+
+    /// Construct the array, in the `CurrentMemoryContext`.
+    ///
+    /// Without any dimensions, the elements make a one-dimensional array, and an array without
+    /// any elements is the empty (zero-dimensional) array.
+    ///
+    /// ## Panics
+    ///
+    /// If the number of elements pushed isn't the product of the dimensions' lengths
+    pub fn build(mut self) -> pg_sys::Datum {
+        if self.dims.is_empty() {
+            let len = self.elements.len();
+            self.push_dimension(len);
+        }
+
+        let nelems = self.dims.iter().map(|len| *len as usize).product::<usize>();
+        if nelems != self.elements.len() {
+            panic!(
+                "array of dimensions {:?} needs {} elements, but {} were pushed",
+                self.dims,
+                nelems,
+                self.elements.len()
+            );
+        }
+
+        let elmtype = T::type_oid();
+        unsafe {
+            if nelems == 0 {
+                return pg_sys::construct_empty_array(elmtype) as pg_sys::Datum;
+            }
+
+            // outvals for get_typlenbyvalalign()
+            let mut typlen = 0;
+            let mut typbyval = false;
+            let mut typalign = 0;
+
+            pg_sys::get_typlenbyvalalign(elmtype, &mut typlen, &mut typbyval, &mut typalign);
+
+            pg_sys::construct_md_array(
+                self.elements.as_mut_ptr(),
+                self.nulls.as_mut_ptr(),
+                self.dims.len() as i32,
+                self.dims.as_mut_ptr(),
+                self.lbounds.as_mut_ptr(),
+                elmtype,
+                typlen as i32,
+                typbyval,
+                typalign,
+            ) as pg_sys::Datum
+        }
     }
 }
@@ -3,7 +3,10 @@
 
 //! for converting a pg_sys::Datum and a corresponding "is_null" bool into a typed Option
 
-use crate::{pg_sys, text_to_rust_str_unchecked, varlena_to_byte_slice, PgBox, PgMemoryContexts};
+use crate::{
+    cast, detoast_datum_copy_checked, detoast_datum_packed_checked, pg_sys,
+    text_to_rust_str_unchecked, varlena_to_byte_slice, Numeric, PgBox, PgMemoryContexts,
+};
 use std::ffi::CStr;
 
 /// Convert a `(pg_sys::Datum, is_null:bool, type_oid:pg_sys::Oid)` tuple into a Rust type
@@ -106,14 +109,19 @@ impl FromDatum for i16 {
     }
 }
 
-/// for integer
+/// for integer, and for a bigint or double precision that's cast to one as SQL would, raising an
+/// ERROR if it's out of range
 impl FromDatum for i32 {
     #[inline]
-    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _: pg_sys::Oid) -> Option<i32> {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, typoid: pg_sys::Oid) -> Option<i32> {
         if is_null {
             None
         } else {
-            Some(datum as i32)
+            Some(match typoid {
+                pg_sys::INT8OID => cast::or_raise(cast::i64_to_i32(datum as i64)),
+                pg_sys::FLOAT8OID => cast::or_raise(cast::f64_to_i32(f64::from_bits(datum as u64))),
+                _ => datum as i32,
+            })
         }
     }
 }
@@ -130,14 +138,22 @@ impl FromDatum for u32 {
     }
 }
 
-/// for bigint
+/// for bigint, and for a double precision or numeric that's cast to one as SQL would, raising an
+/// ERROR if it's out of range
 impl FromDatum for i64 {
     #[inline]
-    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _: pg_sys::Oid) -> Option<i64> {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, typoid: pg_sys::Oid) -> Option<i64> {
         if is_null {
             None
         } else {
-            Some(datum as i64)
+            Some(match typoid {
+                pg_sys::FLOAT8OID => cast::or_raise(cast::f64_to_i64(f64::from_bits(datum as u64))),
+                pg_sys::NUMERICOID => {
+                    let numeric = Numeric::from_datum(datum, false, typoid).unwrap();
+                    cast::or_raise(cast::numeric_to_i64(&numeric))
+                }
+                _ => datum as i64,
+            })
         }
     }
 }
@@ -175,7 +191,7 @@ impl<'a> FromDatum for &'a str {
         } else if datum == 0 {
             panic!("a varlena Datum was flagged as non-null but the datum is zero");
         } else {
-            let varlena = pg_sys::pg_detoast_datum_packed(datum as *mut pg_sys::varlena);
+            let varlena = detoast_datum_packed_checked(datum as *mut pg_sys::varlena, "text");
             Some(text_to_rust_str_unchecked(varlena))
         }
     }
@@ -196,7 +212,7 @@ impl<'a> FromDatum for &'a str {
         } else {
             memory_context.switch_to(|_| {
                 // this gets the varlena Datum copied into this memory context
-                let detoasted = pg_sys::pg_detoast_datum_copy(datum as *mut pg_sys::varlena);
+                let detoasted = detoast_datum_copy_checked(datum as *mut pg_sys::varlena, "text");
 
                 // and we need to unpack it (if necessary), which will decompress it too
                 let varlena = pg_sys::pg_detoast_datum_packed(detoasted);
@@ -262,7 +278,7 @@ impl<'a> FromDatum for &'a [u8] {
         } else if datum == 0 {
             panic!("a bytea Datum was flagged as non-null but the datum is zero");
         } else {
-            let varlena = pg_sys::pg_detoast_datum_packed(datum as *mut pg_sys::varlena);
+            let varlena = detoast_datum_packed_checked(datum as *mut pg_sys::varlena, "bytea");
             Some(varlena_to_byte_slice(varlena))
         }
     }
@@ -283,7 +299,7 @@ impl<'a> FromDatum for &'a [u8] {
         } else {
             memory_context.switch_to(|_| {
                 // this gets the varlena Datum copied into this memory context
-                let detoasted = pg_sys::pg_detoast_datum_copy(datum as *mut pg_sys::varlena);
+                let detoasted = detoast_datum_copy_checked(datum as *mut pg_sys::varlena, "bytea");
 
                 // and we need to unpack it (if necessary), which will decompress it too
                 let varlena = pg_sys::pg_detoast_datum_packed(detoasted);
@@ -1,7 +1,105 @@
 // Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
 // governed by the MIT license that can be found in the LICENSE file.
 
-use crate::{direct_function_call_as_datum, pg_sys, FromDatum, IntoDatum};
+//! Conversion of Postgres' geometric types, from "utils/geo_decls.h"
+//!
+//! These are fixed-size, but too big to be passed by value, so a Datum is a pointer to a
+//! palloc'd struct:
+//!
+//! ```c
+//! typedef struct
+//! {
+//!     float8      x,
+//!                 y;
+//! } Point;
+//!
+//! typedef struct
+//! {
+//!     Point       high,
+//!                 low;            /* corner POINTs */
+//! } BOX;
+//! ```
+use crate::{pg_sys, FromDatum, IntoDatum, PgMemoryContexts};
+
+/// A Postgres `point`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PgPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl PgPoint {
+    pub fn new(x: f64, y: f64) -> Self {
+        PgPoint { x, y }
+    }
+}
+
+/// A Postgres `box`.  (Not to be confused with `PgBox`, pgx's pointer to palloc'd memory)
+///
+/// Postgres expects `high` to be the upper right corner and `low` the lower left, as `new()`
+/// arranges them, and the `box` operators give the wrong answers for a box that isn't
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PgGeoBox {
+    pub high: PgPoint,
+    pub low: PgPoint,
+}
+
+impl PgGeoBox {
+    /// The box with opposite corners `a` and `b`, as `box(point, point)` makes it
+    pub fn new(a: PgPoint, b: PgPoint) -> Self {
+        PgGeoBox {
+            high: PgPoint::new(a.x.max(b.x), a.y.max(b.y)),
+            low: PgPoint::new(a.x.min(b.x), a.y.min(b.y)),
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.high.x - self.low.x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.high.y - self.low.y
+    }
+
+    /// Whether `point` is inside this box or on its edge, as `box @> point` tests
+    pub fn contains(&self, point: &PgPoint) -> bool {
+        (self.low.x..=self.high.x).contains(&point.x)
+            && (self.low.y..=self.high.y).contains(&point.y)
+    }
+}
+
+impl From<pg_sys::Point> for PgPoint {
+    fn from(point: pg_sys::Point) -> Self {
+        PgPoint::new(point.x, point.y)
+    }
+}
+
+impl From<PgPoint> for pg_sys::Point {
+    fn from(point: PgPoint) -> Self {
+        pg_sys::Point {
+            x: point.x,
+            y: point.y,
+        }
+    }
+}
+
+impl From<pg_sys::BOX> for PgGeoBox {
+    fn from(the_box: pg_sys::BOX) -> Self {
+        PgGeoBox {
+            high: the_box.high.into(),
+            low: the_box.low.into(),
+        }
+    }
+}
+
+impl From<PgGeoBox> for pg_sys::BOX {
+    fn from(the_box: PgGeoBox) -> Self {
+        pg_sys::BOX {
+            high: the_box.high.into(),
+            low: the_box.low.into(),
+        }
+    }
+}
 
 impl FromDatum for pg_sys::BOX {
     unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _: pg_sys::Oid) -> Option<Self>
@@ -20,12 +118,10 @@ impl FromDatum for pg_sys::BOX {
 }
 
 impl IntoDatum for pg_sys::BOX {
-    fn into_datum(mut self) -> Option<pg_sys::Datum> {
-        let the_box = &mut self;
-        direct_function_call_as_datum(
-            pg_sys::box_out,
-            vec![Some(the_box as *mut pg_sys::BOX as pg_sys::Datum)],
-        )
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        let the_box = PgMemoryContexts::CurrentMemoryContext.palloc_struct::<pg_sys::BOX>();
+        unsafe { the_box.write(self) };
+        Some(the_box as pg_sys::Datum)
     }
 
     fn type_oid() -> pg_sys::Oid {
@@ -50,12 +146,48 @@ impl FromDatum for pg_sys::Point {
 }
 
 impl IntoDatum for pg_sys::Point {
-    fn into_datum(mut self) -> Option<usize> {
-        let point = &mut self;
-        direct_function_call_as_datum(
-            pg_sys::point_out,
-            vec![Some(point as *mut pg_sys::Point as pg_sys::Datum)],
-        )
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        let point = PgMemoryContexts::CurrentMemoryContext.palloc_struct::<pg_sys::Point>();
+        unsafe { point.write(self) };
+        Some(point as pg_sys::Datum)
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        pg_sys::POINTOID
+    }
+}
+
+impl FromDatum for PgGeoBox {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, typoid: pg_sys::Oid) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        pg_sys::BOX::from_datum(datum, is_null, typoid).map(PgGeoBox::from)
+    }
+}
+
+impl IntoDatum for PgGeoBox {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        pg_sys::BOX::from(self).into_datum()
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        pg_sys::BOXOID
+    }
+}
+
+impl FromDatum for PgPoint {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, typoid: pg_sys::Oid) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        pg_sys::Point::from_datum(datum, is_null, typoid).map(PgPoint::from)
+    }
+}
+
+impl IntoDatum for PgPoint {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        pg_sys::Point::from(self).into_datum()
     }
 
     fn type_oid() -> pg_sys::Oid {
@@ -0,0 +1,97 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+use crate::{pg_sys, FromDatum, PgTupleDesc};
+
+/// A composite (named row-type or `record`) Datum along with the tuple descriptor that
+/// describes it, so that its fields can be read by name or by index without manually
+/// deforming the tuple.
+///
+/// When used as a `#[pg_extern]` argument, it is represented as the SQL `record` type.
+pub struct PgHeapTupleHeader {
+    tupdesc: PgTupleDesc<'static>,
+}
+
+impl PgHeapTupleHeader {
+    /// Wrap a composite `pg_sys::Datum`, resolving its tuple descriptor through
+    /// `lookup_rowtype_tupdesc()`
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as it cannot guarantee that the provided `pg_sys::Datum` actually
+    /// points to a composite type
+    pub unsafe fn from_composite(composite: pg_sys::Datum) -> Self {
+        PgHeapTupleHeader {
+            tupdesc: PgTupleDesc::from_composite(composite),
+        }
+    }
+
+    /// The tuple descriptor describing this composite's fields
+    pub fn tuple_desc(&self) -> &PgTupleDesc<'static> {
+        &self.tupdesc
+    }
+
+    /// The number of fields, including any that have been dropped
+    pub fn len(&self) -> usize {
+        self.tupdesc.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tupdesc.is_empty()
+    }
+
+    /// Returns the zero-based index of the named field, or `None` if there is no
+    /// such (non-dropped) field
+    pub fn field_index(&self, field: &str) -> Option<usize> {
+        self.tupdesc
+            .iter_all()
+            .position(|att| !att.is_dropped() && att.name() == field)
+    }
+
+    /// Get the value of the named field.  Returns `None` if the field is NULL.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the composite has no field of the specified name
+    pub fn get_by_name<T: FromDatum>(&self, field: &str) -> Option<T> {
+        match self.field_index(field) {
+            Some(i) => self.get_by_index(i),
+            None => panic!("composite type has no field named '{}'", field),
+        }
+    }
+
+    /// Get the value of the field at the specified zero-based index.  Returns `None` if the
+    /// field is NULL.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the index is out of bounds
+    pub fn get_by_index<T: FromDatum>(&self, i: usize) -> Option<T> {
+        if i >= self.len() {
+            panic!(
+                "field index {} out of bounds for composite type with {} fields",
+                i,
+                self.len()
+            );
+        }
+
+        self.tupdesc.get_attr(i)
+    }
+}
+
+impl FromDatum for PgHeapTupleHeader {
+    #[inline]
+    unsafe fn from_datum(
+        composite: pg_sys::Datum,
+        is_null: bool,
+        _typoid: pg_sys::Oid,
+    ) -> Option<PgHeapTupleHeader> {
+        if is_null {
+            None
+        } else if composite == 0 {
+            panic!("composite Datum flagged not null but its datum is zero")
+        } else {
+            Some(PgHeapTupleHeader::from_composite(composite))
+        }
+    }
+}
@@ -1,23 +1,179 @@
 // Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
 // governed by the MIT license that can be found in the LICENSE file.
 
-use crate::{pg_sys, FromDatum, PgBox};
+use crate::{pg_sys, void_mut_ptr, FromDatum, IntoDatum, PgBox, PgMemoryContexts};
+use std::any::TypeId;
+use std::marker::PhantomData;
 
-pub struct Internal<T>(pub PgBox<T>);
+/// A Rust value tagged with its `TypeId`.  `#[repr(C)]` guarantees the `type_id` is always at
+/// offset zero, regardless of `T`
+#[repr(C)]
+struct Tagged<T> {
+    type_id: TypeId,
+    value: T,
+}
+
+/// Represents Postgres' `internal` pseudo-type, which is an opaque pointer.
+///
+/// This is typically used to pass Rust state between index AM support functions, aggregate
+/// transition/final functions, and planner support functions.
+///
+/// Values created with `Internal::new()` are tagged with their Rust `TypeId` so that
+/// `Internal::get()` and `Internal::get_mut()` can refuse to downcast to the wrong type, rather
+/// than transmuting garbage.  Pointers that Postgres (or some other C code) created can only
+/// be accessed through `Internal::get_unchecked()`.
+///
+/// An `Internal` does not own its value.  Its value lives as long as the memory context it was
+/// created in, and is dropped when that context is deleted or reset, so an `Internal` borrows
+/// that context for `'mcx`.  It's neither `Clone` nor `Copy`, and for the same reason it's
+/// neither `Send` nor `Sync`, so it can't be moved to another thread, which would outlive it:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// fn escape(internal: Internal<'static>) {
+///     std::thread::spawn(move || internal.as_ptr() as usize);
+/// }
+/// ```
+///
+/// Nor can it outlive the `PgMemoryContexts` it was created in:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// fn escape() -> Internal<'static> {
+///     let context = PgMemoryContexts::CurrentMemoryContext;
+///     Internal::new(42_i32, &context)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Internal<'mcx>(pg_sys::Datum, PhantomData<&'mcx *const ()>);
+
+impl<'mcx> Internal<'mcx> {
+    /// Leak `value` into the specified memory context, which will drop it when the context
+    /// is deleted or reset
+    pub fn new<T: 'static>(value: T, memory_context: &'mcx PgMemoryContexts) -> Self {
+        unsafe { Internal::new_unbounded(value, PgMemoryContexts::For(memory_context.value())) }
+    }
+
+    /// Leak `value` into the specified memory context, as with `Internal::new()`, but for any
+    /// lifetime.  This is how aggregate transition functions create their state, in the
+    /// `MemoryContext` that `AggCheckCallContext()` returns.
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as it cannot know if `memory_context` will live for `'mcx`
+    pub unsafe fn new_unbounded<T: 'static>(
+        value: T,
+        mut memory_context: PgMemoryContexts,
+    ) -> Self {
+        let tagged = memory_context.leak_and_drop_on_delete(Tagged {
+            type_id: TypeId::of::<T>(),
+            value,
+        });
+        Internal(tagged as pg_sys::Datum, PhantomData)
+    }
+
+    /// Get a reference to the value, if it was created by `Internal::new()` with a `T`
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as it cannot know if the pointer was created by `Internal::new()`,
+    /// rather than by Postgres, so that it can be read as one, nor if its memory context has been
+    /// reset since
+    pub unsafe fn get<T: 'static>(&self) -> Option<&T> {
+        self.tagged::<T>().map(|tagged| &(*tagged).value)
+    }
+
+    /// Get a mutable reference to the value, if it was created by `Internal::new()` with a `T`
+    ///
+    /// ## Safety
+    ///
+    /// As for `Internal::get()`.  Also, there can't be any other reference to the value, such as
+    /// through another `Internal` made from the same `Datum`
+    pub unsafe fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.tagged::<T>().map(|tagged| &mut (*tagged).value)
+    }
+
+    /// Treat the pointer as a `T`, without any type checking.  This is how pointers created by
+    /// Postgres should be accessed.
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as it cannot know if the pointer actually points to a `T`
+    pub unsafe fn get_unchecked<T>(&self) -> PgBox<T> {
+        PgBox::from_pg(self.0 as *mut T)
+    }
+
+    /// The raw pointer this `Internal` represents
+    pub fn as_ptr(&self) -> void_mut_ptr {
+        self.0 as void_mut_ptr
+    }
+
+    unsafe fn tagged<T: 'static>(&self) -> Option<*mut Tagged<T>> {
+        let tagged = self.0 as *mut Tagged<T>;
+        if tagged.is_null() || (*tagged).type_id != TypeId::of::<T>() {
+            None
+        } else {
+            Some(tagged)
+        }
+    }
+}
+
+impl<'mcx> FromDatum for Internal<'mcx> {
+    #[inline]
+    unsafe fn from_datum(
+        datum: pg_sys::Datum,
+        is_null: bool,
+        _: pg_sys::Oid,
+    ) -> Option<Internal<'mcx>> {
+        if is_null {
+            None
+        } else if datum == 0 {
+            panic!("Internal-type Datum flagged not null but its datum is zero")
+        } else {
+            Some(Internal(datum, PhantomData))
+        }
+    }
+}
+
+impl<'mcx> IntoDatum for Internal<'mcx> {
+    #[inline]
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        pg_sys::INTERNALOID
+    }
+}
+
+/// What `Internal` used to be: an `internal` `Datum` as a `PgBox<T>`, without any type checking.
+/// Use `Internal` and its `get_unchecked()` instead
+#[deprecated(
+    since = "0.1.22",
+    note = "use `Internal`, and `Internal::get_unchecked()` for pointers created by Postgres"
+)]
+pub struct InternalBox<T>(pub PgBox<T>);
 
-impl<T> FromDatum for Internal<T> {
+#[allow(deprecated)]
+impl<T> FromDatum for InternalBox<T> {
     #[inline]
     unsafe fn from_datum(
         datum: pg_sys::Datum,
         is_null: bool,
         _: pg_sys::Oid,
-    ) -> Option<Internal<T>> {
+    ) -> Option<InternalBox<T>> {
         if is_null {
             None
         } else if datum == 0 {
             panic!("Internal-type Datum flagged not null but its datum is zero")
         } else {
-            Some(Internal::<T>(PgBox::<T>::from_pg(datum as *mut T)))
+            Some(InternalBox::<T>(PgBox::<T>::from_pg(datum as *mut T)))
         }
     }
 }
@@ -0,0 +1,121 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Conversion of Postgres' `interval` type, from "datatype/timestamp.h"
+//!
+//! An interval is fixed-size, but too big to be passed by value, so a Datum is a pointer to a
+//! palloc'd struct:
+//!
+//! ```c
+//! typedef struct
+//! {
+//!     TimeOffset  time;           /* all time units other than days, months and years */
+//!     int32       day;            /* days, after time for alignment */
+//!     int32       month;          /* months and years, after time for alignment */
+//! } Interval;
+//! ```
+use crate::{pg_sys, FromDatum, IntoDatum, PgMemoryContexts};
+use std::time::Duration;
+
+const USECS_PER_DAY: i128 = 86_400_000_000;
+
+/// A Postgres `interval`, which is kept as three separate parts, as its months and days don't
+/// have a fixed length:  a month is 28 to 31 days, and a day is 23 to 25 hours across a daylight
+/// savings time change, depending on the date the interval is added to.  So `interval '1 month'`
+/// and `interval '30 days'` are different intervals, as are `interval '1 day'` and
+/// `interval '24 hours'`, even though they compare as equal in SQL.
+///
+/// Each part can be negative, independently of the others, as in `interval '1 month -1 day'`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PgInterval {
+    pub months: i32,
+    pub days: i32,
+    pub microseconds: i64,
+}
+
+impl PgInterval {
+    pub fn new(months: i32, days: i32, microseconds: i64) -> Self {
+        PgInterval {
+            months,
+            days,
+            microseconds,
+        }
+    }
+
+    /// The interval of exactly `duration`, with no months or days, as `interval '36 hours'` is,
+    /// truncated to whole microseconds.  `None` if it's too long for an interval, which can
+    /// only be about 292,000 years long in microseconds
+    pub fn from_duration(duration: Duration) -> Option<Self> {
+        let microseconds = duration.as_micros();
+        if microseconds > i64::MAX as u128 {
+            None
+        } else {
+            Some(PgInterval::new(0, 0, microseconds as i64))
+        }
+    }
+
+    /// This interval as a `Duration`, **which is lossy**:  a month is counted as 30 days and a
+    /// day as 24 hours, as Postgres does when it compares intervals, so it's only the actual
+    /// length of time between two timestamps that are this interval apart if the interval has no
+    /// months or days, or they're in UTC and the months happen to be 30 days long.  Add the
+    /// interval to a timestamp in SQL to get an exact answer.
+    ///
+    /// `None` if the interval is negative, which a `Duration` can't be
+    pub fn to_duration(&self) -> Option<Duration> {
+        let microseconds = self.months as i128 * 30 * USECS_PER_DAY
+            + self.days as i128 * USECS_PER_DAY
+            + self.microseconds as i128;
+
+        if microseconds < 0 {
+            None
+        } else {
+            let seconds = microseconds / 1_000_000;
+            let nanos = (microseconds % 1_000_000) * 1000;
+            Some(Duration::new(seconds as u64, nanos as u32))
+        }
+    }
+}
+
+impl From<pg_sys::Interval> for PgInterval {
+    fn from(interval: pg_sys::Interval) -> Self {
+        PgInterval::new(interval.month, interval.day, interval.time)
+    }
+}
+
+impl From<PgInterval> for pg_sys::Interval {
+    fn from(interval: PgInterval) -> Self {
+        pg_sys::Interval {
+            time: interval.microseconds,
+            day: interval.days,
+            month: interval.months,
+        }
+    }
+}
+
+impl FromDatum for PgInterval {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _: pg_sys::Oid) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        if is_null {
+            None
+        } else if datum == 0 {
+            panic!("Interval datum declared not null, but datum is zero")
+        } else {
+            let interval = datum as *mut pg_sys::Interval;
+            Some(interval.read().into())
+        }
+    }
+}
+
+impl IntoDatum for PgInterval {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        let interval = PgMemoryContexts::CurrentMemoryContext.palloc_struct::<pg_sys::Interval>();
+        unsafe { interval.write(self.into()) };
+        Some(interval as pg_sys::Datum)
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        pg_sys::INTERVALOID
+    }
+}
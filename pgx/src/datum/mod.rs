@@ -9,18 +9,25 @@ mod array;
 mod date;
 mod from;
 mod geo;
+mod heap_tuple_header;
 mod inet;
 mod internal;
+mod interval;
 mod into;
 mod item_pointer_data;
 mod json;
+#[cfg(feature = "network")]
+mod network;
 mod numeric;
+mod numeric_accumulator;
 mod time;
 mod time_stamp;
 mod time_stamp_with_timezone;
 mod time_with_timezone;
 mod tuples;
+mod varbit;
 mod varlena;
+mod varlena_reader;
 
 pub use self::time::*;
 pub use anyarray::*;
@@ -29,17 +36,24 @@ pub use array::*;
 pub use date::*;
 pub use from::*;
 pub use geo::*;
+pub use heap_tuple_header::*;
 pub use inet::*;
 pub use internal::*;
+pub use interval::*;
 pub use into::*;
 pub use item_pointer_data::*;
 pub use json::*;
+#[cfg(feature = "network")]
+pub use network::*;
 pub use numeric::*;
+pub use numeric_accumulator::*;
 pub use time_stamp::*;
 pub use time_stamp_with_timezone::*;
 pub use time_with_timezone::*;
 pub use tuples::*;
+pub use varbit::*;
 pub use varlena::*;
+pub use varlena_reader::*;
 
 /// A tagging trait to indicate a user type is also meant to be used by Postgres
 /// Implemented automatically by `#[derive(PostgresType)]`
@@ -0,0 +1,233 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Binary conversion between Postgres' `inet`/`cidr` types and `std::net::IpAddr`,
+//! `ipnetwork::IpNetwork` and [`Cidr`], with the `network` feature
+//!
+//! Both types share the same on-disk representation, from "utils/inet.h":
+//!
+//! ```c
+//! typedef struct
+//! {
+//!     unsigned char family;      /* PGSQL_AF_INET or PGSQL_AF_INET6 */
+//!     unsigned char bits;        /* number of bits in netmask */
+//!     unsigned char ipaddr[16];  /* up to 128 bits of address */
+//! } inet_struct;
+//! ```
+//!
+//! wrapped in a varlena whose size only covers as many `ipaddr` bytes as the family needs.
+
+use crate::{
+    pg_sys, set_varsize, vardata_4b, varlena_to_byte_slice, void_mut_ptr, FromDatum, IntoDatum,
+};
+use ipnetwork::IpNetwork;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// These are *not* `AF_INET`/`AF_INET6`, which vary by platform.  From "utils/inet.h":
+///
+/// ```c
+/// #define PGSQL_AF_INET	(AF_INET + 0)
+/// #define PGSQL_AF_INET6	(AF_INET + 1)
+/// ```
+///
+/// and `AF_INET` is 2 everywhere Postgres runs
+const PGSQL_AF_INET: u8 = 2;
+const PGSQL_AF_INET6: u8 = 3;
+
+/// A Postgres `cidr`:  an IPv4 or IPv6 network address and its netmask length.
+///
+/// Unlike `inet`, a `cidr` can't have any bits set to the right of its netmask.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Cidr {
+    address: IpAddr,
+    netmask: u8,
+}
+
+impl Cidr {
+    /// Create a new `Cidr`, validating that `netmask` isn't longer than the address and that
+    /// `address` has no bits set to the right of the netmask
+    pub fn new(address: IpAddr, netmask: u8) -> Result<Self, &'static str> {
+        let bytes = ip_addr_bytes(&address);
+        let max_bits = (bytes.len() * 8) as u8;
+
+        if netmask > max_bits {
+            return Err("netmask is longer than the address");
+        }
+
+        for (i, byte) in bytes.iter().enumerate() {
+            let host_bits = match netmask as usize {
+                n if n >= (i + 1) * 8 => 0,
+                n if n <= i * 8 => 0xFF,
+                n => 0xFF >> (n - i * 8),
+            };
+
+            if byte & host_bits != 0 {
+                return Err("address has bits set to the right of the netmask");
+            }
+        }
+
+        Ok(Cidr { address, netmask })
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn netmask(&self) -> u8 {
+        self.netmask
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address, self.netmask)
+    }
+}
+
+fn ip_addr_bytes(address: &IpAddr) -> Vec<u8> {
+    match address {
+        IpAddr::V4(v4) => v4.octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
+
+/// Decode an `inet` or `cidr` Datum into its address and netmask length
+unsafe fn network_from_datum(datum: pg_sys::Datum) -> (IpAddr, u8) {
+    // the datum could be a short-header varlena, which `varlena_to_byte_slice()` understands, so
+    // this only makes a copy if it's compressed or toasted
+    let varlena = pg_sys::pg_detoast_datum_packed(datum as *mut pg_sys::varlena);
+    let bytes = varlena_to_byte_slice(varlena);
+    let (family, bits, ipaddr) = (bytes[0], bytes[1], &bytes[2..]);
+
+    let address = match family {
+        PGSQL_AF_INET => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(&ipaddr[..4]);
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        PGSQL_AF_INET6 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&ipaddr[..16]);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        other => panic!("unrecognized inet address family: {}", other),
+    };
+
+    if varlena as pg_sys::Datum != datum {
+        pg_sys::pfree(varlena as void_mut_ptr);
+    }
+
+    (address, bits)
+}
+
+fn max_netmask(address: &IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Encode an address and netmask length as an `inet`/`cidr` varlena, allocated in
+/// `CurrentMemoryContext`
+fn network_into_datum(address: &IpAddr, bits: u8) -> pg_sys::Datum {
+    let family = match address {
+        IpAddr::V4(_) => PGSQL_AF_INET,
+        IpAddr::V6(_) => PGSQL_AF_INET6,
+    };
+    let ipaddr = ip_addr_bytes(address);
+    let size = pg_sys::VARHDRSZ + 2 + ipaddr.len();
+
+    unsafe {
+        let varlena = pg_sys::palloc0(size) as *mut pg_sys::varlena;
+        set_varsize(varlena, size as i32);
+
+        let data = vardata_4b(varlena) as *mut u8;
+        *data = family;
+        *data.add(1) = bits;
+        std::ptr::copy_nonoverlapping(ipaddr.as_ptr(), data.add(2), ipaddr.len());
+
+        varlena as pg_sys::Datum
+    }
+}
+
+/// for `inet` and `cidr` values whose netmask covers the entire address.  Use `IpNetwork` or
+/// [`Cidr`] for values that have a shorter netmask
+impl FromDatum for IpAddr {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _typoid: u32) -> Option<IpAddr> {
+        if is_null {
+            None
+        } else if datum == 0 {
+            panic!("inet datum is declared non-null but Datum is zero");
+        } else {
+            let (address, netmask) = network_from_datum(datum);
+            if netmask != max_netmask(&address) {
+                panic!(
+                    "inet value {}/{} has a netmask, so can't be converted to an IpAddr",
+                    address, netmask
+                );
+            }
+            Some(address)
+        }
+    }
+}
+
+/// for `inet`, with a netmask covering the entire address
+impl IntoDatum for IpAddr {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        Some(network_into_datum(&self, max_netmask(&self)))
+    }
+
+    fn type_oid() -> u32 {
+        pg_sys::INETOID
+    }
+}
+
+/// for `inet` and `cidr`
+impl FromDatum for IpNetwork {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _typoid: u32) -> Option<IpNetwork> {
+        if is_null {
+            None
+        } else if datum == 0 {
+            panic!("inet datum is declared non-null but Datum is zero");
+        } else {
+            let (address, netmask) = network_from_datum(datum);
+            Some(IpNetwork::new(address, netmask).expect("inet value has an invalid netmask"))
+        }
+    }
+}
+
+/// for `inet`
+impl IntoDatum for IpNetwork {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        Some(network_into_datum(&self.ip(), self.prefix()))
+    }
+
+    fn type_oid() -> u32 {
+        pg_sys::INETOID
+    }
+}
+
+/// for `cidr`
+impl FromDatum for Cidr {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _typoid: u32) -> Option<Cidr> {
+        if is_null {
+            None
+        } else if datum == 0 {
+            panic!("cidr datum is declared non-null but Datum is zero");
+        } else {
+            let (address, netmask) = network_from_datum(datum);
+            Some(Cidr { address, netmask })
+        }
+    }
+}
+
+/// for `cidr`
+impl IntoDatum for Cidr {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        Some(network_into_datum(&self.address, self.netmask))
+    }
+
+    fn type_oid() -> u32 {
+        pg_sys::CIDROID
+    }
+}
@@ -109,6 +109,12 @@ impl Into<Numeric> for i64 {
     }
 }
 
+impl Into<Numeric> for i128 {
+    fn into(self) -> Numeric {
+        Numeric(format!("{}", self))
+    }
+}
+
 impl Into<Numeric> for u8 {
     fn into(self) -> Numeric {
         Numeric(format!("{}", self))
@@ -133,6 +139,12 @@ impl Into<Numeric> for u64 {
     }
 }
 
+impl Into<Numeric> for u128 {
+    fn into(self) -> Numeric {
+        Numeric(format!("{}", self))
+    }
+}
+
 impl Into<Numeric> for f32 {
     fn into(self) -> Numeric {
         Numeric(format!("{}", self))
@@ -180,3 +192,39 @@ impl IntoDatum for Numeric {
         pg_sys::NUMERICOID
     }
 }
+
+/// An `i128` is a `numeric`, as Postgres has no 128-bit integer type.  A `numeric` with a
+/// fractional part is rounded to the nearest integer, as a cast to `bigint` would.
+impl FromDatum for i128 {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _typoid: u32) -> Option<Self> {
+        if is_null {
+            None
+        } else {
+            let rounded =
+                direct_function_call_as_datum(pg_sys::numeric_round, vec![Some(datum), Some(0)])
+                    .expect("numeric_round returned null");
+            let numeric = Numeric::from_datum(rounded, false, pg_sys::NUMERICOID).unwrap();
+            pg_sys::pfree(rounded as void_mut_ptr);
+
+            if numeric.0 == "NaN" {
+                panic!("cannot convert NaN to i128");
+            }
+            Some(
+                numeric
+                    .0
+                    .parse()
+                    .expect("numeric value out of range for i128"),
+            )
+        }
+    }
+}
+
+impl IntoDatum for i128 {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        Numeric(self.to_string()).into_datum()
+    }
+
+    fn type_oid() -> u32 {
+        pg_sys::NUMERICOID
+    }
+}
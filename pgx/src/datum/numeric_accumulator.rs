@@ -0,0 +1,483 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Building blocks for `numeric` aggregates, equivalent to Postgres' own `NumericAggState`
+use crate::{
+    direct_function_call, direct_function_call_as_datum, pg_sys, void_mut_ptr, FromDatum,
+    IntoDatum, Numeric, PgMemoryContexts,
+};
+use std::collections::BTreeMap;
+
+type NumericFunction = unsafe fn(pg_sys::FunctionCallInfo) -> pg_sys::Datum;
+
+/// Accumulates `numeric` values into the same `sum()`, `avg()`, `var_samp()`/`var_pop()` and
+/// `stddev_samp()`/`stddev_pop()` results as Postgres' builtin aggregates, digit for digit.
+///
+/// Integer values that fit in an `i64` are summed as an `i128`, which is much faster than
+/// `numeric` arithmetic.  When the `i128` sums would overflow, they're folded into the `numeric`
+/// sums and accumulation carries on from there.
+///
+/// The `numeric` sums are allocated in the `MemoryContext` the accumulator is created in, so that
+/// it can be used as the state of an aggregate, and `pfree()`d when it's dropped.  As such, an
+/// accumulator must not outlive its `MemoryContext`.
+///
+/// `remove()` undoes an `add()`, for use as the inverse transition function of a moving
+/// aggregate.
+///
+/// ## Examples
+///
+/// The transition and final functions of a `numeric` aggregate, whose state is allocated in the
+/// aggregate's own `MemoryContext`:
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// #[pg_extern]
+/// fn my_sum_state(
+///     state: Option<Internal>,
+///     value: Option<Numeric>,
+///     fcinfo: pg_sys::FunctionCallInfo,
+/// ) -> Internal {
+///     let mut state = state.unwrap_or_else(|| {
+///         let mut agg_context = std::ptr::null_mut();
+///         if unsafe { pg_sys::AggCheckCallContext(fcinfo, &mut agg_context) } == 0 {
+///             panic!("my_sum_state() called in non-aggregate context");
+///         }
+///         unsafe {
+///             Internal::new_unbounded(
+///                 NumericAccumulator::new_in(PgMemoryContexts::For(agg_context)),
+///                 PgMemoryContexts::For(agg_context),
+///             )
+///         }
+///     });
+///
+///     if let Some(value) = value {
+///         unsafe { state.get_mut::<NumericAccumulator>() }.unwrap().add(&value);
+///     }
+///     state
+/// }
+///
+/// #[pg_extern]
+/// fn my_sum_final(state: Internal) -> Option<Numeric> {
+///     unsafe { state.get::<NumericAccumulator>() }.unwrap().sum()
+/// }
+/// ```
+pub struct NumericAccumulator {
+    memory_context: pg_sys::MemoryContext,
+    count: i64,
+    nan_count: i64,
+    int_sum: i128,
+    int_sum_sq: i128,
+    sum: Option<pg_sys::Datum>,
+    sum_sq: Option<pg_sys::Datum>,
+
+    /// how many of the values being summed have each display scale
+    scales: BTreeMap<i32, i64>,
+}
+
+impl NumericAccumulator {
+    /// An empty accumulator, allocated in the `CurrentMemoryContext`
+    pub fn new() -> Self {
+        NumericAccumulator::new_in(PgMemoryContexts::CurrentMemoryContext)
+    }
+
+    /// An empty accumulator, allocated in `memory_context`
+    pub fn new_in(memory_context: PgMemoryContexts) -> Self {
+        NumericAccumulator {
+            memory_context: memory_context.value(),
+            count: 0,
+            nan_count: 0,
+            int_sum: 0,
+            int_sum_sq: 0,
+            sum: None,
+            sum_sq: None,
+            scales: BTreeMap::new(),
+        }
+    }
+
+    /// The number of values accumulated, including `NaN`s
+    pub fn count(&self) -> i64 {
+        self.count + self.nan_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    pub fn add(&mut self, value: &Numeric) {
+        match value.0.parse::<i64>() {
+            Ok(value) => self.add_i64(value),
+            Err(_) => unsafe {
+                let datum = numeric_datum(&value.0);
+                self.add_datum(datum);
+                pg_sys::pfree(datum as void_mut_ptr);
+            },
+        }
+    }
+
+    /// Undo a previous `add()` of `value`
+    pub fn remove(&mut self, value: &Numeric) {
+        match value.0.parse::<i64>() {
+            Ok(value) => self.remove_i64(value),
+            Err(_) => unsafe {
+                let datum = numeric_datum(&value.0);
+                self.remove_datum(datum);
+                pg_sys::pfree(datum as void_mut_ptr);
+            },
+        }
+    }
+
+    pub fn add_i64(&mut self, value: i64) {
+        let value = value as i128;
+        match (
+            self.int_sum.checked_add(value),
+            self.int_sum_sq.checked_add(value * value),
+        ) {
+            (Some(sum), Some(sum_sq)) => {
+                self.int_sum = sum;
+                self.int_sum_sq = sum_sq;
+            }
+            _ => {
+                self.promote();
+                self.int_sum = value;
+                self.int_sum_sq = value * value;
+            }
+        }
+        self.count += 1;
+        *self.scales.entry(0).or_insert(0) += 1;
+    }
+
+    /// Undo a previous `add_i64()` of `value`
+    pub fn remove_i64(&mut self, value: i64) {
+        self.forget(Some(0));
+
+        let value = value as i128;
+        match (
+            self.int_sum.checked_sub(value),
+            self.int_sum_sq.checked_sub(value * value),
+        ) {
+            (Some(sum), Some(sum_sq)) => {
+                self.int_sum = sum;
+                self.int_sum_sq = sum_sq;
+            }
+            _ => {
+                self.promote();
+                self.int_sum = -value;
+                self.int_sum_sq = -(value * value);
+            }
+        }
+    }
+
+    /// Add a `numeric` Datum
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as we cannot validate that `datum` is a `numeric`
+    pub unsafe fn add_datum(&mut self, datum: pg_sys::Datum) {
+        match numeric_scale(datum) {
+            Some(scale) => {
+                self.apply(pg_sys::numeric_add, datum);
+                self.count += 1;
+                *self.scales.entry(scale).or_insert(0) += 1;
+            }
+            None => self.nan_count += 1,
+        }
+    }
+
+    /// Undo a previous `add_datum()` of the same value
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as we cannot validate that `datum` is a `numeric`
+    pub unsafe fn remove_datum(&mut self, datum: pg_sys::Datum) {
+        let scale = numeric_scale(datum);
+        self.forget(scale);
+        if scale.is_some() {
+            self.apply(pg_sys::numeric_sub, datum);
+        }
+    }
+
+    /// The sum of the values, as `sum(numeric)` computes it.  `None` if there are none.
+    pub fn sum(&self) -> Option<Numeric> {
+        if self.count() == 0 {
+            None
+        } else if self.nan_count > 0 {
+            Some(Numeric("NaN".to_string()))
+        } else {
+            unsafe {
+                let (sum, sum_sq) = self.totals();
+                let result = numeric_string(sum);
+                free(&[sum, sum_sq]);
+                Some(result)
+            }
+        }
+    }
+
+    /// The mean of the values, as `avg(numeric)` computes it.  `None` if there are none.
+    pub fn avg(&self) -> Option<Numeric> {
+        if self.count() == 0 {
+            None
+        } else if self.nan_count > 0 {
+            Some(Numeric("NaN".to_string()))
+        } else {
+            unsafe {
+                let (sum, sum_sq) = self.totals();
+                let n = numeric_datum(self.count);
+                let avg = call(pg_sys::numeric_div, sum, n);
+                let result = numeric_string(avg);
+                free(&[sum, sum_sq, n, avg]);
+                Some(result)
+            }
+        }
+    }
+
+    /// The sample variance of the values, as `var_samp(numeric)` computes it, or their population
+    /// variance, as `var_pop(numeric)` computes it.  `None` if there are too few values.
+    pub fn variance(&self, sample: bool) -> Option<Numeric> {
+        unsafe {
+            self.variance_datum(sample, 0).map(|(variance, _)| {
+                let result = numeric_string(variance);
+                free(&[variance]);
+                result
+            })
+        }
+    }
+
+    /// The sample or population standard deviation of the values, as `stddev_samp(numeric)` or
+    /// `stddev_pop(numeric)` compute it.  `None` if there are too few values.
+    pub fn stddev(&self, sample: bool) -> Option<Numeric> {
+        unsafe {
+            // the square root of a variance with more digits than it has, so that the standard
+            // deviation is only rounded once, to the variance's scale
+            self.variance_datum(sample, EXTRA_STDDEV_DIGITS)
+                .map(|(variance, scale)| {
+                    let result = match scale {
+                        Some(scale) => {
+                            let sqrt = direct_function_call_as_datum(
+                                pg_sys::numeric_sqrt,
+                                vec![Some(variance)],
+                            )
+                            .expect("numeric_sqrt returned null");
+                            let stddev = call(pg_sys::numeric_round, sqrt, scale as pg_sys::Datum);
+                            let result = numeric_string(stddev);
+                            free(&[sqrt, stddev]);
+                            result
+                        }
+                        None => Numeric("NaN".to_string()),
+                    };
+                    free(&[variance]);
+                    result
+                })
+        }
+    }
+
+    /// The variance as a `numeric` in the `CurrentMemoryContext`, the way Postgres'
+    /// `numeric_stddev_internal()` computes it, but with `extra_digits` more digits than the
+    /// display scale Postgres gives it, which is returned too, or `None` if the variance is `NaN`
+    unsafe fn variance_datum(
+        &self,
+        sample: bool,
+        extra_digits: i32,
+    ) -> Option<(pg_sys::Datum, Option<i32>)> {
+        if self.count() == 0 {
+            return None;
+        } else if self.nan_count > 0 {
+            return Some((numeric_datum("NaN"), None));
+        } else if self.count <= sample as i64 {
+            return None;
+        }
+
+        let (sum, sum_sq) = self.totals();
+        let n = numeric_datum(self.count);
+        let sum_squared = call(pg_sys::numeric_mul, sum, sum);
+        let n_sum_sq = call(pg_sys::numeric_mul, n, sum_sq);
+        let numerator = call(pg_sys::numeric_sub, n_sum_sq, sum_squared);
+        let zero = numeric_datum(0);
+
+        // watch out for roundoff error producing a negative numerator
+        let variance = if numeric_cmp(numerator, zero) <= 0 {
+            (numeric_datum(0), Some(0))
+        } else {
+            let count = self.count as i128;
+            let denominator = if sample {
+                numeric_datum(count * (count - 1))
+            } else {
+                numeric_datum(count * count)
+            };
+            let variance = call(pg_sys::numeric_div, numerator, denominator);
+            let scale = numeric_scale(variance);
+            let variance = match scale {
+                Some(scale) if extra_digits > 0 => {
+                    // numeric_div() gives its quotient at least the scale of its dividend, so
+                    // divide again with the numerator padded (exactly) to more digits
+                    let padded = call(
+                        pg_sys::numeric_round,
+                        numerator,
+                        (scale + extra_digits) as pg_sys::Datum,
+                    );
+                    let precise = call(pg_sys::numeric_div, padded, denominator);
+                    free(&[padded, variance]);
+                    precise
+                }
+                _ => variance,
+            };
+            free(&[denominator]);
+            (variance, scale)
+        };
+
+        free(&[sum, sum_sq, n, sum_squared, n_sum_sq, numerator, zero]);
+        Some(variance)
+    }
+
+    /// The sum and sum of squares of every value, in the `CurrentMemoryContext`, with the display
+    /// scales Postgres would give them had the values still here been the only ones added
+    unsafe fn totals(&self) -> (pg_sys::Datum, pg_sys::Datum) {
+        let max_scale = self.scales.keys().next_back().cloned().unwrap_or(0);
+        let total = |int_sum: i128, sum: Option<pg_sys::Datum>, scale: i32| {
+            let int_sum = numeric_datum(int_sum);
+            let total = match sum {
+                Some(sum) => {
+                    let total = call(pg_sys::numeric_add, sum, int_sum);
+                    free(&[int_sum]);
+                    total
+                }
+                None => int_sum,
+            };
+
+            // values that have since been removed may have left it with a larger scale
+            let rounded = call(pg_sys::numeric_round, total, scale as pg_sys::Datum);
+            free(&[total]);
+            rounded
+        };
+
+        (
+            total(self.int_sum, self.sum, max_scale),
+            total(self.int_sum_sq, self.sum_sq, max_scale * 2),
+        )
+    }
+
+    /// Add (or subtract) `datum`, and its square, to the `numeric` sums
+    unsafe fn apply(&mut self, func: NumericFunction, datum: pg_sys::Datum) {
+        let square = call(pg_sys::numeric_mul, datum, datum);
+        self.update_sums(func, datum, square);
+        free(&[square]);
+    }
+
+    /// Fold the `i128` sums into the `numeric` sums
+    fn promote(&mut self) {
+        unsafe {
+            let int_sum = numeric_datum(self.int_sum);
+            let int_sum_sq = numeric_datum(self.int_sum_sq);
+            self.update_sums(pg_sys::numeric_add, int_sum, int_sum_sq);
+            free(&[int_sum, int_sum_sq]);
+        }
+        self.int_sum = 0;
+        self.int_sum_sq = 0;
+    }
+
+    /// Replace the `numeric` sums with `func(sum, value)` and `func(sum_sq, square)`, allocated in
+    /// the accumulator's `MemoryContext`
+    unsafe fn update_sums(
+        &mut self,
+        func: NumericFunction,
+        value: pg_sys::Datum,
+        square: pg_sys::Datum,
+    ) {
+        let (sum, sum_sq) = (self.sum, self.sum_sq);
+        let (new_sum, new_sum_sq) = PgMemoryContexts::For(self.memory_context)
+            .switch_to(|_| (combine(func, sum, value), combine(func, sum_sq, square)));
+
+        free(&[sum.unwrap_or(0), sum_sq.unwrap_or(0)]);
+        self.sum = Some(new_sum);
+        self.sum_sq = Some(new_sum_sq);
+    }
+
+    /// Account for the removal of a value with the display `scale`, or a `NaN`
+    fn forget(&mut self, scale: Option<i32>) {
+        let remaining = match scale {
+            Some(scale) => {
+                let remaining = self.scales.get(&scale).cloned().unwrap_or(0) - 1;
+                if remaining > 0 {
+                    self.scales.insert(scale, remaining);
+                } else {
+                    self.scales.remove(&scale);
+                }
+                self.count -= 1;
+                remaining
+            }
+            None => {
+                self.nan_count -= 1;
+                self.nan_count
+            }
+        };
+
+        if remaining < 0 {
+            panic!("cannot remove a value that was never added to a NumericAccumulator");
+        }
+    }
+}
+
+impl Default for NumericAccumulator {
+    fn default() -> Self {
+        NumericAccumulator::new()
+    }
+}
+
+impl Drop for NumericAccumulator {
+    fn drop(&mut self) {
+        unsafe {
+            free(&[self.sum.unwrap_or(0), self.sum_sq.unwrap_or(0)]);
+        }
+    }
+}
+
+/// How many more digits than its result's scale `stddev()` computes the variance with
+const EXTRA_STDDEV_DIGITS: i32 = 16;
+
+fn numeric_datum<T: ToString>(value: T) -> pg_sys::Datum {
+    Numeric(value.to_string())
+        .into_datum()
+        .expect("numeric_in returned null")
+}
+
+unsafe fn numeric_string(datum: pg_sys::Datum) -> Numeric {
+    Numeric::from_datum(datum, false, pg_sys::NUMERICOID).unwrap()
+}
+
+/// The display scale of a `numeric`, or `None` if it's `NaN`
+unsafe fn numeric_scale(datum: pg_sys::Datum) -> Option<i32> {
+    direct_function_call::<i32>(pg_sys::numeric_scale, vec![Some(datum)])
+}
+
+unsafe fn numeric_cmp(left: pg_sys::Datum, right: pg_sys::Datum) -> i32 {
+    direct_function_call::<i32>(pg_sys::numeric_cmp, vec![Some(left), Some(right)])
+        .expect("numeric_cmp returned null")
+}
+
+unsafe fn call(func: NumericFunction, left: pg_sys::Datum, right: pg_sys::Datum) -> pg_sys::Datum {
+    direct_function_call_as_datum(func, vec![Some(left), Some(right)])
+        .expect("numeric function returned null")
+}
+
+/// `func(total, value)`, where a missing `total` is zero
+unsafe fn combine(
+    func: NumericFunction,
+    total: Option<pg_sys::Datum>,
+    value: pg_sys::Datum,
+) -> pg_sys::Datum {
+    match total {
+        Some(total) => call(func, total, value),
+        None => {
+            let zero = numeric_datum(0);
+            let result = call(func, zero, value);
+            free(&[zero]);
+            result
+        }
+    }
+}
+
+unsafe fn free(datums: &[pg_sys::Datum]) {
+    for datum in datums.iter().filter(|datum| **datum != 0) {
+        pg_sys::pfree(*datum as void_mut_ptr);
+    }
+}
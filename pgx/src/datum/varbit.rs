@@ -0,0 +1,224 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Binary conversion between Postgres' `bit(n)`/`varbit` types and `PgBitString`
+//!
+//! Both types share the same on-disk representation, from "utils/varbit.h":
+//!
+//! ```c
+//! typedef struct
+//! {
+//!     int32 vl_len_;        /* varlena header (do not touch directly!) */
+//!     int32 bit_len;        /* number of valid bits */
+//!     bits8 bit_dat[FLEXIBLE_ARRAY_MEMBER]; /* bit string, most sig. byte first */
+//! } VarBit;
+//! ```
+//!
+//! Within each byte the bits are also packed most significant first, and the unused bits at the
+//! end of the last byte must be zero, as Postgres compares and hashes bit strings a byte at a time.
+
+use crate::{pg_sys, set_varsize, vardata_4b, varlena_to_byte_slice, FromDatum, IntoDatum};
+use std::iter::FromIterator;
+
+/// A Postgres `bit(n)` or `varbit` bit string
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct PgBitString {
+    len: usize,
+    bytes: Vec<u8>,
+}
+
+impl PgBitString {
+    /// An empty bit string
+    pub fn new() -> Self {
+        PgBitString::default()
+    }
+
+    /// A bit string of `len` zero bits
+    pub fn zeros(len: usize) -> Self {
+        PgBitString {
+            len,
+            bytes: vec![0; byte_len(len)],
+        }
+    }
+
+    /// The first `len` bits of `bytes`, most significant first.  Panics if `bytes` is too short
+    pub fn from_bytes(bytes: &[u8], len: usize) -> Self {
+        if bytes.len() < byte_len(len) {
+            panic!(
+                "{} bytes is too short for a bit string of {} bits",
+                bytes.len(),
+                len
+            );
+        }
+
+        let mut bits = PgBitString {
+            len,
+            bytes: bytes[..byte_len(len)].to_vec(),
+        };
+        bits.clear_padding();
+        bits
+    }
+
+    /// The number of bits in this bit string
+    pub fn len_bits(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bit at position `i`, where 0 is the leftmost bit.  Panics if `i` is out of bounds
+    pub fn get_bit(&self, i: usize) -> bool {
+        self.check_bounds(i);
+        self.bytes[i / 8] & mask(i) != 0
+    }
+
+    /// Set the bit at position `i`, where 0 is the leftmost bit.  Panics if `i` is out of bounds
+    pub fn set_bit(&mut self, i: usize, value: bool) {
+        self.check_bounds(i);
+        if value {
+            self.bytes[i / 8] |= mask(i);
+        } else {
+            self.bytes[i / 8] &= !mask(i);
+        }
+    }
+
+    /// Append a bit to the right end of this bit string
+    pub fn push(&mut self, value: bool) {
+        if self.len % 8 == 0 {
+            self.bytes.push(0);
+        }
+        self.len += 1;
+        self.set_bit(self.len - 1, value);
+    }
+
+    /// The packed bits, most significant first, with any unused bits of the last byte zeroed
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The bits, from left to right
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(move |i| self.get_bit(i))
+    }
+
+    fn check_bounds(&self, i: usize) {
+        if i >= self.len {
+            panic!(
+                "bit index {} is out of bounds for a bit string of {} bits",
+                i, self.len
+            );
+        }
+    }
+
+    fn clear_padding(&mut self) {
+        if self.len % 8 != 0 {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] &= 0xFFu8 << (8 - self.len % 8);
+        }
+    }
+}
+
+fn byte_len(bits: usize) -> usize {
+    (bits + 7) / 8
+}
+
+fn mask(i: usize) -> u8 {
+    0x80 >> (i % 8)
+}
+
+impl FromIterator<bool> for PgBitString {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut bits = PgBitString::new();
+        for bit in iter {
+            bits.push(bit);
+        }
+        bits
+    }
+}
+
+/// Formats as Postgres would, such as `01101`
+impl std::fmt::Display for PgBitString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for bit in self.iter() {
+            f.write_str(if bit { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+/// for `bit(n)` and `varbit`
+impl FromDatum for PgBitString {
+    unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _typoid: u32) -> Option<Self> {
+        if is_null {
+            None
+        } else if datum == 0 {
+            panic!("varbit datum is declared non-null but Datum is zero");
+        } else {
+            // the datum could be a short-header varlena, which `varlena_to_byte_slice()`
+            // understands, but then `bit_len` isn't aligned
+            let varlena = pg_sys::pg_detoast_datum_packed(datum as *mut pg_sys::varlena);
+            let bytes = varlena_to_byte_slice(varlena);
+            let mut bit_len = [0u8; 4];
+            bit_len.copy_from_slice(&bytes[..4]);
+            let len = i32::from_ne_bytes(bit_len) as usize;
+
+            Some(PgBitString::from_bytes(&bytes[4..], len))
+        }
+    }
+}
+
+/// for `varbit`, which is assignable to a `bit(n)` of the same length
+impl IntoDatum for PgBitString {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        let size = pg_sys::VARHDRSZ + 4 + self.bytes.len();
+
+        unsafe {
+            let varlena = pg_sys::palloc0(size) as *mut pg_sys::varlena;
+            set_varsize(varlena, size as i32);
+
+            let data = vardata_4b(varlena) as *mut u8;
+            let bit_len = (self.len as i32).to_ne_bytes();
+            std::ptr::copy_nonoverlapping(bit_len.as_ptr(), data, 4);
+            std::ptr::copy_nonoverlapping(self.bytes.as_ptr(), data.add(4), self.bytes.len());
+
+            Some(varlena as pg_sys::Datum)
+        }
+    }
+
+    fn type_oid() -> u32 {
+        pg_sys::VARBITOID
+    }
+}
+
+#[cfg(feature = "bitvec")]
+mod bitvec_conversions {
+    use super::PgBitString;
+    use bitvec::order::{BitOrder, Msb0};
+    use bitvec::slice::BitSlice;
+    use bitvec::store::BitStore;
+    use bitvec::vec::BitVec;
+
+    impl From<PgBitString> for BitVec<u8, Msb0> {
+        fn from(bits: PgBitString) -> Self {
+            let mut vec = BitVec::from_vec(bits.bytes);
+            vec.truncate(bits.len);
+            vec
+        }
+    }
+
+    impl From<BitVec<u8, Msb0>> for PgBitString {
+        fn from(vec: BitVec<u8, Msb0>) -> Self {
+            let len = vec.len();
+            let bytes = vec.into_vec();
+            PgBitString::from_bytes(&bytes, len)
+        }
+    }
+
+    impl<T: BitStore, O: BitOrder> From<&BitSlice<T, O>> for PgBitString {
+        fn from(slice: &BitSlice<T, O>) -> Self {
+            slice.iter().map(|bit| *bit).collect()
+        }
+    }
+}
@@ -1,7 +1,9 @@
 //! Wrapper for Postgres 'varlena' type, over Rust types of a fixed size (ie, `impl Copy`)
+use crate::corruption::sql_type_name;
 use crate::pg_sys::{VARATT_SHORT_MAX, VARHDRSZ_SHORT};
 use crate::{
-    pg_sys, rust_regtypein, set_varsize, set_varsize_short, vardata_any, varsize_any,
+    check_varlena, detoast_datum_copy_checked, detoast_datum_packed_checked, pg_sys,
+    report_corruption, rust_regtypein, set_varsize, set_varsize_short, vardata_any, varsize_any,
     varsize_any_exhdr, void_mut_ptr, FromDatum, IntoDatum, PgMemoryContexts, PostgresType,
     StringInfo,
 };
@@ -150,6 +152,7 @@ where
     /// This function is considered unsafe as it cannot guarantee the provided `pg_sys::Datum` is a
     /// valid `*mut pg_sys::varlena`.
     pub unsafe fn from_datum(datum: pg_sys::Datum) -> Self {
+        check_varlena(datum as *const pg_sys::varlena, &sql_type_name::<T>());
         let ptr = pg_sys::pg_detoast_datum(datum as *mut pg_sys::varlena);
         let len = varsize_any(ptr);
 
@@ -297,7 +300,10 @@ where
         } else {
             memory_context.switch_to(|_| {
                 // this gets the varlena Datum copied into this memory context
-                let detoasted = pg_sys::pg_detoast_datum_copy(datum as *mut pg_sys::varlena);
+                let detoasted = detoast_datum_copy_checked(
+                    datum as *mut pg_sys::varlena,
+                    &sql_type_name::<T>(),
+                );
 
                 // and we need to unpack it (if necessary), which will decompress it too
                 let varlena = pg_sys::pg_detoast_datum_packed(detoasted);
@@ -370,11 +376,15 @@ pub unsafe fn cbor_decode<'de, T>(input: *mut pg_sys::varlena) -> T
 where
     T: Deserialize<'de>,
 {
-    let varlena = pg_sys::pg_detoast_datum_packed(input as *mut pg_sys::varlena);
+    let type_name = sql_type_name::<T>();
+    let varlena = detoast_datum_packed_checked(input, &type_name);
     let len = varsize_any_exhdr(varlena);
     let data = vardata_any(varlena);
     let slice = std::slice::from_raw_parts(data as *const u8, len);
-    serde_cbor::from_slice(slice).expect("failed to decode CBOR")
+    match serde_cbor::from_slice(slice) {
+        Ok(value) => value,
+        Err(e) => report_corruption(&type_name, format!("it isn't valid CBOR: {}", e)),
+    }
 }
 
 pub unsafe fn cbor_decode_into_context<'de, T>(
@@ -386,7 +396,7 @@ where
 {
     memory_context.switch_to(|_| {
         // this gets the varlena Datum copied into this memory context
-        let varlena = pg_sys::pg_detoast_datum_copy(input as *mut pg_sys::varlena);
+        let varlena = detoast_datum_copy_checked(input, &sql_type_name::<T>());
         cbor_decode(varlena)
     })
 }
@@ -0,0 +1,268 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Streaming access to large `bytea` and `text` values
+use crate::{
+    pg_sys, varatt_is_1b_e, vardata_1b_e, varlena_to_byte_slice, varsize_external, vartag_external,
+    FromDatum, PgBox,
+};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Reads a `bytea` or `text` value through `std::io::Read` and `std::io::Seek`, without first
+/// bringing the whole value into memory.
+///
+/// A value that's stored, uncompressed, in a TOAST table (a column with `STORAGE EXTERNAL`) is
+/// fetched a chunk at a time with `pg_detoast_datum_slice()`, so only the chunk being read is in
+/// memory.  Each fetch reads ahead by a whole chunk, so small reads don't each go to the TOAST
+/// table.
+///
+/// Any other value is detoasted in full, exactly as a `&[u8]` argument would be, and read from
+/// memory.  This includes compressed values, both in-line and in a TOAST table, since they can't
+/// be decompressed a slice at a time.  A value that compresses well is rarely large once
+/// compressed, but will be its full size in memory here.
+///
+/// As a `#[pg_extern]` argument, `VarlenaReader` is a `bytea`, and its `TextReader` alias is a
+/// `text`.  For a `text`, the bytes read are its UTF8 encoding, and a read can end in the middle
+/// of a character.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+/// use std::io::Read;
+///
+/// #[pg_extern]
+/// fn count_zero_bytes(mut data: VarlenaReader) -> i64 {
+///     let mut buffer = vec![0; 64 * 1024];
+///     let mut zeros = 0;
+///     loop {
+///         let n = data.read(&mut buffer).expect("failed to read");
+///         if n == 0 {
+///             break;
+///         }
+///         zeros += buffer[..n].iter().filter(|b| **b == 0).count() as i64;
+///     }
+///     zeros
+/// }
+/// ```
+pub struct VarlenaReader {
+    source: VarlenaSource,
+    len: u64,
+    position: u64,
+    chunk_size: usize,
+    chunk: Option<Chunk>,
+}
+
+/// A `text` value, read the same way as `VarlenaReader` reads a `bytea`
+pub type TextReader = VarlenaReader;
+
+enum VarlenaSource {
+    /// A copy of the TOAST pointer of an uncompressed value stored in a TOAST table
+    Toasted(Vec<u8>),
+
+    /// The whole value, already in memory
+    InMemory(*const u8),
+}
+
+/// A slice of a toasted value, as fetched by `pg_detoast_datum_slice()`
+struct Chunk {
+    start: u64,
+    data: PgBox<pg_sys::varlena>,
+}
+
+impl Chunk {
+    fn bytes(&self) -> &[u8] {
+        unsafe { varlena_to_byte_slice(self.data.as_ptr()) }
+    }
+
+    fn contains(&self, position: u64) -> bool {
+        position >= self.start && position < self.start + self.bytes().len() as u64
+    }
+}
+
+impl VarlenaReader {
+    /// The default number of bytes fetched from a TOAST table at a time
+    pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+    /// Read the `bytea` or `text` behind `datum`
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as we cannot validate that `datum` is a varlena, or that it will
+    /// outlive this `VarlenaReader`
+    pub unsafe fn from_varlena_datum(datum: pg_sys::Datum) -> Self {
+        let varlena = datum as *mut pg_sys::varlena;
+
+        if is_ondisk(varlena) {
+            let toast_pointer =
+                std::ptr::read_unaligned(vardata_1b_e(varlena) as *const pg_sys::varatt_external);
+            let rawsize = toast_pointer.va_rawsize as usize - pg_sys::VARHDRSZ;
+            let compressed = (toast_pointer.va_extsize as usize) < rawsize;
+
+            if !compressed {
+                let external =
+                    std::slice::from_raw_parts(varlena as *const u8, varsize_external(varlena));
+                return VarlenaReader::new(
+                    VarlenaSource::Toasted(external.to_vec()),
+                    rawsize as u64,
+                );
+            }
+        }
+
+        let detoasted = varlena_to_byte_slice(pg_sys::pg_detoast_datum_packed(varlena));
+        VarlenaReader::new(
+            VarlenaSource::InMemory(detoasted.as_ptr()),
+            detoasted.len() as u64,
+        )
+    }
+
+    fn new(source: VarlenaSource, len: u64) -> Self {
+        VarlenaReader {
+            source,
+            len,
+            position: 0,
+            chunk_size: VarlenaReader::DEFAULT_CHUNK_SIZE,
+            chunk: None,
+        }
+    }
+
+    /// Fetch `chunk_size` bytes at a time from a TOAST table, instead of `DEFAULT_CHUNK_SIZE`
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        if chunk_size == 0 {
+            panic!("VarlenaReader chunk size must be greater than zero");
+        }
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// The length of the value, in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Is the value fetched from its TOAST table a chunk at a time, rather than being entirely
+    /// in memory?
+    pub fn is_chunked(&self) -> bool {
+        match self.source {
+            VarlenaSource::Toasted(_) => true,
+            VarlenaSource::InMemory(_) => false,
+        }
+    }
+
+    /// The chunk holding `self.position`, fetching it if it isn't the current one
+    fn current_chunk(&mut self, toast_pointer: *const u8) -> &Chunk {
+        let position = self.position;
+        if !matches!(&self.chunk, Some(chunk) if chunk.contains(position)) {
+            // free the previous chunk before fetching the next, so only one is ever in memory
+            self.chunk = None;
+
+            let count = std::cmp::min(self.chunk_size as u64, self.len - position);
+            let data = unsafe {
+                pg_sys::pg_detoast_datum_slice(
+                    toast_pointer as *mut pg_sys::varlena,
+                    position as i32,
+                    count as i32,
+                )
+            };
+            self.chunk = Some(Chunk {
+                start: position,
+                data: PgBox::from_rust(data),
+            });
+        }
+
+        self.chunk.as_ref().unwrap()
+    }
+}
+
+impl Read for VarlenaReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let position = self.position;
+        let n = match &self.source {
+            VarlenaSource::InMemory(data) => {
+                let available = (self.len - position) as usize;
+                let n = std::cmp::min(buf.len(), available);
+                let src = unsafe { std::slice::from_raw_parts(data.add(position as usize), n) };
+                buf[..n].copy_from_slice(src);
+                n
+            }
+            VarlenaSource::Toasted(toast_pointer) => {
+                let toast_pointer = toast_pointer.as_ptr();
+                let chunk = self.current_chunk(toast_pointer);
+                let offset = (position - chunk.start) as usize;
+                let bytes = &chunk.bytes()[offset..];
+                let n = std::cmp::min(buf.len(), bytes.len());
+                buf[..n].copy_from_slice(&bytes[..n]);
+                n
+            }
+        };
+
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for VarlenaReader {
+    /// Seeking past the end is allowed, and reads there return nothing
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => checked_add_signed(self.len, offset),
+            SeekFrom::Current(offset) => checked_add_signed(self.position, offset),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl FromDatum for VarlenaReader {
+    unsafe fn from_datum(
+        datum: pg_sys::Datum,
+        is_null: bool,
+        _typoid: pg_sys::Oid,
+    ) -> Option<Self> {
+        if is_null {
+            None
+        } else if datum == 0 {
+            panic!("a varlena Datum was flagged as non-null but the datum is zero");
+        } else {
+            Some(VarlenaReader::from_varlena_datum(datum))
+        }
+    }
+}
+
+/// Is this varlena a pointer to a value in a TOAST table?
+///
+/// ```c
+/// #define VARATT_IS_EXTERNAL_ONDISK(PTR) \
+///     (VARATT_IS_EXTERNAL(PTR) && VARTAG_EXTERNAL(PTR) == VARTAG_ONDISK)
+/// ```
+unsafe fn is_ondisk(varlena: *const pg_sys::varlena) -> bool {
+    varatt_is_1b_e(varlena)
+        && vartag_external(varlena) as pg_sys::vartag_external
+            == pg_sys::vartag_external_VARTAG_ONDISK
+}
+
+fn checked_add_signed(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.wrapping_neg() as u64)
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Cooperative deadlines derived from Postgres' `statement_timeout`, for long-running Rust loops
+//! that want to stop on their own, rather than rely solely on `check_for_interrupts!()`.
+//!
+//! Postgres enforces `statement_timeout` with a timer that it only arms when a statement begins,
+//! so a timeout `SET` by the running statement itself, such as from within a function, never
+//! fires.  A `DeadlineGuard` measures the timeout from the start of the statement regardless.
+//!
+//! The start of the statement is Postgres' `statement_timestamp()`:
+//!
+//! - with the simple query protocol, it's when the query string arrived.  Every query run through
+//!   SPI, including those of nested function calls, shares the deadline of the outermost statement
+//! - with the extended query protocol, it's when the latest Parse, Bind, or Execute message
+//!   arrived.  Since Postgres 11, its own timer is armed at the first of those messages after a
+//!   Sync, and so may fire before our deadline passes.  `DeadlineGuard::check()` handles a timeout
+//!   that Postgres has already signaled, so that's still reported promptly
+use crate::{interrupt_pending, pg_sys, ErrorReport, PgLogLevel, PgSqlErrorCode};
+use std::time::{Duration, Instant};
+
+/// The current value of `statement_timeout`, or `None` if it's disabled
+pub fn statement_timeout() -> Option<Duration> {
+    millis_to_duration(unsafe { pg_sys::StatementTimeout })
+}
+
+/// The current value of `lock_timeout`, or `None` if it's disabled
+pub fn lock_timeout() -> Option<Duration> {
+    millis_to_duration(unsafe { pg_sys::LockTimeout })
+}
+
+/// The `Instant` the current statement started, as `statement_timestamp()` reports it
+pub fn statement_start() -> Instant {
+    let elapsed =
+        unsafe { pg_sys::GetCurrentTimestamp() - pg_sys::GetCurrentStatementStartTimestamp() };
+
+    // the two clocks can disagree by a little, so don't let the start end up in the future
+    let now = Instant::now();
+    now.checked_sub(Duration::from_micros(elapsed.max(0) as u64))
+        .unwrap_or(now)
+}
+
+/// The `Instant` the current statement exceeds `statement_timeout`, or `None` if it's disabled
+pub fn statement_deadline() -> Option<Instant> {
+    statement_timeout().map(|timeout| statement_start() + timeout)
+}
+
+/// How much time the current statement has left before it exceeds `statement_timeout`, or `None`
+/// if it's disabled.  This is zero once the deadline has passed
+pub fn remaining() -> Option<Duration> {
+    statement_deadline().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Checks the current statement's `statement_timeout` deadline from within a loop.
+///
+/// The deadline is computed once, when the guard is created, so `check()` is cheap enough to call
+/// on every iteration.  A change to `statement_timeout` after that isn't noticed.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::deadline::DeadlineGuard;
+///
+/// let guard = DeadlineGuard::new();
+/// loop {
+///     guard.check();
+///     // ... do some work ...
+/// }
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct DeadlineGuard {
+    deadline: Option<Instant>,
+}
+
+impl Default for DeadlineGuard {
+    fn default() -> Self {
+        DeadlineGuard::new()
+    }
+}
+
+impl DeadlineGuard {
+    /// A guard for the current statement's `statement_deadline()`
+    pub fn new() -> Self {
+        DeadlineGuard {
+            deadline: statement_deadline(),
+        }
+    }
+
+    /// The `Instant` this guard's statement times out, or `None` if `statement_timeout` was
+    /// disabled when the guard was created
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// How much time is left before the deadline, or `None` if there is no deadline
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Has the deadline passed?
+    pub fn is_expired(&self) -> bool {
+        match self.deadline {
+            Some(deadline) => Instant::now() >= deadline,
+            None => false,
+        }
+    }
+
+    /// Process any pending interrupts, as `check_for_interrupts!()` does, and then raise the
+    /// same "canceling statement due to statement timeout" ERROR, with SQLSTATE `57014`
+    /// (`query_canceled`), that Postgres does if the deadline has passed
+    pub fn check(&self) {
+        // a cancel, or a statement timeout Postgres' own timer has already signaled
+        if interrupt_pending() {
+            unsafe { pg_sys::ProcessInterrupts() }
+        }
+
+        if self.is_expired() {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_QUERY_CANCELED,
+                "canceling statement due to statement timeout",
+            )
+            .report(PgLogLevel::ERROR);
+        }
+    }
+}
+
+fn millis_to_duration(millis: i32) -> Option<Duration> {
+    if millis > 0 {
+        Some(Duration::from_millis(millis as u64))
+    } else {
+        None
+    }
+}
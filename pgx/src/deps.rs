@@ -0,0 +1,425 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Recording and inspecting the dependencies between database objects, as stored in `pg_depend`,
+//! along with the objects' comments, as stored in `pg_description`.
+//!
+//! Objects an extension creates at runtime, rather than in its `CREATE EXTENSION` script, need
+//! their dependencies recorded by hand for `DROP` (and `DROP ... CASCADE`) to treat them properly.
+use crate::{
+    direct_function_call_as_datum, pg_sys, void_mut_ptr, ErrorReport, IntoDatum, PgLogLevel,
+    PgRelation, PgSqlErrorCode,
+};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+/// Identifies any database object:  the oid of the system catalog it's in, its oid within that
+/// catalog, and, for a table column, its attribute number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectAddress {
+    pub classid: pg_sys::Oid,
+    pub objid: pg_sys::Oid,
+    pub subid: i32,
+}
+
+/// How one object depends on another.  See the Postgres documentation for `pg_depend` for what
+/// each one means to `DROP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyType {
+    Normal,
+    Auto,
+    Internal,
+    #[cfg(feature = "pg11")]
+    InternalAuto,
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    PartitionPri,
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    PartitionSec,
+    Extension,
+    AutoExtension,
+    Pin,
+}
+
+/// A row of `pg_depend`, from the perspective of one of its objects:  `object` is the other one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dependency {
+    pub object: ObjectAddress,
+    pub deptype: DependencyType,
+}
+
+impl ObjectAddress {
+    pub fn new(classid: pg_sys::Oid, objid: pg_sys::Oid, subid: i32) -> Self {
+        ObjectAddress {
+            classid,
+            objid,
+            subid,
+        }
+    }
+
+    /// A table, index, view, sequence, or any other relation
+    pub fn relation(relation: &PgRelation) -> Self {
+        ObjectAddress::new(pg_sys::RelationRelationId, relation.oid(), 0)
+    }
+
+    /// A column of a relation, by its 1-based attribute number
+    pub fn column(relation: &PgRelation, attnum: i16) -> Self {
+        ObjectAddress::new(pg_sys::RelationRelationId, relation.oid(), attnum as i32)
+    }
+
+    /// A function, by its oid
+    pub fn function(oid: pg_sys::Oid) -> Self {
+        ObjectAddress::new(pg_sys::ProcedureRelationId, oid, 0)
+    }
+
+    /// The installed extension named `name`, if there is one
+    pub fn extension(name: &str) -> Option<Self> {
+        let name = CString::new(name).expect("extension name contains a null byte");
+        let oid = unsafe { pg_sys::get_extension_oid(name.as_ptr(), true) };
+        if oid == pg_sys::InvalidOid {
+            None
+        } else {
+            Some(ObjectAddress::new(pg_sys::ExtensionRelationId, oid, 0))
+        }
+    }
+
+    /// The object's description, as Postgres would word it in an error message, such as
+    /// `table foo` or `function bar(integer)`
+    pub fn description(&self) -> String {
+        let address = pg_sys::ObjectAddress::from(*self);
+        unsafe { palloced_to_string(pg_sys::getObjectDescription(&address)) }
+            .expect("object has no description")
+    }
+}
+
+impl From<&PgRelation> for ObjectAddress {
+    fn from(relation: &PgRelation) -> Self {
+        ObjectAddress::relation(relation)
+    }
+}
+
+impl From<ObjectAddress> for pg_sys::ObjectAddress {
+    fn from(address: ObjectAddress) -> Self {
+        pg_sys::ObjectAddress {
+            classId: address.classid,
+            objectId: address.objid,
+            objectSubId: address.subid,
+        }
+    }
+}
+
+impl From<pg_sys::ObjectAddress> for ObjectAddress {
+    fn from(address: pg_sys::ObjectAddress) -> Self {
+        ObjectAddress::new(address.classId, address.objectId, address.objectSubId)
+    }
+}
+
+impl DependencyType {
+    fn from_deptype(deptype: c_char) -> Self {
+        match deptype as pg_sys::DependencyType {
+            pg_sys::DependencyType_DEPENDENCY_NORMAL => DependencyType::Normal,
+            pg_sys::DependencyType_DEPENDENCY_AUTO => DependencyType::Auto,
+            pg_sys::DependencyType_DEPENDENCY_INTERNAL => DependencyType::Internal,
+            #[cfg(feature = "pg11")]
+            pg_sys::DependencyType_DEPENDENCY_INTERNAL_AUTO => DependencyType::InternalAuto,
+            #[cfg(any(feature = "pg12", feature = "pg13"))]
+            pg_sys::DependencyType_DEPENDENCY_PARTITION_PRI => DependencyType::PartitionPri,
+            #[cfg(any(feature = "pg12", feature = "pg13"))]
+            pg_sys::DependencyType_DEPENDENCY_PARTITION_SEC => DependencyType::PartitionSec,
+            pg_sys::DependencyType_DEPENDENCY_EXTENSION => DependencyType::Extension,
+            pg_sys::DependencyType_DEPENDENCY_AUTO_EXTENSION => DependencyType::AutoExtension,
+            pg_sys::DependencyType_DEPENDENCY_PIN => DependencyType::Pin,
+            other => panic!("unrecognized deptype: {}", other as u8 as char),
+        }
+    }
+
+    fn as_pg(self) -> pg_sys::DependencyType {
+        match self {
+            DependencyType::Normal => pg_sys::DependencyType_DEPENDENCY_NORMAL,
+            DependencyType::Auto => pg_sys::DependencyType_DEPENDENCY_AUTO,
+            DependencyType::Internal => pg_sys::DependencyType_DEPENDENCY_INTERNAL,
+            #[cfg(feature = "pg11")]
+            DependencyType::InternalAuto => pg_sys::DependencyType_DEPENDENCY_INTERNAL_AUTO,
+            #[cfg(any(feature = "pg12", feature = "pg13"))]
+            DependencyType::PartitionPri => pg_sys::DependencyType_DEPENDENCY_PARTITION_PRI,
+            #[cfg(any(feature = "pg12", feature = "pg13"))]
+            DependencyType::PartitionSec => pg_sys::DependencyType_DEPENDENCY_PARTITION_SEC,
+            DependencyType::Extension => pg_sys::DependencyType_DEPENDENCY_EXTENSION,
+            DependencyType::AutoExtension => pg_sys::DependencyType_DEPENDENCY_AUTO_EXTENSION,
+            DependencyType::Pin => pg_sys::DependencyType_DEPENDENCY_PIN,
+        }
+    }
+}
+
+/// Record that `dependent` depends on `referenced`, with `recordDependencyOn()`.
+///
+/// The new `pg_depend` row is made visible with `CommandCounterIncrement()`, so it's seen by
+/// `dependencies_of()`, `dependents_of()`, and `DROP` right away
+pub fn record_dependency(
+    dependent: ObjectAddress,
+    referenced: ObjectAddress,
+    deptype: DependencyType,
+) {
+    let dependent = pg_sys::ObjectAddress::from(dependent);
+    let referenced = pg_sys::ObjectAddress::from(referenced);
+    unsafe {
+        pg_sys::recordDependencyOn(&dependent, &referenced, deptype.as_pg());
+        pg_sys::CommandCounterIncrement();
+    }
+}
+
+/// Make `object` a member of the extension being created or updated, as if its
+/// `CREATE EXTENSION`/`ALTER EXTENSION ... UPDATE` script had created it.
+///
+/// Outside of those commands there is no "current" extension, so record the dependency with
+/// `record_dependency(object, ObjectAddress::extension("...").unwrap(), DependencyType::Extension)`
+/// instead.
+///
+/// ## Panics
+///
+/// This function will panic if no extension is being created or updated
+pub fn record_extension_dependency(object: ObjectAddress) {
+    if unsafe { !pg_sys::creating_extension } {
+        panic!("record_extension_dependency() can only be used by CREATE EXTENSION or ALTER EXTENSION ... UPDATE");
+    }
+
+    let object = pg_sys::ObjectAddress::from(object);
+    unsafe {
+        pg_sys::recordDependencyOnCurrentExtension(&object, false);
+        pg_sys::CommandCounterIncrement();
+    }
+}
+
+/// Mark `relation`, a table (or sequence) of the extension being created or updated, as one of the
+/// extension's configuration tables, whose rows `pg_dump` dumps along with the extension, with
+/// `pg_extension_config_dump()`.  `filter` is a `WHERE` clause that picks which of its rows to
+/// dump, and with `None`, they all are.
+///
+/// This is for tables the extension creates at runtime, such as from a function its script calls.
+/// Tables its script creates are better marked with `extension_config_table!()`.
+///
+/// ## Panics
+///
+/// This function will panic if no extension is being created or updated, or if `relation` isn't
+/// a member of that extension
+pub fn mark_as_config_table(relation: &PgRelation, filter: Option<&str>) {
+    if unsafe { !pg_sys::creating_extension } {
+        panic!("mark_as_config_table() can only be used by CREATE EXTENSION or ALTER EXTENSION ... UPDATE");
+    }
+
+    let extension =
+        unsafe { pg_sys::getExtensionOfObject(pg_sys::RelationRelationId, relation.oid()) };
+    if extension != unsafe { pg_sys::CurrentExtensionObject } {
+        panic!(
+            "table \"{}\" is not a member of the extension being created, so it can't be one of its configuration tables",
+            relation.name()
+        );
+    }
+
+    // an empty filter dumps every row
+    direct_function_call_as_datum(
+        pg_sys::pg_extension_config_dump,
+        vec![
+            relation.oid().into_datum(),
+            filter.unwrap_or("").into_datum(),
+        ],
+    );
+
+    // the extension's pg_extension row has changed, and may be changed again by the next call
+    unsafe {
+        pg_sys::CommandCounterIncrement();
+    }
+}
+
+/// Raise an ERROR naming every one of the extensions in `required` that isn't installed in the
+/// current database, with a hint of how to install them.  `extension_control!()` generates an
+/// `ensure_required_extensions()` that calls this with the extensions the control file
+/// `requires`, which Postgres only checks at `CREATE EXTENSION`, so may have since been dropped
+/// with `DROP EXTENSION ... CASCADE`.
+///
+/// It needs catalog access, so can't be called from the `_PG_init()` of a library in
+/// `shared_preload_libraries`
+pub fn ensure_extensions_installed(required: &[&str]) {
+    let missing = required
+        .iter()
+        .filter(|name| ObjectAddress::extension(name).is_none())
+        .collect::<Vec<_>>();
+
+    if !missing.is_empty() {
+        let names = missing
+            .iter()
+            .map(|name| format!("\"{}\"", name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let message = if missing.len() == 1 {
+            format!("required extension {} is not installed", names)
+        } else {
+            format!("required extensions {} are not installed", names)
+        };
+        let hint = missing
+            .iter()
+            .map(|name| format!("CREATE EXTENSION \"{}\";", name))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        ErrorReport::new(PgSqlErrorCode::ERRCODE_UNDEFINED_OBJECT, message)
+            .with_hint(format!("Install them with: {}", hint))
+            .report(PgLogLevel::ERROR);
+    }
+}
+
+/// Delete every dependency `object` has on other objects, including those of its sub-objects
+/// (such as a table's columns) and any membership in an extension.  Returns how many were deleted
+pub fn delete_dependencies_of(object: ObjectAddress) -> usize {
+    let deleted =
+        unsafe { pg_sys::deleteDependencyRecordsFor(object.classid, object.objid, false) };
+    unsafe {
+        pg_sys::CommandCounterIncrement();
+    }
+    deleted as usize
+}
+
+/// The objects `object` depends on.  When `object.subid` is zero, the dependencies of its
+/// sub-objects (such as a table's columns) are included too
+pub fn dependencies_of(object: ObjectAddress) -> impl Iterator<Item = Dependency> {
+    scan_pg_depend(object, pg_sys::DependDependerIndexId, |depend| {
+        ObjectAddress::new(depend.refclassid, depend.refobjid, depend.refobjsubid)
+    })
+    .into_iter()
+}
+
+/// The objects that depend on `object`.  When `object.subid` is zero, the objects that depend on
+/// its sub-objects (such as a table's columns) are included too
+pub fn dependents_of(object: ObjectAddress) -> impl Iterator<Item = Dependency> {
+    scan_pg_depend(object, pg_sys::DependReferenceIndexId, |depend| {
+        ObjectAddress::new(depend.classid, depend.objid, depend.objsubid)
+    })
+    .into_iter()
+}
+
+/// Set the object's comment, as `COMMENT ON` does, or remove it with `None`.  Shared objects,
+/// such as databases and roles, keep their comments in `pg_shdescription` instead, and aren't
+/// supported
+pub fn set_comment(object: ObjectAddress, comment: Option<&str>) {
+    let comment =
+        comment.map(|comment| CString::new(comment).expect("comment contains a null byte"));
+    unsafe {
+        pg_sys::CreateComments(
+            object.objid,
+            object.classid,
+            object.subid,
+            comment
+                .as_ref()
+                .map_or(std::ptr::null(), |comment| comment.as_ptr()),
+        );
+        pg_sys::CommandCounterIncrement();
+    }
+}
+
+/// The object's comment, if it has one
+pub fn get_comment(object: ObjectAddress) -> Option<String> {
+    unsafe {
+        palloced_to_string(pg_sys::GetComment(
+            object.objid,
+            object.classid,
+            object.subid,
+        ))
+    }
+}
+
+/// Scan `pg_depend` through `indexid`, whose first three columns identify `object` on one side of
+/// each dependency, using `other` to pick out the other side
+fn scan_pg_depend(
+    object: ObjectAddress,
+    indexid: u32,
+    other: impl Fn(&pg_sys::FormData_pg_depend) -> ObjectAddress,
+) -> Vec<Dependency> {
+    // both indexes are on (classid, objid, objsubid), for one side or the other
+    let (classid_attno, objid_attno, subid_attno) = if indexid == pg_sys::DependDependerIndexId {
+        (
+            pg_sys::Anum_pg_depend_classid,
+            pg_sys::Anum_pg_depend_objid,
+            pg_sys::Anum_pg_depend_objsubid,
+        )
+    } else {
+        (
+            pg_sys::Anum_pg_depend_refclassid,
+            pg_sys::Anum_pg_depend_refobjid,
+            pg_sys::Anum_pg_depend_refobjsubid,
+        )
+    };
+
+    let pg_depend = PgRelation::with_lock(
+        pg_sys::DependRelationId,
+        pg_sys::AccessShareLock as pg_sys::LOCKMODE,
+    );
+    let mut keys: [pg_sys::ScanKeyData; 3] = Default::default();
+    let mut nkeys = 2;
+    unsafe {
+        pg_sys::ScanKeyInit(
+            &mut keys[0],
+            classid_attno as pg_sys::AttrNumber,
+            pg_sys::BTEqualStrategyNumber as pg_sys::StrategyNumber,
+            pg_sys::F_OIDEQ as pg_sys::RegProcedure,
+            object.classid as pg_sys::Datum,
+        );
+        pg_sys::ScanKeyInit(
+            &mut keys[1],
+            objid_attno as pg_sys::AttrNumber,
+            pg_sys::BTEqualStrategyNumber as pg_sys::StrategyNumber,
+            pg_sys::F_OIDEQ as pg_sys::RegProcedure,
+            object.objid as pg_sys::Datum,
+        );
+        if object.subid != 0 {
+            pg_sys::ScanKeyInit(
+                &mut keys[2],
+                subid_attno as pg_sys::AttrNumber,
+                pg_sys::BTEqualStrategyNumber as pg_sys::StrategyNumber,
+                pg_sys::F_INT4EQ as pg_sys::RegProcedure,
+                object.subid as pg_sys::Datum,
+            );
+            nkeys = 3;
+        }
+    }
+
+    let mut dependencies = Vec::new();
+    unsafe {
+        let scan = pg_sys::systable_beginscan(
+            pg_depend.as_ptr(),
+            indexid,
+            true,
+            std::ptr::null_mut(),
+            nkeys,
+            keys.as_mut_ptr(),
+        );
+
+        loop {
+            let tuple = pg_sys::systable_getnext(scan);
+            if tuple.is_null() {
+                break;
+            }
+
+            let depend = &*(pg_sys::pgx_GETSTRUCT(tuple) as pg_sys::Form_pg_depend);
+            dependencies.push(Dependency {
+                object: other(depend),
+                deptype: DependencyType::from_deptype(depend.deptype),
+            });
+        }
+
+        pg_sys::systable_endscan(scan);
+    }
+    dependencies
+}
+
+/// Copy a `palloc()`'d string, which may be NULL, into a `String`, and `pfree()` it
+unsafe fn palloced_to_string(s: *mut c_char) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        let string = CStr::from_ptr(s)
+            .to_str()
+            .expect("not a valid UTF8 string")
+            .to_string();
+        pg_sys::pfree(s as void_mut_ptr);
+        Some(string)
+    }
+}
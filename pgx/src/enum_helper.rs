@@ -4,7 +4,122 @@
 //! Helper functions for working with Postgres `enum` types
 
 use crate::pg_sys::pgx_GETSTRUCT;
-use crate::{ereport, pg_sys, PgLogLevel, PgSqlErrorCode};
+use crate::{ereport, pg_guard, pg_sys, PgLogLevel, PgSqlErrorCode};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// The oids of a Postgres enum type and of its array type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgEnumType {
+    pub type_oid: pg_sys::Oid,
+    pub array_type_oid: pg_sys::Oid,
+}
+
+thread_local! {
+    static ENUM_TYPES: RefCell<Option<HashMap<(String, String), PgEnumType>>> = RefCell::new(None);
+}
+
+/// Look up the enum type named `typname` in `schema`, or in whichever schema the current
+/// `search_path` finds it in if `schema` is `None`, and its array type.  Both names are parsed
+/// as SQL identifiers, so are folded to lower case unless they're quoted.  Raises an ERROR if
+/// there's no such type, and panics if it isn't an enum.
+///
+/// Lookups are cached by schema and name until the next change to `pg_type`, so this is cheap
+/// enough to call for every value of the type.  The first call registers our syscache callback,
+/// which lives as long as the backend
+pub fn lookup_enum_type(schema: Option<&str>, typname: &str) -> PgEnumType {
+    // an unqualified name could mean a different type each time the `search_path` changes
+    let (key, resolved) = match schema {
+        Some(schema) => ((schema.to_string(), typname.to_string()), None),
+        None => {
+            let type_oid = crate::regtypein(typname);
+            let schema = type_namespace_name(type_oid);
+            ((schema, typname.to_string()), Some(type_oid))
+        }
+    };
+
+    let cached = ENUM_TYPES.with(|types| {
+        let mut types = types.borrow_mut();
+        let types = types.get_or_insert_with(|| {
+            unsafe {
+                pg_sys::CacheRegisterSyscacheCallback(
+                    pg_sys::SysCacheIdentifier_TYPEOID as i32,
+                    Some(invalidate_enum_types),
+                    0,
+                )
+            };
+            HashMap::new()
+        });
+        types.get(&key).cloned()
+    });
+    if let Some(enum_type) = cached {
+        return enum_type;
+    }
+
+    // not borrowed while we look it up, as the lookup can process invalidations
+    let type_oid = resolved.unwrap_or_else(|| crate::regtypein(&format!("{}.{}", key.0, key.1)));
+    if unsafe { pg_sys::get_typtype(type_oid) } as u8 != pg_sys::TYPTYPE_ENUM {
+        panic!("type {} is not an enum", typname);
+    }
+    let array_type_oid = unsafe { pg_sys::get_array_type(type_oid) };
+    if array_type_oid == pg_sys::InvalidOid {
+        panic!("enum type {} has no array type", typname);
+    }
+
+    let enum_type = PgEnumType {
+        type_oid,
+        array_type_oid,
+    };
+    ENUM_TYPES.with(|types| {
+        if let Some(types) = types.borrow_mut().as_mut() {
+            types.insert(key, enum_type);
+        }
+    });
+    enum_type
+}
+
+/// The quoted name of the schema the type `type_oid` is in
+fn type_namespace_name(type_oid: pg_sys::Oid) -> String {
+    let tup = unsafe {
+        pg_sys::SearchSysCache(
+            pg_sys::SysCacheIdentifier_TYPEOID as i32,
+            type_oid as pg_sys::Datum,
+            0,
+            0,
+            0,
+        )
+    };
+    if tup.is_null() {
+        panic!("cache lookup failed for type {}", type_oid);
+    }
+    let typ = unsafe { pgx_GETSTRUCT(tup) } as pg_sys::Form_pg_type;
+    let namespace = unsafe { typ.as_ref() }.unwrap().typnamespace;
+    unsafe { pg_sys::ReleaseSysCache(tup) };
+
+    unsafe {
+        let name = pg_sys::get_namespace_name(namespace);
+        let quoted = crate::quote::quote_identifier(
+            std::ffi::CStr::from_ptr(name)
+                .to_str()
+                .expect("schema name is not valid UTF-8"),
+        );
+        pg_sys::pfree(name as *mut _);
+        quoted
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn invalidate_enum_types(
+    _arg: pg_sys::Datum,
+    _cacheid: std::os::raw::c_int,
+    _hashvalue: u32,
+) {
+    ENUM_TYPES.with(|types| {
+        if let Some(types) = types.borrow_mut().as_mut() {
+            types.clear();
+        }
+    });
+}
 
 pub fn lookup_enum_by_oid(enumval: pg_sys::Oid) -> (String, pg_sys::Oid, f32) {
     let tup = unsafe {
@@ -47,12 +162,11 @@ pub fn lookup_enum_by_oid(enumval: pg_sys::Oid) -> (String, pg_sys::Oid, f32) {
     result
 }
 
-pub fn lookup_enum_by_label(typname: &str, label: &str) -> pg_sys::Datum {
-    let enumtypoid = crate::regtypein(typname);
-
-    if enumtypoid == pg_sys::InvalidOid {
-        panic!("could not locate type oid for type: {}", typname);
-    }
+/// Look up the value of `typname`, in `schema` as for [`lookup_enum_type`], with the label `label`
+/// in the catalog, which knows about values added with `ALTER TYPE ... ADD VALUE`, rather than
+/// going by its position in a Rust enum
+pub fn lookup_enum_by_label(schema: Option<&str>, typname: &str, label: &str) -> pg_sys::Datum {
+    let enumtypoid = lookup_enum_type(schema, typname).type_oid;
 
     let tup = unsafe {
         let label =
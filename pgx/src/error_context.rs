@@ -0,0 +1,135 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Error context frames, which add lines such as `while indexing row 5` to the `CONTEXT` of any
+//! ERROR raised while they're in place, through Postgres' `error_context_stack`.
+//!
+//! A frame's closure is only called when an error is actually being reported, so it costs
+//! nothing to keep a frame up to date for every row of a large table.  Frames can be nested, and
+//! their context is reported innermost first, after the context of any frames Postgres itself
+//! pushed inside of them.
+//!
+//! This applies to Rust `panic!()`s too, which are reported as ERRORs once they've unwound out of
+//! every frame:  each frame adds its context as the panic unwinds out of it.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//!
+//! fn index_rows(rows: &[String]) {
+//!     let _frame = error_context::push(|| "while indexing the rows table".to_string());
+//!     for (i, row) in rows.iter().enumerate() {
+//!         let _frame = error_context::push(move || format!("while indexing row {}", i + 1));
+//!         if row.is_empty() {
+//!             // CONTEXT:  while indexing row 5
+//!             //           while indexing the rows table
+//!             panic!("rows can't be empty");
+//!         }
+//!     }
+//! }
+//! ```
+use crate::pg_sys;
+use std::panic::AssertUnwindSafe;
+
+type ContextFn = Box<dyn Fn() -> String>;
+
+/// An error context frame, which is in place until it's dropped.
+///
+/// Postgres keeps a pointer to the frame, so a frame that's `std::mem::forget()`en is leaked
+/// rather than freed, and its context is reported until whatever Postgres frame was on top of
+/// the stack when it was pushed is popped.
+#[must_use = "the frame is popped as soon as it's dropped"]
+pub struct ContextFrame {
+    callback: Box<pg_sys::ErrorContextCallback>,
+    context: Box<ContextFn>,
+}
+
+/// Push a frame onto the error context stack, whose context is made by `context` if an error is
+/// reported while the frame is in place.
+///
+/// `context` is called while Postgres is reporting an error, where `CurrentMemoryContext` is its
+/// `ErrorContext`.  It should do no more than format a `String`, which is copied into the error,
+/// and must not raise an ERROR or `panic!()`.  If it panics anyway, its context is left out.
+///
+/// `context` can't borrow anything, as the frame could outlive it if it's forgotten, so it
+/// should `move` whatever it needs:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// let table = String::from("t");
+/// let _frame = error_context::push(|| format!("while processing table {}", table));
+/// ```
+pub fn push<F: Fn() -> String + 'static>(context: F) -> ContextFrame {
+    let context: Box<ContextFn> = Box::new(Box::new(context));
+    let mut callback = Box::new(pg_sys::ErrorContextCallback {
+        previous: unsafe { pg_sys::error_context_stack },
+        callback: Some(context_callback),
+        arg: context.as_ref() as *const ContextFn as *mut std::os::raw::c_void,
+    });
+
+    unsafe {
+        pg_sys::error_context_stack = callback.as_mut();
+    }
+
+    ContextFrame { callback, context }
+}
+
+/// The context of every frame pushed by [`push()`] that's in place, innermost first, as it would
+/// be reported if an error were raised now.  Frames Postgres itself pushed are left out, as their
+/// context can only be made while an error is being reported
+pub fn current() -> Vec<String> {
+    let mut contexts = Vec::new();
+    unsafe {
+        let mut frame = pg_sys::error_context_stack;
+        while !frame.is_null() {
+            if (*frame).callback.map(|callback| callback as usize)
+                == Some(context_callback as usize)
+            {
+                let context = &*((*frame).arg as *const ContextFn);
+                match std::panic::catch_unwind(AssertUnwindSafe(|| context())) {
+                    Ok(line) => contexts.push(line),
+                    Err(_) => pg_sys::guard::forget_panic(),
+                }
+            }
+            frame = (*frame).previous;
+        }
+    }
+    contexts
+}
+
+impl Drop for ContextFrame {
+    fn drop(&mut self) {
+        // a panic is only reported as an ERROR after it has unwound out of this frame
+        if std::thread::panicking() && pg_sys::guard::wants_panic_context() {
+            pg_sys::guard::push_panic_context(|| (self.context)());
+        }
+
+        unsafe {
+            let this = self.callback.as_mut() as *mut pg_sys::ErrorContextCallback;
+
+            // frames are normally popped from the top, but unlink this one wherever it is, and
+            // not at all if an ERROR has already reset the stack to below it
+            let mut next =
+                &mut pg_sys::error_context_stack as *mut *mut pg_sys::ErrorContextCallback;
+            while !(*next).is_null() {
+                if *next == this {
+                    *next = self.callback.previous;
+                    break;
+                }
+                next = &mut (**next).previous;
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn context_callback(arg: *mut std::os::raw::c_void) {
+    let context = &*(arg as *const ContextFn);
+    match std::panic::catch_unwind(AssertUnwindSafe(|| context())) {
+        Ok(line) => pg_sys::guard::errcontext(&line),
+
+        // we're already reporting an error, so there's nothing to do but leave this context out
+        Err(_) => pg_sys::guard::forget_panic(),
+    }
+}
@@ -5,7 +5,10 @@
 //!
 //! Other than the exported macros, typically these functions are not necessary to call directly
 //! as they're used behind the scenes by the code generated by the `#[pg_extern]` macro.
-use crate::{pg_sys, void_mut_ptr, FromDatum, PgBox, PgMemoryContexts};
+use crate::{
+    is_a, pg_sys, reset_each, void_mut_ptr, ErrorReport, FromDatum, IntoDatum, PgBox, PgLogLevel,
+    PgMemoryContexts, PgSqlErrorCode,
+};
 
 /// A macro for specifying default argument values so they get propery translated to SQL in
 /// `CREATE FUNCTION` statements
@@ -76,104 +79,228 @@ macro_rules! variadic {
 
 #[cfg(any(feature = "pg10", feature = "pg11"))]
 mod pg_10_11 {
-    use crate::{pg_sys, FromDatum};
-
-    #[inline]
-    pub fn pg_getarg<T: FromDatum>(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> Option<T> {
-        let datum = unsafe { fcinfo.as_ref() }.unwrap().arg[num];
-        let isnull = pg_arg_is_null(fcinfo, num);
-        unsafe { T::from_datum(datum, isnull, crate::get_getarg_type(fcinfo, num)) }
+    use crate::{pg_sys, FunctionCallInfoArgs, PgFunctionCallInfo};
+    use std::borrow::Cow;
+
+    /// A function argument and whether it's NULL, which Postgres 12 calls a `NullableDatum`
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct PgNullableDatum {
+        pub value: pg_sys::Datum,
+        pub isnull: bool,
     }
 
-    #[inline]
-    pub fn pg_arg_is_null(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> bool {
-        unsafe { fcinfo.as_ref() }.unwrap().argnull[num] as bool
-    }
+    /// Postgres 10 and 11 keep the arguments and their NULL flags in parallel arrays
+    impl FunctionCallInfoArgs for PgFunctionCallInfo<'_> {
+        fn num_args(&self) -> usize {
+            crate::fcinfo::checked_nargs(self.data().nargs)
+        }
 
-    #[inline]
-    pub fn pg_getarg_datum(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> Option<pg_sys::Datum> {
-        if pg_arg_is_null(fcinfo, num) {
-            None
-        } else {
-            Some(unsafe { fcinfo.as_ref() }.unwrap().arg[num])
+        fn args(&self) -> Cow<'_, [PgNullableDatum]> {
+            let nargs = self.num_args();
+            let data = self.data();
+            Cow::Owned(
+                data.arg[..nargs]
+                    .iter()
+                    .zip(data.argnull[..nargs].iter())
+                    .map(|(&value, &isnull)| PgNullableDatum { value, isnull })
+                    .collect(),
+            )
         }
-    }
 
-    #[inline]
-    pub fn pg_getarg_datum_raw(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> pg_sys::Datum {
-        unsafe { fcinfo.as_ref() }.unwrap().arg[num]
+        fn arg(&self, i: usize) -> Option<PgNullableDatum> {
+            if i < self.num_args() {
+                let data = self.data();
+                Some(PgNullableDatum {
+                    value: data.arg[i],
+                    isnull: data.argnull[i],
+                })
+            } else {
+                None
+            }
+        }
     }
+}
 
-    #[inline]
-    pub fn pg_return_null(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
-        unsafe { fcinfo.as_mut() }.unwrap().isnull = true;
-        0 as pg_sys::Datum
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+mod pg_12_13 {
+    use crate::{pg_sys, FunctionCallInfoArgs, PgFunctionCallInfo};
+    use std::borrow::Cow;
+
+    /// A function argument and whether it's NULL
+    pub type PgNullableDatum = pg_sys::NullableDatum;
+
+    /// Postgres 12 and later keep the arguments in the flexible array member `args`, which
+    /// Postgres allocates room for `nargs` of.  That's only true of a `FunctionCallInfo` Postgres
+    /// (or `LOCAL_FCINFO`) allocated, which `PgFunctionCallInfo::from_ptr()` requires
+    impl FunctionCallInfoArgs for PgFunctionCallInfo<'_> {
+        fn num_args(&self) -> usize {
+            crate::fcinfo::checked_nargs(self.data().nargs)
+        }
+
+        fn args(&self) -> Cow<'_, [PgNullableDatum]> {
+            // SAFETY:  from_ptr()'s caller promised room for `nargs` arguments
+            Cow::Borrowed(unsafe { self.data().args.as_slice(self.num_args()) })
+        }
+
+        fn arg(&self, i: usize) -> Option<PgNullableDatum> {
+            self.args().get(i).copied()
+        }
     }
 }
 
+//
+// common
+//
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+pub use pg_10_11::*;
+
 #[cfg(any(feature = "pg12", feature = "pg13"))]
-mod pg_12_13 {
-    use crate::{pg_sys, FromDatum};
+pub use pg_12_13::*;
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::ops::DerefMut;
 
-    #[inline]
-    pub fn pg_getarg<T: FromDatum>(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> Option<T> {
-        let datum = get_nullable_datum(fcinfo, num);
-        unsafe {
-            T::from_datum(
-                datum.value,
-                datum.isnull,
-                crate::get_getarg_type(fcinfo, num),
-            )
+/// A `pg_sys::FunctionCallInfo` whose arguments can be read through `FunctionCallInfoArgs`.
+///
+/// On Postgres 12 and later the arguments live past the end of the struct, so only a pointer to
+/// a `FunctionCallInfo` that was allocated with room for its `nargs` arguments can be wrapped, and
+/// only by the unsafe `from_ptr()`
+#[derive(Debug, Clone, Copy)]
+pub struct PgFunctionCallInfo<'fcx> {
+    fcinfo: pg_sys::FunctionCallInfo,
+    __marker: PhantomData<&'fcx ()>,
+}
+
+impl<'fcx> PgFunctionCallInfo<'fcx> {
+    /// Wrap `fcinfo`.  Panics if it's NULL
+    ///
+    /// ## Safety
+    ///
+    /// `fcinfo` must point to a `FunctionCallInfo` that stays valid, and unchanged, for `'fcx`, and
+    /// that was allocated with room for as many arguments as its `nargs` says it has, as those
+    /// Postgres passes to a function, or that `LOCAL_FCINFO` makes, are
+    pub unsafe fn from_ptr(fcinfo: pg_sys::FunctionCallInfo) -> Self {
+        if fcinfo.is_null() {
+            panic!("fcinfo is NULL");
+        }
+        PgFunctionCallInfo {
+            fcinfo,
+            __marker: PhantomData,
         }
     }
 
-    #[inline]
-    pub fn pg_arg_is_null(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> bool {
-        get_nullable_datum(fcinfo, num).isnull
+    /// The wrapped `FunctionCallInfo`
+    pub fn as_ptr(&self) -> pg_sys::FunctionCallInfo {
+        self.fcinfo
     }
 
-    #[inline]
-    pub fn pg_getarg_datum(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> Option<pg_sys::Datum> {
-        if pg_arg_is_null(fcinfo, num) {
-            None
-        } else {
-            Some(get_nullable_datum(fcinfo, num).value)
-        }
+    #[cfg(any(feature = "pg10", feature = "pg11"))]
+    fn data(&self) -> &'fcx pg_sys::FunctionCallInfoData {
+        unsafe { &*self.fcinfo }
     }
 
-    #[inline]
-    pub fn pg_getarg_datum_raw(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> pg_sys::Datum {
-        get_nullable_datum(fcinfo, num).value
+    #[cfg(any(feature = "pg12", feature = "pg13"))]
+    fn data(&self) -> &'fcx pg_sys::FunctionCallInfoBaseData {
+        unsafe { &*self.fcinfo }
     }
+}
 
-    #[inline]
-    fn get_nullable_datum(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> pg_sys::NullableDatum {
-        let fcinfo = unsafe { fcinfo.as_mut() }.unwrap();
-        unsafe {
-            let nargs = fcinfo.nargs;
-            let len = std::mem::size_of::<pg_sys::NullableDatum>() * nargs as usize;
-            fcinfo.args.as_slice(len)[num]
+/// The arguments of a function call, read the same way on every Postgres version, and checked
+/// against the number of arguments the call has.
+///
+/// Postgres 12 moved the arguments from the parallel `arg` and `argnull` arrays of
+/// `FunctionCallInfoData` to the `args` array of `NullableDatum`s of `FunctionCallInfoBaseData`,
+/// so `args()` borrows them on 12 and later, and copies them on 10 and 11.  It's implemented by
+/// `PgFunctionCallInfo`, rather than the bare struct, as the struct alone doesn't say how many
+/// arguments there's room for
+pub trait FunctionCallInfoArgs {
+    /// How many arguments the call has.  Panics if `nargs` is negative or more than
+    /// `FUNC_MAX_ARGS`, as the struct is then corrupt
+    fn num_args(&self) -> usize;
+
+    /// All of the call's arguments
+    fn args(&self) -> Cow<'_, [PgNullableDatum]>;
+
+    /// Argument `i` (from 0), or `None` if the call doesn't have that many
+    fn arg(&self, i: usize) -> Option<PgNullableDatum>;
+
+    /// Whether argument `i` is NULL, or `None` if the call doesn't have that many
+    fn arg_is_null(&self, i: usize) -> Option<bool> {
+        self.arg(i).map(|arg| arg.isnull)
+    }
+
+    /// The value of argument `i`, or `None` if it's NULL.  Panics if the call doesn't have that
+    /// many arguments
+    fn get_arg_datum(&self, i: usize) -> Option<pg_sys::Datum> {
+        let arg = self.arg(i).unwrap_or_else(|| {
+            panic!(
+                "argument {} is out of range, the function was called with {} arguments",
+                i,
+                self.num_args()
+            )
+        });
+        if arg.isnull {
+            None
+        } else {
+            Some(arg.value)
         }
     }
+}
 
-    #[inline]
-    pub fn pg_return_null(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
-        let fcinfo = unsafe { fcinfo.as_mut() }.unwrap();
-        fcinfo.isnull = true;
-        0 as pg_sys::Datum
+fn checked_nargs(nargs: i16) -> usize {
+    if nargs < 0 || nargs as u32 > pg_sys::FUNC_MAX_ARGS {
+        panic!(
+            "function call info has an invalid number of arguments: {}",
+            nargs
+        );
     }
+    nargs as usize
 }
 
-//
-// common
-//
+fn get_nullable_datum(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> PgNullableDatum {
+    let fcinfo = unsafe { PgFunctionCallInfo::from_ptr(fcinfo) };
+    fcinfo.arg(num).unwrap_or_else(|| {
+        panic!(
+            "argument {} is out of range, the function was called with {} arguments",
+            num,
+            fcinfo.num_args()
+        )
+    })
+}
 
-#[cfg(any(feature = "pg10", feature = "pg11"))]
-pub use pg_10_11::*;
+#[inline]
+pub fn pg_getarg<T: FromDatum>(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> Option<T> {
+    let datum = get_nullable_datum(fcinfo, num);
+    unsafe { T::from_datum(datum.value, datum.isnull, get_getarg_type(fcinfo, num)) }
+}
 
-#[cfg(any(feature = "pg12", feature = "pg13"))]
-pub use pg_12_13::*;
-use std::ops::DerefMut;
+#[inline]
+pub fn pg_arg_is_null(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> bool {
+    get_nullable_datum(fcinfo, num).isnull
+}
+
+#[inline]
+pub fn pg_getarg_datum(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> Option<pg_sys::Datum> {
+    let datum = get_nullable_datum(fcinfo, num);
+    if datum.isnull {
+        None
+    } else {
+        Some(datum.value)
+    }
+}
+
+#[inline]
+pub fn pg_getarg_datum_raw(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> pg_sys::Datum {
+    get_nullable_datum(fcinfo, num).value
+}
+
+#[inline]
+pub fn pg_return_null(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+    unsafe { fcinfo.as_mut() }.unwrap().isnull = true;
+    0 as pg_sys::Datum
+}
 
 #[inline]
 pub fn pg_getarg_pointer<T>(fcinfo: pg_sys::FunctionCallInfo, num: usize) -> Option<*mut T> {
@@ -203,6 +330,18 @@ pub fn pg_getarg_cstr<'a>(
     }
 }
 
+/// `#[pg_extern]` calls this for each argument's type so that a type that can't be converted from
+/// a `Datum` is reported at the argument, rather than somewhere in the generated code
+#[doc(hidden)]
+#[inline(always)]
+pub fn pg_extern_argument_must_implement_from_datum<T: FromDatum>() {}
+
+/// `#[pg_extern]` calls this for its return type so that a type that can't be converted into a
+/// `Datum` is reported at the return type, rather than somewhere in the generated code
+#[doc(hidden)]
+#[inline(always)]
+pub fn pg_extern_return_type_must_implement_into_datum<T: IntoDatum>() {}
+
 #[inline]
 pub fn pg_return_void() -> pg_sys::Datum {
     0 as pg_sys::Datum
@@ -212,8 +351,8 @@ pub fn pg_func_extra<ReturnType, DefaultValue: FnOnce() -> ReturnType>(
     fcinfo: pg_sys::FunctionCallInfo,
     default: DefaultValue,
 ) -> PgBox<ReturnType> {
-    let fcinfo = PgBox::from_pg(fcinfo);
-    let mut flinfo = PgBox::from_pg(fcinfo.flinfo);
+    let mut fcinfo = PgBox::from_pg(fcinfo);
+    let flinfo = unsafe { fcinfo.flinfo_mut() }.expect("fcinfo.flinfo is NULL");
     if flinfo.fn_extra.is_null() {
         flinfo.fn_extra = PgMemoryContexts::For(flinfo.fn_mcxt).leak_and_drop_on_delete(default())
             as void_mut_ptr;
@@ -348,7 +487,7 @@ fn make_function_call_info(
 #[inline]
 pub fn srf_is_first_call(fcinfo: pg_sys::FunctionCallInfo) -> bool {
     let fcinfo = PgBox::from_pg(fcinfo);
-    let flinfo = PgBox::from_pg(fcinfo.flinfo);
+    let flinfo = unsafe { fcinfo.flinfo_ref() }.expect("fcinfo.flinfo is NULL");
 
     flinfo.fn_extra.is_null()
 }
@@ -390,3 +529,77 @@ pub fn srf_return_done(
     let mut rsi = PgBox::from_pg(fcinfo.resultinfo as *mut pg_sys::ReturnSetInfo);
     rsi.isDone = pg_sys::ExprDoneCond_ExprEndResult;
 }
+
+/// Drain `rows` into a tuplestore and hand it to Postgres as the entire result of a
+/// set-returning function, in `SFRM_Materialize` mode.  `fill_row` converts each row into the
+/// `datums` and `nulls` of the function's result columns, which start out as zeroes and `false`.
+///
+/// This is what `#[pg_extern(materialize)]` functions do, on the one call Postgres makes to them.
+/// The tuplestore spills to disk past `work_mem`, and each row's Datums are free'd once they've
+/// been copied into it, so memory use is bounded no matter how many rows there are.
+pub fn srf_materialize<I, F>(
+    fcinfo: pg_sys::FunctionCallInfo,
+    rows: I,
+    mut fill_row: F,
+) -> pg_sys::Datum
+where
+    I: IntoIterator,
+    F: FnMut(I::Item, &mut [pg_sys::Datum], &mut [bool]),
+{
+    let fcinfo = PgBox::from_pg(fcinfo);
+    let rsinfo = fcinfo.resultinfo as *mut pg_sys::ReturnSetInfo;
+    if rsinfo.is_null() || !is_a(rsinfo as *mut pg_sys::Node, pg_sys::NodeTag_T_ReturnSetInfo) {
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "set-valued function called in context that cannot accept a set",
+        )
+        .report(PgLogLevel::ERROR);
+    }
+    let mut rsinfo = PgBox::from_pg(rsinfo);
+    if rsinfo.allowedModes & pg_sys::SetFunctionReturnMode_SFRM_Materialize as i32 == 0 {
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            "materialize mode required, but it is not allowed in this context",
+        )
+        .report(PgLogLevel::ERROR);
+    }
+
+    // both have to outlive this call
+    let previous =
+        PgMemoryContexts::For(unsafe { (*rsinfo.econtext).ecxt_per_query_memory }).set_as_current();
+    let tupdesc = unsafe {
+        if !rsinfo.expectedDesc.is_null() {
+            pg_sys::CreateTupleDescCopy(rsinfo.expectedDesc)
+        } else {
+            let mut tupdesc: pg_sys::TupleDesc = std::ptr::null_mut();
+            if pg_sys::get_call_result_type(fcinfo.as_ptr(), std::ptr::null_mut(), &mut tupdesc)
+                != pg_sys::TypeFuncClass_TYPEFUNC_COMPOSITE
+            {
+                panic!("return type must be a row type");
+            }
+            pg_sys::BlessTupleDesc(tupdesc)
+        }
+    };
+    let random_access =
+        rsinfo.allowedModes & pg_sys::SetFunctionReturnMode_SFRM_Materialize_Random as i32 != 0;
+    let tupstore = unsafe { pg_sys::tuplestore_begin_heap(random_access, false, pg_sys::work_mem) };
+    previous.set_as_current();
+
+    let natts = unsafe { (*tupdesc).natts } as usize;
+    let mut datums = vec![0 as pg_sys::Datum; natts];
+    let mut nulls = vec![false; natts];
+    for row in reset_each(rows, PgMemoryContexts::new("srf_materialize rows")) {
+        datums.iter_mut().for_each(|datum| *datum = 0);
+        nulls.iter_mut().for_each(|null| *null = false);
+        fill_row(row, &mut datums, &mut nulls);
+
+        unsafe {
+            pg_sys::tuplestore_putvalues(tupstore, tupdesc, datums.as_mut_ptr(), nulls.as_mut_ptr())
+        };
+    }
+
+    rsinfo.returnMode = pg_sys::SetFunctionReturnMode_SFRM_Materialize;
+    rsinfo.setResult = tupstore;
+    rsinfo.setDesc = tupdesc;
+    0 as pg_sys::Datum
+}
@@ -0,0 +1,219 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Safe access to the catalog entries a foreign-data wrapper works with:  foreign servers, user
+//! mappings, and foreign tables, along with their `OPTIONS (...)`
+use crate::{pg_sys, PgBox, PgList, PgRelation};
+use std::collections::HashMap;
+
+/// A `CREATE SERVER`
+pub struct ForeignServer(PgBox<pg_sys::ForeignServer>);
+
+/// A `CREATE USER MAPPING`, for a specific user or for `PUBLIC`
+pub struct UserMapping(PgBox<pg_sys::UserMapping>);
+
+/// A `CREATE FOREIGN TABLE`, along with its server
+pub struct ForeignTable {
+    table: PgBox<pg_sys::ForeignTable>,
+    server: ForeignServer,
+}
+
+/// There's no user mapping for the user, nor a `PUBLIC` one, on the server.  Many foreign-data
+/// wrappers don't require one, so this isn't an ERROR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserMappingNotFound {
+    pub userid: pg_sys::Oid,
+    pub serverid: pg_sys::Oid,
+}
+
+impl std::fmt::Display for UserMappingNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "user mapping not found for user {} on server {}",
+            self.userid, self.serverid
+        )
+    }
+}
+
+impl std::error::Error for UserMappingNotFound {}
+
+impl ForeignServer {
+    /// Raises an ERROR if there is no foreign server with this oid
+    pub fn from_oid(oid: pg_sys::Oid) -> Self {
+        ForeignServer(PgBox::from_pg(unsafe { pg_sys::GetForeignServer(oid) }))
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = std::ffi::CString::new(name).expect("server name contains a null byte");
+        let server = unsafe { pg_sys::GetForeignServerByName(name.as_ptr(), true) };
+
+        if server.is_null() {
+            None
+        } else {
+            Some(ForeignServer(PgBox::from_pg(server)))
+        }
+    }
+
+    pub fn oid(&self) -> pg_sys::Oid {
+        self.0.serverid
+    }
+
+    pub fn name(&self) -> &str {
+        unsafe { cstr_to_str(self.0.servername) }.expect("foreign server has no name")
+    }
+
+    /// The oid of the server's foreign-data wrapper
+    pub fn fdw_oid(&self) -> pg_sys::Oid {
+        self.0.fdwid
+    }
+
+    pub fn owner(&self) -> pg_sys::Oid {
+        self.0.owner
+    }
+
+    /// The server's `TYPE`, if it has one
+    pub fn server_type(&self) -> Option<&str> {
+        unsafe { cstr_to_str(self.0.servertype) }
+    }
+
+    /// The server's `VERSION`, if it has one
+    pub fn server_version(&self) -> Option<&str> {
+        unsafe { cstr_to_str(self.0.serverversion) }
+    }
+
+    pub fn options(&self) -> HashMap<String, String> {
+        options_to_map(self.0.options)
+    }
+
+    /// The options of the server's foreign-data wrapper
+    pub fn fdw_options(&self) -> HashMap<String, String> {
+        options_to_map(unsafe { (*pg_sys::GetForeignDataWrapper(self.0.fdwid)).options })
+    }
+
+    /// The user mapping for `userid` on this server.  See `UserMapping::for_user_and_server()`
+    pub fn user_mapping(&self, userid: pg_sys::Oid) -> Result<UserMapping, UserMappingNotFound> {
+        UserMapping::for_user_and_server(userid, self.oid())
+    }
+}
+
+impl UserMapping {
+    /// Find the user mapping for `userid` on the server, falling back to the server's `PUBLIC`
+    /// user mapping, just as Postgres' `GetUserMapping()` does
+    pub fn for_user_and_server(
+        userid: pg_sys::Oid,
+        serverid: pg_sys::Oid,
+    ) -> Result<Self, UserMappingNotFound> {
+        let exists = |userid: pg_sys::Oid| unsafe {
+            pg_sys::SearchSysCacheExists(
+                pg_sys::SysCacheIdentifier_USERMAPPINGUSERSERVER as i32,
+                userid as pg_sys::Datum,
+                serverid as pg_sys::Datum,
+                0,
+                0,
+            )
+        };
+
+        // GetUserMapping() raises an ERROR if it can't find either
+        if !exists(userid) && !exists(pg_sys::InvalidOid) {
+            return Err(UserMappingNotFound { userid, serverid });
+        }
+
+        Ok(UserMapping(PgBox::from_pg(unsafe {
+            pg_sys::GetUserMapping(userid, serverid)
+        })))
+    }
+
+    pub fn oid(&self) -> pg_sys::Oid {
+        self.0.umid
+    }
+
+    /// The user this mapping is for, which is `pg_sys::InvalidOid` for a `PUBLIC` mapping
+    pub fn userid(&self) -> pg_sys::Oid {
+        self.0.userid
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.0.userid == pg_sys::InvalidOid
+    }
+
+    pub fn serverid(&self) -> pg_sys::Oid {
+        self.0.serverid
+    }
+
+    pub fn options(&self) -> HashMap<String, String> {
+        options_to_map(self.0.options)
+    }
+}
+
+impl ForeignTable {
+    /// Panics if `relation` isn't a foreign table
+    pub fn from_relation(relation: &PgRelation) -> Self {
+        if !relation.is_foreign_table() {
+            panic!("\"{}\" is not a foreign table", relation.name());
+        }
+
+        let table = PgBox::from_pg(unsafe { pg_sys::GetForeignTable(relation.oid()) });
+        let server = ForeignServer::from_oid(table.serverid);
+        ForeignTable { table, server }
+    }
+
+    pub fn relid(&self) -> pg_sys::Oid {
+        self.table.relid
+    }
+
+    pub fn server(&self) -> &ForeignServer {
+        &self.server
+    }
+
+    /// The table's own options
+    pub fn options(&self) -> HashMap<String, String> {
+        options_to_map(self.table.options)
+    }
+
+    /// The server's user mapping for the current user, as determined by `GetUserId()`
+    pub fn user_mapping(&self) -> Result<UserMapping, UserMappingNotFound> {
+        self.server.user_mapping(unsafe { pg_sys::GetUserId() })
+    }
+
+    /// All the options that apply to this table, where, by convention, an option from a later
+    /// source overrides the same option from an earlier one.  In order, they are those of:
+    ///
+    /// 1. the foreign-data wrapper
+    /// 2. the server
+    /// 3. the current user's user mapping, if there is one
+    /// 4. the table itself
+    pub fn merged_options(&self) -> HashMap<String, String> {
+        let mut options = self.server.fdw_options();
+        options.extend(self.server.options());
+        if let Ok(user_mapping) = self.user_mapping() {
+            options.extend(user_mapping.options());
+        }
+        options.extend(self.options());
+        options
+    }
+}
+
+/// Convert a `List` of `DefElem`s, as from an `OPTIONS (...)` clause, to a `HashMap`
+fn options_to_map(options: *mut pg_sys::List) -> HashMap<String, String> {
+    PgList::<pg_sys::DefElem>::from_pg(options)
+        .iter_ptr()
+        .map(|def| unsafe {
+            let name = cstr_to_str((*def).defname).expect("option has no name");
+            let value = cstr_to_str(pg_sys::defGetString(def)).unwrap_or_default();
+            (name.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+unsafe fn cstr_to_str<'a>(s: *const std::os::raw::c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        Some(
+            std::ffi::CStr::from_ptr(s)
+                .to_str()
+                .expect("not a valid UTF8 string"),
+        )
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Per-function call statistics, for `#[pg_extern(instrument)]` functions.
+//!
+//! An instrumented function counts its calls, and how long they took and how much memory they
+//! allocated, in a table private to the backend, so there's no locking.  `fn_stats_functions!()`
+//! exports two SQL functions to go with it, which `cargo pgx schema` creates:
+//!
+//! - `pgx_fn_stats()`, which returns a row of `(name, calls, total_ms, mean_ms,
+//!    total_alloc_bytes)` for each function that's been called in this backend
+//! - `pgx_fn_stats_reset()`, which forgets them
+//!
+//! `snapshot()` and `reset()` do the same from Rust, whether or not the extension exports them.
+//!
+//! Functions without `instrument` don't pay anything for it.  An instrumented function pays for
+//! reading the clock twice and a hash table lookup each call, and on Postgres 13, for adding up
+//! the memory allocated by its memory context, so it's best left off of functions that are called
+//! millions of times a query.  `bench_instrument_overhead` in `pgx-tests` measures how much that
+//! is per call, and reports it as an INFO message.
+//!
+//! ## What's measured
+//!
+//! - A call is timed from the start of converting its arguments until its result is returned,
+//!   not counting the conversion of the result to a Datum.  A set-returning function is only timed
+//!   until it returns its iterator, and is counted once per call from SQL, not once per row
+//! - Memory is the growth of the memory context the function is called in, and its children,
+//!   which is how much Postgres allocated from the operating system for them rather than what was
+//!   `palloc()`'d, and so can be negative if the function freed memory.  **It's only tracked on
+//!   Postgres 13**, the first version to keep count of it, so `total_alloc_bytes` is NULL, and
+//!   `FnStats::total_alloc_bytes` is `None`, on Postgres 10, 11 and 12
+//! - A call that raises an ERROR is counted, up until the point it was raised
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//!
+//! fn_stats_functions!();
+//!
+//! #[pg_extern(instrument)]
+//! fn expensive(input: &str) -> String {
+//!     input.repeat(1000)
+//! }
+//! ```
+//!
+//! ```sql
+//! SELECT name, calls, mean_ms FROM pgx_fn_stats();
+//! ```
+use crate::{pg_sys, srf_materialize, IntoDatum};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The statistics of one instrumented function, in this backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FnStats {
+    pub calls: u64,
+    pub total_time: Duration,
+
+    /// Always `None` before Postgres 13, which don't keep track of memory context sizes
+    pub total_alloc_bytes: Option<i64>,
+}
+
+impl FnStats {
+    pub fn mean_time(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::default()
+        } else {
+            Duration::from_secs_f64(self.total_time.as_secs_f64() / self.calls as f64)
+        }
+    }
+}
+
+thread_local! {
+    static STATS: RefCell<HashMap<&'static str, FnStats>> = RefCell::new(HashMap::new());
+}
+
+/// The statistics of each instrumented function that's been called in this backend since the
+/// last `reset()`, sorted by name
+pub fn snapshot() -> Vec<(&'static str, FnStats)> {
+    let mut stats = STATS.with(|stats| {
+        stats
+            .borrow()
+            .iter()
+            .map(|(name, stats)| (*name, *stats))
+            .collect::<Vec<_>>()
+    });
+    stats.sort_by_key(|(name, _)| *name);
+    stats
+}
+
+/// The statistics of the instrumented function `name`, if it's been called
+pub fn get(name: &str) -> Option<FnStats> {
+    STATS.with(|stats| stats.borrow().get(name).copied())
+}
+
+/// Forget the statistics of every function in this backend
+pub fn reset() {
+    STATS.with(|stats| stats.borrow_mut().clear());
+}
+
+/// Times a call of an instrumented function, and records it when dropped.  The wrappers of
+/// `#[pg_extern(instrument)]` functions make one of these for each call
+#[must_use = "the call is recorded when the timer is dropped"]
+pub struct FnStatsTimer {
+    name: &'static str,
+    start: Instant,
+    context: pg_sys::MemoryContext,
+    allocated: Option<usize>,
+}
+
+impl FnStatsTimer {
+    pub fn start(name: &'static str) -> Self {
+        let context = unsafe { pg_sys::CurrentMemoryContext };
+        FnStatsTimer {
+            name,
+            allocated: mem_allocated(context),
+            context,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for FnStatsTimer {
+    fn drop(&mut self) {
+        // also called while unwinding from an ERROR, when the context is still around, as it's
+        // only cleaned up once the ERROR has been caught
+        let elapsed = self.start.elapsed();
+        let allocated = match (self.allocated, mem_allocated(self.context)) {
+            (Some(before), Some(after)) => Some(after as i64 - before as i64),
+            _ => None,
+        };
+
+        STATS.with(|stats| {
+            let mut stats = stats.borrow_mut();
+            let entry = stats.entry(self.name).or_insert_with(|| FnStats {
+                total_alloc_bytes: allocated.map(|_| 0),
+                ..Default::default()
+            });
+            entry.calls += 1;
+            entry.total_time += elapsed;
+            if let (Some(total), Some(allocated)) = (entry.total_alloc_bytes.as_mut(), allocated) {
+                *total += allocated;
+            }
+        });
+    }
+}
+
+#[cfg(feature = "pg13")]
+fn mem_allocated(context: pg_sys::MemoryContext) -> Option<usize> {
+    Some(unsafe { pg_sys::MemoryContextMemAllocated(context, true) })
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11", feature = "pg12"))]
+fn mem_allocated(_context: pg_sys::MemoryContext) -> Option<usize> {
+    None
+}
+
+/// Exports the C functions of the `pgx_fn_stats()` and `pgx_fn_stats_reset()` SQL functions,
+/// which `cargo pgx schema` creates in the schema of the module this is in.  An extension that
+/// doesn't use it doesn't export them, or create them.  It can only be used once per extension.
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// fn_stats_functions!();
+/// ```
+#[macro_export]
+macro_rules! fn_stats_functions {
+    () => {
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn pg_finfo_pgx_fn_stats_wrapper() -> &'static pgx::pg_sys::Pg_finfo_record {
+            const V1_API: pgx::pg_sys::Pg_finfo_record =
+                pgx::pg_sys::Pg_finfo_record { api_version: 1 };
+            &V1_API
+        }
+
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn pgx_fn_stats_wrapper(
+            fcinfo: pgx::pg_sys::FunctionCallInfo,
+        ) -> pgx::pg_sys::Datum {
+            pgx::pg_sys::guard::guard(|| pgx::fn_stats::pgx_fn_stats(fcinfo))
+        }
+
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn pg_finfo_pgx_fn_stats_reset_wrapper(
+        ) -> &'static pgx::pg_sys::Pg_finfo_record {
+            const V1_API: pgx::pg_sys::Pg_finfo_record =
+                pgx::pg_sys::Pg_finfo_record { api_version: 1 };
+            &V1_API
+        }
+
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn pgx_fn_stats_reset_wrapper(
+            fcinfo: pgx::pg_sys::FunctionCallInfo,
+        ) -> pgx::pg_sys::Datum {
+            pgx::pg_sys::guard::guard(|| pgx::fn_stats::pgx_fn_stats_reset(fcinfo))
+        }
+    };
+}
+
+/// The body of the `pgx_fn_stats()` SQL function, which `fn_stats_functions!()` exports
+#[doc(hidden)]
+pub fn pgx_fn_stats(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+    srf_materialize(fcinfo, snapshot(), |(name, stats), datums, nulls| {
+        let total_ms = stats.total_time.as_secs_f64() * 1000.0;
+        let mean_ms = stats.mean_time().as_secs_f64() * 1000.0;
+
+        datums[0] = name.into_datum().unwrap();
+        datums[1] = (stats.calls as i64).into_datum().unwrap();
+        datums[2] = total_ms.into_datum().unwrap();
+        datums[3] = mean_ms.into_datum().unwrap();
+        match stats.total_alloc_bytes {
+            Some(bytes) => datums[4] = bytes.into_datum().unwrap(),
+            None => nulls[4] = true,
+        }
+    })
+}
+
+/// The body of the `pgx_fn_stats_reset()` SQL function, which `fn_stats_functions!()` exports
+#[doc(hidden)]
+pub fn pgx_fn_stats_reset(_fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+    reset();
+    0
+}
@@ -2,10 +2,21 @@
 // governed by the MIT license that can be found in the LICENSE file.
 
 //! Provides a safe interface into Postgres' Configuration System (GUC)
-use crate::{pg_sys, PgMemoryContexts};
-use std::cell::Cell;
-use std::ffi::CStr;
+//!
+//! `GucRegistry` remembers each GUC it defines, so that `GucRegistry::iter()` can list this
+//! extension's GUCs, and their current values, without the rest of `pg_settings`.  Put
+//! `guc_settings_function!(my_ext_settings);` in the extension to have `cargo pgx schema` create
+//! a function returning the same thing as a table:
+//!
+//! ```sql
+//! SELECT name, setting, unit, context, short_desc FROM my_ext_settings();
+//! ```
+use crate::{pg_sys, srf_materialize, IntoDatum, PgCStr, PgLogLevel, PgMemoryContexts};
+use crate::{ErrorReport, PgSqlErrorCode};
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GucContext {
     /// cannot be set by the user at all, but only through
     /// internal processes ("server_version" is an example).  These are GUC
@@ -44,6 +55,21 @@ pub enum GucContext {
     Userset = pg_sys::GucContext_PGC_USERSET as isize,
 }
 
+impl GucContext {
+    /// The name of this context, as it's shown in `pg_settings.context`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GucContext::Internal => "internal",
+            GucContext::Postmaster => "postmaster",
+            GucContext::Sighup => "sighup",
+            GucContext::SuBackend => "superuser-backend",
+            GucContext::Backend => "backend",
+            GucContext::Suset => "superuser",
+            GucContext::Userset => "user",
+        }
+    }
+}
+
 pub trait GucEnum<T>
 where
     T: Copy,
@@ -137,8 +163,109 @@ where
     }
 }
 
+/// A GUC defined by `GucRegistry`, and its current value
+#[derive(Debug, Clone, PartialEq)]
+pub struct GucInfo {
+    pub name: String,
+
+    /// The current value, as `SHOW` would display it
+    pub setting: Option<String>,
+
+    /// The unit of `setting`, such as "ms" or "kB", if it has one
+    pub unit: Option<String>,
+    pub context: GucContext,
+    pub short_description: String,
+}
+
+struct GucDefinition {
+    name: String,
+    short_description: String,
+    context: GucContext,
+}
+
+thread_local! {
+    static DEFINED: RefCell<Vec<GucDefinition>> = RefCell::new(Vec::new());
+}
+
 pub struct GucRegistry {}
 impl GucRegistry {
+    /// The GUCs this extension has defined in this backend, sorted by name, with their current
+    /// values.
+    ///
+    /// A GUC that was set before the extension's library was loaded, such as in
+    /// `postgresql.conf` or with `SET`, is a placeholder until it's defined, at which point
+    /// Postgres gives it the value it was set to, so that's its value here too
+    pub fn iter() -> impl Iterator<Item = GucInfo> {
+        let mut gucs = DEFINED.with(|defined| {
+            defined
+                .borrow()
+                .iter()
+                .map(|guc| GucInfo {
+                    name: guc.name.clone(),
+                    setting: config_option(&guc.name),
+                    unit: config_option_unit(&guc.name),
+                    context: guc.context,
+                    short_description: guc.short_description.clone(),
+                })
+                .collect::<Vec<_>>()
+        });
+        gucs.sort_by(|a, b| a.name.cmp(&b.name));
+        gucs.into_iter()
+    }
+
+    /// Run every one of `checks`, typically from `_PG_init()` once the GUCs are defined, to
+    /// cross-check settings that depend on each other.  If any of them fail, a single ERROR is
+    /// raised listing all of their messages, rather than just the first.
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// static MIN_WORKERS: GucSetting<i32> = GucSetting::new(1);
+    /// static MAX_WORKERS: GucSetting<i32> = GucSetting::new(4);
+    ///
+    /// GucRegistry::validate_all(&[|| {
+    ///     if MIN_WORKERS.get() > MAX_WORKERS.get() {
+    ///         Err("my_ext.min_workers can't be more than my_ext.max_workers".to_string())
+    ///     } else {
+    ///         Ok(())
+    ///     }
+    /// }]);
+    /// ```
+    pub fn validate_all(checks: &[fn() -> Result<(), String>]) {
+        let violations = checks
+            .iter()
+            .filter_map(|check| check().err())
+            .collect::<Vec<_>>();
+
+        if !violations.is_empty() {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                format!(
+                    "{} of {} configuration checks failed",
+                    violations.len(),
+                    checks.len()
+                ),
+            )
+            .with_detail(violations.join("\n"))
+            .report(PgLogLevel::ERROR);
+        }
+    }
+
+    fn remember(name: &str, short_description: &str, context: GucContext) {
+        DEFINED.with(|defined| {
+            let mut defined = defined.borrow_mut();
+
+            // Postgres ignores a GUC that's defined again, so the first definition stands
+            if !defined.iter().any(|guc| guc.name == name) {
+                defined.push(GucDefinition {
+                    name: name.to_string(),
+                    short_description: short_description.to_string(),
+                    context,
+                });
+            }
+        });
+    }
+
     pub fn define_bool_guc(
         name: &str,
         short_description: &str,
@@ -146,6 +273,7 @@ impl GucRegistry {
         setting: &GucSetting<bool>,
         context: GucContext,
     ) {
+        GucRegistry::remember(name, short_description, context);
         unsafe {
             pg_sys::DefineCustomBoolVariable(
                 PgMemoryContexts::TopMemoryContext.pstrdup(name),
@@ -171,6 +299,7 @@ impl GucRegistry {
         max_value: i32,
         context: GucContext,
     ) {
+        GucRegistry::remember(name, short_description, context);
         unsafe {
             pg_sys::DefineCustomIntVariable(
                 PgMemoryContexts::TopMemoryContext.pstrdup(name),
@@ -196,6 +325,7 @@ impl GucRegistry {
         setting: &GucSetting<Option<&'static str>>,
         context: GucContext,
     ) {
+        GucRegistry::remember(name, short_description, context);
         unsafe {
             let boot_value = match setting.value.get() {
                 Some(s) => PgMemoryContexts::TopMemoryContext.pstrdup(s),
@@ -226,6 +356,7 @@ impl GucRegistry {
         max_value: f64,
         context: GucContext,
     ) {
+        GucRegistry::remember(name, short_description, context);
         unsafe {
             pg_sys::DefineCustomRealVariable(
                 PgMemoryContexts::TopMemoryContext.pstrdup(name),
@@ -253,6 +384,7 @@ impl GucRegistry {
     ) where
         T: GucEnum<T> + Copy,
     {
+        GucRegistry::remember(name, short_description, context);
         unsafe {
             pg_sys::DefineCustomEnumVariable(
                 PgMemoryContexts::TopMemoryContext.pstrdup(name),
@@ -270,3 +402,80 @@ impl GucRegistry {
         }
     }
 }
+
+/// The current value of the GUC `name`, or `None` if there is no such GUC.  Like `SHOW`, this
+/// raises an ERROR if the GUC is superuser-only and the current user isn't allowed to see it
+fn config_option(name: &str) -> Option<String> {
+    let name = CString::new(name).expect("GUC name contains a NUL byte");
+    unsafe { PgCStr::from_ptr(pg_sys::GetConfigOption(name.as_ptr(), true, true)) }
+        .map(|value| value.to_str_lossy().into_owned())
+}
+
+/// The unit of the GUC `name`, as `pg_settings.unit` shows it
+fn config_option_unit(name: &str) -> Option<String> {
+    let name = CString::new(name).expect("GUC name contains a NUL byte");
+    let flags = unsafe { pg_sys::GetConfigOptionFlags(name.as_ptr(), true) } as u32;
+
+    match flags & pg_sys::GUC_UNIT {
+        pg_sys::GUC_UNIT_KB => Some("kB".to_string()),
+        pg_sys::GUC_UNIT_BLOCKS => Some(format!("{}kB", pg_sys::BLCKSZ / 1024)),
+        pg_sys::GUC_UNIT_XBLOCKS => Some(format!("{}kB", pg_sys::XLOG_BLCKSZ / 1024)),
+        pg_sys::GUC_UNIT_MB => Some("MB".to_string()),
+        #[cfg(any(feature = "pg11", feature = "pg12", feature = "pg13"))]
+        pg_sys::GUC_UNIT_BYTE => Some("B".to_string()),
+        pg_sys::GUC_UNIT_MS => Some("ms".to_string()),
+        pg_sys::GUC_UNIT_S => Some("s".to_string()),
+        pg_sys::GUC_UNIT_MIN => Some("min".to_string()),
+        _ => None,
+    }
+}
+
+/// Declares a function, named `$name`, returning a row of `(name, setting, unit, context,
+/// short_desc)` for each GUC the extension has defined with `GucRegistry`, which is
+/// `GucRegistry::iter()` as a table.  `cargo pgx schema` creates it, in the schema of the module
+/// this is in, and this exports its C function.  It can only be used once per extension.
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// guc_settings_function!(my_ext_settings);
+/// ```
+#[macro_export]
+macro_rules! guc_settings_function {
+    ($name:ident) => {
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn pg_finfo_pgx_guc_settings_wrapper(
+        ) -> &'static pgx::pg_sys::Pg_finfo_record {
+            const V1_API: pgx::pg_sys::Pg_finfo_record =
+                pgx::pg_sys::Pg_finfo_record { api_version: 1 };
+            &V1_API
+        }
+
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn pgx_guc_settings_wrapper(
+            fcinfo: pgx::pg_sys::FunctionCallInfo,
+        ) -> pgx::pg_sys::Datum {
+            pgx::pg_sys::guard::guard(|| pgx::guc::pgx_guc_settings(fcinfo))
+        }
+    };
+}
+
+/// The body of the function declared by `guc_settings_function!()`, which it exports
+#[doc(hidden)]
+pub fn pgx_guc_settings(fcinfo: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+    srf_materialize(fcinfo, GucRegistry::iter(), |guc, datums, nulls| {
+        datums[0] = guc.name.into_datum().unwrap();
+        match guc.setting {
+            Some(setting) => datums[1] = setting.into_datum().unwrap(),
+            None => nulls[1] = true,
+        }
+        match guc.unit {
+            Some(unit) => datums[2] = unit.into_datum().unwrap(),
+            None => nulls[2] = true,
+        }
+        datums[3] = guc.context.as_str().into_datum().unwrap();
+        datums[4] = guc.short_description.into_datum().unwrap();
+    })
+}
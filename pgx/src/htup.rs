@@ -98,7 +98,7 @@ pub fn heap_getattr<T: FromDatum>(
     if is_null {
         None
     } else {
-        unsafe { T::from_datum(datum, false, typoid.value()) }
+        unsafe { T::from_datum(datum, false, typoid) }
     }
 }
 
@@ -167,13 +167,13 @@ pub fn heap_getattr_datum_ex(
     let mut typalign = 0 as std::os::raw::c_char; // unused
 
     unsafe {
-        pg_sys::get_typlenbyvalalign(typoid.value(), &mut typlen, &mut typbyval, &mut typalign);
+        pg_sys::get_typlenbyvalalign(typoid, &mut typlen, &mut typbyval, &mut typalign);
     }
 
     DatumWithTypeInfo {
         datum,
         is_null,
-        typoid,
+        typoid: PgOid::from(typoid),
         typlen,
         typbyval,
     }
@@ -54,3 +54,179 @@ pub trait JsonInOutFuncs<'de>: serde::de::Deserialize<'de> + serde::ser::Seriali
         serde_json::to_writer(buffer, self).expect("failed to serialize to json")
     }
 }
+
+/// `#[derive(PostgresType)]` types with the `#[copytextformat]` attribute implement this trait to
+/// provide a compact text representation for `COPY` pipelines, separate from their regular
+/// input/output functions.
+///
+/// Postgres has no per-type `COPY` hooks, so the derive generates a pair of SQL functions,
+/// `<type>_to_copy_text(<type>) RETURNS text` and `<type>_from_copy_text(text) RETURNS <type>`,
+/// which `copy_helper_view!()` uses to build a view to `COPY` through.
+pub trait CopyTextFormat {
+    /// Convert `Self` into its compact text form by writing to the supplied `StringInfo` buffer
+    fn to_copy_text(&self, buffer: &mut StringInfo);
+
+    /// Parse the compact text form produced by `to_copy_text()`.
+    ///
+    /// It is expected that malformed input will raise an `error!()` or `panic!()`
+    fn from_copy_text(input: &str) -> Self
+    where
+        Self: Sized;
+}
+
+/// Create a view over `table` that presents every column of type `T` in `T`'s compact
+/// `CopyTextFormat` representation, and an `INSTEAD OF INSERT` trigger that converts those
+/// columns back.  All other columns pass through unchanged.
+///
+/// Export with `COPY (SELECT * FROM view) TO ...` and import with `COPY view FROM ...`.  The view
+/// only supports `INSERT`; rows can't be updated or deleted through it.
+///
+/// The view is created in `table`'s schema, named `view_name` or, if `None`, `<table>_copy`.
+/// The trigger function is named after the view, with an `_insert` suffix.  Returns the view's
+/// schema-qualified name.
+///
+/// Usually called through the `copy_helper_view!()` macro.
+///
+/// ## Panics
+///
+/// If `table` doesn't exist or has no columns of type `T`
+pub fn create_copy_helper_view<T: PostgresType + IntoDatum + CopyTextFormat>(
+    table: &str,
+    view_name: Option<&str>,
+) -> String {
+    ensure_not_in_recovery("CREATE VIEW");
+
+    let relid = unsafe {
+        direct_function_call::<pg_sys::Oid>(pg_sys::to_regclass, vec![table.into_datum()])
+    }
+    .unwrap_or_else(|| panic!("relation \"{}\" does not exist", table));
+    let typoid = T::type_oid();
+
+    // the functions generated by #[derive(PostgresType)] live alongside the type
+    let (type_name, to_copy_text, from_copy_text) =
+        Spi::get_three_with_args::<String, String, String>(
+            "SELECT format_type(t.oid, NULL),
+                    format('%I.%I', n.nspname, t.typname || '_to_copy_text'),
+                    format('%I.%I', n.nspname, t.typname || '_from_copy_text')
+               FROM pg_type t
+               JOIN pg_namespace n ON n.oid = t.typnamespace
+              WHERE t.oid = $1",
+            vec![(PgBuiltInOids::OIDOID.oid(), typoid.into_datum())],
+        );
+    let type_name = type_name.expect("CopyTextFormat type does not exist");
+    let to_copy_text = to_copy_text.expect("CopyTextFormat type does not exist");
+    let from_copy_text = from_copy_text.expect("CopyTextFormat type does not exist");
+
+    let (qualified_table, view, trigger_function) =
+        Spi::get_three_with_args::<String, String, String>(
+            "SELECT $1::regclass::text,
+                format('%I.%I', n.nspname, coalesce($2, c.relname || '_copy')),
+                format('%I.%I', n.nspname, coalesce($2, c.relname || '_copy') || '_insert')
+           FROM pg_class c
+           JOIN pg_namespace n ON n.oid = c.relnamespace
+          WHERE c.oid = $1",
+            vec![
+                (PgBuiltInOids::OIDOID.oid(), relid.into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), view_name.into_datum()),
+            ],
+        );
+    let qualified_table = qualified_table.expect("table name is NULL");
+    let view = view.expect("view name is NULL");
+    let trigger_function = trigger_function.expect("trigger function name is NULL");
+
+    let (select_list, column_list, values_list) = Spi::get_three_with_args::<
+        String,
+        String,
+        String,
+    >(
+        "SELECT string_agg(CASE WHEN atttypid = $2 THEN format('%s(%I) AS %I', $3, attname, attname)
+                                ELSE format('%I', attname) END, ', ' ORDER BY attnum),
+                string_agg(format('%I', attname), ', ' ORDER BY attnum),
+                string_agg(CASE WHEN atttypid = $2 THEN format('%s(NEW.%I)', $4, attname)
+                                ELSE format('NEW.%I', attname) END, ', ' ORDER BY attnum)
+           FROM pg_attribute
+          WHERE attrelid = $1
+            AND attnum > 0
+            AND NOT attisdropped
+         HAVING bool_or(atttypid = $2)",
+        vec![
+            (PgBuiltInOids::OIDOID.oid(), relid.into_datum()),
+            (PgBuiltInOids::OIDOID.oid(), typoid.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), to_copy_text.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), from_copy_text.into_datum()),
+        ],
+    );
+    let select_list = select_list.unwrap_or_else(|| {
+        panic!(
+            "relation \"{}\" has no columns of type {}",
+            table, type_name
+        )
+    });
+    let column_list = column_list.expect("column list is NULL");
+    let values_list = values_list.expect("values list is NULL");
+
+    Spi::run(&format!(
+        "CREATE VIEW {} AS SELECT {} FROM {}",
+        view, select_list, qualified_table
+    ));
+    Spi::run(&format!(
+        "CREATE FUNCTION {}() RETURNS trigger LANGUAGE plpgsql AS $pgx$
+         BEGIN
+             INSERT INTO {} ({}) VALUES ({});
+             RETURN NEW;
+         END;
+         $pgx$",
+        trigger_function, qualified_table, column_list, values_list
+    ));
+    Spi::run(&format!(
+        "CREATE TRIGGER copy_helper_insert INSTEAD OF INSERT ON {} FOR EACH ROW EXECUTE PROCEDURE {}()",
+        view, trigger_function
+    ));
+
+    view
+}
+
+/// Create a view for `COPY`ing a table with columns of a `#[copytextformat]` type in the type's
+/// compact `CopyTextFormat` representation.  See `create_copy_helper_view()`.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, PostgresType)]
+/// #[copytextformat]
+/// pub struct Point {
+///     horizontal: i32,
+///     vertical: i32,
+/// }
+///
+/// impl CopyTextFormat for Point {
+///     fn to_copy_text(&self, buffer: &mut StringInfo) {
+///         buffer.push_str(&format!("{},{}", self.horizontal, self.vertical));
+///     }
+///
+///     fn from_copy_text(input: &str) -> Self {
+///         let mut parts = input.split(',').map(|part| part.parse().expect("invalid Point"));
+///         Point {
+///             horizontal: parts.next().expect("missing horizontal"),
+///             vertical: parts.next().expect("missing vertical"),
+///         }
+///     }
+/// }
+///
+/// // creates "points_copy", whose Point columns are text like "1,2" instead of JSON
+/// let view = copy_helper_view!(Point, "points");
+/// Spi::run(&format!("COPY (SELECT * FROM {}) TO '/tmp/points.copy'", view));
+/// Spi::run(&format!("COPY {} FROM '/tmp/points.copy'", view));
+/// ```
+#[macro_export]
+macro_rules! copy_helper_view {
+    ($type:ty, $table:expr) => {
+        $crate::create_copy_helper_view::<$type>($table, None)
+    };
+    ($type:ty, $table:expr, $view_name:expr) => {
+        $crate::create_copy_helper_view::<$type>($table, Some($view_name))
+    };
+}
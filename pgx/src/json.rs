@@ -0,0 +1,186 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Conversion of rows, and the Datums in them, to `serde_json::Value`s, for logging changed
+//! rows, debugging, and exporting data.
+//!
+//! Each value is converted based on its type:
+//!
+//! - `bool` is a JSON boolean
+//! - `int2`, `int4`, `int8`, `oid`, `float4`, `float8`, and `numeric` are JSON numbers, except
+//!   `NaN` and the infinities, which are strings.  Note that a `numeric` with more precision than
+//!   an `f64` loses it
+//! - `json` and `jsonb` are their parsed values
+//! - arrays are JSON arrays, nested for multidimensional arrays
+//! - composite types and records are JSON objects
+//! - domains are converted as their base type
+//! - everything else is a string, from its type's text output function
+//!
+//! SQL `NULL`s are JSON `null`s.
+use crate::{
+    composite_row_type_make_tuple, heap_getattr_raw, pg_sys, FromDatum, Json, JsonB, PgTupleDesc,
+};
+use serde_json::{Map, Number, Value};
+use std::ffi::CStr;
+
+/// Convert a row, as the `values` of each attribute of `tupdesc`, to a JSON object keyed by the
+/// attribute names.  Dropped attributes are skipped.
+///
+/// The object's keys are ordered as `serde_json::Map` orders them, not by attribute number, and if
+/// two attributes share a name, such as in the result of a join, the last one wins.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// let relation = PgRelation::open_with_name_and_share_lock("my_table").unwrap();
+/// let tupdesc = relation.tuple_desc();
+/// for row in relation.scan_rows() {
+///     info!("{}", unsafe { pgx::json::row_to_json(&tupdesc, &row) });
+/// }
+/// ```
+///
+/// ## Safety
+///
+/// This function is unsafe as it cannot guarantee that each of `values` is actually of its
+/// attribute's type
+///
+/// ## Panics
+///
+/// This function will panic if there isn't exactly one value for each attribute of `tupdesc`
+pub unsafe fn row_to_json(tupdesc: &PgTupleDesc, values: &[Option<pg_sys::Datum>]) -> Value {
+    if values.len() != tupdesc.len() {
+        panic!(
+            "row_to_json() was given {} values for a tuple descriptor with {} attributes",
+            values.len(),
+            tupdesc.len()
+        );
+    }
+
+    let mut object = Map::new();
+    for (attr, value) in tupdesc.iter_all().zip(values) {
+        if !attr.is_dropped() {
+            let value = datum_to_json(*value, attr.type_oid());
+            object.insert(attr.name().to_string(), value);
+        }
+    }
+    Value::Object(object)
+}
+
+/// Convert a single `datum`, of type `typoid`, to JSON, as `row_to_json()` does for each of its
+/// values.  A `None` is a JSON `null`.
+///
+/// ## Safety
+///
+/// This function is unsafe as it cannot guarantee that `datum` is actually of type `typoid`
+pub unsafe fn datum_to_json(datum: Option<pg_sys::Datum>, typoid: pg_sys::Oid) -> Value {
+    let datum = match datum {
+        Some(datum) => datum,
+        None => return Value::Null,
+    };
+
+    let typoid = pg_sys::getBaseType(typoid);
+    match typoid {
+        pg_sys::BOOLOID => Value::Bool(bool::from_datum(datum, false, typoid).unwrap()),
+        pg_sys::INT2OID => Value::from(i16::from_datum(datum, false, typoid).unwrap()),
+        pg_sys::INT4OID => Value::from(i32::from_datum(datum, false, typoid).unwrap()),
+        pg_sys::INT8OID => Value::from(i64::from_datum(datum, false, typoid).unwrap()),
+        pg_sys::OIDOID => Value::from(u32::from_datum(datum, false, typoid).unwrap()),
+
+        // their text output is already the shortest that round-trips, which a JSON number
+        // parsed from it keeps
+        pg_sys::FLOAT4OID | pg_sys::FLOAT8OID | pg_sys::NUMERICOID => {
+            let text = output_text(datum, typoid);
+            match serde_json::from_str::<Number>(&text) {
+                Ok(number) => Value::Number(number),
+
+                // NaN, Infinity, and -Infinity
+                Err(_) => Value::String(text),
+            }
+        }
+
+        pg_sys::JSONOID => Json::from_datum(datum, false, typoid).unwrap().0,
+        pg_sys::JSONBOID => JsonB::from_datum(datum, false, typoid).unwrap().0,
+
+        _ if pg_sys::type_is_rowtype(typoid) => composite_to_json(datum),
+        _ => match pg_sys::get_element_type(typoid) {
+            pg_sys::InvalidOid => Value::String(output_text(datum, typoid)),
+            elemtype => array_to_json(datum, elemtype),
+        },
+    }
+}
+
+unsafe fn composite_to_json(datum: pg_sys::Datum) -> Value {
+    let tupdesc = PgTupleDesc::from_composite(datum);
+    let tuple = composite_row_type_make_tuple(datum);
+    let values = (1..=tupdesc.len())
+        .map(|attno| heap_getattr_raw(tuple.as_ptr(), attno, tupdesc.as_ptr()))
+        .collect::<Vec<_>>();
+
+    row_to_json(&tupdesc, &values)
+}
+
+unsafe fn array_to_json(datum: pg_sys::Datum, elemtype: pg_sys::Oid) -> Value {
+    let array = pg_sys::pg_detoast_datum(datum as *mut pg_sys::varlena) as *mut pg_sys::ArrayType;
+
+    // ARR_DIMS(): the length of each dimension immediately follows the ArrayType header
+    let ndim = (*array).ndim as usize;
+    let dims = std::slice::from_raw_parts(
+        (array as *const u8).add(std::mem::size_of::<pg_sys::ArrayType>()) as *const i32,
+        ndim,
+    );
+
+    // outvals for get_typlenbyvalalign()
+    let mut typlen = 0;
+    let mut typbyval = false;
+    let mut typalign = 0;
+    pg_sys::get_typlenbyvalalign(elemtype, &mut typlen, &mut typbyval, &mut typalign);
+
+    // outvals for deconstruct_array()
+    let mut elements = std::ptr::null_mut();
+    let mut nulls = std::ptr::null_mut();
+    let mut nelems = 0;
+    pg_sys::deconstruct_array(
+        array,
+        elemtype,
+        typlen as i32,
+        typbyval,
+        typalign,
+        &mut elements,
+        &mut nulls,
+        &mut nelems,
+    );
+
+    let elements = std::slice::from_raw_parts(elements, nelems as usize);
+    let nulls = std::slice::from_raw_parts(nulls, nelems as usize);
+    let mut values = elements.iter().zip(nulls).map(|(element, is_null)| {
+        datum_to_json(if *is_null { None } else { Some(*element) }, elemtype)
+    });
+
+    nest(&mut values, dims)
+}
+
+/// Split the elements of a multidimensional array, in row-major order, into nested JSON arrays
+fn nest<I: Iterator<Item = Value>>(elements: &mut I, dims: &[i32]) -> Value {
+    match dims.split_first() {
+        // an empty array has no dimensions
+        None => Value::Array(Vec::new()),
+        Some((len, [])) => Value::Array(elements.by_ref().take(*len as usize).collect()),
+        Some((len, inner)) => Value::Array((0..*len).map(|_| nest(elements, inner)).collect()),
+    }
+}
+
+unsafe fn output_text(datum: pg_sys::Datum, typoid: pg_sys::Oid) -> String {
+    let mut output_func = pg_sys::InvalidOid;
+    let mut is_varlena = false;
+    pg_sys::getTypeOutputInfo(typoid, &mut output_func, &mut is_varlena);
+
+    let text = pg_sys::OidOutputFunctionCall(output_func, datum);
+    let result = CStr::from_ptr(text)
+        .to_str()
+        .expect("text output is not valid UTF8")
+        .to_string();
+    pg_sys::pfree(text as *mut _);
+    result
+}
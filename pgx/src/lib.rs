@@ -30,59 +30,104 @@ extern crate bitflags;
 pub use pgx_macros::*;
 
 pub mod callbacks;
+pub mod cast;
+pub mod corruption;
+pub mod costing;
+pub mod cstr;
 pub mod datum;
+pub mod deadline;
+pub mod deps;
 pub mod enum_helper;
+pub mod error_context;
 pub mod fcinfo;
+pub mod fdw;
+pub mod fn_stats;
 pub mod guc;
 pub mod hooks;
 pub mod htup;
 pub mod inoutfuncs;
 pub mod itemptr;
+pub mod json;
 pub mod list;
 #[macro_use]
 pub mod log;
 pub mod atomics;
 pub mod bgworkers;
+pub mod build_info;
+pub mod lock;
 pub mod lwlock;
 pub mod memcxt;
 pub mod misc;
 pub mod namespace;
 pub mod nodes;
+#[cfg(any(feature = "pg11", feature = "pg12", feature = "pg13"))]
+pub mod partition;
 pub mod pgbox;
+pub mod planner;
+pub mod quote;
+pub mod recovery;
 pub mod rel;
+pub mod retry;
+pub mod session_state;
 pub mod shmem;
+pub mod slot;
+pub mod sortsupport;
 pub mod spi;
+pub mod spinlock;
 pub mod stringinfo;
+pub mod systable;
+pub mod tableam;
+pub mod temp;
 pub mod trigger_support;
 pub mod tupdesc;
 pub mod varlena;
+pub mod wal;
+pub mod window;
 pub mod wrappers;
+pub mod xact;
 pub mod xid;
 
 pub use atomics::*;
+pub use build_info::*;
 pub use callbacks::*;
+pub use corruption::*;
+pub use cstr::*;
 pub use datum::*;
+pub use deps::*;
 pub use enum_helper::*;
 pub use fcinfo::*;
+pub use fdw::*;
 pub use guc::*;
 pub use hooks::*;
 pub use htup::*;
 pub use inoutfuncs::*;
 pub use itemptr::*;
 pub use list::*;
+pub use lock::*;
 pub use log::*;
 pub use lwlock::*;
 pub use memcxt::*;
 pub use namespace::*;
 pub use nodes::*;
+#[cfg(any(feature = "pg11", feature = "pg12", feature = "pg13"))]
+pub use partition::*;
 pub use pgbox::*;
+pub use planner::*;
+pub use recovery::*;
 pub use rel::*;
+pub use retry::*;
 pub use shmem::*;
+pub use slot::*;
+pub use sortsupport::*;
 pub use spi::*;
+pub use spinlock::*;
 pub use stringinfo::*;
+pub use systable::*;
+pub use tableam::*;
 pub use trigger_support::*;
 pub use tupdesc::*;
 pub use varlena::*;
+pub use window::*;
 pub use wrappers::*;
 pub use xid::*;
 
@@ -90,6 +135,41 @@ pub use pgx_pg_sys as pg_sys; // the module only, not its contents
 pub use pgx_pg_sys::submodules::*;
 pub use pgx_pg_sys::PgBuiltInOids; // reexport this so it looks like it comes from here
 
+/// Builtin type names are checked when they're compiled:
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// assert_eq!(sql_type!("integer"), PgBuiltInOids::INT4OID);
+/// assert_eq!(sql_type!("timestamp with time zone"), PgBuiltInOids::TIMESTAMPTZOID);
+///
+/// // types that aren't builtin are looked up when they're used
+/// let hstore: SqlTypeLookup = sql_type!(lookup "public.hstore");
+/// ```
+///
+/// so a typo doesn't compile:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// let oid = sql_type!("timestamp with timezone");
+/// ```
+///
+/// and neither do the `serial` types, which aren't types:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// let oid = sql_type!("serial");
+/// ```
+pub use pgx_pg_sys::sql_type;
+
+#[cfg(feature = "bitvec")]
+pub use bitvec; // the version `PgBitString` converts to and from
+
+#[cfg(feature = "network")]
+pub use ipnetwork; // the version `IpNetwork` converts to and from
+
 /// A macro for marking a library compatible with the Postgres extension framework.
 ///
 /// This macro was initially inspired from the `pg_module` macro in https://github.com/thehydroimpulse/postgres-extension.rs
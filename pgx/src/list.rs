@@ -7,6 +7,7 @@
 //! understandings of Lists of Oids, Integers, and Pointers.
 
 use crate::{is_a, pg_sys, void_mut_ptr};
+use std::collections::HashSet;
 use std::marker::PhantomData;
 
 pub struct PgList<T> {
@@ -46,6 +47,72 @@ impl<T> PgList<T> {
         self.list
     }
 
+    /// A shallow copy of this list, allocated in the `CurrentMemoryContext`.
+    ///
+    /// The copy has its own cells, so it can be added to, removed from, or reordered without
+    /// affecting this list, but for a list of pointers both lists point to the **same** elements.
+    /// Modifying an element through one list modifies it in the other.  Use `copy_deep()` to copy
+    /// the elements too.
+    ///
+    /// Like a list made with `PgList::new()`, the copy is freed when it's dropped, unless it's
+    /// given to Postgres with `into_pg()`.
+    pub fn copy(&self) -> PgList<T> {
+        PgList {
+            list: unsafe { pg_sys::list_copy(self.list) },
+            allocated_by_pg: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// A deep copy of this list, allocated in the `CurrentMemoryContext`, made with Postgres'
+    /// `copyObject()`.
+    ///
+    /// Each element is recursively copied, so the copy shares nothing with this list and can be
+    /// freely modified, such as when rewriting part of a plan or parse tree in a hook.  It's only
+    /// for lists of `Node`s, which `copyObject()` knows how to copy, and for lists of ints and oids
+    /// `copy()` already copies their values.
+    ///
+    /// Like a list made with `PgList::new()`, the copy's cells are freed when it's dropped, unless
+    /// it's given to Postgres with `into_pg()`.  The copied elements are not freed.
+    pub fn copy_deep(&self) -> PgList<T>
+    where
+        T: pg_sys::PgNode,
+    {
+        PgList {
+            list: unsafe { pg_sys::copyObjectImpl(self.list as *const _) as *mut pg_sys::List },
+            allocated_by_pg: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Split this list in two at position `i`, like `slice::split_at()`:  the first list has the
+    /// elements before `i` and the second has the rest.  Both are shallow copies, allocated in
+    /// the `CurrentMemoryContext`, and this list is left as it is.
+    ///
+    /// If `i` is zero the first list is empty, and if it's `len()` or more, the second is empty.
+    pub fn split_at(&self, i: usize) -> (PgList<T>, PgList<T>) {
+        let i = i.min(self.len());
+        let head = if i == 0 {
+            std::ptr::null_mut()
+        } else {
+            unsafe { pg_sys::list_truncate(pg_sys::list_copy(self.list), i as i32) }
+        };
+        let tail = unsafe { pg_sys::list_copy_tail(self.list, i as i32) };
+
+        (
+            PgList {
+                list: head,
+                allocated_by_pg: false,
+                _marker: PhantomData,
+            },
+            PgList {
+                list: tail,
+                allocated_by_pg: false,
+                _marker: PhantomData,
+            },
+        )
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         if self.list.is_null() {
@@ -55,6 +122,17 @@ impl<T> PgList<T> {
         }
     }
 
+    /// The length as the `int` Postgres' List functions take, for passing it back to them.  Use
+    /// `len()` for anything else.
+    #[inline]
+    pub fn len_i32(&self) -> i32 {
+        if self.list.is_null() {
+            0
+        } else {
+            unsafe { self.list.as_ref() }.unwrap().length
+        }
+    }
+
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
@@ -71,10 +149,10 @@ impl<T> PgList<T> {
 
     #[inline]
     pub fn tail(&self) -> Option<*mut T> {
-        if self.list.is_null() {
+        if self.is_empty() {
             None
         } else {
-            Some(unsafe { pg_sys::pgx_list_nth(self.list, (self.len() - 1) as i32) } as *mut T)
+            Some(unsafe { pg_sys::pgx_list_nth(self.list, self.len_i32() - 1) } as *mut T)
         }
     }
 
@@ -116,59 +194,95 @@ impl<T> PgList<T> {
         }
     }
 
-    #[cfg(not(feature = "pg13"))]
+    /// Replace the element at position `i`
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is out of bounds, or if this isn't a List of pointers
     #[inline]
     pub fn replace_ptr(&mut self, i: usize, with: *mut T) {
-        unsafe {
-            let cell = pg_sys::pgx_list_nth_cell(self.list, i as i32);
+        let cell = self.nth_cell(i, pg_sys::NodeTag_T_List, "pointers");
 
-            cell.as_mut().expect("cell is null").data.ptr_value = with as void_mut_ptr;
+        #[cfg(not(feature = "pg13"))]
+        {
+            cell.data.ptr_value = with as void_mut_ptr;
         }
-    }
-
-    #[cfg(feature = "pg13")]
-    #[inline]
-    pub fn replace_ptr(&mut self, i: usize, with: *mut T) {
-        unsafe {
-            let cell = pg_sys::pgx_list_nth_cell(self.list, i as i32);
 
-            cell.as_mut().expect("cell is null").ptr_value = with as void_mut_ptr;
+        #[cfg(feature = "pg13")]
+        {
+            cell.ptr_value = with as void_mut_ptr;
         }
     }
 
-    #[cfg(not(feature = "pg13"))]
+    /// Replace the element at position `i` of this integer List
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is out of bounds, or if this isn't a List of ints
     #[inline]
     pub fn replace_int(&mut self, i: usize, with: i32) {
-        unsafe {
-            let cell = pg_sys::pgx_list_nth_cell(self.list, i as i32);
-            cell.as_mut().expect("cell is null").data.int_value = with;
+        let cell = self.nth_cell(i, pg_sys::NodeTag_T_IntList, "ints");
+
+        #[cfg(not(feature = "pg13"))]
+        {
+            cell.data.int_value = with;
         }
-    }
 
-    #[cfg(feature = "pg13")]
-    #[inline]
-    pub fn replace_int(&mut self, i: usize, with: i32) {
-        unsafe {
-            let cell = pg_sys::pgx_list_nth_cell(self.list, i as i32);
-            cell.as_mut().expect("cell is null").int_value = with;
+        #[cfg(feature = "pg13")]
+        {
+            cell.int_value = with;
         }
     }
 
-    #[cfg(not(feature = "pg13"))]
+    /// Replace the element at position `i` of this Oid List
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is out of bounds, or if this isn't a List of oids
     #[inline]
     pub fn replace_oid(&mut self, i: usize, with: pg_sys::Oid) {
-        unsafe {
-            let cell = pg_sys::pgx_list_nth_cell(self.list, i as i32);
-            cell.as_mut().expect("cell is null").data.oid_value = with;
+        let cell = self.nth_cell(i, pg_sys::NodeTag_T_OidList, "oids");
+
+        #[cfg(not(feature = "pg13"))]
+        {
+            cell.data.oid_value = with;
+        }
+
+        #[cfg(feature = "pg13")]
+        {
+            cell.oid_value = with;
         }
     }
 
-    #[cfg(feature = "pg13")]
+    /// Look up the cell at position `i`, checking that it exists first, as `list_nth_cell()`
+    /// walks off the end of a linked List before Postgres 13, and off the end of the cell array
+    /// on Postgres 13.
+    ///
+    /// The cell must be looked up again after the List is modified:  on Postgres 13, a List's
+    /// cells are an array that's moved as it grows, or shifted when elements are inserted or
+    /// removed, so a cell pointer doesn't survive modifying the List.
     #[inline]
-    pub fn replace_oid(&mut self, i: usize, with: pg_sys::Oid) {
+    fn nth_cell(
+        &mut self,
+        i: usize,
+        tag: pg_sys::NodeTag,
+        contains: &str,
+    ) -> &mut pg_sys::ListCell {
+        if i >= self.len() {
+            panic!(
+                "index {} is out of bounds for a List of length {}",
+                i,
+                self.len()
+            );
+        }
+        if !is_a(self.list as *mut pg_sys::Node, tag) {
+            panic!("PgList does not contain {}", contains)
+        }
+
         unsafe {
-            let cell = pg_sys::pgx_list_nth_cell(self.list, i as i32);
-            cell.as_mut().expect("cell is null").oid_value = with;
+            pg_sys::pgx_list_nth_cell(self.list, i as i32)
+                .as_mut()
+                .expect("cell is null")
         }
     }
 
@@ -188,6 +302,42 @@ impl<T> PgList<T> {
         }
     }
 
+    /// Collect the oids of this List into a `HashSet`, for when it's probed for membership
+    /// repeatedly, such as checking each range table entry against a set of relations.
+    ///
+    /// The cells are walked directly, rather than looked up one at a time as `iter_oid()` does,
+    /// which walks a linked List from its head for every element before Postgres 13.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this isn't a List of oids
+    pub fn to_oid_set(&self) -> HashSet<pg_sys::Oid> {
+        let mut set = HashSet::with_capacity(self.len());
+        if self.is_empty() {
+            return set;
+        }
+        if !is_a(self.list as *mut pg_sys::Node, pg_sys::NodeTag_T_OidList) {
+            panic!("PgList does not contain oids")
+        }
+
+        #[cfg(not(feature = "pg13"))]
+        unsafe {
+            let mut cell = (*self.list).head;
+            while !cell.is_null() {
+                set.insert((*cell).data.oid_value);
+                cell = (*cell).next;
+            }
+        }
+
+        #[cfg(feature = "pg13")]
+        unsafe {
+            let cells = std::slice::from_raw_parts((*self.list).elements, self.len());
+            set.extend(cells.iter().map(|cell| cell.oid_value));
+        }
+
+        set
+    }
+
     #[inline]
     pub fn iter_int(&self) -> impl Iterator<Item = i32> + '_ {
         PgListIteratorInt {
@@ -201,14 +351,114 @@ impl<T> PgList<T> {
         self.list = unsafe { pg_sys::lappend(self.list, ptr as void_mut_ptr) };
     }
 
+    /// Insert `ptr` so that it becomes the element at position `i`, shifting the elements
+    /// after it to the right.  Inserting at `len()` is the same as `push()`.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is greater than `len()`
+    #[cfg(not(feature = "pg13"))]
+    pub fn insert_ptr(&mut self, i: usize, ptr: *mut T) {
+        self.check_insert_position(i);
+        unsafe {
+            if i == 0 {
+                self.list = pg_sys::lcons(ptr as void_mut_ptr, self.list);
+            } else {
+                let prev = pg_sys::pgx_list_nth_cell(self.list, (i - 1) as i32);
+                pg_sys::lappend_cell(self.list, prev, ptr as void_mut_ptr);
+            }
+        }
+    }
+
+    /// Insert `ptr` so that it becomes the element at position `i`, shifting the elements
+    /// after it to the right.  Inserting at `len()` is the same as `push()`.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is greater than `len()`
+    #[cfg(feature = "pg13")]
+    pub fn insert_ptr(&mut self, i: usize, ptr: *mut T) {
+        self.check_insert_position(i);
+        self.list = unsafe { pg_sys::list_insert_nth(self.list, i as i32, ptr as void_mut_ptr) };
+    }
+
+    /// Insert `value` so that it becomes the element at position `i` of this integer List
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is greater than `len()`
+    #[cfg(not(feature = "pg13"))]
+    pub fn insert_int(&mut self, i: usize, value: i32) {
+        self.check_insert_position(i);
+        unsafe {
+            if i == 0 {
+                self.list = pg_sys::lcons_int(value, self.list);
+            } else {
+                let prev = pg_sys::pgx_list_nth_cell(self.list, (i - 1) as i32);
+                pg_sys::lappend_cell_int(self.list, prev, value);
+            }
+        }
+    }
+
+    /// Insert `value` so that it becomes the element at position `i` of this integer List
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is greater than `len()`
+    #[cfg(feature = "pg13")]
+    pub fn insert_int(&mut self, i: usize, value: i32) {
+        self.check_insert_position(i);
+        self.list = unsafe { pg_sys::list_insert_nth_int(self.list, i as i32, value) };
+    }
+
+    /// Insert `oid` so that it becomes the element at position `i` of this Oid List
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is greater than `len()`
+    #[cfg(not(feature = "pg13"))]
+    pub fn insert_oid(&mut self, i: usize, oid: pg_sys::Oid) {
+        self.check_insert_position(i);
+        unsafe {
+            if i == 0 {
+                self.list = pg_sys::lcons_oid(oid, self.list);
+            } else {
+                let prev = pg_sys::pgx_list_nth_cell(self.list, (i - 1) as i32);
+                pg_sys::lappend_cell_oid(self.list, prev, oid);
+            }
+        }
+    }
+
+    /// Insert `oid` so that it becomes the element at position `i` of this Oid List
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `i` is greater than `len()`
+    #[cfg(feature = "pg13")]
+    pub fn insert_oid(&mut self, i: usize, oid: pg_sys::Oid) {
+        self.check_insert_position(i);
+        self.list = unsafe { pg_sys::list_insert_nth_oid(self.list, i as i32, oid) };
+    }
+
     #[inline]
-    pub fn pop(&mut self) -> Option<*mut T> {
-        let tail = self.tail();
+    fn check_insert_position(&self, i: usize) {
+        if i > self.len() {
+            panic!(
+                "insert position {} is out of bounds for a List of length {}",
+                i,
+                self.len()
+            );
+        }
+    }
 
-        if tail.is_some() {
-            self.list = unsafe { pg_sys::list_truncate(self.list, (self.len() - 1) as i32) };
+    #[inline]
+    pub fn pop(&mut self) -> Option<*mut T> {
+        if self.is_empty() {
+            return None;
         }
 
+        let tail = self.tail();
+        self.list = unsafe { pg_sys::list_truncate(self.list, self.len_i32() - 1) };
         tail
     }
 }
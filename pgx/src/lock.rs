@@ -0,0 +1,181 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Heavyweight locks that don't wait indefinitely, for maintenance jobs that would rather skip
+//! a busy object than queue up behind whoever is using it.
+//!
+//! `PgRelation::try_with_lock()` and `PgRelation::with_lock_timeout()` open relations this way,
+//! and `try_lock_object()` locks anything else a `PgLockTag` can name, such as advisory locks.
+use crate::{pg_sys, pg_try, PgSqlErrorCode};
+use std::time::Duration;
+
+/// Another transaction holds a conflicting lock, and we didn't wait (long enough) for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldBlock;
+
+impl std::fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not obtain lock")
+    }
+}
+
+impl std::error::Error for WouldBlock {}
+
+/// Names an object to lock, as a Postgres `LOCKTAG` does
+#[derive(Debug, Clone, Copy)]
+pub struct PgLockTag(pg_sys::LOCKTAG);
+
+impl PgLockTag {
+    /// A database object other than a relation, in the current database, as
+    /// `LockDatabaseObject()` locks it.  `classid` is the oid of its catalog, such as
+    /// `pg_sys::TypeRelationId`
+    pub fn object(classid: pg_sys::Oid, objid: pg_sys::Oid, objsubid: u16) -> Self {
+        PgLockTag(pg_sys::LOCKTAG {
+            locktag_field1: unsafe { pg_sys::MyDatabaseId },
+            locktag_field2: classid,
+            locktag_field3: objid,
+            locktag_field4: objsubid,
+            locktag_type: pg_sys::LockTagType_LOCKTAG_OBJECT as u8,
+            locktag_lockmethodid: pg_sys::DEFAULT_LOCKMETHOD as u8,
+        })
+    }
+
+    /// The advisory lock on `key` in the current database, the same one `pg_advisory_lock(key)`
+    /// takes
+    pub fn advisory(key: i64) -> Self {
+        PgLockTag::advisory_tag((key >> 32) as u32, key as u32, 1)
+    }
+
+    /// The advisory lock on `(key1, key2)` in the current database, the same one
+    /// `pg_advisory_lock(key1, key2)` takes
+    pub fn advisory_pair(key1: i32, key2: i32) -> Self {
+        PgLockTag::advisory_tag(key1 as u32, key2 as u32, 2)
+    }
+
+    fn advisory_tag(field2: u32, field3: u32, field4: u16) -> Self {
+        PgLockTag(pg_sys::LOCKTAG {
+            locktag_field1: unsafe { pg_sys::MyDatabaseId },
+            locktag_field2: field2,
+            locktag_field3: field3,
+            locktag_field4: field4,
+            locktag_type: pg_sys::LockTagType_LOCKTAG_ADVISORY as u8,
+            locktag_lockmethodid: pg_sys::USER_LOCKMETHOD as u8,
+        })
+    }
+
+    /// Wrap a `LOCKTAG` built by Postgres, or by hand
+    pub fn from_pg(tag: pg_sys::LOCKTAG) -> Self {
+        PgLockTag(tag)
+    }
+
+    pub fn as_pg(&self) -> &pg_sys::LOCKTAG {
+        &self.0
+    }
+}
+
+/// Holds a lock taken by `try_lock_object()`, and releases it when dropped.
+///
+/// The lock belongs to the current transaction, so it's released when the transaction ends if
+/// this is still around
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct PgLockGuard {
+    tag: PgLockTag,
+    lockmode: pg_sys::LOCKMODE,
+}
+
+impl PgLockGuard {
+    pub fn tag(&self) -> &PgLockTag {
+        &self.tag
+    }
+
+    pub fn lockmode(&self) -> pg_sys::LOCKMODE {
+        self.lockmode
+    }
+}
+
+impl Drop for PgLockGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            // the transaction is aborting, which will release the lock for us
+            return;
+        }
+
+        unsafe {
+            pg_sys::LockRelease(self.tag.as_pg(), self.lockmode, false);
+        }
+    }
+}
+
+/// Lock the object `tag` names in `lockmode` for the current transaction, unless another
+/// transaction holds a conflicting lock, in which case `WouldBlock` is returned right away
+pub fn try_lock_object(
+    tag: PgLockTag,
+    lockmode: pg_sys::LOCKMODE,
+) -> Result<PgLockGuard, WouldBlock> {
+    let result = unsafe { pg_sys::LockAcquire(tag.as_pg(), lockmode, false, true) };
+    if result == pg_sys::LockAcquireResult_LOCKACQUIRE_NOT_AVAIL {
+        Err(WouldBlock)
+    } else {
+        Ok(PgLockGuard { tag, lockmode })
+    }
+}
+
+/// Run `lock`, which acquires a lock, with `lock_timeout` set to `timeout`, returning
+/// `WouldBlock` if it times out.
+///
+/// `lock` runs in a subtransaction, which is rolled back if it raises an ERROR, as Postgres
+/// requires after a failed lock wait.  The lock it takes is handed to the current transaction
+/// when it succeeds, but anything else owned by the subtransaction, such as an open relcache
+/// reference, isn't, so `lock` shouldn't do more than lock.  `lock_timeout` is restored
+/// afterwards, whether `lock` succeeds, times out, or raises another ERROR, which is rethrown
+pub(crate) fn with_lock_timeout<F>(timeout: Duration, lock: F) -> Result<(), WouldBlock>
+where
+    F: FnOnce() + std::panic::UnwindSafe + std::panic::RefUnwindSafe,
+{
+    // a lock_timeout of zero disables it, so round up, to wait at least a millisecond
+    let millis = ((timeout.as_micros() + 999) / 1000)
+        .max(1)
+        .min(i32::MAX as u128);
+    let value = std::ffi::CString::new(millis.to_string()).unwrap();
+    let name = std::ffi::CString::new("lock_timeout").unwrap();
+
+    unsafe {
+        let nest_level = pg_sys::NewGUCNestLevel();
+        pg_sys::set_config_option(
+            name.as_ptr(),
+            value.as_ptr(),
+            pg_sys::GucContext_PGC_USERSET,
+            pg_sys::GucSource_PGC_S_SESSION,
+            pg_sys::GucAction_GUC_ACTION_SAVE,
+            true,
+            0,
+            false,
+        );
+
+        let (context, owner) = (pg_sys::CurrentMemoryContext, pg_sys::CurrentResourceOwner);
+        pg_sys::BeginInternalSubTransaction(std::ptr::null_mut());
+        pg_sys::CurrentMemoryContext = context;
+
+        let end_subtransaction = |commit: bool| {
+            if commit {
+                pg_sys::ReleaseCurrentSubTransaction();
+            } else {
+                pg_sys::RollbackAndReleaseCurrentSubTransaction();
+            }
+            pg_sys::CurrentMemoryContext = context;
+            pg_sys::CurrentResourceOwner = owner;
+            pg_sys::AtEOXact_GUC(true, nest_level);
+        };
+
+        let result = pg_try(lock);
+        if result.sqlerrcode() == Some(PgSqlErrorCode::ERRCODE_LOCK_NOT_AVAILABLE as i32) {
+            // "canceling statement due to lock timeout"
+            result.unwrap_or_else(|| end_subtransaction(false));
+            Err(WouldBlock)
+        } else {
+            result.unwrap_or_rethrow(|| end_subtransaction(false));
+            end_subtransaction(true);
+            Ok(())
+        }
+    }
+}
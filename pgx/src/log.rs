@@ -448,23 +448,21 @@ const fn MAKE_SQLSTATE(ch1: char, ch2: char, ch3: char, ch4: char, ch5: char) ->
 /// Log messages of level `pg_sys::ERROR` will cause the current transaction to abort
 pub fn elog(level: PgLogLevel, message: &str) {
     use std::ffi::CString;
-    use std::os::raw::c_char;
 
-    unsafe {
-        extern "C" {
-            fn pgx_elog(level: i32, message: *const c_char);
-        }
+    debug_assert!(
+        !crate::spinlock::holding_spinlock(),
+        "cannot log while holding a spinlock"
+    );
 
+    unsafe {
         match CString::new(message) {
-            Ok(s) => crate::guard(|| pgx_elog(level as i32, s.as_ptr())),
-            Err(_) => crate::guard(|| {
-                pgx_elog(
-                    level as i32,
-                    std::ffi::CStr::from_bytes_with_nul(b"log message was null\0")
-                        .unwrap()
-                        .as_ptr(),
-                )
-            }),
+            Ok(s) => crate::pg_sys::pgx_elog(level as i32, s.as_ptr()),
+            Err(_) => crate::pg_sys::pgx_elog(
+                level as i32,
+                std::ffi::CStr::from_bytes_with_nul(b"log message was null\0")
+                    .unwrap()
+                    .as_ptr(),
+            ),
         }
     }
 }
@@ -482,18 +480,11 @@ pub fn ereport(
 ) {
     use std::ffi::CStr;
     use std::ffi::CString;
-    use std::os::raw::c_char;
-
-    extern "C" {
-        fn pgx_ereport(
-            level: i32,
-            code: i32,
-            message: *const c_char,
-            file: *const c_char,
-            lineno: i32,
-            colno: i32,
-        );
-    }
+
+    debug_assert!(
+        !crate::spinlock::holding_spinlock(),
+        "cannot log while holding a spinlock"
+    );
 
     let message = match CString::new(message) {
         Ok(s) => s,
@@ -512,16 +503,100 @@ pub fn ereport(
     };
 
     unsafe {
-        crate::guard(|| {
-            pgx_ereport(
-                level as i32,
-                code as i32,
-                message.as_ptr(),
-                file.as_ptr(),
-                lineno as i32,
-                colno as i32,
-            );
-        });
+        crate::pg_sys::pgx_ereport(
+            level as i32,
+            code as i32,
+            message.as_ptr(),
+            file.as_ptr(),
+            lineno as i32,
+            colno as i32,
+        );
+    }
+}
+
+/// A structured Postgres error report:  a SQLSTATE along with a message and optional detail
+/// and hint.
+///
+/// `#[pg_extern]` functions that return `Result<T, E>` raise their `Err(E)` as an `ERROR` by
+/// converting it into an `ErrorReport`.  Implement `From<E> for ErrorReport` to control the
+/// SQLSTATE of your own error types.  Otherwise any `E: std::error::Error` is reported as
+/// `ERRCODE_DATA_EXCEPTION` using its `Display` representation as the message.
+#[derive(Clone)]
+pub struct ErrorReport {
+    sqlerrcode: PgSqlErrorCode,
+    message: String,
+    detail: Option<String>,
+    hint: Option<String>,
+}
+
+impl ErrorReport {
+    pub fn new<S: Into<String>>(sqlerrcode: PgSqlErrorCode, message: S) -> Self {
+        ErrorReport {
+            sqlerrcode,
+            message: message.into(),
+            detail: None,
+            hint: None,
+        }
+    }
+
+    pub fn with_detail<S: Into<String>>(mut self, detail: S) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_hint<S: Into<String>>(mut self, hint: S) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn sqlerrcode(&self) -> PgSqlErrorCode {
+        self.sqlerrcode
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    /// Raise this report at the specified level.
+    ///
+    /// Reports of level `PgLogLevel::ERROR` (or higher) do not return and will abort the current
+    /// transaction
+    pub fn report(self, level: PgLogLevel) {
+        use crate::PgMemoryContexts;
+
+        debug_assert!(
+            !crate::spinlock::holding_spinlock(),
+            "cannot log while holding a spinlock"
+        );
+
+        // everything is copied into Postgres-allocated memory and `self` is dropped before
+        // we call into Postgres, so that no Rust-owned memory is leaked when an ERROR longjmps
+        // out of here
+        let pstrdup =
+            |s: &str| PgMemoryContexts::CurrentMemoryContext.pstrdup(&s.replace('\0', ""));
+        let code = self.sqlerrcode as i32;
+        let message = pstrdup(&self.message);
+        let detail = self.detail.as_deref().map_or(std::ptr::null_mut(), pstrdup);
+        let hint = self.hint.as_deref().map_or(std::ptr::null_mut(), pstrdup);
+        drop(self);
+
+        unsafe {
+            crate::pg_sys::pgx_ereport_report(level as i32, code, message, detail, hint);
+        }
+    }
+}
+
+impl<E: std::error::Error> From<E> for ErrorReport {
+    fn from(e: E) -> Self {
+        ErrorReport::new(PgSqlErrorCode::ERRCODE_DATA_EXCEPTION, e.to_string())
     }
 }
 
@@ -662,7 +737,7 @@ macro_rules! check_for_interrupts {
             }
         }
 
-        #[cfg(feature = "pg12")]
+        #[cfg(any(feature = "pg12", feature = "pg13"))]
         #[allow(unused_unsafe)]
         unsafe {
             if $crate::pg_sys::InterruptPending != 0 {
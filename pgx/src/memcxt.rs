@@ -10,8 +10,11 @@
 //! simple accessibility to working with MemoryContexts in a compiler-checked manner
 //!
 use crate::pg_sys::AsPgCStr;
-use crate::{guard, pg_sys, PgBox};
+use crate::{guard, pg_guard, pg_sys, PgBox};
+use std::cell::Cell;
 use std::fmt::Debug;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
 
 /// A shorter type name for a `*const std::os::raw::c_void`
 #[allow(non_camel_case_types)]
@@ -262,6 +265,33 @@ impl PgMemoryContexts {
         &mut self,
         f: F,
     ) -> R {
+        self.switch_to_once(f)
+    }
+
+    /// Like `switch_to()`, but `f` can mutate the variables it captures, such as collecting what
+    /// it allocates into a `Vec` that's declared outside of it.
+    ///
+    /// If `f` panics or raises an ERROR, `CurrentMemoryContext` is restored before that's
+    /// propagated.  Whatever `f` had changed by then stays changed, so captured state may only be
+    /// partially updated if the panic is caught.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// let mut names = Vec::new();
+    /// PgMemoryContexts::TopTransactionContext.switch_to_mut(|context| {
+    ///     for name in &["a", "b", "c"] {
+    ///         names.push(context.pstrdup(name));
+    ///     }
+    /// });
+    /// ```
+    pub fn switch_to_mut<R, F: FnMut(&mut PgMemoryContexts) -> R>(&mut self, mut f: F) -> R {
+        self.switch_to_once(|context| f(context))
+    }
+
+    fn switch_to_once<R, F: FnOnce(&mut PgMemoryContexts) -> R>(&mut self, f: F) -> R {
         match self {
             PgMemoryContexts::Transient {
                 parent,
@@ -293,6 +323,27 @@ impl PgMemoryContexts {
         }
     }
 
+    /// Is this memory context the same as, or a descendant of, `ancestor`?
+    ///
+    /// This walks up the parent chain from this context looking for `ancestor`.  Combined with
+    /// `PgMemoryContexts::Of(ptr)`, it can be used to assert that a pointer was allocated where
+    /// you expect it to be, rather than leaked into some longer-lived context.
+    ///
+    /// Neither this context nor `ancestor` can be a `::Transient` context
+    pub fn is_descendant_of(&self, ancestor: &PgMemoryContexts) -> bool {
+        let ancestor = ancestor.value();
+        let mut context = self.value();
+
+        while !context.is_null() {
+            if context == ancestor {
+                return true;
+            }
+            context = unsafe { (*context).parent };
+        }
+
+        false
+    }
+
     /// Duplicate a Rust `&str` into a Postgres-allocated "char *"
     ///
     /// ## Examples
@@ -306,6 +357,28 @@ impl PgMemoryContexts {
         unsafe { pg_sys::MemoryContextStrdup(self.value(), cstring.as_ptr()) }
     }
 
+    /// Duplicate `bytes` into a Postgres-allocated, NUL-terminated "char *", with a single
+    /// allocation in this context.  Unlike `pstrdup()`, the bytes needn't be UTF8, such as when
+    /// they're in the database's encoding.
+    ///
+    /// The bytes are copied as they are, so C code sees the string as ending at the first NUL
+    /// byte, if `bytes` contains one.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::PgMemoryContexts;
+    /// let copy = PgMemoryContexts::CurrentMemoryContext.pstrndup(b"caf\xe9");
+    /// ```
+    pub fn pstrndup(&self, bytes: &[u8]) -> *mut std::os::raw::c_char {
+        unsafe {
+            let copy = pg_sys::MemoryContextAlloc(self.value(), bytes.len() + 1) as *mut u8;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), copy, bytes.len());
+            *copy.add(bytes.len()) = 0;
+            copy as *mut std::os::raw::c_char
+        }
+    }
+
     /// Copies `len` bytes, starting at `src` into this memory context and
     /// returns a raw `*mut T` pointer to the newly allocated location
     pub fn copy_ptr_into<T>(&mut self, src: *mut T, len: usize) -> *mut T {
@@ -351,28 +424,150 @@ impl PgMemoryContexts {
         unsafe { pg_sys::MemoryContextAllocZero(self.value(), len) }
     }
 
+    /// Allocate `len` bytes in this context, which will be free'd whenever Postgres deletes this
+    /// MemoryContext, even if `len` is larger than `palloc()`'s limit of `MaxAllocSize` (1GB)
+    ///
+    /// Huge allocations bypass the normal size check, so the caller is responsible for not
+    /// exhausting the backend's memory.  Postgres will still raise an ERROR if `len` is larger
+    /// than `MaxAllocHugeSize` or if the memory can't be allocated.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::PgMemoryContexts;
+    /// let buffer = PgMemoryContexts::CurrentMemoryContext.palloc_huge::<u8>(2 * 1024 * 1024 * 1024);
+    /// ```
+    pub fn palloc_huge<T>(&mut self, len: usize) -> *mut T {
+        unsafe { pg_sys::MemoryContextAllocHuge(self.value(), len) as *mut T }
+    }
+
+    /// Allocate `len` zero'd bytes in this context, which will be free'd whenever Postgres deletes
+    /// this MemoryContext, even if `len` is larger than `palloc()`'s limit of `MaxAllocSize` (1GB)
+    ///
+    /// As with `palloc_huge()`, the caller is responsible for not exhausting the backend's memory.
+    pub fn palloc_huge0<T>(&mut self, len: usize) -> *mut T {
+        unsafe {
+            pg_sys::MemoryContextAllocExtended(
+                self.value(),
+                len,
+                (pg_sys::MCXT_ALLOC_HUGE | pg_sys::MCXT_ALLOC_ZERO) as i32,
+            ) as *mut T
+        }
+    }
+
+    /// Move `v` to the heap and leak it, dropping it whenever Postgres resets or deletes this
+    /// MemoryContext.
+    ///
+    /// `v`'s `Drop` impl may use memory allocated in this context, as Postgres calls reset
+    /// callbacks before it releases a context's memory.  See `leak_and_drop_on_delete_in()` if it
+    /// uses memory from some other context.
     pub fn leak_and_drop_on_delete<T>(&mut self, v: T) -> *mut T {
+        let value_context = PgMemoryContexts::For(self.value());
+        PgMemoryContexts::leak_and_drop_on_delete_in(v, &value_context, self)
+    }
+
+    /// Move `v` to the heap and leak it, dropping it whenever Postgres resets or deletes
+    /// `callback_context`.  `value_context` is the context holding the Postgres-allocated memory
+    /// `v`'s `Drop` impl uses, often found with `PgMemoryContexts::Of(ptr)`.
+    ///
+    /// Postgres deletes a context's children *before* calling its reset callbacks, so `v` can
+    /// only be dropped safely if `value_context` is `callback_context` or one of its ancestors.
+    /// Otherwise `v`'s memory would already be free'd by the time it's dropped, so this is
+    /// checked with a debug assertion.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// struct Reader(*mut i32);
+    /// impl Drop for Reader {
+    ///     fn drop(&mut self) {
+    ///         info!("dropped with {}", unsafe { *self.0 });
+    ///     }
+    /// }
+    ///
+    /// let buffer = PgMemoryContexts::TopTransactionContext.palloc_struct::<i32>();
+    /// PgMemoryContexts::leak_and_drop_on_delete_in(
+    ///     Reader(buffer),
+    ///     &PgMemoryContexts::Of(buffer as void_ptr),
+    ///     &mut PgMemoryContexts::CurTransactionContext,
+    /// );
+    /// ```
+    pub fn leak_and_drop_on_delete_in<T>(
+        v: T,
+        value_context: &PgMemoryContexts,
+        callback_context: &mut PgMemoryContexts,
+    ) -> *mut T {
         unsafe extern "C" fn drop_on_delete<T>(ptr: void_mut_ptr) {
             let boxed = Box::from_raw(ptr as *mut T);
             drop(boxed);
         }
 
+        debug_assert!(
+            callback_context.is_descendant_of(value_context),
+            "the callback context must be the value's context, or one of its descendants, \
+            for the value to be dropped before its memory is free'd"
+        );
+
         let leaked_ptr = Box::leak(Box::new(v));
         let mut memcxt_callback =
-            PgBox::from_pg(self.palloc_struct::<pg_sys::MemoryContextCallback>());
+            PgBox::from_pg(callback_context.palloc_struct::<pg_sys::MemoryContextCallback>());
         memcxt_callback.func = Some(drop_on_delete::<T>);
         memcxt_callback.arg = leaked_ptr as *mut T as void_mut_ptr;
         unsafe {
-            pg_sys::MemoryContextRegisterResetCallback(self.value(), memcxt_callback.into_pg());
+            pg_sys::MemoryContextRegisterResetCallback(
+                callback_context.value(),
+                memcxt_callback.into_pg(),
+            );
         }
         leaked_ptr
     }
 
+    /// Call `f` when Postgres next resets or deletes this MemoryContext, unless the returned
+    /// `CallbackHandle` is cancelled first.
+    ///
+    /// Unlike `leak_and_drop_on_delete()`, this suits resources whose lifetime may end before the
+    /// context's, such as a file that's usually closed explicitly, but must not be leaked if the
+    /// context goes away first.  `f` is called at most once, as Postgres forgets a reset callback
+    /// once it's called.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// let handle = PgMemoryContexts::CurrentMemoryContext
+    ///     .register_reset_callback(|| info!("the context went away first"));
+    ///
+    /// // ... the resource was released early, so there's nothing left to clean up
+    /// handle.cancel();
+    /// ```
+    pub fn register_reset_callback<F: FnOnce() + 'static>(&mut self, f: F) -> CallbackHandle {
+        let pending = Rc::new(Cell::new(true));
+        let callback = Box::new(ResetCallback {
+            f: Box::new(f),
+            pending: Rc::clone(&pending),
+        });
+
+        let mut memcxt_callback =
+            PgBox::from_pg(self.palloc_struct::<pg_sys::MemoryContextCallback>());
+        memcxt_callback.func = Some(call_reset_callback);
+        memcxt_callback.arg = Box::into_raw(callback) as void_mut_ptr;
+        let memcxt_callback = memcxt_callback.into_pg();
+        unsafe {
+            pg_sys::MemoryContextRegisterResetCallback(self.value(), memcxt_callback);
+        }
+
+        CallbackHandle {
+            context: self.value(),
+            callback: memcxt_callback,
+            pending,
+        }
+    }
+
     /// helper function
-    fn exec_in_context<
-        R,
-        F: Fn(&mut PgMemoryContexts) -> R + std::panic::UnwindSafe + std::panic::RefUnwindSafe,
-    >(
+    fn exec_in_context<R, F: FnOnce(&mut PgMemoryContexts) -> R>(
         context: pg_sys::MemoryContext,
         f: F,
     ) -> R {
@@ -384,14 +579,13 @@ impl PgMemoryContexts {
             pg_sys::CurrentMemoryContext = context;
         }
 
-        let result = guard::guard(|| f(&mut PgMemoryContexts::For(context)));
-
-        // restore our understanding of the current memory context
-        unsafe {
-            pg_sys::CurrentMemoryContext = prev_context;
-        }
-
-        result
+        // restore our understanding of the current memory context, even if `f` panics, before
+        // the panic continues on to `guard`.  `switch_to()` requires `f` to be unwind safe, and
+        // `switch_to_mut()` documents that its captures may be left partially updated
+        guard::pg_try(AssertUnwindSafe(|| f(&mut PgMemoryContexts::For(context))))
+            .finally_or_rethrow(|| unsafe {
+                pg_sys::CurrentMemoryContext = prev_context;
+            })
     }
 
     ///
@@ -457,3 +651,131 @@ impl PgMemoryContexts {
         //        context
     }
 }
+
+/// Iterate over `iter`, resetting `context` and switching into it before yielding each item, so
+/// that whatever the loop body allocates for one item is free'd before the next.  This is the
+/// "per-tuple context" idiom Postgres' executor uses to process any number of rows in bounded
+/// memory.
+///
+/// `iter` itself is advanced in whatever context was current before the loop, and that context
+/// is restored once the iterator is exhausted or dropped.  Anything the loop body needs to keep
+/// must be allocated (or copied) into a longer-lived context.
+///
+/// `context` must not be the current context, or one of its ancestors, as resetting it would
+/// free memory the caller is still using.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// let mut total_len = 0;
+/// for i in reset_each(0..1_000_000, PgMemoryContexts::new("per-element")) {
+///     // pstrdup()'d into "per-element", and free'd before the next element
+///     let text = PgMemoryContexts::CurrentMemoryContext.pstrdup(&i.to_string());
+///     total_len += unsafe { std::ffi::CStr::from_ptr(text) }.to_bytes().len();
+/// }
+/// ```
+pub fn reset_each<I: IntoIterator>(iter: I, context: PgMemoryContexts) -> ResetEach<I::IntoIter> {
+    ResetEach {
+        iter: iter.into_iter(),
+        context,
+        previous: None,
+    }
+}
+
+/// The iterator returned by `reset_each()`
+pub struct ResetEach<I: Iterator> {
+    iter: I,
+    context: PgMemoryContexts,
+    previous: Option<PgMemoryContexts>,
+}
+
+impl<I: Iterator> ResetEach<I> {
+    fn restore_previous(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            previous.set_as_current();
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for ResetEach<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the underlying iterator's allocations aren't ours to free
+        self.restore_previous();
+
+        let item = self.iter.next()?;
+        self.context.reset();
+        self.previous = Some(self.context.set_as_current());
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<I: Iterator> Drop for ResetEach<I> {
+    fn drop(&mut self) {
+        self.restore_previous();
+    }
+}
+
+struct ResetCallback {
+    f: Box<dyn FnOnce()>,
+    pending: Rc<Cell<bool>>,
+}
+
+#[pg_guard]
+unsafe extern "C" fn call_reset_callback(arg: void_mut_ptr) {
+    // Postgres has already unlinked the callback from the context's list
+    let callback = Box::from_raw(arg as *mut ResetCallback);
+    callback.pending.set(false);
+    (callback.f)();
+}
+
+/// A callback registered with `PgMemoryContexts::register_reset_callback()`, which can be
+/// cancelled until it's called.  Dropping the handle leaves the callback registered
+pub struct CallbackHandle {
+    context: pg_sys::MemoryContext,
+    callback: *mut pg_sys::MemoryContextCallback,
+    pending: Rc<Cell<bool>>,
+}
+
+impl CallbackHandle {
+    /// Whether the callback is still waiting for its context to be reset or deleted
+    pub fn is_pending(&self) -> bool {
+        self.pending.get()
+    }
+
+    /// Unregister the callback, dropping it without calling it.  This does nothing if it has
+    /// already been called, or is being called
+    pub fn cancel(self) {
+        if !self.pending.get() {
+            return;
+        }
+
+        // Postgres has no way to unregister a reset callback, so unlink it from the context's list
+        // ourselves.  The context hasn't been reset or deleted since the callback was registered,
+        // as that would have called it, so the context and the list are still valid
+        unsafe {
+            let mut link: *mut *mut pg_sys::MemoryContextCallback = &mut (*self.context).reset_cbs;
+            while !(*link).is_null() {
+                if *link == self.callback {
+                    *link = (*self.callback).next;
+                    self.pending.set(false);
+
+                    let callback = Box::from_raw((*self.callback).arg as *mut ResetCallback);
+                    pg_sys::pfree(self.callback as void_mut_ptr);
+                    drop(callback);
+                    return;
+                }
+                link = &mut (**link).next;
+            }
+        }
+
+        panic!("a pending reset callback isn't registered with its MemoryContext");
+    }
+}
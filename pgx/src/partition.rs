@@ -0,0 +1,221 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Tuple routing for declaratively partitioned tables, for extensions that load rows into them
+//! without going through `INSERT`
+//!
+//! Postgres' tuple routing API changed with every release from 11 through 13, and Postgres 10
+//! has neither default partitions nor a routing API usable outside of `COPY`, so this module is
+//! only available on Postgres 11 and later.
+use crate::slot::exec_clear_tuple;
+use crate::{pg_sys, PgBox, PgMemoryContexts, PgRelation, PgSqlErrorCode};
+
+/// Where `PartitionRouter::route()` sends a row
+pub enum PartitionRoute {
+    /// The leaf partition whose bounds contain the row
+    Partition(PgRelation),
+
+    /// The default partition of the row's (sub-)partitioned table, as none of its other
+    /// partitions' bounds contain the row
+    Default(PgRelation),
+
+    /// No partition accepts the row, so inserting it would raise an ERROR
+    NoPartition,
+}
+
+/// Finds the leaf partition each row of a partitioned table belongs in, the same way `INSERT` and
+/// `COPY FROM` do.  Rows are routed through every level of sub-partitioning.
+///
+/// Routing takes a `RowExclusiveLock` on the partitions it looks at (on Postgres 11, all of them
+/// up front), which is held until the end of the transaction.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// let measurements = PgRelation::open_with_name_and_share_lock("measurements").unwrap();
+/// let mut router = PartitionRouter::new(&measurements);
+///
+/// match unsafe { router.route(&[42i32.into_datum()]) } {
+///     PartitionRoute::Partition(partition) => info!("goes in {}", partition.name()),
+///     PartitionRoute::Default(partition) => info!("goes in the default, {}", partition.name()),
+///     PartitionRoute::NoPartition => info!("doesn't belong anywhere"),
+/// }
+/// ```
+pub struct PartitionRouter<'a> {
+    parent: &'a PgRelation,
+    estate: *mut pg_sys::EState,
+    mtstate: *mut pg_sys::ModifyTableState,
+    root: *mut pg_sys::ResultRelInfo,
+    proute: *mut pg_sys::PartitionTupleRouting,
+    slot: *mut pg_sys::TupleTableSlot,
+}
+
+impl<'a> PartitionRouter<'a> {
+    /// Prepare to route rows into `parent`, which must be a partitioned table
+    pub fn new(parent: &'a PgRelation) -> Self {
+        if !parent.is_partitioned_table() {
+            panic!("\"{}\" is not a partitioned table", parent.name());
+        }
+
+        unsafe {
+            let estate = pg_sys::CreateExecutorState();
+
+            // allocate everything in the executor state's context, so FreeExecutorState() frees it
+            let old_context = PgMemoryContexts::For((*estate).es_query_cxt).set_as_current();
+
+            let root = PgBox::<pg_sys::ResultRelInfo>::alloc_node(pg_sys::NodeTag_T_ResultRelInfo)
+                .into_pg();
+            pg_sys::InitResultRelInfo(root, parent.as_ptr(), 1, std::ptr::null_mut(), 0);
+            (*estate).es_result_relations = root;
+            (*estate).es_num_result_relations = 1;
+            (*estate).es_result_relation_info = root;
+
+            // tuple routing expects to be part of a ModifyTable node, so fake one for an INSERT,
+            // as COPY FROM does
+            let mut mtstate =
+                PgBox::<pg_sys::ModifyTableState>::alloc_node(pg_sys::NodeTag_T_ModifyTableState);
+            mtstate.ps.state = estate;
+            mtstate.operation = pg_sys::CmdType_CMD_INSERT;
+            mtstate.resultRelInfo = root;
+            let mtstate = mtstate.into_pg();
+
+            #[cfg(feature = "pg11")]
+            let (proute, slot) = (
+                pg_sys::ExecSetupPartitionTupleRouting(mtstate, root),
+                pg_sys::MakeSingleTupleTableSlot(parent.rd_att),
+            );
+
+            #[cfg(any(feature = "pg12", feature = "pg13"))]
+            let (proute, slot) = (
+                pg_sys::ExecSetupPartitionTupleRouting(estate, mtstate, parent.as_ptr()),
+                pg_sys::MakeSingleTupleTableSlot(parent.rd_att, &pg_sys::TTSOpsVirtual),
+            );
+
+            old_context.set_as_current();
+
+            PartitionRouter {
+                parent,
+                estate,
+                mtstate,
+                root,
+                proute,
+                slot,
+            }
+        }
+    }
+
+    /// Find the leaf partition a row belongs in.  `values` has an element for each of the
+    /// partitioned table's attributes (`relnatts`, including dropped ones), of the attribute's
+    /// type, with NULLs and dropped attributes as `None` -- the same shape as the rows
+    /// `PgRelation::scan_rows()` yields.
+    ///
+    /// A row that no partition accepts is `PartitionRoute::NoPartition` rather than an ERROR,
+    /// but any other ERROR raised while routing, such as from a partition key expression, is
+    /// rethrown.  So that the ERROR can be recovered from, each row is routed in its own
+    /// subtransaction, which means this can't be used in parallel mode.
+    ///
+    /// ## Safety
+    ///
+    /// Each of `values` must be a valid `Datum` of its attribute's type, as they're passed to
+    /// the partition key's comparison functions and expressions
+    pub unsafe fn route(&mut self, values: &[Option<pg_sys::Datum>]) -> PartitionRoute {
+        let natts = (*self.parent.rd_att).natts as usize;
+        if values.len() != natts {
+            panic!(
+                "\"{}\" has {} attributes, but {} values were provided",
+                self.parent.name(),
+                natts,
+                values.len()
+            );
+        }
+
+        exec_clear_tuple(self.slot);
+        for (i, value) in values.iter().enumerate() {
+            *(*self.slot).tts_values.add(i) = value.unwrap_or(0);
+            *(*self.slot).tts_isnull.add(i) = value.is_none();
+        }
+        pg_sys::ExecStoreVirtualTuple(self.slot);
+
+        // routing allocates in the per-tuple context, so free what the last row used
+        let econtext = (*self.estate).es_per_tuple_exprcontext;
+        if !econtext.is_null() {
+            pg_sys::MemoryContextReset((*econtext).ecxt_per_tuple_memory);
+        }
+
+        let (mtstate, root, proute, slot, estate) =
+            (self.mtstate, self.root, self.proute, self.slot, self.estate);
+        let owner = pg_sys::CurrentResourceOwner;
+        let result = pg_sys::guard::pg_try_subtransaction(move || {
+            // the partitions routing opens and locks are kept in `proute` for later rows, so
+            // they're owned by our resource owner rather than the subtransaction's, which would
+            // close them if the subtransaction is rolled back
+            pg_sys::CurrentResourceOwner = owner;
+            find_partition(mtstate, root, proute, slot, estate)
+        });
+
+        // "no partition of relation ... found for row"
+        let oid = if result.sqlerrcode() == Some(PgSqlErrorCode::ERRCODE_CHECK_VIOLATION as i32) {
+            result.unwrap_or(pg_sys::InvalidOid)
+        } else {
+            result.unwrap()
+        };
+
+        if oid == pg_sys::InvalidOid {
+            return PartitionRoute::NoPartition;
+        }
+
+        // tuple routing has already locked it
+        let partition = PgRelation::open_no_lock(oid);
+        let is_default =
+            pg_sys::get_default_partition_oid(pg_sys::get_partition_parent(oid)) == oid;
+
+        if is_default {
+            PartitionRoute::Default(partition)
+        } else {
+            PartitionRoute::Partition(partition)
+        }
+    }
+}
+
+impl<'a> Drop for PartitionRouter<'a> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            // the transaction is aborting, which will free the executor state and release the
+            // partitions for us
+            return;
+        }
+
+        unsafe {
+            pg_sys::ExecCleanupTupleRouting(self.mtstate, self.proute);
+            pg_sys::ExecDropSingleTupleTableSlot(self.slot);
+            pg_sys::ExecResetTupleTable((*self.estate).es_tupleTable, false);
+            pg_sys::FreeExecutorState(self.estate);
+        }
+    }
+}
+
+#[cfg(feature = "pg11")]
+unsafe fn find_partition(
+    _mtstate: *mut pg_sys::ModifyTableState,
+    root: *mut pg_sys::ResultRelInfo,
+    proute: *mut pg_sys::PartitionTupleRouting,
+    slot: *mut pg_sys::TupleTableSlot,
+    estate: *mut pg_sys::EState,
+) -> pg_sys::Oid {
+    let index = pg_sys::ExecFindPartition(root, (*proute).partition_dispatch_info, slot, estate);
+    *(*proute).partition_oids.add(index as usize)
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn find_partition(
+    mtstate: *mut pg_sys::ModifyTableState,
+    root: *mut pg_sys::ResultRelInfo,
+    proute: *mut pg_sys::PartitionTupleRouting,
+    slot: *mut pg_sys::TupleTableSlot,
+    estate: *mut pg_sys::EState,
+) -> pg_sys::Oid {
+    let partition = pg_sys::ExecFindPartition(mtstate, root, proute, slot, estate);
+    (*(*partition).ri_RelationDesc).rd_id
+}
@@ -88,6 +88,7 @@ use std::ops::{Deref, DerefMut};
 ///  - Interatctions with Rust's panic!() macro
 ///  - Interactions with Poastgres' error!() macro
 ///  - Boxing a null pointer -- it works ::from_pg(), ::into_pg(), and ::to_pg(), but will panic!() on all other uses
+///    except ::try_deref() and ::try_deref_mut(), which return `None`
 ///
 pub struct PgBox<T> {
     inner: Inner<T>,
@@ -272,6 +273,18 @@ impl<T> PgBox<T> {
         self.inner.ptr.is_none()
     }
 
+    /// A reference to the boxed value, or `None` if we're boxing a NULL.  Unlike dereferencing
+    /// the `PgBox`, this never panics.
+    pub fn try_deref(&self) -> Option<&T> {
+        self.inner.ptr.map(|ptr| unsafe { &*ptr })
+    }
+
+    /// A mutable reference to the boxed value, or `None` if we're boxing a NULL.  Unlike
+    /// dereferencing the `PgBox`, this never panics.
+    pub fn try_deref_mut(&mut self) -> Option<&mut T> {
+        self.inner.ptr.map(|ptr| unsafe { &mut *ptr })
+    }
+
     /// Return the boxed pointer, so that it can be passed back into a Postgres function
     pub fn as_ptr(&self) -> *mut T {
         let ptr = self.inner.ptr;
@@ -293,6 +306,59 @@ impl<T> PgBox<T> {
         }
     }
 
+    /// Copy the boxed struct into `memory_context`, so that it outlives the context it's in now.
+    /// This is `copy_ptr_into()` for a single `T`, without having to give its size.
+    ///
+    /// The copy is **shallow**:  it's a `memcpy()` of the struct's `std::mem::size_of::<T>()`
+    /// bytes, so any pointers in it still point to what the original's do, which won't live any
+    /// longer than they would have.  Copy those separately, if they need to.
+    ///
+    /// The copy belongs to `memory_context`, and isn't freed when the returned `PgBox` is dropped.
+    ///
+    /// ## Panics
+    ///
+    /// If the box is NULL
+    ///
+    /// ## Examples
+    /// ```rust,no_run
+    /// use pgx::{PgBox, pg_sys, PgMemoryContexts};
+    /// let mut tid = PgBox::<pg_sys::ItemPointerData>::alloc0();
+    /// tid.ip_posid = 42;
+    ///
+    /// let copy = tid.clone_into_context(&mut PgMemoryContexts::TopTransactionContext);
+    /// assert_eq!(copy.ip_posid, 42);
+    /// ```
+    pub fn clone_into_context(&self, memory_context: &mut PgMemoryContexts) -> PgBox<T> {
+        let ptr = match self.inner.ptr {
+            Some(ptr) => ptr,
+            None => panic!("PgBox::clone_into_context() called on a NULL pointer"),
+        };
+
+        PgBox::from_pg(memory_context.copy_ptr_into(ptr, std::mem::size_of::<T>()))
+    }
+
+    /// The total number of bytes Postgres reserved for the palloc'd chunk this `PgBox` points to,
+    /// including the chunk's header and any padding.  This is `GetMemoryChunkSpace()`, and is
+    /// at least `std::mem::size_of::<T>()`.
+    ///
+    /// ## Panics
+    ///
+    /// If the box is NULL, or if its pointer isn't the start of a chunk allocated by a memory
+    /// context that descends from `TopMemoryContext`.  Before asking Postgres about the chunk,
+    /// the pointer is checked to be MAXALIGN'd and to be preceded by a pointer to a live memory
+    /// context.
+    pub fn chunk_space(&self) -> usize {
+        let ptr = match self.inner.ptr {
+            Some(ptr) => ptr as void_mut_ptr,
+            None => panic!("PgBox::chunk_space() called on a NULL pointer"),
+        };
+
+        match palloc_chunk_space(ptr) {
+            Some(space) => space,
+            None => panic!("pointer {:?} is not a palloc'd memory chunk", ptr),
+        }
+    }
+
     fn owner_string(&self) -> &str {
         if self.inner.allocated_by_pg {
             "Postgres"
@@ -307,6 +373,47 @@ impl<T> PgBox<T> {
     }
 }
 
+/// `GetMemoryChunkSpace()` for `ptr`, if it's MAXALIGN'd and preceded by a pointer to a live
+/// memory context that contains it.  `None` if it isn't the start of a palloc'd chunk
+pub(crate) fn palloc_chunk_space(ptr: void_mut_ptr) -> Option<usize> {
+    extern "C" {
+        fn pgx_GetMemoryContextChunk(pointer: void_mut_ptr) -> pg_sys::MemoryContext;
+    }
+
+    if ptr.is_null() || ptr as usize % pg_sys::MAXIMUM_ALIGNOF as usize != 0 {
+        return None;
+    }
+
+    let context = unsafe { pgx_GetMemoryContextChunk(ptr) };
+    if !is_live_memory_context(context) || !unsafe { pg_sys::MemoryContextContains(context, ptr) } {
+        return None;
+    }
+
+    Some(unsafe { pg_sys::GetMemoryChunkSpace(ptr) as usize })
+}
+
+/// Is `context` one of the memory contexts in the tree rooted at `TopMemoryContext`?  This only
+/// compares pointers, so `context` can be anything
+fn is_live_memory_context(context: pg_sys::MemoryContext) -> bool {
+    fn search(node: pg_sys::MemoryContext, context: pg_sys::MemoryContext) -> bool {
+        let mut node = node;
+        while !node.is_null() {
+            if node == context {
+                return true;
+            }
+
+            let first_child = unsafe { (*node).firstchild };
+            if search(first_child, context) {
+                return true;
+            }
+            node = unsafe { (*node).nextchild };
+        }
+        false
+    }
+
+    !context.is_null() && search(unsafe { pg_sys::TopMemoryContext }, context)
+}
+
 impl<T> Deref for PgBox<T> {
     type Target = T;
 
@@ -351,3 +458,69 @@ impl<T: Debug> std::fmt::Display for PgBox<T> {
         }
     }
 }
+
+/// Borrow mutable references to one or more fields of a `PgBox<T>`'s struct at the same time.
+///
+/// Each field access through a `PgBox<T>` goes through `DerefMut`, which borrows the entire
+/// `PgBox`, so it's not possible to hold mutable references to two different fields at once.
+/// `pgbox_project!` calls `DerefMut` once and splits that borrow across the named fields.
+///
+/// With a single field it returns a `&mut` to that field, otherwise a tuple of them, in order.
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// fn example(fcinfo: pg_sys::FunctionCallInfo) {
+///     let mut fcinfo = PgBox::from_pg(fcinfo);
+///     let (flinfo, isnull) = pgbox_project!(fcinfo => flinfo, isnull);
+///
+///     *isnull = flinfo.is_null();
+/// }
+/// ```
+///
+/// For the pointer fields commonly layered on by wrapper types (`FunctionCallInfo`'s `flinfo`
+/// and `context`, `TriggerData`'s `tg_relation` and `tg_trigtuple`, and `IndexScanDesc`'s
+/// `opaque`), `pg_sys` also provides unsafe `field_ref()`/`field_mut()` accessors that return a
+/// typed reference to what the field points to, which the caller must know is NULL or valid.
+///
+/// Either way, the references borrow from the parent, so they can't outlive it:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// fn escape(fcinfo: pg_sys::FunctionCallInfo) -> &'static mut bool {
+///     let mut fcinfo = PgBox::from_pg(fcinfo);
+///     pgbox_project!(fcinfo => isnull)
+/// }
+/// ```
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// fn escape(fcinfo: pg_sys::FunctionCallInfo) -> &'static pg_sys::FmgrInfo {
+///     let fcinfo = PgBox::from_pg(fcinfo);
+///     unsafe { fcinfo.flinfo_ref() }.unwrap()
+/// }
+/// ```
+///
+/// And the same field can't be projected twice:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// fn alias(fcinfo: pg_sys::FunctionCallInfo) {
+///     let mut fcinfo = PgBox::from_pg(fcinfo);
+///     let (a, b) = pgbox_project!(fcinfo => isnull, isnull);
+///     *a = *b;
+/// }
+/// ```
+#[macro_export]
+macro_rules! pgbox_project {
+    ($parent:expr => $field:ident) => {
+        &mut (*$parent).$field
+    };
+    ($parent:expr => $($field:ident),+ $(,)?) => {{
+        let parent = &mut *$parent;
+        ($(&mut parent.$field,)+)
+    }};
+}
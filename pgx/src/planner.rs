@@ -0,0 +1,185 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Read-mostly views over the planner's `PlannerInfo`, `RelOptInfo`, and `RangeTblEntry` nodes,
+//! for planner hooks like `set_rel_pathlist_hook`
+use crate::{pg_sys, PgBox, PgList};
+use std::ffi::CStr;
+
+/// A view over the planner's state for a single query level
+pub struct PgPlannerInfo {
+    boxed: PgBox<pg_sys::PlannerInfo>,
+}
+
+impl PgPlannerInfo {
+    /// Wrap the `PlannerInfo` Postgres passed to a planner hook
+    pub fn from_pg(ptr: *mut pg_sys::PlannerInfo) -> Self {
+        PgPlannerInfo {
+            boxed: PgBox::from_pg(ptr),
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut pg_sys::PlannerInfo {
+        self.boxed.as_ptr()
+    }
+
+    /// The `Query` being planned
+    pub fn query(&self) -> PgBox<pg_sys::Query> {
+        PgBox::from_pg(self.boxed.parse)
+    }
+
+    /// The number of range table entries, which are numbered from 1
+    pub fn rel_array_size(&self) -> usize {
+        // element zero is always unused
+        (self.boxed.simple_rel_array_size as usize).saturating_sub(1)
+    }
+
+    /// The `RelOptInfo` for each base relation (and "other member" relation, such as a
+    /// partition) the planner has created so far, in range table order.  Range table entries
+    /// that don't have one, such as joins, are skipped
+    pub fn rel_array(&self) -> impl Iterator<Item = PgRelOptInfo> + '_ {
+        (1..=self.rel_array_size() as pg_sys::Index).filter_map(move |index| self.rel(index))
+    }
+
+    /// The `RelOptInfo` for the range table entry numbered `index`, if it has one
+    pub fn rel(&self, index: pg_sys::Index) -> Option<PgRelOptInfo> {
+        if index < 1 || index as usize > self.rel_array_size() {
+            return None;
+        }
+
+        let rel = unsafe { *self.boxed.simple_rel_array.add(index as usize) };
+        if rel.is_null() {
+            None
+        } else {
+            Some(PgRelOptInfo::from_pg(rel))
+        }
+    }
+
+    /// The range table entry numbered `index`
+    pub fn rte(&self, index: pg_sys::Index) -> Option<PgRangeTblEntry> {
+        if index < 1 || index as usize > self.rel_array_size() {
+            return None;
+        }
+
+        let rte = unsafe { *self.boxed.simple_rte_array.add(index as usize) };
+        if rte.is_null() {
+            None
+        } else {
+            Some(PgRangeTblEntry::from_pg(rte))
+        }
+    }
+}
+
+/// A view over the planner's information about a relation (`RelOptInfo`)
+pub struct PgRelOptInfo {
+    boxed: PgBox<pg_sys::RelOptInfo>,
+}
+
+impl PgRelOptInfo {
+    /// Wrap a `RelOptInfo` provided by Postgres
+    pub fn from_pg(ptr: *mut pg_sys::RelOptInfo) -> Self {
+        PgRelOptInfo {
+            boxed: PgBox::from_pg(ptr),
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut pg_sys::RelOptInfo {
+        self.boxed.as_ptr()
+    }
+
+    /// The range table index of this relation, or zero if it's a join relation
+    pub fn relid(&self) -> pg_sys::Index {
+        self.boxed.relid
+    }
+
+    /// The estimated number of rows this relation produces, after its restrictions are applied
+    pub fn rows(&self) -> f64 {
+        self.boxed.rows
+    }
+
+    /// The estimated average width, in bytes, of this relation's output rows
+    pub fn width(&self) -> i32 {
+        if self.boxed.reltarget.is_null() {
+            0
+        } else {
+            unsafe { (*self.boxed.reltarget).width }
+        }
+    }
+
+    /// The restriction clauses (`WHERE` conditions) that apply only to this relation
+    pub fn baserestrictinfo(&self) -> PgList<pg_sys::RestrictInfo> {
+        PgList::from_pg(self.boxed.baserestrictinfo)
+    }
+
+    /// The paths the planner is considering for this relation.  A path's `pathtype` is the plan
+    /// node it would become, such as `pg_sys::NodeTag_T_SeqScan`
+    pub fn pathlist(&self) -> Vec<PgBox<pg_sys::Path>> {
+        PgList::<pg_sys::Path>::from_pg(self.boxed.pathlist)
+            .iter_ptr()
+            .map(PgBox::from_pg)
+            .collect()
+    }
+
+    /// Offer `path` to the planner with `add_path()`, which keeps it only if it's not dominated
+    /// by a path the relation already has, and may discard existing paths it dominates.
+    ///
+    /// A discarded path is freed, so `path` must have been allocated by Postgres in the
+    /// planner's memory context, and neither it nor a path it dominates can be used afterwards.
+    pub fn add_path(&mut self, path: PgBox<pg_sys::Path>) {
+        unsafe { pg_sys::add_path(self.boxed.as_ptr(), path.into_pg()) }
+    }
+}
+
+/// A view over a range table entry (`RangeTblEntry`)
+pub struct PgRangeTblEntry {
+    boxed: PgBox<pg_sys::RangeTblEntry>,
+}
+
+impl PgRangeTblEntry {
+    /// Wrap a `RangeTblEntry` provided by Postgres
+    pub fn from_pg(ptr: *mut pg_sys::RangeTblEntry) -> Self {
+        PgRangeTblEntry {
+            boxed: PgBox::from_pg(ptr),
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut pg_sys::RangeTblEntry {
+        self.boxed.as_ptr()
+    }
+
+    /// What this entry is, such as `pg_sys::RTEKind_RTE_RELATION`
+    pub fn rtekind(&self) -> pg_sys::RTEKind {
+        self.boxed.rtekind
+    }
+
+    pub fn is_relation(&self) -> bool {
+        self.rtekind() == pg_sys::RTEKind_RTE_RELATION
+    }
+
+    /// The oid of the relation, if this entry is one
+    pub fn relid(&self) -> Option<pg_sys::Oid> {
+        if self.is_relation() {
+            Some(self.boxed.relid)
+        } else {
+            None
+        }
+    }
+
+    /// The alias the query gave this entry (`FROM table AS alias`), if any
+    pub fn alias(&self) -> Option<&str> {
+        alias_name(self.boxed.alias)
+    }
+
+    /// The name the query refers to this entry by:  its alias, or else the relation's name
+    pub fn name(&self) -> Option<&str> {
+        alias_name(self.boxed.eref)
+    }
+}
+
+fn alias_name<'a>(alias: *mut pg_sys::Alias) -> Option<&'a str> {
+    if alias.is_null() || unsafe { (*alias).aliasname.is_null() } {
+        None
+    } else {
+        unsafe { CStr::from_ptr((*alias).aliasname) }.to_str().ok()
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Quoting of SQL identifiers and literals, for building SQL strings to run through SPI or to
+//! send to a remote server.
+//!
+//! These call Postgres' own quoting functions, so the results are exactly what the SQL-level
+//! `quote_ident()`, `quote_literal()`, and `quote_nullable()` functions return.
+//!
+//! All of these functions panic if the provided string contains a NUL byte, as it can't be
+//! represented in a Postgres string.
+use crate::pg_sys;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+/// Quote `ident` as a SQL identifier, but only if necessary:  when it's not entirely lowercase
+/// letters, digits, and underscores, starts with a digit, or is a SQL keyword.  Embedded double
+/// quotes are doubled.
+///
+/// ```rust,no_run
+/// use pgx::quote::quote_identifier;
+///
+/// assert_eq!(quote_identifier("my_table"), "my_table");
+/// assert_eq!(quote_identifier("MyTable"), "\"MyTable\"");
+/// assert_eq!(quote_identifier("select"), "\"select\"");
+/// ```
+pub fn quote_identifier(ident: &str) -> String {
+    let ident = to_cstring(ident);
+    unsafe {
+        let quoted = pg_sys::quote_identifier(ident.as_ptr());
+        let result = from_cstr(quoted);
+        if quoted != ident.as_ptr() {
+            // it was quoted into a palloc'd string
+            pg_sys::pfree(quoted as *mut _);
+        }
+        result
+    }
+}
+
+/// Quote `name`, optionally qualified by `schema`, as a SQL identifier.  Each part is only
+/// quoted if necessary, as with `quote_identifier()`.
+pub fn quote_qualified_identifier(schema: Option<&str>, name: &str) -> String {
+    let schema = schema.map(to_cstring);
+    let name = to_cstring(name);
+    unsafe {
+        let quoted = pg_sys::quote_qualified_identifier(
+            schema
+                .as_ref()
+                .map_or(std::ptr::null(), |schema| schema.as_ptr()),
+            name.as_ptr(),
+        );
+        let result = from_cstr(quoted);
+        pg_sys::pfree(quoted as *mut _);
+        result
+    }
+}
+
+/// Quote `literal` as a SQL string literal.  Embedded single quotes and backslashes are
+/// doubled, and if there were any backslashes the result is an `E''` string so that it means
+/// the same thing regardless of `standard_conforming_strings`.
+///
+/// ```rust,no_run
+/// use pgx::quote::quote_literal;
+///
+/// assert_eq!(quote_literal("it's"), "'it''s'");
+/// assert_eq!(quote_literal("C:\\"), "E'C:\\\\'");
+/// ```
+pub fn quote_literal(literal: &str) -> String {
+    let literal = to_cstring(literal);
+    unsafe {
+        let quoted = pg_sys::quote_literal_cstr(literal.as_ptr());
+        let result = from_cstr(quoted);
+        pg_sys::pfree(quoted as *mut _);
+        result
+    }
+}
+
+/// Like `quote_literal()`, but `None` becomes an unquoted `NULL`
+pub fn quote_nullable(literal: Option<&str>) -> String {
+    match literal {
+        Some(literal) => quote_literal(literal),
+        None => "NULL".to_string(),
+    }
+}
+
+/// Convert `datum` to text using the output function of the type `typoid`, and quote the
+/// result as a SQL string literal.  A NULL `datum` becomes an unquoted `NULL`.
+///
+/// Note that the literal is not cast back to its type.  Append `::typename` if the context
+/// it's used in doesn't imply the type.
+///
+/// ## Safety
+///
+/// This function is unsafe as it cannot guarantee that `datum` is actually of type `typoid`
+pub unsafe fn quote_literal_datum(datum: Option<pg_sys::Datum>, typoid: pg_sys::Oid) -> String {
+    match datum {
+        Some(datum) => {
+            let mut output_func = pg_sys::InvalidOid;
+            let mut is_varlena = false;
+            pg_sys::getTypeOutputInfo(typoid, &mut output_func, &mut is_varlena);
+
+            let text = pg_sys::OidOutputFunctionCall(output_func, datum);
+            let quoted = pg_sys::quote_literal_cstr(text);
+            let result = from_cstr(quoted);
+            pg_sys::pfree(quoted as *mut _);
+            pg_sys::pfree(text as *mut _);
+            result
+        }
+        None => "NULL".to_string(),
+    }
+}
+
+fn to_cstring(s: &str) -> CString {
+    CString::new(s).expect("string contains a NUL byte")
+}
+
+unsafe fn from_cstr(s: *const c_char) -> String {
+    CStr::from_ptr(s)
+        .to_str()
+        .expect("quoted string is not valid UTF8")
+        .to_string()
+}
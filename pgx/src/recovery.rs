@@ -0,0 +1,69 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Helpers for code that must behave differently on a hot standby, where nothing can be written
+//! and no transaction id can be assigned
+use crate::{pg_sys, ErrorReport, PgLogLevel, PgSqlErrorCode};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const NO_OVERRIDE: u8 = 0;
+const OVERRIDE_FALSE: u8 = 1;
+const OVERRIDE_TRUE: u8 = 2;
+
+/// A backend-local override of `recovery_in_progress()`, for testing standby code paths on a
+/// primary
+static RECOVERY_OVERRIDE: AtomicU8 = AtomicU8::new(NO_OVERRIDE);
+
+/// Is the server still in recovery?  That's always the case on a hot standby, and briefly the case
+/// on a primary that's replaying WAL after a crash.
+///
+/// This is Postgres' `RecoveryInProgress()`, which only checks shared memory until it sees that
+/// recovery has finished, so it's cheap to call repeatedly.
+pub fn recovery_in_progress() -> bool {
+    match RECOVERY_OVERRIDE.load(Ordering::Relaxed) {
+        OVERRIDE_FALSE => false,
+        OVERRIDE_TRUE => true,
+        _ => unsafe { pg_sys::RecoveryInProgress() },
+    }
+}
+
+/// Make `recovery_in_progress()` (and so `ensure_not_in_recovery()`) return `value` in this
+/// backend, regardless of the server's actual state.  `None` restores the real check.
+///
+/// This is meant for testing standby-only code paths on a primary.  Postgres itself isn't
+/// affected, so writes still succeed.
+pub fn override_recovery_in_progress(value: Option<bool>) {
+    let value = match value {
+        None => NO_OVERRIDE,
+        Some(false) => OVERRIDE_FALSE,
+        Some(true) => OVERRIDE_TRUE,
+    };
+    RECOVERY_OVERRIDE.store(value, Ordering::Relaxed);
+}
+
+/// Raise an `ERROR` with `ERRCODE_READ_ONLY_SQL_TRANSACTION`, the same error Postgres raises for
+/// its own commands, if the server is in recovery.  `operation` names what can't be done, ie
+/// `"COPY FROM"` reports `cannot execute COPY FROM during recovery`.
+///
+/// Call this before starting work that writes, so the user sees why it's not allowed rather than
+/// an error from deep inside Postgres.
+pub fn ensure_not_in_recovery(operation: &str) {
+    if recovery_in_progress() {
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_READ_ONLY_SQL_TRANSACTION,
+            format!("cannot execute {} during recovery", operation),
+        )
+        .report(PgLogLevel::ERROR);
+    }
+}
+
+/// Is this a hot standby that's accepting read-only connections?
+pub fn hot_standby_active() -> bool {
+    unsafe { pg_sys::HotStandbyActive() }
+}
+
+/// Is an exclusive backup (`pg_start_backup(..., exclusive => true)`) in progress?  This is
+/// `pg_is_in_backup()`, which is always false on a standby.
+pub fn backup_in_progress() -> bool {
+    unsafe { pg_sys::BackupInProgress() }
+}
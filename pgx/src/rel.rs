@@ -3,16 +3,28 @@
 
 //! Provides a safe wrapper around Postgres' `pg_sys::RelationData` struct
 use crate::{
-    direct_function_call, name_data_to_str, pg_sys, FromDatum, IntoDatum, PgBox, PgList,
-    PgTupleDesc,
+    direct_function_call, name_data_to_str, pg_sys, pg_try, FromDatum, IntoDatum, PgBox, PgList,
+    PgTupleDesc, TableScan, TriggerInfo, WouldBlock,
 };
 use std::ops::Deref;
 use std::os::raw::c_char;
+use std::time::Duration;
 
+/// ## Invalidation
+///
+/// Postgres rebuilds a relation's relcache entry when the relation is changed, such as by an
+/// `ALTER TABLE` in another backend, or a `GRANT` or `ANALYZE` in this one, and anything borrowed
+/// from the old entry (`rd_rel`, its tuple descriptor, its index list) may then point to freed
+/// memory.  In debug
+/// builds, a `PgRelation` notices when its relcache entry has been invalidated since it was
+/// opened, and panics rather than hand out anything from it.  Re-open the relation, or call
+/// `revalidate()`, to continue using it.
 pub struct PgRelation {
     boxed: PgBox<pg_sys::RelationData>,
     need_close: bool,
     lockmode: Option<pg_sys::LOCKMODE>,
+    #[cfg(debug_assertions)]
+    invalidations: u64,
 }
 
 impl PgRelation {
@@ -25,22 +37,14 @@ impl PgRelation {
     ///
     /// This method is unsafe as we cannot ensure that this relation will later be closed by Postgres
     pub unsafe fn from_pg(ptr: pg_sys::Relation) -> Self {
-        PgRelation {
-            boxed: PgBox::from_pg(ptr),
-            need_close: false,
-            lockmode: None,
-        }
+        PgRelation::new(ptr, false, None)
     }
 
     /// Wrap a Postgres-provided `pg_sys::Relation`.
     ///
     /// The provided `Relation` will be closed via `pg_sys::RelationClose` when this instance is dropped
     pub fn from_pg_owned(ptr: pg_sys::Relation) -> Self {
-        PgRelation {
-            boxed: PgBox::from_pg(ptr),
-            need_close: true,
-            lockmode: None,
-        }
+        PgRelation::new(ptr, true, None)
     }
 
     /// Given a relation oid, use `pg_sys::RelationIdGetRelation()` to open the relation
@@ -63,11 +67,7 @@ impl PgRelation {
             panic!("Cannot open relation with oid={}", oid);
         }
 
-        PgRelation {
-            boxed: PgBox::from_pg(rel),
-            need_close: true,
-            lockmode: None,
-        }
+        PgRelation::new(rel, true, None)
     }
 
     /// relation_open - open any relation by relation OID
@@ -85,15 +85,82 @@ impl PgRelation {
     /// The opened relation is automatically closed via `pg_sys::relation_close()`
     /// when this instance is dropped
     pub fn with_lock(oid: pg_sys::Oid, lockmode: pg_sys::LOCKMODE) -> Self {
-        unsafe {
-            PgRelation {
-                boxed: PgBox::from_pg(pg_sys::relation_open(oid, lockmode)),
-                need_close: true,
-                lockmode: Some(lockmode),
-            }
+        let rel = unsafe { pg_sys::relation_open(oid, lockmode) };
+        PgRelation::new(rel, true, Some(lockmode))
+    }
+
+    /// Like `with_lock()`, but returns `WouldBlock` right away, rather than waiting, if another
+    /// transaction holds a conflicting lock on the relation, as `LOCK TABLE ... NOWAIT` does.
+    ///
+    /// An error is raised if the relation does not exist.
+    pub fn try_with_lock(
+        oid: pg_sys::Oid,
+        lockmode: pg_sys::LOCKMODE,
+    ) -> std::result::Result<Self, WouldBlock> {
+        if unsafe { pg_sys::ConditionalLockRelationOid(oid, lockmode) } {
+            Ok(unsafe { PgRelation::open_locked(oid, lockmode) })
+        } else {
+            Err(WouldBlock)
         }
     }
 
+    /// Like `with_lock()`, but waits at most `timeout` for a conflicting lock held by another
+    /// transaction, and then returns `WouldBlock`.
+    ///
+    /// The wait uses Postgres' `lock_timeout`, which is set to `timeout` in a subtransaction
+    /// and then restored, however the wait ends.  An error is raised if the relation does not
+    /// exist, as is any other error raised while waiting, such as for a deadlock or a cancelled
+    /// query.
+    pub fn with_lock_timeout(
+        oid: pg_sys::Oid,
+        lockmode: pg_sys::LOCKMODE,
+        timeout: Duration,
+    ) -> std::result::Result<Self, WouldBlock> {
+        // only the lock is taken in the subtransaction, as the relation would be closed with it
+        crate::lock::with_lock_timeout(timeout, || unsafe {
+            pg_sys::LockRelationOid(oid, lockmode)
+        })?;
+        Ok(unsafe { PgRelation::open_locked(oid, lockmode) })
+    }
+
+    /// Open a relation this transaction has just locked in `lockmode`, and release that lock
+    /// when this instance is dropped
+    unsafe fn open_locked(oid: pg_sys::Oid, lockmode: pg_sys::LOCKMODE) -> Self {
+        let rel = pg_sys::relation_open(oid, pg_sys::NoLock as pg_sys::LOCKMODE);
+        PgRelation::new(rel, true, Some(lockmode))
+    }
+
+    fn new(ptr: pg_sys::Relation, need_close: bool, lockmode: Option<pg_sys::LOCKMODE>) -> Self {
+        PgRelation {
+            boxed: PgBox::from_pg(ptr),
+            need_close,
+            lockmode,
+            #[cfg(debug_assertions)]
+            invalidations: if ptr.is_null() {
+                0
+            } else {
+                invalidation::count(unsafe { (*ptr).rd_id })
+            },
+        }
+    }
+
+    /// Given a relation oid, use `pg_sys::relation_open(oid, NoLock)` to open a relation the
+    /// caller has already locked, such as one the executor hands to us.
+    ///
+    /// The relation is closed via `pg_sys::relation_close(rel, NoLock)` when this instance is
+    /// dropped, so it neither acquires nor releases a lock.  Unlike `open()`, an error is raised
+    /// if the relation does not exist.
+    ///
+    /// ## Safety
+    ///
+    /// The caller must already hold a suitable lock on the relation, else there are nasty race
+    /// conditions.
+    ///
+    /// As such, this function is unsafe as we cannot guarantee that this requirement is true.
+    pub unsafe fn open_no_lock(oid: pg_sys::Oid) -> Self {
+        PgRelation::with_lock(oid, pg_sys::NoLock as pg_sys::LOCKMODE)
+    }
+
     /// Given a relation name, use `pg_sys::to_regclass` to look up its oid, and then
     /// `pg_sys::RelationIdGetRelation()` to open the relation.
     ///
@@ -146,7 +213,7 @@ impl PgRelation {
     ///
     /// Note that the name is only unique within the containing namespace.
     pub fn name(&self) -> &str {
-        let rd_rel = unsafe { self.boxed.rd_rel.as_ref() }.unwrap();
+        let rd_rel = unsafe { self.rel().rd_rel.as_ref() }.unwrap();
         name_data_to_str(&rd_rel.relname)
     }
 
@@ -154,14 +221,13 @@ impl PgRelation {
     ///          Returns the OID of the relation
     #[inline]
     pub fn oid(&self) -> pg_sys::Oid {
-        let rel = &self.boxed;
-        rel.rd_id
+        self.boxed.rd_id
     }
 
     /// RelationGetNamespace
     ///            Returns the rel's namespace OID.
     pub fn namespace_oid(&self) -> pg_sys::Oid {
-        let rd_rel: PgBox<pg_sys::FormData_pg_class> = PgBox::from_pg(self.boxed.rd_rel);
+        let rd_rel: PgBox<pg_sys::FormData_pg_class> = PgBox::from_pg(self.rel().rd_rel);
         rd_rel.relnamespace
     }
 
@@ -172,15 +238,109 @@ impl PgRelation {
             .expect("unable to convert namespace name to UTF8")
     }
 
+    /// The Oid of the role that owns this relation, its `pg_class.relowner`
+    pub fn owner_oid(&self) -> pg_sys::Oid {
+        let rd_rel = unsafe { self.rel().rd_rel.as_ref() }.unwrap();
+        rd_rel.relowner
+    }
+
+    /// Can the current user, as `GetUserId()` reports it, act as this relation's owner?
+    ///
+    /// That's the case when it's a superuser, or has the privileges of the owning role, either by
+    /// being that role or by inheriting from a role that's a member of it.  This is the check
+    /// Postgres makes before `ALTER TABLE` or `DROP TABLE` (`pg_class_ownercheck()`), without
+    /// looking the relation up again
+    pub fn is_owned_by_current_user(&self) -> bool {
+        unsafe {
+            let user = pg_sys::GetUserId();
+            pg_sys::superuser_arg(user) || pg_sys::has_privs_of_role(user, self.owner_oid())
+        }
+    }
+
+    /// The `pg_attribute` catalog entry of the attribute numbered `attnum`, with everything the
+    /// catalog knows about the column, such as `atttypid`, `atttypmod`, `attnotnull`, `attndims`
+    /// and `attcollation`.
+    ///
+    /// Attribute numbers start at 1.  Returns `None` for system attributes (`attnum <= 0`), which
+    /// aren't part of the relation's tuple descriptor, and for numbers past the last attribute.
+    /// Dropped attributes keep their number, and are returned with `attisdropped` set
+    pub fn attribute_form(&self, attnum: i16) -> Option<&pg_sys::FormData_pg_attribute> {
+        let tupdesc = unsafe { self.rel().rd_att.as_ref() }?;
+        if attnum < 1 || attnum as i32 > tupdesc.natts {
+            None
+        } else {
+            Some(crate::tupdesc::tupdesc_get_attr(
+                tupdesc,
+                (attnum - 1) as usize,
+            ))
+        }
+    }
+
     /// If this `PgRelation` represents an index, return the `PgRelation` for the heap
     /// relation to which it is attached
     pub fn heap_relation(&self) -> Option<PgRelation> {
-        let rd_index: PgBox<pg_sys::FormData_pg_index> = PgBox::from_pg(self.boxed.rd_index);
-        if rd_index.is_null() {
-            None
-        } else {
-            Some(unsafe { PgRelation::open(rd_index.indrelid) })
+        self.index_form()
+            .map(|index| unsafe { PgRelation::open(index.indrelid) })
+    }
+
+    /// If this `PgRelation` represents an index, return its `pg_index` catalog entry (`rd_index`),
+    /// which carries flags such as `indisunique`, `indisprimary` and `indisvalid`, along with the
+    /// `indkey` vector of indexed columns.
+    ///
+    /// Returns `None` if this relation is not an index
+    pub fn index_form(&self) -> Option<&pg_sys::FormData_pg_index> {
+        unsafe { self.rel().rd_index.as_ref() }
+    }
+
+    /// If this `PgRelation` represents an index, return the heap attribute numbers of its columns,
+    /// decoded from the `indkey` int2vector.  An attribute number of zero indicates an expression
+    /// column.  On Postgres 11+ any `INCLUDE` columns follow the key columns.
+    ///
+    /// Returns an empty `Vec` if this relation is not an index
+    pub fn index_key_attnums(&self) -> Vec<i16> {
+        match self.index_form() {
+            Some(index) => {
+                unsafe { index.indkey.values.as_slice(index.indnatts as usize) }.to_vec()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// If this `PgRelation` represents an index, return the expression trees of its expression
+    /// columns, one for each zero in `index_key_attnums()`, in order.  Empty if it has none.
+    ///
+    /// Postgres builds the trees from `pg_index.indexprs` the first time they're asked for, and
+    /// caches them in the relcache's memory context for the index.  What's returned is a copy,
+    /// allocated in the current memory context, so it can be walked, or even changed, after the
+    /// relation is closed or its relcache entry is rebuilt.
+    ///
+    /// ## Panics
+    ///
+    /// If this relation is not an index
+    pub fn index_expressions(&self) -> PgList<pg_sys::Node> {
+        if !self.is_index() {
+            panic!("\"{}\" is not an index", self.name());
         }
+
+        PgList::from_pg(unsafe { pg_sys::RelationGetIndexExpressions(self.rel().as_ptr()) })
+    }
+
+    /// If this `PgRelation` represents a partial index, return its `WHERE` clause's predicate, in
+    /// implicit-AND form:  a list of conditions that must all be true.  Empty if it's not a
+    /// partial index.
+    ///
+    /// Like `index_expressions()`, this is a copy, in the current memory context, of what's
+    /// cached in the relcache.
+    ///
+    /// ## Panics
+    ///
+    /// If this relation is not an index
+    pub fn index_predicate(&self) -> PgList<pg_sys::Node> {
+        if !self.is_index() {
+            panic!("\"{}\" is not an index", self.name());
+        }
+
+        PgList::from_pg(unsafe { pg_sys::RelationGetIndexPredicate(self.rel().as_ptr()) })
     }
 
     /// Return an iterator of indices, as `PgRelation`s, attached to this relation
@@ -189,7 +349,7 @@ impl PgRelation {
         lockmode: pg_sys::LOCKMODE,
     ) -> impl std::iter::Iterator<Item = PgRelation> {
         let list = PgList::<pg_sys::Oid>::from_pg(unsafe {
-            pg_sys::RelationGetIndexList(self.boxed.as_ptr())
+            pg_sys::RelationGetIndexList(self.rel().as_ptr())
         });
 
         list.iter_oid()
@@ -199,6 +359,128 @@ impl PgRelation {
             .into_iter()
     }
 
+    /// Return the relations that directly inherit from this one, which for a partitioned table
+    /// are its partitions, in oid order.  A partition that is itself partitioned is returned, but
+    /// not its partitions -- see `all_leaf_partitions()` for those.
+    ///
+    /// Each is locked with `lockmode` (unless it's `NoLock`) by `find_inheritance_children()`,
+    /// and like Postgres, the lock is held until the end of the transaction
+    pub fn partitions(&self, lockmode: pg_sys::LOCKMODE) -> Vec<PgRelation> {
+        let list = PgList::<pg_sys::Oid>::from_pg(unsafe {
+            pg_sys::find_inheritance_children(self.oid(), lockmode)
+        });
+
+        list.iter_oid()
+            .map(|oid| unsafe { PgRelation::open_no_lock(oid) })
+            .collect()
+    }
+
+    /// Return every partition at the bottom of this partitioned table's partition hierarchy,
+    /// which are the relations rows are actually stored in.  Partitions are locked as in
+    /// `partitions()`
+    pub fn all_leaf_partitions(&self, lockmode: pg_sys::LOCKMODE) -> Vec<PgRelation> {
+        let mut leaves = Vec::new();
+        for partition in self.partitions(lockmode) {
+            if partition.is_partitioned_table() {
+                leaves.extend(partition.all_leaf_partitions(lockmode));
+            } else {
+                leaves.push(partition);
+            }
+        }
+        leaves
+    }
+
+    /// Open every partition of this partitioned table, at every level of its partition
+    /// hierarchy, via `find_all_inheritors()`.  With `leaves_only`, partitions that are
+    /// themselves partitioned are left out, leaving the ones rows are actually stored in.
+    ///
+    /// Partitions are returned in breadth-first order, and each is locked with `lockmode`
+    /// (unless it's `NoLock`) until the end of the transaction.  A relation that isn't a
+    /// partitioned table has no partitions, even if other tables inherit from it
+    pub fn open_all_partitions(
+        &self,
+        lockmode: pg_sys::LOCKMODE,
+        leaves_only: bool,
+    ) -> Vec<PgRelation> {
+        if !self.is_partitioned_table() {
+            return Vec::new();
+        }
+
+        let list = PgList::<pg_sys::Oid>::from_pg(unsafe {
+            pg_sys::find_all_inheritors(self.oid(), lockmode, std::ptr::null_mut())
+        });
+
+        // the first is this relation itself
+        list.iter_oid()
+            .skip(1)
+            .map(|oid| unsafe { PgRelation::open_no_lock(oid) })
+            .filter(|partition| !leaves_only || !partition.is_partitioned_table())
+            .collect()
+    }
+
+    /// If this `PgRelation` represents a partitioned table, return its partition key as it would
+    /// appear in `CREATE TABLE`, such as `RANGE (created_at)`, via `pg_get_partkeydef()`
+    pub fn partition_key_text(&self) -> Option<String> {
+        unsafe {
+            direct_function_call::<String>(pg_sys::pg_get_partkeydef, vec![self.oid().into_datum()])
+        }
+    }
+
+    /// If this `PgRelation` represents a partition, return its bounds as they would appear in
+    /// `CREATE TABLE`, such as `FOR VALUES FROM (1) TO (100)` or `DEFAULT`, by deparsing
+    /// `pg_class.relpartbound` with `pg_get_expr()`
+    pub fn partition_bounds_text(&self) -> Option<String> {
+        unsafe {
+            let tuple = pg_sys::SearchSysCache(
+                pg_sys::SysCacheIdentifier_RELOID as i32,
+                self.oid() as pg_sys::Datum,
+                0,
+                0,
+                0,
+            );
+            if tuple.is_null() {
+                panic!("cache lookup failed for relation {}", self.oid());
+            }
+
+            let mut is_null = false;
+            let bound = pg_sys::SysCacheGetAttr(
+                pg_sys::SysCacheIdentifier_RELOID as i32,
+                tuple,
+                pg_sys::Anum_pg_class_relpartbound as pg_sys::AttrNumber,
+                &mut is_null,
+            );
+
+            // the bound points into the cached tuple, so deparse it before releasing the tuple
+            let text = if is_null {
+                None
+            } else {
+                direct_function_call::<String>(
+                    pg_sys::pg_get_expr,
+                    vec![Some(bound), self.oid().into_datum()],
+                )
+            };
+
+            pg_sys::ReleaseSysCache(tuple);
+            text
+        }
+    }
+
+    /// The triggers defined on this relation, in the order Postgres fires them, which is by name.
+    /// Disabled triggers are included, as are the internal triggers that enforce foreign keys.
+    ///
+    /// Returns an empty `Vec` if the relation has no triggers
+    pub fn triggers(&self) -> Vec<TriggerInfo> {
+        let trigdesc = match unsafe { self.rel().trigdesc.as_ref() } {
+            Some(trigdesc) => trigdesc,
+            None => return Vec::new(),
+        };
+
+        unsafe { std::slice::from_raw_parts(trigdesc.triggers, trigdesc.numtriggers as usize) }
+            .iter()
+            .map(TriggerInfo::from_pg)
+            .collect()
+    }
+
     /// Returned a wrapped `PgTupleDesc`
     ///
     /// The returned `PgTupleDesc` is tied to the lifetime of this `PgRelation` instance.
@@ -216,9 +498,52 @@ impl PgRelation {
         PgTupleDesc::from_relation(&self)
     }
 
+    /// Sequentially scan this relation, yielding each visible row deformed into a `Vec` with
+    /// one element per attribute (`relnatts`).  NULL values, and dropped attributes, are `None`.
+    ///
+    /// The scan uses `GetTransactionSnapshot()` as of this call, which is registered for as long
+    /// as the iterator lives.  Under `READ COMMITTED` this is a fresh snapshot that includes
+    /// changes made by earlier commands in the current transaction.
+    ///
+    /// Each tuple is copied into the `CurrentMemoryContext` as of the call to `next()`, so
+    /// pass-by-reference Datums remain valid after the iterator moves on to the next row, for as
+    /// long as that memory context lives.  When scanning large relations, consider iterating
+    /// within a memory context that can be reset periodically.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this relation is not a table or materialized view
+    pub fn scan_rows(&self) -> impl std::iter::Iterator<Item = Vec<Option<pg_sys::Datum>>> + '_ {
+        let tupdesc = self.rel().rd_att;
+        let natts = unsafe { (*tupdesc).natts } as usize;
+
+        TableScan::begin(self, &[]).map(move |tuple| {
+            let mut datums = vec![0 as pg_sys::Datum; natts];
+            let mut nulls = vec![false; natts];
+            unsafe {
+                pg_sys::heap_deform_tuple(
+                    tuple.as_ptr(),
+                    tupdesc,
+                    datums.as_mut_ptr(),
+                    nulls.as_mut_ptr(),
+                );
+            }
+
+            // pass-by-reference values point into the copy of the tuple, so leave it to the
+            // memory context
+            tuple.into_inner().into_pg();
+
+            datums
+                .into_iter()
+                .zip(nulls)
+                .map(|(datum, is_null)| if is_null { None } else { Some(datum) })
+                .collect()
+        })
+    }
+
     /// Number of tuples in this relation (not always up-to-date)
     pub fn reltuples(&self) -> Option<f32> {
-        let reltuples = unsafe { self.boxed.rd_rel.as_ref() }
+        let reltuples = unsafe { self.rel().rd_rel.as_ref() }
             .expect("rd_rel is NULL")
             .reltuples;
 
@@ -229,60 +554,308 @@ impl PgRelation {
         }
     }
 
+    /// The number of pages in this relation's main fork, as `RelationGetNumberOfBlocks()` returns.
+    /// Unlike `reltuples()`, this isn't an estimate:  it's the current size according to the
+    /// storage manager, and cheap to ask for.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this relation has no storage of its own, such as a view or a
+    /// partitioned table
+    pub fn number_of_blocks(&self) -> pg_sys::BlockNumber {
+        self.number_of_blocks_in_fork(pg_sys::ForkNumber_MAIN_FORKNUM)
+    }
+
+    /// The number of pages in one of this relation's forks, such as
+    /// `pg_sys::ForkNumber_FSM_FORKNUM` or `pg_sys::ForkNumber_VISIBILITYMAP_FORKNUM`.  A fork
+    /// that doesn't exist yet, such as the visibility map of a table that's never been vacuumed,
+    /// has zero pages.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this relation has no storage of its own, such as a view or a
+    /// partitioned table
+    pub fn number_of_blocks_in_fork(&self, fork: pg_sys::ForkNumber) -> pg_sys::BlockNumber {
+        if !self.has_storage() {
+            panic!("relation \"{}\" has no storage", self.name());
+        }
+
+        let rel = self.rel();
+        unsafe {
+            let smgr = pg_sys::smgropen(rel.rd_node, rel.rd_backend);
+            if !pg_sys::smgrexists(smgr, fork) {
+                return 0;
+            }
+            pg_sys::RelationGetNumberOfBlocksInFork(rel.as_ptr(), fork)
+        }
+    }
+
+    /// The size of this relation's main fork, in bytes:  `number_of_blocks() * BLCKSZ`
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this relation has no storage of its own, such as a view or a
+    /// partitioned table
+    pub fn size_in_bytes(&self) -> u64 {
+        self.number_of_blocks() as u64 * pg_sys::BLCKSZ as u64
+    }
+
+    /// Does this relation have storage of its own, and therefore blocks?
+    fn has_storage(&self) -> bool {
+        self.is_table()
+            || self.is_index()
+            || self.is_sequence()
+            || self.is_toast_value()
+            || self.is_matview()
+    }
+
     pub fn is_table(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_RELATION as c_char
     }
 
     pub fn is_matview(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_MATVIEW as c_char
     }
 
     pub fn is_index(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_INDEX as c_char
     }
 
     pub fn is_view(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_VIEW as c_char
     }
 
     pub fn is_sequence(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_SEQUENCE as c_char
     }
 
     pub fn is_composite_type(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_COMPOSITE_TYPE as c_char
     }
 
     pub fn is_foreign_table(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_FOREIGN_TABLE as c_char
     }
 
     pub fn is_partitioned_table(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_PARTITIONED_TABLE as c_char
     }
 
     pub fn is_toast_value(&self) -> bool {
         let rd_rel: &pg_sys::FormData_pg_class =
-            unsafe { self.boxed.rd_rel.as_ref().expect("rd_rel is NULL") };
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
         rd_rel.relkind == pg_sys::RELKIND_TOASTVALUE as c_char
     }
 
+    /// How this relation's old rows are identified in the WAL for logical decoding
+    /// (`ALTER TABLE ... REPLICA IDENTITY`), from `rd_rel.relreplident`
+    pub fn replica_identity_type(&self) -> ReplicaIdentity {
+        let rd_rel: &pg_sys::FormData_pg_class =
+            unsafe { self.rel().rd_rel.as_ref().expect("rd_rel is NULL") };
+        match rd_rel.relreplident as u8 {
+            pg_sys::REPLICA_IDENTITY_DEFAULT => ReplicaIdentity::Default,
+            pg_sys::REPLICA_IDENTITY_NOTHING => ReplicaIdentity::Nothing,
+            pg_sys::REPLICA_IDENTITY_FULL => ReplicaIdentity::Full,
+            pg_sys::REPLICA_IDENTITY_INDEX => ReplicaIdentity::Index,
+            other => panic!("unrecognized relreplident: {}", other as char),
+        }
+    }
+
+    /// The index logical decoding uses to identify changed rows, as determined by
+    /// `RelationGetReplicaIndex()`:  the primary key for `ReplicaIdentity::Default`, or the
+    /// chosen index for `ReplicaIdentity::Index`.  This isn't necessarily the primary key.
+    ///
+    /// The index is opened with an `AccessShareLock`, and closed when the returned `PgRelation`
+    /// is dropped.  Returns `None` if the relation has no such index
+    pub fn replica_identity_index(&self) -> Option<PgRelation> {
+        let oid = unsafe { pg_sys::RelationGetReplicaIndex(self.rel().as_ptr()) };
+        if oid == pg_sys::InvalidOid {
+            None
+        } else {
+            Some(PgRelation::with_lock(
+                oid,
+                pg_sys::AccessShareLock as pg_sys::LOCKMODE,
+            ))
+        }
+    }
+
+    /// Lock the tuple at `tid`, as `SELECT ... FOR UPDATE` and its siblings do, waiting for any
+    /// conflicting lock to be released.  The lock is held until the end of the transaction.
+    ///
+    /// `mode` is one of `pg_sys::LockTupleMode_LockTupleKeyShare`, `LockTupleShare`,
+    /// `LockTupleNoKeyExclusive` or `LockTupleExclusive`.
+    ///
+    /// A tuple that a concurrent transaction has updated isn't locked, and newer versions aren't
+    /// followed:  it's up to the caller to decide whether to lock the `ctid` of
+    /// `LockTupleResult::Updated` instead.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this relation is not a table or materialized view
+    pub fn lock_tuple(
+        &self,
+        tid: pg_sys::ItemPointerData,
+        mode: pg_sys::LockTupleMode,
+    ) -> LockTupleResult {
+        self.lock_tuple_with_policy(tid, mode, pg_sys::LockWaitPolicy_LockWaitBlock)
+    }
+
+    /// Like `lock_tuple()`, but returns `LockTupleResult::WouldBlock` rather than waiting for a
+    /// conflicting lock, as `SELECT ... FOR UPDATE SKIP LOCKED` does
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this relation is not a table or materialized view
+    pub fn try_lock_tuple(
+        &self,
+        tid: pg_sys::ItemPointerData,
+        mode: pg_sys::LockTupleMode,
+    ) -> LockTupleResult {
+        self.lock_tuple_with_policy(tid, mode, pg_sys::LockWaitPolicy_LockWaitSkip)
+    }
+
+    fn lock_tuple_with_policy(
+        &self,
+        tid: pg_sys::ItemPointerData,
+        mode: pg_sys::LockTupleMode,
+        wait_policy: pg_sys::LockWaitPolicy,
+    ) -> LockTupleResult {
+        if !self.is_table() && !self.is_matview() {
+            panic!(
+                "relation \"{}\" is not a table or materialized view",
+                self.name()
+            );
+        }
+
+        unsafe { lock_tuple(self.rel().as_ptr(), tid, mode, wait_policy) }
+    }
+
+    /// Re-open this relation, with the lock it was opened with, so that it can be used again
+    /// after its relcache entry was invalidated.  See "Invalidation" above.
+    ///
+    /// A relation wrapped with `from_pg()` is re-opened with `RelationIdGetRelation()`, and the
+    /// new reference is closed when this instance is dropped.
+    ///
+    /// This function will raise an error if the relation no longer exists
+    pub fn revalidate(&mut self) {
+        let oid = self.oid();
+        let rel = match self.lockmode {
+            // take the new reference, and any lock, before releasing the old
+            Some(lockmode) => unsafe { pg_sys::relation_open(oid, lockmode) },
+            None => {
+                let rel = unsafe { pg_sys::RelationIdGetRelation(oid) };
+                if rel.is_null() {
+                    panic!("Cannot open relation with oid={}", oid);
+                }
+                rel
+            }
+        };
+
+        // dropping the old instance closes the old reference, if it's ours to close
+        *self = PgRelation::new(rel, true, self.lockmode);
+    }
+
+    /// Run `ANALYZE` on this relation, as part of the current transaction, and then `revalidate()`
+    /// it, as updating its statistics invalidates its relcache entry.
+    ///
+    /// ## Locking
+    ///
+    /// `ANALYZE` takes a `ShareUpdateExclusiveLock` on the relation until the end of the
+    /// transaction.  That conflicts with other `ANALYZE`s and `VACUUM`s, and most DDL, but not with
+    /// reads or writes.  If this relation was opened with a weaker lock, it's upgraded, which can
+    /// deadlock with another backend doing the same.
+    ///
+    /// ## Transactions
+    ///
+    /// Like the `ANALYZE` command, this can run inside a transaction block or a function, and in
+    /// a background worker's `BackgroundWorker::transaction()`.  Its statistics are rolled back if
+    /// the transaction aborts.
+    pub fn analyze(&mut self) {
+        unsafe {
+            vacuum_relation(
+                self.oid(),
+                pg_sys::VacuumOption_VACOPT_ANALYZE as i32,
+                false,
+            );
+        }
+        self.revalidate();
+    }
+
+    /// Run `VACUUM` on this relation, with `options`.
+    ///
+    /// ## Transactions
+    ///
+    /// Like the `VACUUM` command, this can't run inside a transaction block or a function:  it
+    /// commits the current transaction, vacuums the relation in transactions of its own, and then
+    /// starts a new one before returning.  So it can only be called from a background worker,
+    /// outside of any `Spi::connect()`, such as in the closure given to
+    /// `BackgroundWorker::transaction()`.  Anywhere else it raises the same ERROR as `VACUUM`,
+    /// such as "VACUUM cannot be executed from a function".
+    ///
+    /// Because the transaction this relation was opened in is committed, this consumes the relation
+    /// and closes it first.  Nothing else from that transaction, such as another `PgRelation` or
+    /// memory allocated in `CurrentMemoryContext`, can be used once it returns.  If the caller had
+    /// an active snapshot, a new one is pushed for the new transaction.
+    ///
+    /// ## Locking
+    ///
+    /// Any lock this relation was opened with is released.  `VACUUM` takes a
+    /// `ShareUpdateExclusiveLock` on the relation, as `analyze()` does, while
+    /// `VacuumOptions::FULL` takes an `AccessExclusiveLock`, which blocks all access to the
+    /// relation while it's rewritten.  Each is released when `VACUUM` commits.
+    pub fn vacuum(self, options: VacuumOptions) {
+        let oid = self.oid();
+        drop(self);
+
+        let is_top_level = unsafe { pg_sys::IsBackgroundWorker } && pg_sys::guard::spi_depth() == 0;
+        unsafe {
+            let had_snapshot = pg_sys::ActiveSnapshotSet();
+            vacuum_relation(
+                oid,
+                options.bits() | pg_sys::VacuumOption_VACOPT_VACUUM as i32,
+                is_top_level,
+            );
+            if had_snapshot && !pg_sys::ActiveSnapshotSet() {
+                pg_sys::PushActiveSnapshot(pg_sys::GetTransactionSnapshot());
+            }
+        }
+    }
+
+    /// The relcache entry, after checking that it hasn't been invalidated since we opened it
+    fn rel(&self) -> &PgBox<pg_sys::RelationData> {
+        #[cfg(debug_assertions)]
+        {
+            if !self.boxed.is_null() && invalidation::count(self.boxed.rd_id) != self.invalidations
+            {
+                let relname = unsafe { self.boxed.rd_rel.as_ref() }
+                    .map(|rd_rel| name_data_to_str(&rd_rel.relname))
+                    .unwrap_or("???");
+                panic!(
+                    "relation \"{}\" was invalidated after it was opened; re-open it or call PgRelation::revalidate()",
+                    relname
+                );
+            }
+        }
+
+        &self.boxed
+    }
+
     /// ensures that the returned `PgRelation` is closed by Rust when it is dropped
     pub fn to_owned(mut self) -> Self {
         self.need_close = true;
@@ -290,13 +863,322 @@ impl PgRelation {
     }
 }
 
+/// A relation's replica identity, which determines what logical decoding records about the old
+/// version of an updated or deleted row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicaIdentity {
+    /// The primary key's columns, if there is a primary key
+    Default,
+
+    /// Nothing
+    Nothing,
+
+    /// All the columns
+    Full,
+
+    /// The columns of a specific unique index
+    Index,
+}
+
+/// The outcome of `PgRelation::lock_tuple()`
+#[derive(Debug, Clone, Copy)]
+pub enum LockTupleResult {
+    /// The tuple is locked
+    Ok,
+
+    /// A concurrent transaction updated the tuple and committed, and `ctid` is the newer version
+    Updated { ctid: pg_sys::ItemPointerData },
+
+    /// A concurrent transaction deleted the tuple and committed
+    Deleted,
+
+    /// Another transaction holds a conflicting lock, and `try_lock_tuple()` didn't wait for it
+    WouldBlock,
+
+    /// The current transaction updated or deleted the tuple, in a later command than the one
+    /// locking it
+    SelfModified,
+
+    /// The tuple isn't visible to the current command
+    Invisible,
+}
+
+bitflags! {
+    /// Options for `PgRelation::vacuum()`, as for the `VACUUM` command
+    pub struct VacuumOptions: i32 {
+        /// Also `ANALYZE` the relation
+        const ANALYZE               = pg_sys::VacuumOption_VACOPT_ANALYZE as i32;
+        /// Report progress at `INFO`
+        const VERBOSE               = pg_sys::VacuumOption_VACOPT_VERBOSE as i32;
+        /// Freeze every tuple, as if the freeze ages were zero
+        const FREEZE                = pg_sys::VacuumOption_VACOPT_FREEZE as i32;
+        /// Rewrite the relation, which takes an `AccessExclusiveLock`
+        const FULL                  = pg_sys::VacuumOption_VACOPT_FULL as i32;
+        /// Don't skip pages the visibility map says are all-visible or all-frozen
+        const DISABLE_PAGE_SKIPPING = pg_sys::VacuumOption_VACOPT_DISABLE_PAGE_SKIPPING as i32;
+        /// Skip the relation, with a `WARNING`, if it can't be locked immediately
+        #[cfg(any(feature = "pg12", feature = "pg13"))]
+        const SKIP_LOCKED           = pg_sys::VacuumOption_VACOPT_SKIP_LOCKED as i32;
+    }
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn lock_tuple(
+    relation: pg_sys::Relation,
+    tid: pg_sys::ItemPointerData,
+    mode: pg_sys::LockTupleMode,
+    wait_policy: pg_sys::LockWaitPolicy,
+) -> LockTupleResult {
+    let mut tuple = PgBox::<pg_sys::HeapTupleData>::alloc0();
+    tuple.t_self = tid;
+    let mut buffer = pg_sys::InvalidBuffer as pg_sys::Buffer;
+    let mut hufd = pg_sys::HeapUpdateFailureData::default();
+
+    // `follow_updates` locks any versions of the tuple that are still being updated, as
+    // `ExecLockRows()` does
+    let result = pg_sys::heap_lock_tuple(
+        relation,
+        tuple.as_ptr(),
+        pg_sys::GetCurrentCommandId(true),
+        mode,
+        wait_policy,
+        true,
+        &mut buffer,
+        &mut hufd,
+    );
+    if buffer != pg_sys::InvalidBuffer as pg_sys::Buffer {
+        pg_sys::ReleaseBuffer(buffer);
+    }
+
+    match result {
+        pg_sys::HTSU_Result_HeapTupleMayBeUpdated => LockTupleResult::Ok,
+        pg_sys::HTSU_Result_HeapTupleUpdated => {
+            // a deleted tuple's ctid points at itself
+            if crate::item_pointer_get_both(hufd.ctid) == crate::item_pointer_get_both(tid) {
+                LockTupleResult::Deleted
+            } else {
+                LockTupleResult::Updated { ctid: hufd.ctid }
+            }
+        }
+        pg_sys::HTSU_Result_HeapTupleWouldBlock => LockTupleResult::WouldBlock,
+        pg_sys::HTSU_Result_HeapTupleSelfUpdated => LockTupleResult::SelfModified,
+        pg_sys::HTSU_Result_HeapTupleInvisible => LockTupleResult::Invisible,
+        other => panic!("unexpected heap_lock_tuple() result: {}", other),
+    }
+}
+
+/// `table_tuple_lock()` is a static inline function, so we call the table access method's
+/// `tuple_lock` directly
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn lock_tuple(
+    relation: pg_sys::Relation,
+    tid: pg_sys::ItemPointerData,
+    mode: pg_sys::LockTupleMode,
+    wait_policy: pg_sys::LockWaitPolicy,
+) -> LockTupleResult {
+    let tuple_lock = (*(*relation).rd_tableam)
+        .tuple_lock
+        .expect("table access method has no tuple_lock function");
+    let slot = pg_sys::table_slot_create(relation, std::ptr::null_mut());
+    let mut tid = tid;
+    let mut tmfd = pg_sys::TM_FailureData::default();
+    let (tid_ptr, tmfd_ptr) = (&mut tid as *mut _, &mut tmfd as *mut _);
+
+    // without TUPLE_LOCK_FLAG_FIND_LAST_VERSION the snapshot isn't used, and like
+    // `ExecLockRows()`, we lock any versions of the tuple that are still being updated.  The
+    // slot, and the buffer pin it may hold, is dropped even if locking raises an ERROR
+    let result = pg_try(|| {
+        tuple_lock(
+            relation,
+            tid_ptr,
+            pg_sys::GetLatestSnapshot(),
+            slot,
+            pg_sys::GetCurrentCommandId(true),
+            mode,
+            wait_policy,
+            pg_sys::TUPLE_LOCK_FLAG_LOCK_UPDATE_IN_PROGRESS as u8,
+            tmfd_ptr,
+        )
+    })
+    .finally_or_rethrow(|| pg_sys::ExecDropSingleTupleTableSlot(slot));
+
+    match result {
+        pg_sys::TM_Result_TM_Ok => LockTupleResult::Ok,
+        pg_sys::TM_Result_TM_Updated => LockTupleResult::Updated { ctid: tmfd.ctid },
+        pg_sys::TM_Result_TM_Deleted => LockTupleResult::Deleted,
+        pg_sys::TM_Result_TM_WouldBlock => LockTupleResult::WouldBlock,
+        pg_sys::TM_Result_TM_SelfModified => LockTupleResult::SelfModified,
+        pg_sys::TM_Result_TM_Invisible => LockTupleResult::Invisible,
+        other => panic!("unexpected table_tuple_lock() result: {}", other),
+    }
+}
+
+/// `VACUUM` or `ANALYZE` just `relid`, as `ExecVacuum()` would.
+///
+/// `vacuum()` makes its memory context a child of `PortalContext`, which a background worker
+/// doesn't have, so then it's given one for the duration
+unsafe fn vacuum_relation(relid: pg_sys::Oid, options: i32, is_top_level: bool) {
+    let portal_context = pg_sys::PortalContext;
+    if portal_context.is_null() {
+        pg_sys::PortalContext = pg_sys::AllocSetContextCreateExtended(
+            pg_sys::TopMemoryContext,
+            b"PgRelation vacuum\0".as_ptr() as *const c_char,
+            pg_sys::ALLOCSET_DEFAULT_MINSIZE as usize,
+            pg_sys::ALLOCSET_DEFAULT_INITSIZE as usize,
+            pg_sys::ALLOCSET_DEFAULT_MAXSIZE as usize,
+        );
+    }
+
+    pg_try(|| call_vacuum(relid, options, is_top_level)).finally_or_rethrow(|| {
+        if portal_context.is_null() {
+            pg_sys::MemoryContextDelete(pg_sys::PortalContext);
+            pg_sys::PortalContext = portal_context;
+        }
+    })
+}
+
+/// The freeze ages for `options`, where -1 means to use the GUC's value
+fn freeze_age(options: i32) -> i32 {
+    if options & pg_sys::VacuumOption_VACOPT_FREEZE as i32 != 0 {
+        0
+    } else {
+        -1
+    }
+}
+
+#[cfg(feature = "pg10")]
+unsafe fn call_vacuum(relid: pg_sys::Oid, options: i32, is_top_level: bool) {
+    let mut params = pg_sys::VacuumParams {
+        freeze_min_age: freeze_age(options),
+        freeze_table_age: freeze_age(options),
+        multixact_freeze_min_age: freeze_age(options),
+        multixact_freeze_table_age: freeze_age(options),
+        is_wraparound: false,
+        log_min_duration: -1,
+    };
+
+    pg_sys::vacuum(
+        options,
+        std::ptr::null_mut(),
+        relid,
+        &mut params,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+        is_top_level,
+    );
+}
+
+#[cfg(feature = "pg11")]
+unsafe fn call_vacuum(relid: pg_sys::Oid, options: i32, is_top_level: bool) {
+    let mut params = pg_sys::VacuumParams {
+        freeze_min_age: freeze_age(options),
+        freeze_table_age: freeze_age(options),
+        multixact_freeze_min_age: freeze_age(options),
+        multixact_freeze_table_age: freeze_age(options),
+        is_wraparound: false,
+        log_min_duration: -1,
+    };
+    let mut relations = PgList::<pg_sys::VacuumRelation>::new();
+    relations.push(pg_sys::makeVacuumRelation(
+        std::ptr::null_mut(),
+        relid,
+        std::ptr::null_mut(),
+    ));
+
+    pg_sys::vacuum(
+        options,
+        relations.into_pg(),
+        &mut params,
+        std::ptr::null_mut(),
+        is_top_level,
+    );
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn call_vacuum(relid: pg_sys::Oid, options: i32, is_top_level: bool) {
+    // `index_cleanup` and `truncate` default to the relation's reloptions, and `nworkers` to 0
+    let mut params = pg_sys::VacuumParams {
+        options,
+        freeze_min_age: freeze_age(options),
+        freeze_table_age: freeze_age(options),
+        multixact_freeze_min_age: freeze_age(options),
+        multixact_freeze_table_age: freeze_age(options),
+        is_wraparound: false,
+        log_min_duration: -1,
+        ..Default::default()
+    };
+    let mut relations = PgList::<pg_sys::VacuumRelation>::new();
+    relations.push(pg_sys::makeVacuumRelation(
+        std::ptr::null_mut(),
+        relid,
+        std::ptr::null_mut(),
+    ));
+
+    pg_sys::vacuum(
+        relations.into_pg(),
+        &mut params,
+        std::ptr::null_mut(),
+        is_top_level,
+    );
+}
+
+/// Counts of relcache invalidations, by relation, for debug builds to notice that a `PgRelation`
+/// has been invalidated
+#[cfg(debug_assertions)]
+mod invalidation {
+    use crate::{pg_guard, pg_sys};
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Invalidations {
+        /// Invalidations of every relation at once, such as after a sinval queue overflow
+        all: u64,
+        by_oid: HashMap<pg_sys::Oid, u64>,
+    }
+
+    // Postgres has a fixed number of relcache callback slots, and a callback can't be unregistered,
+    // so ours is registered the first time we count, and then never again for the backend's life
+    static mut INVALIDATIONS: Option<Invalidations> = None;
+
+    /// How many times has `relid`'s relcache entry been invalidated since we started counting?
+    /// An `InvalidOid` only counts invalidations of every relation.
+    pub(super) fn count(relid: pg_sys::Oid) -> u64 {
+        let invalidations = unsafe {
+            if INVALIDATIONS.is_none() {
+                // an ERROR here, such as when every slot is taken, leaves us to try again next time
+                pg_sys::CacheRegisterRelcacheCallback(Some(count_invalidation), 0);
+                INVALIDATIONS = Some(Invalidations::default());
+            }
+            INVALIDATIONS.as_ref().unwrap()
+        };
+        invalidations.all + invalidations.by_oid.get(&relid).cloned().unwrap_or(0)
+    }
+
+    #[pg_guard]
+    unsafe extern "C" fn count_invalidation(_arg: pg_sys::Datum, relid: pg_sys::Oid) {
+        if let Some(invalidations) = INVALIDATIONS.as_mut() {
+            // Postgres passes InvalidOid when it invalidates every relation
+            if relid == pg_sys::InvalidOid {
+                invalidations.all += 1;
+            } else {
+                *invalidations.by_oid.entry(relid).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
 impl Clone for PgRelation {
     /// Same as calling `PgRelation::with_lock(AccessShareLock)` on the underlying relation id
     fn clone(&self) -> Self {
-        PgRelation::with_lock(self.rd_id, pg_sys::AccessShareLock as pg_sys::LOCKMODE)
+        PgRelation::with_lock(self.oid(), pg_sys::AccessShareLock as pg_sys::LOCKMODE)
     }
 }
 
+/// A `regclass` Datum is opened with an `AccessShareLock`, which is released when the
+/// `PgRelation` is dropped.
+///
+/// The relation comes from the relcache rather than the current memory context, so one read from
+/// an SPI result, such as with `Spi::get_one::<PgRelation>()`, stays open after `SPI_finish()`
 impl FromDatum for PgRelation {
     unsafe fn from_datum(datum: pg_sys::Datum, is_null: bool, _typoid: u32) -> Option<PgRelation> {
         if is_null {
@@ -311,7 +1193,14 @@ impl FromDatum for PgRelation {
 }
 
 impl IntoDatum for PgRelation {
-    fn into_datum(self) -> Option<pg_sys::Datum> {
+    /// The relation's oid.  The relation is closed, but a lock it was opened with is kept until
+    /// the end of the transaction, so that the relation can't be dropped or altered before
+    /// whoever gets the oid opens it again.  `Spi::connect()` relies on this when it copies a
+    /// `PgRelation` out of SPI as a Datum
+    fn into_datum(mut self) -> Option<pg_sys::Datum> {
+        if self.lockmode.is_some() {
+            self.lockmode = Some(pg_sys::NoLock as pg_sys::LOCKMODE);
+        }
         Some(self.oid() as pg_sys::Datum)
     }
 
@@ -324,7 +1213,7 @@ impl Deref for PgRelation {
     type Target = PgBox<pg_sys::RelationData>;
 
     fn deref(&self) -> &Self::Target {
-        &self.boxed
+        self.rel()
     }
 }
 
@@ -0,0 +1,359 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Sleeping and retrying that stay responsive to Postgres, for code that talks to flaky external
+//! resources.
+//!
+//! `std::thread::sleep()` ignores the process latch, so a backend sleeping in it can't be
+//! cancelled, and a background worker sleeping in it delays a shutdown until it wakes up.
+//! `sleep()` waits on the latch instead, and `retry()` sleeps between attempts with it.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//! use std::io::{Read, Write};
+//! use std::net::TcpStream;
+//! use std::time::Duration;
+//!
+//! /// What the server at `addr` says to an HTTP `GET /`, within `timeout`
+//! fn fetch(addr: &str, timeout: Duration) -> Result<String, std::io::Error> {
+//!     let addr = addr.parse().expect("invalid address");
+//!     let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+//!     stream.set_read_timeout(Some(timeout))?;
+//!     stream.write_all(b"GET / HTTP/1.0\r\n\r\n")?;
+//!
+//!     let mut body = String::new();
+//!     stream.read_to_string(&mut body)?;
+//!     Ok(body)
+//! }
+//!
+//! let policy = RetryPolicy::new(5)
+//!     .with_backoff(Duration::from_millis(100), Duration::from_secs(5))
+//!     .with_attempt_timeout(Duration::from_secs(2));
+//! let body = retry(&policy, |attempt| {
+//!     fetch("127.0.0.1:8080", attempt.remaining().unwrap()).map_err(|e| match e.kind() {
+//!         std::io::ErrorKind::TimedOut | std::io::ErrorKind::ConnectionRefused => {
+//!             AttemptError::Transient(e)
+//!         }
+//!         _ => AttemptError::Permanent(e),
+//!     })
+//! });
+//! ```
+use crate::{bgworkers::BackgroundWorker, pg_sys};
+use std::os::raw::c_long;
+use std::time::{Duration, Instant};
+
+/// Why `sleep()` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeReason {
+    /// The whole duration passed
+    Elapsed,
+
+    /// The process latch was set, such as by a signal handler, or another process
+    LatchSet,
+
+    /// The postmaster died, and this process should exit as soon as it can
+    PostmasterDeath,
+}
+
+/// Sleep for `duration` on the process latch, returning early if the latch is set or the
+/// postmaster dies.
+///
+/// The latch is reset before returning, and any pending interrupt is then handled, so a cancelled
+/// query raises its ERROR from here rather than after the full duration
+pub fn sleep(duration: Duration) -> WakeReason {
+    // round up, so that a sub-millisecond sleep still sleeps
+    let timeout = ((duration.as_micros() + 999) / 1000).min(c_long::MAX as u128) as c_long;
+
+    let events = unsafe {
+        pg_sys::WaitLatch(
+            pg_sys::MyLatch,
+            (pg_sys::WL_LATCH_SET | pg_sys::WL_TIMEOUT | pg_sys::WL_POSTMASTER_DEATH) as i32,
+            timeout,
+            pg_sys::PG_WAIT_EXTENSION,
+        )
+    };
+
+    if events & pg_sys::WL_POSTMASTER_DEATH as i32 != 0 {
+        WakeReason::PostmasterDeath
+    } else if events & pg_sys::WL_LATCH_SET as i32 != 0 {
+        unsafe {
+            pg_sys::ResetLatch(pg_sys::MyLatch);
+        }
+        check_for_interrupts!();
+        WakeReason::LatchSet
+    } else {
+        WakeReason::Elapsed
+    }
+}
+
+/// How `retry()` retries:  how many attempts it makes, and how long it waits between them.
+///
+/// The wait after the `n`th attempt is `initial_backoff * multiplier^(n - 1)`, up to
+/// `max_backoff`, less a random fraction of up to `jitter` of that, so that many backends
+/// retrying the same resource don't all do so at once
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    attempt_timeout: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// A policy of at most `max_attempts` attempts, waiting 100ms after the first and doubling
+    /// up to 10s, with a jitter of 0.5 and no per-attempt timeout.  Panics if `max_attempts` is 0
+    pub fn new(max_attempts: u32) -> Self {
+        if max_attempts == 0 {
+            panic!("a RetryPolicy needs at least one attempt");
+        }
+
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.5,
+            attempt_timeout: None,
+        }
+    }
+
+    /// Wait `initial` after the first attempt, and at most `max` after any
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Multiply the wait by `multiplier` after each attempt.  Panics if it's less than 1
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        if multiplier.is_nan() || multiplier < 1.0 {
+            panic!(
+                "a RetryPolicy's multiplier must be at least 1, not {}",
+                multiplier
+            );
+        }
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Shorten each wait by a random fraction of up to `jitter`, from 0 (never) to 1 (by up to
+    /// all of it).  Panics if it's out of that range
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        if !(0.0..=1.0).contains(&jitter) {
+            panic!("a RetryPolicy's jitter must be from 0 to 1, not {}", jitter);
+        }
+        self.jitter = jitter;
+        self
+    }
+
+    /// Give each attempt a deadline of `timeout` after it starts, which the operation can get
+    /// from its `Attempt`, and should give up by.  An attempt that returns after its deadline has
+    /// timed out, even if it succeeded, and is retried like one that failed with a transient
+    /// error, so only an operation that's safe to repeat should have a timeout
+    pub fn with_attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The wait after attempt number `attempt` (starting at 1), before jitter
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let secs = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::from_secs_f64(secs.min(self.max_backoff.as_secs_f64()))
+    }
+
+    fn jittered_backoff(&self, attempt: u32) -> Duration {
+        let random = unsafe { pg_sys::random() } as f64 / i32::MAX as f64;
+        self.backoff(attempt)
+            .mul_f64(1.0 - self.jitter * random.min(1.0))
+    }
+}
+
+/// The attempt `retry()` is making
+#[derive(Debug, Clone, Copy)]
+pub struct Attempt {
+    number: u32,
+    deadline: Option<Instant>,
+}
+
+impl Attempt {
+    /// Which attempt this is, starting at 1
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// When this attempt times out, if the policy has a per-attempt timeout.  See
+    /// `RetryPolicy::with_attempt_timeout()`
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// How long this attempt has left, if the policy has a per-attempt timeout.  This is zero once
+    /// the deadline has passed
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    fn timed_out(&self) -> bool {
+        self.deadline
+            .map_or(false, |deadline| Instant::now() > deadline)
+    }
+}
+
+/// How an attempt failed, which decides whether `retry()` tries again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttemptError<E> {
+    /// The operation might succeed if it's tried again, such as after a timeout
+    Transient(E),
+
+    /// The operation won't succeed however many times it's tried
+    Permanent(E),
+}
+
+/// Why `retry()` gave up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetryError<E> {
+    /// An attempt failed with a permanent error
+    Permanent(E),
+
+    /// Every attempt failed with a transient error or timed out, and `last` is the last attempt's
+    /// error, or `None` if it timed out
+    Exhausted { attempts: u32, last: Option<E> },
+
+    /// The background worker was told to shut down, or the postmaster died, while waiting to try
+    /// again after the transient error `last`, or after a timeout if it's `None`
+    Interrupted { attempts: u32, last: Option<E> },
+}
+
+impl<E> RetryError<E> {
+    /// The error of the last attempt, or `None` if it timed out
+    pub fn into_inner(self) -> Option<E> {
+        match self {
+            RetryError::Permanent(e) => Some(e),
+            RetryError::Exhausted { last, .. } => last,
+            RetryError::Interrupted { last, .. } => last,
+        }
+    }
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetryError::Permanent(e) => write!(f, "{}", e),
+            RetryError::Exhausted { attempts, last } => {
+                write!(f, "gave up after {} attempts: ", attempts)?;
+                write_last(f, last)
+            }
+            RetryError::Interrupted { attempts, last } => {
+                write!(f, "interrupted after {} attempts: ", attempts)?;
+                write_last(f, last)
+            }
+        }
+    }
+}
+
+fn write_last<E: std::fmt::Display>(
+    f: &mut std::fmt::Formatter<'_>,
+    last: &Option<E>,
+) -> std::fmt::Result {
+    match last {
+        Some(e) => write!(f, "{}", e),
+        None => write!(f, "the last attempt timed out"),
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for RetryError<E> {}
+
+/// Call `op` until it succeeds, fails with `AttemptError::Permanent`, or has been tried as many
+/// times as `policy` allows, sleeping between attempts with `sleep()`.
+///
+/// Only the `AttemptError::Transient` errors `op` returns, and attempts that run past the policy's
+/// per-attempt timeout, are retried.  A Postgres ERROR (or
+/// panic) raised by `op` isn't caught, and so propagates immediately, as do those raised for
+/// pending interrupts, which are checked before each attempt and whenever a wait is woken early.
+///
+/// A wait is cut short, with `RetryError::Interrupted`, if the postmaster dies, or the process is
+/// asked to shut down:  a background worker by its SIGTERM handler, or a backend by
+/// `pg_terminate_backend()` while interrupts are held.  A latch set for any other reason doesn't
+/// stop the wait
+pub fn retry<T, E, F>(policy: &RetryPolicy, mut op: F) -> Result<T, RetryError<E>>
+where
+    F: FnMut(&Attempt) -> Result<T, AttemptError<E>>,
+{
+    let mut number = 1;
+    loop {
+        check_for_interrupts!();
+
+        let attempt = Attempt {
+            number,
+            deadline: policy
+                .attempt_timeout
+                .map(|timeout| Instant::now() + timeout),
+        };
+        let last = match op(&attempt) {
+            Err(AttemptError::Permanent(e)) => return Err(RetryError::Permanent(e)),
+            _ if attempt.timed_out() => None,
+            Ok(result) => return Ok(result),
+            Err(AttemptError::Transient(e)) => Some(e),
+        };
+
+        if number >= policy.max_attempts {
+            return Err(RetryError::Exhausted {
+                attempts: number,
+                last,
+            });
+        }
+
+        if !wait(policy.jittered_backoff(number)) {
+            return Err(RetryError::Interrupted {
+                attempts: number,
+                last,
+            });
+        }
+        number += 1;
+    }
+}
+
+/// Sleep for `duration`, unless this process is told to shut down.  Returns whether it slept the
+/// whole time
+fn wait(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return true;
+        }
+
+        match sleep(remaining) {
+            WakeReason::Elapsed => return true,
+            WakeReason::PostmasterDeath => return false,
+            WakeReason::LatchSet if shutdown_requested() => return false,
+            WakeReason::LatchSet => {}
+        }
+    }
+}
+
+/// Has this process been asked to shut down?  A backend with the standard SIGTERM handler
+/// raises a FATAL error from `check_for_interrupts!()` instead, unless interrupts are held
+fn shutdown_requested() -> bool {
+    BackgroundWorker::sigterm_pending() || proc_die_pending()
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+fn proc_die_pending() -> bool {
+    unsafe { pg_sys::ProcDiePending }
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+fn proc_die_pending() -> bool {
+    unsafe { pg_sys::ProcDiePending != 0 }
+}
@@ -0,0 +1,231 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Session-scoped Rust state:  at most one value of each type per backend, which lives across
+//! function calls and transactions until it's removed or the backend exits.
+//!
+//! This is for per-backend caches, such as compiled patterns or loaded models, that would
+//! otherwise be a `static mut` or a `lazy_static!` that's never dropped.  Values are dropped by
+//! `remove()` and `clear_all()`, and when the backend exits.
+//!
+//! Postgres backends are single-threaded, so the values needn't be `Send` or `Sync`, but they
+//! can only be used from the backend's main thread.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//! use std::collections::HashMap;
+//!
+//! #[derive(Default)]
+//! struct UppercaseCache(HashMap<String, String>);
+//!
+//! #[pg_extern]
+//! fn cached_upper(input: &str) -> String {
+//!     session_state::with_or_init(UppercaseCache::default, |cache| {
+//!         cache
+//!             .0
+//!             .entry(input.to_string())
+//!             .or_insert_with(|| input.to_uppercase())
+//!             .clone()
+//!     })
+//! }
+//! ```
+use crate::{pg_guard, pg_sys, PgMemoryContexts};
+use once_cell::sync::OnceCell;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::thread::ThreadId;
+
+struct Entry {
+    /// `None` while it's lent to `with()`
+    value: Option<Box<dyn Any>>,
+    type_name: &'static str,
+    size: usize,
+}
+
+#[derive(Default)]
+struct Registry {
+    entries: HashMap<TypeId, Entry>,
+    session_end_hooks: Vec<Box<dyn FnOnce()>>,
+}
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        // the entries are dropped after this, and may use the session state themselves
+        unsafe {
+            REGISTRY = std::ptr::null_mut();
+        }
+    }
+}
+
+/// The registry, which is dropped whenever `CONTEXT` is reset
+static mut REGISTRY: *mut Registry = std::ptr::null_mut();
+static mut CONTEXT: pg_sys::MemoryContext = std::ptr::null_mut();
+static MAIN_THREAD: OnceCell<ThreadId> = OnceCell::new();
+
+/// Call `f` with the value of type `T`, which is made by `init` if there isn't one yet, and
+/// return what `f` returns.
+///
+/// `f` may use the rest of the session state, but not the value it's given, which is lent to it:
+/// using that again with `with()` or `with_or_init()`, or removing it with `remove()` or
+/// `clear_all()`, panics until `f` returns.
+pub fn with_or_init<T, R, I, F>(init: I, f: F) -> R
+where
+    T: Any,
+    I: FnOnce() -> T,
+    F: FnOnce(&mut T) -> R,
+{
+    if !contains::<T>() {
+        // `init` may use the session state too, so it's made before the registry is borrowed
+        let value = Box::new(init());
+        registry().entries.insert(
+            TypeId::of::<T>(),
+            Entry {
+                value: Some(value),
+                type_name: std::any::type_name::<T>(),
+                size: std::mem::size_of::<T>(),
+            },
+        );
+    }
+
+    with(f).unwrap()
+}
+
+/// Call `f` with the value of type `T`, if there is one, and return what `f` returns.  The
+/// value is lent to `f` as it is by `with_or_init()`
+pub fn with<T: Any, R, F: FnOnce(&mut T) -> R>(f: F) -> Option<R> {
+    let mut lent = Lent::<T>::take()?;
+    Some(f(lent.value.as_mut().unwrap()))
+}
+
+/// Is there a value of type `T`?
+pub fn contains<T: Any>() -> bool {
+    registry().entries.contains_key(&TypeId::of::<T>())
+}
+
+/// Remove the value of type `T`, if there is one, returning it to be dropped by the caller
+pub fn remove<T: Any>() -> Option<T> {
+    let entries = &mut registry().entries;
+    if let Some(entry) = entries.get(&TypeId::of::<T>()) {
+        if entry.value.is_none() {
+            panic!("{} is in use, so can't be removed", entry.type_name);
+        }
+    }
+
+    entries
+        .remove(&TypeId::of::<T>())
+        .map(|entry| *entry.value.unwrap().downcast::<T>().unwrap())
+}
+
+/// Drop every value, and free everything allocated in `memory_context()`
+pub fn clear_all() {
+    assert_main_thread();
+    unsafe {
+        if let Some(registry) = REGISTRY.as_ref() {
+            if let Some(entry) = registry
+                .entries
+                .values()
+                .find(|entry| entry.value.is_none())
+            {
+                panic!("{} is in use, so can't be removed", entry.type_name);
+            }
+        }
+
+        if !CONTEXT.is_null() {
+            // drops the registry, before the memory its values may use is free'd
+            pg_sys::MemoryContextReset(CONTEXT);
+        }
+    }
+}
+
+/// Call `hook` when the backend exits, before the session state is dropped.  Hooks are called in
+/// the order they're added
+pub fn on_session_end<F: FnOnce() + 'static>(hook: F) {
+    registry().session_end_hooks.push(Box::new(hook));
+}
+
+/// The memory context for any Postgres allocations a value makes, which is reset along with
+/// `clear_all()`.  It's named "pgx session state", so `MemoryContextStats()` accounts for it
+pub fn memory_context() -> PgMemoryContexts {
+    registry();
+    PgMemoryContexts::For(unsafe { CONTEXT })
+}
+
+/// The type name and size of each value, not including any memory it owns
+pub fn memory_usage() -> Vec<(&'static str, usize)> {
+    let mut usage = registry()
+        .entries
+        .values()
+        .map(|entry| (entry.type_name, entry.size))
+        .collect::<Vec<_>>();
+    usage.sort_unstable();
+    usage
+}
+
+/// A value taken out of its entry while it's lent to `with()`, which is put back when dropped,
+/// even if `with()`'s function panics
+struct Lent<T: Any> {
+    value: Option<Box<T>>,
+}
+
+impl<T: Any> Lent<T> {
+    fn take() -> Option<Self> {
+        let entry = registry().entries.get_mut(&TypeId::of::<T>())?;
+        match entry.value.take() {
+            Some(value) => Some(Lent {
+                value: Some(value.downcast::<T>().unwrap()),
+            }),
+            None => panic!("{} is already in use", entry.type_name),
+        }
+    }
+}
+
+impl<T: Any> Drop for Lent<T> {
+    fn drop(&mut self) {
+        let value = self.value.take().unwrap();
+        if let Some(entry) = registry().entries.get_mut(&TypeId::of::<T>()) {
+            entry.value = Some(value);
+        }
+    }
+}
+
+fn assert_main_thread() {
+    let current = std::thread::current().id();
+    if *MAIN_THREAD.get_or_init(|| current) != current {
+        panic!("session state can only be used from the backend's main thread");
+    }
+}
+
+fn registry() -> &'static mut Registry {
+    assert_main_thread();
+    unsafe {
+        if CONTEXT.is_null() {
+            CONTEXT = pg_sys::AllocSetContextCreateExtended(
+                pg_sys::TopMemoryContext,
+                // Postgres keeps this pointer, so it has to be static
+                b"pgx session state\0".as_ptr() as *const std::os::raw::c_char,
+                pg_sys::ALLOCSET_DEFAULT_MINSIZE as usize,
+                pg_sys::ALLOCSET_DEFAULT_INITSIZE as usize,
+                pg_sys::ALLOCSET_DEFAULT_MAXSIZE as usize,
+            );
+            pg_sys::on_proc_exit(Some(drop_at_exit), 0);
+        }
+
+        if REGISTRY.is_null() {
+            REGISTRY = PgMemoryContexts::For(CONTEXT).leak_and_drop_on_delete(Registry::default());
+        }
+
+        &mut *REGISTRY
+    }
+}
+
+#[pg_guard]
+unsafe extern "C" fn drop_at_exit(_code: i32, _arg: pg_sys::Datum) {
+    if let Some(registry) = REGISTRY.as_mut() {
+        for hook in std::mem::take(&mut registry.session_end_hooks) {
+            hook();
+        }
+    }
+    clear_all();
+}
@@ -0,0 +1,270 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Provides a safe wrapper around Postgres' `pg_sys::TupleTableSlot` struct, which is how the
+//! executor (and so custom scans and foreign-data wrappers) passes rows around
+use crate::{pg_sys, FromDatum, PgBox, PgRelation, PgTupleDesc};
+use std::marker::PhantomData;
+
+/// A `pg_sys::TupleTableSlot` holds either a physical `HeapTuple`, or a "virtual" tuple that's
+/// just an array of values and null flags, for tuples described by its `TupleDesc`.
+///
+/// Attribute numbers are 1-based, as they are throughout Postgres.
+pub struct PgTupleTableSlot<'a> {
+    slot: *mut pg_sys::TupleTableSlot,
+    need_drop: bool,
+    _tupdesc: PhantomData<&'a PgTupleDesc<'a>>,
+}
+
+impl<'a> PgTupleTableSlot<'a> {
+    /// Make a standalone slot, allocated in the `CurrentMemoryContext`, that holds tuples
+    /// described by `tupdesc`.  It's dropped with `ExecDropSingleTupleTableSlot()`.
+    pub fn new(tupdesc: &'a PgTupleDesc) -> Self {
+        #[cfg(any(feature = "pg10", feature = "pg11"))]
+        let slot = unsafe { pg_sys::MakeSingleTupleTableSlot(tupdesc.as_ptr()) };
+
+        // a heap tuple slot can hold virtual tuples too
+        #[cfg(any(feature = "pg12", feature = "pg13"))]
+        let slot =
+            unsafe { pg_sys::MakeSingleTupleTableSlot(tupdesc.as_ptr(), &pg_sys::TTSOpsHeapTuple) };
+
+        PgTupleTableSlot {
+            slot,
+            need_drop: true,
+            _tupdesc: PhantomData,
+        }
+    }
+
+    /// Make a standalone slot suited to the rows of `relation`, as `table_slot_create()` does, which
+    /// is the kind of slot a `TableScan` of the relation needs on Postgres 12 and later.  It's
+    /// dropped with `ExecDropSingleTupleTableSlot()`.
+    pub fn for_relation(relation: &'a PgRelation) -> Self {
+        #[cfg(any(feature = "pg10", feature = "pg11"))]
+        let slot = unsafe { pg_sys::MakeSingleTupleTableSlot(relation.rd_att) };
+
+        #[cfg(any(feature = "pg12", feature = "pg13"))]
+        let slot = unsafe { pg_sys::table_slot_create(relation.as_ptr(), std::ptr::null_mut()) };
+
+        PgTupleTableSlot {
+            slot,
+            need_drop: true,
+            _tupdesc: PhantomData,
+        }
+    }
+
+    /// Wrap a slot provided by Postgres, such as the `ss_ScanTupleSlot` of a scan node.  It's
+    /// left alone when this `PgTupleTableSlot` is dropped.
+    ///
+    /// ## Safety
+    ///
+    /// This method is unsafe as we cannot validate that the provided `pg_sys::TupleTableSlot` is
+    /// valid, or that it will outlive this `PgTupleTableSlot`
+    pub unsafe fn from_pg(slot: *mut pg_sys::TupleTableSlot) -> Self {
+        if slot.is_null() {
+            panic!("attempt to wrap a null TupleTableSlot");
+        }
+
+        PgTupleTableSlot {
+            slot,
+            need_drop: false,
+            _tupdesc: PhantomData,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut pg_sys::TupleTableSlot {
+        self.slot
+    }
+
+    pub fn tuple_desc(&self) -> pg_sys::TupleDesc {
+        unsafe { (*self.slot).tts_tupleDescriptor }
+    }
+
+    /// The number of attributes in the slot's `TupleDesc`
+    pub fn natts(&self) -> usize {
+        unsafe { (*self.tuple_desc()).natts as usize }
+    }
+
+    /// Does the slot not hold a tuple?
+    pub fn is_empty(&self) -> bool {
+        unsafe { slot_is_empty(self.slot) }
+    }
+
+    /// Store a physical tuple in the slot.  The slot takes ownership of the tuple and `pfree()`s it
+    /// when it's cleared or another tuple is stored, so the tuple can't be referenced anywhere
+    /// else, as is the case for one from `PgTupleDesc::form_tuple()`
+    pub fn store_heap_tuple(&mut self, tuple: PgBox<pg_sys::HeapTupleData>) {
+        #[cfg(any(feature = "pg10", feature = "pg11"))]
+        unsafe {
+            pg_sys::ExecStoreTuple(
+                tuple.into_pg(),
+                self.slot,
+                pg_sys::InvalidBuffer as pg_sys::Buffer,
+                true,
+            );
+        }
+
+        // unlike ExecStoreHeapTuple(), this also works for the buffer heap tuple slots of
+        // `for_relation()`, into which it stores a copy
+        #[cfg(any(feature = "pg12", feature = "pg13"))]
+        unsafe {
+            pg_sys::ExecForceStoreHeapTuple(tuple.into_pg(), self.slot, true);
+        }
+    }
+
+    /// Store a virtual tuple in the slot, with a value for each of its attributes, where `None`
+    /// is NULL.  Pass-by-reference values aren't copied, so they must live as long as the slot
+    /// holds them.
+    ///
+    /// ## Safety
+    ///
+    /// Each value must be a valid `Datum` of its attribute's type, as nothing else reading the slot
+    /// checks that it is, and a pass-by-reference value must outlive its time in the slot
+    pub unsafe fn store_virtual(&mut self, values: &[Option<pg_sys::Datum>]) {
+        let natts = self.natts();
+        if values.len() != natts {
+            panic!(
+                "tuple descriptor has {} attributes but {} values were provided",
+                natts,
+                values.len()
+            );
+        }
+
+        self.clear();
+        for (i, value) in values.iter().enumerate() {
+            *(*self.slot).tts_values.add(i) = value.unwrap_or(0);
+            *(*self.slot).tts_isnull.add(i) = value.is_none();
+        }
+        pg_sys::ExecStoreVirtualTuple(self.slot);
+    }
+
+    /// The value of attribute `attnum`, or `None` if it's NULL.  Only as many attributes as are
+    /// needed are deformed from a physical tuple.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the slot is empty, or if `attnum` is out of bounds
+    pub fn get_datum(&self, attnum: usize) -> Option<pg_sys::Datum> {
+        if self.is_empty() {
+            panic!("cannot get an attribute from an empty TupleTableSlot");
+        }
+        if attnum < 1 || attnum > self.natts() {
+            panic!(
+                "attribute number {} is out of bounds for a tuple with {} attributes",
+                attnum,
+                self.natts()
+            );
+        }
+
+        let mut is_null = false;
+        let datum = unsafe { slot_getattr(self.slot, attnum, &mut is_null) };
+        if is_null {
+            None
+        } else {
+            Some(datum)
+        }
+    }
+
+    /// The value of attribute `attnum` as a Rust type, or `None` if it's NULL
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the slot is empty, or if `attnum` is out of bounds
+    pub fn get<T: FromDatum>(&self, attnum: usize) -> Option<T> {
+        let datum = self.get_datum(attnum);
+        let typoid = unsafe { pg_sys::SPI_gettypeid(self.tuple_desc(), attnum as i32) };
+        unsafe { T::from_datum(datum.unwrap_or(0), datum.is_none(), typoid) }
+    }
+
+    /// A copy of the slot's row as a heap tuple, allocated in the `CurrentMemoryContext`, which is
+    /// `pfree()`'d when dropped
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the slot is empty
+    pub fn copy_heap_tuple(&self) -> PgBox<pg_sys::HeapTupleData> {
+        if self.is_empty() {
+            panic!("cannot copy the tuple of an empty TupleTableSlot");
+        }
+
+        PgBox::from_rust(unsafe { copy_heap_tuple(self.slot) })
+    }
+
+    /// Empty the slot, freeing the tuple it holds if it owns it
+    pub fn clear(&mut self) {
+        unsafe {
+            exec_clear_tuple(self.slot);
+        }
+    }
+}
+
+impl<'a> Drop for PgTupleTableSlot<'a> {
+    fn drop(&mut self) {
+        if self.need_drop {
+            unsafe {
+                pg_sys::ExecDropSingleTupleTableSlot(self.slot);
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+pub(crate) unsafe fn exec_clear_tuple(slot: *mut pg_sys::TupleTableSlot) {
+    pg_sys::ExecClearTuple(slot);
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+pub(crate) unsafe fn exec_clear_tuple(slot: *mut pg_sys::TupleTableSlot) {
+    // ExecClearTuple() is a static inline function
+    let clear = (*(*slot).tts_ops)
+        .clear
+        .expect("TupleTableSlotOps has no clear function");
+    clear(slot);
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn copy_heap_tuple(slot: *mut pg_sys::TupleTableSlot) -> pg_sys::HeapTuple {
+    pg_sys::ExecCopySlotTuple(slot)
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn copy_heap_tuple(slot: *mut pg_sys::TupleTableSlot) -> pg_sys::HeapTuple {
+    // ExecCopySlotHeapTuple() is a static inline function
+    let copy = (*(*slot).tts_ops)
+        .copy_heap_tuple
+        .expect("TupleTableSlotOps has no copy_heap_tuple function");
+    copy(slot)
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn slot_is_empty(slot: *mut pg_sys::TupleTableSlot) -> bool {
+    (*slot).tts_isempty
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn slot_is_empty(slot: *mut pg_sys::TupleTableSlot) -> bool {
+    (*slot).tts_flags as u32 & pg_sys::TTS_FLAG_EMPTY != 0
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn slot_getattr(
+    slot: *mut pg_sys::TupleTableSlot,
+    attnum: usize,
+    is_null: &mut bool,
+) -> pg_sys::Datum {
+    pg_sys::slot_getattr(slot, attnum as i32, is_null)
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn slot_getattr(
+    slot: *mut pg_sys::TupleTableSlot,
+    attnum: usize,
+    is_null: &mut bool,
+) -> pg_sys::Datum {
+    // slot_getattr() is a static inline function
+    if ((*slot).tts_nvalid as usize) < attnum {
+        pg_sys::slot_getsomeattrs_int(slot, attnum as i32);
+    }
+
+    *is_null = *(*slot).tts_isnull.add(attnum - 1);
+    *(*slot).tts_values.add(attnum - 1)
+}
@@ -0,0 +1,204 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Sort support for types that `#[derive(PostgresOrd)]`, so that `ORDER BY`, `CREATE INDEX`, and
+//! merge joins compare them without going through the `_cmp` SQL function for every comparison.
+//!
+//! It's opt-in:  for a type marked `#[sort_abbreviation]`, the derive creates a
+//! `<type>_sortsupport(internal)` function, which is registered as support function 2 of the
+//! type's btree operator class.  Other types are sorted with the `_cmp` function alone.
+//!
+//! ## Abbreviated keys
+//!
+//! A `PostgresType` is expensive to convert from a Datum, as it has to be deserialized, but most
+//! comparisons can be skipped altogether by implementing `SortAbbreviation` to give each value
+//! an "abbreviated key":  an `i64` that's ordered like the values are, but that may be the same
+//! for different values.  Each value is converted once, to make its key, and is only converted
+//! again when its key is the same as another's, and then only if the two aren't byte-for-byte
+//! the same value.
+//!
+//! Mark the type with `#[sort_abbreviation]`, and implement `SortAbbreviation` for it:
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+//! #[derive(PostgresType, PostgresEq, PostgresOrd)]
+//! #[sort_abbreviation]
+//! pub struct Name(String);
+//!
+//! impl SortAbbreviation for Name {
+//!     const ABBREVIATED: bool = true;
+//!
+//!     fn abbreviate(&self) -> i64 {
+//!         pgx::sortsupport::abbreviate_bytes(self.0.as_bytes())
+//!     }
+//! }
+//! ```
+//!
+//! Postgres gives up on abbreviated keys part way through a sort if they don't tell enough of
+//! the values apart to be worth making, such as when most of the `Name`s above start with the
+//! same 8 bytes, in which case the values are compared in full from then on.
+//!
+//! `bench_sort_abbreviation` in `pgx-tests` sorts a million values with and without abbreviated
+//! keys, and reports the times as an INFO message.
+use crate::{pg_sys, varlena_to_byte_slice, void_mut_ptr, FromDatum, PgMemoryContexts};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// How a type that `#[derive(PostgresOrd)]` abbreviates its values for sorting, which a type
+/// marked `#[sort_abbreviation]` must implement.  See the module documentation.
+pub trait SortAbbreviation {
+    /// Does this type have abbreviated keys?  If not, `abbreviate()` is never called
+    const ABBREVIATED: bool = false;
+
+    /// The abbreviated key of this value, which must be consistent with `Ord`:  when the key of
+    /// `a` is less than that of `b`, `a` must be less than `b`.  Values whose keys are equal
+    /// are compared in full
+    fn abbreviate(&self) -> i64 {
+        0
+    }
+}
+
+/// The abbreviated key of a sequence of bytes, compared as `&[u8]`s are, from its first 8
+/// bytes.  Values with the same first 8 bytes (or that are all of a shorter sequence followed
+/// by zeros) have the same key
+pub fn abbreviate_bytes(bytes: &[u8]) -> i64 {
+    let mut prefix = [0u8; 8];
+    let len = bytes.len().min(8);
+    prefix[..len].copy_from_slice(&bytes[..len]);
+
+    // flip the sign bit, so that the signed keys are ordered like the unsigned bytes
+    (u64::from_be_bytes(prefix) ^ (1 << 63)) as i64
+}
+
+/// Fill in the `SortSupport` of `T`, which is the body of the `<type>_sortsupport` function that
+/// `#[derive(PostgresOrd)]` creates
+///
+/// ## Safety
+///
+/// `ssup` must be a valid `SortSupport` Postgres is preparing, for values of `T`, which must be
+/// a varlena type, as every `PostgresType` is
+pub unsafe fn prepare_sort_support<T>(ssup: pg_sys::SortSupport)
+where
+    T: FromDatum + Ord + SortAbbreviation,
+{
+    let ssup = ssup.as_mut().expect("SortSupport is NULL");
+    ssup.comparator = Some(compare::<T>);
+
+    if T::ABBREVIATED && ssup.abbreviate {
+        let state =
+            PgMemoryContexts::For(ssup.ssup_cxt).leak_and_drop_on_delete(AbbreviationState {
+                input_count: 0,
+                distinct_keys: Some(HashSet::new()),
+            });
+
+        ssup.ssup_extra = state as *mut std::os::raw::c_void;
+        ssup.comparator = Some(compare_abbreviated);
+        ssup.abbrev_converter = Some(abbreviate::<T>);
+        ssup.abbrev_abort = Some(abort_abbreviation);
+        ssup.abbrev_full_comparator = Some(compare::<T>);
+    }
+}
+
+/// Once there are this many distinct abbreviated keys, they're always worth making
+const ENOUGH_DISTINCT_KEYS: usize = 100_000;
+
+/// What's known of the abbreviated keys made so far, to decide whether they're worth making
+struct AbbreviationState {
+    input_count: usize,
+
+    /// `None` once there are `ENOUGH_DISTINCT_KEYS`
+    distinct_keys: Option<HashSet<i64>>,
+}
+
+fn from_datum<T: FromDatum>(datum: pg_sys::Datum) -> T {
+    unsafe { T::from_datum(datum, false, pg_sys::InvalidOid) }
+        .expect("sort support was given a NULL Datum")
+}
+
+fn ordering_to_int(ordering: Ordering) -> std::os::raw::c_int {
+    match ordering {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+/// Compare two values in full.  Values with the same bytes are equal, without converting either
+/// of them, which is common among values with the same abbreviated key
+unsafe extern "C" fn compare<T: FromDatum + Ord>(
+    x: pg_sys::Datum,
+    y: pg_sys::Datum,
+    _ssup: pg_sys::SortSupport,
+) -> std::os::raw::c_int {
+    pg_sys::guard::guard(|| {
+        if x == y || same_bytes(x, y) {
+            0
+        } else {
+            ordering_to_int(from_datum::<T>(x).cmp(&from_datum::<T>(y)))
+        }
+    })
+}
+
+/// Are the varlenas `x` and `y` the same bytes, once they're detoasted?
+unsafe fn same_bytes(x: pg_sys::Datum, y: pg_sys::Datum) -> bool {
+    let x_varlena = pg_sys::pg_detoast_datum_packed(x as *mut pg_sys::varlena);
+    let y_varlena = pg_sys::pg_detoast_datum_packed(y as *mut pg_sys::varlena);
+    let same = varlena_to_byte_slice(x_varlena) == varlena_to_byte_slice(y_varlena);
+
+    for (varlena, datum) in [(x_varlena, x), (y_varlena, y)].iter() {
+        if *varlena as pg_sys::Datum != *datum {
+            pg_sys::pfree(*varlena as void_mut_ptr);
+        }
+    }
+    same
+}
+
+unsafe extern "C" fn compare_abbreviated(
+    x: pg_sys::Datum,
+    y: pg_sys::Datum,
+    _ssup: pg_sys::SortSupport,
+) -> std::os::raw::c_int {
+    ordering_to_int((x as i64).cmp(&(y as i64)))
+}
+
+unsafe extern "C" fn abbreviate<T: FromDatum + SortAbbreviation>(
+    original: pg_sys::Datum,
+    ssup: pg_sys::SortSupport,
+) -> pg_sys::Datum {
+    pg_sys::guard::guard(|| {
+        let key = from_datum::<T>(original).abbreviate();
+
+        let state = &mut *((*ssup).ssup_extra as *mut AbbreviationState);
+        state.input_count += 1;
+        if let Some(distinct_keys) = state.distinct_keys.as_mut() {
+            distinct_keys.insert(key);
+            if distinct_keys.len() >= ENOUGH_DISTINCT_KEYS {
+                state.distinct_keys = None;
+            }
+        }
+
+        key as pg_sys::Datum
+    })
+}
+
+/// The same heuristic as Postgres' own types:  give up on abbreviated keys if, after the first
+/// 10,000 values, there's less than one distinct key for every 10,000 values
+unsafe extern "C" fn abort_abbreviation(
+    memtupcount: std::os::raw::c_int,
+    ssup: pg_sys::SortSupport,
+) -> bool {
+    let state = &*((*ssup).ssup_extra as *const AbbreviationState);
+    if memtupcount < 10_000 || state.input_count < 10_000 {
+        return false;
+    }
+
+    match &state.distinct_keys {
+        Some(distinct_keys) => {
+            (distinct_keys.len() as f64) < state.input_count as f64 / 10_000.0 + 0.5
+        }
+        None => false,
+    }
+}
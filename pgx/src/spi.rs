@@ -3,11 +3,17 @@
 
 //! Safe access to Postgres' *Server Programming Interface* (SPI).
 
-use crate::{pg_sys, FromDatum, IntoDatum, Json, PgMemoryContexts, PgOid};
+use crate::{
+    direct_function_call, ensure_not_in_recovery, is_a, pg_guard, pg_sys, varlena_to_byte_slice,
+    void_mut_ptr, FromDatum, IntoDatum, Json, PgBox, PgList, PgMemoryContexts, PgOid, PgRelation,
+    PgTupleDesc,
+};
 use enum_primitive_derive::*;
 use num_traits::FromPrimitive;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::marker::PhantomData;
 use std::ops::{Index, IndexMut};
 
 #[derive(Debug, Primitive)]
@@ -31,7 +37,7 @@ pub enum SpiOk {
     TdRegister = 17,
 }
 
-#[derive(Debug, Primitive)]
+#[derive(Debug, PartialEq, Eq, Primitive)]
 pub enum SpiError {
     // NB:  These are #define'd as negative, but we redefine them as positive so that
     // #[derive(Primitive)] will work.  We just need to negate result codes from the
@@ -77,6 +83,58 @@ pub struct SpiHeapTupleData {
     entries: HashMap<usize, SpiHeapTupleDataEntry>,
 }
 
+/// A single row, with as many columns as its query produced, copied out of SPI along with the
+/// tuple descriptor that describes it
+pub struct SpiRow {
+    tupdesc: PgTupleDesc<'static>,
+    tuple: PgBox<pg_sys::HeapTupleData>,
+}
+
+/// A statement and the values of its `$n` arguments, bound one at a time, in order.  This is
+/// the same as the `_with_args` functions of `Spi`, without building the `Vec` of `PgOid`s and
+/// Datums by hand.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// let count = Query::new("SELECT count(*) FROM pg_class WHERE relkind = $1 AND relpages > $2")
+///     .bind("r")
+///     .bind(10)
+///     .get_one::<i64>();
+///
+/// let deleted = Query::new("DELETE FROM sessions WHERE user_id = $1")
+///     .bind(42i64)
+///     .execute();
+///
+/// for row in Query::new("SELECT relname FROM pg_class WHERE relnamespace = $1::regnamespace")
+///     .bind("pg_catalog")
+///     .iter()
+/// {
+///     let name = row.get::<String>(1).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    query: String,
+    args: Vec<(PgOid, Option<pg_sys::Datum>)>,
+}
+
+/// The rows returned by `SpiClient::update_returning()`, fetched from a cursor a batch at a time
+pub struct SpiReturning<'a> {
+    /// `None` once every row has been fetched
+    portal: Option<pg_sys::Portal>,
+    batch: *mut pg_sys::SPITupleTable,
+    batch_len: usize,
+    next_in_batch: usize,
+    rows: u64,
+    _client: PhantomData<&'a mut SpiClient>,
+}
+
+/// How many rows `SpiReturning` fetches at once
+const RETURNING_BATCH_SIZE: i64 = 100;
+
 impl Spi {
     pub fn get_one<A: FromDatum + IntoDatum>(query: &str) -> Option<A> {
         Spi::connect(|client| {
@@ -154,6 +212,46 @@ impl Spi {
         .unwrap()
     }
 
+    /// Run `query` and return its first row, for when it has more columns than `get_three()`
+    /// can return, or their types aren't known until runtime.  Returns `None` if the query
+    /// produces no rows.
+    ///
+    /// The row is copied into the `CurrentMemoryContext` at the time of this function call
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// let row = Spi::get_one_row("SELECT datname, encoding FROM pg_database LIMIT 1").unwrap();
+    /// let name = row.get_by_name::<&str>("datname");
+    /// let encoding = row.get::<i32>(2);
+    /// ```
+    pub fn get_one_row(query: &str) -> Option<SpiRow> {
+        Spi::get_one_row_with_args(query, vec![])
+    }
+
+    /// Like `get_one_row()`, with `$n` arguments
+    pub fn get_one_row_with_args(
+        query: &str,
+        args: Vec<(PgOid, Option<pg_sys::Datum>)>,
+    ) -> Option<SpiRow> {
+        let args = if args.is_empty() { None } else { Some(args) };
+        let mut outer_memory_context =
+            PgMemoryContexts::For(PgMemoryContexts::CurrentMemoryContext.value());
+
+        let mut row = None;
+        Spi::connect(|client| {
+            // the row must be copied *before* we disconnect from SPI
+            row = client
+                .select(query, Some(1), args)
+                .first()
+                .copy_row_into(&mut outer_memory_context);
+            Ok(Some(()))
+        });
+        row
+    }
+
     /// just run an arbitrary SQL statement.
     ///
     /// ## Safety
@@ -165,6 +263,29 @@ impl Spi {
         })
     }
 
+    /// Execute a statement that modifies the database, such as `INSERT`, `UPDATE`, `DELETE`, or
+    /// DDL, with optional `$n` arguments.  Returns the number of rows it processed.
+    ///
+    /// The statement is executed in read/write mode, so it sees the effects of prior statements
+    /// in this transaction, and subsequent statements see its effects.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// let deleted = Spi::update(
+    ///     "DELETE FROM sessions WHERE last_seen < now() - $1::interval",
+    ///     vec![(PgBuiltInOids::TEXTOID.oid(), "1 hour".into_datum())],
+    /// );
+    /// info!("expired {} sessions", deleted);
+    /// ```
+    pub fn update(query: &str, args: Vec<(PgOid, Option<pg_sys::Datum>)>) -> u64 {
+        let args = if args.is_empty() { None } else { Some(args) };
+        Spi::connect(|mut client| Ok(Some(client.update(query, None, args).len() as i64)))
+            .expect("SPI processed count was NULL") as u64
+    }
+
     /// explain a query, returning its result in json form
     pub fn explain(query: &str) -> Json {
         Spi::connect(|mut client| {
@@ -180,6 +301,109 @@ impl Spi {
         .unwrap()
     }
 
+    /// Load `rows` into `table` with `COPY ... FROM`, as if each row were a line of COPY's text
+    /// format.  `None` values are loaded as NULL.  If `columns` is empty, each row must provide
+    /// a value for every column in the table, in order.
+    ///
+    /// This is intended for quickly loading test fixtures.  It drives COPY directly on the
+    /// server, so any error is reported with COPY's usual context (ie, `COPY table, line 3`).
+    ///
+    /// As with the `COPY` command, the current user needs `INSERT` privileges on `table`, and
+    /// tables with row-level security enabled are rejected.
+    ///
+    /// Returns the number of rows loaded.
+    pub fn copy_in<I: Iterator<Item = Vec<Option<String>>>>(
+        table: &str,
+        columns: &[&str],
+        rows: I,
+    ) -> u64 {
+        let mut buffer = Vec::new();
+        for row in rows {
+            for (i, value) in row.iter().enumerate() {
+                if i > 0 {
+                    buffer.push(b'\t');
+                }
+                match value {
+                    Some(value) => copy_text_escape(value, &mut buffer),
+                    None => buffer.extend_from_slice(b"\\N"),
+                }
+            }
+            buffer.push(b'\n');
+        }
+
+        copy_from_buffer(table, columns, buffer, PgList::new())
+    }
+
+    /// Load `rows` into `table` with `COPY ... FROM (format binary)`.  Each value is converted
+    /// with its type's binary send function, so the `PgOid` of each value must match the type of
+    /// the column it's loaded into.  If `columns` is empty, each row must provide a value for
+    /// every column in the table, in order.
+    ///
+    /// As with `Spi::copy_in()`, the current user needs `INSERT` privileges on `table`, and tables
+    /// with row-level security enabled are rejected.
+    ///
+    /// Returns the number of rows loaded.
+    pub fn copy_in_binary<I: Iterator<Item = Vec<(PgOid, Option<pg_sys::Datum>)>>>(
+        table: &str,
+        columns: &[&str],
+        rows: I,
+    ) -> u64 {
+        let mut send_funcs = HashMap::new();
+        let mut buffer = Vec::new();
+
+        // header: signature, flags, and header extension length
+        buffer.extend_from_slice(b"PGCOPY\n\xFF\r\n\0");
+        buffer.extend_from_slice(&0i32.to_be_bytes());
+        buffer.extend_from_slice(&0i32.to_be_bytes());
+
+        for row in rows {
+            buffer.extend_from_slice(&(row.len() as i16).to_be_bytes());
+            for (oid, datum) in row {
+                match datum {
+                    Some(datum) => {
+                        let typoid = oid.value();
+                        let send_func = *send_funcs.entry(typoid).or_insert_with(|| {
+                            let mut send_func = pg_sys::InvalidOid;
+                            let mut is_varlena = false;
+                            unsafe {
+                                pg_sys::getTypeBinaryOutputInfo(
+                                    typoid,
+                                    &mut send_func,
+                                    &mut is_varlena,
+                                );
+                            }
+                            send_func
+                        });
+
+                        unsafe {
+                            let bytea = pg_sys::OidSendFunctionCall(send_func, datum);
+                            let bytes = varlena_to_byte_slice(bytea);
+                            buffer.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+                            buffer.extend_from_slice(bytes);
+                            pg_sys::pfree(bytea as void_mut_ptr);
+                        }
+                    }
+                    None => buffer.extend_from_slice(&(-1i32).to_be_bytes()),
+                }
+            }
+        }
+
+        // trailer
+        buffer.extend_from_slice(&(-1i16).to_be_bytes());
+
+        let mut options = PgList::new();
+        unsafe {
+            options.push(pg_sys::makeDefElem(
+                PgMemoryContexts::CurrentMemoryContext.pstrdup("format"),
+                pg_sys::makeString(PgMemoryContexts::CurrentMemoryContext.pstrdup("binary"))
+                    as *mut pg_sys::Node,
+                -1,
+            ));
+        }
+
+        copy_from_buffer(table, columns, buffer, options)
+    }
+
     /// execute SPI commands via the provided `SpiClient`
     pub fn execute<F: FnOnce(SpiClient) + std::panic::UnwindSafe>(f: F) {
         Spi::connect(|client| {
@@ -206,6 +430,7 @@ impl Spi {
             fn connect() -> Self {
                 // connect to SPI
                 Spi::check_status(unsafe { pg_sys::SPI_connect() });
+                pg_sys::guard::push_spi_connection();
                 SpiConnection
             }
         }
@@ -213,8 +438,21 @@ impl Spi {
         impl Drop for SpiConnection {
             /// when SpiConnection is dropped, we make sure to disconnect from SPI
             fn drop(&mut self) {
+                if pg_sys::guard::unwinding_from_error() {
+                    // the ERROR may have longjmp'd over C code with SPI connections of its own,
+                    // on top of ours, so only aborting the (sub)transaction can clean them up
+                    return;
+                }
+
                 // disconnect from SPI
-                Spi::check_status(unsafe { pg_sys::SPI_finish() });
+                pg_sys::guard::pop_spi_connection();
+                let status = unsafe { pg_sys::SPI_finish() };
+
+                // a panic can't have come through any C code, so ours is the innermost
+                // connection, but we mustn't panic again while unwinding
+                if !std::thread::panicking() {
+                    Spi::check_status(status);
+                }
             }
         }
 
@@ -223,8 +461,8 @@ impl Spi {
 
         // run the provided closure within the memory context that SPI_connect()
         // just put us un.  We'll disconnect from SPI when the closure is finished.
-        // If there's a panic or elog(ERROR), we don't care about also disconnecting from
-        // SPI b/c Postgres will do that for us automatically
+        // If there's a panic we disconnect while unwinding, but after an elog(ERROR) it's up to
+        // the (sub)transaction abort
         match f(SpiClient) {
             // copy the result to the outer memory context we saved above
             Ok(result) => {
@@ -276,6 +514,168 @@ impl Spi {
     }
 }
 
+impl Query {
+    pub fn new(query: &str) -> Self {
+        Query {
+            query: query.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Bind the next `$n` argument to `value`, as its `IntoDatum::type_oid()`.  A `None` binds
+    /// NULL
+    pub fn bind<T: IntoDatum>(mut self, value: T) -> Self {
+        self.args
+            .push((PgOid::from(T::type_oid()), value.into_datum()));
+        self
+    }
+
+    /// The first column of the first row, as `Spi::get_one_with_args()`
+    pub fn get_one<A: FromDatum + IntoDatum>(self) -> Option<A> {
+        Spi::get_one_with_args(&self.query, self.args)
+    }
+
+    /// The first two columns of the first row, as `Spi::get_two_with_args()`
+    pub fn get_two<A: FromDatum + IntoDatum, B: FromDatum + IntoDatum>(
+        self,
+    ) -> (Option<A>, Option<B>) {
+        Spi::get_two_with_args(&self.query, self.args)
+    }
+
+    /// The first three columns of the first row, as `Spi::get_three_with_args()`
+    pub fn get_three<
+        A: FromDatum + IntoDatum,
+        B: FromDatum + IntoDatum,
+        C: FromDatum + IntoDatum,
+    >(
+        self,
+    ) -> (Option<A>, Option<B>, Option<C>) {
+        Spi::get_three_with_args(&self.query, self.args)
+    }
+
+    /// The first row, as `Spi::get_one_row_with_args()`
+    pub fn get_one_row(self) -> Option<SpiRow> {
+        Spi::get_one_row_with_args(&self.query, self.args)
+    }
+
+    /// Execute a statement that modifies the database, as `Spi::update()`, returning the number
+    /// of rows it processed
+    pub fn execute(self) -> u64 {
+        Spi::update(&self.query, self.args)
+    }
+
+    /// Every row, each copied into the `CurrentMemoryContext`, so that they outlive the SPI
+    /// connection the query was run in
+    pub fn iter(self) -> impl Iterator<Item = SpiRow> {
+        let mut outer_memory_context =
+            PgMemoryContexts::For(PgMemoryContexts::CurrentMemoryContext.value());
+
+        let mut rows = Vec::new();
+        Spi::connect(|client| {
+            // the rows must be copied *before* we disconnect from SPI
+            let mut table = client.select(&self.query, None, Some(self.args));
+            for i in 0..table.len() {
+                table.current = i as isize;
+                rows.extend(table.copy_row_into(&mut outer_memory_context));
+            }
+            Ok(Some(()))
+        });
+        rows.into_iter()
+    }
+}
+
+thread_local! {
+    /// The data for the `COPY ... FROM` currently being run by `Spi::copy_in()` or
+    /// `Spi::copy_in_binary()`, and how much of it has been consumed
+    static COPY_IN_BUFFER: RefCell<(Vec<u8>, usize)> = RefCell::new((Vec::new(), 0));
+}
+
+/// Escape `value` for COPY's text format, per "Text Format" in the `COPY` documentation
+fn copy_text_escape(value: &str, buffer: &mut Vec<u8>) {
+    for byte in value.bytes() {
+        match byte {
+            b'\\' => buffer.extend_from_slice(b"\\\\"),
+            b'\t' => buffer.extend_from_slice(b"\\t"),
+            b'\n' => buffer.extend_from_slice(b"\\n"),
+            b'\r' => buffer.extend_from_slice(b"\\r"),
+            other => buffer.push(other),
+        }
+    }
+}
+
+fn copy_from_buffer(
+    table: &str,
+    columns: &[&str],
+    buffer: Vec<u8>,
+    options: PgList<pg_sys::DefElem>,
+) -> u64 {
+    ensure_not_in_recovery("COPY FROM");
+
+    extern "C" {
+        fn pgx_copy_from(
+            rel: pg_sys::Relation,
+            attnamelist: *mut pg_sys::List,
+            options: *mut pg_sys::List,
+            data_source_cb: Option<
+                unsafe extern "C" fn(outbuf: void_mut_ptr, minread: i32, maxread: i32) -> i32,
+            >,
+        ) -> u64;
+    }
+
+    let relid = unsafe {
+        direct_function_call::<pg_sys::Oid>(pg_sys::to_regclass, vec![table.into_datum()])
+    }
+    .unwrap_or_else(|| panic!("relation \"{}\" does not exist", table));
+    let relation = PgRelation::with_lock(relid, pg_sys::RowExclusiveLock as pg_sys::LOCKMODE);
+
+    let mut attnamelist = PgList::<pg_sys::Value>::new();
+    for column in columns {
+        attnamelist.push(unsafe {
+            pg_sys::makeString(PgMemoryContexts::CurrentMemoryContext.pstrdup(column))
+        });
+    }
+
+    // a trigger on `table` could itself call `Spi::copy_in()`, so hang on to the outer buffer, and
+    // put it back even if this COPY raises an ERROR the trigger catches
+    let _outer = RestoreCopyInBuffer(COPY_IN_BUFFER.with(|cell| cell.replace((buffer, 0))));
+    unsafe {
+        crate::guard(|| {
+            pgx_copy_from(
+                relation.as_ptr(),
+                attnamelist.into_pg(),
+                options.into_pg(),
+                Some(copy_in_data_source),
+            )
+        })
+    }
+}
+
+/// Puts an outer `COPY_IN_BUFFER` back when dropped
+struct RestoreCopyInBuffer((Vec<u8>, usize));
+
+impl Drop for RestoreCopyInBuffer {
+    fn drop(&mut self) {
+        let outer = std::mem::take(&mut self.0);
+        COPY_IN_BUFFER.with(|cell| cell.replace(outer));
+    }
+}
+
+/// The `copy_data_source_cb` for `Spi::copy_in()`.  COPY considers a return of zero bytes to
+/// be EOF
+#[pg_guard]
+unsafe extern "C" fn copy_in_data_source(outbuf: void_mut_ptr, _minread: i32, maxread: i32) -> i32 {
+    COPY_IN_BUFFER.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let (buffer, consumed) = &mut *cell;
+        let len = (buffer.len() - *consumed).min(maxread as usize);
+
+        std::ptr::copy_nonoverlapping(buffer.as_ptr().add(*consumed), outbuf as *mut u8, len);
+        *consumed += len;
+
+        len as i32
+    })
+}
+
 impl SpiClient {
     /// perform a SELECT statement
     pub fn select(
@@ -298,6 +698,21 @@ impl SpiClient {
         SpiClient::execute(query, false, limit, args)
     }
 
+    /// perform a SELECT statement in read-only mode.
+    ///
+    /// Unlike `SpiClient::select()`, the query runs against the snapshot of the calling
+    /// statement, so it does **not** see changes made by statements previously executed through
+    /// SPI, and any attempt to modify the database raises an ERROR.  This also lets Postgres skip
+    /// taking a new snapshot and incrementing the command counter.
+    pub fn select_read_only(
+        &self,
+        query: &str,
+        limit: Option<i64>,
+        args: Option<Vec<(PgOid, Option<pg_sys::Datum>)>>,
+    ) -> SpiTupleTable {
+        SpiClient::execute(query, true, limit, args)
+    }
+
     /// perform any query (including utility statements) that modify the database in some way
     pub fn update(
         &mut self,
@@ -357,6 +772,163 @@ impl SpiClient {
             None => unsafe { pg_sys::SPI_execute(src.as_ptr(), read_only, limit.unwrap_or(0)) },
         };
 
+        SpiClient::tuple_table(status_code)
+    }
+
+    /// prepare a statement for later execution via `SpiClient::execute_plan()`.  `args` are the
+    /// types of the statement's `$n` parameters
+    pub fn prepare(&self, query: &str, args: Option<Vec<PgOid>>) -> SpiPlan<'_> {
+        let src = std::ffi::CString::new(query).expect("query contained a null byte");
+        let mut argtypes = args
+            .unwrap_or_default()
+            .into_iter()
+            .map(|oid| oid.value())
+            .collect::<Vec<_>>();
+
+        let plan = unsafe {
+            pg_sys::SPI_prepare(src.as_ptr(), argtypes.len() as i32, argtypes.as_mut_ptr())
+        };
+        if plan.is_null() {
+            Spi::check_status(unsafe { pg_sys::SPI_result });
+            unreachable!("SPI_prepare() failed without an error status");
+        }
+
+        SpiPlan {
+            plan,
+            argtypes,
+            kept: false,
+            _client: PhantomData,
+        }
+    }
+
+    /// execute a statement prepared with `SpiClient::prepare()`
+    pub fn execute_plan(
+        &mut self,
+        plan: &SpiPlan<'_>,
+        limit: Option<i64>,
+        args: Option<Vec<Option<pg_sys::Datum>>>,
+    ) -> SpiTupleTable {
+        unsafe {
+            pg_sys::SPI_tuptable = std::ptr::null_mut();
+        }
+
+        let args = args.unwrap_or_default();
+        if args.len() != plan.nargs() {
+            panic!(
+                "plan expects {} arguments but {} were provided",
+                plan.nargs(),
+                args.len()
+            );
+        }
+
+        let mut datums = args
+            .iter()
+            .map(|datum| datum.unwrap_or(0))
+            .collect::<Vec<_>>();
+        let nulls = args
+            .iter()
+            .map(|datum| match datum {
+                Some(_) => b' ' as std::os::raw::c_char,
+                None => b'n' as std::os::raw::c_char,
+            })
+            .collect::<Vec<_>>();
+
+        let status_code = unsafe {
+            pg_sys::SPI_execute_plan(
+                plan.as_ptr(),
+                datums.as_mut_ptr(),
+                nulls.as_ptr(),
+                false,
+                limit.unwrap_or(0),
+            )
+        };
+
+        SpiClient::tuple_table(status_code)
+    }
+
+    /// Execute an `INSERT`, `UPDATE`, or `DELETE` that has a `RETURNING` clause, and stream the
+    /// rows it returns through a cursor, rather than materializing them all in a `SpiTupleTable`.
+    /// With `INSERT ... ON CONFLICT ... RETURNING`, this can claim a row, or get the one that
+    /// already exists, in a single statement.
+    ///
+    /// The statement runs to completion before this returns.  Once every row has been iterated
+    /// over, `SpiReturning::rows_affected()` is how many there were.
+    ///
+    /// ## Panics
+    ///
+    /// If the statement isn't a single `INSERT`, `UPDATE`, or `DELETE` with a `RETURNING` clause,
+    /// rather than returning no rows
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// Spi::execute(|mut client| {
+    ///     let rows = client.update_returning(
+    ///         "INSERT INTO jobs (name) VALUES ($1)
+    ///              ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+    ///              RETURNING id",
+    ///         Some(vec![(PgBuiltInOids::TEXTOID.oid(), "reindex".into_datum())]),
+    ///     );
+    ///     for row in rows {
+    ///         let id = row.by_ordinal(1).unwrap().value::<i64>();
+    ///         info!("claimed job {:?}", id);
+    ///     }
+    /// });
+    /// ```
+    pub fn update_returning(
+        &mut self,
+        query: &str,
+        args: Option<Vec<(PgOid, Option<pg_sys::Datum>)>>,
+    ) -> SpiReturning<'_> {
+        let args = args.unwrap_or_default();
+        let plan = self.prepare(query, Some(args.iter().map(|(oid, _)| *oid).collect()));
+        check_returning(&plan);
+
+        let mut datums = args
+            .iter()
+            .map(|(_, datum)| datum.unwrap_or(0))
+            .collect::<Vec<_>>();
+        let nulls = args
+            .iter()
+            .map(|(_, datum)| match datum {
+                Some(_) => b' ' as std::os::raw::c_char,
+                None => b'n' as std::os::raw::c_char,
+            })
+            .collect::<Vec<_>>();
+
+        // the portal has its own copy of the plan
+        let portal = unsafe {
+            pg_sys::SPI_cursor_open(
+                std::ptr::null(),
+                plan.as_ptr(),
+                datums.as_mut_ptr(),
+                nulls.as_ptr(),
+                false,
+            )
+        };
+        if portal.is_null() {
+            Spi::check_status(unsafe { pg_sys::SPI_result });
+            unreachable!("SPI_cursor_open() failed without an error status");
+        }
+
+        let mut returning = SpiReturning {
+            portal: Some(portal),
+            batch: std::ptr::null_mut(),
+            batch_len: 0,
+            next_in_batch: 0,
+            rows: 0,
+            _client: PhantomData,
+        };
+
+        // the first fetch is what executes the statement, so that it's run even if none of its
+        // rows are
+        returning.fetch_batch();
+        returning
+    }
+
+    fn tuple_table(status_code: i32) -> SpiTupleTable {
         SpiTupleTable {
             status_code: Spi::check_status(status_code),
             table: unsafe { pg_sys::SPI_tuptable },
@@ -371,6 +943,384 @@ impl SpiClient {
     }
 }
 
+/// The output formats supported by `SpiPlan::explain()`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ExplainFormat {
+    Text = pg_sys::ExplainFormat_EXPLAIN_FORMAT_TEXT as isize,
+    Xml = pg_sys::ExplainFormat_EXPLAIN_FORMAT_XML as isize,
+    Json = pg_sys::ExplainFormat_EXPLAIN_FORMAT_JSON as isize,
+    Yaml = pg_sys::ExplainFormat_EXPLAIN_FORMAT_YAML as isize,
+}
+
+/// A statement prepared with `SpiClient::prepare()`.
+///
+/// Unless `SpiPlan::keep()` is called, the plan is freed when the SPI connection that prepared
+/// it is finished, so it borrows the `SpiClient` that prepared it and can't be used afterwards:
+///
+/// ```rust,compile_fail
+/// use pgx::*;
+///
+/// let mut escaped = None;
+/// let escaped_ref = std::panic::AssertUnwindSafe(&mut escaped);
+/// Spi::execute(move |client| {
+///     let escaped_ref = escaped_ref;
+///     *escaped_ref.0 = Some(client.prepare("SELECT 1", None));
+/// });
+/// ```
+#[derive(Debug)]
+pub struct SpiPlan<'a> {
+    plan: pg_sys::SPIPlanPtr,
+    argtypes: Vec<pg_sys::Oid>,
+    kept: bool,
+    _client: PhantomData<&'a SpiClient>,
+}
+
+impl<'a> SpiPlan<'a> {
+    /// Save this plan so that it outlives the SPI connection that prepared it.  A kept plan is
+    /// freed when the returned `SpiPlan` is dropped
+    pub fn keep(mut self) -> SpiPlan<'static> {
+        Spi::check_status(unsafe { pg_sys::SPI_keepplan(self.plan) });
+        SpiPlan {
+            plan: self.plan,
+            argtypes: std::mem::take(&mut self.argtypes),
+            kept: true,
+            _client: PhantomData,
+        }
+    }
+
+    pub fn as_ptr(&self) -> pg_sys::SPIPlanPtr {
+        self.plan
+    }
+
+    /// The number of arguments this plan expects
+    pub fn nargs(&self) -> usize {
+        self.argtypes.len()
+    }
+
+    /// EXPLAIN the plan that would be used to execute this statement, without executing it.
+    ///
+    /// If `args` are provided, Postgres chooses between a custom plan for those argument values
+    /// and the generic plan, just as it would when executing the statement.  Note that building
+    /// a custom plan here counts towards that choice for later executions.  Without `args` the
+    /// generic plan is always explained.
+    pub fn explain(
+        &self,
+        format: ExplainFormat,
+        args: Option<Vec<Option<pg_sys::Datum>>>,
+    ) -> String {
+        unsafe {
+            let plan_sources = PgList::<pg_sys::CachedPlanSource>::from_pg(
+                pg_sys::SPI_plan_get_plan_sources(self.plan),
+            );
+            let params = match args {
+                Some(args) => self.make_param_list(args),
+                None => std::ptr::null_mut(),
+            };
+
+            let mut es = PgBox::from_pg(pg_sys::NewExplainState());
+            es.format = format as pg_sys::ExplainFormat;
+
+            pg_sys::ExplainBeginOutput(es.as_ptr());
+            for (i, plansource) in plan_sources.iter_ptr().enumerate() {
+                let cplan = CachedPlanRef(pg_sys::GetCachedPlan(
+                    plansource,
+                    params,
+                    true,
+                    std::ptr::null_mut(),
+                ));
+                let stmts = PgList::<pg_sys::PlannedStmt>::from_pg((*cplan.0).stmt_list);
+
+                for (j, stmt) in stmts.iter_ptr().enumerate() {
+                    if (*stmt).commandType == pg_sys::CmdType_CMD_UTILITY {
+                        panic!("cannot EXPLAIN a utility statement");
+                    }
+
+                    if i > 0 || j > 0 {
+                        pg_sys::ExplainSeparatePlans(es.as_ptr());
+                    }
+
+                    explain_one_plan(stmt, es.as_ptr(), (*plansource).query_string, params);
+                }
+            }
+            pg_sys::ExplainEndOutput(es.as_ptr());
+
+            std::ffi::CStr::from_ptr((*es.str_).data)
+                .to_string_lossy()
+                .trim_end()
+                .to_string()
+        }
+    }
+
+    /// Would executing this statement use its generic plan?
+    ///
+    /// This mirrors the choice Postgres makes between custom and generic plans.  Returns `None`
+    /// if the statement consists of more than one query, as each is planned independently
+    pub fn uses_generic_plan(&self) -> Option<bool> {
+        let plan_sources = PgList::<pg_sys::CachedPlanSource>::from_pg(unsafe {
+            pg_sys::SPI_plan_get_plan_sources(self.plan)
+        });
+        if plan_sources.len() != 1 {
+            return None;
+        }
+
+        let plansource = unsafe {
+            plan_sources
+                .get_ptr(0)
+                .unwrap()
+                .as_ref()
+                .expect("CachedPlanSource is NULL")
+        };
+        let cursor_options = plansource.cursor_options as u32;
+
+        let use_custom = if plansource.is_oneshot {
+            true
+        } else if plansource.num_params == 0 {
+            // there's never any point in a custom plan if there's no parameters
+            false
+        } else if let Some(force_generic) = plan_cache_mode_forces_generic() {
+            !force_generic
+        } else if cursor_options & pg_sys::CURSOR_OPT_GENERIC_PLAN != 0 {
+            false
+        } else if cursor_options & pg_sys::CURSOR_OPT_CUSTOM_PLAN != 0 {
+            true
+        } else if plansource.num_custom_plans < 5 {
+            // Postgres always builds a few custom plans before considering the generic plan
+            true
+        } else {
+            let avg_custom_cost = plansource.total_custom_cost / plansource.num_custom_plans as f64;
+            plansource.generic_cost < 0.0 || plansource.generic_cost >= avg_custom_cost
+        };
+
+        Some(!use_custom)
+    }
+
+    /// Set the `CURSOR_OPT_*` flags used when planning this statement, such as
+    /// `pg_sys::CURSOR_OPT_GENERIC_PLAN` or `pg_sys::CURSOR_OPT_CUSTOM_PLAN` to force the
+    /// choice between generic and custom plans.
+    ///
+    /// On Postgres 12 and later, a `plan_cache_mode` setting other than `auto` takes precedence
+    pub fn set_cursor_options(&mut self, flags: i32) {
+        let plan_sources = PgList::<pg_sys::CachedPlanSource>::from_pg(unsafe {
+            pg_sys::SPI_plan_get_plan_sources(self.plan)
+        });
+
+        for plansource in plan_sources.iter_ptr() {
+            unsafe {
+                (*plansource).cursor_options = flags;
+            }
+        }
+    }
+
+    /// Build a `ParamListInfo` for `args`, allocated in `CurrentMemoryContext`
+    fn make_param_list(&self, args: Vec<Option<pg_sys::Datum>>) -> pg_sys::ParamListInfo {
+        if args.len() != self.nargs() {
+            panic!(
+                "plan expects {} arguments but {} were provided",
+                self.nargs(),
+                args.len()
+            );
+        }
+
+        unsafe {
+            let params = pg_sys::palloc0(
+                std::mem::size_of::<pg_sys::ParamListInfoData>()
+                    + args.len() * std::mem::size_of::<pg_sys::ParamExternData>(),
+            ) as pg_sys::ParamListInfo;
+            (*params).numParams = args.len() as i32;
+
+            let slots = (*params).params.as_mut_slice(args.len());
+            for ((slot, datum), argtype) in slots.iter_mut().zip(args).zip(&self.argtypes) {
+                slot.value = datum.unwrap_or(0);
+                slot.isnull = datum.is_none();
+                slot.pflags = pg_sys::PARAM_FLAG_CONST as u16;
+                slot.ptype = *argtype;
+            }
+
+            params
+        }
+    }
+}
+
+impl Drop for SpiPlan<'_> {
+    fn drop(&mut self) {
+        if self.kept {
+            unsafe {
+                pg_sys::SPI_freeplan(self.plan);
+            }
+        }
+    }
+}
+
+/// A reference to a `CachedPlan`, counted by the current resource owner, that's released when
+/// it's dropped
+struct CachedPlanRef(*mut pg_sys::CachedPlan);
+
+impl Drop for CachedPlanRef {
+    fn drop(&mut self) {
+        // while unwinding from an ERROR, the resource owner releases it when the (sub)transaction
+        // is aborted
+        if pg_sys::guard::unwinding_from_error() {
+            return;
+        }
+
+        unsafe {
+            pg_sys::ReleaseCachedPlan(self.0, true);
+        }
+    }
+}
+
+/// Panic unless `plan` is a single `INSERT`, `UPDATE`, or `DELETE` with a `RETURNING` clause
+fn check_returning(plan: &SpiPlan<'_>) {
+    let plan_sources = PgList::<pg_sys::CachedPlanSource>::from_pg(unsafe {
+        pg_sys::SPI_plan_get_plan_sources(plan.as_ptr())
+    });
+    if plan_sources.len() != 1 {
+        panic!(
+            "update_returning() requires a single statement, not {}",
+            plan_sources.len()
+        );
+    }
+
+    let returning_list = unsafe {
+        let stmt = (*(*plan_sources.get_ptr(0).unwrap()).raw_parse_tree).stmt;
+        if is_a(stmt, pg_sys::NodeTag_T_InsertStmt) {
+            (*(stmt as *mut pg_sys::InsertStmt)).returningList
+        } else if is_a(stmt, pg_sys::NodeTag_T_UpdateStmt) {
+            (*(stmt as *mut pg_sys::UpdateStmt)).returningList
+        } else if is_a(stmt, pg_sys::NodeTag_T_DeleteStmt) {
+            (*(stmt as *mut pg_sys::DeleteStmt)).returningList
+        } else {
+            panic!("update_returning() requires an INSERT, UPDATE, or DELETE statement")
+        }
+    };
+    if returning_list.is_null() {
+        panic!("update_returning() requires a RETURNING clause, but the statement has none");
+    }
+}
+
+impl SpiReturning<'_> {
+    /// How many rows the statement affected, once they've all been iterated over.  `None` until
+    /// then
+    pub fn rows_affected(&self) -> Option<u64> {
+        match self.portal {
+            Some(_) => None,
+            None => Some(self.rows),
+        }
+    }
+
+    /// Fetch the next batch of rows, closing the cursor if there are none.  Earlier batches are
+    /// left for `SPI_finish()` to free, as the `SpiHeapTupleData`s made from them still
+    /// point into them
+    fn fetch_batch(&mut self) {
+        if let Some(portal) = self.portal {
+            unsafe {
+                pg_sys::SPI_cursor_fetch(portal, true, RETURNING_BATCH_SIZE);
+                self.batch = pg_sys::SPI_tuptable;
+                self.batch_len = pg_sys::SPI_processed as usize;
+            }
+            self.next_in_batch = 0;
+
+            if self.batch_len == 0 {
+                unsafe {
+                    pg_sys::SPI_cursor_close(portal);
+                }
+                self.portal = None;
+            }
+        }
+    }
+}
+
+impl Iterator for SpiReturning<'_> {
+    type Item = SpiHeapTupleData;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.portal.is_some() && self.next_in_batch >= self.batch_len {
+            self.fetch_batch();
+        }
+        if self.portal.is_none() {
+            return None;
+        }
+
+        let row = unsafe {
+            let tuple =
+                std::slice::from_raw_parts((*self.batch).vals, self.batch_len)[self.next_in_batch];
+            SpiHeapTupleData::new((*self.batch).tupdesc, tuple)
+        };
+        self.next_in_batch += 1;
+        self.rows += 1;
+        Some(row)
+    }
+}
+
+impl Drop for SpiReturning<'_> {
+    fn drop(&mut self) {
+        // while unwinding from an error, Postgres closes the cursor when it aborts the transaction
+        if std::thread::panicking() {
+            return;
+        }
+
+        if let Some(portal) = self.portal.take() {
+            unsafe {
+                pg_sys::SPI_cursor_close(portal);
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11", feature = "pg12"))]
+unsafe fn explain_one_plan(
+    stmt: *mut pg_sys::PlannedStmt,
+    es: *mut pg_sys::ExplainState,
+    query_string: *const std::os::raw::c_char,
+    params: pg_sys::ParamListInfo,
+) {
+    pg_sys::ExplainOnePlan(
+        stmt,
+        std::ptr::null_mut(),
+        es,
+        query_string,
+        params,
+        std::ptr::null_mut(),
+        std::ptr::null(),
+    );
+}
+
+#[cfg(feature = "pg13")]
+unsafe fn explain_one_plan(
+    stmt: *mut pg_sys::PlannedStmt,
+    es: *mut pg_sys::ExplainState,
+    query_string: *const std::os::raw::c_char,
+    params: pg_sys::ParamListInfo,
+) {
+    pg_sys::ExplainOnePlan(
+        stmt,
+        std::ptr::null_mut(),
+        es,
+        query_string,
+        params,
+        std::ptr::null_mut(),
+        std::ptr::null(),
+        std::ptr::null(),
+    );
+}
+
+/// `plan_cache_mode` was added in Postgres 12
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+fn plan_cache_mode_forces_generic() -> Option<bool> {
+    None
+}
+
+/// Returns `Some(true)` if `plan_cache_mode` forces generic plans, `Some(false)` if it forces
+/// custom plans, and `None` if it's `auto`
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+fn plan_cache_mode_forces_generic() -> Option<bool> {
+    match unsafe { pg_sys::plan_cache_mode } as u32 {
+        pg_sys::PlanCacheMode_PLAN_CACHE_MODE_FORCE_GENERIC_PLAN => Some(true),
+        pg_sys::PlanCacheMode_PLAN_CACHE_MODE_FORCE_CUSTOM_PLAN => Some(false),
+        _ => None,
+    }
+}
+
 impl SpiTupleTable {
     /// `SpiTupleTable`s are positioned before the start, for iteration purposes.
     ///
@@ -427,6 +1377,26 @@ impl SpiTupleTable {
         }
     }
 
+    /// Copy the current row, and the tuple descriptor, into `memory_context`, so that it
+    /// outlives the SPI connection
+    fn copy_row_into(&self, memory_context: &mut PgMemoryContexts) -> Option<SpiRow> {
+        if self.current < 0 {
+            panic!("SpiTupleTable positioned before start")
+        }
+        if self.current as usize >= self.size {
+            return None;
+        }
+
+        let tupdesc = self.tupdesc.expect("TupDesc is NULL");
+        let heap_tuple = unsafe {
+            std::slice::from_raw_parts((*self.table).vals, self.size)[self.current as usize]
+        };
+        Some(memory_context.switch_to(|_| SpiRow {
+            tupdesc: PgTupleDesc::from_pg_copy(tupdesc),
+            tuple: PgBox::from_rust(unsafe { pg_sys::heap_copytuple(heap_tuple) }),
+        }))
+    }
+
     pub fn get_datum<T: FromDatum>(&self, ordinal: i32) -> Option<T> {
         if self.current < 0 {
             panic!("SpiTupleTable positioned before start")
@@ -599,6 +1569,61 @@ impl SpiHeapTupleData {
     }
 }
 
+impl SpiRow {
+    /// How many columns the row has
+    pub fn len(&self) -> usize {
+        self.tupdesc.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The row's tuple descriptor, which describes each of its columns
+    pub fn tuple_desc(&self) -> &PgTupleDesc<'static> {
+        &self.tupdesc
+    }
+
+    /// Get the value of a column, by its ordinal position, or `None` if it's NULL.
+    ///
+    /// The ordinal position is 1-based.
+    ///
+    /// If the specified ordinal is out of bounds a `Err(SpiError::Noattribute)` is returned
+    pub fn get<T: FromDatum>(&self, ordinal: usize) -> std::result::Result<Option<T>, SpiError> {
+        if ordinal < 1 || ordinal > self.len() {
+            return Err(SpiError::Noattribute);
+        }
+
+        unsafe {
+            let tupdesc = self.tupdesc.as_ptr();
+            let mut is_null = false;
+            let datum =
+                pg_sys::SPI_getbinval(self.tuple.as_ptr(), tupdesc, ordinal as i32, &mut is_null);
+            Ok(T::from_datum(
+                datum,
+                is_null,
+                pg_sys::SPI_gettypeid(tupdesc, ordinal as i32),
+            ))
+        }
+    }
+
+    /// Get the value of a column, by its name, or `None` if it's NULL.
+    ///
+    /// If the specified name does not exist a `Err(SpiError::Noattribute)` is returned
+    pub fn get_by_name<T: FromDatum>(
+        &self,
+        name: &str,
+    ) -> std::result::Result<Option<T>, SpiError> {
+        use crate::pg_sys::AsPgCStr;
+        let fnumber = unsafe { pg_sys::SPI_fnumber(self.tupdesc.as_ptr(), name.as_pg_cstr()) };
+        if fnumber == pg_sys::SPI_ERROR_NOATTRIBUTE {
+            Err(SpiError::Noattribute)
+        } else {
+            self.get(fnumber as usize)
+        }
+    }
+}
+
 impl<Datum: IntoDatum + FromDatum> From<Datum> for SpiHeapTupleDataEntry {
     fn from(datum: Datum) -> Self {
         SpiHeapTupleDataEntry {
@@ -0,0 +1,139 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Postgres spinlocks, for tiny critical sections inside shared memory structs
+use crate::{pg_sys, PGXSharedMemory};
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+extern "C" {
+    fn pgx_SpinLockInit(lock: *mut pg_sys::slock_t);
+    fn pgx_SpinLockAcquire(lock: *mut pg_sys::slock_t);
+    fn pgx_SpinLockRelease(lock: *mut pg_sys::slock_t);
+}
+
+/// How many spinlocks this backend currently holds
+static SPINLOCKS_HELD: AtomicUsize = AtomicUsize::new(0);
+
+/// Does this backend currently hold a `PgSpinLock`?
+///
+/// pgx' logging functions `debug_assert!()` that it doesn't
+#[inline]
+pub fn holding_spinlock() -> bool {
+    SPINLOCKS_HELD.load(Ordering::Relaxed) > 0
+}
+
+/// A Postgres spinlock (`slock_t`) protecting a `T`, meant to be embedded in a struct that lives
+/// in shared memory.
+///
+/// Spinlocks are much cheaper than a `PgLwLock`, but other backends busy-wait while one is held,
+/// so the critical section must be only a few instructions long.  While holding the lock, don't:
+///
+///  - log or raise an error (`info!()`, `error!()`, etc),
+///  - allocate memory, or
+///  - call into Postgres (including `check_for_interrupts!()`)
+///
+/// Postgres doesn't release spinlocks during transaction abort.  `PgSpinLockGuard` does release
+/// its lock when dropped, including during a panic, but an `ERROR` raised from C while holding
+/// the lock leaves it held.  In debug builds, pgx' logging functions assert that no spinlock is
+/// held.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// #[derive(Default)]
+/// struct Stats {
+///     calls: PgSpinLock<(u64, u64)>,
+/// }
+/// unsafe impl PGXSharedMemory for Stats {}
+///
+/// static STATS: PgLwLock<Stats> = PgLwLock::new();
+///
+/// fn record_call(rows: u64) {
+///     let stats = STATS.share();
+///     let mut calls = stats.calls.lock();
+///     calls.0 += 1;
+///     calls.1 += rows;
+/// }
+/// ```
+#[repr(C)]
+pub struct PgSpinLock<T> {
+    lock: UnsafeCell<pg_sys::slock_t>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for PgSpinLock<T> {}
+unsafe impl<T: Send> Sync for PgSpinLock<T> {}
+unsafe impl<T: PGXSharedMemory> PGXSharedMemory for PgSpinLock<T> {}
+
+impl<T> PgSpinLock<T> {
+    /// Create a new, unlocked, spinlock protecting `value`
+    pub fn new(value: T) -> Self {
+        let mut spinlock = PgSpinLock {
+            lock: UnsafeCell::new(unsafe { std::mem::zeroed() }),
+            data: UnsafeCell::new(value),
+        };
+        spinlock.init();
+        spinlock
+    }
+
+    /// Reset the lock to its unlocked state.  Only needed if the `PgSpinLock` was placed in
+    /// memory without going through `PgSpinLock::new()`, such as a zeroed shared memory segment
+    pub fn init(&mut self) {
+        unsafe { pgx_SpinLockInit(self.lock.get()) }
+    }
+
+    /// Acquire the lock, spinning until it's available.  It's released when the returned guard
+    /// is dropped
+    pub fn lock(&self) -> PgSpinLockGuard<T> {
+        unsafe {
+            pgx_SpinLockAcquire(self.lock.get());
+        }
+        SPINLOCKS_HELD.fetch_add(1, Ordering::Relaxed);
+
+        PgSpinLockGuard { spinlock: self }
+    }
+
+    /// Mutable access to the protected value without locking, as `&mut self` proves that no
+    /// other reference to the lock exists
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: Default> Default for PgSpinLock<T> {
+    fn default() -> Self {
+        PgSpinLock::new(T::default())
+    }
+}
+
+/// A held `PgSpinLock`, which releases the lock when dropped
+pub struct PgSpinLockGuard<'a, T> {
+    spinlock: &'a PgSpinLock<T>,
+}
+
+impl<T> Deref for PgSpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.spinlock.data.get() }
+    }
+}
+
+impl<T> DerefMut for PgSpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.spinlock.data.get() }
+    }
+}
+
+impl<T> Drop for PgSpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            pgx_SpinLockRelease(self.spinlock.lock.get());
+        }
+        SPINLOCKS_HELD.fetch_sub(1, Ordering::Relaxed);
+    }
+}
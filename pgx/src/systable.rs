@@ -0,0 +1,344 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Scanning system catalogs directly, with `systable_beginscan()`, rather than with SPI
+use crate::{heap_getattr, pg_sys, FromDatum, IntoDatum, PgBox, PgRelation, PgTupleDesc};
+
+/// One condition of a `SysTableScan`:  the catalog attribute `attno` compared with `value` by a
+/// btree `strategy`, such as `pg_sys::BTEqualStrategyNumber`.
+///
+/// The comparison function is looked up in the btree operator family of the attribute's type,
+/// so `value` can be of any type that family can compare the attribute with, such as an `i32`
+/// for an `int2` attribute
+pub struct ScanKeyInit {
+    attno: pg_sys::AttrNumber,
+    strategy: pg_sys::StrategyNumber,
+    value: pg_sys::Datum,
+    typoid: pg_sys::Oid,
+}
+
+impl ScanKeyInit {
+    /// Compare the attribute numbered `attno`, one of the `pg_sys::Anum_*` constants, with
+    /// `value` by the btree `strategy`, one of `pg_sys::BTLessStrategyNumber`,
+    /// `BTLessEqualStrategyNumber`, `BTEqualStrategyNumber`, `BTGreaterEqualStrategyNumber`, or
+    /// `BTGreaterStrategyNumber`
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `value` is NULL
+    pub fn new<T: IntoDatum>(attno: u32, strategy: u32, value: T) -> Self {
+        ScanKeyInit {
+            attno: attno as pg_sys::AttrNumber,
+            strategy: strategy as pg_sys::StrategyNumber,
+            value: value
+                .into_datum()
+                .expect("a ScanKeyInit value cannot be NULL"),
+            typoid: T::type_oid(),
+        }
+    }
+
+    /// Match rows whose attribute numbered `attno` is equal to `value`
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `value` is NULL
+    pub fn equal<T: IntoDatum>(attno: u32, value: T) -> Self {
+        ScanKeyInit::new(attno, pg_sys::BTEqualStrategyNumber, value)
+    }
+
+    /// Look up the comparison function and fill in `key`
+    pub(crate) unsafe fn init(&self, key: &mut pg_sys::ScanKeyData, tupdesc: &PgTupleDesc) {
+        let attr = (self.attno as usize)
+            .checked_sub(1)
+            .and_then(|i| tupdesc.get(i))
+            .unwrap_or_else(|| {
+                panic!(
+                    "catalog with {} attributes has no attribute number {}",
+                    tupdesc.len(),
+                    self.attno
+                )
+            });
+
+        let typcache =
+            pg_sys::lookup_type_cache(attr.atttypid, pg_sys::TYPECACHE_BTREE_OPFAMILY as i32);
+        let opfamily = (*typcache).btree_opf;
+        if opfamily == pg_sys::InvalidOid {
+            panic!(
+                "the type of catalog attribute \"{}\" has no btree operator family",
+                attr.name()
+            );
+        }
+
+        // the family's operators are declared for its opclass' input type, which may differ from
+        // the attribute's binary-compatible type, such as `text` for a `varchar`
+        let lefttype = (*typcache).btree_opintype;
+        let righttype = if self.typoid == attr.atttypid {
+            lefttype
+        } else {
+            self.typoid
+        };
+        let operator =
+            pg_sys::get_opfamily_member(opfamily, lefttype, righttype, self.strategy as i16);
+        if operator == pg_sys::InvalidOid {
+            panic!(
+                "no btree operator with strategy {} can compare catalog attribute \"{}\" with a value of type {}",
+                self.strategy,
+                attr.name(),
+                self.typoid
+            );
+        }
+
+        pg_sys::ScanKeyEntryInitialize(
+            key,
+            0,
+            self.attno,
+            self.strategy,
+            if righttype == lefttype {
+                pg_sys::InvalidOid
+            } else {
+                righttype
+            },
+            attr.attcollation,
+            pg_sys::get_opcode(operator),
+            self.value,
+        );
+    }
+}
+
+/// A scan of a system catalog, through one of its indexes or sequentially, as
+/// `systable_beginscan()` does, yielding each matching row as a `PgHeapTuple`.
+///
+/// The scan is ended when this instance is dropped.
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// use pgx::*;
+///
+/// // the columns of the table with oid 16384, in attnum order
+/// let pg_attribute = PgRelation::with_lock(
+///     pg_sys::AttributeRelationId,
+///     pg_sys::AccessShareLock as pg_sys::LOCKMODE,
+/// );
+/// let columns = SysTableScan::new(
+///     &pg_attribute,
+///     Some(pg_sys::AttributeRelidNumIndexId),
+///     None,
+///     &[
+///         ScanKeyInit::equal(pg_sys::Anum_pg_attribute_attrelid, 16384 as pg_sys::Oid),
+///         ScanKeyInit::new(pg_sys::Anum_pg_attribute_attnum, pg_sys::BTGreaterStrategyNumber, 0i16),
+///     ],
+/// )
+/// .map(|tuple| tuple.get_struct::<pg_sys::FormData_pg_attribute>().name().to_string())
+/// .collect::<Vec<_>>();
+/// ```
+pub struct SysTableScan<'a> {
+    catalog: &'a PgRelation,
+    scan: pg_sys::SysScanDesc,
+}
+
+impl<'a> SysTableScan<'a> {
+    /// Begin scanning `catalog` for the rows that match all the `keys`, through the index
+    /// `index_oid` if it's `Some` and the index is usable, else sequentially.  The rows are
+    /// those visible to `snapshot`, or to the latest catalog snapshot if `None`.
+    ///
+    /// Rows come in the index's order when it's used, but are otherwise unordered.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if a key's attribute doesn't exist, or if a key's value can't be
+    /// compared with its attribute.  Postgres raises an error if a key's attribute isn't a column
+    /// of the index.
+    pub fn new(
+        catalog: &'a PgRelation,
+        index_oid: Option<pg_sys::Oid>,
+        snapshot: Option<pg_sys::Snapshot>,
+        keys: &[ScanKeyInit],
+    ) -> Self {
+        let tupdesc = catalog.tuple_desc();
+        let mut scankeys = vec![pg_sys::ScanKeyData::default(); keys.len()];
+        for (key, scankey) in keys.iter().zip(scankeys.iter_mut()) {
+            unsafe { key.init(scankey, &tupdesc) };
+        }
+
+        // the scan keeps its own copy of the keys
+        let scan = unsafe {
+            pg_sys::systable_beginscan(
+                catalog.as_ptr(),
+                index_oid.unwrap_or(pg_sys::InvalidOid),
+                index_oid.is_some(),
+                snapshot.unwrap_or(std::ptr::null_mut()),
+                scankeys.len() as i32,
+                scankeys.as_mut_ptr(),
+            )
+        };
+
+        SysTableScan { catalog, scan }
+    }
+}
+
+impl<'a> Iterator for SysTableScan<'a> {
+    type Item = PgHeapTuple<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tuple = unsafe { pg_sys::systable_getnext(self.scan) };
+        if tuple.is_null() {
+            None
+        } else {
+            // the scan's tuple is only valid until the next one, so hand out a copy
+            Some(PgHeapTuple::from_rust(
+                PgBox::from_rust(unsafe { pg_sys::heap_copytuple(tuple) }),
+                self.catalog,
+            ))
+        }
+    }
+}
+
+impl<'a> Drop for SysTableScan<'a> {
+    fn drop(&mut self) {
+        unsafe { pg_sys::systable_endscan(self.scan) }
+    }
+}
+
+/// A row of a relation, which can be read attribute by attribute, or for a system catalog,
+/// through the catalog's fixed-size row struct with `get_struct()`.
+///
+/// A row returned by a `SysTableScan` or `TableScan` is a copy of the relation's tuple, and is
+/// `pfree()`'d when dropped.  The `OLD` and `NEW` rows of a `PgTrigger` belong to Postgres.
+pub struct PgHeapTuple<'a> {
+    tuple: PgBox<pg_sys::HeapTupleData>,
+    relation: &'a PgRelation,
+}
+
+impl<'a> PgHeapTuple<'a> {
+    /// Wrap a Postgres-provided `tuple` of `relation`, which is not freed when dropped
+    pub(crate) fn from_pg(tuple: pg_sys::HeapTuple, relation: &'a PgRelation) -> Self {
+        PgHeapTuple {
+            tuple: PgBox::from_pg(tuple),
+            relation,
+        }
+    }
+
+    /// Wrap a copy of a `tuple` of `relation`, which is `pfree()`'d when dropped
+    pub(crate) fn from_rust(tuple: PgBox<pg_sys::HeapTupleData>, relation: &'a PgRelation) -> Self {
+        PgHeapTuple { tuple, relation }
+    }
+
+    /// The tuple descriptor of the relation this row is from
+    pub fn tuple_desc(&self) -> PgTupleDesc<'a> {
+        self.relation.tuple_desc()
+    }
+
+    /// The number of attributes, including any that have been dropped
+    pub fn len(&self) -> usize {
+        self.tuple_desc().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the value of the named attribute.  Returns `None` if the attribute is NULL.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the relation has no attribute of the specified name
+    pub fn get_by_name<T: FromDatum>(&self, name: &str) -> Option<T> {
+        // positions count dropped attributes, which keep their place in the tuple
+        match self
+            .tuple_desc()
+            .iter_all()
+            .position(|attr| !attr.is_dropped() && attr.name() == name)
+        {
+            Some(i) => self.get_by_index(i),
+            None => panic!(
+                "relation \"{}\" has no attribute named '{}'",
+                self.relation.name(),
+                name
+            ),
+        }
+    }
+
+    /// Get the value of the attribute at the specified zero-based index.  Returns `None` if the
+    /// attribute is NULL.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the index is out of bounds
+    pub fn get_by_index<T: FromDatum>(&self, i: usize) -> Option<T> {
+        let tupdesc = self.tuple_desc();
+        if i >= tupdesc.len() {
+            panic!(
+                "attribute index {} out of bounds for relation with {} attributes",
+                i,
+                tupdesc.len()
+            );
+        }
+
+        heap_getattr(&self.tuple, i + 1, &tupdesc)
+    }
+
+    /// The fixed-size part of this row, as `GETSTRUCT()` returns, such as a
+    /// `pg_sys::FormData_pg_attribute` for a row of `pg_attribute`
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `T` isn't the row struct of the catalog this row is from
+    pub fn get_struct<T: CatalogStruct>(&self) -> &T {
+        if self.relation.oid() != T::RELATION_ID {
+            panic!(
+                "a row of \"{}\" is not a {}",
+                self.relation.name(),
+                std::any::type_name::<T>()
+            );
+        }
+
+        unsafe { &*(pg_sys::pgx_GETSTRUCT(self.tuple.as_ptr()) as *const T) }
+    }
+
+    /// The underlying `pg_sys::HeapTuple`
+    pub fn as_ptr(&self) -> pg_sys::HeapTuple {
+        self.tuple.as_ptr()
+    }
+
+    /// The underlying tuple, which is still freed when dropped if this row is a copy
+    pub fn into_inner(self) -> PgBox<pg_sys::HeapTupleData> {
+        self.tuple
+    }
+}
+
+/// The fixed-size row struct of a system catalog, as `GETSTRUCT()` returns, readable from a
+/// `PgHeapTuple` of that catalog with `PgHeapTuple::get_struct()`
+///
+/// ## Safety
+///
+/// Implementations must be the `#[repr(C)]` layout of the fixed-size columns of the catalog
+/// `RELATION_ID`
+pub unsafe trait CatalogStruct {
+    /// The oid of the catalog, such as `pg_sys::AttributeRelationId`
+    const RELATION_ID: pg_sys::Oid;
+}
+
+unsafe impl CatalogStruct for pg_sys::FormData_pg_attribute {
+    const RELATION_ID: pg_sys::Oid = pg_sys::AttributeRelationId;
+}
+
+unsafe impl CatalogStruct for pg_sys::FormData_pg_class {
+    const RELATION_ID: pg_sys::Oid = pg_sys::RelationRelationId;
+}
+
+unsafe impl CatalogStruct for pg_sys::FormData_pg_constraint {
+    const RELATION_ID: pg_sys::Oid = pg_sys::ConstraintRelationId;
+}
+
+unsafe impl CatalogStruct for pg_sys::FormData_pg_depend {
+    const RELATION_ID: pg_sys::Oid = pg_sys::DependRelationId;
+}
+
+unsafe impl CatalogStruct for pg_sys::FormData_pg_index {
+    const RELATION_ID: pg_sys::Oid = pg_sys::IndexRelationId;
+}
+
+unsafe impl CatalogStruct for pg_sys::FormData_pg_type {
+    const RELATION_ID: pg_sys::Oid = pg_sys::TypeRelationId;
+}
@@ -0,0 +1,424 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Scanning and modifying tables the same way on every supported Postgres version.
+//!
+//! Postgres 12 put table access methods between the executor and the heap, replacing the
+//! `heap_*()` functions that extensions used to call with `table_*()` functions that dispatch
+//! to the relation's access method, and many of those are static inline functions.  The
+//! functions here call whichever the Postgres version pgx is compiled for provides, so the
+//! same extension code works with all of them.
+//!
+//! Rows are passed in and out through a `PgTupleTableSlot`.  On Postgres 10 and 11, which have
+//! no table access methods, the slot holds a heap tuple.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//! use pgx::tableam::{tuple_insert, TableScan};
+//!
+//! // copy every row of one table into another with the same columns
+//! let source = PgRelation::open_with_name_and_share_lock("source").unwrap();
+//! let target_oid = Spi::get_one::<pg_sys::Oid>("SELECT 'target'::regclass::oid").unwrap();
+//! let target = PgRelation::with_lock(target_oid, pg_sys::RowExclusiveLock as pg_sys::LOCKMODE);
+//! let tupdesc = target.tuple_desc();
+//! let mut row = PgTupleTableSlot::new(&tupdesc);
+//! let mut scan = TableScan::begin(&source, &[]);
+//! while let Some(slot) = scan.next_slot() {
+//!     row.store_heap_tuple(slot.copy_heap_tuple());
+//!
+//!     // `target` has no indexes, constraints or triggers, and we've checked that we may
+//!     // insert into it
+//!     unsafe { tuple_insert(&target, &mut row) };
+//! }
+//! scan.end();
+//! ```
+use crate::{
+    pg_sys, tupdesc::tupdesc_get_attr, PgHeapTuple, PgRelation, PgTupleTableSlot, ScanKeyInit,
+};
+
+/// A sequential scan of a table or materialized view, yielding the rows that match all of its
+/// keys, in no particular order.
+///
+/// Rows can be read one at a time into the scan's own slot with `next_slot()`, or as copies with
+/// `next_tuple()`, which is also what the scan yields as an `Iterator`.
+///
+/// The scan is ended when this instance is dropped, or by `end()`.
+pub struct TableScan<'a> {
+    relation: &'a PgRelation,
+    scan: ScanDesc,
+    slot: PgTupleTableSlot<'a>,
+    registered_snapshot: Option<pg_sys::Snapshot>,
+}
+
+impl<'a> TableScan<'a> {
+    /// Begin scanning `relation` for the rows that match all the `keys`, as of a snapshot the scan
+    /// registers from `GetTransactionSnapshot()`.  Under `READ COMMITTED` this is a fresh snapshot
+    /// that includes changes made by earlier commands in the current transaction.
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if `relation` is not a table or materialized view, if a key's
+    /// attribute doesn't exist, or if a key's value can't be compared with its attribute
+    pub fn begin(relation: &'a PgRelation, keys: &[ScanKeyInit]) -> Self {
+        unsafe { TableScan::begin_with_snapshot(relation, None, keys) }
+    }
+
+    /// Begin scanning `relation` for the rows visible to `snapshot` that match all the `keys`,
+    /// or as `begin()` does if `snapshot` is `None`
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe as it cannot know if `snapshot` is valid, and will stay registered
+    /// or active until the scan ends
+    ///
+    /// ## Panics
+    ///
+    /// As for `begin()`
+    pub unsafe fn begin_with_snapshot(
+        relation: &'a PgRelation,
+        snapshot: Option<pg_sys::Snapshot>,
+        keys: &[ScanKeyInit],
+    ) -> Self {
+        if !relation.is_table() && !relation.is_matview() {
+            panic!(
+                "relation \"{}\" is not a table or materialized view",
+                relation.name()
+            );
+        }
+
+        let tupdesc = relation.tuple_desc();
+        let mut scankeys = vec![pg_sys::ScanKeyData::default(); keys.len()];
+        for (key, scankey) in keys.iter().zip(scankeys.iter_mut()) {
+            key.init(scankey, &tupdesc);
+        }
+
+        let registered_snapshot = match snapshot {
+            Some(_) => None,
+            None => Some(pg_sys::RegisterSnapshot(pg_sys::GetTransactionSnapshot())),
+        };
+
+        // the scan keeps its own copy of the keys
+        let scan = begin_scan(
+            relation.as_ptr(),
+            snapshot.or(registered_snapshot).unwrap(),
+            &mut scankeys,
+        );
+
+        TableScan {
+            relation,
+            scan,
+            slot: PgTupleTableSlot::for_relation(relation),
+            registered_snapshot,
+        }
+    }
+
+    /// The relation being scanned
+    pub fn relation(&self) -> &'a PgRelation {
+        self.relation
+    }
+
+    /// Read the next row into the scan's slot, and return the slot, or `None` once there are no
+    /// more rows.
+    ///
+    /// The slot's row may point into a shared buffer, and is only valid until the next call.
+    pub fn next_slot(&mut self) -> Option<&PgTupleTableSlot<'a>> {
+        if unsafe { getnext_slot(self.relation.as_ptr(), self.scan, self.slot.as_ptr()) } {
+            Some(&self.slot)
+        } else {
+            None
+        }
+    }
+
+    /// The next row, copied into the `CurrentMemoryContext`, or `None` once there are no more
+    /// rows.  The copy is `pfree()`'d when it's dropped.
+    pub fn next_tuple(&mut self) -> Option<PgHeapTuple<'a>> {
+        let relation = self.relation;
+        self.next_slot()
+            .map(|slot| PgHeapTuple::from_rust(slot.copy_heap_tuple(), relation))
+    }
+
+    /// End the scan now, rather than when this instance is dropped
+    pub fn end(self) {}
+}
+
+impl<'a> Iterator for TableScan<'a> {
+    type Item = PgHeapTuple<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_tuple()
+    }
+}
+
+impl<'a> Drop for TableScan<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            // drop the slot's pin on the current buffer before the scan releases its own
+            self.slot.clear();
+            end_scan(self.relation.as_ptr(), self.scan);
+            if let Some(snapshot) = self.registered_snapshot {
+                pg_sys::UnregisterSnapshot(snapshot);
+            }
+        }
+    }
+}
+
+/// Insert the row held by `slot` into `relation`, as the current command, and return its tid.
+///
+/// ## Safety
+///
+/// As with Postgres' `simple_heap_insert()`, the relation's indexes are not updated, constraints
+/// and triggers aren't checked or fired, and neither are privileges or row level security
+/// policies.  The caller must make sure that's acceptable for `relation`, which has been opened
+/// with at least a `RowExclusiveLock`.  The row isn't visible to scans started by the same
+/// command until `pg_sys::CommandCounterIncrement()` is called.
+///
+/// ## Panics
+///
+/// This function will panic if `relation` isn't a table, or if `slot` is empty or its attributes'
+/// types aren't those of `relation`
+pub unsafe fn tuple_insert(
+    relation: &PgRelation,
+    slot: &mut PgTupleTableSlot,
+) -> pg_sys::ItemPointerData {
+    check_relation(relation);
+    check_slot(relation, slot);
+    insert(relation.as_ptr(), slot.as_ptr())
+}
+
+/// Delete the row of `relation` with the tid `tid`, as the current command.  An ERROR is raised if
+/// the row has been updated or deleted concurrently
+///
+/// ## Safety
+///
+/// As with Postgres' `simple_heap_delete()`, triggers aren't fired, foreign keys referencing the
+/// row aren't checked, and neither are privileges or row level security policies.  The caller
+/// must make sure that's acceptable for `relation`, which has been opened with at least a
+/// `RowExclusiveLock`.
+///
+/// ## Panics
+///
+/// This function will panic if `relation` isn't a table
+pub unsafe fn tuple_delete(relation: &PgRelation, tid: pg_sys::ItemPointerData) {
+    check_relation(relation);
+    delete(relation.as_ptr(), tid)
+}
+
+/// Replace the row of `relation` with the tid `tid` with the row held by `slot`, as the current
+/// command, and return the tid of the new version of the row.  An ERROR is raised if the row has
+/// been updated or deleted concurrently
+///
+/// ## Safety
+///
+/// As with Postgres' `simple_heap_update()`, the relation's indexes are not updated, constraints
+/// and triggers aren't checked or fired, and neither are privileges or row level security
+/// policies.  The caller must make sure that's acceptable for `relation`, which has been opened
+/// with at least a `RowExclusiveLock`.
+///
+/// ## Panics
+///
+/// This function will panic if `relation` isn't a table, or if `slot` is empty or its attributes'
+/// types aren't those of `relation`
+pub unsafe fn tuple_update(
+    relation: &PgRelation,
+    tid: pg_sys::ItemPointerData,
+    slot: &mut PgTupleTableSlot,
+) -> pg_sys::ItemPointerData {
+    check_relation(relation);
+    check_slot(relation, slot);
+    update(relation.as_ptr(), tid, slot.as_ptr())
+}
+
+/// Views, partitioned tables, foreign tables and the like have no storage (or table access
+/// method) to write to
+fn check_relation(relation: &PgRelation) {
+    if !relation.is_table() {
+        panic!("relation \"{}\" is not a table", relation.name());
+    }
+}
+
+fn check_slot(relation: &PgRelation, slot: &PgTupleTableSlot) {
+    if slot.is_empty() {
+        panic!("cannot write an empty TupleTableSlot");
+    }
+
+    let natts = relation.tuple_desc().len();
+    if slot.natts() != natts {
+        panic!(
+            "relation \"{}\" has {} attributes but the TupleTableSlot has {}",
+            relation.name(),
+            natts,
+            slot.natts()
+        );
+    }
+
+    let (relation_tupdesc, slot_tupdesc) = unsafe { (&*relation.rd_att, &*slot.tuple_desc()) };
+    for i in 0..natts {
+        let expected = tupdesc_get_attr(relation_tupdesc, i);
+        let actual = tupdesc_get_attr(slot_tupdesc, i);
+
+        // a dropped attribute's value is always NULL, whatever its type
+        if !expected.attisdropped && actual.atttypid != expected.atttypid {
+            panic!(
+                "attribute {} of relation \"{}\" has type oid {} but the TupleTableSlot's has type oid {}",
+                i + 1,
+                relation.name(),
+                expected.atttypid,
+                actual.atttypid
+            );
+        }
+    }
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+type ScanDesc = pg_sys::HeapScanDesc;
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+type ScanDesc = pg_sys::TableScanDesc;
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn begin_scan(
+    relation: pg_sys::Relation,
+    snapshot: pg_sys::Snapshot,
+    keys: &mut [pg_sys::ScanKeyData],
+) -> ScanDesc {
+    pg_sys::heap_beginscan(relation, snapshot, keys.len() as i32, keys.as_mut_ptr())
+}
+
+/// Same flags as `table_beginscan()`, which is a static inline function, so we call the table
+/// access method's `scan_begin` directly
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn begin_scan(
+    relation: pg_sys::Relation,
+    snapshot: pg_sys::Snapshot,
+    keys: &mut [pg_sys::ScanKeyData],
+) -> ScanDesc {
+    let scan_begin = (*(*relation).rd_tableam)
+        .scan_begin
+        .expect("table access method has no scan_begin function");
+    let flags = pg_sys::ScanOptions_SO_TYPE_SEQSCAN
+        | pg_sys::ScanOptions_SO_ALLOW_STRAT
+        | pg_sys::ScanOptions_SO_ALLOW_SYNC
+        | pg_sys::ScanOptions_SO_ALLOW_PAGEMODE;
+    let (nkeys, keys) = (keys.len() as i32, keys.as_mut_ptr());
+
+    pg_sys::guard(|| scan_begin(relation, snapshot, nkeys, keys, std::ptr::null_mut(), flags))
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn getnext_slot(
+    _relation: pg_sys::Relation,
+    scan: ScanDesc,
+    slot: *mut pg_sys::TupleTableSlot,
+) -> bool {
+    let tuple = pg_sys::heap_getnext(scan, pg_sys::ScanDirection_ForwardScanDirection);
+    if tuple.is_null() {
+        crate::slot::exec_clear_tuple(slot);
+        false
+    } else {
+        // the slot keeps its own pin on the scan's current buffer
+        pg_sys::ExecStoreTuple(tuple, slot, (*scan).rs_cbuf, false);
+        true
+    }
+}
+
+/// `table_scan_getnextslot()` is a static inline function
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn getnext_slot(
+    relation: pg_sys::Relation,
+    scan: ScanDesc,
+    slot: *mut pg_sys::TupleTableSlot,
+) -> bool {
+    let scan_getnextslot = (*(*relation).rd_tableam)
+        .scan_getnextslot
+        .expect("table access method has no scan_getnextslot function");
+    pg_sys::guard(|| scan_getnextslot(scan, pg_sys::ScanDirection_ForwardScanDirection, slot))
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn end_scan(_relation: pg_sys::Relation, scan: ScanDesc) {
+    pg_sys::heap_endscan(scan);
+}
+
+/// `table_endscan()` is a static inline function
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn end_scan(relation: pg_sys::Relation, scan: ScanDesc) {
+    let scan_end = (*(*relation).rd_tableam)
+        .scan_end
+        .expect("table access method has no scan_end function");
+    pg_sys::guard(|| scan_end(scan));
+}
+
+/// The slot's heap tuple, which the slot owns, is given its new tid by `simple_heap_insert()`
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn insert(
+    relation: pg_sys::Relation,
+    slot: *mut pg_sys::TupleTableSlot,
+) -> pg_sys::ItemPointerData {
+    let tuple = pg_sys::ExecMaterializeSlot(slot);
+    pg_sys::simple_heap_insert(relation, tuple);
+    (*tuple).t_self
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn insert(
+    relation: pg_sys::Relation,
+    slot: *mut pg_sys::TupleTableSlot,
+) -> pg_sys::ItemPointerData {
+    pg_sys::simple_table_tuple_insert(relation, slot);
+    (*slot).tts_tid
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn delete(relation: pg_sys::Relation, tid: pg_sys::ItemPointerData) {
+    let mut tid = tid;
+    pg_sys::simple_heap_delete(relation, &mut tid);
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn delete(relation: pg_sys::Relation, tid: pg_sys::ItemPointerData) {
+    let mut tid = tid;
+    pg_sys::simple_table_tuple_delete(relation, &mut tid, active_snapshot());
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+unsafe fn update(
+    relation: pg_sys::Relation,
+    tid: pg_sys::ItemPointerData,
+    slot: *mut pg_sys::TupleTableSlot,
+) -> pg_sys::ItemPointerData {
+    let mut tid = tid;
+    let tuple = pg_sys::ExecMaterializeSlot(slot);
+    pg_sys::simple_heap_update(relation, &mut tid, tuple);
+    (*tuple).t_self
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn update(
+    relation: pg_sys::Relation,
+    tid: pg_sys::ItemPointerData,
+    slot: *mut pg_sys::TupleTableSlot,
+) -> pg_sys::ItemPointerData {
+    let mut tid = tid;
+    let mut update_indexes = false;
+    pg_sys::simple_table_tuple_update(
+        relation,
+        &mut tid,
+        slot,
+        active_snapshot(),
+        &mut update_indexes,
+    );
+    (*slot).tts_tid
+}
+
+/// The snapshot the `simple_table_tuple_*()` functions are given, which the heap doesn't use,
+/// as the executor would give them
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+unsafe fn active_snapshot() -> pg_sys::Snapshot {
+    if pg_sys::ActiveSnapshotSet() {
+        pg_sys::GetActiveSnapshot()
+    } else {
+        pg_sys::GetTransactionSnapshot()
+    }
+}
@@ -0,0 +1,374 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Temporary tables that clean up after themselves
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//! use pgx::temp::{create_temp_table, OnCommit, TempTableColumns};
+//!
+//! let scratch = create_temp_table(
+//!     "scratch",
+//!     TempTableColumns::Columns(&[("id", "int"), ("value", "text")]),
+//!     OnCommit::Preserve,
+//! );
+//! scratch.copy_in(&["id", "value"], (1..=10).map(|i| vec![Some(i.to_string()), None]));
+//!
+//! let cnt = Spi::get_one::<i64>(&format!("SELECT count(*) FROM {}", scratch.qualified_name()));
+//! assert_eq!(cnt, Some(10));
+//!
+//! // `scratch` is dropped here, and so is the table
+//! ```
+use crate::quote::quote_identifier;
+use crate::{
+    direct_function_call, ensure_not_in_recovery, pg_sys, register_subxact_callback,
+    register_xact_callback, IntoDatum, PgCStr, PgOid, PgRelation, PgSubXactCallbackEvent,
+    PgTupleDesc, PgXactCallbackEvent, Spi, SubXactCallbackReceipt, XactCallbackReceipt,
+};
+use std::cell::Cell;
+use std::ffi::CString;
+use std::ops::Deref;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+/// What happens to a temporary table at the end of the transaction that created it, as
+/// per `CREATE TEMPORARY TABLE ... ON COMMIT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnCommit {
+    /// The table is dropped
+    Drop,
+    /// The table's rows are deleted, but the table remains for the rest of the session
+    DeleteRows,
+    /// The table and its rows remain for the rest of the session
+    Preserve,
+}
+
+impl OnCommit {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            OnCommit::Drop => "DROP",
+            OnCommit::DeleteRows => "DELETE ROWS",
+            OnCommit::Preserve => "PRESERVE ROWS",
+        }
+    }
+}
+
+/// The columns of a temporary table
+pub enum TempTableColumns<'a> {
+    /// Copy the (non-dropped) attributes of a tuple descriptor, such as another relation's
+    TupleDesc(&'a PgTupleDesc<'a>),
+
+    /// `(name, SQL type)` pairs, such as `("id", "bigint")`
+    Columns(&'a [(&'a str, &'a str)]),
+}
+
+impl<'a> From<&'a PgTupleDesc<'a>> for TempTableColumns<'a> {
+    fn from(tupdesc: &'a PgTupleDesc<'a>) -> Self {
+        TempTableColumns::TupleDesc(tupdesc)
+    }
+}
+
+impl<'a> From<&'a [(&'a str, &'a str)]> for TempTableColumns<'a> {
+    fn from(columns: &'a [(&'a str, &'a str)]) -> Self {
+        TempTableColumns::Columns(columns)
+    }
+}
+
+/// A temporary table created by `create_temp_table()`.
+///
+/// It derefs to the table's `PgRelation`, opened with a `RowExclusiveLock`.
+///
+/// The table is dropped when the `TempTable` is dropped, unless `.keep()` is called, in which case
+/// its `OnCommit` behavior applies (and Postgres drops any remaining temporary tables when the
+/// session ends).  If the (sub)transaction that created the table aborts, the table no longer
+/// exists and dropping the `TempTable` is a no-op.  If it's dropped while unwinding from an ERROR
+/// that a subtransaction catches, the table is dropped when the transaction commits.
+pub struct TempTable {
+    name: String,
+    oid: pg_sys::Oid,
+    on_commit: OnCommit,
+    relation: Option<PgRelation>,
+
+    /// cleared when the transaction or subtransaction that created the table ends such that
+    /// the table (and our relcache reference to it) no longer exists
+    alive: Rc<Cell<bool>>,
+    xact_receipts: Vec<XactCallbackReceipt>,
+    subxact_receipts: Vec<SubXactCallbackReceipt>,
+}
+
+/// Create a temporary table with a unique name based on `name_hint`.
+///
+/// Characters of `name_hint` that aren't ASCII alphanumerics are replaced with underscores, and
+/// if a temporary table of that name already exists in this session, a counter is appended to it
+/// (`hint_1`, `hint_2`, ...).  The generated name is available from `TempTable::name()`.
+///
+/// ## Panics
+///
+/// If the table can't be created, such as when a column type doesn't exist
+pub fn create_temp_table<'a, C: Into<TempTableColumns<'a>>>(
+    name_hint: &str,
+    columns: C,
+    on_commit: OnCommit,
+) -> TempTable {
+    ensure_not_in_recovery("CREATE TEMPORARY TABLE");
+
+    let columns = match columns.into() {
+        TempTableColumns::TupleDesc(tupdesc) => tupdesc
+            .iter()
+            .map(|attr| {
+                format!(
+                    "{} {}",
+                    quote_identifier(attr.name()),
+                    format_type(attr.type_oid(), attr.type_mod())
+                )
+            })
+            .collect::<Vec<_>>(),
+        TempTableColumns::Columns(columns) => columns
+            .iter()
+            .map(|(name, ty)| {
+                let (typid, typmod) = parse_type(ty);
+                format!("{} {}", quote_identifier(name), format_type(typid, typmod))
+            })
+            .collect::<Vec<_>>(),
+    };
+
+    let name = unique_name(name_hint);
+    Spi::run(&format!(
+        "CREATE TEMPORARY TABLE {} ({}) ON COMMIT {}",
+        quote_identifier(&name),
+        columns.join(", "),
+        on_commit.as_sql()
+    ));
+
+    let oid = temp_table_oid(&name).expect("newly created temporary table does not exist");
+    let alive = Rc::new(Cell::new(true));
+
+    // once the subtransaction that owns the table aborts, the table, and the relcache reference
+    // we're about to take, are gone.  A committed subtransaction hands the table to its parent
+    let owner = Rc::new(Cell::new(unsafe { pg_sys::GetCurrentSubTransactionId() }));
+    let subxact_receipts = vec![
+        {
+            let owner = AssertUnwindSafe(Rc::clone(&owner));
+            register_subxact_callback(
+                PgSubXactCallbackEvent::CommitSub,
+                move |my_subid, parent_subid| {
+                    if my_subid == owner.get() {
+                        owner.set(parent_subid);
+                    }
+                },
+            )
+        },
+        {
+            let owner = AssertUnwindSafe(owner);
+            let alive = AssertUnwindSafe(Rc::clone(&alive));
+            register_subxact_callback(PgSubXactCallbackEvent::AbortSub, move |my_subid, _| {
+                if my_subid == owner.get() {
+                    alive.set(false);
+                }
+            })
+        },
+    ];
+
+    // and once the top-level transaction ends, our relcache reference has been released
+    let xact_receipts = [PgXactCallbackEvent::Commit, PgXactCallbackEvent::Abort]
+        .iter()
+        .map(|event| {
+            let alive = AssertUnwindSafe(Rc::clone(&alive));
+            register_xact_callback(event.clone(), move || alive.set(false))
+        })
+        .collect();
+
+    TempTable {
+        name,
+        oid,
+        on_commit,
+        relation: Some(PgRelation::with_lock(
+            oid,
+            pg_sys::RowExclusiveLock as pg_sys::LOCKMODE,
+        )),
+        alive,
+        xact_receipts,
+        subxact_receipts,
+    }
+}
+
+impl TempTable {
+    /// The generated, unqualified, name of this table
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// This table's name qualified with `pg_temp` and quoted if necessary, suitable for SQL
+    pub fn qualified_name(&self) -> String {
+        format!("pg_temp.{}", quote_identifier(&self.name))
+    }
+
+    /// The `pg_class` oid of this table
+    pub fn oid(&self) -> pg_sys::Oid {
+        self.oid
+    }
+
+    pub fn on_commit(&self) -> OnCommit {
+        self.on_commit
+    }
+
+    /// Bulk load rows into this table using `COPY`.  See `Spi::copy_in()`
+    pub fn copy_in<I: Iterator<Item = Vec<Option<String>>>>(
+        &self,
+        columns: &[&str],
+        rows: I,
+    ) -> u64 {
+        Spi::copy_in(&self.qualified_name(), columns, rows)
+    }
+
+    /// Bulk load rows of Datums into this table using binary `COPY`.  See `Spi::copy_in_binary()`
+    pub fn copy_in_binary<I: Iterator<Item = Vec<(PgOid, Option<pg_sys::Datum>)>>>(
+        &self,
+        columns: &[&str],
+        rows: I,
+    ) -> u64 {
+        Spi::copy_in_binary(&self.qualified_name(), columns, rows)
+    }
+
+    /// Don't drop the table when this `TempTable` is dropped, leaving it to its `OnCommit`
+    /// behavior or the end of the session.  Returns the table's name.
+    pub fn keep(mut self) -> String {
+        let name = std::mem::replace(&mut self.name, String::new());
+        self.close();
+        self.alive.set(false);
+        name
+    }
+
+    fn close(&mut self) {
+        for receipt in self.xact_receipts.drain(..) {
+            receipt.unregister_callback();
+        }
+        for receipt in self.subxact_receipts.drain(..) {
+            receipt.unregister_callback();
+        }
+
+        let relation = self.relation.take();
+        if self.alive.get() {
+            drop(relation);
+        } else {
+            // Postgres already released the relcache reference
+            std::mem::forget(relation);
+        }
+    }
+}
+
+impl Deref for TempTable {
+    type Target = PgRelation;
+
+    fn deref(&self) -> &Self::Target {
+        self.relation
+            .as_ref()
+            .expect("TempTable's relation is closed")
+    }
+}
+
+impl Drop for TempTable {
+    fn drop(&mut self) {
+        let alive = self.alive.get();
+        self.close();
+
+        // any table created in an earlier transaction lives until session end
+        if !alive || !unsafe { pg_sys::IsTransactionState() } {
+            return;
+        }
+
+        if std::thread::panicking() {
+            // we can't run SQL while unwinding from an ERROR.  If it aborts the transaction the
+            // table goes with it, but if a subtransaction catches it the table would outlive us,
+            // so drop it when the transaction commits instead, unless it's already gone
+            let (name, oid) = (std::mem::replace(&mut self.name, String::new()), self.oid);
+            register_xact_callback(PgXactCallbackEvent::PreCommit, move || {
+                if temp_table_oid(&name) == Some(oid) {
+                    drop_table(&name);
+                }
+            });
+        } else {
+            drop_table(&self.name);
+        }
+    }
+}
+
+fn drop_table(name: &str) {
+    Spi::run(&format!(
+        "DROP TABLE IF EXISTS pg_temp.{}",
+        quote_identifier(name)
+    ));
+}
+
+/// `name_hint`, sanitized, with a counter appended if necessary to make it unique among this
+/// session's temporary tables
+fn unique_name(name_hint: &str) -> String {
+    let mut base = name_hint
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if base.is_empty() || base.starts_with(|c: char| c.is_ascii_digit()) {
+        base.insert_str(0, "temp_");
+    }
+
+    let mut counter = 0;
+    loop {
+        let suffix = if counter == 0 {
+            String::new()
+        } else {
+            format!("_{}", counter)
+        };
+
+        // leave room for the suffix within NAMEDATALEN
+        let max_len = pg_sys::NAMEDATALEN as usize - 1 - suffix.len();
+        let name = format!("{}{}", &base[..base.len().min(max_len)], suffix);
+        if temp_table_oid(&name).is_none() {
+            return name;
+        }
+        counter += 1;
+    }
+}
+
+fn temp_table_oid(name: &str) -> Option<pg_sys::Oid> {
+    let qualified = format!("pg_temp.{}", quote_identifier(name));
+    unsafe {
+        direct_function_call::<pg_sys::Oid>(pg_sys::to_regclass, vec![qualified.into_datum()])
+    }
+}
+
+/// The type oid and typmod of a SQL type name, such as `varchar(10)`
+fn parse_type(type_name: &str) -> (pg_sys::Oid, i32) {
+    let type_name = CString::new(type_name).expect("type name contains a NUL");
+    let (mut typid, mut typmod) = (pg_sys::InvalidOid, -1);
+    unsafe { pg_sys::parseTypeString(type_name.as_ptr(), &mut typid, &mut typmod, false) };
+    (typid, typmod)
+}
+
+#[cfg(feature = "pg10")]
+fn format_type(typid: pg_sys::Oid, typmod: i32) -> String {
+    unsafe { PgCStr::from_ptr(pg_sys::format_type_with_typemod_qualified(typid, typmod)) }
+        .expect("format_type_with_typemod_qualified() returned NULL")
+        .to_string()
+}
+
+#[cfg(any(feature = "pg11", feature = "pg12", feature = "pg13"))]
+fn format_type(typid: pg_sys::Oid, typmod: i32) -> String {
+    unsafe {
+        PgCStr::from_ptr(pg_sys::format_type_extended(
+            typid,
+            typmod,
+            (pg_sys::FORMAT_TYPE_TYPEMOD_GIVEN | pg_sys::FORMAT_TYPE_FORCE_QUALIFY)
+                as pg_sys::bits16,
+        ))
+    }
+    .expect("format_type_extended() returned NULL")
+    .to_string()
+}
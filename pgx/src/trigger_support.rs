@@ -2,13 +2,38 @@
 // governed by the MIT license that can be found in the LICENSE file.
 
 //! Helper functions for working with custom Rust trigger functions
+//!
+//! A trigger function is a `#[pg_extern]` function that takes a `pg_sys::FunctionCallInfo` and
+//! returns a `TriggerResult`, which is declared `RETURNS trigger`.  It reads the `TriggerData`
+//! Postgres calls it with through a `PgTrigger`.  `create_trigger!()` creates a trigger that calls
+//! it from the extension's script, including a deferrable `CONSTRAINT` trigger.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//!
+//! /// `CREATE TRIGGER ... BEFORE INSERT OR UPDATE ON ... FOR EACH ROW EXECUTE PROCEDURE ...`
+//! #[pg_extern]
+//! fn skip_empty_names(fcinfo: pg_sys::FunctionCallInfo) -> TriggerResult {
+//!     let trigger = PgTrigger::from_fcinfo(fcinfo);
+//!     let new = trigger.new_row().expect("not a row-level INSERT or UPDATE trigger");
+//!     match new.get_by_name::<&str>("name") {
+//!         Some("") => TriggerResult::Skip,
+//!         _ => TriggerResult::from(new),
+//!     }
+//! }
+//! ```
 
-use crate::{is_a, pg_sys};
+use crate::{pg_sys, IntoDatum, PgBox, PgHeapTuple, PgRelation, PgTupleDesc};
+use std::ffi::CStr;
 
 #[inline]
 pub fn called_as_trigger(fcinfo: pg_sys::FunctionCallInfo) -> bool {
     let fcinfo = unsafe { fcinfo.as_ref() }.expect("fcinfo was null");
-    !fcinfo.context.is_null() && is_a(fcinfo.context, pg_sys::NodeTag_T_TriggerData)
+    unsafe { fcinfo.context_ref() }.map_or(false, |context| {
+        context.type_ == pg_sys::NodeTag_T_TriggerData
+    })
 }
 
 #[inline]
@@ -55,3 +80,319 @@ pub fn trigger_fired_after(event: u32) -> bool {
 pub fn trigger_fired_instead(event: u32) -> bool {
     event & pg_sys::TRIGGER_EVENT_TIMINGMASK == pg_sys::TRIGGER_EVENT_INSTEAD
 }
+
+/// The `TriggerData` of a call to a trigger function.
+///
+/// ## INSTEAD OF triggers
+///
+/// A row-level `INSTEAD OF` trigger on a view does the `INSERT`, `UPDATE`, or `DELETE` on the
+/// view's behalf, typically by modifying the tables the view is over.  It then returns:
+///
+/// - `TriggerResult::Processed` with a row of the **view** if it processed the row, which is
+///   counted in the command's row count and is the row any `RETURNING` clause sees.  That's
+///   usually the `NEW` row for an `INSERT` or `UPDATE`, perhaps with values the trigger filled in,
+///   such as a generated key, and the `OLD` row for a `DELETE`, which `processed()` picks
+/// - `TriggerResult::Skip` if it didn't, such as when the row to delete is already gone
+///
+/// The trigger's `relation()` is the view itself, so rows built with `processed_with()` have the
+/// view's columns, not those of any of the tables underneath it.
+///
+/// ## Deferred constraint triggers
+///
+/// A `CONSTRAINT` trigger that's `deferrable` is fired at the end of the transaction when its
+/// constraint is deferred, either `initially_deferred` or by `SET CONSTRAINTS`, instead of at the
+/// end of each statement, and its ERRORs are raised by `COMMIT`.  Its `tg_event` is the same
+/// either way, and Postgres doesn't tell it which it is, so `is_deferrable()` is as close as it
+/// can get.
+///
+/// Each firing is for an event that happened earlier in the transaction, and its `OLD` and `NEW`
+/// rows are the versions of the row at the time, which later statements may have updated or
+/// deleted since.  A trigger that checks an invariant should check the current rows, such as by
+/// querying them with SPI, and can expect to be fired once for each change it's checking.
+pub struct PgTrigger {
+    data: PgBox<pg_sys::TriggerData>,
+    relation: PgRelation,
+}
+
+impl PgTrigger {
+    /// The `TriggerData` of a trigger function's `fcinfo`
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the function wasn't called by the trigger manager
+    pub fn from_fcinfo(fcinfo: pg_sys::FunctionCallInfo) -> Self {
+        if !called_as_trigger(fcinfo) {
+            panic!("function was not called by the trigger manager");
+        }
+
+        let data = PgBox::from_pg(unsafe { (*fcinfo).context } as *mut pg_sys::TriggerData);
+        let relation = unsafe { PgRelation::from_pg(data.tg_relation) };
+        PgTrigger { data, relation }
+    }
+
+    /// The name of the trigger, from `CREATE TRIGGER`
+    pub fn name(&self) -> &str {
+        unsafe { CStr::from_ptr((*self.data.tg_trigger).tgname) }
+            .to_str()
+            .expect("trigger name is not valid UTF8")
+    }
+
+    /// The arguments given to the trigger function in `CREATE TRIGGER`
+    pub fn args(&self) -> Vec<&str> {
+        let trigger = unsafe { self.data.tg_trigger.as_ref() }.expect("tg_trigger is NULL");
+        (0..trigger.tgnargs as usize)
+            .map(|i| {
+                unsafe { CStr::from_ptr(*trigger.tgargs.add(i)) }
+                    .to_str()
+                    .expect("trigger argument is not valid UTF8")
+            })
+            .collect()
+    }
+
+    /// The `TRIGGER_EVENT_*` flags the trigger was fired for, which the `trigger_fired_*()`
+    /// functions test
+    pub fn event(&self) -> u32 {
+        self.data.tg_event
+    }
+
+    pub fn fired_by_insert(&self) -> bool {
+        trigger_fired_by_insert(self.event())
+    }
+
+    pub fn fired_by_update(&self) -> bool {
+        trigger_fired_by_update(self.event())
+    }
+
+    pub fn fired_by_delete(&self) -> bool {
+        trigger_fired_by_delete(self.event())
+    }
+
+    pub fn fired_by_truncate(&self) -> bool {
+        trigger_fired_by_truncate(self.event())
+    }
+
+    pub fn fired_for_row(&self) -> bool {
+        trigger_fired_for_row(self.event())
+    }
+
+    pub fn fired_for_statement(&self) -> bool {
+        trigger_fired_for_statement(self.event())
+    }
+
+    pub fn fired_before(&self) -> bool {
+        trigger_fired_before(self.event())
+    }
+
+    pub fn fired_after(&self) -> bool {
+        trigger_fired_after(self.event())
+    }
+
+    pub fn fired_instead(&self) -> bool {
+        trigger_fired_instead(self.event())
+    }
+
+    /// Is this a `CONSTRAINT` trigger?
+    pub fn is_constraint(&self) -> bool {
+        self.trigger().tgconstraint != pg_sys::InvalidOid
+    }
+
+    /// Can this constraint trigger be fired at the end of the transaction, rather than the end
+    /// of the statement?  It is when the constraint is deferred
+    pub fn is_deferrable(&self) -> bool {
+        self.trigger().tgdeferrable
+    }
+
+    /// Is this constraint trigger fired at the end of the transaction, unless `SET CONSTRAINTS`
+    /// says otherwise?
+    pub fn is_initially_deferred(&self) -> bool {
+        self.trigger().tginitdeferred
+    }
+
+    /// The oid of the table a constraint trigger references, its `FROM` table
+    pub fn referenced_relation_oid(&self) -> Option<pg_sys::Oid> {
+        match self.trigger().tgconstrrelid {
+            pg_sys::InvalidOid => None,
+            oid => Some(oid),
+        }
+    }
+
+    fn trigger(&self) -> &pg_sys::Trigger {
+        unsafe { self.data.tg_trigger.as_ref() }.expect("tg_trigger is NULL")
+    }
+
+    /// The relation the trigger is on:  a table, or the view of an `INSTEAD OF` trigger
+    pub fn relation(&self) -> &PgRelation {
+        &self.relation
+    }
+
+    /// The tuple descriptor of `relation()`, which describes the `OLD` and `NEW` rows
+    pub fn tuple_desc(&self) -> PgTupleDesc {
+        self.relation.tuple_desc()
+    }
+
+    /// The `OLD` row of a row-level `UPDATE` or `DELETE` trigger
+    pub fn old_row(&self) -> Option<PgHeapTuple> {
+        if self.fired_for_row() && (self.fired_by_update() || self.fired_by_delete()) {
+            self.row(self.data.tg_trigtuple)
+        } else {
+            None
+        }
+    }
+
+    /// The `NEW` row of a row-level `INSERT` or `UPDATE` trigger
+    pub fn new_row(&self) -> Option<PgHeapTuple> {
+        if !self.fired_for_row() {
+            None
+        } else if self.fired_by_insert() {
+            self.row(self.data.tg_trigtuple)
+        } else if self.fired_by_update() {
+            self.row(self.data.tg_newtuple)
+        } else {
+            None
+        }
+    }
+
+    fn row(&self, tuple: pg_sys::HeapTuple) -> Option<PgHeapTuple> {
+        if tuple.is_null() {
+            None
+        } else {
+            Some(PgHeapTuple::from_pg(tuple, &self.relation))
+        }
+    }
+
+    /// The row that tells Postgres a row-level trigger processed the row unchanged:  `NEW` for
+    /// an `INSERT` or `UPDATE`, and `OLD` for a `DELETE`
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if this isn't a row-level trigger
+    pub fn processed(&self) -> TriggerResult {
+        match self.new_row().or_else(|| self.old_row()) {
+            Some(row) => TriggerResult::from(row),
+            None => panic!("only a row-level trigger can process a row"),
+        }
+    }
+
+    /// A new row of `relation()`, which for an `INSTEAD OF` trigger is a row of the view, built
+    /// from one value for each of its attributes, to return in place of `NEW`
+    ///
+    /// ## Safety
+    ///
+    /// Each value must be a Datum of its attribute's type, as Postgres takes it on trust
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if there isn't exactly one value for each attribute
+    pub unsafe fn processed_with(&self, values: &[Option<pg_sys::Datum>]) -> TriggerResult {
+        TriggerResult::Processed(self.tuple_desc().form_tuple(values))
+    }
+}
+
+/// What a trigger function returns to Postgres
+pub enum TriggerResult {
+    /// A row-level `BEFORE` or `INSTEAD OF` trigger processed this row.  For a `BEFORE` trigger,
+    /// it's the row that's inserted or updated in place of `NEW`
+    Processed(PgBox<pg_sys::HeapTupleData>),
+
+    /// A row-level `BEFORE` or `INSTEAD OF` trigger skipped the row, as a trigger function that
+    /// returns `NULL` does.  This is also what `AFTER` and statement-level triggers return, as
+    /// their result is ignored
+    Skip,
+}
+
+impl<'a> From<PgHeapTuple<'a>> for TriggerResult {
+    fn from(row: PgHeapTuple<'a>) -> Self {
+        TriggerResult::Processed(row.into_inner())
+    }
+}
+
+impl IntoDatum for TriggerResult {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        match self {
+            // Postgres takes the tuple from here
+            TriggerResult::Processed(tuple) => Some(tuple.into_pg() as pg_sys::Datum),
+
+            // a NULL pointer, as the trigger manager raises an ERROR if the Datum itself is NULL
+            TriggerResult::Skip => Some(0),
+        }
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        pg_sys::TRIGGEROID
+    }
+}
+
+/// A trigger defined on a relation, copied from the relation's `TriggerDesc` by
+/// `PgRelation::triggers()`
+#[derive(Debug, Clone)]
+pub struct TriggerInfo {
+    /// The trigger's `pg_trigger` oid
+    pub oid: pg_sys::Oid,
+
+    /// The name of the trigger, from `CREATE TRIGGER`
+    pub name: String,
+
+    /// The oid of the trigger function
+    pub function_oid: pg_sys::Oid,
+
+    /// The arguments given to the trigger function in `CREATE TRIGGER`
+    pub args: Vec<String>,
+
+    /// Is this a trigger Postgres created itself, such as to enforce a foreign key?
+    pub is_internal: bool,
+
+    /// The `TRIGGER_TYPE_*` flags of `pg_trigger.tgtype`, which the methods below test
+    pub tgtype: u32,
+}
+
+impl TriggerInfo {
+    pub(crate) fn from_pg(trigger: &pg_sys::Trigger) -> Self {
+        let to_string = |s: *const std::os::raw::c_char| {
+            unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+        };
+
+        TriggerInfo {
+            oid: trigger.tgoid,
+            name: to_string(trigger.tgname),
+            function_oid: trigger.tgfoid,
+            args: (0..trigger.tgnargs as usize)
+                .map(|i| to_string(unsafe { *trigger.tgargs.add(i) }))
+                .collect(),
+            is_internal: trigger.tgisinternal,
+            tgtype: trigger.tgtype as u16 as u32,
+        }
+    }
+
+    /// Is this a `FOR EACH ROW` trigger, rather than `FOR EACH STATEMENT`?
+    pub fn is_row(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_LEVEL_MASK == pg_sys::TRIGGER_TYPE_ROW
+    }
+
+    pub fn is_before(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_TIMING_MASK == pg_sys::TRIGGER_TYPE_BEFORE
+    }
+
+    pub fn is_after(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_TIMING_MASK == pg_sys::TRIGGER_TYPE_AFTER
+    }
+
+    pub fn is_instead(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_TIMING_MASK == pg_sys::TRIGGER_TYPE_INSTEAD
+    }
+
+    pub fn on_insert(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_INSERT != 0
+    }
+
+    pub fn on_update(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_UPDATE != 0
+    }
+
+    pub fn on_delete(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_DELETE != 0
+    }
+
+    pub fn on_truncate(&self) -> bool {
+        self.tgtype & pg_sys::TRIGGER_TYPE_TRUNCATE != 0
+    }
+}
@@ -2,8 +2,11 @@
 // governed by the MIT license that can be found in the LICENSE file.
 
 //! Provides a safe wrapper around Postgres' `pg_sys::TupleDescData` struct
-use crate::{pg_sys, void_mut_ptr, FromDatum, PgBox, PgRelation};
+use crate::{
+    name_data_to_str, pg_sys, void_mut_ptr, FromDatum, IntoDatum, PgBox, PgOid, PgRelation,
+};
 
+use std::collections::HashSet;
 use std::ops::Deref;
 
 /// This struct is passed around within the backend to describe the structure
@@ -152,6 +155,76 @@ impl<'a> PgTupleDesc<'a> {
         }
     }
 
+    /// Start building a new anonymous `RECORD` tuple descriptor, column by column.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust,no_run
+    /// use pgx::*;
+    ///
+    /// let tupdesc = PgTupleDesc::builder()
+    ///     .column_for::<i64>("id")
+    ///     .column("name", PgBuiltInOids::VARCHAROID.oid(), 64 + pg_sys::VARHDRSZ as i32)
+    ///     .column_for::<f64>("score")
+    ///     .bless()
+    ///     .build();
+    ///
+    /// let tuple = tupdesc.form_tuple(&[42i64.into_datum(), "bob".into_datum(), None]);
+    /// let datum = heap_tuple_get_datum(tuple.into_pg());
+    /// ```
+    pub fn builder() -> PgTupleDescBuilder {
+        PgTupleDescBuilder::default()
+    }
+
+    /// Can composite Datums of this descriptor's row type be interpreted by Postgres?
+    ///
+    /// This is true for named row types, and for anonymous `RECORD` types that have been
+    /// registered in the type cache with `BlessTupleDesc()` (which assigns them a typmod).
+    pub fn is_blessed(&self) -> bool {
+        self.tupdesc.tdtypeid != pg_sys::RECORDOID || self.tupdesc.tdtypmod >= 0
+    }
+
+    /// Form a `HeapTuple`, in the `CurrentMemoryContext`, from one value per attribute.  `None`
+    /// values are NULL.
+    ///
+    /// The returned tuple is freed when dropped.  Use `.into_pg()` to hand it (or a Datum made
+    /// from it with `heap_tuple_get_datum()`) to Postgres.
+    ///
+    /// ## Panics
+    ///
+    /// If the number of values doesn't match the number of attributes, or if this descriptor is
+    /// an anonymous `RECORD` type that hasn't been blessed, as Postgres would be unable to
+    /// interpret the tuple as a composite Datum
+    pub fn form_tuple(&self, values: &[Option<pg_sys::Datum>]) -> PgBox<pg_sys::HeapTupleData> {
+        if values.len() != self.len() {
+            panic!(
+                "tuple descriptor has {} attributes but {} values were provided",
+                self.len(),
+                values.len()
+            );
+        }
+        if !self.is_blessed() {
+            panic!("cannot form a tuple from a RECORD tuple descriptor that has not been blessed");
+        }
+
+        let mut datums = values
+            .iter()
+            .map(|value| value.unwrap_or(0))
+            .collect::<Vec<_>>();
+        let mut nulls = values
+            .iter()
+            .map(|value| value.is_none())
+            .collect::<Vec<_>>();
+
+        PgBox::from_rust(unsafe {
+            pg_sys::heap_form_tuple(
+                self.tupdesc.as_ptr(),
+                datums.as_mut_ptr(),
+                nulls.as_mut_ptr(),
+            )
+        })
+    }
+
     /// From which relation was this TupleDesc created, if any?
     pub fn parent(&self) -> Option<&PgRelation> {
         self.parent
@@ -177,12 +250,13 @@ impl<'a> PgTupleDesc<'a> {
         self.len() == 0
     }
 
-    /// Get a numbered attribute.  Attribute numbers are zero-based
-    pub fn get(&self, i: usize) -> Option<&pg_sys::FormData_pg_attribute> {
+    /// Get a numbered attribute, dropped or not.  Attribute numbers are zero-based, and `None` is
+    /// returned for numbers past the last attribute
+    pub fn get(&self, i: usize) -> Option<PgAttribute> {
         if i >= self.len() {
             None
         } else {
-            Some(tupdesc_get_attr(&self.tupdesc, i))
+            Some(PgAttribute(tupdesc_get_attr(&self.tupdesc, i)))
         }
     }
 
@@ -201,11 +275,24 @@ impl<'a> PgTupleDesc<'a> {
         )
     }
 
-    /// Iterate over our attributes
+    /// Iterate over our attributes, skipping those that have been dropped.  As dropped attributes
+    /// keep their place, use `PgAttribute::num()` rather than counting to find an attribute's
+    /// position
     pub fn iter(&self) -> TupleDescIterator {
         TupleDescIterator {
             tupdesc: self,
             curr: 0,
+            include_dropped: false,
+        }
+    }
+
+    /// Iterate over all of our attributes, including those that have been dropped, which is one
+    /// for each value of our tuples
+    pub fn iter_all(&self) -> TupleDescIterator {
+        TupleDescIterator {
+            tupdesc: self,
+            curr: 0,
+            include_dropped: true,
         }
     }
 }
@@ -228,6 +315,124 @@ impl<'a> Drop for PgTupleDesc<'a> {
     }
 }
 
+struct BuilderColumn {
+    name: String,
+    typoid: pg_sys::Oid,
+    typmod: i32,
+    collation: Option<pg_sys::Oid>,
+}
+
+/// Builds a `PgTupleDesc` for an anonymous `RECORD` type, such as the rows of a tuplestore or
+/// of a function returning `RECORD`.  Created with `PgTupleDesc::builder()`
+#[derive(Default)]
+pub struct PgTupleDescBuilder {
+    columns: Vec<BuilderColumn>,
+    bless: bool,
+}
+
+impl PgTupleDescBuilder {
+    /// Add a column of type `typoid`.  Use a `typmod` of -1 if the type has no modifier
+    pub fn column(mut self, name: &str, typoid: PgOid, typmod: i32) -> Self {
+        self.columns.push(BuilderColumn {
+            name: name.to_string(),
+            typoid: typoid.value(),
+            typmod,
+            collation: None,
+        });
+        self
+    }
+
+    /// Add a column of the SQL type that `T` converts to
+    pub fn column_for<T: IntoDatum>(self, name: &str) -> Self {
+        self.column(name, PgOid::from(T::type_oid()), -1)
+    }
+
+    /// Use `collation` for the most recently added column, instead of its type's default
+    /// collation
+    ///
+    /// ## Panics
+    ///
+    /// If no columns have been added yet
+    pub fn collation(mut self, collation: pg_sys::Oid) -> Self {
+        self.columns
+            .last_mut()
+            .expect("PgTupleDescBuilder::collation() called before adding a column")
+            .collation = Some(collation);
+        self
+    }
+
+    /// Register the built descriptor with `BlessTupleDesc()`, which is necessary if it will be
+    /// used to make composite `RECORD` Datums
+    pub fn bless(mut self) -> Self {
+        self.bless = true;
+        self
+    }
+
+    /// Build the tuple descriptor, in the `CurrentMemoryContext`.  It is freed when dropped.
+    ///
+    /// ## Panics
+    ///
+    /// If there are more than `MaxTupleAttributeNumber` columns, if two columns have the same
+    /// name, or if a column's type doesn't exist
+    pub fn build(self) -> PgTupleDesc<'static> {
+        if self.columns.len() > pg_sys::MaxTupleAttributeNumber as usize {
+            panic!(
+                "tuple descriptors can have at most {} columns, not {}",
+                pg_sys::MaxTupleAttributeNumber,
+                self.columns.len()
+            );
+        }
+
+        let mut names = HashSet::new();
+        for column in &self.columns {
+            if !names.insert(column.name.as_str()) {
+                panic!("column \"{}\" specified more than once", column.name);
+            }
+        }
+
+        let tupdesc = create_template_tupdesc(self.columns.len());
+        for (i, column) in self.columns.iter().enumerate() {
+            let attno = (i + 1) as pg_sys::AttrNumber;
+            let name = std::ffi::CString::new(column.name.as_str())
+                .expect("column name contains a NUL byte");
+            unsafe {
+                // also sets the type's default collation
+                pg_sys::TupleDescInitEntry(
+                    tupdesc,
+                    attno,
+                    name.as_ptr(),
+                    column.typoid,
+                    column.typmod,
+                    0,
+                );
+
+                if let Some(collation) = column.collation {
+                    pg_sys::TupleDescInitEntryCollation(tupdesc, attno, collation);
+                }
+            }
+        }
+
+        if self.bless {
+            // blesses in place, copying the descriptor into the type cache
+            unsafe { pg_sys::BlessTupleDesc(tupdesc) };
+        }
+
+        unsafe { PgTupleDesc::from_pg_is_copy(tupdesc) }
+    }
+}
+
+#[cfg(any(feature = "pg10", feature = "pg11"))]
+#[inline]
+fn create_template_tupdesc(natts: usize) -> pg_sys::TupleDesc {
+    unsafe { pg_sys::CreateTemplateTupleDesc(natts as i32, false) }
+}
+
+#[cfg(any(feature = "pg12", feature = "pg13"))]
+#[inline]
+fn create_template_tupdesc(natts: usize) -> pg_sys::TupleDesc {
+    unsafe { pg_sys::CreateTemplateTupleDesc(natts as i32) }
+}
+
 pub unsafe fn release_tupdesc(ptr: pg_sys::TupleDesc) {
     if (*ptr).tdrefcount >= 0 {
         pg_sys::DecrTupleDescRefCount(ptr)
@@ -237,8 +442,8 @@ pub unsafe fn release_tupdesc(ptr: pg_sys::TupleDesc) {
 /// `attno` is 0-based
 #[cfg(feature = "pg10")]
 #[inline]
-fn tupdesc_get_attr(
-    tupdesc: &PgBox<pg_sys::TupleDescData>,
+pub(crate) fn tupdesc_get_attr(
+    tupdesc: &pg_sys::TupleDescData,
     attno: usize,
 ) -> &pg_sys::FormData_pg_attribute {
     let atts = unsafe { std::slice::from_raw_parts(tupdesc.attrs, tupdesc.natts as usize) };
@@ -248,26 +453,86 @@ fn tupdesc_get_attr(
 /// `attno` is 0-based
 #[cfg(any(feature = "pg11", feature = "pg12", feature = "pg13"))]
 #[inline]
-fn tupdesc_get_attr(
-    tupdesc: &PgBox<pg_sys::TupleDescData>,
+pub(crate) fn tupdesc_get_attr(
+    tupdesc: &pg_sys::TupleDescData,
     attno: usize,
 ) -> &pg_sys::FormData_pg_attribute {
     let atts = unsafe { tupdesc.attrs.as_slice(tupdesc.natts as usize) };
     &atts[attno]
 }
 
+/// An attribute of a `PgTupleDesc`, its `pg_attribute` catalog entry.  It derefs to the
+/// `pg_sys::FormData_pg_attribute`, for what isn't covered by its methods
+#[derive(Clone, Copy)]
+pub struct PgAttribute<'a>(&'a pg_sys::FormData_pg_attribute);
+
+impl<'a> PgAttribute<'a> {
+    /// The attribute's name, `attname`
+    pub fn name(&self) -> &'a str {
+        name_data_to_str(&self.0.attname)
+    }
+
+    /// The Oid of the attribute's type, `atttypid`
+    pub fn type_oid(&self) -> pg_sys::Oid {
+        self.0.atttypid
+    }
+
+    /// The attribute's type modifier, `atttypmod`, such as the length of a `varchar(n)`, or -1 if
+    /// it has none
+    pub fn type_mod(&self) -> i32 {
+        self.0.atttypmod
+    }
+
+    /// The attribute's number, `attnum`, which starts at 1
+    pub fn num(&self) -> i16 {
+        self.0.attnum
+    }
+
+    /// Has the attribute been dropped, by `ALTER TABLE ... DROP COLUMN`?  A dropped attribute
+    /// keeps its number, and its value is always NULL
+    pub fn is_dropped(&self) -> bool {
+        self.0.attisdropped
+    }
+
+    /// The length of the attribute's type, `attlen`:  its size in bytes if it's fixed, -1 for a
+    /// varlena, or -2 for a NUL-terminated C string
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> i16 {
+        self.0.attlen
+    }
+
+    /// The `pg_attribute` catalog entry
+    pub fn form(&self) -> &'a pg_sys::FormData_pg_attribute {
+        self.0
+    }
+}
+
+impl<'a> Deref for PgAttribute<'a> {
+    type Target = pg_sys::FormData_pg_attribute;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
 pub struct TupleDescIterator<'a> {
     tupdesc: &'a PgTupleDesc<'a>,
     curr: usize,
+    include_dropped: bool,
 }
 
 impl<'a> Iterator for TupleDescIterator<'a> {
-    type Item = &'a pg_sys::FormData_pg_attribute;
+    type Item = PgAttribute<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = self.tupdesc.get(self.curr);
-        self.curr += 1;
-        result
+        loop {
+            let result = self.tupdesc.get(self.curr);
+            self.curr += 1;
+            match result {
+                Some(attr) if attr.is_dropped() && !self.include_dropped => continue,
+                result => return result,
+            }
+        }
     }
 }
 
@@ -288,17 +553,17 @@ impl<'a> IntoIterator for PgTupleDesc<'a> {
     }
 }
 
+/// As with `PgTupleDesc::iter()`, attributes that have been dropped are skipped
 impl<'a> Iterator for TupleDescDataIntoIterator<'a> {
     type Item = pg_sys::FormData_pg_attribute;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = match self.tupdesc.get(self.curr) {
-            Some(result) => *result,
-            None => {
-                return None;
+        loop {
+            let result = self.tupdesc.get(self.curr)?;
+            self.curr += 1;
+            if !result.is_dropped() {
+                return Some(*result.form());
             }
-        };
-        self.curr += 1;
-        Some(result)
+        }
     }
 }
@@ -0,0 +1,177 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Custom messages in the write-ahead log, for extension state that should be crash-recoverable
+//! or replicated, such as a queue's or an allocator's, and reading them back in a logical
+//! decoding output plugin.
+//!
+//! Postgres 10 through 13 can't register custom WAL resource managers, so the closest an
+//! extension can get to its own WAL records is a logical decoding message, which is what
+//! `log_custom_message()` writes, with the same `LogLogicalMessage()` that
+//! `pg_logical_emit_message()` uses.  They're written whatever the `wal_level`, but can only be
+//! decoded with `wal_level = logical`, when an output plugin's `message_cb` receives them, which
+//! `LogicalMessage::from_callback()` reads.
+//!
+//! Each message has a prefix, which identifies what wrote it, as other extensions can write
+//! messages too.  The `WalMessage` trait ties a prefix to a Rust type, for `log_message()` and
+//! `LogicalMessage::decode()`.
+//!
+//! ## Transactional and non-transactional messages
+//!
+//! A transactional message is decoded when the transaction that wrote it commits, in commit
+//! order along with the transaction's other changes, and is never decoded if it aborts.
+//!
+//! A non-transactional message is decoded as soon as decoding reaches it, whether or not the
+//! transaction that wrote it later commits, so it isn't rolled back with the transaction.
+//!
+//! ## Size limits
+//!
+//! Decoding reads a whole WAL record into memory, and Postgres can't allocate more than
+//! `MaxAllocSize` (1GB) at once, so a message's prefix and payload together are limited to
+//! `MAX_MESSAGE_SIZE`.  Every message is also written to the WAL in full, and so is archived and
+//! sent to replicas, so they're best kept small.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//! use pgx::wal::WalMessage;
+//!
+//! struct Allocated(u64);
+//!
+//! impl WalMessage for Allocated {
+//!     const PREFIX: &'static str = "my_extension.allocated";
+//!
+//!     fn to_payload(&self) -> Vec<u8> {
+//!         self.0.to_le_bytes().to_vec()
+//!     }
+//!
+//!     fn from_payload(payload: &[u8]) -> Option<Self> {
+//!         let mut bytes = [0; 8];
+//!         bytes.copy_from_slice(payload.get(..8)?);
+//!         Some(Allocated(u64::from_le_bytes(bytes)))
+//!     }
+//! }
+//!
+//! #[pg_extern]
+//! fn allocate(next: i64) {
+//!     pgx::wal::log_message(&Allocated(next as u64), true);
+//! }
+//! ```
+use crate::pg_sys;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+/// The most bytes a message's prefix, including its terminating NUL, and payload can add up
+/// to.  This is Postgres' `MaxAllocSize`, less room for the WAL record's headers
+pub const MAX_MESSAGE_SIZE: usize = 0x3fff_ffff - 8192;
+
+/// A Rust type that's written to the WAL as the messages with its `PREFIX`
+pub trait WalMessage: Sized {
+    /// Identifies this type's messages, so it shouldn't be used by any other type, or extension
+    const PREFIX: &'static str;
+
+    fn to_payload(&self) -> Vec<u8>;
+
+    /// Read a message written by `to_payload()`, or `None` if `payload` isn't one
+    fn from_payload(payload: &[u8]) -> Option<Self>;
+}
+
+/// Write `message` to the WAL, with its type's prefix, returning the message's LSN
+pub fn log_message<M: WalMessage>(message: &M, transactional: bool) -> pg_sys::XLogRecPtr {
+    log_custom_message(M::PREFIX, &message.to_payload(), transactional)
+}
+
+/// Write a logical decoding message with `prefix` and `payload` to the WAL, returning its LSN.
+///
+/// A transactional message gives the current transaction an xid, if it doesn't have one
+/// already.  Panics if `prefix` contains a NUL, or the message is larger than
+/// `MAX_MESSAGE_SIZE`, and raises an ERROR during recovery, as a standby can't write WAL
+pub fn log_custom_message(prefix: &str, payload: &[u8], transactional: bool) -> pg_sys::XLogRecPtr {
+    let size = prefix.len() + 1 + payload.len();
+    if size > MAX_MESSAGE_SIZE {
+        panic!(
+            "WAL message of {} bytes is larger than the limit of {} bytes",
+            size, MAX_MESSAGE_SIZE
+        );
+    }
+    let prefix = CString::new(prefix).expect("WAL message prefix contains a NUL");
+
+    emit(&prefix, payload, transactional)
+}
+
+// when a version of Postgres that can register custom WAL resource managers is supported, it
+// gets its own `emit()`, to write records of its own
+#[cfg(any(feature = "pg10", feature = "pg11", feature = "pg12", feature = "pg13"))]
+fn emit(prefix: &CStr, payload: &[u8], transactional: bool) -> pg_sys::XLogRecPtr {
+    unsafe {
+        pg_sys::LogLogicalMessage(
+            prefix.as_ptr(),
+            payload.as_ptr() as *const c_char,
+            payload.len(),
+            transactional,
+        )
+    }
+}
+
+/// A message written by `log_custom_message()` (or `pg_logical_emit_message()`), as an output
+/// plugin's `message_cb` receives it
+#[derive(Debug, Clone, Copy)]
+pub struct LogicalMessage<'a> {
+    pub lsn: pg_sys::XLogRecPtr,
+    pub transactional: bool,
+    pub prefix: &'a CStr,
+    pub payload: &'a [u8],
+}
+
+impl<'a> LogicalMessage<'a> {
+    /// Read the message from the arguments Postgres passed to a `message_cb`
+    ///
+    /// ## Safety
+    ///
+    /// The arguments must be those of a `message_cb` call, and the message can't be used after
+    /// the callback returns
+    pub unsafe fn from_callback(
+        message_lsn: pg_sys::XLogRecPtr,
+        transactional: bool,
+        prefix: *const c_char,
+        message_size: pg_sys::Size,
+        message: *const c_char,
+    ) -> Self {
+        let payload = if message_size == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(message as *const u8, message_size)
+        };
+
+        LogicalMessage {
+            lsn: message_lsn,
+            transactional,
+            prefix: CStr::from_ptr(prefix),
+            payload,
+        }
+    }
+
+    /// The message as `M`, if it has `M`'s prefix and `M` can read its payload
+    pub fn decode<M: WalMessage>(&self) -> Option<M> {
+        if self.prefix.to_bytes() == M::PREFIX.as_bytes() {
+            M::from_payload(self.payload)
+        } else {
+            None
+        }
+    }
+}
+
+/// Write `data` as an output plugin's output for the change it's decoding, such as from its
+/// `message_cb`.  `last_write` is whether this is the last write for the change
+///
+/// ## Safety
+///
+/// `ctx` must be the `LogicalDecodingContext` Postgres passed to the current callback
+pub unsafe fn write_output(
+    ctx: *mut pg_sys::LogicalDecodingContext,
+    data: &[u8],
+    last_write: bool,
+) {
+    pg_sys::pgx_OutputPluginWriteBytes(ctx, data.as_ptr() as *const c_char, data.len(), last_write);
+}
@@ -0,0 +1,244 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! Support for custom window functions, through Postgres' `WindowObject` API
+//!
+//! A window function is a `#[pg_extern(window)]` function, which is declared `WINDOW`.  Postgres
+//! doesn't pass a window function its arguments like it does other functions, so `#[pg_extern]`
+//! reads each one from the current row of the window partition instead.
+//!
+//! A window function can keep state across the rows of a partition by taking a `&mut` reference
+//! to any `Default + 'static` type as an additional argument, which isn't an argument of the SQL
+//! function.  The state is created with `Default::default()` for the partition's first row, and
+//! dropped when Postgres moves on to the next partition.
+//!
+//! Other rows of the partition, or of the current row's window frame, can be read through the
+//! `PgWindowObject` of a `pg_sys::FunctionCallInfo` argument.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//!
+//! #[derive(Default)]
+//! struct Distinct {
+//!     seen: std::collections::HashSet<i64>,
+//! }
+//!
+//! /// `SELECT distinct_so_far(x) OVER (PARTITION BY ... ORDER BY ...)`:  how many distinct
+//! /// values of `x` the partition has had, up to and including the current row
+//! #[pg_extern(window)]
+//! fn distinct_so_far(value: Option<i64>, state: &mut Distinct) -> i64 {
+//!     if let Some(value) = value {
+//!         state.seen.insert(value);
+//!     }
+//!     state.seen.len() as i64
+//! }
+//! ```
+use crate::{pg_sys, void_ptr, FromDatum, Internal, IntoDatum, PgMemoryContexts};
+
+#[inline]
+pub fn called_as_window_function(fcinfo: pg_sys::FunctionCallInfo) -> bool {
+    let fcinfo = unsafe { fcinfo.as_ref() }.expect("fcinfo was null");
+    unsafe { fcinfo.context_ref() }.map_or(false, |context| {
+        context.type_ == pg_sys::NodeTag_T_WindowObjectData
+    })
+}
+
+/// Where `PgWindowObject::arg_in_partition()` and `arg_in_frame()` count their `relpos` from
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WindowSeek {
+    /// The current row
+    Current,
+
+    /// The first row of the partition or frame
+    Head,
+
+    /// The last row of the partition or frame
+    Tail,
+}
+
+impl WindowSeek {
+    fn seektype(self) -> i32 {
+        (match self {
+            WindowSeek::Current => pg_sys::WINDOW_SEEK_CURRENT,
+            WindowSeek::Head => pg_sys::WINDOW_SEEK_HEAD,
+            WindowSeek::Tail => pg_sys::WINDOW_SEEK_TAIL,
+        }) as i32
+    }
+}
+
+/// The `pg_sys::WindowObject` Postgres calls a window function with, for reading the rows of the
+/// current window partition.
+///
+/// Row positions are zero-based, and count from the first row of the partition.
+pub struct PgWindowObject {
+    fcinfo: pg_sys::FunctionCallInfo,
+    winobj: pg_sys::WindowObject,
+}
+
+impl PgWindowObject {
+    /// The `WindowObject` of a window function call
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the function wasn't called as a window function, such as if
+    /// it was declared without `#[pg_extern(window)]`
+    pub fn from_fcinfo(fcinfo: pg_sys::FunctionCallInfo) -> Self {
+        if !called_as_window_function(fcinfo) {
+            panic!("function was not called as a window function");
+        }
+
+        PgWindowObject {
+            fcinfo,
+            winobj: unsafe { (*fcinfo).context } as pg_sys::WindowObject,
+        }
+    }
+
+    /// The position of the current row
+    pub fn current_position(&self) -> i64 {
+        unsafe { pg_sys::WinGetCurrentPosition(self.winobj) }
+    }
+
+    /// The number of rows in the partition, which requires Postgres to read all of them
+    pub fn partition_row_count(&self) -> i64 {
+        unsafe { pg_sys::WinGetPartitionRowCount(self.winobj) }
+    }
+
+    /// Let Postgres discard the partition's rows before `position`, which this function won't
+    /// read again.  The mark can only move forward.
+    pub fn set_mark_position(&self, position: i64) {
+        unsafe { pg_sys::WinSetMarkPosition(self.winobj, position) }
+    }
+
+    /// Are the rows at the two positions peers, sorting equally by the window's `ORDER BY`?
+    /// Every row is a peer of every other when there's no `ORDER BY`.
+    pub fn rows_are_peers(&self, position1: i64, position2: i64) -> bool {
+        unsafe { pg_sys::WinRowsArePeers(self.winobj, position1, position2) }
+    }
+
+    /// The zero-based argument `argno` of the current row, or `None` if it's NULL
+    pub fn arg_current<T: FromDatum>(&self, argno: usize) -> Option<T> {
+        let mut is_null = false;
+        unsafe {
+            let datum = pg_sys::WinGetFuncArgCurrent(self.winobj, argno as i32, &mut is_null);
+            T::from_datum(datum, is_null, self.arg_type(argno))
+        }
+    }
+
+    /// The zero-based argument `argno` of the row `relpos` rows after the row `seek` names, or
+    /// before it if `relpos` is negative.  If `set_mark` is true, the rows before that one are
+    /// discarded, as with `set_mark_position()`.
+    ///
+    /// Returns `None` if there's no such row in the partition, else `Some` of its value, which is
+    /// `None` if it's NULL.
+    pub fn arg_in_partition<T: FromDatum>(
+        &self,
+        argno: usize,
+        relpos: i32,
+        seek: WindowSeek,
+        set_mark: bool,
+    ) -> Option<Option<T>> {
+        let (mut is_null, mut is_out) = (false, false);
+        unsafe {
+            let datum = pg_sys::WinGetFuncArgInPartition(
+                self.winobj,
+                argno as i32,
+                relpos,
+                seek.seektype(),
+                set_mark,
+                &mut is_null,
+                &mut is_out,
+            );
+            self.arg_unless_out(argno, datum, is_null, is_out)
+        }
+    }
+
+    /// The zero-based argument `argno` of the row `relpos` rows after the row `seek` names, or
+    /// before it if `relpos` is negative, where `Head` and `Tail` are the ends of the current
+    /// row's window frame.  If `set_mark` is true, the rows before that one are discarded, as with
+    /// `set_mark_position()`.
+    ///
+    /// Returns `None` if there's no such row in the frame, else `Some` of its value, which is
+    /// `None` if it's NULL.
+    pub fn arg_in_frame<T: FromDatum>(
+        &self,
+        argno: usize,
+        relpos: i32,
+        seek: WindowSeek,
+        set_mark: bool,
+    ) -> Option<Option<T>> {
+        let (mut is_null, mut is_out) = (false, false);
+        unsafe {
+            let datum = pg_sys::WinGetFuncArgInFrame(
+                self.winobj,
+                argno as i32,
+                relpos,
+                seek.seektype(),
+                set_mark,
+                &mut is_null,
+                &mut is_out,
+            );
+            self.arg_unless_out(argno, datum, is_null, is_out)
+        }
+    }
+
+    /// This function's state for the current partition, which is created with `T::default()` the
+    /// first time it's asked for in each partition.  It's allocated in the partition's memory
+    /// context, and dropped when Postgres moves on to the next partition.
+    ///
+    /// ## Safety
+    ///
+    /// Every `PgWindowObject` of the same call shares the one state, so this function is unsafe
+    /// as it cannot guarantee that no other reference to it is still alive, such as the `&mut`
+    /// state argument `#[pg_extern(window)]` already passed to the function
+    ///
+    /// ## Panics
+    ///
+    /// This function will panic if the partition's state was created as some other type
+    pub unsafe fn partition_state<T: Default + 'static>(&mut self) -> &mut T {
+        // Postgres allocates, and zeroes, this memory only once per partition
+        let local =
+            pg_sys::WinGetPartitionLocalMemory(self.winobj, std::mem::size_of::<pg_sys::Datum>())
+                as *mut pg_sys::Datum;
+
+        if *local == 0 {
+            let state =
+                Internal::new_unbounded(T::default(), PgMemoryContexts::Of(local as void_ptr));
+            *local = state.into_datum().expect("partition state is NULL");
+        }
+
+        let mut state = Internal::from_datum(*local, false, pg_sys::INTERNALOID)
+            .expect("partition state is NULL");
+        let state = state.get_mut::<T>().unwrap_or_else(|| {
+            panic!(
+                "window partition state is not a {}",
+                std::any::type_name::<T>()
+            )
+        }) as *mut T;
+        &mut *state
+    }
+
+    /// The underlying `pg_sys::WindowObject`
+    pub fn as_ptr(&self) -> pg_sys::WindowObject {
+        self.winobj
+    }
+
+    fn arg_type(&self, argno: usize) -> pg_sys::Oid {
+        crate::get_getarg_type(self.fcinfo, argno)
+    }
+
+    unsafe fn arg_unless_out<T: FromDatum>(
+        &self,
+        argno: usize,
+        datum: pg_sys::Datum,
+        is_null: bool,
+        is_out: bool,
+    ) -> Option<Option<T>> {
+        if is_out {
+            None
+        } else {
+            Some(T::from_datum(datum, is_null, self.arg_type(argno)))
+        }
+    }
+}
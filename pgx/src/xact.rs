@@ -0,0 +1,192 @@
+// Copyright 2020 ZomboDB, LLC <zombodb@gmail.com>. All rights reserved. Use of this source code is
+// governed by the MIT license that can be found in the LICENSE file.
+
+//! One-shot closures for the end of the current transaction, for the common "do this once it's
+//! committed" pattern, such as notifying an external system of a change only once it's visible.
+//!
+//! These are built on `register_xact_callback()`, and so are called only once, at the end of the
+//! current top-level transaction, and then forgotten.
+//!
+//! ## Two-phase commit
+//!
+//! A transaction prepared with `PREPARE TRANSACTION` is committed or rolled back later, possibly
+//! by another backend, or after a restart.  Nothing local to the backend that prepared it travels
+//! with it:  neither `at_commit()` nor `at_abort()` closures are called for it, and state such as
+//! a session registry, values leaked into long-lived memory contexts, or session-level advisory
+//! locks, stays behind with the backend.
+//!
+//! An extension that can't work that way should refuse to prepare, rather than let the
+//! transaction be prepared without it:
+//!
+//! - [`register_precommit_veto()`] for state that outlives a transaction, checked whenever one is
+//!   prepared
+//! - [`forbid_prepare()`] for something done in the current transaction only
+//!
+//! Either raises an ERROR that aborts the `PREPARE TRANSACTION`, which is how Postgres itself
+//! refuses to prepare a transaction that has used temporary tables.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! use pgx::*;
+//!
+//! #[pg_extern]
+//! fn enqueue_job(job: String) {
+//!     Spi::run(&format!("INSERT INTO jobs VALUES ({})", pgx::quote::quote_literal(&job)));
+//!     pgx::xact::at_commit(move || info!("job {} is ready for the workers", job));
+//! }
+//! ```
+use crate::{
+    pg_sys, register_subxact_callback, register_xact_callback, ErrorReport, PgLogLevel,
+    PgSqlErrorCode, PgSubXactCallbackEvent, PgXactCallbackEvent, XactCallbackReceipt,
+};
+use std::cell::{Cell, RefCell};
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+type PrepareVeto = Box<dyn Fn() -> Result<(), String>>;
+
+thread_local! {
+    static PREPARE_VETOES: RefCell<Vec<PrepareVeto>> = RefCell::new(Vec::new());
+    static PREPARING: Cell<bool> = Cell::new(false);
+}
+
+/// Call `f` once the current top-level transaction commits.
+///
+/// If it's registered in a subtransaction (such as within a `BEGIN ... EXCEPTION` block) that
+/// then rolls back, `f` is dropped without being called, as the work it was to follow up on was
+/// rolled back too.  It isn't called if the transaction aborts or is prepared with
+/// `PREPARE TRANSACTION`.
+///
+/// ## Safety
+///
+/// `f` is called after the commit, so it can't change the outcome of the transaction, and a
+/// Rust `panic!()` or Postgres `ereport(ERROR)` within it will cause the backend to abort and the
+/// entire cluster to restart.  Use `register_xact_callback(PgXactCallbackEvent::PreCommit, ..)`
+/// for work that can still fail the transaction
+pub fn at_commit<F: FnOnce() + 'static>(f: F) -> XactCallbackReceipt {
+    let f = Rc::new(RefCell::new(Some(f)));
+
+    // a committed subtransaction hands `f` to its parent, and an aborted one drops it
+    let owner = Rc::new(Cell::new(unsafe { pg_sys::GetCurrentSubTransactionId() }));
+    {
+        let owner = AssertUnwindSafe(Rc::clone(&owner));
+        register_subxact_callback(
+            PgSubXactCallbackEvent::CommitSub,
+            move |my_subid, parent_subid| {
+                if my_subid == owner.get() {
+                    owner.set(parent_subid);
+                }
+            },
+        );
+    }
+    {
+        let f = AssertUnwindSafe(Rc::clone(&f));
+        register_subxact_callback(PgSubXactCallbackEvent::AbortSub, move |my_subid, _| {
+            if my_subid == owner.get() {
+                f.borrow_mut().take();
+            }
+        });
+    }
+
+    register_once(PgXactCallbackEvent::Commit, f)
+}
+
+/// Call `f` once the current top-level transaction aborts, whether or not the subtransaction it
+/// was registered in committed.  It isn't called if the transaction commits.
+///
+/// ## Safety
+///
+/// A Rust `panic!()` or Postgres `ereport(ERROR)` within `f` will cause the backend to abort and
+/// the entire cluster to restart
+pub fn at_abort<F: FnOnce() + 'static>(f: F) -> XactCallbackReceipt {
+    register_once(PgXactCallbackEvent::Abort, Rc::new(RefCell::new(Some(f))))
+}
+
+/// Register `veto`, which is called whenever a transaction is prepared with
+/// `PREPARE TRANSACTION`, for the rest of the backend's life.  If it returns an `Err`, its message
+/// is raised as an ERROR, with `ERRCODE_FEATURE_NOT_SUPPORTED`, and the transaction is aborted
+/// instead of prepared.
+///
+/// Vetoes are called in the order they were registered, until one of them returns an `Err`, and
+/// before any `PgXactCallbackEvent::PrePrepare` callbacks.  They're typically registered in
+/// `_PG_init()`, and check for state that can't be prepared:
+///
+/// ```rust,no_run
+/// use pgx::*;
+/// use std::cell::Cell;
+///
+/// thread_local! {
+///     static OPEN_CONNECTIONS: Cell<usize> = Cell::new(0);
+/// }
+///
+/// #[pg_guard]
+/// pub extern "C" fn _PG_init() {
+///     pgx::xact::register_precommit_veto(|| match OPEN_CONNECTIONS.with(|open| open.get()) {
+///         0 => Ok(()),
+///         n => Err(format!(
+///             "my_extension does not support prepared transactions while holding {} connections",
+///             n
+///         )),
+///     });
+/// }
+/// ```
+pub fn register_precommit_veto<F: Fn() -> Result<(), String> + 'static>(veto: F) {
+    crate::callbacks::ensure_xact_callback();
+    PREPARE_VETOES.with(|vetoes| vetoes.borrow_mut().push(Box::new(veto)));
+}
+
+/// Refuse to prepare the current transaction, because it `reason`, such as "has opened a socket".
+/// If it's prepared, an ERROR is raised, with `ERRCODE_FEATURE_NOT_SUPPORTED` and the message
+/// "cannot PREPARE a transaction that `reason`", and the transaction is aborted instead.
+///
+/// This only applies to the current transaction, even if it's called in a subtransaction that
+/// rolls back, as whatever led to it is rarely rolled back with it
+pub fn forbid_prepare(reason: &str) -> XactCallbackReceipt {
+    let message = format!("cannot PREPARE a transaction that {}", reason);
+    register_xact_callback(PgXactCallbackEvent::PrePrepare, move || {
+        ErrorReport::new(PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED, message)
+            .report(PgLogLevel::ERROR)
+    })
+}
+
+/// Is the current transaction being prepared by `PREPARE TRANSACTION`?
+///
+/// That's from the time the vetoes of [`register_precommit_veto()`] are called, until it's
+/// prepared or aborted, which is when `PgXactCallbackEvent::PrePrepare` and
+/// `PgXactCallbackEvent::Prepare` callbacks are called.  Code that's called from those callbacks,
+/// and from callbacks for other events, can use it to decide whether it's able to do its work.
+/// It's `false` while a prepared transaction is committed or rolled back, by
+/// `COMMIT PREPARED` or `ROLLBACK PREPARED`, which start a transaction of their own
+pub fn in_prepared_transaction_context() -> bool {
+    // we only find out by watching for the transaction events
+    crate::callbacks::ensure_xact_callback();
+    PREPARING.with(|preparing| preparing.get())
+}
+
+pub(crate) fn set_preparing(preparing: bool) {
+    PREPARING.with(|flag| flag.set(preparing));
+}
+
+/// Raise an ERROR with the message of the first veto that refuses to prepare the transaction
+pub(crate) fn check_prepare_vetoes() {
+    let refusal =
+        PREPARE_VETOES.with(|vetoes| vetoes.borrow().iter().find_map(|veto| veto().err()));
+
+    if let Some(message) = refusal {
+        ErrorReport::new(PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED, message)
+            .report(PgLogLevel::ERROR);
+    }
+}
+
+fn register_once<F: FnOnce() + 'static>(
+    event: PgXactCallbackEvent,
+    f: Rc<RefCell<Option<F>>>,
+) -> XactCallbackReceipt {
+    let f = AssertUnwindSafe(f);
+    register_xact_callback(event, move || {
+        if let Some(f) = f.borrow_mut().take() {
+            f();
+        }
+    })
+}